@@ -0,0 +1,166 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod options;
+mod report;
+mod workload;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+use radish_client::protocol;
+use radish_types::*;
+
+use options::Options;
+use report::Report;
+use workload::{Test, Workload};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 3;
+
+/// Takes up to `wanted` requests from the shared budget of the test
+fn claim(remaining: &AtomicUsize, wanted: usize) -> usize {
+	let mut current = remaining.load(Ordering::Relaxed);
+	loop {
+		let taken = std::cmp::min(current, wanted);
+		if taken == 0 {
+			return 0;
+		}
+		match remaining.compare_exchange_weak(current, current - taken, Ordering::Relaxed, Ordering::Relaxed) {
+			Ok(_) => return taken,
+			Err(actual) => current = actual,
+		}
+	}
+}
+
+struct ClientResult {
+	latencies: Vec<Duration>,
+	errors: usize,
+}
+
+/// Sends batches of `pipeline` commands until the shared budget is exhausted
+async fn drive(mut sock: TcpStream, mut workload: Workload, pipeline: usize, remaining: Arc<AtomicUsize>) -> Result<ClientResult> {
+	let mut result = ClientResult {
+		latencies: Vec::new(),
+		errors: 0,
+	};
+	let mut batch = Vec::with_capacity(pipeline);
+	loop {
+		let count = claim(&remaining, pipeline);
+		if count == 0 {
+			return Ok(result);
+		}
+		batch.clear();
+		for _ in 0..count {
+			batch.push(protocol::encode_command(&workload.command())?);
+		}
+
+		let start = Instant::now();
+		let mut writer = tokio::io::BufWriter::new(&mut sock);
+		for buf in &batch {
+			protocol::write_frame(&mut writer, buf).await?;
+		}
+		writer.flush().await?;
+		drop(writer);
+		for _ in 0..count {
+			if let Value::Error(err) = protocol::receive_value(&mut sock, frame::DEFAULT_MAX_FRAME_SIZE).await? {
+				log::debug!("Error reply: {}", err);
+				result.errors += 1;
+			}
+		}
+		let elapsed = start.elapsed();
+		let total = result.latencies.len() + count;
+		result.latencies.resize(total, elapsed);
+	}
+}
+
+async fn connect(addr: &str) -> Result<TcpStream> {
+	let sock = TcpStream::connect(addr).await?;
+	sock.set_nodelay(true)?;
+	Ok(sock)
+}
+
+/// Connections are opened before the clock starts, so the connect time is not measured
+async fn run_test(test: Test, options: &Options) -> Result<Report> {
+	let addr = options.addr();
+	let mut socks = Vec::with_capacity(options.clients);
+	for _ in 0..options.clients {
+		socks.push(connect(&addr).await?);
+	}
+
+	let remaining = Arc::new(AtomicUsize::new(options.requests));
+	let start = Instant::now();
+	let tasks: Vec<_> = socks.into_iter()
+		.enumerate()
+		.map(|(index, sock)| {
+			let workload = Workload::new(test, options.data_size, options.keyspace, index as u64 + 1);
+			tokio::spawn(drive(sock, workload, options.pipeline, remaining.clone()))
+		})
+		.collect();
+
+	let mut latencies = Vec::with_capacity(options.requests);
+	let mut errors = 0;
+	for task in tasks {
+		let result = task.await??;
+		latencies.extend(result.latencies);
+		errors += result.errors;
+	}
+	let elapsed = start.elapsed();
+	Ok(Report::new(test.name(), options.clients, options.pipeline, options.data_size, elapsed, errors, latencies))
+}
+
+async fn run(options: &Options) -> Result<()> {
+	if options.csv {
+		Report::print_csv_header();
+	}
+	for test in &options.tests {
+		let report = run_test(*test, options).await?;
+		if options.csv {
+			report.print_csv();
+		} else {
+			report.print_human();
+		}
+	}
+	Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+	env_logger::init();
+
+	let options = match Options::parse(std::env::args().skip(1)) {
+		Ok(Some(options)) => options,
+		Ok(None) => {
+			println!("{}", options::USAGE);
+			return;
+		},
+		Err(err) => {
+			eprintln!("{}\n\n{}", err, options::USAGE);
+			std::process::exit(EXIT_USAGE);
+		},
+	};
+
+	if let Err(err) = run(&options).await {
+		eprintln!("Error: {}", err);
+		std::process::exit(EXIT_FAILURE);
+	}
+}
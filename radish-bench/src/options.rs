@@ -0,0 +1,109 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use super::workload::Test;
+
+pub struct Options {
+	pub host: String,
+	pub port: u16,
+	pub clients: usize,
+	pub requests: usize,
+	pub pipeline: usize,
+	pub data_size: usize,
+	pub keyspace: Option<u64>,
+	pub tests: Vec<Test>,
+	pub csv: bool,
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Self {
+			host: "127.0.0.1".to_owned(),
+			port: 6142,
+			clients: 50,
+			requests: 100_000,
+			pipeline: 1,
+			data_size: 3,
+			keyspace: None,
+			tests: Test::ALL.to_vec(),
+			csv: false,
+		}
+	}
+}
+
+pub const USAGE: &str = "\
+Usage: radish-bench [-h <host>] [-p <port>] [-c <clients>] [-n <requests>] [-P <pipeline>]
+                    [-d <size>] [-r <keyspace>] [-t <tests>] [--csv]
+
+  -h <host>      Server hostname (default 127.0.0.1)
+  -p <port>      Server port (default 6142)
+  -c <clients>   Number of parallel connections (default 50)
+  -n <requests>  Total number of requests of each test (default 100000)
+  -P <pipeline>  Number of requests sent before waiting for replies (default 1)
+  -d <size>      Value size in bytes for SET, LPUSH and SADD (default 3)
+  -r <keyspace>  Use random keys from a space of the given size instead of a single key
+  -t <tests>     Comma separated list of tests: set,get,lpush,sadd,incr (default all)
+  --csv          Print the results in CSV format
+  --help         Print this help";
+
+fn next_value(args: &mut impl Iterator<Item=String>, flag: &str) -> Result<String, String> {
+	args.next().ok_or_else(||format!("Option '{}' requires a value", flag))
+}
+
+fn parse_positive<T: std::str::FromStr + PartialOrd + Default>(value: &str, what: &str) -> Result<T, String> {
+	match value.parse::<T>() {
+		Ok(number) if number > T::default() => Ok(number),
+		_ => Err(format!("Invalid {} '{}': a positive number is expected", what, value)),
+	}
+}
+
+impl Options {
+	/// Returns None if the help is requested
+	pub fn parse(mut args: impl Iterator<Item=String>) -> Result<Option<Self>, String> {
+		let mut options = Self::default();
+		while let Some(arg) = args.next() {
+			match &arg[..] {
+				"-h" => options.host = next_value(&mut args, &arg)?,
+				"-p" => options.port = parse_positive(&next_value(&mut args, &arg)?, "port")?,
+				"-c" => options.clients = parse_positive(&next_value(&mut args, &arg)?, "number of clients")?,
+				"-n" => options.requests = parse_positive(&next_value(&mut args, &arg)?, "number of requests")?,
+				"-P" => options.pipeline = parse_positive(&next_value(&mut args, &arg)?, "pipeline depth")?,
+				"-d" => options.data_size = parse_positive(&next_value(&mut args, &arg)?, "data size")?,
+				"-r" => options.keyspace = Some(parse_positive(&next_value(&mut args, &arg)?, "keyspace size")?),
+				"-t" => {
+					let tests = next_value(&mut args, &arg)?;
+					options.tests = tests.split(',')
+						.map(|name|name.trim())
+						.filter(|name|!name.is_empty())
+						.map(|name|Test::parse(name).ok_or_else(||format!("Unknown test '{}'", name)))
+						.collect::<Result<_, _>>()?;
+					if options.tests.is_empty() {
+						return Err("No tests to run".to_owned());
+					}
+				},
+				"--csv" => options.csv = true,
+				"--help" => return Ok(None),
+				_ => return Err(format!("Unknown option '{}'", arg)),
+			}
+		}
+		Ok(Some(options))
+	}
+
+	pub fn addr(&self) -> String {
+		format!("{}:{}", self.host, self.port)
+	}
+}
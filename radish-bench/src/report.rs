@@ -0,0 +1,108 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::time::Duration;
+
+/// Results of one test; latency of a request is the round trip of its pipeline batch
+pub struct Report {
+	pub test: &'static str,
+	pub clients: usize,
+	pub pipeline: usize,
+	pub data_size: usize,
+	pub elapsed: Duration,
+	pub errors: usize,
+	latencies: Vec<Duration>,
+}
+
+const PERCENTILES: [f64; 4] = [50.0, 95.0, 99.0, 100.0];
+
+fn ms(duration: Duration) -> f64 {
+	duration.as_secs_f64() * 1000.0
+}
+
+impl Report {
+	pub fn new(test: &'static str, clients: usize, pipeline: usize, data_size: usize, elapsed: Duration, errors: usize, mut latencies: Vec<Duration>) -> Self {
+		latencies.sort_unstable();
+		Self {
+			test,
+			clients,
+			pipeline,
+			data_size,
+			elapsed,
+			errors,
+			latencies,
+		}
+	}
+
+	pub fn requests(&self) -> usize {
+		self.latencies.len()
+	}
+
+	pub fn throughput(&self) -> f64 {
+		match self.elapsed.as_secs_f64() {
+			secs if secs > 0.0 => self.requests() as f64 / secs,
+			_ => 0.0,
+		}
+	}
+
+	/// Nearest-rank percentile over the sorted samples
+	pub fn percentile(&self, percentile: f64) -> Duration {
+		if self.latencies.is_empty() {
+			return Duration::from_secs(0);
+		}
+		let rank = (percentile / 100.0 * self.latencies.len() as f64).ceil() as usize;
+		self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+	}
+
+	pub fn average(&self) -> Duration {
+		match self.latencies.len() {
+			0 => Duration::from_secs(0),
+			n => self.latencies.iter().sum::<Duration>() / n as u32,
+		}
+	}
+
+	pub fn print_human(&self) {
+		println!("====== {} ======", self.test);
+		println!("  {} requests completed in {:.2} seconds", self.requests(), self.elapsed.as_secs_f64());
+		println!("  {} parallel clients", self.clients);
+		println!("  {} bytes payload", self.data_size);
+		println!("  pipeline depth {}", self.pipeline);
+		if self.errors > 0 {
+			println!("  {} error replies", self.errors);
+		}
+		println!();
+		println!("Latency by percentile distribution:");
+		for percentile in PERCENTILES.iter() {
+			println!("{:>7.3}% <= {:.3} milliseconds", percentile, ms(self.percentile(*percentile)));
+		}
+		println!("  avg {:.3} milliseconds", ms(self.average()));
+		println!();
+		println!("  throughput summary: {:.2} requests per second", self.throughput());
+		println!();
+	}
+
+	pub fn print_csv_header() {
+		println!("\"test\",\"rps\",\"avg_latency_ms\",\"p50_latency_ms\",\"p95_latency_ms\",\"p99_latency_ms\",\"max_latency_ms\",\"errors\"");
+	}
+
+	pub fn print_csv(&self) {
+		println!("\"{}\",\"{:.2}\",\"{:.3}\",\"{:.3}\",\"{:.3}\",\"{:.3}\",\"{:.3}\",\"{}\"",
+			self.test, self.throughput(), ms(self.average()),
+			ms(self.percentile(50.0)), ms(self.percentile(95.0)), ms(self.percentile(99.0)), ms(self.percentile(100.0)),
+			self.errors);
+	}
+}
@@ -0,0 +1,119 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use radish_types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Test {
+	Set,
+	Get,
+	Lpush,
+	Sadd,
+	Incr,
+}
+
+impl Test {
+	pub const ALL: [Test; 5] = [Test::Set, Test::Get, Test::Lpush, Test::Sadd, Test::Incr];
+
+	pub fn parse(name: &str) -> Option<Self> {
+		match &name.to_lowercase()[..] {
+			"set" => Some(Test::Set),
+			"get" => Some(Test::Get),
+			"lpush" => Some(Test::Lpush),
+			"sadd" => Some(Test::Sadd),
+			"incr" => Some(Test::Incr),
+			_ => None,
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Test::Set => "SET",
+			Test::Get => "GET",
+			Test::Lpush => "LPUSH",
+			Test::Sadd => "SADD",
+			Test::Incr => "INCR",
+		}
+	}
+}
+
+/// Xorshift generator: random keys only have to be spread over the keyspace
+pub struct Random(u64);
+
+impl Random {
+	pub fn new(seed: u64) -> Self {
+		Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+	}
+
+	pub fn next(&mut self, bound: u64) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0 % bound
+	}
+}
+
+/// Builds the commands of one test; with a keyspace every key or member is random
+pub struct Workload {
+	test: Test,
+	value: Vec<u8>,
+	keyspace: Option<u64>,
+	random: Random,
+}
+
+fn buffer(value: impl Into<Vec<u8>>) -> Value {
+	Value::Buffer(value.into())
+}
+
+impl Workload {
+	pub fn new(test: Test, data_size: usize, keyspace: Option<u64>, seed: u64) -> Self {
+		Self {
+			test,
+			value: vec![b'x'; data_size],
+			keyspace,
+			random: Random::new(seed),
+		}
+	}
+
+	fn key(&mut self, prefix: &str) -> Value {
+		match self.keyspace {
+			None => buffer(format!("{}:__rand_int__", prefix)),
+			Some(keyspace) => buffer(format!("{}:{:012}", prefix, self.random.next(keyspace))),
+		}
+	}
+
+	fn member(&mut self) -> Value {
+		match self.keyspace {
+			None => buffer(self.value.clone()),
+			Some(keyspace) => buffer(format!("element:{:012}", self.random.next(keyspace))),
+		}
+	}
+
+	pub fn command(&mut self) -> Command {
+		let arguments = match self.test {
+			Test::Set => vec![self.key("key"), buffer(self.value.clone())],
+			Test::Get => vec![self.key("key")],
+			Test::Lpush => vec![buffer("mylist"), buffer(self.value.clone())],
+			Test::Sadd => vec![buffer("myset"), self.member()],
+			Test::Incr => vec![self.key("counter")],
+		};
+		Command {
+			command: self.test.name().to_owned(),
+			arguments: arguments.into_iter().collect(),
+		}
+	}
+}
@@ -0,0 +1,115 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A tiny benchmark of the built radish-bench binary against an in-process server
+
+use std::process::{Command, Output};
+
+use radish_database::{Session, Storage, Value};
+use radish_server::{Server, ServerHandle};
+
+async fn start() -> (Storage, String, ServerHandle) {
+	let storage = Storage::new();
+	storage.config().set("bind", "127.0.0.1").unwrap();
+	storage.config().set("port", "0").unwrap();
+	let server = Server::bind(storage.clone()).await.unwrap();
+	let addr = server.listeners()[0].trim_start_matches("tcp ").to_owned();
+	let port = addr.rsplit(':').next().unwrap().to_owned();
+	(storage, port, server.start())
+}
+
+/// The process is waited on a blocking thread so the server keeps serving on the test runtime
+async fn bench(port: &str, args: &[&str]) -> Output {
+	let mut all = vec!["-h".to_owned(), "127.0.0.1".to_owned(), "-p".to_owned(), port.to_owned()];
+	all.extend(args.iter().map(|arg|arg.to_string()));
+	tokio::task::spawn_blocking(move || {
+		Command::new(env!("CARGO_BIN_EXE_radish-bench")).args(&all).output().unwrap()
+	}).await.unwrap()
+}
+
+async fn query(storage: &Storage, command: &str, key: &str) -> Value {
+	let command = radish_database::Command {
+		command: command.to_owned(),
+		arguments: vec![Value::Buffer(key.as_bytes().to_vec())].into(),
+	};
+	storage.execute(&mut Session::default(), command).await
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn csv_report() {
+	let (storage, port, server) = start().await;
+	let output = bench(&port, &["-c", "4", "-n", "200", "-P", "4", "-d", "8", "-t", "set,get,lpush,incr", "--csv"]).await;
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	let lines: Vec<_> = stdout.lines().collect();
+	assert_eq!(lines[0], "\"test\",\"rps\",\"avg_latency_ms\",\"p50_latency_ms\",\"p95_latency_ms\",\"p99_latency_ms\",\"max_latency_ms\",\"errors\"");
+	assert_eq!(lines.len(), 5, "{}", stdout);
+	for (line, test) in lines[1..].iter().zip(&["SET", "GET", "LPUSH", "INCR"]) {
+		let fields: Vec<_> = line.split(',').map(|field|field.trim_matches('"')).collect();
+		assert_eq!(fields.len(), 8, "{}", line);
+		assert_eq!(fields[0], *test);
+		let numbers: Vec<f64> = fields[1..7].iter().map(|field|field.parse().unwrap()).collect();
+		assert!(numbers[0] > 0.0, "{}", line);
+		// average, then the percentiles in order
+		assert!(numbers[2] <= numbers[3] && numbers[3] <= numbers[4] && numbers[4] <= numbers[5], "{}", line);
+		assert_eq!(fields[7], "0");
+	}
+
+	// every request reached the storage
+	assert_eq!(query(&storage, "LLEN", "mylist").await, Value::Integer(200));
+	assert_eq!(query(&storage, "GET", "counter:__rand_int__").await, Value::Buffer(b"200".to_vec()));
+	assert_eq!(query(&storage, "STRLEN", "key:__rand_int__").await, Value::Integer(8));
+	server.shutdown().await.unwrap();
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn human_report() {
+	let (storage, port, server) = start().await;
+	let output = bench(&port, &["-c", "2", "-n", "50", "-r", "1000", "-t", "sadd"]).await;
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert!(stdout.starts_with("====== SADD ======\n"), "{}", stdout);
+	for line in &[
+		"  50 requests completed in ",
+		"  2 parallel clients\n",
+		"  3 bytes payload\n",
+		"  pipeline depth 1\n",
+		"Latency by percentile distribution:\n",
+		" 50.000% <= ",
+		"100.000% <= ",
+		"  throughput summary: ",
+	] {
+		assert!(stdout.contains(line), "{:?} is missing in\n{}", line, stdout);
+	}
+	assert!(!stdout.contains("error replies"));
+	// random members of a keyspace
+	match query(&storage, "SCARD", "myset").await {
+		Value::Integer(members) => assert!(members > 1 && members <= 50, "{}", members),
+		reply => panic!("unexpected SCARD reply {:?}", reply),
+	}
+	server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn unreachable_server() {
+	let (_storage, port, server) = start().await;
+	server.shutdown().await.unwrap();
+	let output = bench(&port, &["-n", "10", "-t", "get"]).await;
+	assert_eq!(output.status.code(), Some(1));
+	assert!(String::from_utf8_lossy(&output.stderr).starts_with("Error: "));
+}
@@ -0,0 +1,132 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use radish_client::Value;
+
+use super::Result;
+use super::connection::Connection;
+use super::options::Options;
+use super::output;
+
+/// Separates command groups on the command line: `radish-cli SET a 1 \; GET a`
+pub const SEPARATOR: &str = ";";
+
+pub struct BatchCommand {
+	pub line: usize,
+	pub args: Vec<String>,
+}
+
+pub struct BatchSummary {
+	pub executed: usize,
+	pub errors: usize,
+	pub aborted: bool,
+}
+
+pub fn split_groups(args: &[String]) -> Vec<BatchCommand> {
+	args
+	.split(|arg|arg == SEPARATOR)
+	.filter(|group|!group.is_empty())
+	.enumerate()
+	.map(|(i, group)|BatchCommand {
+		line: i + 1,
+		args: group.to_vec(),
+	})
+	.collect()
+}
+
+pub fn parse_script(script: &str) -> Vec<BatchCommand> {
+	script
+	.lines()
+	.enumerate()
+	.filter(|(_, line)|!line.trim_start().starts_with('#'))
+	.map(|(i, line)|BatchCommand {
+		line: i + 1,
+		args: super::parse_line(line),
+	})
+	.filter(|cmd|!cmd.args.is_empty())
+	.collect()
+}
+
+/// Executes commands one by one; a connection error stops the batch immediately,
+/// an error reply stops it only with `--abort-on-error`
+pub async fn run_batch(conn: &mut Connection, commands: Vec<BatchCommand>, options: &Options, numbered: bool) -> Result<BatchSummary> {
+	let mut summary = BatchSummary {
+		executed: 0,
+		errors: 0,
+		aborted: false,
+	};
+	for cmd in commands {
+		let request = super::new_command(&cmd.args[0], &cmd.args[1..]);
+		let result = conn.request(request).await?;
+		summary.executed += 1;
+
+		if numbered {
+			output::print_prefixed_value(&format!("{}: ", cmd.line), &result, options.output, &cmd.args[0]);
+		} else {
+			output::print_value(&result, options.output, &cmd.args[0]);
+		}
+		if let Value::Error(_) = result {
+			summary.errors += 1;
+			if options.abort_on_error {
+				summary.aborted = true;
+				break;
+			}
+		}
+	}
+	Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn args(args: &[&str]) -> Vec<String> {
+		args.iter().map(|arg|arg.to_string()).collect()
+	}
+
+	fn groups(commands: &[BatchCommand]) -> Vec<(usize, Vec<String>)> {
+		commands.iter().map(|cmd|(cmd.line, cmd.args.clone())).collect()
+	}
+
+	#[test]
+	fn split_on_separator() {
+		let commands = split_groups(&args(&["SET", "a", "1", ";", "GET", "a"]));
+		assert_eq!(groups(&commands), vec![(1, args(&["SET", "a", "1"])), (2, args(&["GET", "a"]))]);
+
+		let commands = split_groups(&args(&["GET", "a"]));
+		assert_eq!(groups(&commands), vec![(1, args(&["GET", "a"]))]);
+	}
+
+	#[test]
+	fn empty_groups_are_skipped() {
+		let commands = split_groups(&args(&[";", "PING", ";", ";", "GET", "a;b", ";"]));
+		assert_eq!(groups(&commands), vec![(1, args(&["PING"])), (2, args(&["GET", "a;b"]))]);
+
+		assert!(split_groups(&args(&[";"])).is_empty());
+	}
+
+	#[test]
+	fn script_lines() {
+		let script = "# setup\nSET a v\n\n   \n  # indented comment\nGET  a\nDEL a # not a comment\n";
+		let commands = parse_script(script);
+		assert_eq!(groups(&commands), vec![
+			(2, args(&["SET", "a", "v"])),
+			(6, args(&["GET", "a"])),
+			(7, args(&["DEL", "a", "#", "not", "a", "comment"])),
+		]);
+	}
+}
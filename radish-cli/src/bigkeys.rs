@@ -0,0 +1,156 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use radish_client::{Command, Value};
+
+use super::Result;
+use super::connection::Connection;
+use super::options::Options;
+use super::scan::{scan_command, parse_page};
+
+/// Key types in the order of the report with the command probing the size of each
+const PROBES: [(&str, &str, &str); 5] = [
+	("string", "STRLEN", "bytes"),
+	("list", "LLEN", "items"),
+	("set", "SCARD", "members"),
+	("hash", "HLEN", "fields"),
+	("zset", "ZCARD", "members"),
+];
+
+struct TypeStats {
+	name: &'static str,
+	probe: &'static str,
+	unit: &'static str,
+	keys: u64,
+	total: u64,
+	biggest: Option<(Vec<u8>, u64)>,
+}
+
+impl TypeStats {
+	fn add(&mut self, key: &[u8], size: u64) -> bool {
+		self.keys += 1;
+		self.total += size;
+		match &self.biggest {
+			Some((_, biggest)) if *biggest >= size => false,
+			_ => {
+				self.biggest = Some((key.to_vec(), size));
+				true
+			},
+		}
+	}
+}
+
+fn command(name: &str, arguments: Vec<Value>) -> Command {
+	Command {
+		command: name.to_owned(),
+		arguments: arguments.into_iter().collect(),
+	}
+}
+
+fn display_key(key: &[u8]) -> String {
+	format!("{:?}", String::from_utf8_lossy(key))
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+	if total == 0 {0.0} else {part as f64 * 100.0 / total as f64}
+}
+
+fn average(total: u64, count: u64) -> f64 {
+	if count == 0 {0.0} else {total as f64 / count as f64}
+}
+
+/// Returns None if the key has gone or changed its type since the TYPE probe
+async fn probe_size(conn: &mut Connection, probe: &str, key: &[u8], memkeys: bool) -> Result<Option<u64>> {
+	let cmd = if memkeys {
+		command("MEMORY", vec![Value::Buffer(b"USAGE".to_vec()), Value::Buffer(key.to_vec())])
+	} else {
+		command(probe, vec![Value::Buffer(key.to_vec())])
+	};
+	match conn.request(cmd).await? {
+		Value::Integer(size) if size >= 0 => Ok(Some(size as u64)),
+		Value::Error(err) if memkeys => Err(format!("MEMORY USAGE is not supported by the server: {}", err).into()),
+		_ => Ok(None),
+	}
+}
+
+/// Samples every key with TYPE and the type's size probe and reports the biggest
+/// key of each type. With `--memkeys` the sizes are taken from MEMORY USAGE
+pub async fn bigkeys_mode(conn: &mut Connection, options: &Options) -> Result<()> {
+	let mut stats: Vec<TypeStats> = PROBES.iter()
+		.map(|(name, probe, unit)| TypeStats {
+			name,
+			probe,
+			unit: if options.memkeys {"bytes"} else {unit},
+			keys: 0,
+			total: 0,
+			biggest: None,
+		})
+		.collect();
+	let mut sampled = 0u64;
+	let mut key_bytes = 0u64;
+
+	println!();
+	println!("# Scanning the entire keyspace to find biggest keys as well as");
+	println!("# average sizes per key type.");
+	println!();
+
+	let mut cursor = 0;
+	loop {
+		let (next, keys) = parse_page(conn.request(scan_command(cursor, options)).await?)?;
+		for key in keys {
+			let key_type = match conn.request(command("TYPE", vec![Value::Buffer(key.clone())])).await? {
+				Value::Buffer(key_type) => String::from_utf8_lossy(&key_type).into_owned(),
+				_ => continue,
+			};
+			let stats = match stats.iter_mut().find(|stats|stats.name == key_type) {
+				Some(stats) => stats,
+				None => continue,
+			};
+			let size = match probe_size(conn, stats.probe, &key, options.memkeys).await? {
+				Some(size) => size,
+				None => continue,
+			};
+			sampled += 1;
+			key_bytes += key.len() as u64;
+			if stats.add(&key, size) {
+				println!("Biggest {:>6} found so far {} with {} {}", stats.name, display_key(&key), size, stats.unit);
+			}
+		}
+		if next == 0 {
+			break;
+		}
+		cursor = next;
+	}
+
+	println!();
+	println!("-------- summary -------");
+	println!();
+	println!("Sampled {} keys in the keyspace!", sampled);
+	println!("Total key length in bytes is {} (avg len {:.2})", key_bytes, average(key_bytes, sampled));
+	println!();
+	for stats in &stats {
+		if let Some((key, size)) = &stats.biggest {
+			println!("Biggest {:>6} found {} has {} {}", stats.name, display_key(key), size, stats.unit);
+		}
+	}
+	println!();
+	for stats in &stats {
+		println!("{} {}s with {} {} ({:05.2}% of keys, avg size {:.2})",
+			stats.keys, stats.name, stats.total, stats.unit, percent(stats.keys, sampled), average(stats.total, stats.keys));
+	}
+	Ok(())
+}
@@ -0,0 +1,218 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use rustyline::Context;
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+
+use radish_client::Value;
+
+/// Used when the server can't describe its commands itself
+const BUILTIN_COMMANDS: &[&str] = &[
+	"NOW", "PNOW", "DEL", "KEYS", "EXISTS", "RENAME", "EXPIRE", "EXPIREAT", "PEXPIRE", "PEXPIREAT",
+	"PTTL", "TTL", "TYPE", "SCAN", "OBJECT", "EXPIRING", "SORT", "COPY",
+	"APPEND", "GET", "GETSET", "STRLEN", "BITCOUNT", "BITOP", "DECR", "DECRBY", "GETBIT", "GETRANGE",
+	"INCR", "INCRBY", "INCRBYFLOAT", "MGET", "MSET", "PSETEX", "SET", "SETBIT", "SETEX", "SETNX", "SETRANGE",
+	"LLEN", "LPOP", "RPOP", "LREM", "LSET", "LPUSH", "RPUSH", "LPUSHX", "RPUSHX", "LINDEX", "LRANGE",
+	"LINSERT", "LTRIM",
+	"SADD", "SREM", "SPOP", "SSCAN", "SCARD", "SMOVE", "SMEMBERS", "SISMEMBER", "SDIFF", "SINTER",
+	"SUNION", "SDIFFSTORE", "SINTERSTORE", "SUNIONSTORE",
+	"HSET", "HSETNX", "HDEL", "HGET", "HGETALL", "HEXISTS", "HKEYS", "HVALUES", "HLEN", "HSTRLEN",
+	"HINCRBY", "HINCRBYFLOAT", "HMGET", "HMSET", "HSCAN",
+	"JSON.SET", "JSON.GET", "JSON.DEL", "JSON.NUMINCRBY", "JSON.ARRAPPEND",
+	"BF.RESERVE", "BF.ADD", "BF.MADD", "BF.EXISTS", "BF.MEXISTS", "BF.INFO",
+	"TS.CREATE", "TS.ADD", "TS.RANGE", "TS.GET", "TS.INFO",
+	"PING", "SAVE", "EXPORT", "IMPORT", "AUTH", "SCRIPT", "EVALSHA", "SELECT", "CLIENT", "INFO", "MEMORY", "HOTKEYS", "CONFIG", "COMMAND", "HELP",
+];
+
+/// Keywords accepted after the command name
+const KEYWORDS: &[(&str, &[&str])] = &[
+	("SET", &["EX", "PX", "NX", "XX", "KEEPTTL", "IFEQ", "IFGT"]),
+	("SCAN", &["MATCH", "COUNT", "TYPE"]),
+	("SSCAN", &["MATCH", "COUNT"]),
+	("HSCAN", &["MATCH", "COUNT"]),
+	("JSON.SET", &["NX", "XX"]),
+	("BF.RESERVE", &["EXPANSION", "NONSCALING"]),
+	("TS.CREATE", &["RETENTION", "LABELS"]),
+	("TS.RANGE", &["AGGREGATION", "AVG", "MIN", "MAX", "SUM"]),
+	("LINSERT", &["BEFORE", "AFTER"]),
+	("BITOP", &["AND", "OR", "XOR", "NOT"]),
+	("INFO", &["SERVER", "CLIENTS", "MEMORY", "STATS", "KEYSPACE", "ALL"]),
+	("HOTKEYS", &["RESET"]),
+	("MEMORY", &["USAGE", "STATS", "DOCTOR", "PURGE"]),
+	("OBJECT", &["IDLETIME", "FREQ"]),
+	("CONFIG", &["GET", "SET", "RESETSTAT"]),
+	("IMPORT", &["REPLACE", "MERGE"]),
+	("COMMAND", &["COUNT", "INFO"]),
+	("CLIENT", &["ID", "GETNAME", "SETNAME", "COMPRESSION"]),
+	("SCRIPT", &["LOAD", "EXISTS", "FLUSH"]),
+];
+
+pub struct CommandCompleter {
+	commands: Vec<String>,
+}
+
+impl CommandCompleter {
+	pub fn new(mut commands: Vec<String>) -> Self {
+		commands.sort();
+		commands.dedup();
+		Self {
+			commands,
+		}
+	}
+
+	pub fn builtin() -> Self {
+		Self::new(BUILTIN_COMMANDS.iter().map(|c|c.to_string()).collect())
+	}
+
+	/// Builds the completer from a COMMAND reply or falls back to the builtin table
+	pub fn from_command_reply(reply: &Value) -> Self {
+		let commands = match reply {
+			Value::Array(commands) => commands
+				.iter()
+				.filter_map(|command| match command {
+					Value::Buffer(name) => Some(name),
+					Value::Array(spec) => match spec.front() {
+						Some(Value::Buffer(name)) => Some(name),
+						_ => None,
+					},
+					_ => None,
+				})
+				.map(|name|String::from_utf8_lossy(name).to_uppercase())
+				.collect::<Vec<String>>(),
+			_ => Vec::new(),
+		};
+		if commands.is_empty() {
+			Self::builtin()
+		} else {
+			Self::new(commands)
+		}
+	}
+
+	/// Returns the start of the word under the cursor and the candidates to replace it
+	pub fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+		let head = &line[..pos];
+		let start = head.rfind(' ').map(|i|i + 1).unwrap_or(0);
+		let word = &head[start..];
+		let lowercase = !word.is_empty() && word.chars().all(|c|!c.is_uppercase());
+		let word = word.to_uppercase();
+
+		let preceding = head[..start].split_whitespace().collect::<Vec<&str>>();
+		let table: Vec<&str> = match preceding.first() {
+			None => self.commands.iter().map(|c|&c[..]).collect(),
+			Some(command) => {
+				let command = command.to_uppercase();
+				KEYWORDS
+					.iter()
+					.find(|(name, _)|*name == command)
+					.map(|(_, keywords)|keywords.to_vec())
+					.unwrap_or_default()
+			},
+		};
+
+		let candidates = table
+			.into_iter()
+			.filter(|candidate|candidate.starts_with(&word[..]))
+			.map(|candidate| if lowercase {candidate.to_lowercase()} else {candidate.to_owned()})
+			.collect();
+		(start, candidates)
+	}
+}
+
+impl Completer for CommandCompleter {
+	type Candidate = String;
+
+	fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+		Ok(self.candidates(line, pos))
+	}
+}
+
+impl Hinter for CommandCompleter {
+	type Hint = String;
+}
+
+impl Highlighter for CommandCompleter {}
+
+impl Validator for CommandCompleter {}
+
+impl rustyline::Helper for CommandCompleter {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn complete(line: &str) -> (usize, Vec<String>) {
+		CommandCompleter::builtin().candidates(line, line.len())
+	}
+
+	fn strings(items: &[&str]) -> Vec<String> {
+		items.iter().map(|item|item.to_string()).collect()
+	}
+
+	#[test]
+	fn partial_command() {
+		assert_eq!(complete("HGETA"), (0, strings(&["HGETALL"])));
+		assert_eq!(complete("LPUSH"), (0, strings(&["LPUSH", "LPUSHX"])));
+		assert_eq!(complete("JSON.A"), (0, strings(&["JSON.ARRAPPEND"])));
+		assert_eq!(complete("XYZ"), (0, strings(&[])));
+		assert_eq!(complete("").1.len(), CommandCompleter::builtin().commands.len());
+	}
+
+	#[test]
+	fn command_case() {
+		assert_eq!(complete("hgeta"), (0, strings(&["hgetall"])));
+		assert_eq!(complete("lpus"), (0, strings(&["lpush", "lpushx"])));
+		// mixed case is completed in the canonical form
+		assert_eq!(complete("hGeTa"), (0, strings(&["HGETALL"])));
+	}
+
+	#[test]
+	fn subcommands_and_keywords() {
+		assert_eq!(complete("CONFIG "), (7, strings(&["GET", "SET", "RESETSTAT"])));
+		assert_eq!(complete("CONFIG RE"), (7, strings(&["RESETSTAT"])));
+		assert_eq!(complete("config re"), (7, strings(&["resetstat"])));
+		assert_eq!(complete("Config Re"), (7, strings(&["RESETSTAT"])));
+		assert_eq!(complete("memory p"), (7, strings(&["purge"])));
+		assert_eq!(complete("SET key value E"), (14, strings(&["EX"])));
+		assert_eq!(complete("set key value k"), (14, strings(&["keepttl"])));
+		assert_eq!(complete("GET k"), (4, strings(&[])));
+	}
+
+	#[test]
+	fn cursor_inside_the_line() {
+		let completer = CommandCompleter::builtin();
+		assert_eq!(completer.candidates("scr key", 3), (0, strings(&["script"])));
+		assert_eq!(completer.candidates("SCRIPT FL key", 9), (7, strings(&["FLUSH"])));
+	}
+
+	#[test]
+	fn commands_from_the_server() {
+		let reply = Value::Array(vec![
+			Value::Array(vec![Value::Buffer(b"get".to_vec()), Value::Integer(2)].into()),
+			Value::Buffer(b"getset".to_vec()),
+			Value::Integer(1),
+		].into());
+		let completer = CommandCompleter::from_command_reply(&reply);
+		assert_eq!(completer.candidates("ge", 2), (0, strings(&["get", "getset"])));
+		assert_eq!(completer.candidates("HGET", 4), (0, strings(&[])));
+
+		let completer = CommandCompleter::from_command_reply(&Value::Error("ERR unknown command".to_owned()));
+		assert_eq!(completer.candidates("HGETA", 5), (0, strings(&["HGETALL"])));
+	}
+}
@@ -0,0 +1,151 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use radish_client::{Address, Arguments, Client, ClientConfig, Command, Value};
+
+use super::Result;
+
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_millis(100);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub struct AuthError(pub String);
+
+impl std::fmt::Display for AuthError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "AUTH failed: {}", self.0)
+	}
+}
+
+impl std::error::Error for AuthError {}
+
+#[derive(Clone)]
+pub struct ConnectOptions {
+	pub address: Address,
+	pub user: Option<String>,
+	pub password: Option<String>,
+	/// Database selected after AUTH
+	pub db: Option<i64>,
+	/// TLS, compression, timeouts and retries, see `radish_client::ClientConfig`
+	pub client: ClientConfig,
+}
+
+/// Connection to the server. The client reopens it on the next request after a failure
+/// and replays the accepted AUTH and SELECT on the new connection
+pub struct Connection {
+	address: Address,
+	client: Client,
+}
+
+fn verbose() -> bool {
+	super::VERBOSE.load(Ordering::Relaxed)
+}
+
+async fn authenticate(client: &Client, options: &ConnectOptions) -> Result<()> {
+	let password = match &options.password {
+		Some(password) => password,
+		None => return Ok(()),
+	};
+	let mut arguments = Arguments::new();
+	if let Some(user) = &options.user {
+		arguments.push_back(Value::Buffer(user.as_bytes().to_vec()));
+	}
+	arguments.push_back(Value::Buffer(password.as_bytes().to_vec()));
+	let cmd = Command {
+		command: "AUTH".to_owned(),
+		arguments,
+	};
+	match client.command(cmd).await? {
+		Value::Error(err) => Err(AuthError(err).into()),
+		_ => Ok(()),
+	}
+}
+
+async fn select(client: &Client, options: &ConnectOptions) -> Result<()> {
+	let db = match options.db {
+		Some(db) => db,
+		None => return Ok(()),
+	};
+	let cmd = Command {
+		command: "SELECT".to_owned(),
+		arguments: Arguments::from(vec![Value::Integer(db)]),
+	};
+	match client.command(cmd).await? {
+		Value::Error(err) => Err(format!("SELECT {} failed: {}", db, err).into()),
+		_ => Ok(()),
+	}
+}
+
+impl Connection {
+	pub async fn connect(options: ConnectOptions) -> Result<Self> {
+		let client = Client::connect_to(options.address.clone(), options.client.clone()).await?;
+		authenticate(&client, &options).await?;
+		select(&client, &options).await?;
+		Ok(Self {
+			address: options.address,
+			client,
+		})
+	}
+
+	/// Sends the command and waits for its reply; Error replies are returned as `Value::Error`
+	pub async fn request(&mut self, cmd: Command) -> Result<Value> {
+		if verbose() {
+			eprintln!("> {:?}", cmd);
+		}
+		let value = self.client.command(cmd).await?;
+		if verbose() {
+			eprintln!("< {:?}", value);
+		}
+		Ok(value)
+	}
+
+	/// Sends all commands before reading any reply
+	pub async fn pipeline(&mut self, commands: Vec<Command>) -> Result<Vec<Value>> {
+		let mut pipeline = self.client.pipeline();
+		for cmd in commands {
+			if verbose() {
+				eprintln!("> {:?}", cmd);
+			}
+			pipeline = pipeline.command(cmd);
+		}
+		let values = pipeline.execute().await?.into_values();
+		if verbose() {
+			for value in &values {
+				eprintln!("< {:?}", value);
+			}
+		}
+		Ok(values)
+	}
+
+	/// Retries to reach the server with capped exponential backoff until success;
+	/// gives up only if the server rejects the restored session, e.g. the credentials
+	pub async fn reconnect(&mut self) -> Result<()> {
+		let mut delay = RECONNECT_INITIAL_DELAY;
+		loop {
+			match self.client.ping().await {
+				Ok(()) => return Ok(()),
+				Err(radish_client::Error::Server(err)) => return Err(AuthError(err).into()),
+				Err(err) => {
+					log::debug!("{}: failed to connect: {}", self.address, err);
+					tokio::time::delay_for(delay).await;
+					delay = std::cmp::min(delay * 2, RECONNECT_MAX_DELAY);
+				},
+			}
+		}
+	}
+}
@@ -0,0 +1,236 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! `--export` and `--import`: JSON dumps made by the EXPORT and IMPORT commands.
+//! `--dump-to` and `--import-from`: binary snapshots in the format of the server's SAVE
+
+use std::io::Write;
+use std::time::{Duration, SystemTime};
+
+use radish_client::{Command, Value};
+use radish_database::{DatasetSnapshot, SnapshotEntry, SnapshotData, StorageError};
+
+use super::Result;
+use super::connection::Connection;
+use super::options::Options;
+use super::scan::{scan_command, parse_page};
+
+fn command(name: &str, arguments: Vec<Value>) -> Command {
+	Command {
+		command: name.to_owned(),
+		arguments: arguments.into_iter().collect(),
+	}
+}
+
+fn count_keys(document: &[u8]) -> usize {
+	serde_json::from_slice::<serde_json::Value>(document)
+		.ok()
+		.and_then(|doc|doc.get("keys").and_then(|keys|keys.as_array()).map(|keys|keys.len()))
+		.unwrap_or(0)
+}
+
+/// Writes the keys matching `--pattern` to `file`
+pub async fn export_mode(conn: &mut Connection, file: &str, options: &Options) -> Result<()> {
+	let arguments = options.pattern.iter().map(|p|Value::Buffer(p.as_bytes().to_vec())).collect();
+	let document = match conn.request(command("EXPORT", arguments)).await? {
+		Value::Buffer(document) => document,
+		Value::Error(err) => return Err(err.into()),
+		value => return Err(format!("Unexpected reply to EXPORT: {:?}", value).into()),
+	};
+	tokio::fs::write(file, &document).await?;
+	eprintln!("Exported {} keys to {}", count_keys(&document), file);
+	Ok(())
+}
+
+/// Sends the document from `file` as is; the server validates it before touching the dataset
+pub async fn import_mode(conn: &mut Connection, file: &str, options: &Options) -> Result<()> {
+	let document = tokio::fs::read(file).await?;
+	let mode = if options.replace {"REPLACE"} else {"MERGE"};
+	let arguments = vec![Value::Buffer(document), Value::Buffer(mode.as_bytes().to_vec())];
+	match conn.request(command("IMPORT", arguments)).await? {
+		Value::Integer(count) => eprintln!("Imported {} keys from {}", count, file),
+		Value::Error(err) => return Err(err.into()),
+		value => return Err(format!("Unexpected reply to IMPORT: {:?}", value).into()),
+	}
+	Ok(())
+}
+
+fn buffer(value: &[u8]) -> Value {
+	Value::Buffer(value.to_vec())
+}
+
+fn unexpected(command: &str, value: Value) -> Box<dyn std::error::Error + Send + Sync> {
+	match value {
+		Value::Error(err) => err.into(),
+		value => format!("Unexpected reply to {}: {:?}", command, value).into(),
+	}
+}
+
+/// Count of keys in the dataset from INFO keyspace; DBSIZE is not available
+async fn total_keys(conn: &mut Connection) -> Result<Option<u64>> {
+	let info = match conn.request(command("INFO", vec![buffer(b"keyspace")])).await? {
+		Value::Buffer(info) => String::from_utf8_lossy(&info).into_owned(),
+		value => return Err(unexpected("INFO", value)),
+	};
+	Ok(info.lines()
+		.find_map(|line|line.strip_prefix("keys:"))
+		.and_then(|keys|keys.trim().parse().ok()))
+}
+
+fn escape_glob(key: &[u8]) -> Vec<u8> {
+	let mut out = Vec::with_capacity(key.len());
+	for &b in key {
+		if matches!(b, b'*' | b'?' | b'[' | b']' | b'\\') {
+			out.push(b'\\');
+		}
+		out.push(b);
+	}
+	out
+}
+
+/// Types without a read command returning the whole value go through EXPORT of the single key
+async fn export_entry(conn: &mut Connection, key: &[u8]) -> Result<Option<SnapshotEntry>> {
+	let document = match conn.request(command("EXPORT", vec![Value::Buffer(escape_glob(key))])).await? {
+		Value::Buffer(document) => document,
+		value => return Err(unexpected("EXPORT", value)),
+	};
+	let json = serde_json::from_slice::<serde_json::Value>(&document)?;
+	let snapshot = radish_database::snapshot_from_json(&json)?;
+	Ok(snapshot.entries.into_iter().find(|entry|entry.key == key))
+}
+
+fn to_items(command: &str, value: Value) -> Result<Option<Vec<Value>>> {
+	match value {
+		Value::Nill => Ok(None),
+		Value::Array(items) if items.is_empty() => Ok(None),
+		Value::Array(items) => Ok(Some(items.into_iter().collect())),
+		value => Err(unexpected(command, value)),
+	}
+}
+
+async fn read_data(conn: &mut Connection, key: &[u8], key_type: &str) -> Result<Option<SnapshotData>> {
+	let data = match key_type {
+		"string" => match conn.request(command("GET", vec![buffer(key)])).await? {
+			Value::Nill => None,
+			Value::Buffer(value) => Some(SnapshotData::String(value)),
+			value => return Err(unexpected("GET", value)),
+		},
+		"list" => {
+			let reply = conn.request(command("LRANGE", vec![buffer(key), Value::Integer(0), Value::Integer(i64::MAX)])).await?;
+			to_items("LRANGE", reply)?.map(SnapshotData::List)
+		},
+		"set" => {
+			let reply = conn.request(command("SMEMBERS", vec![buffer(key)])).await?;
+			to_items("SMEMBERS", reply)?.map(SnapshotData::Set)
+		},
+		"hash" => {
+			let reply = conn.request(command("HGETALL", vec![buffer(key)])).await?;
+			to_items("HGETALL", reply)?.map(|items|{
+				let mut items = items.into_iter();
+				let mut pairs = Vec::with_capacity(items.len() / 2);
+				while let (Some(field), Some(value)) = (items.next(), items.next()) {
+					pairs.push((field, value));
+				}
+				SnapshotData::Hash(pairs)
+			})
+		},
+		"ReJSON-RL" => match conn.request(command("JSON.GET", vec![buffer(key)])).await? {
+			Value::Nill => None,
+			Value::Buffer(document) => Some(SnapshotData::Json(serde_json::from_slice(&document)?)),
+			value => return Err(unexpected("JSON.GET", value)),
+		},
+		_ => return Ok(export_entry(conn, key).await?.map(|entry|entry.data)),
+	};
+	Ok(data)
+}
+
+/// Reads the value by its type and then the time to live; None if the key disappeared meanwhile
+async fn read_entry(conn: &mut Connection, key: &[u8]) -> Result<Option<SnapshotEntry>> {
+	let key_type = match conn.request(command("TYPE", vec![buffer(key)])).await? {
+		Value::Nill => return Ok(None),
+		Value::Buffer(key_type) => String::from_utf8_lossy(&key_type).into_owned(),
+		value => return Err(unexpected("TYPE", value)),
+	};
+	let data = match read_data(conn, key, &key_type).await {
+		Ok(Some(data)) => data,
+		Ok(None) => return Ok(None),
+		// The key was replaced by a value of another type between TYPE and the read
+		Err(err) if StorageError::from(err.to_string()) == StorageError::WrongType => return Ok(None),
+		Err(err) => return Err(err),
+	};
+	let expire_at = match conn.request(command("PTTL", vec![buffer(key)])).await? {
+		Value::Integer(-2) => return Ok(None),
+		Value::Integer(ttl) if ttl < 0 => None,
+		Value::Integer(ttl) => {
+			let expire_at = SystemTime::now() + Duration::from_millis(ttl as u64);
+			Some(expire_at.duration_since(SystemTime::UNIX_EPOCH)?.as_millis() as u64)
+		},
+		value => return Err(unexpected("PTTL", value)),
+	};
+	Ok(Some(SnapshotEntry {key: key.to_vec(), data, expire_at}))
+}
+
+fn progress(done: usize, total: Option<u64>) -> Result<()> {
+	match total {
+		Some(total) => eprint!("\rDumped {}/{} keys", done, total),
+		None => eprint!("\rDumped {} keys", done),
+	}
+	std::io::stderr().flush()?;
+	Ok(())
+}
+
+/// Walks the keys matching `--pattern` with SCAN and reads them one by one, so the server
+/// is never blocked by a whole dataset reply. Keys deleted during the walk are skipped
+pub async fn dump_to_mode(conn: &mut Connection, file: &str, options: &Options) -> Result<()> {
+	let total = total_keys(conn).await?;
+	let mut snapshot = DatasetSnapshot::default();
+	let mut skipped = 0;
+	let mut cursor = 0;
+	loop {
+		let (next, keys) = parse_page(conn.request(scan_command(cursor, options)).await?)?;
+		for key in keys {
+			match read_entry(conn, &key).await? {
+				Some(entry) => snapshot.entries.push(entry),
+				None => skipped += 1,
+			}
+		}
+		progress(snapshot.entries.len(), total)?;
+		if next == 0 {
+			break;
+		}
+		cursor = next;
+	}
+	eprintln!();
+
+	tokio::fs::write(file, radish_database::encode_snapshot(&snapshot, 0)?).await?;
+	eprintln!("Dumped {} keys to {}, {} disappeared during the dump", snapshot.entries.len(), file, skipped);
+	Ok(())
+}
+
+/// Loads a snapshot file made by `--dump-to` or SAVE through IMPORT
+pub async fn import_from_mode(conn: &mut Connection, file: &str, options: &Options) -> Result<()> {
+	let data = tokio::fs::read(file).await?;
+	let (_, snapshot) = radish_database::decode_snapshot(&data, true)?;
+	let document = radish_database::snapshot_to_json(&snapshot).to_string().into_bytes();
+	let mode = if options.replace {"REPLACE"} else {"MERGE"};
+	let arguments = vec![Value::Buffer(document), Value::Buffer(mode.as_bytes().to_vec())];
+	match conn.request(command("IMPORT", arguments)).await? {
+		Value::Integer(count) => eprintln!("Imported {} keys from {}", count, file),
+		value => return Err(unexpected("IMPORT", value)),
+	}
+	Ok(())
+}
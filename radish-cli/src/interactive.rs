@@ -0,0 +1,82 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use rustyline::Editor;
+use rustyline::error::ReadlineError;
+
+use radish_client::{Arguments, Command};
+
+use super::Result;
+use super::options::Options;
+use super::connection::Connection;
+use super::completion::CommandCompleter;
+use super::output;
+
+async fn load_completer(conn: &mut Connection) -> Result<CommandCompleter> {
+	let cmd = Command {
+		command: "COMMAND".to_owned(),
+		arguments: Arguments::new(),
+	};
+	let reply = conn.request(cmd).await?;
+	Ok(CommandCompleter::from_command_reply(&reply))
+}
+
+/// Reads the next line on a blocking thread so the runtime is not stalled by the terminal
+async fn read_line(mut editor: Editor<CommandCompleter>, prompt: String) -> Result<(Editor<CommandCompleter>, Option<String>)> {
+	tokio::task::spawn_blocking(move || {
+		match editor.readline(&prompt) {
+			Ok(line) => {
+				editor.add_history_entry(&line[..]);
+				Ok((editor, Some(line)))
+			},
+			Err(ReadlineError::Eof) | Err(ReadlineError::Interrupted) => Ok((editor, None)),
+			Err(err) => Err(err.into()),
+		}
+	}).await?
+}
+
+pub async fn interactive_mode(conn: &mut Connection, addr: &str, options: &Options) -> Result<()> {
+	let completer = load_completer(conn).await?;
+	let mut editor = Editor::<CommandCompleter>::new();
+	editor.set_helper(Some(completer));
+
+	let prompt = format!("{}> ", addr);
+	loop {
+		let (next_editor, line) = read_line(editor, prompt.clone()).await?;
+		editor = next_editor;
+		let line = match line {
+			Some(line) => line,
+			None => break,
+		};
+
+		let args = super::parse_line(&line);
+		if args.is_empty() {
+			continue;
+		}
+		let cmd = super::new_command(&args[0], &args[1..]);
+		match conn.request(cmd).await {
+			Ok(result) => output::print_value(&result, options.output, &args[0]),
+			Err(err) => {
+				eprintln!("Failed to execute '{}': {}", args[0], err);
+				eprintln!("Reconnecting...");
+				conn.reconnect().await?;
+				eprintln!("Connected");
+			},
+		}
+	}
+	Ok(())
+}
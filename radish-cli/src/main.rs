@@ -14,14 +14,24 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+mod batch;
+mod bigkeys;
+mod completion;
+mod connection;
+mod dump;
+mod interactive;
+mod monitor;
+mod options;
+mod output;
+mod pipe;
+mod repeat;
+mod scan;
+
 use std::iter::FromIterator;
-use std::convert::TryFrom;
 use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use tokio::net::TcpStream;
-use tokio::io::{BufReader, AsyncReadExt, AsyncBufReadExt, AsyncWriteExt};
-
-use radish_types::*;
+use radish_client::{Address, ClientConfig, Command, RetryPolicy, TlsConfig, Value};
 
 fn arg_to_value(arg: &String) -> Value {
 	if arg.starts_with("'") && arg.ends_with("'") || arg.starts_with("\"") && arg.ends_with("\"") {
@@ -54,65 +64,159 @@ fn new_command(cmd: &String, args: &[String]) -> Command {
 	}
 }
 
-fn value_to_string (v: &Value) -> String {
-	match v {
-		Value::Nill => String::from("nill"),
-		Value::Ok => String::from("OK"),
-		Value::Bool(b) => format!("{}", b),
-		Value::Integer(i) => format!("{}", i),
-		Value::Float(n) => format!("{}", f64::from_bits(*n)),
-		Value::Buffer(b) => {
-			match std::str::from_utf8(&b[..]) {
-				Ok(s) => format!("{:?}", s),
-				Err(_) => format!("{:?}", &b[..]),
-			}
-		},
-		Value::Array(a) => format!("[{}]", a.iter().map(value_to_string).collect::<Vec<String>>().join(", ")),
-		Value::Error(e) => e.clone(),
-	}
+fn parse_line(line: &str) -> Vec<String> {
+	line.split(" ").map(|i|i.trim().to_owned()).filter(|s|!s.is_empty()).collect()
 }
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+/// Dump commands and replies to stderr, enabled by `--verbose`
+static VERBOSE: AtomicBool = AtomicBool::new(false);
 
-async fn request(sock: &mut TcpStream, cmd: Command) -> Result<Value> {
-	let buf = rmp_serde::to_vec(&cmd)?;
-	log::debug!("{:?}", buf);
-	let len = u32::try_from(buf.len())?;
-	sock.write_u32(len).await?;
-	sock.write_all(&buf[..]).await?;
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
-	let len = sock.read_u32().await?;
-	let mut buf = vec![0; len as usize];
-	sock.read_exact(&mut buf[..]).await?;
-	log::debug!("{:?}", buf);
+/// Exit codes: a nil reply is still a success
+const EXIT_SUCCESS: i32 = 0;
+const EXIT_ERROR_REPLY: i32 = 1;
+const EXIT_CONNECTION_FAILURE: i32 = 2;
+const EXIT_USAGE: i32 = 3;
+const EXIT_AUTH_FAILURE: i32 = 4;
 
-	Ok(rmp_serde::from_read_ref(&buf)?)
+fn exit_code(errors: usize) -> i32 {
+	if errors > 0 {EXIT_ERROR_REPLY} else {EXIT_SUCCESS}
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-	env_logger::init();
-
-	let addr = "127.0.0.1:6142";
-	let args: Vec<String> = std::env::args().collect();
+const DEFAULT_ADDR: &str = "127.0.0.1:6142";
+/// `host:port` of the server when there is no `-u`
+const ADDR_ENV: &str = "RADISH_ADDR";
 
-	let mut sock = TcpStream::connect(addr).await?;
+/// The address of `-u`, RADISH_ADDR or the default one, with the host and the port replaced by `-h` and `-p`
+fn server_address(options: &options::Options) -> Address {
+	let address = match &options.url {
+		Some(url) => url.address.clone(),
+		None => Address::Tcp(std::env::var(ADDR_ENV).unwrap_or_else(|_|DEFAULT_ADDR.to_owned())),
+	};
+	if options.host.is_none() && options.port.is_none() {
+		return address;
+	}
+	let base = match &address {
+		Address::Tcp(addr) => addr.clone(),
+		Address::Unix(_) => DEFAULT_ADDR.to_owned(),
+	};
+	let default_port = radish_client::DEFAULT_PORT.to_string();
+	let (host, port) = base.rsplit_once(':').unwrap_or((&base[..], &default_port));
+	let host = options.host.as_deref().unwrap_or(host);
+	let port = options.port.map_or_else(||port.to_owned(), |port|port.to_string());
+	Address::Tcp(format!("{}:{}", host, port))
+}
 
-	if args.len() > 1 {
-		let cmd = new_command(&args[1], &args[2..]);
-		let result = request(&mut sock, cmd).await?;
-		println!("{}", value_to_string(&result));
+/// Flags take precedence over the parts of the `-u` URL
+fn connect_options(options: &options::Options) -> Result<connection::ConnectOptions> {
+	let url = options.url.as_ref();
+	let password = if options.askpass {
+		Some(rpassword::read_password_from_tty(Some("Please input password: "))?)
+	} else if options.password.is_some() {
+		eprintln!("Warning: Using a password with '-a' option on the command line interface may not be safe.");
+		options.password.clone()
 	} else {
-		let mut lines = BufReader::new(tokio::io::stdin()).lines();
-		while let Ok(Some(line)) = lines.next_line().await {
-			let args: Vec<String> = line.split(" ").map(|i|i.trim().to_owned()).filter(|s|!s.is_empty()).collect();
-			let cmd = new_command(&args[0], &args[1..]);
-			let result = request(&mut sock, cmd).await?;
-			println!("{}", value_to_string(&result));
+		let password = url.and_then(|url|url.password.clone());
+		if password.is_some() {
+			eprintln!("Warning: Using a password in the '-u' URL on the command line interface may not be safe.");
 		}
+		password
+	};
+	let mut client = url.map_or_else(||ClientConfig {retry: RetryPolicy::none(), ..Default::default()}, |url|url.client.clone());
+	if options.tls {
+		client.tls = Some(TlsConfig {
+			cacert: options.cacert.as_ref().map(Into::into),
+			insecure: options.insecure,
+			sni: options.sni.clone(),
+		});
 	}
+	client.compression |= options.compression;
+	Ok(connection::ConnectOptions {
+		address: server_address(options),
+		user: options.user.clone().or_else(||url.and_then(|url|url.user.clone())),
+		password,
+		db: url.and_then(|url|url.db),
+		client,
+	})
+}
 
-	Ok(())
+async fn run(options: &options::Options) -> Result<i32> {
+	let connect_options = connect_options(options)?;
+	let addr = connect_options.address.to_string();
+	let mut conn = connection::Connection::connect(connect_options).await?;
+
+	if options.pipe {
+		let summary = pipe::pipe_mode(&mut conn).await?;
+		println!("All data transferred. errors: {}, replies: {}", summary.errors, summary.replies);
+		Ok(exit_code(summary.errors))
+	} else if options.latency {
+		monitor::latency_mode(&mut conn).await?;
+		Ok(EXIT_SUCCESS)
+	} else if options.stat {
+		monitor::stat_mode(&mut conn).await?;
+		Ok(EXIT_SUCCESS)
+	} else if options.bigkeys || options.memkeys {
+		bigkeys::bigkeys_mode(&mut conn, options).await?;
+		Ok(EXIT_SUCCESS)
+	} else if let Some(file) = &options.export {
+		dump::export_mode(&mut conn, file, options).await?;
+		Ok(EXIT_SUCCESS)
+	} else if let Some(file) = &options.import {
+		dump::import_mode(&mut conn, file, options).await?;
+		Ok(EXIT_SUCCESS)
+	} else if let Some(file) = &options.dump_to {
+		dump::dump_to_mode(&mut conn, file, options).await?;
+		Ok(EXIT_SUCCESS)
+	} else if let Some(file) = &options.import_from {
+		dump::import_from_mode(&mut conn, file, options).await?;
+		Ok(EXIT_SUCCESS)
+	} else if options.scan {
+		let summary = scan::scan_mode(&mut conn, options).await?;
+		Ok(exit_code(summary.errors))
+	} else if let Some(file) = &options.file {
+		let script = tokio::fs::read_to_string(file).await?;
+		let commands = batch::parse_script(&script);
+		let summary = batch::run_batch(&mut conn, commands, options, true).await?;
+		Ok(exit_code(summary.errors))
+	} else if !options.command.is_empty() {
+		let commands = batch::split_groups(&options.command);
+		if commands.len() == 1 {
+			let errors = repeat::repeat_command(&mut conn, options).await?;
+			Ok(exit_code(errors))
+		} else {
+			let summary = batch::run_batch(&mut conn, commands, options, false).await?;
+			Ok(exit_code(summary.errors))
+		}
+	} else {
+		interactive::interactive_mode(&mut conn, &addr, options).await?;
+		Ok(EXIT_SUCCESS)
+	}
 }
 
+#[tokio::main]
+async fn main() {
+	env_logger::init();
+
+	let options = match options::Options::parse(std::env::args().skip(1)) {
+		Ok(options) => options,
+		Err(err) => {
+			eprintln!("{}", err);
+			std::process::exit(EXIT_USAGE);
+		},
+	};
+	VERBOSE.store(options.verbose, Ordering::Relaxed);
 
+	let code = match run(&options).await {
+		Ok(code) => code,
+		Err(err) if err.downcast_ref::<connection::AuthError>().is_some() => {
+			eprintln!("{}", err);
+			EXIT_AUTH_FAILURE
+		},
+		Err(err) => {
+			eprintln!("Error: {}", err);
+			EXIT_CONNECTION_FAILURE
+		},
+	};
+	std::process::exit(code);
+}
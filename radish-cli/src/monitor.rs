@@ -0,0 +1,198 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::io::Write;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use radish_client::{Arguments, Command, Value};
+
+use super::Result;
+use super::connection::Connection;
+
+const LATENCY_SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+const STAT_HEADER_EVERY: usize = 20;
+
+pub struct LatencyStats {
+	pub min: Duration,
+	pub max: Duration,
+	pub last: Duration,
+	pub total: Duration,
+	pub samples: u32,
+}
+
+impl LatencyStats {
+	pub fn new() -> Self {
+		Self {
+			min: Duration::from_secs(u64::MAX),
+			max: Duration::from_secs(0),
+			last: Duration::from_secs(0),
+			total: Duration::from_secs(0),
+			samples: 0,
+		}
+	}
+
+	pub fn add(&mut self, sample: Duration) {
+		self.min = std::cmp::min(self.min, sample);
+		self.max = std::cmp::max(self.max, sample);
+		self.last = sample;
+		self.total += sample;
+		self.samples += 1;
+	}
+
+	pub fn avg(&self) -> Duration {
+		match self.samples {
+			0 => Duration::from_secs(0),
+			n => self.total / n,
+		}
+	}
+}
+
+impl std::fmt::Display for LatencyStats {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+		write!(f, "min: {:.2}, max: {:.2}, avg: {:.2}, last: {:.2} ms ({} samples)",
+			ms(self.min), ms(self.max), ms(self.avg()), ms(self.last), self.samples)
+	}
+}
+
+fn command(name: &str) -> Command {
+	Command {
+		command: name.to_owned(),
+		arguments: Arguments::new(),
+	}
+}
+
+/// Sends PING in a loop and prints round trip statistics once per second until Ctrl-C
+pub async fn latency_mode(conn: &mut Connection) -> Result<()> {
+	let ctrl_c = tokio::signal::ctrl_c();
+	tokio::pin!(ctrl_c);
+
+	let mut stats = LatencyStats::new();
+	let mut last_refresh = Instant::now();
+	loop {
+		let start = Instant::now();
+		let reply = tokio::select! {
+			_ = &mut ctrl_c => break,
+			reply = conn.request(command("PING")) => reply?,
+		};
+		stats.add(start.elapsed());
+		if let Value::Error(err) = reply {
+			return Err(err.into());
+		}
+
+		if last_refresh.elapsed() >= REFRESH_INTERVAL {
+			print!("\r{}", stats);
+			std::io::stdout().flush()?;
+			last_refresh = Instant::now();
+		}
+		tokio::select! {
+			_ = &mut ctrl_c => break,
+			_ = tokio::time::delay_for(LATENCY_SAMPLE_INTERVAL) => (),
+		}
+	}
+	println!("\r{}", stats);
+	Ok(())
+}
+
+pub fn parse_info(info: &[u8]) -> HashMap<String, String> {
+	String::from_utf8_lossy(info)
+	.lines()
+	.filter(|line|!line.starts_with('#'))
+	.filter_map(|line| {
+		let mut parts = line.splitn(2, ':');
+		match (parts.next(), parts.next()) {
+			(Some(field), Some(value)) => Some((field.trim().to_owned(), value.trim().to_owned())),
+			_ => None,
+		}
+	})
+	.collect()
+}
+
+/// Polls INFO once per second and prints a rolling view until Ctrl-C
+pub async fn stat_mode(conn: &mut Connection) -> Result<()> {
+	let ctrl_c = tokio::signal::ctrl_c();
+	tokio::pin!(ctrl_c);
+
+	let mut previous_requests: Option<u64> = None;
+	let mut rows = 0;
+	loop {
+		let reply = tokio::select! {
+			_ = &mut ctrl_c => break,
+			reply = conn.request(command("INFO")) => reply?,
+		};
+		let info = match reply {
+			Value::Buffer(info) => parse_info(&info),
+			Value::Error(err) => return Err(err.into()),
+			other => return Err(format!("Unexpected INFO reply: {}", other).into()),
+		};
+
+		if rows % STAT_HEADER_EVERY == 0 {
+			println!("{:<12}{:<12}{:<12}{:<24}", "keys", "mem", "clients", "requests");
+		}
+		rows += 1;
+
+		let field = |name: &str| info.get(name).cloned().unwrap_or_else(||"-".to_owned());
+		let requests = info.get("total_commands_processed").and_then(|r|r.parse::<u64>().ok());
+		let requests = match (requests, previous_requests) {
+			(Some(now), Some(before)) => format!("{} (+{})", now, now.saturating_sub(before)),
+			(Some(now), None) => format!("{}", now),
+			(None, _) => "-".to_owned(),
+		};
+		previous_requests = info.get("total_commands_processed").and_then(|r|r.parse::<u64>().ok());
+		println!("{:<12}{:<12}{:<12}{:<24}", field("keys"), field("used_memory_human"), field("connected_clients"), requests);
+
+		tokio::select! {
+			_ = &mut ctrl_c => break,
+			_ = tokio::time::delay_for(REFRESH_INTERVAL) => (),
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ms(ms: u64) -> Duration {
+		Duration::from_millis(ms)
+	}
+
+	#[test]
+	fn latency_stats() {
+		let mut stats = LatencyStats::new();
+		assert_eq!(stats.avg(), ms(0));
+		for sample in &[4, 1, 7, 2] {
+			stats.add(ms(*sample));
+		}
+		assert_eq!(stats.min, ms(1));
+		assert_eq!(stats.max, ms(7));
+		assert_eq!(stats.last, ms(2));
+		assert_eq!(stats.avg(), Duration::from_micros(3500));
+		assert_eq!(stats.to_string(), "min: 1.00, max: 7.00, avg: 3.50, last: 2.00 ms (4 samples)");
+	}
+
+	#[test]
+	fn info_fields() {
+		let info = parse_info(b"# Server\r\nuptime_in_seconds:12\r\n\r\n# Keyspace\r\nkeys: 3 \r\nbroken line\r\nurl:redis://h:1\r\n");
+		assert_eq!(info.len(), 3);
+		assert_eq!(info["uptime_in_seconds"], "12");
+		assert_eq!(info["keys"], "3");
+		assert_eq!(info["url"], "redis://h:1");
+	}
+}
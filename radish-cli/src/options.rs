@@ -0,0 +1,240 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::time::Duration;
+
+use radish_client::ConnectionInfo;
+
+use super::output::OutputMode;
+
+pub struct Options {
+	pub pipe: bool,
+	pub latency: bool,
+	pub stat: bool,
+	pub repeat: i64,
+	pub interval: Option<Duration>,
+	pub quiet: bool,
+	pub file: Option<String>,
+	pub abort_on_error: bool,
+	pub output: OutputMode,
+	pub verbose: bool,
+	/// Server address and defaults for the connection flags from `-u`
+	pub url: Option<ConnectionInfo>,
+	/// Replace the host and the port of the address from `-u`
+	pub host: Option<String>,
+	pub port: Option<u16>,
+	pub user: Option<String>,
+	pub password: Option<String>,
+	pub askpass: bool,
+	pub tls: bool,
+	pub cacert: Option<String>,
+	pub insecure: bool,
+	pub sni: Option<String>,
+	pub compression: bool,
+	pub scan: bool,
+	pub pattern: Option<String>,
+	pub count: Option<i64>,
+	pub scan_type: Option<String>,
+	pub delete: bool,
+	pub no_confirm: bool,
+	pub bigkeys: bool,
+	pub memkeys: bool,
+	pub export: Option<String>,
+	pub import: Option<String>,
+	pub replace: bool,
+	pub dump_to: Option<String>,
+	pub import_from: Option<String>,
+	pub command: Vec<String>,
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Self {
+			pipe: false,
+			latency: false,
+			stat: false,
+			repeat: 1,
+			interval: None,
+			quiet: false,
+			file: None,
+			abort_on_error: false,
+			output: OutputMode::Pretty,
+			verbose: false,
+			url: None,
+			host: None,
+			port: None,
+			user: None,
+			password: None,
+			askpass: false,
+			tls: false,
+			cacert: None,
+			insecure: false,
+			sni: None,
+			compression: false,
+			scan: false,
+			pattern: None,
+			count: None,
+			scan_type: None,
+			delete: false,
+			no_confirm: false,
+			bigkeys: false,
+			memkeys: false,
+			export: None,
+			import: None,
+			replace: false,
+			dump_to: None,
+			import_from: None,
+			command: Vec::new(),
+		}
+	}
+}
+
+fn next_value(args: &mut impl Iterator<Item=String>, flag: &str) -> Result<String, String> {
+	args.next().ok_or_else(||format!("Option '{}' requires a value", flag))
+}
+
+impl Options {
+	/// Parses flags up to the first argument which is not a flag; the rest is a command
+	pub fn parse(mut args: impl Iterator<Item=String>) -> Result<Self, String> {
+		let mut options = Self::default();
+		while let Some(arg) = args.next() {
+			match &arg[..] {
+				"--pipe" => options.pipe = true,
+				"--latency" => options.latency = true,
+				"--stat" => options.stat = true,
+				"-q" => options.quiet = true,
+				"--abort-on-error" => options.abort_on_error = true,
+				"--raw" => options.output = OutputMode::Raw,
+				"-o" => {
+					let mode = next_value(&mut args, &arg)?;
+					options.output = OutputMode::parse(&mode).ok_or_else(||format!("Unknown output mode '{}': use pretty, raw or json", mode))?;
+				},
+				"--verbose" => options.verbose = true,
+				"-u" => {
+					let url = next_value(&mut args, &arg)?;
+					options.url = Some(ConnectionInfo::parse(&url).map_err(|e|e.to_string())?);
+				},
+				"-h" => options.host = Some(next_value(&mut args, &arg)?),
+				"-p" => {
+					let port = next_value(&mut args, &arg)?;
+					options.port = Some(port.parse::<u16>().map_err(|_|format!("Invalid port '{}'", port))?);
+				},
+				"-a" => options.password = Some(next_value(&mut args, &arg)?),
+				"--user" => options.user = Some(next_value(&mut args, &arg)?),
+				"--askpass" => options.askpass = true,
+				"--tls" => options.tls = true,
+				"--cacert" => options.cacert = Some(next_value(&mut args, &arg)?),
+				"--insecure" => options.insecure = true,
+				"--sni" => options.sni = Some(next_value(&mut args, &arg)?),
+				"--compression" => options.compression = true,
+				"--scan" => options.scan = true,
+				"--pattern" => options.pattern = Some(next_value(&mut args, &arg)?),
+				"--count" => {
+					let count = next_value(&mut args, &arg)?;
+					match count.parse::<i64>() {
+						Ok(count) if count > 0 => options.count = Some(count),
+						_ => return Err(format!("Invalid count '{}': a positive number is expected", count)),
+					}
+				},
+				"--type" => options.scan_type = Some(next_value(&mut args, &arg)?),
+				"--delete" => options.delete = true,
+				"--no-confirm" => options.no_confirm = true,
+				"--bigkeys" => options.bigkeys = true,
+				"--memkeys" => options.memkeys = true,
+				"--export" => options.export = Some(next_value(&mut args, &arg)?),
+				"--import" => options.import = Some(next_value(&mut args, &arg)?),
+				"--replace" => options.replace = true,
+				"--dump-to" => options.dump_to = Some(next_value(&mut args, &arg)?),
+				"--import-from" => options.import_from = Some(next_value(&mut args, &arg)?),
+				"--file" => options.file = Some(next_value(&mut args, &arg)?),
+				"-r" => {
+					let repeat = next_value(&mut args, &arg)?;
+					options.repeat = repeat.parse::<i64>().map_err(|e|format!("Invalid repeat count '{}': {}", repeat, e))?;
+					if options.repeat < -1 {
+						return Err(format!("Invalid repeat count '{}': use -1 to repeat forever", repeat));
+					}
+				},
+				"-i" => {
+					let interval = next_value(&mut args, &arg)?;
+					let seconds = interval.parse::<f64>().map_err(|e|format!("Invalid interval '{}': {}", interval, e))?;
+					if !seconds.is_finite() || seconds < 0.0 {
+						return Err(format!("Invalid interval '{}'", interval));
+					}
+					options.interval = Some(Duration::from_secs_f64(seconds));
+				},
+				_ => {
+					options.command.push(arg);
+					options.command.extend(args);
+					break;
+				},
+			}
+		}
+		if options.delete && !options.scan {
+			return Err("Option '--delete' requires '--scan'".to_owned());
+		}
+		if options.replace && options.import.is_none() && options.import_from.is_none() {
+			return Err("Option '--replace' requires '--import' or '--import-from'".to_owned());
+		}
+		Ok(options)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(args: &[&str]) -> Result<Options, String> {
+		Options::parse(args.iter().map(|arg|arg.to_string()))
+	}
+
+	fn error(args: &[&str]) -> String {
+		parse(args).err().expect("the options must be rejected")
+	}
+
+	#[test]
+	fn repeat_and_interval() {
+		let options = parse(&["-r", "100", "-i", "0.5", "INCR", "counter"]).unwrap();
+		assert_eq!(options.repeat, 100);
+		assert_eq!(options.interval, Some(Duration::from_millis(500)));
+		assert_eq!(options.command, vec!["INCR", "counter"]);
+
+		let options = parse(&["-r", "-1", "-q", "PING"]).unwrap();
+		assert_eq!(options.repeat, -1);
+		assert!(options.quiet);
+		assert_eq!(options.interval, None);
+
+		let options = parse(&["PING"]).unwrap();
+		assert_eq!(options.repeat, 1);
+	}
+
+	#[test]
+	fn flags_after_the_command_are_arguments() {
+		let options = parse(&["GET", "-r", "3"]).unwrap();
+		assert_eq!(options.repeat, 1);
+		assert_eq!(options.command, vec!["GET", "-r", "3"]);
+	}
+
+	#[test]
+	fn invalid_repeat_and_interval() {
+		assert!(error(&["-r"]).contains("requires a value"));
+		assert!(error(&["-r", "x", "PING"]).starts_with("Invalid repeat count 'x'"));
+		assert!(error(&["-r", "-2", "PING"]).contains("use -1 to repeat forever"));
+		assert!(error(&["-i", "-1", "PING"]).starts_with("Invalid interval '-1'"));
+		assert!(error(&["-i", "inf", "PING"]).starts_with("Invalid interval 'inf'"));
+		assert!(error(&["-i", "soon", "PING"]).starts_with("Invalid interval 'soon'"));
+	}
+}
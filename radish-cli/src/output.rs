@@ -0,0 +1,316 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::io::Write;
+use std::collections::VecDeque;
+
+use radish_client::Value;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutputMode {
+	/// Human readable output similar to redis-cli
+	Pretty,
+	/// Values as is without any decoration, one per line
+	Raw,
+	/// Every reply as a single JSON document
+	Json,
+}
+
+impl OutputMode {
+	pub fn parse(mode: &str) -> Option<Self> {
+		match &mode.to_lowercase()[..] {
+			"pretty" => Some(OutputMode::Pretty),
+			"raw" => Some(OutputMode::Raw),
+			"json" => Some(OutputMode::Json),
+			_ => None,
+		}
+	}
+}
+
+/// Replies of these commands are flat arrays of field/value pairs
+fn is_map_reply(command: &str) -> bool {
+	matches!(&command.to_uppercase()[..], "HGETALL")
+}
+
+fn escape_buffer(buf: &[u8]) -> String {
+	let mut out = String::with_capacity(buf.len() + 2);
+	out.push('"');
+	for &ch in buf {
+		match ch {
+			b'"' => out.push_str("\\\""),
+			b'\\' => out.push_str("\\\\"),
+			b'\n' => out.push_str("\\n"),
+			b'\r' => out.push_str("\\r"),
+			b'\t' => out.push_str("\\t"),
+			0x20..=0x7e => out.push(ch as char),
+			_ => out.push_str(&format!("\\x{:02x}", ch)),
+		}
+	}
+	out.push('"');
+	out
+}
+
+fn format_pretty_scalar(value: &Value) -> String {
+	match value {
+		Value::Nill => "(nil)".to_owned(),
+		Value::Ok => "OK".to_owned(),
+		Value::Bool(b) => format!("(boolean) {}", b),
+		Value::Integer(i) => format!("(integer) {}", i),
+		Value::Float(n) => format!("(double) {}", f64::from_bits(*n)),
+		Value::Buffer(b) => match std::str::from_utf8(b) {
+			Ok(s) if !s.chars().any(char::is_control) => format!("{:?}", s),
+			_ => escape_buffer(b),
+		},
+		Value::Error(e) => format!("(error) {}", e),
+		Value::Array(_) => unreachable!("arrays are formatted by format_pretty_lines"),
+	}
+}
+
+fn format_pretty_lines(value: &Value, map: bool) -> Vec<String> {
+	let items = match value {
+		Value::Array(items) => items,
+		scalar => return vec![format_pretty_scalar(scalar)],
+	};
+	if items.is_empty() {
+		return vec!["(empty array)".to_owned()];
+	}
+
+	let mut lines = Vec::new();
+	if map && items.len() % 2 == 0 {
+		let count = items.len() / 2;
+		let width = count.to_string().len();
+		for i in 0..count {
+			let field = format_pretty_lines(&items[2 * i], false).join(" ");
+			let prefix = format!("{:>w$}# {} => ", i + 1, field, w = width);
+			push_nested(&mut lines, prefix, format_pretty_lines(&items[2 * i + 1], false));
+		}
+	} else {
+		let width = items.len().to_string().len();
+		for (i, item) in items.iter().enumerate() {
+			let prefix = format!("{:>w$}) ", i + 1, w = width);
+			push_nested(&mut lines, prefix, format_pretty_lines(item, false));
+		}
+	}
+	lines
+}
+
+fn push_nested(lines: &mut Vec<String>, prefix: String, nested: Vec<String>) {
+	let indent = " ".repeat(prefix.chars().count());
+	for (i, line) in nested.into_iter().enumerate() {
+		if i == 0 {
+			lines.push(format!("{}{}", prefix, line));
+		} else {
+			lines.push(format!("{}{}", indent, line));
+		}
+	}
+}
+
+fn format_raw(value: &Value, out: &mut Vec<u8>) {
+	match value {
+		Value::Nill => (),
+		Value::Ok => out.extend_from_slice(b"OK"),
+		Value::Bool(b) => out.extend_from_slice(if *b {b"1"} else {b"0"}),
+		Value::Integer(i) => out.extend_from_slice(i.to_string().as_bytes()),
+		Value::Float(n) => out.extend_from_slice(f64::from_bits(*n).to_string().as_bytes()),
+		Value::Buffer(b) => out.extend_from_slice(b),
+		Value::Error(e) => out.extend_from_slice(e.as_bytes()),
+		Value::Array(items) => {
+			for (i, item) in items.iter().enumerate() {
+				if i > 0 {
+					out.push(b'\n');
+				}
+				format_raw(item, out);
+			}
+		},
+	}
+}
+
+/// Buffers which are not valid UTF-8 are written as `{"base64": "..."}`
+fn buffer_to_json(buf: &[u8]) -> serde_json::Value {
+	match std::str::from_utf8(buf) {
+		Ok(s) => serde_json::Value::String(s.to_owned()),
+		Err(_) => serde_json::json!({"base64": base64::encode(buf)}),
+	}
+}
+
+/// Flat field/value arrays become objects if every field is a UTF-8 buffer
+fn map_to_json(items: &VecDeque<Value>) -> Option<serde_json::Value> {
+	let mut fields = serde_json::Map::with_capacity(items.len() / 2);
+	let mut items = items.iter();
+	while let Some(field) = items.next() {
+		let field = match field {
+			Value::Buffer(field) => std::str::from_utf8(field).ok()?,
+			_ => return None,
+		};
+		fields.insert(field.to_owned(), to_json(items.next()?, false));
+	}
+	Some(serde_json::Value::Object(fields))
+}
+
+pub fn to_json(value: &Value, map: bool) -> serde_json::Value {
+	match value {
+		Value::Nill => serde_json::Value::Null,
+		Value::Ok => serde_json::Value::String("OK".to_owned()),
+		Value::Bool(b) => serde_json::Value::Bool(*b),
+		Value::Integer(i) => serde_json::Value::from(*i),
+		Value::Float(n) => serde_json::Number::from_f64(f64::from_bits(*n)).map_or(serde_json::Value::Null, serde_json::Value::Number),
+		Value::Buffer(b) => buffer_to_json(b),
+		Value::Error(e) => serde_json::json!({"error": e}),
+		Value::Array(items) => {
+			let object = if map {map_to_json(items)} else {None};
+			object.unwrap_or_else(||serde_json::Value::Array(items.iter().map(|item|to_json(item, false)).collect()))
+		},
+	}
+}
+
+/// Formats the reply of `command` without a trailing newline
+pub fn format_value(value: &Value, mode: OutputMode, command: &str) -> Vec<u8> {
+	match mode {
+		OutputMode::Pretty => format_pretty_lines(value, is_map_reply(command)).join("\n").into_bytes(),
+		OutputMode::Raw => {
+			let mut out = Vec::new();
+			format_raw(value, &mut out);
+			out
+		},
+		OutputMode::Json => to_json(value, is_map_reply(command)).to_string().into_bytes(),
+	}
+}
+
+pub fn print_value(value: &Value, mode: OutputMode, command: &str) {
+	print_prefixed_value("", value, mode, command)
+}
+
+/// Error replies go to stderr, everything else to stdout; nil is omitted in raw mode.
+/// JSON documents are printed without the prefix to keep every line parseable
+pub fn print_prefixed_value(prefix: &str, value: &Value, mode: OutputMode, command: &str) {
+	if mode == OutputMode::Raw && *value == Value::Nill {
+		return;
+	}
+	let prefix = if mode == OutputMode::Json {""} else {prefix};
+	let mut out = Vec::from(prefix.as_bytes());
+	out.extend(format_value(value, mode, command));
+	out.push(b'\n');
+
+	let written = match value {
+		Value::Error(_) => {
+			let stderr = std::io::stderr();
+			let mut stderr = stderr.lock();
+			stderr.write_all(&out).and_then(|_|stderr.flush())
+		},
+		_ => {
+			let stdout = std::io::stdout();
+			let mut stdout = stdout.lock();
+			stdout.write_all(&out).and_then(|_|stdout.flush())
+		},
+	};
+	if let Err(err) = written {
+		log::error!("Failed to write the reply: {}", err);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn buf(s: &str) -> Value {
+		Value::Buffer(Vec::from(s.as_bytes()))
+	}
+
+	fn array(items: Vec<Value>) -> Value {
+		Value::Array(items.into_iter().collect())
+	}
+
+	fn format(value: &Value, mode: OutputMode, command: &str) -> String {
+		String::from_utf8(format_value(value, mode, command)).unwrap()
+	}
+
+	fn nested() -> Value {
+		array(vec![buf("a"), array(vec![Value::Integer(1), Value::Nill, array(vec![])]), Value::Nill])
+	}
+
+	fn map() -> Value {
+		array(vec![buf("name"), buf("radish"), buf("size"), buf("10")])
+	}
+
+	#[test]
+	fn pretty() {
+		assert_eq!(format(&Value::Nill, OutputMode::Pretty, "GET"), "(nil)");
+		assert_eq!(format(&Value::Integer(5), OutputMode::Pretty, "INCR"), "(integer) 5");
+		assert_eq!(format(&Value::Error("ERR x".to_owned()), OutputMode::Pretty, "GET"), "(error) ERR x");
+		assert_eq!(format(&buf("a\x01"), OutputMode::Pretty, "GET"), "\"a\\x01\"");
+		assert_eq!(format(&nested(), OutputMode::Pretty, "LRANGE"), "1) \"a\"\n2) 1) (integer) 1\n   2) (nil)\n   3) (empty array)\n3) (nil)");
+		assert_eq!(format(&map(), OutputMode::Pretty, "hgetall"), "1# \"name\" => \"radish\"\n2# \"size\" => \"10\"");
+		assert_eq!(format(&map(), OutputMode::Pretty, "LRANGE"), "1) \"name\"\n2) \"radish\"\n3) \"size\"\n4) \"10\"");
+	}
+
+	#[test]
+	fn pretty_aligns_indexes() {
+		let items = (0..10).map(Value::Integer).collect();
+		let lines = format(&array(items), OutputMode::Pretty, "LRANGE");
+		let lines: Vec<&str> = lines.lines().collect();
+		assert_eq!(lines[0], " 1) (integer) 0");
+		assert_eq!(lines[9], "10) (integer) 9");
+	}
+
+	#[test]
+	fn raw() {
+		assert_eq!(format(&Value::Nill, OutputMode::Raw, "GET"), "");
+		assert_eq!(format(&Value::Integer(5), OutputMode::Raw, "INCR"), "5");
+		assert_eq!(format(&buf("a \"b\""), OutputMode::Raw, "GET"), "a \"b\"");
+		assert_eq!(format(&nested(), OutputMode::Raw, "LRANGE"), "a\n1\n\n\n");
+		assert_eq!(format(&map(), OutputMode::Raw, "HGETALL"), "name\nradish\nsize\n10");
+	}
+
+	#[test]
+	fn json() {
+		assert_eq!(format(&Value::Nill, OutputMode::Json, "GET"), "null");
+		assert_eq!(format(&Value::Integer(5), OutputMode::Json, "INCR"), "5");
+		assert_eq!(format(&Value::Error("ERR x".to_owned()), OutputMode::Json, "GET"), r#"{"error":"ERR x"}"#);
+		assert_eq!(format(&Value::Buffer(vec![0xff]), OutputMode::Json, "GET"), r#"{"base64":"/w=="}"#);
+		assert_eq!(format(&nested(), OutputMode::Json, "LRANGE"), r#"["a",[1,null,[]],null]"#);
+		assert_eq!(format(&map(), OutputMode::Json, "HGETALL"), r#"{"name":"radish","size":"10"}"#);
+		assert_eq!(format(&array(vec![Value::Integer(1), buf("v")]), OutputMode::Json, "HGETALL"), r#"[1,"v"]"#);
+	}
+
+	#[test]
+	fn json_of_every_variant() {
+		let json = |value: &Value|to_json(value, false);
+		assert_eq!(json(&Value::Nill), serde_json::Value::Null);
+		assert_eq!(json(&Value::Ok), serde_json::json!("OK"));
+		assert_eq!(json(&Value::Bool(true)), serde_json::json!(true));
+		assert_eq!(json(&Value::Integer(-7)), serde_json::json!(-7));
+		assert_eq!(json(&Value::Float(1.5f64.to_bits())), serde_json::json!(1.5));
+		assert_eq!(json(&Value::Float(f64::NAN.to_bits())), serde_json::Value::Null);
+		assert_eq!(json(&buf("\u{444}\n\"")), serde_json::json!("\u{444}\n\""));
+		assert_eq!(json(&Value::Buffer(vec![b'a', 0xc3])), serde_json::json!({"base64": "YcM="}));
+		assert_eq!(json(&Value::Error("WRONGTYPE x".to_owned())), serde_json::json!({"error": "WRONGTYPE x"}));
+		assert_eq!(json(&array(vec![])), serde_json::json!([]));
+		assert_eq!(json(&array(vec![Value::Ok, array(vec![Value::Bool(false)])])), serde_json::json!(["OK", [false]]));
+	}
+
+	#[test]
+	fn json_maps() {
+		let map = |value: &Value|to_json(value, true);
+		assert_eq!(map(&array(vec![])), serde_json::json!({}));
+		assert_eq!(map(&array(vec![buf("f"), array(vec![Value::Integer(1)])])), serde_json::json!({"f": [1]}));
+		// not a map: odd length, non-buffer or non-UTF-8 field
+		assert_eq!(map(&array(vec![buf("f")])), serde_json::json!(["f"]));
+		assert_eq!(map(&array(vec![Value::Nill, buf("v")])), serde_json::json!([null, "v"]));
+		assert_eq!(map(&array(vec![Value::Buffer(vec![0xff]), buf("v")])), serde_json::json!([{"base64": "/w=="}, "v"]));
+		assert_eq!(map(&Value::Integer(1)), serde_json::json!(1));
+	}
+}
@@ -0,0 +1,72 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use tokio::sync::mpsc;
+use tokio::io::{BufReader, AsyncBufReadExt};
+
+use radish_client::{Command, Value};
+
+use super::Result;
+use super::connection::Connection;
+
+/// Maximum number of commands sent without a reply yet
+const PIPE_WINDOW: usize = 1000;
+
+pub struct PipeSummary {
+	pub replies: usize,
+	pub errors: usize,
+}
+
+async fn read_commands(mut commands: mpsc::Sender<Command>) -> Result<()> {
+	let mut lines = BufReader::new(tokio::io::stdin()).lines();
+	while let Some(line) = lines.next_line().await? {
+		let args = super::parse_line(&line);
+		if args.is_empty() {
+			continue;
+		}
+		let cmd = super::new_command(&args[0], &args[1..]);
+		commands.send(cmd).await.map_err(|_|"Reply reader is gone".to_owned())?;
+	}
+	Ok(())
+}
+
+/// Sends the commands already read from stdin as one pipeline, up to `PIPE_WINDOW` at once
+pub async fn pipe_mode(conn: &mut Connection) -> Result<PipeSummary> {
+	let (commands, mut pending) = mpsc::channel(PIPE_WINDOW);
+	let reader = tokio::spawn(read_commands(commands));
+
+	let mut summary = PipeSummary {
+		replies: 0,
+		errors: 0,
+	};
+	while let Some(cmd) = pending.recv().await {
+		let mut batch = vec![cmd];
+		while batch.len() < PIPE_WINDOW {
+			match pending.try_recv() {
+				Ok(cmd) => batch.push(cmd),
+				Err(_) => break,
+			}
+		}
+		for value in conn.pipeline(batch).await? {
+			if let Value::Error(err) = value {
+				eprintln!("{}", err);
+				summary.errors += 1;
+			}
+			summary.replies += 1;
+		}
+	}
+	reader.await??;
+	Ok(summary)
+}
@@ -0,0 +1,70 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use radish_client::Value;
+
+use super::Result;
+use super::connection::Connection;
+use super::options::Options;
+use super::output;
+
+/// Executes the command `options.repeat` times (forever for -1) until Ctrl-C
+/// and returns the number of error replies
+pub async fn repeat_command(conn: &mut Connection, options: &Options) -> Result<usize> {
+	let args = &options.command;
+	let args = args.split(|arg|arg == super::batch::SEPARATOR).find(|group|!group.is_empty()).unwrap_or(args);
+	let ctrl_c = tokio::signal::ctrl_c();
+	tokio::pin!(ctrl_c);
+
+	let mut completed: i64 = 0;
+	let mut errors = 0;
+	let mut last: Option<Value> = None;
+	while options.repeat < 0 || completed < options.repeat {
+		if completed > 0 {
+			if let Some(interval) = options.interval {
+				tokio::select! {
+					_ = &mut ctrl_c => break,
+					_ = tokio::time::delay_for(interval) => (),
+				}
+			}
+		}
+
+		let cmd = super::new_command(&args[0], &args[1..]);
+		let result = tokio::select! {
+			_ = &mut ctrl_c => break,
+			result = conn.request(cmd) => result?,
+		};
+		if !options.quiet {
+			output::print_value(&result, options.output, &args[0]);
+		}
+		if let Value::Error(_) = result {
+			errors += 1;
+		}
+		last = Some(result);
+		completed += 1;
+	}
+
+	if options.quiet {
+		if let Some(last) = &last {
+			output::print_value(last, options.output, &args[0]);
+		}
+	}
+	if options.repeat != 1 {
+		eprintln!("{} iterations completed", completed);
+	}
+	Ok(errors)
+}
@@ -0,0 +1,148 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::io::Write;
+
+use radish_client::{Arguments, Command, Value};
+
+use super::Result;
+use super::connection::Connection;
+use super::options::Options;
+
+pub struct ScanSummary {
+	pub matched: usize,
+	pub deleted: usize,
+	pub errors: usize,
+}
+
+fn buffer(value: &str) -> Value {
+	Value::Buffer(value.as_bytes().to_vec())
+}
+
+pub fn scan_command(cursor: i64, options: &Options) -> Command {
+	let mut arguments = Arguments::new();
+	arguments.push_back(Value::Integer(cursor));
+	if let Some(pattern) = &options.pattern {
+		arguments.push_back(buffer("MATCH"));
+		arguments.push_back(buffer(pattern));
+	}
+	if let Some(count) = options.count {
+		arguments.push_back(buffer("COUNT"));
+		arguments.push_back(Value::Integer(count));
+	}
+	if let Some(scan_type) = &options.scan_type {
+		arguments.push_back(buffer("TYPE"));
+		arguments.push_back(buffer(scan_type));
+	}
+	Command {
+		command: "SCAN".to_owned(),
+		arguments,
+	}
+}
+
+/// Splits a SCAN reply into the next cursor and the keys of the page
+pub fn parse_page(reply: Value) -> Result<(i64, Vec<Vec<u8>>)> {
+	let mut items = match reply {
+		Value::Error(err) => return Err(err.into()),
+		Value::Array(items) if items.len() == 2 => items,
+		reply => return Err(format!("Unexpected SCAN reply: {:?}", reply).into()),
+	};
+	let keys = match items.pop_back() {
+		Some(Value::Array(keys)) => keys,
+		reply => return Err(format!("Unexpected SCAN keys: {:?}", reply).into()),
+	};
+	let cursor = match items.pop_front() {
+		Some(Value::Integer(cursor)) => cursor,
+		reply => return Err(format!("Unexpected SCAN cursor: {:?}", reply).into()),
+	};
+	let keys = keys.into_iter()
+		.map(|key| match key {
+			Value::Buffer(key) => Ok(key),
+			key => Err(format!("Unexpected SCAN key: {:?}", key).into()),
+		})
+		.collect::<Result<_>>()?;
+	Ok((cursor, keys))
+}
+
+fn confirm(options: &Options) -> Result<bool> {
+	let pattern = options.pattern.as_deref().unwrap_or("*");
+	eprint!("Delete all keys matching '{}'? [y/N] ", pattern);
+	std::io::stderr().flush()?;
+	let mut answer = String::new();
+	std::io::stdin().read_line(&mut answer)?;
+	Ok(matches!(&answer.trim().to_lowercase()[..], "y" | "yes"))
+}
+
+/// Prints matched keys one per line as soon as each page arrives, so the keyspace
+/// is never held in memory. With `--delete` the keys of every page are removed by one DEL
+pub async fn scan_mode(conn: &mut Connection, options: &Options) -> Result<ScanSummary> {
+	let mut summary = ScanSummary {
+		matched: 0,
+		deleted: 0,
+		errors: 0,
+	};
+	if options.delete && !options.no_confirm && !confirm(options)? {
+		eprintln!("Aborted");
+		return Ok(summary);
+	}
+
+	let stdout = std::io::stdout();
+	let mut cursor = 0;
+	loop {
+		let (next, keys) = parse_page(conn.request(scan_command(cursor, options)).await?)?;
+		{
+			let mut out = stdout.lock();
+			for key in &keys {
+				out.write_all(key)?;
+				out.write_all(b"\n")?;
+			}
+			out.flush()?;
+		}
+		summary.matched += keys.len();
+
+		if options.delete && !keys.is_empty() {
+			let cmd = Command {
+				command: "DEL".to_owned(),
+				arguments: keys.into_iter().map(Value::Buffer).collect(),
+			};
+			let deleted = match conn.request(cmd).await? {
+				Value::Integer(deleted) => deleted as usize,
+				Value::Error(err) => {
+					eprintln!("{}", err);
+					summary.errors += 1;
+					break;
+				},
+				reply => return Err(format!("Unexpected DEL reply: {:?}", reply).into()),
+			};
+			summary.deleted += deleted;
+			// Removal moves keys from the tail of the keyspace into the freed slots
+			// behind the cursor, so the same page is scanned again
+			if deleted > 0 {
+				continue;
+			}
+		}
+
+		if next == 0 {
+			break;
+		}
+		cursor = next;
+	}
+	if options.delete {
+		eprintln!("{} keys matched, {} keys deleted", summary.matched, summary.deleted);
+	}
+	Ok(summary)
+}
@@ -0,0 +1,77 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The address comes from -u, RADISH_ADDR or the default one; -h and -p replace its host and port
+
+mod common;
+
+use common::*;
+
+fn port(addr: &str) -> &str {
+	addr.rsplit_once(':').unwrap().1
+}
+
+#[tokio::test]
+async fn port_replaces_the_one_of_radish_addr() {
+	let server = TestServer::start().await;
+	let other = TestServer::start().await;
+
+	// RADISH_ADDR names `server`, -p moves the command to `other`
+	let output = server.cli(&["-p", port(&other.addr), "SET", "k", "other"], "").await;
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert_eq!(other.get("k").await, radish_database::Value::Buffer(b"other".to_vec()));
+	assert_eq!(server.get("k").await, radish_database::Value::Nill);
+}
+
+#[tokio::test]
+async fn host_replaces_the_one_of_radish_addr() {
+	let server = TestServer::start().await;
+	let other = TestServer::start_on("127.0.0.2:0").await;
+
+	let output = server.cli(&["-h", "127.0.0.2", "-p", port(&other.addr), "SET", "k", "v"], "").await;
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert_eq!(other.get("k").await, radish_database::Value::Buffer(b"v".to_vec()));
+
+	// the port of RADISH_ADDR is kept, and nothing listens on it at 127.0.0.2
+	let output = server.cli(&["-h", "127.0.0.2", "GET", "k"], "").await;
+	assert_eq!(output.status.code(), Some(2), "{}", stdout(&output));
+}
+
+#[tokio::test]
+async fn host_and_port_replace_the_ones_of_the_url() {
+	let server = TestServer::start().await;
+	let stopped = TestServer::start().await;
+	let url = format!("redis://{}/", stopped.addr);
+	stopped.stop().await;
+
+	let output = server.cli(&["-u", &url, "PING"], "").await;
+	assert_eq!(output.status.code(), Some(2));
+	let output = server.cli(&["-u", &url, "-p", port(&server.addr), "PING"], "").await;
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert_eq!(stdout(&output), "\"PONG\"\n");
+}
+
+#[tokio::test]
+async fn invalid_port_is_a_usage_error() {
+	let server = TestServer::start().await;
+	for port in &["http", "-1", "65536", ""] {
+		let output = server.cli(&["-p", port, "PING"], "").await;
+		assert_eq!(output.status.code(), Some(3), "-p {:?}", port);
+		assert!(stderr(&output).contains(&format!("Invalid port '{}'", port)), "{}", stderr(&output));
+	}
+	let output = server.cli(&["-h"], "").await;
+	assert_eq!(output.status.code(), Some(3));
+}
@@ -0,0 +1,75 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Command, Session, Value};
+
+use common::*;
+
+async fn protected() -> TestServer {
+	let server = TestServer::start().await;
+	server.storage.execute(&mut Session::default(), Command {
+		command: "SET".to_owned(),
+		arguments: vec![Value::Buffer(b"key".to_vec()), Value::Buffer(b"value".to_vec())].into(),
+	}).await;
+	server.storage.config().set("requirepass", "s3cret").unwrap();
+	server
+}
+
+#[tokio::test]
+async fn right_password() {
+	let server = protected().await;
+	let output = server.cli(&["-a", "s3cret", "GET", "key"], "").await;
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert_eq!(stdout(&output), "\"value\"\n");
+	assert!(stderr(&output).starts_with("Warning: Using a password with '-a' option"), "{}", stderr(&output));
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn wrong_password() {
+	let server = protected().await;
+	let output = server.cli(&["-a", "wrong", "GET", "key"], "").await;
+	assert_eq!(output.status.code(), Some(4));
+	assert_eq!(stdout(&output), "");
+	assert!(stderr(&output).contains("AUTH failed: WRONGPASS"), "{}", stderr(&output));
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn missing_password() {
+	let server = protected().await;
+	let output = server.cli(&["GET", "key"], "").await;
+	assert_eq!(output.status.code(), Some(1));
+	assert_eq!(stdout(&output), "");
+	assert_eq!(stderr(&output), "(error) NOAUTH Authentication required.\n");
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn password_from_the_url() {
+	let server = protected().await;
+	let url = format!("redis://:s3cret@{}", server.addr);
+	let output = server.cli(&["-u", &url, "GET", "key"], "").await;
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert_eq!(stdout(&output), "\"value\"\n");
+
+	let url = format!("redis://:wrong@{}", server.addr);
+	let output = server.cli(&["-u", &url, "GET", "key"], "").await;
+	assert_eq!(output.status.code(), Some(4));
+	server.stop().await;
+}
@@ -0,0 +1,108 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use common::*;
+
+struct Script(std::path::PathBuf);
+
+impl Script {
+	fn new(name: &str, content: &str) -> Self {
+		let path = std::env::temp_dir().join(format!("radish-cli-{}-{}.txt", name, std::process::id()));
+		std::fs::write(&path, content).unwrap();
+		Self(path)
+	}
+
+	fn path(&self) -> &str {
+		self.0.to_str().unwrap()
+	}
+}
+
+impl Drop for Script {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_file(&self.0);
+	}
+}
+
+const SCRIPT: &str = "# fill\nSET a v\nRPUSH a x\n\nGET a\n";
+
+#[tokio::test]
+async fn separated_commands() {
+	let server = TestServer::start().await;
+
+	let output = server.cli(&["SET", "a", "v", ";", "APPEND", "a", "w", ";", "GET", "a"], "").await;
+	assert_eq!(output.status.code(), Some(0));
+	assert_eq!(stdout(&output), "OK\n(integer) 2\n\"vw\"\n");
+
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn file_continues_past_error_replies() {
+	let server = TestServer::start().await;
+	let script = Script::new("continue", SCRIPT);
+
+	let output = server.cli(&["--file", script.path()], "").await;
+	assert_eq!(output.status.code(), Some(1));
+	assert_eq!(stdout(&output), "2: OK\n5: \"v\"\n");
+	assert!(stderr(&output).starts_with("3: (error) WRONGTYPE"), "{}", stderr(&output));
+
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn file_aborts_on_error() {
+	let server = TestServer::start().await;
+	let script = Script::new("abort", SCRIPT);
+
+	let output = server.cli(&["--abort-on-error", "--file", script.path()], "").await;
+	assert_eq!(output.status.code(), Some(1));
+	assert_eq!(stdout(&output), "2: OK\n");
+	assert!(stderr(&output).starts_with("3: (error) WRONGTYPE"), "{}", stderr(&output));
+
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn file_stops_on_connection_error() {
+	let server = TestServer::start().await;
+	let script = Script::new("connection", "PING\n");
+	let addr = server.addr.clone();
+	server.stop().await;
+
+	let output = tokio::task::spawn_blocking(move || {
+		std::process::Command::new(env!("CARGO_BIN_EXE_radish-cli"))
+			.args(["--file", script.path()])
+			.env("RADISH_ADDR", addr)
+			.output()
+			.unwrap()
+	}).await.unwrap();
+	assert_eq!(output.status.code(), Some(2));
+	assert_eq!(stdout(&output), "");
+	assert!(stderr(&output).starts_with("Error: "), "{}", stderr(&output));
+}
+
+#[tokio::test]
+async fn missing_file() {
+	let server = TestServer::start().await;
+
+	let output = server.cli(&["--file", "/nonexistent/radish-script.txt"], "").await;
+	assert_eq!(output.status.code(), Some(2));
+	assert!(stderr(&output).starts_with("Error: "), "{}", stderr(&output));
+
+	server.stop().await;
+}
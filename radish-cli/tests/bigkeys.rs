@@ -0,0 +1,130 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use common::*;
+
+async fn populate(server: &TestServer) {
+	for i in 1..=20 {
+		server.run("SET", &[&format!("str:{}", i), &"x".repeat(i * 10)]).await;
+	}
+	server.run("SET", &["str:big", &"x".repeat(5000)]).await;
+	for i in 1..=10 {
+		let items: Vec<String> = (0..i).map(|n|n.to_string()).collect();
+		let mut args = vec![format!("list:{}", i)];
+		args.extend(items);
+		let args: Vec<&str> = args.iter().map(|s|s.as_str()).collect();
+		server.run("RPUSH", &args).await;
+	}
+	server.run("SADD", &["set:small", "a"]).await;
+	server.run("SADD", &["set:big", "a", "b", "c", "d"]).await;
+	server.run("HSET", &["hash:1", "f1", "v", "f2", "v", "f3", "v"]).await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn biggest_keys_and_summary() {
+	let server = TestServer::start().await;
+	populate(&server).await;
+
+	let output = server.cli(&["--bigkeys"], "").await;
+	assert!(output.status.success(), "{}", stderr(&output));
+	let output = stdout(&output);
+	let lines: Vec<&str> = output.lines().collect();
+	let has = |line: &str|lines.contains(&line);
+
+	assert!(has("Sampled 34 keys in the keyspace!"), "{}", output);
+	assert!(has("Biggest string found \"str:big\" has 5000 bytes"), "{}", output);
+	assert!(has("Biggest   list found \"list:10\" has 10 items"), "{}", output);
+	assert!(has("Biggest    set found \"set:big\" has 4 members"), "{}", output);
+	assert!(has("Biggest   hash found \"hash:1\" has 3 fields"), "{}", output);
+	// the server has no sorted sets
+	assert!(!output.contains("Biggest   zset found"), "{}", output);
+
+	assert!(has("21 strings with 7100 bytes (61.76% of keys, avg size 338.10)"), "{}", output);
+	assert!(has("10 lists with 55 items (29.41% of keys, avg size 5.50)"), "{}", output);
+	assert!(has("2 sets with 5 members (05.88% of keys, avg size 2.50)"), "{}", output);
+	assert!(has("1 hashs with 3 fields (02.94% of keys, avg size 3.00)"), "{}", output);
+	assert!(has("0 zsets with 0 members (00.00% of keys, avg size 0.00)"), "{}", output);
+
+	// progress lines only grow
+	let progress: Vec<u64> = lines.iter()
+		.filter(|line|line.starts_with("Biggest string found so far"))
+		.map(|line|line.split(" with ").nth(1).unwrap().split(' ').next().unwrap().parse().unwrap())
+		.collect();
+	assert!(!progress.is_empty());
+	assert!(progress.windows(2).all(|pair|pair[0] < pair[1]), "{:?}", progress);
+	assert_eq!(progress.last(), Some(&5000));
+	server.stop().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn empty_keyspace() {
+	let server = TestServer::start().await;
+	let output = server.cli(&["--bigkeys"], "").await;
+	assert!(output.status.success(), "{}", stderr(&output));
+	let output = stdout(&output);
+	assert!(output.contains("Sampled 0 keys in the keyspace!"), "{}", output);
+	assert!(output.contains("0 strings with 0 bytes (00.00% of keys, avg size 0.00)"), "{}", output);
+	assert!(!output.contains("Biggest string found"), "{}", output);
+	server.stop().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn memkeys_reports_bytes() {
+	let server = TestServer::start().await;
+	populate(&server).await;
+
+	let output = server.cli(&["--memkeys"], "").await;
+	assert!(output.status.success(), "{}", stderr(&output));
+	let output = stdout(&output);
+	assert!(output.contains("Sampled 34 keys in the keyspace!"), "{}", output);
+	assert!(output.contains("Biggest string found \"str:big\" has "), "{}", output);
+	assert!(output.contains("Biggest   list found \"list:10\" has "), "{}", output);
+	assert!(!output.contains(" items"), "{}", output);
+	assert!(!output.contains(" fields"), "{}", output);
+	server.stop().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn keys_disappearing_mid_scan() {
+	let server = TestServer::start().await;
+	for i in 0..3_000 {
+		server.run("SET", &[&format!("key:{}", i), "v"]).await;
+		server.run("RPUSH", &[&format!("list:{}", i), "a", "b"]).await;
+	}
+	server.run("RPUSH", &["stable", "a", "b", "c"]).await;
+
+	let storage = server.storage.clone();
+	let mutator = tokio::spawn(async move {
+		let session = &mut radish_database::Session {authenticated: true, ..Default::default()};
+		for i in 0..3_000 {
+			for key in &[format!("key:{}", i), format!("list:{}", i)] {
+				storage.execute(session, radish_database::Command {
+					command: "DEL".to_owned(),
+					arguments: vec![radish_database::Value::Buffer(key.as_bytes().to_vec())].into(),
+				}).await;
+			}
+			tokio::time::delay_for(std::time::Duration::from_micros(50)).await;
+		}
+	});
+	let output = server.cli(&["--bigkeys"], "").await;
+	mutator.await.unwrap();
+
+	assert!(output.status.success(), "{}", stderr(&output));
+	assert!(stdout(&output).contains("Biggest   list found \"stable\" has 3 items"), "{}", stdout(&output));
+	server.stop().await;
+}
@@ -0,0 +1,128 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! In-process server on an ephemeral port and a runner of the built radish-cli binary
+
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::process::{Child, Command, Output, Stdio};
+use std::time::Duration;
+
+use radish_database::{Session, Storage, Value};
+use radish_server::{Server, ServerHandle};
+
+pub struct TestServer {
+	pub storage: Storage,
+	pub addr: String,
+	handle: ServerHandle,
+}
+
+impl TestServer {
+	pub async fn start() -> Self {
+		Self::start_on("127.0.0.1:0").await
+	}
+
+	/// Server on the `host:port` of a stopped one, e.g. to restart it under a running client
+	pub async fn start_on(addr: &str) -> Self {
+		let (host, port) = addr.rsplit_once(':').unwrap();
+		let storage = Storage::new();
+		storage.config().set("bind", host).unwrap();
+		storage.config().set("port", port).unwrap();
+		let server = Server::bind(storage.clone()).await.unwrap();
+		let addr = server.listeners()[0].trim_start_matches("tcp ").to_owned();
+		Self {storage, addr, handle: server.start()}
+	}
+
+	/// Runs radish-cli against the server; the process is waited on a blocking
+	/// thread so the server keeps serving on the test runtime
+	pub async fn cli(&self, args: &[&str], stdin: &str) -> Output {
+		let addr = self.addr.clone();
+		let args: Vec<String> = args.iter().map(|arg|arg.to_string()).collect();
+		let stdin = stdin.to_owned();
+		tokio::task::spawn_blocking(move || {
+			let mut child = Command::new(env!("CARGO_BIN_EXE_radish-cli"))
+				.args(&args)
+				.env("RADISH_ADDR", addr)
+				.stdin(Stdio::piped())
+				.stdout(Stdio::piped())
+				.stderr(Stdio::piped())
+				.spawn()
+				.unwrap();
+			child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+			child.wait_with_output().unwrap()
+		}).await.unwrap()
+	}
+
+	/// Interactive radish-cli; lines written to its stdin are run one by one
+	pub fn spawn_cli(&self, args: &[&str]) -> Child {
+		Command::new(env!("CARGO_BIN_EXE_radish-cli"))
+			.args(args)
+			.env("RADISH_ADDR", &self.addr)
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.spawn()
+			.unwrap()
+	}
+
+	/// Reply of the command run on an authenticated session
+	pub async fn run(&self, command: &str, args: &[&str]) -> Value {
+		let session = &mut Session {authenticated: true, ..Default::default()};
+		self.storage.execute(session, radish_database::Command {
+			command: command.to_owned(),
+			arguments: args.iter().map(|arg|Value::Buffer(arg.as_bytes().to_vec())).collect(),
+		}).await
+	}
+
+	pub async fn get(&self, key: &str) -> Value {
+		self.run("GET", &[key]).await
+	}
+
+	/// Waits until a client sets `key`
+	pub async fn wait_for(&self, key: &str) {
+		for _ in 0..100 {
+			if self.get(key).await != Value::Nill {
+				return;
+			}
+			tokio::time::delay_for(Duration::from_millis(50)).await;
+		}
+		panic!("{} was not set", key);
+	}
+
+	pub async fn stop(self) {
+		self.handle.shutdown().await.unwrap();
+	}
+}
+
+pub fn stdout(output: &Output) -> String {
+	String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+	String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+/// Sends one line to an interactive radish-cli
+pub fn type_line(cli: &mut Child, line: &str) {
+	writeln!(cli.stdin.as_mut().unwrap(), "{}", line).unwrap();
+}
+
+/// Closes stdin of an interactive radish-cli and waits for it to exit
+pub async fn quit(mut cli: Child) -> Output {
+	drop(cli.stdin.take());
+	tokio::task::spawn_blocking(move ||cli.wait_with_output().unwrap()).await.unwrap()
+}
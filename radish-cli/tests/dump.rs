@@ -0,0 +1,201 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Session, Value};
+
+use common::*;
+
+struct SnapshotFile(std::path::PathBuf);
+
+impl SnapshotFile {
+	fn new(name: &str) -> Self {
+		Self(std::env::temp_dir().join(format!("radish-cli-{}-{}.rdsh", name, std::process::id())))
+	}
+
+	fn path(&self) -> &str {
+		self.0.to_str().unwrap()
+	}
+}
+
+impl Drop for SnapshotFile {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_file(&self.0);
+	}
+}
+
+async fn execute(server: &TestServer, command: &str, arguments: Vec<Value>) -> Value {
+	let session = &mut Session {authenticated: true, ..Default::default()};
+	server.storage.execute(session, radish_database::Command {command: command.to_owned(), arguments: arguments.into()}).await
+}
+
+/// A key of every type; `volatile:*` keys expire
+async fn populate(server: &TestServer) {
+	let commands: &[&[&str]] = &[
+		&["SET", "string", "value"],
+		&["RPUSH", "list", "a", "b", "c"],
+		&["SADD", "set", "x", "y"],
+		&["HSET", "hash", "f", "v", "g", "w"],
+		&["JSON.SET", "json", "$", r#"{"a":[1,{"b":null}]}"#],
+		&["BF.ADD", "bloom", "a"],
+		&["TS.ADD", "series", "*", "1.5"],
+		&["RPUSH", "volatile:list", "a"],
+	];
+	for command in commands {
+		let reply = server.run(command[0], &command[1..]).await;
+		assert!(!matches!(reply, Value::Error(_)), "{:?}: {:?}", command, reply);
+	}
+	let buf = |value: &str|Value::Buffer(value.as_bytes().to_vec());
+	assert_eq!(execute(server, "SET", vec![buf("volatile:string"), buf("v"), buf("EX"), Value::Integer(1000)]).await, Value::Ok);
+	assert_eq!(execute(server, "PEXPIRE", vec![buf("volatile:list"), Value::Integer(500_000)]).await, Value::Bool(true));
+	assert_eq!(execute(server, "SET", vec![buf("binary"), Value::Buffer(vec![0, 0xff])]).await, Value::Ok);
+}
+
+/// EXPORT of the server sorted by key; `expire_at` is taken out and returned separately
+async fn dataset(server: &TestServer) -> (Vec<serde_json::Value>, Vec<(String, u64)>) {
+	let document = match server.run("EXPORT", &[]).await {
+		Value::Buffer(document) => serde_json::from_slice::<serde_json::Value>(&document).unwrap(),
+		reply => panic!("unexpected EXPORT reply {:?}", reply),
+	};
+	let mut entries = document["keys"].as_array().unwrap().clone();
+	let mut expirations = Vec::new();
+	for entry in &mut entries {
+		if entry["type"] == "set" {
+			entry["value"].as_array_mut().unwrap().sort_by_key(|member|member.to_string());
+		}
+		if let Some(expire_at) = entry.as_object_mut().unwrap().remove("expire_at") {
+			expirations.push((entry["key"].as_str().unwrap().to_owned(), expire_at.as_u64().unwrap()));
+		}
+	}
+	entries.sort_by_key(|entry|entry["key"].to_string());
+	expirations.sort();
+	(entries, expirations)
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn dump_to_a_file_and_import_into_a_fresh_server() {
+	let file = SnapshotFile::new("dump");
+	let source = TestServer::start().await;
+	populate(&source).await;
+
+	let output = source.cli(&["--dump-to", file.path()], "").await;
+	assert!(output.status.success(), "{}", stderr(&output));
+	let log = stderr(&output);
+	assert!(log.contains("\rDumped 10/10 keys\n"), "{:?}", log);
+	assert!(log.ends_with(&format!("Dumped 10 keys to {}, 0 disappeared during the dump\n", file.path())), "{:?}", log);
+
+	// the file is in the format of the server's own snapshots
+	let (_, snapshot) = radish_database::decode_snapshot(&std::fs::read(&file.0).unwrap(), true).unwrap();
+	assert_eq!(snapshot.entries.len(), 10);
+
+	let target = TestServer::start().await;
+	let output = target.cli(&["--import-from", file.path()], "").await;
+	assert!(output.status.success(), "{}", stderr(&output));
+	assert_eq!(stderr(&output), format!("Imported 10 keys from {}\n", file.path()));
+
+	let (source_entries, source_expirations) = dataset(&source).await;
+	let (target_entries, target_expirations) = dataset(&target).await;
+	assert_eq!(target_entries, source_entries);
+	// the absolute expiration time is rebuilt from PTTL, so it may drift by the time of the dump
+	let keys = |expirations: &[(String, u64)]|expirations.iter().map(|(key, _)|key.clone()).collect::<Vec<_>>();
+	assert_eq!(keys(&target_expirations), vec!["volatile:list", "volatile:string"]);
+	assert_eq!(keys(&target_expirations), keys(&source_expirations));
+	for ((key, target), (_, source)) in target_expirations.iter().zip(&source_expirations) {
+		assert!((*target as i64 - *source as i64).abs() < 1000, "{} expires at {} instead of {}", key, target, source);
+	}
+	assert_eq!(execute(&target, "TTL", vec![Value::Buffer(b"string".to_vec())]).await, Value::Integer(-1));
+	assert_eq!(target.get("binary").await, Value::Buffer(vec![0, 0xff]));
+	source.stop().await;
+	target.stop().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn pattern_and_replace() {
+	let file = SnapshotFile::new("pattern");
+	let source = TestServer::start().await;
+	populate(&source).await;
+	let output = source.cli(&["--dump-to", file.path(), "--pattern", "^volatile:"], "").await;
+	assert!(output.status.success(), "{}", stderr(&output));
+	assert!(stderr(&output).ends_with(&format!("Dumped 2 keys to {}, 0 disappeared during the dump\n", file.path())));
+
+	let target = TestServer::start().await;
+	target.run("SET", &["volatile:string", "old"]).await;
+	target.run("SET", &["other", "v"]).await;
+	let output = target.cli(&["--import-from", file.path()], "").await;
+	assert!(output.status.success(), "{}", stderr(&output));
+	assert_eq!(target.get("volatile:string").await, Value::Buffer(b"v".to_vec()));
+	assert_eq!(target.get("other").await, Value::Buffer(b"v".to_vec()));
+
+	let output = target.cli(&["--import-from", file.path(), "--replace"], "").await;
+	assert!(output.status.success(), "{}", stderr(&output));
+	assert_eq!(target.get("other").await, Value::Nill);
+	assert_eq!(dataset(&target).await.0.len(), 2);
+	source.stop().await;
+	target.stop().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn keys_disappearing_during_the_dump() {
+	let file = SnapshotFile::new("disappearing");
+	let server = TestServer::start().await;
+	for i in 0..2_000 {
+		server.run("SET", &[&format!("temp:{}", i), "v"]).await;
+		server.run("RPUSH", &[&format!("list:{}", i), "a"]).await;
+	}
+	server.run("RPUSH", &["stable", "a", "b"]).await;
+
+	let storage = server.storage.clone();
+	let mutator = tokio::spawn(async move {
+		let session = &mut Session {authenticated: true, ..Default::default()};
+		for i in 0..2_000 {
+			for key in &[format!("temp:{}", i), format!("list:{}", i)] {
+				storage.execute(session, radish_database::Command {
+					command: "DEL".to_owned(),
+					arguments: vec![Value::Buffer(key.as_bytes().to_vec())].into(),
+				}).await;
+			}
+			tokio::time::delay_for(std::time::Duration::from_micros(50)).await;
+		}
+	});
+	let output = server.cli(&["--dump-to", file.path(), "--count", "10"], "").await;
+	mutator.await.unwrap();
+	assert!(output.status.success(), "{}", stderr(&output));
+
+	let (_, snapshot) = radish_database::decode_snapshot(&std::fs::read(&file.0).unwrap(), true).unwrap();
+	let summary = format!("Dumped {} keys to {}, ", snapshot.entries.len(), file.path());
+	assert!(stderr(&output).contains(&summary), "{}", stderr(&output));
+	assert!(snapshot.entries.iter().any(|entry|entry.key == b"stable"));
+	assert!(snapshot.entries.len() <= 4_001);
+	server.stop().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn unreadable_files_fail() {
+	let file = SnapshotFile::new("unreadable");
+	let server = TestServer::start().await;
+	let output = server.cli(&["--import-from", file.path()], "").await;
+	assert_eq!(output.status.code(), Some(2));
+
+	std::fs::write(&file.0, b"not a snapshot").unwrap();
+	let output = server.cli(&["--import-from", file.path()], "").await;
+	assert_eq!(output.status.code(), Some(2));
+	assert!(stderr(&output).contains("not a snapshot file"), "{}", stderr(&output));
+
+	let output = server.cli(&["--dump-to", "/nonexistent/dir/file"], "").await;
+	assert_eq!(output.status.code(), Some(2));
+	server.stop().await;
+}
@@ -0,0 +1,86 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Contract for scripts: 0 on success including nil, 1 on an Error reply, 2 on a connection
+//! failure, 3 on a usage error; replies go to stdout, errors only to stderr
+
+mod common;
+
+use common::*;
+
+/// Address where nothing listens
+async fn dead_url() -> String {
+	let server = TestServer::start().await;
+	let url = format!("redis://{}", server.addr);
+	server.stop().await;
+	url
+}
+
+#[tokio::test]
+async fn one_shot() {
+	let server = TestServer::start().await;
+
+	let output = server.cli(&["SET", "a", "v"], "").await;
+	assert_eq!(output.status.code(), Some(0));
+	assert_eq!(stdout(&output), "OK\n");
+	assert_eq!(stderr(&output), "");
+
+	let output = server.cli(&["GET", "missing"], "").await;
+	assert_eq!(output.status.code(), Some(0));
+	assert_eq!(stdout(&output), "(nil)\n");
+	let output = server.cli(&["--raw", "GET", "missing"], "").await;
+	assert_eq!(output.status.code(), Some(0));
+	assert_eq!(stdout(&output), "");
+
+	let output = server.cli(&["LPUSH", "a", "x"], "").await;
+	assert_eq!(output.status.code(), Some(1));
+	assert_eq!(stdout(&output), "");
+	assert!(stderr(&output).starts_with("(error) WRONGTYPE"), "{}", stderr(&output));
+
+	let output = server.cli(&["-u", &dead_url().await, "GET", "a"], "").await;
+	assert_eq!(output.status.code(), Some(2));
+	assert_eq!(stdout(&output), "");
+	assert!(stderr(&output).starts_with("Error: "), "{}", stderr(&output));
+
+	let output = server.cli(&["-p", "http"], "").await;
+	assert_eq!(output.status.code(), Some(3));
+	assert_eq!(stdout(&output), "");
+	assert_eq!(stderr(&output), "Invalid port 'http'\n");
+
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn pipe() {
+	let server = TestServer::start().await;
+
+	let output = server.cli(&["--pipe"], "SET a v\nGET missing\n").await;
+	assert_eq!(output.status.code(), Some(0));
+	assert_eq!(stdout(&output), "All data transferred. errors: 0, replies: 2\n");
+	assert_eq!(stderr(&output), "");
+
+	let output = server.cli(&["--pipe"], "LPUSH a x\nGET a\n").await;
+	assert_eq!(output.status.code(), Some(1));
+	assert_eq!(stdout(&output), "All data transferred. errors: 1, replies: 2\n");
+	assert!(stderr(&output).starts_with("WRONGTYPE"), "{}", stderr(&output));
+
+	let output = server.cli(&["-u", &dead_url().await, "--pipe"], "GET a\n").await;
+	assert_eq!(output.status.code(), Some(2));
+	assert_eq!(stdout(&output), "");
+	assert!(stderr(&output).starts_with("Error: "), "{}", stderr(&output));
+
+	server.stop().await;
+}
@@ -0,0 +1,145 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::Value;
+
+use common::*;
+
+struct DumpFile(std::path::PathBuf);
+
+impl DumpFile {
+	fn new(name: &str) -> Self {
+		Self(std::env::temp_dir().join(format!("radish-cli-{}-{}.json", name, std::process::id())))
+	}
+
+	fn path(&self) -> &str {
+		self.0.to_str().unwrap()
+	}
+}
+
+impl Drop for DumpFile {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_file(&self.0);
+	}
+}
+
+async fn populate(server: &TestServer) {
+	let commands: &[&[&str]] = &[
+		&["SET", "string", "value"],
+		&["RPUSH", "list", "a", "b"],
+		&["SADD", "set", "x", "y"],
+		&["HSET", "hash", "f", "v"],
+		&["JSON.SET", "json", "$", r#"{"a":[1,{"b":null}]}"#],
+		&["BF.ADD", "bloom", "a"],
+	];
+	for command in commands {
+		let reply = server.run(command[0], &command[1..]).await;
+		assert!(!matches!(reply, Value::Error(_)), "{:?}: {:?}", command, reply);
+	}
+	let session = &mut radish_database::Session {authenticated: true, ..Default::default()};
+	let typed = vec![
+		vec![Value::Buffer(b"ttl".to_vec()), Value::Buffer(b"v".to_vec()), Value::Buffer(b"EX".to_vec()), Value::Integer(1000)],
+		vec![Value::Buffer(b"binary".to_vec()), Value::Buffer(vec![0, 0xff])],
+	];
+	for arguments in typed {
+		let reply = server.storage.execute(session, radish_database::Command {command: "SET".to_owned(), arguments: arguments.into()}).await;
+		assert_eq!(reply, Value::Ok);
+	}
+}
+
+/// EXPORT of the server parsed with the entries sorted by key and the set members sorted
+async fn dataset(server: &TestServer) -> Vec<serde_json::Value> {
+	let document = match server.run("EXPORT", &[]).await {
+		Value::Buffer(document) => serde_json::from_slice::<serde_json::Value>(&document).unwrap(),
+		reply => panic!("unexpected EXPORT reply {:?}", reply),
+	};
+	let mut entries = document["keys"].as_array().unwrap().clone();
+	for entry in &mut entries {
+		if entry["type"] == "set" {
+			entry["value"].as_array_mut().unwrap().sort_by_key(|member|member.to_string());
+		}
+	}
+	entries.sort_by_key(|entry|entry["key"].to_string());
+	entries
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn export_to_a_fresh_server_and_back() {
+	let file = DumpFile::new("export");
+	let source = TestServer::start().await;
+	populate(&source).await;
+
+	let output = source.cli(&["--export", file.path()], "").await;
+	assert!(output.status.success(), "{}", stderr(&output));
+	assert_eq!(stderr(&output), format!("Exported 8 keys to {}\n", file.path()));
+	let document: serde_json::Value = serde_json::from_slice(&std::fs::read(&file.0).unwrap()).unwrap();
+	assert_eq!(document["keys"].as_array().unwrap().len(), 8);
+
+	let target = TestServer::start().await;
+	let output = target.cli(&["--import", file.path()], "").await;
+	assert!(output.status.success(), "{}", stderr(&output));
+	assert_eq!(stderr(&output), format!("Imported 8 keys from {}\n", file.path()));
+
+	assert_eq!(dataset(&target).await, dataset(&source).await);
+	assert_eq!(target.get("binary").await, Value::Buffer(vec![0, 0xff]));
+	source.stop().await;
+	target.stop().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn pattern_and_replace() {
+	let file = DumpFile::new("replace");
+	let source = TestServer::start().await;
+	populate(&source).await;
+	let output = source.cli(&["--export", file.path(), "--pattern", "s*"], "").await;
+	assert_eq!(stderr(&output), format!("Exported 2 keys to {}\n", file.path()));
+
+	let target = TestServer::start().await;
+	target.run("SET", &["string", "old"]).await;
+	target.run("SET", &["other", "v"]).await;
+
+	let output = target.cli(&["--import", file.path()], "").await;
+	assert!(output.status.success(), "{}", stderr(&output));
+	assert_eq!(target.get("string").await, Value::Buffer(b"value".to_vec()));
+	assert_eq!(target.get("other").await, Value::Buffer(b"v".to_vec()));
+
+	let output = target.cli(&["--import", file.path(), "--replace"], "").await;
+	assert!(output.status.success(), "{}", stderr(&output));
+	assert_eq!(target.get("other").await, Value::Nill);
+	assert_eq!(dataset(&target).await.len(), 2);
+	source.stop().await;
+	target.stop().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn import_failures() {
+	let file = DumpFile::new("invalid");
+	let server = TestServer::start().await;
+	server.run("SET", &["a", "v"]).await;
+
+	let output = server.cli(&["--import", file.path()], "").await;
+	assert_eq!(output.status.code(), Some(2));
+	assert!(stderr(&output).starts_with("Error: "), "{}", stderr(&output));
+
+	std::fs::write(&file.0, r#"{"keys": [{"key": "a", "type": "list", "value": ["x"]}]}"#).unwrap();
+	let output = server.cli(&["--import", file.path()], "").await;
+	assert_eq!(output.status.code(), Some(2));
+	assert!(stderr(&output).starts_with("Error: "), "{}", stderr(&output));
+	assert_eq!(server.get("a").await, Value::Buffer(b"v".to_vec()));
+	server.stop().await;
+}
@@ -0,0 +1,101 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use serde_json::{json, Value as Json};
+
+use common::*;
+
+fn documents(output: &str) -> Vec<Json> {
+	output.lines().map(|line|serde_json::from_str(line).unwrap_or_else(|err|panic!("{:?}: {}", line, err))).collect()
+}
+
+#[tokio::test]
+async fn one_shot_commands() {
+	let server = TestServer::start().await;
+	server.run("SET", &["user:1", "{\"name\": \"Ann\"}"]).await;
+	server.run("HSET", &["h", "f1", "v1", "f2", "v2"]).await;
+	server.run("RPUSH", &["l", "a", "b"]).await;
+
+	let output = server.cli(&["-o", "json", "GET", "user:1"], "").await;
+	assert_eq!(output.status.code(), Some(0));
+	assert_eq!(documents(&stdout(&output)), vec![json!("{\"name\": \"Ann\"}")]);
+
+	let output = server.cli(&["-o", "json", "GET", "missing"], "").await;
+	assert_eq!(documents(&stdout(&output)), vec![Json::Null]);
+
+	let output = server.cli(&["-o", "json", "HGETALL", "h"], "").await;
+	assert_eq!(documents(&stdout(&output)), vec![json!({"f1": "v1", "f2": "v2"})]);
+
+	let output = server.cli(&["-o", "json", "LRANGE", "l", "0", "-1"], "").await;
+	assert_eq!(documents(&stdout(&output)), vec![json!(["a", "b"])]);
+
+	let output = server.cli(&["-o", "json", "INCR", "counter"], "").await;
+	assert_eq!(documents(&stdout(&output)), vec![json!(1)]);
+
+	let output = server.cli(&["-o", "json", "SET", "k", "v"], "").await;
+	assert_eq!(documents(&stdout(&output)), vec![json!("OK")]);
+
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn errors_go_to_stderr() {
+	let server = TestServer::start().await;
+	server.run("RPUSH", &["l", "a"]).await;
+
+	let output = server.cli(&["-o", "json", "GET", "l"], "").await;
+	assert_eq!(output.status.code(), Some(1));
+	assert_eq!(stdout(&output), "");
+	let errors = documents(&stderr(&output));
+	assert_eq!(errors.len(), 1);
+	assert!(errors[0]["error"].as_str().unwrap().starts_with("WRONGTYPE"), "{:?}", errors);
+
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn separated_commands_print_a_document_each() {
+	let server = TestServer::start().await;
+
+	let output = server.cli(&["-o", "json", "SET", "a", "v", ";", "GET", "a", ";", "LPUSH", "a", "x", ";", "EXISTS", "a"], "").await;
+	assert_eq!(output.status.code(), Some(1));
+	assert_eq!(documents(&stdout(&output)), vec![json!("OK"), json!("v"), json!(1)]);
+	assert_eq!(documents(&stderr(&output)).len(), 1);
+
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn binary_values_are_base64() {
+	let server = TestServer::start().await;
+	let session = &mut radish_database::Session {authenticated: true, ..Default::default()};
+	server.storage.execute(session, radish_database::Command {
+		command: "SET".to_owned(),
+		arguments: vec![
+			radish_database::Value::Buffer(b"bin".to_vec()),
+			radish_database::Value::Buffer(vec![0, 0xff, 0xfe]),
+		].into(),
+	}).await;
+
+	let output = server.cli(&["-o", "json", "GET", "bin"], "").await;
+	let documents = documents(&stdout(&output));
+	assert_eq!(documents, vec![json!({"base64": "AP/+"})]);
+	assert_eq!(base64::decode(documents[0]["base64"].as_str().unwrap()).unwrap(), vec![0, 0xff, 0xfe]);
+
+	server.stop().await;
+}
@@ -0,0 +1,65 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use common::*;
+
+/// Runs a mode which stops on Ctrl-C for a while, then interrupts it
+async fn interrupted(server: &TestServer, mode: &str) -> std::process::Output {
+	let cli = server.spawn_cli(&[mode]);
+	tokio::time::delay_for(Duration::from_millis(2500)).await;
+	let status = std::process::Command::new("kill").args(["-INT", &cli.id().to_string()]).status().unwrap();
+	assert!(status.success());
+	quit(cli).await
+}
+
+#[tokio::test]
+async fn latency() {
+	let server = TestServer::start().await;
+	let output = interrupted(&server, "--latency").await;
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+
+	// refreshed in place, the final line after Ctrl-C
+	let stdout = stdout(&output);
+	let last = stdout.trim_end().rsplit('\r').next().unwrap();
+	assert!(last.starts_with("min: ") && last.ends_with(" samples)"), "{:?}", stdout);
+	let samples: u32 = last.rsplit('(').next().unwrap().trim_end_matches(" samples)").parse().unwrap();
+	assert!(samples >= 50, "{}", samples);
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn stat() {
+	let server = TestServer::start().await;
+	server.cli(&["SET", "a", "b"], "").await;
+	server.cli(&["SET", "c", "d"], "").await;
+	let output = interrupted(&server, "--stat").await;
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+
+	let stdout = stdout(&output);
+	let lines: Vec<_> = stdout.lines().collect();
+	assert!(lines.len() >= 3, "{:?}", stdout);
+	assert!(lines[0].split_whitespace().eq(vec!["keys", "mem", "clients", "requests"]), "{:?}", stdout);
+	let row: Vec<_> = lines[1].split_whitespace().collect();
+	assert_eq!(row[0], "2", "{:?}", stdout);
+	assert_eq!(row[2], "1", "{:?}", stdout);
+	// the next rows count the INFO requests in between
+	assert!(lines[2].trim_end().ends_with("(+1)"), "{:?}", stdout);
+	server.stop().await;
+}
@@ -0,0 +1,65 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use common::*;
+
+#[tokio::test]
+async fn pipe_sends_every_line() {
+	let server = TestServer::start().await;
+
+	let mut input = String::new();
+	for i in 0..5000 {
+		input.push_str(&format!("SET key:{} v{}\n", i, i));
+	}
+	input.push('\n');
+	input.push_str("RPUSH list a b\n");
+
+	let output = server.cli(&["--pipe"], &input).await;
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert_eq!(stdout(&output), "All data transferred. errors: 0, replies: 5001\n");
+
+	let output = server.cli(&["--raw", "KEYS", ".*"], "").await;
+	assert_eq!(stdout(&output).lines().count(), 5001);
+	let output = server.cli(&["GET", "key:4999"], "").await;
+	assert_eq!(stdout(&output), "\"v4999\"\n");
+	let output = server.cli(&["LRANGE", "list", "0", "-1"], "").await;
+	assert_eq!(stdout(&output), "1) \"a\"\n2) \"b\"\n");
+
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn pipe_counts_error_replies() {
+	let server = TestServer::start().await;
+
+	let mut input = String::new();
+	for i in 0..3000 {
+		input.push_str(&format!("SET key:{} v{}\n", i, i));
+		if i % 100 == 0 {
+			input.push_str(&format!("LPUSH key:{} x\n", i));
+		}
+	}
+
+	let output = server.cli(&["--pipe"], &input).await;
+	assert_eq!(output.status.code(), Some(1));
+	assert_eq!(stdout(&output), "All data transferred. errors: 30, replies: 3030\n");
+	assert_eq!(stderr(&output).lines().count(), 30);
+	assert!(stderr(&output).lines().all(|line|line.starts_with("WRONGTYPE")), "{}", stderr(&output));
+
+	server.stop().await;
+}
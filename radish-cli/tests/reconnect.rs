@@ -0,0 +1,91 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::Value;
+
+use common::*;
+
+#[tokio::test]
+async fn interactive_mode_reconnects_after_a_restart() {
+	let server = TestServer::start().await;
+	let addr = server.addr.clone();
+	let mut cli = server.spawn_cli(&[]);
+	type_line(&mut cli, "SET before v");
+	server.wait_for("before").await;
+
+	server.stop().await;
+	let server = TestServer::start_on(&addr).await;
+	type_line(&mut cli, "SET lost v");
+	type_line(&mut cli, "SET after v");
+	server.wait_for("after").await;
+
+	let output = quit(cli).await;
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	let stderr = stderr(&output);
+	assert!(stderr.starts_with("Failed to execute 'SET': "), "{}", stderr);
+	assert!(stderr.ends_with("Reconnecting...\nConnected\n"), "{}", stderr);
+	// the failed command is reported, not replayed
+	assert_eq!(server.get("lost").await, Value::Nill);
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn one_shot_and_pipe_modes_fail_fast() {
+	let server = TestServer::start().await;
+	let addr = server.addr.clone();
+	server.stop().await;
+
+	let server = TestServer::start().await;
+	let output = server.cli(&["-u", &format!("redis://{}", addr), "GET", "a"], "").await;
+	assert_eq!(output.status.code(), Some(2));
+	assert!(stderr(&output).starts_with("Error: "), "{}", stderr(&output));
+
+	let output = server.cli(&["-u", &format!("redis://{}", addr), "--pipe"], "SET a b\n").await;
+	assert_eq!(output.status.code(), Some(2));
+	assert_eq!(stdout(&output), "");
+	server.stop().await;
+}
+
+async fn protected(addr: &str) -> TestServer {
+	let server = TestServer::start_on(addr).await;
+	server.storage.config().set("requirepass", "s3cret").unwrap();
+	server
+}
+
+#[tokio::test]
+async fn reconnect_restores_the_login() {
+	let server = protected("127.0.0.1:0").await;
+	let addr = server.addr.clone();
+	let mut cli = server.spawn_cli(&["-a", "s3cret"]);
+	type_line(&mut cli, "SET before v");
+	server.wait_for("before").await;
+
+	server.stop().await;
+	let server = protected(&addr).await;
+	// fails on the dead connection and is not replayed
+	type_line(&mut cli, "SET lost v");
+	type_line(&mut cli, "SET after v");
+	server.wait_for("after").await;
+
+	let output = quit(cli).await;
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert!(stderr(&output).contains("Reconnecting...\nConnected\n"), "{}", stderr(&output));
+	assert!(!stderr(&output).contains("NOAUTH"), "{}", stderr(&output));
+	assert_eq!(server.get("lost").await, Value::Nill);
+	server.stop().await;
+}
@@ -0,0 +1,55 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use common::*;
+
+#[tokio::test]
+async fn repeat_stops_after_the_count() {
+	let server = TestServer::start().await;
+
+	let output = server.cli(&["-r", "3", "INCR", "counter"], "").await;
+	assert_eq!(output.status.code(), Some(0));
+	assert_eq!(stdout(&output), "(integer) 1\n(integer) 2\n(integer) 3\n");
+	assert_eq!(stderr(&output), "3 iterations completed\n");
+
+	let output = server.cli(&["-r", "2", "-i", "0.01", "-q", "INCR", "counter"], "").await;
+	assert_eq!(stdout(&output), "(integer) 5\n");
+	assert_eq!(stderr(&output), "2 iterations completed\n");
+
+	let output = server.cli(&["-r", "0", "INCR", "counter"], "").await;
+	assert_eq!(stdout(&output), "");
+	assert_eq!(stderr(&output), "0 iterations completed\n");
+
+	let output = server.cli(&["GET", "counter"], "").await;
+	assert_eq!(stdout(&output), "\"5\"\n");
+
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn repeat_counts_error_replies() {
+	let server = TestServer::start().await;
+
+	server.cli(&["RPUSH", "list", "a"], "").await;
+	let output = server.cli(&["-r", "2", "INCR", "list"], "").await;
+	assert_eq!(output.status.code(), Some(1));
+	assert_eq!(stderr(&output).lines().filter(|line|line.starts_with("(error) WRONGTYPE")).count(), 2);
+	assert!(stderr(&output).ends_with("2 iterations completed\n"));
+
+	server.stop().await;
+}
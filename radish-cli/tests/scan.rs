@@ -0,0 +1,156 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::collections::HashMap;
+
+use radish_database::Value;
+
+use common::*;
+
+/// 300 `user:` strings, 100 `order:` strings and 50 `user:h:` hashes
+async fn populate(server: &TestServer) {
+	for i in 0..300 {
+		server.run("SET", &[&format!("user:{}", i), "v"]).await;
+	}
+	for i in 0..100 {
+		server.run("SET", &[&format!("order:{}", i), "v"]).await;
+	}
+	for i in 0..50 {
+		server.run("HSET", &[&format!("user:h:{}", i), "f", "v"]).await;
+	}
+}
+
+fn counts(output: &str) -> HashMap<&str, usize> {
+	let mut counts = HashMap::new();
+	for key in output.lines() {
+		*counts.entry(key).or_insert(0) += 1;
+	}
+	counts
+}
+
+fn assert_once(output: &str, expected: usize, filter: impl Fn(&str) -> bool) {
+	let counts = counts(output);
+	assert_eq!(counts.len(), expected);
+	assert!(counts.keys().all(|key|filter(key)), "unexpected key in {:?}", counts);
+	assert!(counts.values().all(|&count|count == 1), "a key is printed twice");
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn whole_keyspace() {
+	let server = TestServer::start().await;
+	populate(&server).await;
+	let output = server.cli(&["--scan"], "").await;
+	assert!(output.status.success(), "{}", stderr(&output));
+	assert_once(&stdout(&output), 450, |_|true);
+	server.stop().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn pattern_count_and_type() {
+	let server = TestServer::start().await;
+	populate(&server).await;
+
+	let output = server.cli(&["--scan", "--pattern", "user:*", "--count", "7"], "").await;
+	assert_once(&stdout(&output), 350, |key|key.starts_with("user:"));
+
+	let output = server.cli(&["--scan", "--type", "hash"], "").await;
+	assert_once(&stdout(&output), 50, |key|key.starts_with("user:h:"));
+
+	// MATCH of SCAN is a regular expression
+	let output = server.cli(&["--scan", "--pattern", "^order:1.$"], "").await;
+	assert_once(&stdout(&output), 10, |key|key.starts_with("order:1") && key.len() == 8);
+
+	let output = server.cli(&["--scan", "--pattern", "^nothing:"], "").await;
+	assert!(output.status.success());
+	assert_eq!(stdout(&output), "");
+	server.stop().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn keyspace_changing_during_the_scan() {
+	let server = TestServer::start().await;
+	populate(&server).await;
+	for i in 0..2_000 {
+		server.run("SET", &[&format!("temp:{}", i), "v"]).await;
+	}
+
+	let storage = server.storage.clone();
+	let mutator = tokio::spawn(async move {
+		let session = &mut radish_database::Session {authenticated: true, ..Default::default()};
+		for i in 0..2_000 {
+			for (command, key) in &[("DEL", format!("temp:{}", i)), ("SET", format!("new:{}", i))] {
+				let mut arguments: std::collections::VecDeque<Value> = vec![Value::Buffer(key.as_bytes().to_vec())].into();
+				if *command == "SET" {
+					arguments.push_back(Value::Buffer(b"v".to_vec()));
+				}
+				storage.execute(session, radish_database::Command {command: command.to_string(), arguments}).await;
+			}
+			tokio::time::delay_for(std::time::Duration::from_micros(50)).await;
+		}
+	});
+	let output = server.cli(&["--scan", "--count", "10"], "").await;
+	mutator.await.unwrap();
+
+	assert!(output.status.success(), "{}", stderr(&output));
+	let output = stdout(&output);
+	let counts = counts(&output);
+	// keys present for the whole scan are printed
+	for i in 0..300 {
+		assert!(counts.contains_key(&format!("user:{}", i)[..]), "user:{} is missed", i);
+	}
+	for i in 0..100 {
+		assert!(counts.contains_key(&format!("order:{}", i)[..]), "order:{} is missed", i);
+	}
+	server.stop().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn delete_without_confirmation() {
+	let server = TestServer::start().await;
+	populate(&server).await;
+
+	let output = server.cli(&["--scan", "--pattern", "order:*", "--delete", "--no-confirm", "--count", "10"], "").await;
+	assert!(output.status.success(), "{}", stderr(&output));
+	assert_once(&stdout(&output), 100, |key|key.starts_with("order:"));
+	assert_eq!(stderr(&output), "100 keys matched, 100 keys deleted\n");
+
+	assert_eq!(server.run("EXISTS", &["order:0", "order:99"]).await, Value::Integer(0));
+	assert_eq!(server.get("user:0").await, Value::Buffer(b"v".to_vec()));
+	server.stop().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn delete_asks_for_confirmation() {
+	let server = TestServer::start().await;
+	populate(&server).await;
+
+	let output = server.cli(&["--scan", "--pattern", "order:*", "--delete"], "n\n").await;
+	assert!(output.status.success());
+	assert_eq!(stdout(&output), "");
+	assert_eq!(stderr(&output), "Delete all keys matching 'order:*'? [y/N] Aborted\n");
+	assert_eq!(server.get("order:0").await, Value::Buffer(b"v".to_vec()));
+
+	let output = server.cli(&["--scan", "--pattern", "order:*", "--delete"], "yes\n").await;
+	assert!(stderr(&output).ends_with("100 keys matched, 100 keys deleted\n"), "{}", stderr(&output));
+	assert_eq!(server.get("order:0").await, Value::Nill);
+
+	let output = server.cli(&["--delete"], "").await;
+	assert_eq!(output.status.code(), Some(3));
+	assert!(stderr(&output).contains("Option '--delete' requires '--scan'"));
+	server.stop().await;
+}
@@ -0,0 +1,373 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Blocking client over std TcpStream with the same framing as the async one.
+//! It does not own a runtime, so it may be called from any thread, including
+//! rayon pools and threads which already run a tokio runtime.
+
+use std::io::{BufWriter, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use radish_types::*;
+
+use super::{ClientConfig, Error, Replies, Result};
+use super::{buffer, make_command, into_reply, into_integer, into_bool, into_ok, into_optional_buffer, into_buffers};
+use super::protocol::{encode_command, decode_value, compression_command, hello_features, is_closed, unpack_frame};
+use super::session::SessionSetup;
+
+/// Blocking counterpart of `radish_client::Client`: requests are serialized over one
+/// connection which is reopened with the same session on the next request after a failure
+pub struct Client {
+	addr: String,
+	config: ClientConfig,
+	sock: Mutex<Option<TcpStream>>,
+	broken: AtomicBool,
+	setup: Mutex<SessionSetup>,
+}
+
+fn timeout_error(err: std::io::Error) -> Error {
+	match err.kind() {
+		std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => Error::Timeout,
+		_ => Error::Io(err),
+	}
+}
+
+fn connect_stream(addr: &str, config: &ClientConfig) -> Result<TcpStream> {
+	let sock = match config.connect_timeout {
+		None => TcpStream::connect(addr)?,
+		Some(timeout) => {
+			let mut last_error = None;
+			let mut connected = None;
+			for addr in addr.to_socket_addrs()? {
+				match TcpStream::connect_timeout(&addr, timeout) {
+					Ok(sock) => {
+						connected = Some(sock);
+						break;
+					},
+					Err(err) => last_error = Some(err),
+				}
+			}
+			match (connected, last_error) {
+				(Some(sock), _) => sock,
+				(None, Some(err)) => return Err(timeout_error(err)),
+				(None, None) => return Err(Error::Config(format!("Address '{}' is not resolved", addr))),
+			}
+		},
+	};
+	sock.set_nodelay(true)?;
+	sock.set_read_timeout(config.command_timeout)?;
+	sock.set_write_timeout(config.command_timeout)?;
+	Ok(sock)
+}
+
+/// Blocking counterpart of `protocol::handshake`
+fn handshake(sock: &mut TcpStream, features: u32) -> Result<Option<handshake::Hello>> {
+	write_frame(sock, &handshake::Hello::new(features).encode())?;
+	let buf = match read_frame(sock, frame::DEFAULT_MAX_FRAME_SIZE) {
+		Ok(buf) => buf,
+		Err(Error::Io(err)) if is_closed(&err) => return Ok(None),
+		Err(Error::Io(err)) => return Err(timeout_error(err)),
+		Err(err) => return Err(err),
+	};
+	match handshake::Hello::decode(&buf).map_err(Error::Protocol)? {
+		Some(hello) => Ok(Some(hello)),
+		None => Err(Error::Protocol("Unexpected reply to the handshake".to_owned())),
+	}
+}
+
+fn open(addr: &str, config: &ClientConfig) -> Result<TcpStream> {
+	if config.tls.is_some() {
+		return Err(Error::Config("TLS is not supported by the blocking client".to_owned()));
+	}
+	let mut sock = connect_stream(addr, config)?;
+	let hello = match handshake(&mut sock, hello_features(config.compression))? {
+		Some(hello) => hello,
+		None => {
+			log::debug!("{}: the server does not support the handshake, using protocol v0", addr);
+			sock = connect_stream(addr, config)?;
+			handshake::Hello::legacy()
+		},
+	};
+	if config.compression && hello.version == handshake::LEGACY_VERSION {
+		write_frame(&mut sock, &encode_command(&compression_command())?)?;
+		if let Value::Error(err) = decode_value(&read_frame(&mut sock, config.max_frame_size)?)? {
+			log::debug!("{}: compression is not supported: {}", addr, err);
+		}
+	} else if config.compression && !hello.has(handshake::FEATURE_COMPRESSION) {
+		log::debug!("{}: compression is not supported", addr);
+	}
+	Ok(sock)
+}
+
+fn write_frame<W: Write>(sock: &mut W, buf: &[u8]) -> Result<()> {
+	sock.write_all(&frame::encode_header(buf.len(), false).map_err(Error::Protocol)?)?;
+	sock.write_all(buf)?;
+	Ok(())
+}
+
+fn read_frame<R: Read>(sock: &mut R, max_size: usize) -> Result<Vec<u8>> {
+	let mut header = [0; frame::HEADER_SIZE];
+	sock.read_exact(&mut header)?;
+	let (len, compressed) = frame::decode_header(header, max_size).map_err(Error::Protocol)?;
+	let mut buf = vec![0; len];
+	sock.read_exact(&mut buf[..])?;
+	unpack_frame(compressed, buf, max_size)
+}
+
+impl Client {
+	pub fn connect(addr: &str) -> Result<Self> {
+		Self::connect_with_config(addr, ClientConfig::default())
+	}
+
+	pub fn connect_with_config(addr: &str, config: ClientConfig) -> Result<Self> {
+		let sock = open(addr, &config)?;
+		Ok(Self {
+			addr: addr.to_owned(),
+			config,
+			sock: Mutex::new(Some(sock)),
+			broken: AtomicBool::new(false),
+			setup: Mutex::new(SessionSetup::default()),
+		})
+	}
+
+	/// Opens a new connection and restores the session of the previous one
+	fn reopen(&self) -> Result<TcpStream> {
+		let mut sock = open(&self.addr, &self.config)?;
+		let setup = self.setup.lock().unwrap().commands();
+		for cmd in setup {
+			write_frame(&mut sock, &encode_command(&cmd)?)?;
+			if let Value::Error(err) = decode_value(&read_frame(&mut sock, self.config.max_frame_size)?)? {
+				return Err(Error::Server(format!("Failed to restore the session with {}: {}", cmd.command, err)));
+			}
+		}
+		Ok(sock)
+	}
+
+	/// Sends any command and returns the raw reply; Error replies are returned as `Value::Error`.
+	/// Idempotent commands are retried on connection errors according to the retry policy
+	pub fn command(&self, cmd: Command) -> Result<Value> {
+		let policy = &self.config.retry;
+		let retriable = policy.max_retries > 0 && policy.is_retriable(&cmd.command);
+		let mut attempt = 0;
+		loop {
+			match self.exchange(std::slice::from_ref(&cmd)) {
+				Err(Error::Io(err)) if retriable && attempt < policy.max_retries => {
+					log::debug!("{}: {} failed: {}; retrying", self.addr, cmd.command, err);
+					std::thread::sleep(policy.backoff(attempt));
+					attempt += 1;
+				},
+				result => return result.map(|mut values|values.pop().unwrap_or(Value::Nill)),
+			}
+		}
+	}
+
+	/// `command` which returns Error replies as `Error::Server`
+	pub fn execute(&self, cmd: Command) -> Result<Value> {
+		into_reply(self.command(cmd)?)
+	}
+
+	/// Writes all commands before reading any reply
+	fn exchange(&self, commands: &[Command]) -> Result<Vec<Value>> {
+		let mut guard = self.sock.lock().map_err(|_|Error::Protocol("Connection lock is poisoned".to_owned()))?;
+		if guard.is_none() {
+			*guard = Some(self.reopen()?);
+			self.broken.store(false, Ordering::Relaxed);
+		}
+		let sock = guard.as_mut().unwrap();
+		let result = (|| {
+			let mut writer = BufWriter::new(&mut *sock);
+			for cmd in commands {
+				write_frame(&mut writer, &encode_command(cmd)?)?;
+			}
+			writer.flush()?;
+			drop(writer);
+			let mut values = Vec::with_capacity(commands.len());
+			for _ in commands {
+				values.push(decode_value(&read_frame(&mut *sock, self.config.max_frame_size)?)?);
+			}
+			Ok(values)
+		})().map_err(|err| match err {
+			Error::Io(err) => timeout_error(err),
+			err => err,
+		});
+		match &result {
+			Ok(values) => {
+				let mut setup = self.setup.lock().unwrap();
+				for (cmd, value) in commands.iter().zip(values) {
+					setup.record(cmd, value);
+				}
+			},
+			Err(_) => {
+				*guard = None;
+				self.broken.store(true, Ordering::Relaxed);
+			},
+		}
+		result
+	}
+
+	pub fn pipeline(&self) -> Pipeline<'_> {
+		Pipeline {
+			client: self,
+			commands: Vec::new(),
+		}
+	}
+
+	pub fn is_broken(&self) -> bool {
+		self.broken.load(Ordering::Relaxed)
+	}
+
+	pub fn ping(&self) -> Result<()> {
+		match into_reply(self.call("PING", vec![])?)? {
+			Value::Buffer(_) => Ok(()),
+			value => Err(Error::UnexpectedReply(value)),
+		}
+	}
+
+	fn call(&self, command: &str, arguments: Vec<Value>) -> Result<Value> {
+		self.command(make_command(command, arguments))
+	}
+
+	pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+		into_optional_buffer(self.call("GET", vec![buffer(key)])?)
+	}
+
+	pub fn set(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<()> {
+		into_ok(self.call("SET", vec![buffer(key), buffer(value)])?)
+	}
+
+	pub fn set_ex(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>, seconds: u64) -> Result<()> {
+		into_ok(self.call("SET", vec![buffer(key), buffer(value), buffer("EX"), Value::Integer(seconds as i64)])?)
+	}
+
+	pub fn del<K: AsRef<[u8]>>(&self, keys: &[K]) -> Result<i64> {
+		into_integer(self.call("DEL", keys.iter().map(buffer).collect())?)
+	}
+
+	pub fn incr(&self, key: impl AsRef<[u8]>) -> Result<i64> {
+		into_integer(self.call("INCR", vec![buffer(key)])?)
+	}
+
+	pub fn incr_by(&self, key: impl AsRef<[u8]>, increment: i64) -> Result<i64> {
+		into_integer(self.call("INCRBY", vec![buffer(key), Value::Integer(increment)])?)
+	}
+
+	pub fn lpush<V: AsRef<[u8]>>(&self, key: impl AsRef<[u8]>, values: &[V]) -> Result<i64> {
+		let mut arguments = vec![buffer(key)];
+		arguments.extend(values.iter().map(buffer));
+		into_integer(self.call("LPUSH", arguments)?)
+	}
+
+	pub fn lrange(&self, key: impl AsRef<[u8]>, start: i64, stop: i64) -> Result<Vec<Vec<u8>>> {
+		into_buffers(self.call("LRANGE", vec![buffer(key), Value::Integer(start), Value::Integer(stop)])?)
+	}
+
+	pub fn sadd<V: AsRef<[u8]>>(&self, key: impl AsRef<[u8]>, members: &[V]) -> Result<i64> {
+		let mut arguments = vec![buffer(key)];
+		arguments.extend(members.iter().map(buffer));
+		into_integer(self.call("SADD", arguments)?)
+	}
+
+	pub fn smembers(&self, key: impl AsRef<[u8]>) -> Result<Vec<Vec<u8>>> {
+		into_buffers(self.call("SMEMBERS", vec![buffer(key)])?)
+	}
+
+	pub fn hset(&self, key: impl AsRef<[u8]>, field: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<i64> {
+		into_integer(self.call("HSET", vec![buffer(key), buffer(field), buffer(value)])?)
+	}
+
+	pub fn hget(&self, key: impl AsRef<[u8]>, field: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+		into_optional_buffer(self.call("HGET", vec![buffer(key), buffer(field)])?)
+	}
+
+	pub fn hget_all(&self, key: impl AsRef<[u8]>) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+		let mut flat = into_buffers(self.call("HGETALL", vec![buffer(key)])?)?.into_iter();
+		let mut pairs = Vec::with_capacity(flat.len() / 2);
+		while let (Some(field), Some(value)) = (flat.next(), flat.next()) {
+			pairs.push((field, value));
+		}
+		Ok(pairs)
+	}
+
+	pub fn expire(&self, key: impl AsRef<[u8]>, seconds: u64) -> Result<bool> {
+		into_bool(self.call("EXPIRE", vec![buffer(key), Value::Integer(seconds as i64)])?)
+	}
+
+	/// Remaining time to live in seconds, -1 if the key has no expiration and -2 if it does not exist
+	pub fn ttl(&self, key: impl AsRef<[u8]>) -> Result<i64> {
+		into_integer(self.call("TTL", vec![buffer(key)])?)
+	}
+}
+
+/// Blocking counterpart of `radish_client::Pipeline`
+pub struct Pipeline<'a> {
+	client: &'a Client,
+	commands: Vec<Command>,
+}
+
+impl<'a> Pipeline<'a> {
+	pub fn command(mut self, cmd: Command) -> Self {
+		self.commands.push(cmd);
+		self
+	}
+
+	fn call(self, command: &str, arguments: Vec<Value>) -> Self {
+		self.command(make_command(command, arguments))
+	}
+
+	pub fn get(self, key: impl AsRef<[u8]>) -> Self {
+		self.call("GET", vec![buffer(key)])
+	}
+
+	pub fn set(self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Self {
+		self.call("SET", vec![buffer(key), buffer(value)])
+	}
+
+	pub fn del(self, key: impl AsRef<[u8]>) -> Self {
+		self.call("DEL", vec![buffer(key)])
+	}
+
+	pub fn incr(self, key: impl AsRef<[u8]>) -> Self {
+		self.incr_by(key, 1)
+	}
+
+	pub fn incr_by(self, key: impl AsRef<[u8]>, increment: i64) -> Self {
+		self.call("INCRBY", vec![buffer(key), Value::Integer(increment)])
+	}
+
+	pub fn expire(self, key: impl AsRef<[u8]>, seconds: u64) -> Self {
+		self.call("EXPIRE", vec![buffer(key), Value::Integer(seconds as i64)])
+	}
+
+	pub fn ttl(self, key: impl AsRef<[u8]>) -> Self {
+		self.call("TTL", vec![buffer(key)])
+	}
+
+	pub fn len(&self) -> usize {
+		self.commands.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.commands.is_empty()
+	}
+
+	pub fn execute(self) -> Result<Replies> {
+		let values = self.client.exchange(&self.commands)?;
+		Ok(Replies::new(values))
+	}
+}
@@ -0,0 +1,109 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use radish_types::*;
+
+use super::{Client, Error, Result};
+use super::{buffer, into_integer, into_buffers};
+
+fn serialize_error(key: &[u8], err: impl std::fmt::Display) -> Error {
+	Error::Serialize(format!("{}: {}", String::from_utf8_lossy(key), err))
+}
+
+fn deserialize_error(key: &[u8], err: impl std::fmt::Display) -> Error {
+	Error::Deserialize {
+		key: key.to_vec(),
+		message: format!("{}", err),
+	}
+}
+
+/// Helpers storing serde values in Buffer values.
+/// Serde hash models keep every field as a separate hash field encoded as JSON;
+/// see `HashModel` for models with typed fields
+impl Client {
+	pub async fn set_json<T: Serialize>(&self, key: impl AsRef<[u8]>, value: &T) -> Result<()> {
+		let key = key.as_ref();
+		let data = serde_json::to_vec(value).map_err(|e|serialize_error(key, e))?;
+		self.set(key, data).await
+	}
+
+	pub async fn get_json<T: DeserializeOwned>(&self, key: impl AsRef<[u8]>) -> Result<Option<T>> {
+		let key = key.as_ref();
+		match self.get(key).await? {
+			None => Ok(None),
+			Some(data) => serde_json::from_slice(&data).map(Some).map_err(|e|deserialize_error(key, e)),
+		}
+	}
+
+	pub async fn set_msgpack<T: Serialize>(&self, key: impl AsRef<[u8]>, value: &T) -> Result<()> {
+		let key = key.as_ref();
+		let data = rmp_serde::to_vec_named(value).map_err(|e|serialize_error(key, e))?;
+		self.set(key, data).await
+	}
+
+	pub async fn get_msgpack<T: DeserializeOwned>(&self, key: impl AsRef<[u8]>) -> Result<Option<T>> {
+		let key = key.as_ref();
+		match self.get(key).await? {
+			None => Ok(None),
+			Some(data) => rmp_serde::from_read_ref(&data).map(Some).map_err(|e|deserialize_error(key, e)),
+		}
+	}
+
+	/// Writes every field of a struct (or map) as a hash field; returns the count of written fields
+	pub async fn hset_serde_model<T: Serialize>(&self, key: impl AsRef<[u8]>, model: &T) -> Result<i64> {
+		let key = key.as_ref();
+		let fields = match serde_json::to_value(model).map_err(|e|serialize_error(key, e))? {
+			serde_json::Value::Object(fields) => fields,
+			_ => return Err(serialize_error(key, "model must serialize into a map")),
+		};
+		let mut arguments = Arguments::with_capacity(1 + 2 * fields.len());
+		arguments.push_back(buffer(key));
+		for (field, value) in fields {
+			let value = serde_json::to_vec(&value).map_err(|e|serialize_error(key, e))?;
+			arguments.push_back(Value::Buffer(field.into_bytes()));
+			arguments.push_back(Value::Buffer(value));
+		}
+		into_integer(self.command(Command {
+			command: "HSET".to_owned(),
+			arguments,
+		}).await?)
+	}
+
+	/// Reads a hash written by `hset_serde_model`; absent fields are left to serde defaults
+	pub async fn hget_serde_model<T: DeserializeOwned>(&self, key: impl AsRef<[u8]>) -> Result<Option<T>> {
+		let key = key.as_ref();
+		let flat = into_buffers(self.command(Command {
+			command: "HGETALL".to_owned(),
+			arguments: Arguments::from(vec![buffer(key)]),
+		}).await?)?;
+		if flat.is_empty() {
+			return Ok(None);
+		}
+		let mut fields = serde_json::Map::with_capacity(flat.len() / 2);
+		let mut flat = flat.into_iter();
+		while let (Some(field), Some(value)) = (flat.next(), flat.next()) {
+			let field = String::from_utf8(field).map_err(|e|deserialize_error(key, e))?;
+			let value = serde_json::from_slice(&value).map_err(|e|deserialize_error(key, format!("field '{}': {}", field, e)))?;
+			fields.insert(field, value);
+		}
+		serde_json::from_value(serde_json::Value::Object(fields)).map(Some).map_err(|e|deserialize_error(key, e))
+	}
+}
+
@@ -0,0 +1,100 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use radish_types::frame;
+
+use super::TlsConfig;
+
+/// Commands which do not modify the dataset and may be safely repeated
+const READONLY_COMMANDS: &[&str] = &[
+	"PING", "INFO", "NOW", "PNOW", "COMMAND",
+	"EXISTS", "KEYS", "TTL", "PTTL", "TYPE", "SCAN",
+	"GET", "MGET", "STRLEN", "GETRANGE", "GETBIT", "BITCOUNT",
+	"LLEN", "LINDEX", "LRANGE",
+	"SCARD", "SMEMBERS", "SISMEMBER", "SDIFF", "SINTER", "SUNION", "SSCAN",
+	"HGET", "HGETALL", "HEXISTS", "HKEYS", "HVALUES", "HLEN", "HSTRLEN", "HMGET", "HSCAN",
+];
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+	/// Additional attempts after a connection error; 0 disables retries
+	pub max_retries: u32,
+	pub initial_backoff: Duration,
+	pub max_backoff: Duration,
+	/// Write commands which the application knows to be idempotent (e.g. "SET")
+	pub idempotent_commands: HashSet<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ClientConfig {
+	pub connect_timeout: Option<Duration>,
+	/// Deadline for a reply; on expiration the connection is dropped because a late reply would desync the stream
+	pub command_timeout: Option<Duration>,
+	pub retry: RetryPolicy,
+	/// Ask the server for compressed replies; servers without the support are used as is
+	pub compression: bool,
+	/// Encrypt TCP connections; supported only by the async client
+	pub tls: Option<TlsConfig>,
+	/// Replies of a bigger frame fail with a protocol error, zero means no limit
+	pub max_frame_size: usize,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		Self {
+			max_retries: 3,
+			initial_backoff: Duration::from_millis(50),
+			max_backoff: Duration::from_secs(1),
+			idempotent_commands: HashSet::new(),
+		}
+	}
+}
+
+impl RetryPolicy {
+	pub fn none() -> Self {
+		Self {
+			max_retries: 0,
+			..Default::default()
+		}
+	}
+
+	pub fn is_retriable(&self, command: &str) -> bool {
+		let command = command.to_uppercase();
+		READONLY_COMMANDS.contains(&&command[..]) || self.idempotent_commands.contains(&command)
+	}
+
+	pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+		let backoff = self.initial_backoff.checked_mul(1 << attempt.min(16)).unwrap_or(self.max_backoff);
+		std::cmp::min(backoff, self.max_backoff)
+	}
+}
+
+impl Default for ClientConfig {
+	fn default() -> Self {
+		Self {
+			connect_timeout: Some(Duration::from_secs(5)),
+			command_timeout: None,
+			retry: RetryPolicy::default(),
+			compression: false,
+			tls: None,
+			max_frame_size: frame::DEFAULT_MAX_FRAME_SIZE,
+		}
+	}
+}
@@ -0,0 +1,73 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use radish_types::Value;
+
+#[derive(Debug)]
+pub enum Error {
+	/// Transport failure; the connection should be considered broken
+	Io(std::io::Error),
+	/// Frame could not be encoded or decoded
+	Protocol(String),
+	/// Server replied with an Error value
+	Server(String),
+	/// Reply has a type which the typed method does not expect
+	UnexpectedReply(Value),
+	/// Value could not be serialized by a serde helper
+	Serialize(String),
+	/// Stored value does not match the requested type, e.g. after a schema change
+	Deserialize {
+		key: Vec<u8>,
+		message: String,
+	},
+	/// Operation did not complete in time
+	Timeout,
+	/// Invalid client or pool configuration
+	Config(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Error::Io(err) => write!(f, "I/O error: {}", err),
+			Error::Protocol(err) => write!(f, "Protocol error: {}", err),
+			Error::Server(err) => write!(f, "{}", err),
+			Error::UnexpectedReply(value) => write!(f, "Unexpected reply: {:?}", value),
+			Error::Serialize(err) => write!(f, "Failed to serialize {}", err),
+			Error::Deserialize {key, message} => write!(f, "Failed to deserialize '{}': {}", String::from_utf8_lossy(key), message),
+			Error::Timeout => write!(f, "Timeout"),
+			Error::Config(err) => write!(f, "Invalid configuration: {}", err),
+		}
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Error::Io(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+impl From<std::io::Error> for Error {
+	fn from(err: std::io::Error) -> Self {
+		Error::Io(err)
+	}
+}
@@ -0,0 +1,436 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+pub mod blocking;
+mod codec;
+mod config;
+mod error;
+mod model;
+mod pipeline;
+mod pool;
+mod session;
+mod tls;
+mod trace;
+mod url;
+pub mod protocol;
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{Mutex, watch};
+
+pub use radish_types::*;
+pub use config::{ClientConfig, RetryPolicy};
+pub use error::{Error, Result};
+pub use model::{HashModel, HashField};
+pub use radish_derive::HashModel;
+pub use pipeline::{Pipeline, Replies};
+pub use pool::{Pool, PoolConfig, PooledClient};
+pub use session::ConnectionState;
+pub use tls::TlsConfig;
+pub use url::{Address, ConnectionInfo, DEFAULT_PORT};
+
+use session::SessionSetup;
+
+/// Async client to Radish server. Requests are serialized over one connection
+/// which is reopened on the next request after a failure; the accepted AUTH, SELECT,
+/// CLIENT SETNAME and CLIENT COMPRESSION commands are replayed on the new connection
+pub struct Client {
+	address: Address,
+	/// Address for logs
+	addr: String,
+	config: ClientConfig,
+	sock: Mutex<Option<Stream>>,
+	/// Changes on every reconnect, so spans of one connection can be told apart
+	connection_id: AtomicU64,
+	/// Set after a transport failure or timeout: the stream may be desynchronized
+	broken: AtomicBool,
+	setup: std::sync::Mutex<SessionSetup>,
+	state: watch::Sender<ConnectionState>,
+	/// Keeps the channel open while the application has no subscribers
+	state_receiver: watch::Receiver<ConnectionState>,
+}
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+fn next_connection_id() -> u64 {
+	NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+async fn with_timeout<T, F: Future<Output=Result<T>>>(timeout: Option<Duration>, future: F) -> Result<T> {
+	match timeout {
+		None => future.await,
+		Some(timeout) => tokio::time::timeout(timeout, future).await.map_err(|_|Error::Timeout)?,
+	}
+}
+
+trait Socket: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Socket for T {}
+
+type Stream = Box<dyn Socket>;
+
+async fn connect_stream(address: &Address, config: &ClientConfig) -> Result<Stream> {
+	match address {
+		Address::Tcp(addr) => {
+			let sock = TcpStream::connect(&addr[..]).await?;
+			sock.set_nodelay(true)?;
+			match &config.tls {
+				None => Ok(Box::new(sock)),
+				Some(tls) => Ok(Box::new(tls::connect(sock, addr, tls).await?)),
+			}
+		},
+		#[cfg(unix)]
+		Address::Unix(path) => {
+			if config.tls.is_some() {
+				return Err(Error::Config("TLS is not supported over unix sockets".to_owned()));
+			}
+			Ok(Box::new(tokio::net::UnixStream::connect(path).await?))
+		},
+		#[cfg(not(unix))]
+		Address::Unix(_) => Err(Error::Config("Unix sockets are not supported on this platform".to_owned())),
+	}
+}
+
+async fn open(address: &Address, config: &ClientConfig) -> Result<Stream> {
+	let mut sock = with_timeout(config.connect_timeout, connect_stream(address, config)).await?;
+	let features = protocol::hello_features(config.compression);
+	let hello = match with_timeout(config.command_timeout, protocol::handshake(&mut sock, features)).await? {
+		Some(hello) => hello,
+		None => {
+			log::debug!("{}: the server does not support the handshake, using protocol v0", address);
+			sock = with_timeout(config.connect_timeout, connect_stream(address, config)).await?;
+			handshake::Hello::legacy()
+		},
+	};
+	if config.compression && hello.version == handshake::LEGACY_VERSION {
+		let reply = with_timeout(config.command_timeout, protocol::request(&mut sock, &protocol::compression_command(), config.max_frame_size)).await?;
+		if let Value::Error(err) = reply {
+			log::debug!("{}: compression is not supported: {}", address, err);
+		}
+	} else if config.compression && !hello.has(handshake::FEATURE_COMPRESSION) {
+		log::debug!("{}: compression is not supported", address);
+	}
+	Ok(sock)
+}
+
+fn buffer(value: impl AsRef<[u8]>) -> Value {
+	Value::Buffer(value.as_ref().to_vec())
+}
+
+fn make_command(command: &str, arguments: Vec<Value>) -> Command {
+	Command {
+		command: command.to_owned(),
+		arguments: arguments.into_iter().collect(),
+	}
+}
+
+fn into_reply(value: Value) -> Result<Value> {
+	match value {
+		Value::Error(err) => Err(Error::Server(err)),
+		value => Ok(value),
+	}
+}
+
+fn into_integer(value: Value) -> Result<i64> {
+	match into_reply(value)? {
+		Value::Integer(i) => Ok(i),
+		value => Err(Error::UnexpectedReply(value)),
+	}
+}
+
+fn into_bool(value: Value) -> Result<bool> {
+	match into_reply(value)? {
+		Value::Bool(b) => Ok(b),
+		Value::Integer(i) => Ok(i != 0),
+		value => Err(Error::UnexpectedReply(value)),
+	}
+}
+
+fn into_ok(value: Value) -> Result<()> {
+	match into_reply(value)? {
+		Value::Ok => Ok(()),
+		value => Err(Error::UnexpectedReply(value)),
+	}
+}
+
+fn into_buffer(value: Value) -> Result<Vec<u8>> {
+	match into_reply(value)? {
+		Value::Buffer(b) => Ok(b),
+		Value::Integer(i) => Ok(i.to_string().into_bytes()),
+		value => Err(Error::UnexpectedReply(value)),
+	}
+}
+
+fn into_optional_buffer(value: Value) -> Result<Option<Vec<u8>>> {
+	match into_reply(value)? {
+		Value::Nill => Ok(None),
+		value => into_buffer(value).map(Some),
+	}
+}
+
+fn into_buffers(value: Value) -> Result<Vec<Vec<u8>>> {
+	match into_reply(value)? {
+		Value::Array(values) => values.into_iter().map(into_buffer).collect(),
+		value => Err(Error::UnexpectedReply(value)),
+	}
+}
+
+impl Client {
+	pub async fn connect(addr: &str) -> Result<Self> {
+		Self::connect_with_config(addr, ClientConfig::default()).await
+	}
+
+	pub async fn connect_with_config(addr: &str, config: ClientConfig) -> Result<Self> {
+		Self::connect_to(Address::Tcp(addr.to_owned()), config).await
+	}
+
+	/// Connects by a connection string, see `ConnectionInfo`; AUTH and SELECT
+	/// from the URL are sent at once and restored after every reconnect
+	pub async fn from_url(url: &str) -> Result<Self> {
+		Self::connect_with_info(&ConnectionInfo::parse(url)?).await
+	}
+
+	pub async fn connect_with_info(info: &ConnectionInfo) -> Result<Self> {
+		let client = Self::connect_to(info.address.clone(), info.client.clone()).await?;
+		for cmd in info.setup_commands() {
+			if let Value::Error(err) = client.command(cmd.clone()).await? {
+				return Err(Error::Server(format!("{} failed: {}", cmd.command, err)));
+			}
+		}
+		Ok(client)
+	}
+
+	pub async fn connect_to(address: Address, config: ClientConfig) -> Result<Self> {
+		let sock = open(&address, &config).await?;
+		let (state, state_receiver) = watch::channel(ConnectionState::Connected);
+		Ok(Self {
+			addr: address.to_string(),
+			address,
+			config,
+			sock: Mutex::new(Some(sock)),
+			connection_id: AtomicU64::new(next_connection_id()),
+			broken: AtomicBool::new(false),
+			setup: std::sync::Mutex::new(SessionSetup::default()),
+			state,
+			state_receiver,
+		})
+	}
+
+	/// Receives every change of the connection state, e.g. to log flaps
+	pub fn state_changes(&self) -> watch::Receiver<ConnectionState> {
+		self.state_receiver.clone()
+	}
+
+	fn set_state(&self, state: ConnectionState) {
+		let _ = self.state.broadcast(state);
+	}
+
+	pub(crate) fn connection_id(&self) -> u64 {
+		self.connection_id.load(Ordering::Relaxed)
+	}
+
+	/// Id of the current connection if it is not the one with `connection_id`
+	pub(crate) fn reconnected_since(&self, connection_id: u64) -> Option<u64> {
+		Some(self.connection_id()).filter(|&current|current != connection_id)
+	}
+
+	/// Opens a new connection and restores the session of the previous one
+	async fn reopen(&self) -> Result<Stream> {
+		let result = async {
+			let mut sock = open(&self.address, &self.config).await?;
+			let setup = self.setup.lock().unwrap().commands();
+			for cmd in setup {
+				let reply = with_timeout(self.config.command_timeout, protocol::request(&mut sock, &cmd, self.config.max_frame_size)).await?;
+				if let Value::Error(err) = reply {
+					return Err(Error::Server(format!("Failed to restore the session with {}: {}", cmd.command, err)));
+				}
+			}
+			Ok(sock)
+		}.await;
+		match &result {
+			Ok(_) => {
+				let connection_id = next_connection_id();
+				self.connection_id.store(connection_id, Ordering::Relaxed);
+				log::debug!("{}: reconnected", self.addr);
+				trace::reconnected(&self.addr, connection_id);
+			},
+			Err(err) => trace::reconnect_failed(&self.addr, err),
+		}
+		result
+	}
+
+	/// Sends any command and returns the raw reply; Error replies are returned as `Value::Error`.
+	/// Idempotent commands are retried on connection errors according to the retry policy
+	pub async fn command(&self, cmd: Command) -> Result<Value> {
+		let connection_id = self.connection_id();
+		let span = trace::command_span(&cmd, connection_id);
+		let result = trace::instrument(&span, self.command_with_retries(cmd)).await;
+		trace::record_reply(&span, self.reconnected_since(connection_id), &result);
+		result
+	}
+
+	/// `command` which returns Error replies as `Error::Server`
+	pub async fn execute(&self, cmd: Command) -> Result<Value> {
+		into_reply(self.command(cmd).await?)
+	}
+
+	async fn command_with_retries(&self, cmd: Command) -> Result<Value> {
+		let policy = &self.config.retry;
+		let retriable = policy.max_retries > 0 && policy.is_retriable(&cmd.command);
+		let mut attempt = 0;
+		loop {
+			match self.exchange(std::slice::from_ref(&cmd)).await {
+				Err(Error::Io(err)) if retriable && attempt < policy.max_retries => {
+					log::debug!("{}: {} failed: {}; retrying", self.addr, cmd.command, err);
+					tokio::time::delay_for(policy.backoff(attempt)).await;
+					attempt += 1;
+				},
+				result => return result.map(|mut values|values.pop().unwrap_or(Value::Nill)),
+			}
+		}
+	}
+
+	/// Writes all commands before reading any reply; the command timeout covers the whole exchange
+	async fn exchange(&self, commands: &[Command]) -> Result<Vec<Value>> {
+		let mut guard = self.sock.lock().await;
+		if guard.is_none() {
+			*guard = Some(self.reopen().await?);
+			self.broken.store(false, Ordering::Relaxed);
+			self.set_state(ConnectionState::Connected);
+		}
+		let sock = guard.as_mut().unwrap();
+		let result = with_timeout(self.config.command_timeout, async {
+			let mut writer = tokio::io::BufWriter::new(&mut *sock);
+			for cmd in commands {
+				protocol::send_command(&mut writer, cmd).await?;
+			}
+			writer.flush().await?;
+			drop(writer);
+			let mut values = Vec::with_capacity(commands.len());
+			for _ in commands {
+				values.push(protocol::receive_value(&mut *sock, self.config.max_frame_size).await?);
+			}
+			Ok(values)
+		}).await;
+		match &result {
+			Ok(values) => {
+				let mut setup = self.setup.lock().unwrap();
+				for (cmd, value) in commands.iter().zip(values) {
+					setup.record(cmd, value);
+				}
+			},
+			Err(_) => {
+				*guard = None;
+				self.broken.store(true, Ordering::Relaxed);
+				self.set_state(ConnectionState::Disconnected);
+			},
+		}
+		result
+	}
+
+	pub fn pipeline(&self) -> Pipeline<'_> {
+		Pipeline::new(self)
+	}
+
+	pub fn is_broken(&self) -> bool {
+		self.broken.load(Ordering::Relaxed)
+	}
+
+	pub async fn ping(&self) -> Result<()> {
+		match into_reply(self.call("PING", vec![]).await?)? {
+			Value::Buffer(_) => Ok(()),
+			value => Err(Error::UnexpectedReply(value)),
+		}
+	}
+
+	async fn call(&self, command: &str, arguments: Vec<Value>) -> Result<Value> {
+		self.command(make_command(command, arguments)).await
+	}
+
+	pub async fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+		into_optional_buffer(self.call("GET", vec![buffer(key)]).await?)
+	}
+
+	pub async fn set(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<()> {
+		into_ok(self.call("SET", vec![buffer(key), buffer(value)]).await?)
+	}
+
+	pub async fn set_ex(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>, seconds: u64) -> Result<()> {
+		into_ok(self.call("SET", vec![buffer(key), buffer(value), buffer("EX"), Value::Integer(seconds as i64)]).await?)
+	}
+
+	pub async fn del<K: AsRef<[u8]>>(&self, keys: &[K]) -> Result<i64> {
+		into_integer(self.call("DEL", keys.iter().map(buffer).collect()).await?)
+	}
+
+	pub async fn incr(&self, key: impl AsRef<[u8]>) -> Result<i64> {
+		into_integer(self.call("INCR", vec![buffer(key)]).await?)
+	}
+
+	pub async fn incr_by(&self, key: impl AsRef<[u8]>, increment: i64) -> Result<i64> {
+		into_integer(self.call("INCRBY", vec![buffer(key), Value::Integer(increment)]).await?)
+	}
+
+	pub async fn lpush<V: AsRef<[u8]>>(&self, key: impl AsRef<[u8]>, values: &[V]) -> Result<i64> {
+		let mut arguments = vec![buffer(key)];
+		arguments.extend(values.iter().map(buffer));
+		into_integer(self.call("LPUSH", arguments).await?)
+	}
+
+	pub async fn lrange(&self, key: impl AsRef<[u8]>, start: i64, stop: i64) -> Result<Vec<Vec<u8>>> {
+		into_buffers(self.call("LRANGE", vec![buffer(key), Value::Integer(start), Value::Integer(stop)]).await?)
+	}
+
+	pub async fn sadd<V: AsRef<[u8]>>(&self, key: impl AsRef<[u8]>, members: &[V]) -> Result<i64> {
+		let mut arguments = vec![buffer(key)];
+		arguments.extend(members.iter().map(buffer));
+		into_integer(self.call("SADD", arguments).await?)
+	}
+
+	pub async fn smembers(&self, key: impl AsRef<[u8]>) -> Result<Vec<Vec<u8>>> {
+		into_buffers(self.call("SMEMBERS", vec![buffer(key)]).await?)
+	}
+
+	pub async fn hset(&self, key: impl AsRef<[u8]>, field: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<i64> {
+		into_integer(self.call("HSET", vec![buffer(key), buffer(field), buffer(value)]).await?)
+	}
+
+	pub async fn hget(&self, key: impl AsRef<[u8]>, field: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+		into_optional_buffer(self.call("HGET", vec![buffer(key), buffer(field)]).await?)
+	}
+
+	pub async fn hget_all(&self, key: impl AsRef<[u8]>) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+		let mut flat = into_buffers(self.call("HGETALL", vec![buffer(key)]).await?)?.into_iter();
+		let mut pairs = Vec::with_capacity(flat.len() / 2);
+		while let (Some(field), Some(value)) = (flat.next(), flat.next()) {
+			pairs.push((field, value));
+		}
+		Ok(pairs)
+	}
+
+	pub async fn expire(&self, key: impl AsRef<[u8]>, seconds: u64) -> Result<bool> {
+		into_bool(self.call("EXPIRE", vec![buffer(key), Value::Integer(seconds as i64)]).await?)
+	}
+
+	/// Remaining time to live in seconds, -1 if the key has no expiration and -2 if it does not exist
+	pub async fn ttl(&self, key: impl AsRef<[u8]>) -> Result<i64> {
+		into_integer(self.call("TTL", vec![buffer(key)]).await?)
+	}
+}
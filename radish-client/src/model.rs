@@ -0,0 +1,177 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::convert::TryFrom;
+
+use radish_types::*;
+
+use super::{Client, Error, Result};
+use super::{buffer, into_integer, into_reply};
+
+/// Struct stored as a hash, usually implemented by `#[derive(HashModel)]`
+pub trait HashModel: Sized {
+	fn to_hash(&self) -> Vec<(Value, Value)>;
+	/// Fields of the hash which are not in the model are ignored
+	fn from_hash(fields: Vec<(Value, Value)>) -> std::result::Result<Self, String>;
+}
+
+/// Type of a model field. Numbers and booleans are written as typed values
+/// but are also parsed from text, e.g. when the hash was filled by HSET from a CLI
+pub trait HashField: Sized {
+	fn to_field(&self) -> Value;
+	fn from_field(value: Value) -> std::result::Result<Self, String>;
+}
+
+fn unexpected(value: &Value, expected: &str) -> String {
+	format!("expected {}, got {:?}", expected, value)
+}
+
+fn text(value: &[u8]) -> std::result::Result<&str, String> {
+	std::str::from_utf8(value).map_err(|e|format!("{}", e))
+}
+
+impl HashField for String {
+	fn to_field(&self) -> Value {
+		Value::Buffer(self.as_bytes().to_vec())
+	}
+
+	fn from_field(value: Value) -> std::result::Result<Self, String> {
+		match value {
+			Value::Buffer(value) => String::from_utf8(value).map_err(|e|format!("{}", e)),
+			Value::Integer(i) => Ok(i.to_string()),
+			Value::Float(f) => Ok(f64::from_bits(f).to_string()),
+			value => Err(unexpected(&value, "a string")),
+		}
+	}
+}
+
+impl HashField for bool {
+	fn to_field(&self) -> Value {
+		Value::Bool(*self)
+	}
+
+	fn from_field(value: Value) -> std::result::Result<Self, String> {
+		match value {
+			Value::Bool(b) => Ok(b),
+			Value::Integer(0) => Ok(false),
+			Value::Integer(1) => Ok(true),
+			Value::Buffer(ref b) => match text(b)? {
+				"true" | "1" => Ok(true),
+				"false" | "0" => Ok(false),
+				_ => Err(unexpected(&value, "a boolean")),
+			},
+			value => Err(unexpected(&value, "a boolean")),
+		}
+	}
+}
+
+macro_rules! integer_field {
+	($($t:ty),*) => {$(
+		impl HashField for $t {
+			/// Values out of the range of Integer are written as text
+			fn to_field(&self) -> Value {
+				match i64::try_from(*self) {
+					Ok(i) => Value::Integer(i),
+					Err(_) => Value::Buffer(self.to_string().into_bytes()),
+				}
+			}
+
+			fn from_field(value: Value) -> std::result::Result<Self, String> {
+				match value {
+					Value::Integer(i) => <$t>::try_from(i).map_err(|e|format!("{}: {}", i, e)),
+					Value::Buffer(b) => {
+						let text = text(&b)?;
+						text.parse::<$t>().map_err(|e|format!("'{}': {}", text, e))
+					},
+					value => Err(unexpected(&value, "an integer")),
+				}
+			}
+		}
+	)*};
+}
+
+integer_field!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+macro_rules! float_field {
+	($($t:ty),*) => {$(
+		impl HashField for $t {
+			fn to_field(&self) -> Value {
+				Value::Float((*self as f64).to_bits())
+			}
+
+			fn from_field(value: Value) -> std::result::Result<Self, String> {
+				match value {
+					Value::Float(f) => Ok(f64::from_bits(f) as $t),
+					Value::Integer(i) => Ok(i as $t),
+					Value::Buffer(b) => {
+						let text = text(&b)?;
+						text.parse::<$t>().map_err(|e|format!("'{}': {}", text, e))
+					},
+					value => Err(unexpected(&value, "a number")),
+				}
+			}
+		}
+	)*};
+}
+
+float_field!(f32, f64);
+
+fn model_error(key: &[u8], message: String) -> Error {
+	Error::Deserialize {
+		key: key.to_vec(),
+		message,
+	}
+}
+
+impl Client {
+	/// Writes the fields of a model by one HSET; returns its reply, the count of written fields.
+	/// Fields already in the hash but not in the model are kept
+	pub async fn hset_model<T: HashModel>(&self, key: impl AsRef<[u8]>, model: &T) -> Result<i64> {
+		let fields = model.to_hash();
+		let mut arguments = Arguments::with_capacity(1 + 2 * fields.len());
+		arguments.push_back(buffer(key));
+		for (field, value) in fields {
+			arguments.push_back(field);
+			arguments.push_back(value);
+		}
+		into_integer(self.command(Command {
+			command: "HSET".to_owned(),
+			arguments,
+		}).await?)
+	}
+
+	/// Reads a hash by HGETALL; None if the key does not exist
+	pub async fn hget_model<T: HashModel>(&self, key: impl AsRef<[u8]>) -> Result<Option<T>> {
+		let key = key.as_ref();
+		let flat = match into_reply(self.command(Command {
+			command: "HGETALL".to_owned(),
+			arguments: Arguments::from(vec![buffer(key)]),
+		}).await?)? {
+			Value::Array(flat) => flat,
+			value => return Err(Error::UnexpectedReply(value)),
+		};
+		if flat.is_empty() {
+			return Ok(None);
+		}
+		let mut fields = Vec::with_capacity(flat.len() / 2);
+		let mut flat = flat.into_iter();
+		while let (Some(field), Some(value)) = (flat.next(), flat.next()) {
+			fields.push((field, value));
+		}
+		T::from_hash(fields).map(Some).map_err(|e|model_error(key, e))
+	}
+}
@@ -0,0 +1,145 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use radish_types::*;
+
+use super::{Client, Error, Result};
+use super::trace;
+use super::{buffer, make_command, into_reply, into_integer, into_ok, into_optional_buffer, into_buffers};
+
+/// Batch of commands sent in one write; replies are matched by order over the connection
+pub struct Pipeline<'a> {
+	client: &'a Client,
+	commands: Vec<Command>,
+}
+
+/// Replies of a pipeline in the order of its commands. An Error reply of one command
+/// is kept at its position and reported only by the accessor of that position
+#[derive(Debug)]
+pub struct Replies {
+	values: Vec<Value>,
+}
+
+impl<'a> Pipeline<'a> {
+	pub(crate) fn new(client: &'a Client) -> Self {
+		Self {
+			client,
+			commands: Vec::new(),
+		}
+	}
+
+	pub fn command(mut self, cmd: Command) -> Self {
+		self.commands.push(cmd);
+		self
+	}
+
+	fn call(self, command: &str, arguments: Vec<Value>) -> Self {
+		self.command(make_command(command, arguments))
+	}
+
+	pub fn get(self, key: impl AsRef<[u8]>) -> Self {
+		self.call("GET", vec![buffer(key)])
+	}
+
+	pub fn set(self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Self {
+		self.call("SET", vec![buffer(key), buffer(value)])
+	}
+
+	pub fn del(self, key: impl AsRef<[u8]>) -> Self {
+		self.call("DEL", vec![buffer(key)])
+	}
+
+	pub fn incr(self, key: impl AsRef<[u8]>) -> Self {
+		self.incr_by(key, 1)
+	}
+
+	pub fn incr_by(self, key: impl AsRef<[u8]>, increment: i64) -> Self {
+		self.call("INCRBY", vec![buffer(key), Value::Integer(increment)])
+	}
+
+	pub fn expire(self, key: impl AsRef<[u8]>, seconds: u64) -> Self {
+		self.call("EXPIRE", vec![buffer(key), Value::Integer(seconds as i64)])
+	}
+
+	pub fn ttl(self, key: impl AsRef<[u8]>) -> Self {
+		self.call("TTL", vec![buffer(key)])
+	}
+
+	pub fn len(&self) -> usize {
+		self.commands.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.commands.is_empty()
+	}
+
+	pub async fn execute(self) -> Result<Replies> {
+		let connection_id = self.client.connection_id();
+		let span = trace::pipeline_span(&self.commands, connection_id);
+		let children = trace::child_spans(&span, &self.commands, connection_id);
+		let result = trace::instrument(&span, self.client.exchange(&self.commands)).await;
+		trace::record_pipeline(&span, &children, self.client.reconnected_since(connection_id), &result);
+		Ok(Replies::new(result?))
+	}
+}
+
+impl Replies {
+	pub(crate) fn new(values: Vec<Value>) -> Self {
+		Self {values}
+	}
+
+	pub fn len(&self) -> usize {
+		self.values.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.values.is_empty()
+	}
+
+	fn at(&self, index: usize) -> Result<Value> {
+		self.values.get(index).cloned().ok_or_else(||Error::Protocol(format!("No reply at position {}", index)))
+	}
+
+	/// Raw reply; Error replies are returned as `Value::Error`
+	pub fn value(&self, index: usize) -> Option<&Value> {
+		self.values.get(index)
+	}
+
+	pub fn reply(&self, index: usize) -> Result<Value> {
+		into_reply(self.at(index)?)
+	}
+
+	pub fn ok(&self, index: usize) -> Result<()> {
+		into_ok(self.at(index)?)
+	}
+
+	pub fn integer(&self, index: usize) -> Result<i64> {
+		into_integer(self.at(index)?)
+	}
+
+	pub fn buffer(&self, index: usize) -> Result<Option<Vec<u8>>> {
+		into_optional_buffer(self.at(index)?)
+	}
+
+	pub fn buffers(&self, index: usize) -> Result<Vec<Vec<u8>>> {
+		into_buffers(self.at(index)?)
+	}
+
+	pub fn into_values(self) -> Vec<Value> {
+		self.values
+	}
+}
@@ -0,0 +1,232 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Semaphore;
+
+use radish_types::*;
+
+use super::{Address, Client, ClientConfig, ConnectionInfo, Error, Result};
+use super::trace;
+
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+	/// Connections opened at start and kept open by the health checker
+	pub min_connections: usize,
+	/// Upper bound of connections opened at the same time
+	pub max_connections: usize,
+	/// How long `get` waits for a free connection
+	pub checkout_timeout: Duration,
+	/// PING an idle connection before handing it out
+	pub test_on_checkout: bool,
+	/// PING idle connections periodically
+	pub health_check_interval: Option<Duration>,
+	/// Configuration of every pooled connection
+	pub client: ClientConfig,
+}
+
+impl Default for PoolConfig {
+	fn default() -> Self {
+		Self {
+			min_connections: 1,
+			max_connections: 8,
+			checkout_timeout: Duration::from_secs(5),
+			test_on_checkout: false,
+			health_check_interval: Some(Duration::from_secs(30)),
+			client: ClientConfig::default(),
+		}
+	}
+}
+
+struct Inner {
+	address: Address,
+	/// Address for logs
+	addr: String,
+	/// AUTH and SELECT sent on every new connection
+	setup: Vec<Command>,
+	config: PoolConfig,
+	idle: std::sync::Mutex<VecDeque<Client>>,
+	/// One permit per connection which may be checked out
+	permits: Semaphore,
+}
+
+/// Bounded set of connections shared between tasks
+#[derive(Clone)]
+pub struct Pool {
+	inner: Arc<Inner>,
+}
+
+/// Checked out connection; returns to the pool on drop unless it is broken
+pub struct PooledClient {
+	client: Option<Client>,
+	inner: Arc<Inner>,
+}
+
+impl Inner {
+	fn pop_idle(&self) -> Option<Client> {
+		self.idle.lock().unwrap().pop_front()
+	}
+
+	fn push_idle(&self, client: Client) {
+		self.idle.lock().unwrap().push_back(client);
+	}
+
+	fn idle_count(&self) -> usize {
+		self.idle.lock().unwrap().len()
+	}
+
+	async fn connect(&self) -> Result<Client> {
+		let client = Client::connect_to(self.address.clone(), self.config.client.clone()).await?;
+		for cmd in &self.setup {
+			if let Value::Error(err) = client.command(cmd.clone()).await? {
+				return Err(Error::Server(format!("{} failed: {}", cmd.command, err)));
+			}
+		}
+		Ok(client)
+	}
+
+	async fn checkout(&self) -> Result<Client> {
+		while let Some(client) = self.pop_idle() {
+			if client.is_broken() {
+				continue;
+			}
+			if self.config.test_on_checkout && client.ping().await.is_err() {
+				log::debug!("{}: dropped broken connection on checkout", self.addr);
+				continue;
+			}
+			return Ok(client);
+		}
+		self.connect().await
+	}
+
+	async fn health_check(&self) {
+		let count = self.idle_count();
+		for _ in 0..count {
+			let client = match self.pop_idle() {
+				Some(client) => client,
+				None => break,
+			};
+			match client.ping().await {
+				Ok(_) => self.push_idle(client),
+				Err(err) => log::debug!("{}: dropped broken connection: {}", self.addr, err),
+			}
+		}
+		self.fill().await;
+	}
+
+	/// Opens connections until the pool holds `min_connections` idle ones
+	async fn fill(&self) {
+		while self.idle_count() < self.config.min_connections && self.permits.available_permits() > self.idle_count() {
+			match self.connect().await {
+				Ok(client) => self.push_idle(client),
+				Err(err) => {
+					log::warn!("{}: failed to open pooled connection: {}", self.addr, err);
+					break;
+				},
+			}
+		}
+	}
+}
+
+async fn health_checker(inner: Weak<Inner>, interval: Duration) {
+	loop {
+		tokio::time::delay_for(interval).await;
+		match inner.upgrade() {
+			Some(inner) => inner.health_check().await,
+			None => break,
+		}
+	}
+}
+
+impl Pool {
+	pub async fn new(addr: &str, config: PoolConfig) -> Result<Self> {
+		Self::open(Address::Tcp(addr.to_owned()), config, Vec::new()).await
+	}
+
+	/// Pool by a connection string, see `ConnectionInfo`; pool sizes come from the URL parameters
+	pub async fn from_url(url: &str) -> Result<Self> {
+		let info = ConnectionInfo::parse(url)?;
+		Self::open(info.address.clone(), info.pool_config(), info.setup_commands()).await
+	}
+
+	async fn open(address: Address, config: PoolConfig, setup: Vec<Command>) -> Result<Self> {
+		if config.max_connections == 0 || config.min_connections > config.max_connections {
+			return Err(Error::Config(format!("Invalid pool size: min {}, max {}", config.min_connections, config.max_connections)));
+		}
+		let inner = Arc::new(Inner {
+			addr: address.to_string(),
+			address,
+			setup,
+			permits: Semaphore::new(config.max_connections),
+			idle: std::sync::Mutex::new(VecDeque::with_capacity(config.max_connections)),
+			config,
+		});
+		for _ in 0..inner.config.min_connections {
+			inner.push_idle(inner.connect().await?);
+		}
+		if let Some(interval) = inner.config.health_check_interval {
+			tokio::spawn(health_checker(Arc::downgrade(&inner), interval));
+		}
+		Ok(Self {inner})
+	}
+
+	/// Waits for a free connection up to `checkout_timeout`; opens a new one if none is idle
+	pub async fn get(&self) -> Result<PooledClient> {
+		let started = Instant::now();
+		let result = self.checkout().await;
+		trace::pool_checkout(&self.inner.addr, started.elapsed(), result.as_ref().map(|client|client.connection_id()));
+		result
+	}
+
+	async fn checkout(&self) -> Result<PooledClient> {
+		let permit = tokio::time::timeout(self.inner.config.checkout_timeout, self.inner.permits.acquire())
+			.await
+			.map_err(|_|Error::Timeout)?;
+		let client = self.inner.checkout().await?;
+		permit.forget();
+		Ok(PooledClient {
+			client: Some(client),
+			inner: self.inner.clone(),
+		})
+	}
+
+	pub fn idle_connections(&self) -> usize {
+		self.inner.idle_count()
+	}
+}
+
+impl std::ops::Deref for PooledClient {
+	type Target = Client;
+
+	fn deref(&self) -> &Client {
+		self.client.as_ref().unwrap()
+	}
+}
+
+impl Drop for PooledClient {
+	fn drop(&mut self) {
+		if let Some(client) = self.client.take() {
+			if !client.is_broken() {
+				self.inner.push_idle(client);
+			}
+		}
+		self.inner.permits.add_permits(1);
+	}
+}
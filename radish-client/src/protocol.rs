@@ -0,0 +1,106 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Wire format: every frame is a big-endian u32 length followed by a msgpack body, see `radish_types::frame`.
+//! Client sends `Command` frames and receives `Value` frames. The first frame of a connection
+//! is the hello of `radish_types::handshake`; if compression is negotiated by it, or asked for
+//! by `CLIENT COMPRESSION LZ4` on a server without the handshake, the server may send
+//! compressed frames, see `radish_types::compression`.
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt};
+
+use radish_types::*;
+
+use super::{Error, Result};
+
+pub fn encode_command(cmd: &Command) -> Result<Vec<u8>> {
+	rmp_serde::to_vec(cmd).map_err(|e|Error::Protocol(format!("Failed to serialize command: {}", e)))
+}
+
+pub fn decode_value(buf: &[u8]) -> Result<Value> {
+	rmp_serde::from_read_ref(buf).map_err(|e|Error::Protocol(format!("Failed to deserialize value: {}", e)))
+}
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(sock: &mut W, buf: &[u8]) -> Result<()> {
+	sock.write_all(&frame::encode_header(buf.len(), false).map_err(Error::Protocol)?).await?;
+	sock.write_all(buf).await?;
+	Ok(())
+}
+
+/// Sends the hello and returns the negotiated one. None if the server dropped the connection:
+/// it predates the handshake, so the connection has to be opened again without it
+pub async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(sock: &mut S, features: u32) -> Result<Option<handshake::Hello>> {
+	write_frame(sock, &handshake::Hello::new(features).encode()).await?;
+	let buf = match read_frame(sock, frame::DEFAULT_MAX_FRAME_SIZE).await {
+		Ok(buf) => buf,
+		Err(Error::Io(err)) if is_closed(&err) => return Ok(None),
+		Err(err) => return Err(err),
+	};
+	match handshake::Hello::decode(&buf).map_err(Error::Protocol)? {
+		Some(hello) => Ok(Some(hello)),
+		None => Err(Error::Protocol("Unexpected reply to the handshake".to_owned())),
+	}
+}
+
+pub(crate) fn is_closed(err: &std::io::Error) -> bool {
+	matches!(err.kind(), std::io::ErrorKind::UnexpectedEof | std::io::ErrorKind::ConnectionReset | std::io::ErrorKind::BrokenPipe)
+}
+
+/// Features of the hello sent with this configuration
+pub fn hello_features(compression: bool) -> u32 {
+	if compression {handshake::FEATURE_COMPRESSION} else {0}
+}
+
+/// Command asking the server for compressed replies
+pub fn compression_command() -> Command {
+	Command {
+		command: "CLIENT".to_owned(),
+		arguments: vec![Value::Buffer(b"COMPRESSION".to_vec()), Value::Buffer(b"LZ4".to_vec())].into_iter().collect(),
+	}
+}
+
+/// Compressed frames are only sent by the server if the connection asked for them;
+/// the decompressed body is limited by `max_size` as well
+pub(crate) fn unpack_frame(compressed: bool, buf: Vec<u8>, max_size: usize) -> Result<Vec<u8>> {
+	if !compressed {
+		return Ok(buf);
+	}
+	compression::decompress_frame(&buf, max_size).map_err(|e|Error::Protocol(format!("Failed to decompress frame: {}", e)))
+}
+
+/// Returns the body of the frame, decompressed if needed; a frame over `max_size` is an error
+pub async fn read_frame<R: AsyncRead + Unpin>(sock: &mut R, max_size: usize) -> Result<Vec<u8>> {
+	let mut header = [0; frame::HEADER_SIZE];
+	sock.read_exact(&mut header).await?;
+	let (len, compressed) = frame::decode_header(header, max_size).map_err(Error::Protocol)?;
+	let mut buf = vec![0; len];
+	sock.read_exact(&mut buf[..]).await?;
+	unpack_frame(compressed, buf, max_size)
+}
+
+pub async fn send_command<W: AsyncWrite + Unpin>(sock: &mut W, cmd: &Command) -> Result<()> {
+	write_frame(sock, &encode_command(cmd)?).await
+}
+
+pub async fn receive_value<R: AsyncRead + Unpin>(sock: &mut R, max_size: usize) -> Result<Value> {
+	decode_value(&read_frame(sock, max_size).await?)
+}
+
+pub async fn request<S: AsyncRead + AsyncWrite + Unpin>(sock: &mut S, cmd: &Command, max_size: usize) -> Result<Value> {
+	send_command(sock, cmd).await?;
+	receive_value(sock, max_size).await
+}
@@ -0,0 +1,69 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use radish_types::*;
+
+/// State of the connection reported to the application on every change
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConnectionState {
+	Connected,
+	/// The connection failed; it is reopened by the next request
+	Disconnected,
+}
+
+/// Commands which configured the server side session of the connection.
+/// They are replayed in the same order after a reconnect
+#[derive(Default)]
+pub(crate) struct SessionSetup {
+	auth: Option<Command>,
+	select: Option<Command>,
+	name: Option<Command>,
+	compression: Option<Command>,
+}
+
+fn is_subcommand(cmd: &Command, name: &[u8]) -> bool {
+	match cmd.arguments.front() {
+		Some(Value::Buffer(subcommand)) => subcommand.eq_ignore_ascii_case(name),
+		_ => false,
+	}
+}
+
+impl SessionSetup {
+	/// Remembers a setup command once the server has accepted it
+	pub fn record(&mut self, cmd: &Command, reply: &Value) {
+		if let Value::Error(_) = reply {
+			return;
+		}
+		let slot = match &cmd.command.to_uppercase()[..] {
+			"AUTH" => &mut self.auth,
+			"SELECT" => &mut self.select,
+			"CLIENT" if is_subcommand(cmd, b"SETNAME") => &mut self.name,
+			"CLIENT" if is_subcommand(cmd, b"COMPRESSION") => &mut self.compression,
+			_ => return,
+		};
+		*slot = Some(cmd.clone());
+	}
+
+	pub fn commands(&self) -> Vec<Command> {
+		self.auth.iter()
+			.chain(self.select.iter())
+			.chain(self.name.iter())
+			.chain(self.compression.iter())
+			.cloned()
+			.collect()
+	}
+}
@@ -0,0 +1,72 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::{self, Certificate, RootCertStore, ServerCertVerified, TLSError};
+use tokio_rustls::webpki::DNSNameRef;
+
+use super::{Error, Result};
+
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+	/// PEM file with the certificate authorities; the web PKI roots if None
+	pub cacert: Option<PathBuf>,
+	/// Accept any server certificate; for tests only
+	pub insecure: bool,
+	/// Server name to verify the certificate against instead of the host of the address
+	pub sni: Option<String>,
+}
+
+struct NoCertificateVerification;
+
+impl rustls::ServerCertVerifier for NoCertificateVerification {
+	fn verify_server_cert(&self, _roots: &RootCertStore, _presented_certs: &[Certificate], _dns_name: DNSNameRef<'_>, _ocsp_response: &[u8]) -> std::result::Result<ServerCertVerified, TLSError> {
+		Ok(ServerCertVerified::assertion())
+	}
+}
+
+fn make_config(config: &TlsConfig) -> Result<rustls::ClientConfig> {
+	let mut tls = rustls::ClientConfig::new();
+	match &config.cacert {
+		Some(path) => {
+			let file = std::fs::File::open(path).map_err(|e|Error::Config(format!("Failed to open CA certificate '{}': {}", path.display(), e)))?;
+			let mut reader = std::io::BufReader::new(file);
+			tls.root_store.add_pem_file(&mut reader).map_err(|_|Error::Config(format!("Failed to parse CA certificate '{}'", path.display())))?;
+		},
+		None => tls.root_store.add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS),
+	}
+	if config.insecure {
+		tls.dangerous().set_certificate_verifier(Arc::new(NoCertificateVerification));
+	}
+	Ok(tls)
+}
+
+pub async fn connect(sock: TcpStream, addr: &str, config: &TlsConfig) -> Result<TlsStream<TcpStream>> {
+	let host = match &config.sni {
+		Some(sni) => &sni[..],
+		None => addr.rsplit_once(':').map_or(addr, |(host, _)|host),
+	};
+	let host = host.trim_start_matches('[').trim_end_matches(']');
+	let name = DNSNameRef::try_from_ascii_str(host).map_err(|_|Error::Config(format!("'{}' is not a valid DNS name for TLS, set the server name", host)))?;
+	let connector = TlsConnector::from(Arc::new(make_config(config)?));
+	Ok(connector.connect(name, sock).await?)
+}
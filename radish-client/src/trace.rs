@@ -0,0 +1,237 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Spans and events for `tracing` subscribers, enabled by the `tracing` feature.
+//! Fields follow the OpenTelemetry conventions for database clients: the span is named
+//! after the operation through `otel.name`, and only the first key of a command is
+//! recorded; arguments after it may hold values and are never recorded.
+//! Without the feature every function is a no-op, so the call sites need no cfg
+
+use std::future::Future;
+use std::time::Duration;
+
+use radish_types::*;
+
+use super::{Error, Result};
+
+/// Commands whose first argument is not a key; for AUTH and HELLO it is a secret
+#[cfg(feature = "tracing")]
+const KEYLESS_COMMANDS: &[&str] = &[
+	"AUTH", "HELLO", "PING", "ECHO", "SELECT", "INFO", "NOW", "PNOW", "COMMAND", "CONFIG", "CLIENT",
+	"KEYS", "SCAN", "EXPORT", "IMPORT", "SAVE", "BGSAVE", "MULTI", "EXEC", "DISCARD",
+	"SUBSCRIBE", "PSUBSCRIBE", "UNSUBSCRIBE", "PUNSUBSCRIBE", "PUBLISH", "SLOWLOG", "MEMORY",
+];
+
+#[cfg(feature = "tracing")]
+pub type Span = tracing::Span;
+
+#[cfg(not(feature = "tracing"))]
+pub struct Span;
+
+#[cfg(feature = "tracing")]
+fn first_key(cmd: &Command) -> Option<String> {
+	if KEYLESS_COMMANDS.contains(&&cmd.command.to_uppercase()[..]) {
+		return None;
+	}
+	match cmd.arguments.front() {
+		Some(Value::Buffer(key)) => Some(String::from_utf8_lossy(key).into_owned()),
+		Some(Value::Integer(key)) => Some(key.to_string()),
+		_ => None,
+	}
+}
+
+#[cfg(feature = "tracing")]
+pub fn command_span(cmd: &Command, connection_id: u64) -> Span {
+	let operation = cmd.command.to_uppercase();
+	let span = tracing::info_span!("radish.command",
+		otel.name = %operation,
+		otel.kind = "client",
+		otel.status_code = tracing::field::Empty,
+		db.system = "radish",
+		db.operation = %operation,
+		db.radish.key = tracing::field::Empty,
+		db.radish.connection_id = connection_id,
+		outcome = tracing::field::Empty,
+		error.message = tracing::field::Empty,
+	);
+	if let Some(key) = first_key(cmd) {
+		span.record("db.radish.key", key.as_str());
+	}
+	span
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn command_span(_cmd: &Command, _connection_id: u64) -> Span {
+	Span
+}
+
+/// Span of a whole pipeline; spans of its commands are created as children
+#[cfg(feature = "tracing")]
+pub fn pipeline_span(commands: &[Command], connection_id: u64) -> Span {
+	tracing::info_span!("radish.pipeline",
+		otel.name = "PIPELINE",
+		otel.kind = "client",
+		otel.status_code = tracing::field::Empty,
+		db.system = "radish",
+		db.operation = "PIPELINE",
+		db.radish.connection_id = connection_id,
+		db.radish.commands = commands.len() as u64,
+		outcome = tracing::field::Empty,
+		error.message = tracing::field::Empty,
+	)
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn pipeline_span(_commands: &[Command], _connection_id: u64) -> Span {
+	Span
+}
+
+#[cfg(feature = "tracing")]
+pub fn child_spans(parent: &Span, commands: &[Command], connection_id: u64) -> Vec<Span> {
+	parent.in_scope(||commands.iter().map(|cmd|command_span(cmd, connection_id)).collect())
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn child_spans(_parent: &Span, _commands: &[Command], _connection_id: u64) -> Vec<Span> {
+	Vec::new()
+}
+
+#[cfg(feature = "tracing")]
+pub async fn instrument<F: Future>(span: &Span, future: F) -> F::Output {
+	use tracing::Instrument;
+	future.instrument(span.clone()).await
+}
+
+#[cfg(not(feature = "tracing"))]
+pub async fn instrument<F: Future>(_span: &Span, future: F) -> F::Output {
+	future.await
+}
+
+#[cfg(feature = "tracing")]
+fn record_outcome(span: &Span, outcome: &str, error: Option<&str>) {
+	span.record("outcome", outcome);
+	span.record("otel.status_code", if error.is_none() {"OK"} else {"ERROR"});
+	if let Some(error) = error {
+		span.record("error.message", error);
+	}
+}
+
+#[cfg(feature = "tracing")]
+fn record_failure(span: &Span, err: &Error) {
+	match err {
+		Error::Timeout => record_outcome(span, "timeout", Some("Timeout")),
+		err => record_outcome(span, "failed", Some(&err.to_string())),
+	}
+}
+
+#[cfg(feature = "tracing")]
+fn timed_out(span: &Span) {
+	span.in_scope(||tracing::warn!(db.system = "radish", "command timed out"));
+}
+
+#[cfg(feature = "tracing")]
+fn record_reconnect(span: &Span, reconnected: Option<u64>) {
+	if let Some(connection_id) = reconnected {
+		span.record("db.radish.connection_id", connection_id);
+	}
+}
+
+/// Records the outcome of one command: ok, an error reply, a timeout or a failure of the connection.
+/// `reconnected` is the id of the connection opened while the command was running
+#[cfg(feature = "tracing")]
+pub fn record_reply(span: &Span, reconnected: Option<u64>, result: &Result<Value>) {
+	record_reconnect(span, reconnected);
+	match result {
+		Ok(Value::Error(err)) => record_outcome(span, "error", Some(err)),
+		Ok(_) => record_outcome(span, "ok", None),
+		Err(err) => {
+			record_failure(span, err);
+			if let Error::Timeout = err {
+				timed_out(span);
+			}
+		},
+	}
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn record_reply(_span: &Span, _reconnected: Option<u64>, _result: &Result<Value>) {
+}
+
+/// Outcome of a pipeline; error replies are reported only by the spans of their commands
+#[cfg(feature = "tracing")]
+pub fn record_pipeline(span: &Span, children: &[Span], reconnected: Option<u64>, result: &Result<Vec<Value>>) {
+	record_reconnect(span, reconnected);
+	for child in children {
+		record_reconnect(child, reconnected);
+	}
+	match result {
+		Ok(values) => {
+			for (child, value) in children.iter().zip(values) {
+				match value {
+					Value::Error(err) => record_outcome(child, "error", Some(err)),
+					_ => record_outcome(child, "ok", None),
+				}
+			}
+			record_outcome(span, "ok", None);
+		},
+		Err(err) => {
+			for child in children {
+				record_failure(child, err);
+			}
+			record_failure(span, err);
+			if let Error::Timeout = err {
+				timed_out(span);
+			}
+		},
+	}
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn record_pipeline(_span: &Span, _children: &[Span], _reconnected: Option<u64>, _result: &Result<Vec<Value>>) {
+}
+
+#[cfg(feature = "tracing")]
+pub fn reconnected(addr: &str, connection_id: u64) {
+	tracing::info!(db.system = "radish", net.peer.name = addr, db.radish.connection_id = connection_id, "reconnected");
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn reconnected(_addr: &str, _connection_id: u64) {
+}
+
+#[cfg(feature = "tracing")]
+pub fn reconnect_failed(addr: &str, err: &Error) {
+	tracing::warn!(db.system = "radish", net.peer.name = addr, error.message = %err, "reconnect failed");
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn reconnect_failed(_addr: &str, _err: &Error) {
+}
+
+#[cfg(feature = "tracing")]
+pub fn pool_checkout(addr: &str, wait: Duration, result: std::result::Result<u64, &Error>) {
+	let wait_ms = wait.as_millis() as u64;
+	match result {
+		Ok(connection_id) => tracing::debug!(db.system = "radish", net.peer.name = addr, db.radish.connection_id = connection_id, wait_ms, "pool checkout"),
+		Err(Error::Timeout) => tracing::warn!(db.system = "radish", net.peer.name = addr, wait_ms, "pool checkout timed out"),
+		Err(err) => tracing::warn!(db.system = "radish", net.peer.name = addr, wait_ms, error.message = %err, "pool checkout failed"),
+	}
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn pool_checkout(_addr: &str, _wait: Duration, _result: std::result::Result<u64, &Error>) {
+}
@@ -0,0 +1,202 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use radish_client::{Client, Command, Value};
+
+use common::TestServer;
+
+fn command(name: &str, args: &[&str]) -> Command {
+	Command {
+		command: name.to_owned(),
+		arguments: args.iter().map(|arg|Value::Buffer(arg.as_bytes().to_vec())).collect(),
+	}
+}
+
+/// Directory of the audit log files removed with its content on drop
+struct LogDir(PathBuf);
+
+impl LogDir {
+	fn new(name: &str) -> Self {
+		let dir = std::env::temp_dir().join(format!("radish-audit-{}-{}", name, std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+		std::fs::create_dir_all(&dir).unwrap();
+		Self(dir)
+	}
+
+	fn file(&self, name: &str) -> PathBuf {
+		self.0.join(name)
+	}
+}
+
+impl Drop for LogDir {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_dir_all(&self.0);
+	}
+}
+
+async fn audited(path: &Path, parameters: &[(&str, &str)]) -> TestServer {
+	let server = TestServer::start().await;
+	server.storage.config().set("audit-log", path.to_str().unwrap()).unwrap();
+	for (name, value) in parameters {
+		server.storage.config().set(name, value).unwrap();
+	}
+	assert!(server.storage.start_audit_log().unwrap());
+	server
+}
+
+fn read_lines(path: &Path) -> Vec<String> {
+	std::fs::read_to_string(path).unwrap_or_default().lines().map(str::to_owned).collect()
+}
+
+/// Waits for the writer thread to flush `count` records
+async fn wait_for_lines(path: &Path, count: usize) -> Vec<String> {
+	let deadline = Instant::now() + Duration::from_secs(5);
+	loop {
+		let lines = read_lines(path);
+		if lines.len() >= count || Instant::now() > deadline {
+			return lines;
+		}
+		tokio::time::delay_for(Duration::from_millis(5)).await;
+	}
+}
+
+/// The record without its leading timestamp
+fn strip_time(line: &str) -> &str {
+	let (time, rest) = line.split_once(' ').unwrap();
+	assert!(time.parse::<u128>().is_ok(), "{}", line);
+	rest
+}
+
+#[tokio::test]
+async fn scripted_workload() {
+	let dir = LogDir::new("workload");
+	let path = dir.file("audit.log");
+	let server = audited(&path, &[]).await;
+	let client = Client::connect(&server.addr).await.unwrap();
+	let id = match client.command(command("CLIENT", &["ID"])).await.unwrap() {
+		Value::Integer(id) => id,
+		reply => panic!("unexpected CLIENT ID reply {:?}", reply),
+	};
+
+	client.set("a", "secret").await.unwrap();
+	client.get("a").await.unwrap();
+	client.incr("n").await.unwrap();
+	client.lpush("l", &["x", "y"]).await.unwrap();
+	// failed writes are not audited
+	assert!(matches!(client.command(command("LPUSH", &["a", "x"])).await.unwrap(), Value::Error(_)));
+	client.command(command("MSET", &["k1", "v1", "k2", "v2"])).await.unwrap();
+	client.del(&["a", "missing"]).await.unwrap();
+	client.command(command("SORT", &["l", "ALPHA"])).await.unwrap();
+	client.command(command("SORT", &["l", "ALPHA", "STORE", "sorted"])).await.unwrap();
+
+	let lines = wait_for_lines(&path, 6).await;
+	let records: Vec<&str> = lines.iter().map(|line|strip_time(line)).collect();
+	let prefix = format!("id={} addr=127.0.0.1:", id);
+	for record in &records {
+		assert!(record.starts_with(&prefix), "{}", record);
+	}
+	let tails: Vec<&str> = records.iter().map(|record|record.split_once(" user=").unwrap().1).collect();
+	assert_eq!(tails, vec![
+		r#"default cmd=set keys=["a"]"#,
+		r#"default cmd=incr keys=["n"]"#,
+		r#"default cmd=lpush keys=["l"]"#,
+		r#"default cmd=mset keys=["k1","k2"]"#,
+		r#"default cmd=del keys=["a","missing"]"#,
+		r#"default cmd=sort keys=["l"]"#,
+	]);
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn values_are_truncated() {
+	let dir = LogDir::new("values");
+	let path = dir.file("audit.log");
+	let server = audited(&path, &[("audit-log-values", "yes"), ("audit-log-value-length", "4")]).await;
+	let client = Client::connect(&server.addr).await.unwrap();
+
+	client.set("a", "abcdefgh").await.unwrap();
+	client.hset("h", "f", "v").await.unwrap();
+
+	let lines = wait_for_lines(&path, 2).await;
+	assert_eq!(lines.len(), 2);
+	assert!(lines[0].ends_with(r#"cmd=set keys=["a"] values=["abcd"...]"#), "{}", lines[0]);
+	assert!(lines[1].ends_with(r#"cmd=hset keys=["h"] values=["f","v"]"#), "{}", lines[1]);
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn authenticated_user() {
+	let dir = LogDir::new("user");
+	let path = dir.file("audit.log");
+	let server = audited(&path, &[]).await;
+	server.storage.config().set("requirepass", "s3cret").unwrap();
+	let client = Client::connect(&server.addr).await.unwrap();
+
+	client.command(command("AUTH", &["s3cret"])).await.unwrap();
+	client.set("a", "v").await.unwrap();
+
+	let lines = wait_for_lines(&path, 1).await;
+	assert_eq!(lines.len(), 1);
+	assert!(lines[0].contains(" user=default cmd=set "), "{}", lines[0]);
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn rotation_by_size() {
+	let dir = LogDir::new("rotation");
+	let path = dir.file("audit.log");
+	let server = audited(&path, &[("audit-log-max-size", "512"), ("audit-log-retention", "2")]).await;
+	let client = Client::connect(&server.addr).await.unwrap();
+
+	for i in 0..100 {
+		client.set(format!("key:{:03}", i), "v").await.unwrap();
+	}
+	// the last record lands in the current file
+	let deadline = Instant::now() + Duration::from_secs(5);
+	while !read_lines(&path).last().is_some_and(|line|line.contains("key:099")) && Instant::now() < deadline {
+		tokio::time::delay_for(Duration::from_millis(5)).await;
+	}
+
+	let mut kept = Vec::new();
+	for file in &[dir.file("audit.log.2"), dir.file("audit.log.1"), path.clone()] {
+		let size = std::fs::metadata(file).unwrap().len();
+		assert!(size <= 512, "{} has {} bytes", file.display(), size);
+		kept.extend(read_lines(file));
+	}
+	assert!(!dir.file("audit.log.3").exists());
+
+	// the kept files hold the latest whole records in order
+	let keys: Vec<usize> = kept.iter()
+		.map(|line|line.split("keys=[\"key:").nth(1).unwrap()[..3].parse().unwrap())
+		.collect();
+	assert!(keys.len() < 100);
+	let first = keys[0];
+	assert_eq!(keys, (first..100).collect::<Vec<_>>());
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn disabled_without_a_path() {
+	let server = TestServer::start().await;
+	assert!(!server.storage.start_audit_log().unwrap());
+	assert_eq!(server.info("audit_log_dropped").await, 0);
+	server.stop().await;
+}
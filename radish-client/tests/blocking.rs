@@ -0,0 +1,119 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Blocking pops on one connection woken by pushes from another
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use radish_client::{Client, Command, Value};
+
+use common::TestServer;
+
+fn cmd(command: &str, arguments: Vec<Value>) -> Command {
+	Command {
+		command: command.to_owned(),
+		arguments: arguments.into_iter().collect(),
+	}
+}
+
+fn buf(value: &str) -> Value {
+	Value::Buffer(value.as_bytes().to_vec())
+}
+
+fn popped(key: &str, value: &str) -> Value {
+	Value::Array(vec![buf(key), buf(value)].into_iter().collect())
+}
+
+/// Waits until the server counts `count` blocked clients
+async fn blocked(server: &TestServer, count: i64) {
+	while server.info("blocked_clients").await != count {
+		tokio::time::delay_for(Duration::from_millis(5)).await;
+	}
+}
+
+async fn spawn_pop(server: &TestServer, command: &'static str, keys: &[&str]) -> tokio::task::JoinHandle<Value> {
+	let client = Client::connect(&server.addr).await.unwrap();
+	let mut arguments: Vec<Value> = keys.iter().map(|key|buf(key)).collect();
+	arguments.push(Value::Integer(0));
+	tokio::spawn(async move {
+		client.execute(cmd(command, arguments)).await.unwrap()
+	})
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn push_wakes_a_pop_blocked_on_another_connection() {
+	let server = TestServer::start().await;
+	let pusher = Client::connect(&server.addr).await.unwrap();
+
+	let blpop = spawn_pop(&server, "BLPOP", &["empty", "list"]).await;
+	blocked(&server, 1).await;
+	// the blocked connection does not stall the others
+	pusher.ping().await.unwrap();
+	// LPUSH prepends one by one, so the list reads a, b
+	assert_eq!(pusher.lpush("list", &["b", "a"]).await.unwrap(), 2);
+	assert_eq!(blpop.await.unwrap(), popped("list", "a"));
+
+	let brpop = spawn_pop(&server, "BRPOP", &["list"]).await;
+	assert_eq!(brpop.await.unwrap(), popped("list", "b"));
+	assert_eq!(server.info("blocked_clients").await, 0);
+
+	server.stop().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn waiters_are_served_in_fifo_order() {
+	let server = TestServer::start().await;
+	let pusher = Client::connect(&server.addr).await.unwrap();
+
+	let mut waiters = Vec::new();
+	for i in 0..3 {
+		waiters.push(spawn_pop(&server, "BLPOP", &["list"]).await);
+		blocked(&server, i + 1).await;
+	}
+	let mut waiters = waiters.into_iter();
+
+	// one element wakes exactly one waiter, the oldest
+	pusher.lpush("list", &["a"]).await.unwrap();
+	assert_eq!(waiters.next().unwrap().await.unwrap(), popped("list", "a"));
+	tokio::time::delay_for(Duration::from_millis(50)).await;
+	assert_eq!(server.info("blocked_clients").await, 2);
+
+	pusher.lpush("list", &["c", "b"]).await.unwrap();
+	assert_eq!(waiters.next().unwrap().await.unwrap(), popped("list", "b"));
+	assert_eq!(waiters.next().unwrap().await.unwrap(), popped("list", "c"));
+
+	server.stop().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn timeout_replies_nil() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+
+	let started = Instant::now();
+	let reply = client.execute(cmd("BRPOP", vec![buf("list"), Value::Float(0.1f64.to_bits())])).await.unwrap();
+	assert_eq!(reply, Value::Nill);
+	assert!(started.elapsed() >= Duration::from_millis(100));
+	assert_eq!(server.info("blocked_clients").await, 0);
+
+	let reply = client.command(cmd("BLPOP", vec![buf("list"), Value::Integer(-1)])).await.unwrap();
+	assert_eq!(reply, Value::Error("ERR timeout is negative".to_owned()));
+
+	server.stop().await;
+}
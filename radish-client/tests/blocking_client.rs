@@ -0,0 +1,131 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! The blocking client from plain threads, with no runtime of the test around it
+
+mod common;
+
+use std::sync::Arc;
+
+use radish_client::blocking::Client;
+use radish_client::{Command, Error, Value};
+
+use common::ThreadServer;
+
+#[test]
+fn typed_methods() {
+	let server = ThreadServer::start();
+	let client = Client::connect(&server.addr).unwrap();
+
+	client.ping().unwrap();
+	assert_eq!(client.get("a").unwrap(), None);
+	client.set("a", "1").unwrap();
+	assert_eq!(client.incr("a").unwrap(), 2);
+	assert_eq!(client.incr_by("a", -5).unwrap(), -3);
+	assert_eq!(client.get("a").unwrap(), Some(b"-3".to_vec()));
+
+	assert_eq!(client.lpush("list", &["b", "a"]).unwrap(), 2);
+	assert_eq!(client.lrange("list", 0, -1).unwrap(), vec![b"a".to_vec(), b"b".to_vec()]);
+	assert_eq!(client.sadd("set", &["x", "x"]).unwrap(), 1);
+	assert_eq!(client.smembers("set").unwrap(), vec![b"x".to_vec()]);
+	client.hset("hash", "f", "v").unwrap();
+	assert_eq!(client.hget("hash", "f").unwrap(), Some(b"v".to_vec()));
+	assert_eq!(client.hget_all("hash").unwrap(), vec![(b"f".to_vec(), b"v".to_vec())]);
+
+	assert!(client.expire("a", 100).unwrap());
+	assert!((99..=100).contains(&client.ttl("a").unwrap()));
+	client.set_ex("b", "v", 50).unwrap();
+	assert_eq!(client.del(&["a", "b", "missing"]).unwrap(), 2);
+
+	match client.get("list") {
+		Err(Error::Server(err)) => assert!(err.starts_with("WRONGTYPE"), "{}", err),
+		reply => panic!("unexpected reply {:?}", reply),
+	}
+	let reply = client.command(Command {command: "GET".to_owned(), arguments: vec![Value::Buffer(b"list".to_vec())].into()}).unwrap();
+	assert!(matches!(reply, Value::Error(err) if err.starts_with("WRONGTYPE")));
+}
+
+#[test]
+fn pipeline() {
+	let server = ThreadServer::start();
+	let client = Client::connect(&server.addr).unwrap();
+
+	let replies = client.pipeline()
+		.set("k", "v")
+		.get("k")
+		.incr("counter")
+		.incr("k")
+		.expire("k", 100)
+		.ttl("k")
+		.del("k")
+		.execute()
+		.unwrap();
+	assert_eq!(replies.len(), 7);
+	replies.ok(0).unwrap();
+	assert_eq!(replies.buffer(1).unwrap(), Some(b"v".to_vec()));
+	assert_eq!(replies.integer(2).unwrap(), 1);
+	assert!(matches!(replies.value(3), Some(Value::Error(_))));
+	assert!((99..=100).contains(&replies.integer(5).unwrap()));
+	assert_eq!(replies.integer(6).unwrap(), 1);
+	assert!(client.pipeline().execute().unwrap().is_empty());
+}
+
+/// Threads without a runtime share one client
+#[test]
+fn shared_between_threads() {
+	let server = ThreadServer::start();
+	let client = Arc::new(Client::connect(&server.addr).unwrap());
+
+	let threads: Vec<_> = (0..8).map(|_| {
+		let client = client.clone();
+		std::thread::spawn(move || {
+			for _ in 0..100 {
+				client.incr("counter").unwrap();
+			}
+		})
+	}).collect();
+	for thread in threads {
+		thread.join().unwrap();
+	}
+	assert_eq!(client.get("counter").unwrap(), Some(b"800".to_vec()));
+}
+
+/// A caller which already runs a runtime, on its own worker thread and from a blocking task
+#[test]
+fn inside_a_caller_runtime() {
+	let server = ThreadServer::start();
+	let client = Arc::new(Client::connect(&server.addr).unwrap());
+
+	let mut runtime = tokio::runtime::Builder::new()
+		.basic_scheduler()
+		.enable_all()
+		.build()
+		.unwrap();
+	runtime.block_on(async {
+		client.set("k", "from the runtime").unwrap();
+		let client = client.clone();
+		let value = tokio::task::spawn_blocking(move ||client.get("k").unwrap()).await.unwrap();
+		assert_eq!(value, Some(b"from the runtime".to_vec()));
+	});
+
+	// a client made inside one runtime keeps working after it is gone
+	let made_inside = runtime.block_on(async {
+		Client::connect(&server.addr).unwrap()
+	});
+	drop(runtime);
+	assert_eq!(made_inside.get("k").unwrap(), Some(b"from the runtime".to_vec()));
+}
@@ -0,0 +1,105 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use serde::{Deserialize, Serialize};
+
+use radish_client::{Client, Error};
+
+use common::TestServer;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Profile {
+	name: String,
+	nickname: Option<String>,
+	age: Option<u32>,
+	scores: Vec<Vec<i64>>,
+	tags: Vec<String>,
+}
+
+fn profile() -> Profile {
+	Profile {
+		name: "Дмитрий 🌱 日本".to_owned(),
+		nickname: None,
+		age: Some(42),
+		scores: vec![vec![], vec![1, -2], vec![i64::MAX, i64::MIN]],
+		tags: vec!["ä".to_owned(), String::new()],
+	}
+}
+
+/// `Profile` after a schema change: a field changed its type
+#[derive(Deserialize, Debug)]
+struct NewProfile {
+	#[allow(dead_code)]
+	name: u64,
+}
+
+#[tokio::test]
+async fn json_and_msgpack_round_trip() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+
+	client.set_json("json", &profile()).await.unwrap();
+	assert_eq!(client.get_json::<Profile>("json").await.unwrap(), Some(profile()));
+	client.set_msgpack("msgpack", &profile()).await.unwrap();
+	assert_eq!(client.get_msgpack::<Profile>("msgpack").await.unwrap(), Some(profile()));
+
+	assert_eq!(client.get_json::<Profile>("missing").await.unwrap(), None);
+	assert_eq!(client.get_msgpack::<Profile>("missing").await.unwrap(), None);
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn hash_model_round_trip() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+
+	assert_eq!(client.hset_serde_model("hash", &profile()).await.unwrap(), 5);
+	assert_eq!(client.hget_serde_model::<Profile>("hash").await.unwrap(), Some(profile()));
+	assert_eq!(client.hget_serde_model::<Profile>("missing").await.unwrap(), None);
+	assert!(matches!(client.hset_serde_model("hash", &vec![1, 2]).await, Err(Error::Serialize(_))));
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn stale_schema_reports_the_key() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+	client.set_json("user:1", &profile()).await.unwrap();
+	client.set_msgpack("user:2", &profile()).await.unwrap();
+	client.hset_serde_model("user:3", &profile()).await.unwrap();
+
+	match client.get_json::<NewProfile>("user:1").await {
+		Err(Error::Deserialize {key, ..}) => assert_eq!(key, b"user:1"),
+		reply => panic!("unexpected reply {:?}", reply),
+	}
+	match client.get_msgpack::<NewProfile>("user:2").await {
+		Err(Error::Deserialize {key, ..}) => assert_eq!(key, b"user:2"),
+		reply => panic!("unexpected reply {:?}", reply),
+	}
+	match client.hget_serde_model::<NewProfile>("user:3").await {
+		Err(Error::Deserialize {key, ..}) => assert_eq!(key, b"user:3"),
+		reply => panic!("unexpected reply {:?}", reply),
+	}
+	// a value which is not JSON at all
+	client.set("user:4", [0xff, 0x00]).await.unwrap();
+	match client.get_json::<Profile>("user:4").await {
+		Err(err @ Error::Deserialize {..}) => assert!(err.to_string().contains("user:4"), "{}", err),
+		reply => panic!("unexpected reply {:?}", reply),
+	}
+	server.stop().await;
+}
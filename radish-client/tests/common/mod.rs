@@ -0,0 +1,98 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! In-process server for the integration tests, listening on an ephemeral port
+
+#![allow(dead_code)]
+
+use radish_client::{Command, Value};
+use radish_database::{Session, Storage};
+use radish_server::{Server, ServerHandle};
+
+pub struct TestServer {
+	pub storage: Storage,
+	pub addr: String,
+	handle: ServerHandle,
+}
+
+impl TestServer {
+	pub async fn start() -> Self {
+		Self::start_on("127.0.0.1:0").await
+	}
+
+	/// Server on the `host:port` of a stopped one, e.g. to restart it under a running client
+	pub async fn start_on(addr: &str) -> Self {
+		let (host, port) = addr.rsplit_once(':').unwrap();
+		let storage = Storage::new();
+		storage.config().set("bind", host).unwrap();
+		storage.config().set("port", port).unwrap();
+		let server = Server::bind(storage.clone()).await.unwrap();
+		let addr = server.listeners()[0].trim_start_matches("tcp ").to_owned();
+		Self {storage, addr, handle: server.start()}
+	}
+
+	/// Integer field of the INFO reply, e.g. `total_connections_received`
+	pub async fn info(&self, field: &str) -> i64 {
+		let info = self.storage.execute(&mut Session::default(), Command {command: "INFO".to_owned(), arguments: Default::default()}).await;
+		let info = match info {
+			Value::Buffer(info) => String::from_utf8(info).unwrap(),
+			info => panic!("unexpected INFO reply {:?}", info),
+		};
+		let prefix = format!("{}:", field);
+		let line = info.lines().find(|line|line.starts_with(&prefix)).unwrap_or_else(||panic!("no {} in INFO", field));
+		line[prefix.len()..].trim().parse().unwrap()
+	}
+
+	pub async fn stop(self) {
+		self.handle.shutdown().await.unwrap();
+	}
+}
+
+/// Server running on its own runtime in a background thread, for the tests of the
+/// blocking client which must not run inside a runtime themselves
+pub struct ThreadServer {
+	pub addr: String,
+	stop: Option<tokio::sync::oneshot::Sender<()>>,
+	thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl ThreadServer {
+	pub fn start() -> Self {
+		let (started, addr) = std::sync::mpsc::channel();
+		let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+		let thread = std::thread::spawn(move || {
+			let mut runtime = tokio::runtime::Runtime::new().unwrap();
+			runtime.block_on(async move {
+				let server = TestServer::start().await;
+				started.send(server.addr.clone()).unwrap();
+				let _ = stopped.await;
+				server.stop().await;
+			});
+		});
+		Self {
+			addr: addr.recv().unwrap(),
+			stop: Some(stop),
+			thread: Some(thread),
+		}
+	}
+}
+
+impl Drop for ThreadServer {
+	fn drop(&mut self) {
+		let _ = self.stop.take().unwrap().send(());
+		let _ = self.thread.take().unwrap().join();
+	}
+}
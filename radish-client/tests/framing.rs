@@ -0,0 +1,136 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use radish_client::{Client, ClientConfig, Error, RetryPolicy};
+
+use common::TestServer;
+
+const MIB: usize = 1024 * 1024;
+
+fn config() -> ClientConfig {
+	ClientConfig {
+		retry: RetryPolicy::none(),
+		..Default::default()
+	}
+}
+
+/// Forwards one connection to `upstream`, counting the bytes sent back to the client
+async fn counting_proxy(upstream: String) -> (String, Arc<AtomicUsize>) {
+	let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let addr = listener.local_addr().unwrap().to_string();
+	let received = Arc::new(AtomicUsize::new(0));
+	let counter = received.clone();
+	tokio::spawn(async move {
+		let (client, _) = listener.accept().await.unwrap();
+		let server = TcpStream::connect(&upstream).await.unwrap();
+		let (mut client_read, mut client_write) = tokio::io::split(client);
+		let (mut server_read, mut server_write) = tokio::io::split(server);
+		tokio::spawn(async move {
+			let _ = tokio::io::copy(&mut client_read, &mut server_write).await;
+		});
+		let mut buf = vec![0u8; 64 * 1024];
+		loop {
+			let n = match server_read.read(&mut buf).await {
+				Ok(0) | Err(_) => break,
+				Ok(n) => n,
+			};
+			counter.fetch_add(n, Ordering::SeqCst);
+			if client_write.write_all(&buf[..n]).await.is_err() {
+				break;
+			}
+		}
+	});
+	(addr, received)
+}
+
+#[tokio::test]
+async fn one_mib_value_round_trip() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+	let value = (0..MIB).map(|i|(i % 251) as u8).collect::<Vec<u8>>();
+	client.set("big", &value).await.unwrap();
+	assert_eq!(client.get("big").await.unwrap(), Some(value));
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn compressed_reply_round_trip() {
+	let server = TestServer::start().await;
+	let client = Client::connect_with_config(&server.addr, ClientConfig {compression: true, ..config()}).await.unwrap();
+	let value = b"radish ".repeat(MIB / 7);
+	client.set("big", &value).await.unwrap();
+	assert_eq!(client.get("big").await.unwrap(), Some(value));
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn reply_over_the_client_limit_is_rejected() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+	client.set("big", vec![b'x'; 64 * 1024]).await.unwrap();
+
+	let limited = Client::connect_with_config(&server.addr, ClientConfig {max_frame_size: 1024, ..config()}).await.unwrap();
+	assert!(matches!(limited.get("big").await, Err(Error::Protocol(_))));
+
+	// a compressed frame is small, but the body it expands to is over the limit
+	let compressed = Client::connect_with_config(&server.addr, ClientConfig {max_frame_size: 1024, compression: true, ..config()}).await.unwrap();
+	match compressed.get("big").await {
+		Err(Error::Protocol(err)) => assert!(err.contains("exceeds the limit"), "{}", err),
+		reply => panic!("unexpected reply {:?}", reply),
+	}
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn command_over_the_server_limit_is_rejected() {
+	let server = TestServer::start().await;
+	server.storage.config().set("max-frame-size", "1024").unwrap();
+	let client = Client::connect_with_config(&server.addr, config()).await.unwrap();
+	match client.set("big", vec![b'x'; 4096]).await {
+		Err(Error::Server(err)) => assert!(err.contains("over the limit"), "{}", err),
+		reply => panic!("unexpected reply {:?}", reply),
+	}
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn five_mib_value_with_and_without_compression() {
+	let server = TestServer::start().await;
+	let value = (0..5 * MIB / 16).flat_map(|i|format!("{:>15}\n", i % 1000).into_bytes()).collect::<Vec<u8>>();
+	Client::connect(&server.addr).await.unwrap().set("big", &value).await.unwrap();
+
+	let (plain_addr, plain_bytes) = counting_proxy(server.addr.clone()).await;
+	let plain = Client::connect_with_config(&plain_addr, config()).await.unwrap();
+	assert_eq!(plain.get("big").await.unwrap().as_ref(), Some(&value));
+
+	let (compressed_addr, compressed_bytes) = counting_proxy(server.addr.clone()).await;
+	let compressed = Client::connect_with_config(&compressed_addr, ClientConfig {compression: true, ..config()}).await.unwrap();
+	assert_eq!(compressed.get("big").await.unwrap().as_ref(), Some(&value));
+
+	let plain_bytes = plain_bytes.load(Ordering::SeqCst);
+	let compressed_bytes = compressed_bytes.load(Ordering::SeqCst);
+	assert!(plain_bytes > value.len(), "{}", plain_bytes);
+	assert!(compressed_bytes * 10 < value.len(), "{}", compressed_bytes);
+	server.stop().await;
+}
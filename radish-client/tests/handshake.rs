@@ -0,0 +1,119 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use tokio::net::{TcpListener, TcpStream};
+
+use radish_client::{protocol, handshake, Client, ClientConfig, Command, Value};
+
+use common::TestServer;
+
+/// Server predating the handshake: it drops a connection starting with a hello,
+/// otherwise it replies to GET with "legacy", to CLIENT with an error and to the rest with OK.
+/// Returns its address and the log of the first frames and commands it received
+async fn legacy_server() -> (String, Arc<Mutex<Vec<String>>>) {
+	let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let addr = listener.local_addr().unwrap().to_string();
+	let log = Arc::new(Mutex::new(Vec::new()));
+	let received = log.clone();
+	tokio::spawn(async move {
+		loop {
+			let (mut sock, _) = listener.accept().await.unwrap();
+			let log = received.clone();
+			tokio::spawn(async move {
+				while let Ok(frame) = protocol::read_frame(&mut sock, 0).await {
+					if frame.starts_with(&handshake::MAGIC) {
+						log.lock().unwrap().push("hello".to_owned());
+						return;
+					}
+					let cmd: Command = rmp_serde::from_read_ref(&frame).unwrap();
+					log.lock().unwrap().push(cmd.command.clone());
+					let reply = match &cmd.command[..] {
+						"GET" => Value::Buffer(b"legacy".to_vec()),
+						"CLIENT" => Value::Error("ERR unknown command 'CLIENT'".to_owned()),
+						_ => Value::Ok,
+					};
+					protocol::write_frame(&mut sock, &rmp_serde::to_vec(&reply).unwrap()).await.unwrap();
+				}
+			});
+		}
+	});
+	(addr, log)
+}
+
+fn command(name: &str, arguments: &[&str]) -> Command {
+	Command {
+		command: name.to_owned(),
+		arguments: arguments.iter().map(|arg|Value::Buffer(arg.as_bytes().to_vec())).collect(),
+	}
+}
+
+#[tokio::test]
+async fn new_client_falls_back_to_a_legacy_server() {
+	let (addr, log) = legacy_server().await;
+	let client = Client::connect(&addr).await.unwrap();
+	assert_eq!(client.get("k").await.unwrap(), Some(b"legacy".to_vec()));
+	client.set("k", "v").await.unwrap();
+	// the hello is sent once, the commands go over the second connection in v0
+	assert_eq!(*log.lock().unwrap(), vec!["hello", "GET", "SET"]);
+}
+
+#[tokio::test]
+async fn compression_is_asked_by_command_from_a_legacy_server() {
+	let (addr, log) = legacy_server().await;
+	let config = ClientConfig {compression: true, ..Default::default()};
+	let client = Client::connect_with_config(&addr, config).await.unwrap();
+	// the server refused compression, so the replies stay plain
+	assert_eq!(client.get("k").await.unwrap(), Some(b"legacy".to_vec()));
+	assert_eq!(*log.lock().unwrap(), vec!["hello", "CLIENT", "GET"]);
+}
+
+#[tokio::test]
+async fn legacy_client_is_served_by_a_new_server() {
+	let server = TestServer::start().await;
+	let mut sock = TcpStream::connect(&server.addr).await.unwrap();
+	let max = radish_client::frame::DEFAULT_MAX_FRAME_SIZE;
+	assert_eq!(protocol::request(&mut sock, &command("SET", &["k", "v"]), max).await.unwrap(), Value::Ok);
+	assert_eq!(protocol::request(&mut sock, &command("GET", &["k"]), max).await.unwrap(), Value::Buffer(b"v".to_vec()));
+	// only the first frame may be a hello, later a frame with the magic is a broken command
+	protocol::write_frame(&mut sock, &handshake::Hello::new(0).encode()).await.unwrap();
+	assert!(protocol::receive_value(&mut sock, max).await.is_err());
+}
+
+#[tokio::test]
+async fn new_client_negotiates_with_a_new_server() {
+	let server = TestServer::start().await;
+	let max = radish_client::frame::DEFAULT_MAX_FRAME_SIZE;
+
+	let mut sock = TcpStream::connect(&server.addr).await.unwrap();
+	let hello = protocol::handshake(&mut sock, handshake::FEATURE_COMPRESSION | 1 << 31).await.unwrap().unwrap();
+	// unknown features of the client are not granted
+	assert_eq!(hello, handshake::Hello::new(handshake::FEATURE_COMPRESSION));
+	assert_eq!(protocol::request(&mut sock, &command("SET", &["k", "v"]), max).await.unwrap(), Value::Ok);
+
+	let mut sock = TcpStream::connect(&server.addr).await.unwrap();
+	let hello = protocol::handshake(&mut sock, 0).await.unwrap().unwrap();
+	assert_eq!(hello, handshake::Hello::new(0));
+	assert_eq!(protocol::request(&mut sock, &command("GET", &["k"]), max).await.unwrap(), Value::Buffer(b"v".to_vec()));
+
+	// a broken hello closes the connection
+	let mut sock = TcpStream::connect(&server.addr).await.unwrap();
+	protocol::write_frame(&mut sock, b"RDSH\x00").await.unwrap();
+	assert!(protocol::read_frame(&mut sock, max).await.is_err());
+}
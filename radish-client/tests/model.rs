@@ -0,0 +1,179 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_client::{Client, Command, Error, HashModel, Value};
+
+use common::TestServer;
+
+#[derive(HashModel, Debug, Clone, PartialEq)]
+struct User {
+	name: String,
+	#[radish(rename = "years")]
+	age: u8,
+	score: f64,
+	ratio: f32,
+	balance: i64,
+	visits: u64,
+	active: bool,
+	nickname: Option<String>,
+	email: Option<String>,
+	#[radish(skip)]
+	cache: Vec<u8>,
+	r#type: String,
+}
+
+fn user() -> User {
+	User {
+		name: "Ann".to_owned(),
+		age: 42,
+		score: 1.5,
+		ratio: 0.25,
+		balance: -7,
+		visits: u64::MAX,
+		active: true,
+		nickname: Some("ann".to_owned()),
+		email: None,
+		cache: b"cached".to_vec(),
+		r#type: "admin".to_owned(),
+	}
+}
+
+async fn hgetall(client: &Client, key: &str) -> Vec<(String, Value)> {
+	let reply = client.execute(Command {
+		command: "HGETALL".to_owned(),
+		arguments: vec![Value::Buffer(key.as_bytes().to_vec())].into(),
+	}).await.unwrap();
+	let flat: Vec<Value> = match reply {
+		Value::Array(flat) => flat.into_iter().collect(),
+		reply => panic!("unexpected HGETALL reply {:?}", reply),
+	};
+	let mut fields: Vec<(String, Value)> = flat.chunks(2).map(|pair|match &pair[0] {
+		Value::Buffer(name) => (String::from_utf8(name.clone()).unwrap(), pair[1].clone()),
+		name => panic!("unexpected field name {:?}", name),
+	}).collect();
+	fields.sort_by(|a, b|a.0.cmp(&b.0));
+	fields
+}
+
+async fn hset_text(client: &Client, key: &str, fields: &[(&str, &str)]) {
+	for (field, value) in fields {
+		client.hset(key, field, value).await.unwrap();
+	}
+}
+
+#[tokio::test]
+async fn round_trip() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+
+	assert_eq!(client.hset_model("user:1", &user()).await.unwrap(), 9);
+	let loaded: User = client.hget_model("user:1").await.unwrap().unwrap();
+	// the skipped field is not stored, so it is loaded as the default
+	assert_eq!(loaded, User {cache: Vec::new(), ..user()});
+	assert_eq!(client.hget_model::<User>("user:2").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn renames_options_and_skip() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+	client.hset_model("user:1", &user()).await.unwrap();
+
+	let names: Vec<String> = hgetall(&client, "user:1").await.into_iter().map(|(name, _)|name).collect();
+	assert_eq!(names, vec!["active", "balance", "name", "nickname", "ratio", "score", "type", "visits", "years"]);
+
+	// None is not written, and a field of the hash missing in the model is kept
+	client.hset("user:1", "extra", "x").await.unwrap();
+	assert_eq!(client.hset_model("user:1", &User {nickname: None, email: Some("a@b.c".to_owned()), ..user()}).await.unwrap(), 9);
+	let loaded: User = client.hget_model("user:1").await.unwrap().unwrap();
+	// HSET does not remove the previous nickname
+	assert_eq!(loaded.nickname, Some("ann".to_owned()));
+	assert_eq!(loaded.email, Some("a@b.c".to_owned()));
+	assert!(hgetall(&client, "user:1").await.iter().any(|(name, _)|name == "extra"));
+}
+
+#[tokio::test]
+async fn numeric_types_are_parsed_from_text() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+	hset_text(&client, "user:1", &[
+		("name", "Bob"), ("years", "7"), ("score", "-2.5"), ("ratio", "3"), ("balance", "-9223372036854775808"),
+		("visits", "18446744073709551615"), ("active", "false"), ("type", "guest"),
+	]).await;
+
+	let loaded: User = client.hget_model("user:1").await.unwrap().unwrap();
+	assert_eq!(loaded, User {
+		name: "Bob".to_owned(),
+		age: 7,
+		score: -2.5,
+		ratio: 3.0,
+		balance: i64::MIN,
+		visits: u64::MAX,
+		active: false,
+		nickname: None,
+		email: None,
+		cache: Vec::new(),
+		r#type: "guest".to_owned(),
+	});
+}
+
+#[tokio::test]
+async fn missing_and_invalid_fields() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+	client.hset_model("user:1", &user()).await.unwrap();
+	client.execute(Command {
+		command: "HDEL".to_owned(),
+		arguments: vec![Value::Buffer(b"user:1".to_vec()), Value::Buffer(b"years".to_vec())].into(),
+	}).await.unwrap();
+
+	match client.hget_model::<User>("user:1").await {
+		Err(Error::Deserialize {key, message}) => {
+			assert_eq!(key, b"user:1");
+			assert_eq!(message, "missing field 'years'");
+		},
+		result => panic!("unexpected result {:?}", result),
+	}
+	let err = client.hget_model::<User>("user:1").await.unwrap_err();
+	assert_eq!(err.to_string(), "Failed to deserialize 'user:1': missing field 'years'");
+
+	hset_text(&client, "user:1", &[("years", "300")]).await;
+	let err = client.hget_model::<User>("user:1").await.unwrap_err();
+	assert!(err.to_string().starts_with("Failed to deserialize 'user:1': field 'years': '300': "), "{}", err);
+
+	hset_text(&client, "user:1", &[("years", "1"), ("active", "yes")]).await;
+	let err = client.hget_model::<User>("user:1").await.unwrap_err();
+	assert!(err.to_string().starts_with("Failed to deserialize 'user:1': field 'active': expected a boolean"), "{}", err);
+}
+
+#[test]
+fn to_and_from_hash_without_a_server() {
+	let fields = user().to_hash();
+	let field = |name: &str|fields.iter().find(|(n, _)|*n == Value::Buffer(name.as_bytes().to_vec())).map(|(_, v)|v.clone());
+	assert_eq!(field("years"), Some(Value::Integer(42)));
+	assert_eq!(field("age"), None);
+	assert_eq!(field("score"), Some(Value::Float(1.5f64.to_bits())));
+	assert_eq!(field("active"), Some(Value::Bool(true)));
+	// out of the range of Integer
+	assert_eq!(field("visits"), Some(Value::Buffer(u64::MAX.to_string().into_bytes())));
+	assert_eq!(field("email"), None);
+	assert_eq!(field("cache"), None);
+
+	assert_eq!(User::from_hash(fields).unwrap(), User {cache: Vec::new(), ..user()});
+	assert_eq!(User::from_hash(Vec::new()).unwrap_err(), "missing field 'name'");
+}
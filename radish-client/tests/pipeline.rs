@@ -0,0 +1,136 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use radish_client::{Client, ClientConfig, Error, Value};
+
+use common::TestServer;
+
+const COUNT: usize = 100;
+
+async fn sequential(client: &Client) -> Duration {
+	let started = Instant::now();
+	for i in 0..COUNT {
+		assert_eq!(client.get(format!("key:{}", i)).await.unwrap(), Some(format!("value:{}", i).into_bytes()));
+	}
+	started.elapsed()
+}
+
+async fn pipelined(client: &Client) -> Duration {
+	let started = Instant::now();
+	let pipeline = (0..COUNT).fold(client.pipeline(), |pipeline, i|pipeline.get(format!("key:{}", i)));
+	let replies = pipeline.execute().await.unwrap();
+	let elapsed = started.elapsed();
+	assert_eq!(replies.len(), COUNT);
+	for i in 0..COUNT {
+		assert_eq!(replies.buffer(i).unwrap(), Some(format!("value:{}", i).into_bytes()));
+	}
+	elapsed
+}
+
+#[tokio::test]
+async fn pipelined_gets_are_faster_than_sequential() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+	for i in 0..COUNT {
+		client.set(format!("key:{}", i), format!("value:{}", i)).await.unwrap();
+	}
+
+	// the best of a few runs smooths out scheduler noise on a busy machine
+	let mut best_sequential = Duration::from_secs(3600);
+	let mut best_pipelined = Duration::from_secs(3600);
+	for _ in 0..5 {
+		best_sequential = best_sequential.min(sequential(&client).await);
+		best_pipelined = best_pipelined.min(pipelined(&client).await);
+	}
+	assert!(best_pipelined < best_sequential, "pipelined {:?}, sequential {:?}", best_pipelined, best_sequential);
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn replies_keep_the_order_of_commands() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+	client.sadd("set", &["x"]).await.unwrap();
+
+	let replies = client.pipeline()
+		.set("a", "1")
+		.get("b")
+		.incr("a")
+		.incr("set")
+		.incr_by("a", 10)
+		.expire("a", 100)
+		.ttl("a")
+		.del("a")
+		.get("a")
+		.execute().await.unwrap();
+
+	assert_eq!(replies.len(), 9);
+	replies.ok(0).unwrap();
+	assert_eq!(replies.buffer(1).unwrap(), None);
+	assert_eq!(replies.integer(2).unwrap(), 2);
+	match replies.integer(3) {
+		Err(Error::Server(err)) => assert!(err.starts_with("WRONGTYPE"), "{}", err),
+		reply => panic!("unexpected reply {:?}", reply),
+	}
+	assert!(matches!(replies.value(3), Some(Value::Error(_))));
+	assert_eq!(replies.integer(4).unwrap(), 12);
+	assert!((99..=100).contains(&replies.integer(6).unwrap()));
+	assert_eq!(replies.integer(7).unwrap(), 1);
+	assert_eq!(replies.buffer(8).unwrap(), None);
+	assert!(matches!(replies.reply(9), Err(Error::Protocol(_))));
+
+	// the connection stays in sync after the pipeline
+	assert!(client.get("set").await.is_err());
+	client.set("after", "v").await.unwrap();
+	assert_eq!(client.get("after").await.unwrap(), Some(b"v".to_vec()));
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn compressed_replies_keep_the_order() {
+	let server = TestServer::start().await;
+	let client = Client::connect_with_config(&server.addr, ClientConfig {compression: true, ..Default::default()}).await.unwrap();
+	let big = b"radish ".repeat(16 * 1024);
+
+	let replies = client.pipeline()
+		.set("big", &big)
+		.get("big")
+		.get("missing")
+		.incr("counter")
+		.get("big")
+		.execute().await.unwrap();
+	replies.ok(0).unwrap();
+	assert_eq!(replies.buffer(1).unwrap(), Some(big.clone()));
+	assert_eq!(replies.buffer(2).unwrap(), None);
+	assert_eq!(replies.integer(3).unwrap(), 1);
+	assert_eq!(replies.buffer(4).unwrap(), Some(big));
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn empty_pipeline() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+	let pipeline = client.pipeline();
+	assert!(pipeline.is_empty());
+	assert!(pipeline.execute().await.unwrap().is_empty());
+	client.ping().await.unwrap();
+	server.stop().await;
+}
@@ -0,0 +1,82 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use radish_client::{Error, Pool, PoolConfig};
+
+use common::TestServer;
+
+fn config(max_connections: usize) -> PoolConfig {
+	PoolConfig {
+		min_connections: 1,
+		max_connections,
+		health_check_interval: None,
+		..Default::default()
+	}
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn concurrent_tasks_share_bounded_connections() {
+	let server = TestServer::start().await;
+	let pool = Pool::new(&server.addr, config(4)).await.unwrap();
+
+	let tasks = (0..100).map(|i| {
+		let pool = pool.clone();
+		tokio::spawn(async move {
+			let key = format!("key:{}", i);
+			let value = format!("value:{}", i);
+			pool.get().await.unwrap().set(&key, &value).await.unwrap();
+			let client = pool.get().await.unwrap();
+			assert_eq!(client.get(&key).await.unwrap(), Some(value.into_bytes()));
+		})
+	}).collect::<Vec<_>>();
+	for task in tasks {
+		task.await.unwrap();
+	}
+
+	let opened = server.info("total_connections_received").await;
+	assert!((1..=4).contains(&opened), "{} connections opened", opened);
+	assert!(pool.idle_connections() <= 4);
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn checkout_times_out_when_the_pool_is_exhausted() {
+	let server = TestServer::start().await;
+	let pool = Pool::new(&server.addr, PoolConfig {
+		checkout_timeout: Duration::from_millis(50),
+		..config(1)
+	}).await.unwrap();
+
+	let held = pool.get().await.unwrap();
+	assert!(matches!(pool.get().await, Err(Error::Timeout)));
+	drop(held);
+	pool.get().await.unwrap().ping().await.unwrap();
+
+	assert_eq!(server.info("total_connections_received").await, 1);
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn invalid_sizes_are_rejected() {
+	let server = TestServer::start().await;
+	assert!(matches!(Pool::new(&server.addr, config(0)).await, Err(Error::Config(_))));
+	assert!(matches!(Pool::new(&server.addr, PoolConfig {min_connections: 3, ..config(2)}).await, Err(Error::Config(_))));
+	server.stop().await;
+}
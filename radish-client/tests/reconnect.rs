@@ -0,0 +1,141 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use radish_client::{Client, ClientConfig, Command, ConnectionState, RetryPolicy, Value};
+
+use common::TestServer;
+
+fn command(name: &str, args: &[&str]) -> Command {
+	Command {
+		command: name.to_owned(),
+		arguments: args.iter().map(|arg|Value::Buffer(arg.as_bytes().to_vec())).collect(),
+	}
+}
+
+fn config() -> ClientConfig {
+	ClientConfig {
+		retry: RetryPolicy {
+			initial_backoff: Duration::from_millis(10),
+			..Default::default()
+		},
+		..Default::default()
+	}
+}
+
+async fn protected(addr: &str) -> TestServer {
+	let server = TestServer::start_on(addr).await;
+	server.storage.config().set("requirepass", "s3cret").unwrap();
+	server
+}
+
+#[tokio::test]
+async fn session_is_restored_after_a_restart() {
+	let server = protected("127.0.0.1:0").await;
+	let addr = server.addr.clone();
+	let client = Client::connect_with_config(&addr, config()).await.unwrap();
+	// the watch channel keeps only the latest state, so it is recorded as it changes
+	let mut states = client.state_changes();
+	let recorder = tokio::spawn(async move {
+		let mut recorded = Vec::new();
+		while let Some(state) = states.recv().await {
+			recorded.push(state);
+			if recorded.len() == 3 {
+				break;
+			}
+		}
+		recorded
+	});
+
+	assert_eq!(client.command(command("AUTH", &["s3cret"])).await.unwrap(), Value::Ok);
+	assert_eq!(client.command(command("CLIENT", &["SETNAME", "worker"])).await.unwrap(), Value::Ok);
+	client.set("a", "before").await.unwrap();
+
+	server.stop().await;
+	let server = protected(&addr).await;
+	let session = &mut radish_database::Session {authenticated: true, ..Default::default()};
+	server.storage.execute(session, command("SET", &["a", "after"])).await;
+
+	// GET is retried on the reopened connection, AUTH and SETNAME are replayed before it
+	assert_eq!(client.get("a").await.unwrap(), Some(b"after".to_vec()));
+	assert_eq!(client.command(command("CLIENT", &["GETNAME"])).await.unwrap(), Value::Buffer(b"worker".to_vec()));
+	assert!(!client.is_broken());
+
+	let recorded = tokio::time::timeout(Duration::from_secs(5), recorder).await.unwrap().unwrap();
+	assert_eq!(recorded, vec![ConnectionState::Connected, ConnectionState::Disconnected, ConnectionState::Connected]);
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn failed_setup_commands_are_not_replayed() {
+	let server = TestServer::start().await;
+	let addr = server.addr.clone();
+	let client = Client::connect_with_config(&addr, config()).await.unwrap();
+	// no password is set, so AUTH fails and must not break the reconnect
+	assert!(matches!(client.command(command("AUTH", &["wrong"])).await.unwrap(), Value::Error(_)));
+	client.ping().await.unwrap();
+
+	server.stop().await;
+	let server = TestServer::start_on(&addr).await;
+	client.ping().await.unwrap();
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn rejected_setup_fails_the_reconnect() {
+	let server = protected("127.0.0.1:0").await;
+	let addr = server.addr.clone();
+	let client = Client::connect_with_config(&addr, config()).await.unwrap();
+	assert_eq!(client.command(command("AUTH", &["s3cret"])).await.unwrap(), Value::Ok);
+
+	server.stop().await;
+	let server = TestServer::start_on(&addr).await;
+	server.storage.config().set("requirepass", "changed").unwrap();
+	let err = client.ping().await.unwrap_err().to_string();
+	assert!(err.contains("Failed to restore the session with AUTH"), "{}", err);
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn in_flight_writes_follow_the_retry_policy() {
+	let server = TestServer::start().await;
+	let addr = server.addr.clone();
+	let client = Client::connect_with_config(&addr, config()).await.unwrap();
+	let idempotent = Client::connect_with_config(&addr, ClientConfig {
+		retry: RetryPolicy {
+			idempotent_commands: vec!["SET".to_owned()].into_iter().collect(),
+			..config().retry
+		},
+		..config()
+	}).await.unwrap();
+	client.ping().await.unwrap();
+	idempotent.ping().await.unwrap();
+
+	server.stop().await;
+	let server = TestServer::start_on(&addr).await;
+
+	// INCR is not retried: the application decides whether to repeat it
+	assert!(client.incr("counter").await.is_err());
+	assert_eq!(client.incr("counter").await.unwrap(), 1);
+
+	// SET is declared idempotent and is repeated transparently
+	idempotent.set("k", "v").await.unwrap();
+	assert_eq!(client.get("k").await.unwrap(), Some(b"v".to_vec()));
+	server.stop().await;
+}
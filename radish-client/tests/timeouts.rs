@@ -0,0 +1,121 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use radish_client::{Client, ClientConfig, Error, RetryPolicy};
+
+use common::TestServer;
+
+const TIMEOUT: Duration = Duration::from_millis(200);
+
+fn config() -> ClientConfig {
+	ClientConfig {
+		command_timeout: Some(TIMEOUT),
+		retry: RetryPolicy::none(),
+		..Default::default()
+	}
+}
+
+/// Proxy to `upstream` which holds back the replies while `stalled` is set
+struct StallingProxy {
+	addr: String,
+	stalled: Arc<AtomicBool>,
+	accepted: Arc<AtomicUsize>,
+}
+
+async fn forward(mut from: tokio::io::ReadHalf<TcpStream>, mut to: tokio::io::WriteHalf<TcpStream>, stalled: Option<Arc<AtomicBool>>) {
+	let mut buf = vec![0u8; 64 * 1024];
+	loop {
+		let n = match from.read(&mut buf).await {
+			Ok(0) | Err(_) => break,
+			Ok(n) => n,
+		};
+		if stalled.as_ref().is_some_and(|stalled|stalled.load(Ordering::SeqCst)) {
+			// the reply is lost, the client only sees silence
+			continue;
+		}
+		if to.write_all(&buf[..n]).await.is_err() {
+			break;
+		}
+	}
+}
+
+impl StallingProxy {
+	async fn start(upstream: String) -> Self {
+		let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap().to_string();
+		let stalled = Arc::new(AtomicBool::new(false));
+		let accepted = Arc::new(AtomicUsize::new(0));
+		let proxy = Self {addr, stalled: stalled.clone(), accepted: accepted.clone()};
+		tokio::spawn(async move {
+			while let Ok((client, _)) = listener.accept().await {
+				accepted.fetch_add(1, Ordering::SeqCst);
+				let server = TcpStream::connect(&upstream).await.unwrap();
+				let (client_read, client_write) = tokio::io::split(client);
+				let (server_read, server_write) = tokio::io::split(server);
+				tokio::spawn(forward(client_read, server_write, None));
+				tokio::spawn(forward(server_read, client_write, Some(stalled.clone())));
+			}
+		});
+		proxy
+	}
+}
+
+#[tokio::test]
+async fn stalled_reply_times_out_and_the_connection_is_replaced() {
+	let server = TestServer::start().await;
+	let proxy = StallingProxy::start(server.addr.clone()).await;
+	let client = Client::connect_with_config(&proxy.addr, config()).await.unwrap();
+	client.set("a", "v").await.unwrap();
+	assert!(!client.is_broken());
+
+	proxy.stalled.store(true, Ordering::SeqCst);
+	let started = Instant::now();
+	assert!(matches!(client.get("a").await, Err(Error::Timeout)));
+	assert!(started.elapsed() >= TIMEOUT);
+	assert!(client.is_broken());
+
+	// the late reply would desync the old connection, so the next command opens a new one
+	proxy.stalled.store(false, Ordering::SeqCst);
+	assert_eq!(client.get("a").await.unwrap(), Some(b"v".to_vec()));
+	assert!(!client.is_broken());
+	assert_eq!(proxy.accepted.load(Ordering::SeqCst), 2);
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn server_which_never_replies() {
+	let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let addr = listener.local_addr().unwrap().to_string();
+	tokio::spawn(async move {
+		let mut connections = Vec::new();
+		while let Ok((sock, _)) = listener.accept().await {
+			connections.push(sock);
+		}
+	});
+
+	let started = Instant::now();
+	assert!(matches!(Client::connect_with_config(&addr, config()).await, Err(Error::Timeout)));
+	assert!(started.elapsed() < TIMEOUT * 10);
+}
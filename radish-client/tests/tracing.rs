@@ -0,0 +1,260 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+#![cfg(feature = "tracing")]
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+use radish_client::{Client, Command, Error, Value};
+
+use common::TestServer;
+
+#[derive(Debug, Clone, Default)]
+struct CapturedSpan {
+	name: String,
+	parent: Option<u64>,
+	fields: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct CapturedEvent {
+	fields: BTreeMap<String, String>,
+}
+
+struct FieldsVisitor<'a>(&'a mut BTreeMap<String, String>);
+
+impl Visit for FieldsVisitor<'_> {
+	fn record_str(&mut self, field: &Field, value: &str) {
+		self.0.insert(field.name().to_owned(), value.to_owned());
+	}
+
+	fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+		self.0.insert(field.name().to_owned(), format!("{:?}", value));
+	}
+}
+
+#[derive(Default)]
+struct Captured {
+	spans: BTreeMap<u64, CapturedSpan>,
+	events: Vec<CapturedEvent>,
+	stack: Vec<u64>,
+}
+
+/// Subscriber keeping every span with its recorded fields, and every event
+#[derive(Clone, Default)]
+struct Capture {
+	next: Arc<AtomicU64>,
+	captured: Arc<Mutex<Captured>>,
+}
+
+impl Capture {
+	fn spans(&self, name: &str) -> Vec<(u64, CapturedSpan)> {
+		self.captured.lock().unwrap().spans.iter()
+			.filter(|(_, span)|span.name == name)
+			.map(|(id, span)|(*id, span.clone()))
+			.collect()
+	}
+
+	fn events(&self) -> Vec<CapturedEvent> {
+		self.captured.lock().unwrap().events.clone()
+	}
+}
+
+impl Subscriber for Capture {
+	fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+		true
+	}
+
+	fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+		let id = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+		let mut captured = self.captured.lock().unwrap();
+		let parent = match attributes.parent() {
+			Some(parent) => Some(parent.into_u64()),
+			None if attributes.is_contextual() => captured.stack.last().cloned(),
+			None => None,
+		};
+		let mut span = CapturedSpan {name: attributes.metadata().name().to_owned(), parent, fields: BTreeMap::new()};
+		attributes.record(&mut FieldsVisitor(&mut span.fields));
+		captured.spans.insert(id, span);
+		Id::from_u64(id)
+	}
+
+	fn record(&self, span: &Id, values: &Record<'_>) {
+		let mut captured = self.captured.lock().unwrap();
+		if let Some(span) = captured.spans.get_mut(&span.into_u64()) {
+			values.record(&mut FieldsVisitor(&mut span.fields));
+		}
+	}
+
+	fn record_follows_from(&self, _span: &Id, _follows: &Id) {
+	}
+
+	fn event(&self, event: &Event<'_>) {
+		let mut captured = self.captured.lock().unwrap();
+		let mut fields = BTreeMap::new();
+		event.record(&mut FieldsVisitor(&mut fields));
+		captured.events.push(CapturedEvent {fields});
+	}
+
+	fn enter(&self, span: &Id) {
+		self.captured.lock().unwrap().stack.push(span.into_u64());
+	}
+
+	fn exit(&self, span: &Id) {
+		let mut captured = self.captured.lock().unwrap();
+		if let Some(position) = captured.stack.iter().rposition(|id|*id == span.into_u64()) {
+			captured.stack.remove(position);
+		}
+	}
+}
+
+/// Runs the test on a single thread with the capture as the default subscriber
+fn traced<F: std::future::Future<Output = ()>>(test: impl FnOnce() -> F) -> Capture {
+	let capture = Capture::default();
+	tracing::subscriber::with_default(capture.clone(), ||{
+		let mut runtime = tokio::runtime::Builder::new().basic_scheduler().enable_all().build().unwrap();
+		runtime.block_on(test());
+	});
+	capture
+}
+
+fn field<'a>(span: &'a CapturedSpan, name: &str) -> Option<&'a str> {
+	span.fields.get(name).map(|value|&value[..])
+}
+
+#[test]
+fn get_span_fields() {
+	let capture = traced(||async {
+		let server = TestServer::start().await;
+		let client = Client::connect(&server.addr).await.unwrap();
+		client.set("user:1", "secret value").await.unwrap();
+		assert_eq!(client.get("user:1").await.unwrap(), Some(b"secret value".to_vec()));
+	});
+
+	let spans = capture.spans("radish.command");
+	let get = spans.iter().map(|(_, span)|span).find(|span|field(span, "db.operation") == Some("GET")).expect("no GET span");
+	assert_eq!(field(get, "otel.name"), Some("GET"));
+	assert_eq!(field(get, "otel.kind"), Some("client"));
+	assert_eq!(field(get, "db.system"), Some("radish"));
+	assert_eq!(field(get, "db.radish.key"), Some("user:1"));
+	assert_eq!(field(get, "outcome"), Some("ok"));
+	assert_eq!(field(get, "otel.status_code"), Some("OK"));
+	assert_eq!(field(get, "error.message"), None);
+	assert!(field(get, "db.radish.connection_id").unwrap().parse::<u64>().is_ok());
+
+	// values are never recorded
+	for (_, span) in &spans {
+		assert!(span.fields.values().all(|value|!value.contains("secret value")), "{:?}", span);
+	}
+}
+
+#[test]
+fn failed_command_span_fields() {
+	let capture = traced(||async {
+		let server = TestServer::start().await;
+		let client = Client::connect(&server.addr).await.unwrap();
+		client.set("counter", "not a number").await.unwrap();
+		let reply = client.execute(Command {
+			command: "incr".to_owned(),
+			arguments: vec![Value::Buffer(b"counter".to_vec())].into(),
+		}).await;
+		assert!(matches!(reply, Err(Error::Server(_))), "{:?}", reply);
+	});
+
+	let spans = capture.spans("radish.command");
+	let incr = spans.iter().map(|(_, span)|span).find(|span|field(span, "db.operation") == Some("INCR")).expect("no INCR span");
+	assert_eq!(field(incr, "otel.name"), Some("INCR"));
+	assert_eq!(field(incr, "db.radish.key"), Some("counter"));
+	assert_eq!(field(incr, "outcome"), Some("error"));
+	assert_eq!(field(incr, "otel.status_code"), Some("ERROR"));
+	assert_eq!(field(incr, "error.message"), Some("invalid digit found in string"));
+}
+
+#[test]
+fn keyless_and_secret_arguments_are_not_recorded() {
+	let capture = traced(||async {
+		let server = TestServer::start().await;
+		let client = Client::connect(&server.addr).await.unwrap();
+		let reply = client.execute(Command {
+			command: "AUTH".to_owned(),
+			arguments: vec![Value::Buffer(b"hunter2".to_vec())].into(),
+		}).await;
+		assert!(matches!(reply, Err(Error::Server(_))), "{:?}", reply);
+		client.ping().await.unwrap();
+	});
+
+	let spans = capture.spans("radish.command");
+	for operation in &["AUTH", "PING"] {
+		let span = spans.iter().map(|(_, span)|span).find(|span|field(span, "db.operation") == Some(operation)).expect("no span");
+		assert_eq!(field(span, "db.radish.key"), None, "{:?}", span);
+	}
+	assert!(spans.iter().all(|(_, span)|span.fields.values().all(|value|!value.contains("hunter2"))));
+}
+
+#[test]
+fn pipeline_span_with_children() {
+	let capture = traced(||async {
+		let server = TestServer::start().await;
+		let client = Client::connect(&server.addr).await.unwrap();
+		client.set("text", "v").await.unwrap();
+		client.pipeline().set("a", "1").incr("a").incr("text").execute().await.unwrap();
+	});
+
+	let pipelines = capture.spans("radish.pipeline");
+	assert_eq!(pipelines.len(), 1);
+	let (pipeline_id, pipeline) = &pipelines[0];
+	assert_eq!(field(pipeline, "otel.name"), Some("PIPELINE"));
+	assert_eq!(field(pipeline, "db.radish.commands"), Some("3"));
+	assert_eq!(field(pipeline, "outcome"), Some("ok"));
+
+	let children: Vec<CapturedSpan> = capture.spans("radish.command").into_iter()
+		.map(|(_, span)|span)
+		.filter(|span|span.parent == Some(*pipeline_id))
+		.collect();
+	let summary: Vec<(Option<&str>, Option<&str>, Option<&str>)> = children.iter()
+		.map(|span|(field(span, "db.operation"), field(span, "db.radish.key"), field(span, "outcome")))
+		.collect();
+	assert_eq!(summary, vec![
+		(Some("SET"), Some("a"), Some("ok")),
+		(Some("INCRBY"), Some("a"), Some("ok")),
+		(Some("INCRBY"), Some("text"), Some("error")),
+	]);
+}
+
+#[test]
+fn reconnect_events() {
+	let capture = traced(||async {
+		let server = TestServer::start().await;
+		let addr = server.addr.clone();
+		let client = Client::connect(&addr).await.unwrap();
+		client.ping().await.unwrap();
+		server.stop().await;
+		assert!(client.ping().await.is_err());
+	});
+
+	let events = capture.events();
+	assert!(events.iter().any(|event|event.fields.get("message").map(|m|&m[..]) == Some("reconnect failed")
+		&& event.fields.get("db.system").map(|s|&s[..]) == Some("radish")), "{:?}", events);
+}
@@ -0,0 +1,138 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_client::{Client, ClientConfig, Command, Error, RetryPolicy, Value};
+
+use common::TestServer;
+
+fn bytes(items: &[&str]) -> Vec<Vec<u8>> {
+	items.iter().map(|item|item.as_bytes().to_vec()).collect()
+}
+
+#[tokio::test]
+async fn strings() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+
+	client.ping().await.unwrap();
+	assert_eq!(client.get("a").await.unwrap(), None);
+	client.set("a", "1").await.unwrap();
+	assert_eq!(client.get("a").await.unwrap(), Some(b"1".to_vec()));
+	assert_eq!(client.incr("a").await.unwrap(), 2);
+	assert_eq!(client.incr_by("a", -5).await.unwrap(), -3);
+	assert_eq!(client.get("a").await.unwrap(), Some(b"-3".to_vec()));
+
+	client.set("b", "v").await.unwrap();
+	assert_eq!(client.del(&["a", "b", "c"]).await.unwrap(), 2);
+	assert_eq!(client.get("a").await.unwrap(), None);
+
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn expiration() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+
+	assert_eq!(client.ttl("a").await.unwrap(), -2);
+	client.set("a", "v").await.unwrap();
+	assert_eq!(client.ttl("a").await.unwrap(), -1);
+	assert!(client.expire("a", 100).await.unwrap());
+	assert!((99..=100).contains(&client.ttl("a").await.unwrap()));
+	assert!(!client.expire("missing", 100).await.unwrap());
+
+	client.set_ex("b", "v", 50).await.unwrap();
+	assert_eq!(client.get("b").await.unwrap(), Some(b"v".to_vec()));
+	assert!((49..=50).contains(&client.ttl("b").await.unwrap()));
+
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn collections() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+
+	assert_eq!(client.lpush("list", &["a", "b"]).await.unwrap(), 2);
+	assert_eq!(client.lpush("list", &["c"]).await.unwrap(), 3);
+	assert_eq!(client.lrange("list", 0, -1).await.unwrap(), bytes(&["c", "b", "a"]));
+	assert_eq!(client.lrange("list", 1, 1).await.unwrap(), bytes(&["b"]));
+	assert!(client.lrange("missing", 0, -1).await.unwrap().is_empty());
+
+	assert_eq!(client.sadd("set", &["x", "y", "x"]).await.unwrap(), 2);
+	let mut members = client.smembers("set").await.unwrap();
+	members.sort();
+	assert_eq!(members, bytes(&["x", "y"]));
+
+	assert_eq!(client.hset("hash", "f1", "v1").await.unwrap(), 1);
+	assert_eq!(client.hset("hash", "f2", "v2").await.unwrap(), 1);
+	client.hset("hash", "f1", "v3").await.unwrap();
+	assert_eq!(client.hget("hash", "f1").await.unwrap(), Some(b"v3".to_vec()));
+	assert_eq!(client.hget("hash", "f9").await.unwrap(), None);
+	let mut pairs = client.hget_all("hash").await.unwrap();
+	pairs.sort();
+	assert_eq!(pairs, vec![(b"f1".to_vec(), b"v3".to_vec()), (b"f2".to_vec(), b"v2".to_vec())]);
+
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn generic_commands() {
+	let server = TestServer::start().await;
+	let client = Client::connect(&server.addr).await.unwrap();
+
+	let cmd = |command: &str, arguments: Vec<Value>|Command {
+		command: command.to_owned(),
+		arguments: arguments.into_iter().collect(),
+	};
+	let reply = client.command(cmd("SET", vec![Value::Buffer(b"k".to_vec()), Value::Buffer(b"v".to_vec())])).await.unwrap();
+	assert_eq!(reply, Value::Ok);
+	let reply = client.command(cmd("LPUSH", vec![Value::Buffer(b"k".to_vec()), Value::Buffer(b"v".to_vec())])).await.unwrap();
+	assert!(matches!(reply, Value::Error(err) if err.starts_with("WRONGTYPE")));
+	let reply = client.execute(cmd("LPUSH", vec![Value::Buffer(b"k".to_vec()), Value::Buffer(b"v".to_vec())])).await;
+	assert!(matches!(reply, Err(Error::Server(err)) if err.starts_with("WRONGTYPE")));
+
+	server.stop().await;
+}
+
+#[tokio::test]
+async fn error_replies_and_io_errors_are_distinct() {
+	let server = TestServer::start().await;
+	let config = ClientConfig {
+		retry: RetryPolicy::none(),
+		..Default::default()
+	};
+	let client = Client::connect_with_config(&server.addr, config.clone()).await.unwrap();
+
+	client.sadd("set", &["x"]).await.unwrap();
+	match client.get("set").await {
+		Err(Error::Server(err)) => assert!(err.starts_with("WRONGTYPE"), "{}", err),
+		reply => panic!("unexpected reply {:?}", reply),
+	}
+	match client.incr("set").await {
+		Err(Error::Server(err)) => assert!(err.starts_with("WRONGTYPE"), "{}", err),
+		reply => panic!("unexpected reply {:?}", reply),
+	}
+	assert!(!client.is_broken());
+
+	let addr = server.addr.clone();
+	server.stop().await;
+	assert!(matches!(client.get("a").await, Err(Error::Io(_))));
+	assert!(client.is_broken());
+	assert!(matches!(Client::connect_with_config(&addr, config).await, Err(Error::Io(_))));
+}
@@ -0,0 +1,148 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use super::container::Container;
+use super::clock::Clock;
+
+type Key = super::Key;
+type Value = super::Value;
+type Arguments = super::Arguments;
+type ExecResult = super::ExecResult;
+
+/// Counter of a new key, so it is not the first candidate for eviction
+const LFU_INIT_VAL: u8 = 5;
+/// Higher factor needs more hits to grow the counter: ~1M hits saturate it with factor 10
+const LFU_LOG_FACTOR: f64 = 10.0;
+/// The counter is decremented once per this period of idle time
+const LFU_DECAY_SECONDS: u32 = 60;
+
+/// Coarse clock in seconds since the storage was created
+pub struct AccessClock {
+	source: Arc<dyn Clock>,
+	start: Instant,
+}
+
+impl AccessClock {
+	pub fn new(source: Arc<dyn Clock>) -> Self {
+		Self {
+			start: source.now_monotonic(),
+			source,
+		}
+	}
+
+	pub fn now(&self) -> u32 {
+		(self.source.now_monotonic() - self.start).as_secs() as u32
+	}
+}
+
+/// LRU/LFU metadata; updated under a shared container lock, so it is atomic
+#[derive(Debug)]
+pub struct AccessMeta {
+	last_access: AtomicU32,
+	lfu_counter: AtomicU8,
+}
+
+impl Default for AccessMeta {
+	fn default() -> Self {
+		Self {
+			last_access: AtomicU32::new(0),
+			lfu_counter: AtomicU8::new(LFU_INIT_VAL),
+		}
+	}
+}
+
+/// Logarithmic increment: probability of growth falls as the counter grows
+fn lfu_log_incr(counter: u8) -> u8 {
+	if counter == u8::MAX {
+		return counter;
+	}
+	let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+	let probability = 1.0 / (base * LFU_LOG_FACTOR + 1.0);
+	if rand::random::<f64>() < probability {
+		counter + 1
+	} else {
+		counter
+	}
+}
+
+impl AccessMeta {
+	pub fn touch(&self, now: u32) {
+		let counter = lfu_log_incr(self.frequency(now));
+		self.lfu_counter.store(counter, Ordering::Relaxed);
+		self.last_access.store(now, Ordering::Relaxed);
+	}
+
+	pub fn idle_seconds(&self, now: u32) -> u32 {
+		now.saturating_sub(self.last_access.load(Ordering::Relaxed))
+	}
+
+	/// Counter with the decay for the idle time applied
+	pub fn frequency(&self, now: u32) -> u8 {
+		let periods = self.idle_seconds(now) / LFU_DECAY_SECONDS;
+		let counter = self.lfu_counter.load(Ordering::Relaxed);
+		counter.saturating_sub(std::cmp::min(periods, u8::MAX as u32) as u8)
+	}
+}
+
+impl Container {
+	pub fn access(&self) -> &AccessMeta {
+		match self {
+			Container::Set(c) => &c.access,
+			Container::List(c) => &c.access,
+			Container::Hash(c) => &c.access,
+			Container::Strings(c) => &c.access,
+			Container::Json(c) => &c.access,
+			Container::Bloom(c) => &c.access,
+			Container::TimeSeries(c) => &c.access,
+		}
+	}
+}
+
+impl super::Storage {
+	/// Should be called by each read or write of a container
+	pub fn access_touch(&self, container: &Container) {
+		container.access().touch(self.access_clock.now());
+	}
+
+	/// Time since the last read or write of the key
+	pub async fn idle_time(&self, key: &[u8]) -> Option<Duration> {
+		let container = self.try_get_live_container(&key.to_vec()).await?;
+		let idle = container.read().await.access().idle_seconds(self.access_clock.now());
+		Some(Duration::from_secs(idle as u64))
+	}
+
+	/// Logarithmic LFU counter of the key, 0..255
+	pub async fn access_frequency(&self, key: &[u8]) -> Option<u8> {
+		let container = self.try_get_live_container(&key.to_vec()).await?;
+		let frequency = container.read().await.access().frequency(self.access_clock.now());
+		Some(frequency)
+	}
+
+	pub async fn access_object(&self, mut args: Arguments) -> ExecResult {
+		let subcommand = Self::extract_string(args.pop_front())?;
+		let key: Key = Self::extract_key(args.pop_front())?;
+		match &subcommand.to_uppercase()[..] {
+			"IDLETIME" => Ok(self.idle_time(&key).await.map_or(Value::Nill, |idle|Value::Integer(idle.as_secs() as i64))),
+			"FREQ" => Ok(self.access_frequency(&key).await.map_or(Value::Nill, |freq|Value::Integer(freq as i64))),
+			arg => Err(format!("Unexpected argument '{}'", arg)),
+		}
+	}
+}
@@ -0,0 +1,214 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Append-only audit trail of the successful write commands. Records are formatted by
+//! the hook and written by a dedicated thread, so a slow disk does not stall the commands.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{Receiver, SyncSender};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::commands::CommandSpec;
+use super::hooks::ExecutionHook;
+
+type Value = super::Value;
+type Command = super::Command;
+type Session = super::Session;
+
+/// The user of an authenticated session; there are no other users so far
+const DEFAULT_USER: &str = "default";
+
+struct AuditHook {
+	records: SyncSender<String>,
+	/// Commands wait for a free slot of the queue instead of dropping the record
+	blocking: bool,
+	/// None hides the values, zero logs them in full
+	values: Option<usize>,
+	counters: Arc<super::server::Counters>,
+	config: Arc<super::config::Config>,
+}
+
+fn quote(value: &Value, limit: usize) -> String {
+	match value {
+		Value::Buffer(b) if limit > 0 && b.len() > limit => format!("{:?}...", String::from_utf8_lossy(&b[..limit])),
+		Value::Buffer(b) => format!("{:?}", String::from_utf8_lossy(b)),
+		value => format!("{}", value),
+	}
+}
+
+impl AuditHook {
+	/// `<unix ms> id=<client id> addr=<address> user=<user> cmd=<name> keys=[..] values=[..]`
+	fn format(&self, session: &Session, command: &Command, spec: &CommandSpec) -> String {
+		let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+		let user = if session.authenticated || !self.config.auth_required() {DEFAULT_USER} else {"-"};
+		let indices = spec.key_indices(command.arguments.len());
+		let keys = indices.iter()
+			.filter_map(|&i|command.arguments.get(i))
+			.map(|key|quote(key, 0))
+			.collect::<Vec<_>>()
+			.join(",");
+		let mut record = format!("{} id={} addr={} user={} cmd={} keys=[{}]",
+			time, session.id, session.addr.as_deref().unwrap_or("-"), user, spec.name.to_lowercase(), keys);
+		if let Some(limit) = self.values {
+			let values = command.arguments.iter()
+				.enumerate()
+				.filter(|(i, _)|!indices.contains(i))
+				.map(|(_, value)|quote(value, limit))
+				.collect::<Vec<_>>()
+				.join(",");
+			record.push_str(&format!(" values=[{}]", values));
+		}
+		record.push('\n');
+		record
+	}
+}
+
+impl ExecutionHook for AuditHook {
+	fn after(&self, session: &Session, command: &Command, spec: &CommandSpec, reply: &Value, _elapsed: Duration) {
+		if !spec.writes(&command.arguments) {
+			return;
+		}
+		if let Value::Error(_) = reply {
+			return;
+		}
+		let record = self.format(session, command, spec);
+		let sent = if self.blocking {
+			self.records.send(record).is_ok()
+		} else {
+			self.records.try_send(record).is_ok()
+		};
+		if !sent {
+			self.counters.audit_log_dropped.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+}
+
+/// File rotated by size: `path` is renamed to `path.1`, `path.1` to `path.2` and so on,
+/// files older than `retention` are removed. A write is never split across files
+pub struct RotatingFile {
+	path: PathBuf,
+	max_size: usize,
+	retention: usize,
+	writer: BufWriter<File>,
+	size: usize,
+}
+
+fn rotated(path: &Path, index: usize) -> PathBuf {
+	let mut name = path.as_os_str().to_owned();
+	name.push(format!(".{}", index));
+	PathBuf::from(name)
+}
+
+fn open_append(path: &Path) -> std::io::Result<File> {
+	OpenOptions::new().create(true).append(true).open(path)
+}
+
+impl RotatingFile {
+	pub fn open(path: PathBuf, max_size: usize, retention: usize) -> std::io::Result<Self> {
+		let file = open_append(&path)?;
+		let size = file.metadata()?.len() as usize;
+		Ok(Self {
+			path,
+			max_size,
+			retention,
+			writer: BufWriter::new(file),
+			size,
+		})
+	}
+
+	fn rotate(&mut self) -> std::io::Result<()> {
+		self.writer.flush()?;
+		if self.retention == 0 {
+			std::fs::remove_file(&self.path)?;
+		} else {
+			let oldest = rotated(&self.path, self.retention);
+			if oldest.exists() {
+				std::fs::remove_file(&oldest)?;
+			}
+			for index in (1..self.retention).rev() {
+				let from = rotated(&self.path, index);
+				if from.exists() {
+					std::fs::rename(&from, rotated(&self.path, index + 1))?;
+				}
+			}
+			std::fs::rename(&self.path, rotated(&self.path, 1))?;
+		}
+		self.writer = BufWriter::new(open_append(&self.path)?);
+		self.size = 0;
+		Ok(())
+	}
+
+	/// Writes records until every hook is gone; the buffer is flushed whenever the queue is drained
+	fn run(mut self, records: Receiver<String>) {
+		while let Ok(record) = records.recv() {
+			let mut result = self.write_all(record.as_bytes());
+			while result.is_ok() {
+				match records.try_recv() {
+					Ok(record) => result = self.write_all(record.as_bytes()),
+					Err(_) => break,
+				}
+			}
+			if let Err(err) = result.and_then(|_|self.writer.flush()) {
+				log::error!("Failed to write the audit log '{}': {}", self.path.display(), err);
+			}
+		}
+	}
+}
+
+impl Write for RotatingFile {
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		if self.max_size > 0 && self.size > 0 && self.size + buf.len() > self.max_size {
+			self.rotate()?;
+		}
+		self.writer.write_all(buf)?;
+		self.size += buf.len();
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> {
+		self.writer.flush()
+	}
+}
+
+impl super::Storage {
+	/// Starts the audit log if the `audit-log` parameter is set; returns false if it is disabled
+	pub fn start_audit_log(&self) -> Result<bool, String> {
+		let path = self.config.audit_log();
+		if path.is_empty() {
+			return Ok(false);
+		}
+		let file = RotatingFile::open(PathBuf::from(&path), self.config.audit_log_max_size(), self.config.audit_log_retention())
+			.map_err(|e|format!("Failed to open the audit log '{}': {}", path, e))?;
+		let (records, receiver) = std::sync::mpsc::sync_channel(self.config.audit_log_queue());
+		std::thread::Builder::new()
+			.name("audit-log".to_owned())
+			.spawn(move||file.run(receiver))
+			.map_err(|e|format!("Failed to start the audit log writer: {}", e))?;
+		self.add_hook(Box::new(AuditHook {
+			records,
+			blocking: self.config.audit_log_blocking(),
+			values: self.config.audit_log_values(),
+			counters: self.counters.clone(),
+			config: self.config.clone(),
+		}));
+		Ok(true)
+	}
+}
@@ -0,0 +1,183 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Clients blocked by BLPOP, BRPOP and BRPOPLPUSH until one of their keys gets elements.
+//!
+//! A waiter is queued on each of its keys before it tries to pop, so an element pushed between
+//! the try and the wait is not missed. After a successful write command the first waiter of each
+//! written key holding a non-empty list is woken, so a push wakes exactly one waiter and waiters
+//! are served in the order they blocked. The woken waiter pops by a write command too, which wakes
+//! the next one if elements are left. A waiter woken in vain, e.g. when another client popped the
+//! element first, is queued again at the head.
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+use super::container::Container;
+
+type Key = super::Key;
+type CompactKey = super::CompactKey;
+type Value = super::Value;
+type ExecResult = super::ExecResult;
+
+#[derive(Default)]
+struct Waiter {
+	notify: Notify,
+	woken: AtomicBool,
+}
+
+#[derive(Default)]
+pub struct BlockedClients {
+	queues: Mutex<HashMap<CompactKey, VecDeque<Arc<Waiter>>>>,
+	/// Count of blocked clients, so writes skip the queues while nobody waits
+	blocked: AtomicUsize,
+}
+
+impl BlockedClients {
+	pub fn blocked(&self) -> usize {
+		self.blocked.load(Ordering::Relaxed)
+	}
+
+	fn register(&self, keys: &[Key], waiter: &Arc<Waiter>, first: bool) {
+		let mut queues = self.queues.lock().unwrap();
+		for key in keys {
+			let queue = queues.entry(key[..].into()).or_default();
+			match first {
+				true => queue.push_front(waiter.clone()),
+				false => queue.push_back(waiter.clone()),
+			}
+		}
+	}
+
+	fn unregister(&self, keys: &[Key], waiter: &Arc<Waiter>) {
+		let mut queues = self.queues.lock().unwrap();
+		for key in keys {
+			if let Some(queue) = queues.get_mut(&key[..]) {
+				queue.retain(|w| !Arc::ptr_eq(w, waiter));
+				if queue.is_empty() {
+					queues.remove(&key[..]);
+				}
+			}
+		}
+	}
+
+	fn is_waited(&self, key: &[u8]) -> bool {
+		self.queues.lock().unwrap().contains_key(key)
+	}
+
+	/// Wakes the first waiter of the key which is not woken yet by another key
+	fn wake(&self, key: &[u8]) {
+		let mut queues = self.queues.lock().unwrap();
+		if let Some(queue) = queues.get_mut(key) {
+			while let Some(waiter) = queue.pop_front() {
+				if !waiter.woken.swap(true, Ordering::SeqCst) {
+					waiter.notify.notify();
+					break;
+				}
+			}
+			if queue.is_empty() {
+				queues.remove(key);
+			}
+		}
+	}
+}
+
+/// Removes the waiter from the queues when the blocked command ends or is dropped;
+/// a wakeup it didn't use is passed on to the next waiter
+struct Registration<'a> {
+	clients: &'a BlockedClients,
+	keys: &'a [Key],
+	waiter: Arc<Waiter>,
+	used: bool,
+}
+
+impl Drop for Registration<'_> {
+	fn drop(&mut self) {
+		self.clients.unregister(self.keys, &self.waiter);
+		self.clients.blocked.fetch_sub(1, Ordering::Relaxed);
+		if self.waiter.woken.load(Ordering::SeqCst) && !self.used {
+			for key in self.keys {
+				self.clients.wake(key);
+			}
+		}
+	}
+}
+
+impl super::Storage {
+	/// Calls `try_pop` until it gives a reply, then again each time one of `keys` is written;
+	/// Nill if nothing is popped within the timeout, None waits forever
+	pub(crate) async fn block_on<F, R>(&self, keys: &[Key], timeout: Option<Duration>, mut try_pop: F) -> ExecResult
+	where F: FnMut() -> R, R: Future<Output = Result<Option<Value>, String>> {
+		let deadline = timeout.map(|timeout|tokio::time::Instant::now() + timeout);
+		self.blocked.blocked.fetch_add(1, Ordering::Relaxed);
+		let mut registration = Registration {
+			clients: &self.blocked,
+			keys,
+			waiter: Arc::new(Waiter::default()),
+			used: false,
+		};
+		self.blocked.register(keys, &registration.waiter, false);
+		loop {
+			if let Some(reply) = try_pop().await? {
+				registration.used = true;
+				return Ok(reply);
+			}
+			let notified = registration.waiter.notify.notified();
+			let woken = match deadline {
+				None => {
+					notified.await;
+					true
+				},
+				Some(deadline) => tokio::time::timeout_at(deadline, notified).await.is_ok(),
+			};
+			if !woken {
+				// a wakeup racing with the timeout still gets its element
+				if registration.waiter.woken.load(Ordering::SeqCst) {
+					if let Some(reply) = try_pop().await? {
+						registration.used = true;
+						return Ok(reply);
+					}
+				}
+				return Ok(Value::Nill);
+			}
+			self.blocked.unregister(keys, &registration.waiter);
+			registration.waiter.woken.store(false, Ordering::SeqCst);
+			self.blocked.register(keys, &registration.waiter, true);
+		}
+	}
+
+	/// Wakes a waiter of each written key which holds elements now
+	pub(crate) async fn blocking_signal(&self, keys: &[Key]) {
+		for key in keys {
+			if !self.blocked.is_waited(key) {
+				continue;
+			}
+			let container = match self.try_get_live_container(key).await {
+				Some(container) => container,
+				None => continue,
+			};
+			let ready = matches!(&*container.read().await, Container::List(list) if !list.inner.is_empty());
+			if ready {
+				self.blocked.wake(key);
+			}
+		}
+	}
+}
@@ -0,0 +1,324 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+
+//! Scalable bloom filters modeled on RedisBloom.
+//!
+//! A filter is a chain of layers: when the last one reaches its capacity a new layer is added,
+//! `expansion` times bigger and with a twice tighter error rate, so the compound rate stays
+//! near the requested one. An item is present if any layer has all its bits set.
+
+use serde::{Deserialize, Serialize};
+use indexmap::map::Entry;
+
+use super::container::Container;
+use super::container::WRONG_TYPE_ERROR;
+use super::container::ContainerImpl;
+use super::options::OptionParser;
+use super::limits::Limits;
+
+type Key = super::Key;
+type Value = super::Value;
+type Arguments = super::Arguments;
+type ExecResult = super::ExecResult;
+
+/// Filter created implicitly by BF.ADD and BF.MADD
+const DEFAULT_ERROR_RATE: f64 = 0.01;
+const DEFAULT_CAPACITY: u64 = 100;
+const DEFAULT_EXPANSION: u32 = 2;
+/// Error rate of each next layer relative to the previous one
+const TIGHTENING_RATIO: f64 = 0.5;
+
+const RESERVE_OPTIONS: OptionParser = OptionParser::new(&["NONSCALING"], &["EXPANSION"])
+	.exclusive(&[&["NONSCALING", "EXPANSION"]]);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct BloomLayer {
+	pub bits: Vec<u8>,
+	pub hashes: u32,
+	pub capacity: u64,
+	pub items: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct BloomFilter {
+	pub error_rate: f64,
+	/// Zero for a filter which does not scale
+	pub expansion: u32,
+	pub layers: Vec<BloomLayer>,
+}
+
+/// FNV-1a with the splitmix64 finalizer; stable across builds, since the bits are persisted
+fn hash(item: &[u8], seed: u64) -> u64 {
+	let mut h = 0xcbf2_9ce4_8422_2325u64 ^ seed;
+	for byte in item {
+		h ^= *byte as u64;
+		h = h.wrapping_mul(0x0000_0100_0000_01b3);
+	}
+	h ^= h >> 30;
+	h = h.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+	h ^= h >> 27;
+	h = h.wrapping_mul(0x94d0_49bb_1331_11eb);
+	h ^ (h >> 31)
+}
+
+impl BloomLayer {
+	/// Optimal size in bits is -n*ln(p)/ln(2)^2 with -log2(p) hash functions
+	fn new(error_rate: f64, capacity: u64) -> Self {
+		let ln2 = std::f64::consts::LN_2;
+		let bits = (-(capacity as f64) * error_rate.ln() / (ln2 * ln2)).ceil() as u64;
+		let bytes = std::cmp::max(bits.div_ceil(8), 1) as usize;
+		let hashes = std::cmp::max((-error_rate.log2()).ceil() as u32, 1);
+		Self {
+			bits: vec![0; bytes],
+			hashes,
+			capacity,
+			items: 0,
+		}
+	}
+
+	/// Double hashing: the i-th position is h1 + i * h2
+	fn positions<'a>(&self, item: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+		let size = self.bits.len() as u64 * 8;
+		let h1 = hash(item, 0);
+		let h2 = hash(item, h1) | 1;
+		(0..self.hashes as u64).map(move |i|(h1.wrapping_add(i.wrapping_mul(h2)) % size) as usize)
+	}
+
+	fn contains(&self, item: &[u8]) -> bool {
+		self.positions(item).all(|bit|self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+	}
+
+	fn insert(&mut self, item: &[u8]) {
+		for bit in self.positions(item).collect::<Vec<_>>() {
+			self.bits[bit / 8] |= 1 << (bit % 8);
+		}
+		self.items += 1;
+	}
+
+	fn set_bits(&self) -> u64 {
+		self.bits.iter().map(|b|b.count_ones() as u64).sum()
+	}
+}
+
+impl BloomFilter {
+	pub fn new(error_rate: f64, capacity: u64, expansion: u32) -> Self {
+		Self {
+			error_rate,
+			expansion,
+			layers: vec![BloomLayer::new(error_rate, capacity)],
+		}
+	}
+
+	pub fn contains(&self, item: &[u8]) -> bool {
+		self.layers.iter().any(|layer|layer.contains(item))
+	}
+
+	/// Returns false if the item is probably present already; a new layer must fit into max-value-size
+	pub fn insert(&mut self, item: &[u8], limits: &Limits) -> Result<bool, String> {
+		if self.contains(item) {
+			return Ok(false);
+		}
+		let last = self.layers.last().expect("filter has at least one layer");
+		if last.items >= last.capacity {
+			if self.expansion == 0 {
+				return Err("ERR non scaling filter is full".to_owned());
+			}
+			let error_rate = self.error_rate * TIGHTENING_RATIO.powi(self.layers.len() as i32);
+			let capacity = last.capacity.saturating_mul(self.expansion as u64);
+			let layer = BloomLayer::new(error_rate, capacity);
+			limits.check_value_size(self.size_bytes() + layer.bits.len())?;
+			self.layers.push(layer);
+		}
+		self.layers.last_mut().expect("filter has at least one layer").insert(item);
+		Ok(true)
+	}
+
+	pub fn capacity(&self) -> u64 {
+		self.layers.iter().map(|layer|layer.capacity).sum()
+	}
+
+	pub fn items(&self) -> u64 {
+		self.layers.iter().map(|layer|layer.items).sum()
+	}
+
+	pub fn size_bytes(&self) -> usize {
+		self.layers.iter().map(|layer|layer.bits.len()).sum()
+	}
+
+	/// Share of the set bits over all layers
+	pub fn fill_ratio(&self) -> f64 {
+		let bits = self.size_bytes() as u64 * 8;
+		let set: u64 = self.layers.iter().map(BloomLayer::set_bits).sum();
+		if bits == 0 {0.0} else {set as f64 / bits as f64}
+	}
+}
+
+/// Items are compared as bytes, so the number 1 and the string "1" are the same item
+fn item_bytes(arg: Value) -> Result<Vec<u8>, String> {
+	match arg {
+		Value::Buffer(b) => Ok(b),
+		Value::Integer(i) => Ok(i.to_string().into_bytes()),
+		Value::Float(n) => Ok(f64::from_bits(n).to_string().into_bytes()),
+		_ => Err("Unexpected item type".to_owned()),
+	}
+}
+
+impl super::Storage {
+	fn bloom_unwrap_container(container: &Container) -> Result<&ContainerImpl<BloomFilter>, String> {
+		match container {
+			Container::Bloom(ref c) => Ok(c),
+			_ => Err(WRONG_TYPE_ERROR.to_owned()),
+		}
+	}
+	fn bloom_unwrap_mut_container(container: &mut Container) -> Result<&mut ContainerImpl<BloomFilter>, String> {
+		match container {
+			Container::Bloom(ref mut c) => Ok(c),
+			_ => Err(WRONG_TYPE_ERROR.to_owned()),
+		}
+	}
+	/// Missing key is given to the processor as None without creating it
+	async fn bloom_lock<F: FnOnce(Option<&BloomFilter>) -> ExecResult>(&self, key: &Key, processor: F) -> ExecResult {
+		match self.try_get_live_container(key).await {
+			None => processor(None),
+			Some(c1) => {
+				let c2 = c1.read().await;
+				self.access_touch(&c2);
+				let c3 = Self::bloom_unwrap_container(&c2)?;
+				processor(Some(&c3.inner))
+			},
+		}
+	}
+	/// Missing key is created with the default error rate and capacity
+	async fn bloom_lock_mut<F: FnOnce(&mut BloomFilter) -> ExecResult>(&self, key: Key, processor: F) -> ExecResult {
+		let c1 = self.get_live_container(key, ||{
+			let filter = BloomFilter::new(DEFAULT_ERROR_RATE, DEFAULT_CAPACITY, DEFAULT_EXPANSION);
+			let mut c = ContainerImpl::<BloomFilter>::new();
+			c.inner = filter;
+			Container::Bloom(c)
+		}).await;
+		let mut c2 = c1.write().await;
+		self.snapshot_preserve(&c1, &c2);
+		self.access_touch(&c2);
+		let c3 = Self::bloom_unwrap_mut_container(&mut c2)?;
+		let result = processor(&mut c3.inner);
+		self.memory_track(&mut c2);
+		result
+	}
+
+	/// BF.RESERVE key error_rate capacity [EXPANSION expansion] [NONSCALING]
+	pub async fn bloom_reserve(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let error_rate = Self::extract_float(args.pop_front())?;
+		let capacity = Self::extract_unsigned_integer(args.pop_front())?;
+		let mut options = RESERVE_OPTIONS.parse(args)?;
+		if !(error_rate > 0.0 && error_rate < 1.0) {
+			return Err("ERR error rate should be between 0 and 1".to_owned());
+		}
+		if capacity == 0 {
+			return Err("ERR capacity should be positive".to_owned());
+		}
+		let expansion = match (options.flag("NONSCALING"), options.unsigned("EXPANSION")?) {
+			(true, _) => 0,
+			(false, None) => DEFAULT_EXPANSION,
+			(false, Some(expansion)) if expansion >= 1 && expansion <= u32::MAX as u64 => expansion as u32,
+			(false, Some(_)) => return Err("ERR expansion should be positive".to_owned()),
+		};
+		let ln2 = std::f64::consts::LN_2;
+		let bytes = -(capacity as f64) * error_rate.ln() / (ln2 * ln2) / 8.0;
+		self.limits().check_value_size(bytes as usize)?;
+
+		let mut cnt = ContainerImpl::<BloomFilter>::new();
+		cnt.inner = BloomFilter::new(error_rate, capacity, expansion);
+		let mut cnt = Container::Bloom(cnt);
+
+		let mut containers = self.containers.lock().await;
+		self.reap_expired(&mut containers, &key).await;
+		match containers.entry(key[..].into()) {
+			Entry::Occupied(_) => Err("ERR item exists".to_owned()),
+			Entry::Vacant(e) => {
+				self.memory_track_insert(&key, &mut cnt);
+				self.access_touch(&cnt);
+				e.insert(Self::make_container(cnt));
+				Ok(Value::Ok)
+			},
+		}
+	}
+
+	/// BF.ADD key item; true if the item was not present
+	pub async fn bloom_add(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let item = item_bytes(Self::extract(args.pop_front())?)?;
+		let limits = self.limits();
+		self.bloom_lock_mut(key, |filter| -> ExecResult {
+			Ok(Value::Bool(filter.insert(&item, &limits)?))
+		}).await
+	}
+
+	/// BF.MADD key item [item ...]; an array of BF.ADD replies
+	pub async fn bloom_madd(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let items = args.into_iter().map(item_bytes).collect::<Result<Vec<_>, String>>()?;
+		let limits = self.limits();
+		self.bloom_lock_mut(key, |filter| -> ExecResult {
+			let mut out = std::collections::VecDeque::with_capacity(items.len());
+			for item in items {
+				out.push_back(match filter.insert(&item, &limits) {
+					Ok(added) => Value::Bool(added),
+					Err(err) => Value::Error(err),
+				});
+			}
+			Ok(Value::Array(out))
+		}).await
+	}
+
+	/// BF.EXISTS key item; false for a missing key
+	pub async fn bloom_exists(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let item = item_bytes(Self::extract(args.pop_front())?)?;
+		self.bloom_lock(&key, |filter| -> ExecResult {
+			Ok(Value::Bool(filter.is_some_and(|filter|filter.contains(&item))))
+		}).await
+	}
+
+	/// BF.MEXISTS key item [item ...]
+	pub async fn bloom_mexists(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let items = args.into_iter().map(item_bytes).collect::<Result<Vec<_>, String>>()?;
+		self.bloom_lock(&key, |filter| -> ExecResult {
+			Ok(Value::Array(items.iter().map(|item|Value::Bool(filter.is_some_and(|filter|filter.contains(item)))).collect()))
+		}).await
+	}
+
+	/// BF.INFO key: pairs of the property name and value
+	pub async fn bloom_info(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		self.bloom_lock(&key, |filter| -> ExecResult {
+			let filter = filter.ok_or("ERR not found")?;
+			let info = vec![
+				("Capacity", Value::Integer(filter.capacity() as i64)),
+				("Size", Value::Integer(filter.size_bytes() as i64)),
+				("Number of filters", Value::Integer(filter.layers.len() as i64)),
+				("Number of items inserted", Value::Integer(filter.items() as i64)),
+				("Expansion rate", Value::Integer(filter.expansion as i64)),
+				("Error rate", Value::Float(filter.error_rate.to_bits())),
+				("Fill ratio", Value::Float(filter.fill_ratio().to_bits())),
+			];
+			Ok(Value::Array(info.into_iter().flat_map(|(name, value)|vec![Value::Buffer(name.as_bytes().to_vec()), value]).collect()))
+		}).await
+	}
+}
@@ -0,0 +1,149 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Timeline of the expiration deadlines.
+//!
+//! Deadlines are kept as `SystemTime` of the server timeline: the wall clock at startup
+//! advanced by the monotonic clock. Steps of the wall clock, e.g. by NTP, do not move it,
+//! so relative TTLs (EXPIRE, PEXPIRE, SET EX, SETEX) fire after the given duration whatever
+//! the wall clock does. Absolute deadlines (EXPIREAT, PEXPIREAT, `expire_at` of snapshots)
+//! follow the wall clock as of the moment they are set or loaded: they are converted with
+//! the current offset between the wall clock and the timeline.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Source of the current time; every time read of the storage goes through it
+pub trait Clock: Send + Sync {
+	fn now_system(&self) -> SystemTime;
+	fn now_monotonic(&self) -> Instant;
+}
+
+/// The real clocks of the system
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+	fn now_system(&self) -> SystemTime {
+		SystemTime::now()
+	}
+
+	fn now_monotonic(&self) -> Instant {
+		Instant::now()
+	}
+}
+
+/// Clock which stands still until advanced, to test expirations without sleeping
+pub struct MockClock {
+	now: Mutex<(SystemTime, Instant)>,
+}
+
+impl MockClock {
+	/// Starts at the current time of the system
+	pub fn new() -> Self {
+		Self {
+			now: Mutex::new((SystemTime::now(), Instant::now())),
+		}
+	}
+
+	/// Moves both clocks forward and runs the expiration check of the storage, so keys
+	/// with deadlines up to the new time are removed when it returns
+	pub async fn advance(&self, storage: &super::Storage, by: Duration) {
+		{
+			let mut now = self.now.lock().unwrap();
+			now.0 += by;
+			now.1 += by;
+		}
+		storage.keys_check_expirations().await;
+	}
+}
+
+impl Default for MockClock {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Clock for MockClock {
+	fn now_system(&self) -> SystemTime {
+		self.now.lock().unwrap().0
+	}
+
+	fn now_monotonic(&self) -> Instant {
+		self.now.lock().unwrap().1
+	}
+}
+
+/// Timeline of the expiration deadlines following a `Clock`, see the module documentation
+pub struct Timeline {
+	source: Arc<dyn Clock>,
+	started: Instant,
+	started_at: SystemTime,
+}
+
+impl Timeline {
+	pub fn new(source: Arc<dyn Clock>) -> Self {
+		Self {
+			started: source.now_monotonic(),
+			started_at: source.now_system(),
+			source,
+		}
+	}
+
+	/// The clock the timeline follows
+	pub fn source(&self) -> &dyn Clock {
+		&*self.source
+	}
+
+	/// Current point of the timeline
+	pub fn now(&self) -> SystemTime {
+		self.started_at + (self.source.now_monotonic() - self.started)
+	}
+
+	/// Monotonic instant of the point of the timeline, to sleep until a deadline
+	pub fn instant(&self, tm: SystemTime) -> Instant {
+		let now = self.source.now_monotonic();
+		let elapsed = now - self.started;
+		match tm.duration_since(self.started_at) {
+			Ok(offset) if offset > elapsed => now + (offset - elapsed),
+			_ => now,
+		}
+	}
+
+	/// Point of the timeline which is the wall clock time `wall` as of now
+	pub fn from_wall(&self, wall: SystemTime) -> SystemTime {
+		shift(wall, self.source.now_system(), self.now())
+	}
+
+	/// Wall clock time of the point of the timeline as of now
+	pub fn to_wall(&self, tm: SystemTime) -> SystemTime {
+		shift(tm, self.now(), self.source.now_system())
+	}
+}
+
+impl Default for Timeline {
+	fn default() -> Self {
+		Self::new(Arc::new(SystemClock))
+	}
+}
+/// `tm` moved by the difference between `to` and `from`
+fn shift(tm: SystemTime, from: SystemTime, to: SystemTime) -> SystemTime {
+	match to.duration_since(from) {
+		Ok(ahead) => tm + ahead,
+		Err(behind) => tm.checked_sub(behind.duration()).unwrap_or(SystemTime::UNIX_EPOCH),
+	}
+}
+
@@ -0,0 +1,85 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Per command counters for INFO commandstats, indexed by the position in the registry
+
+use std::fmt::Write;
+use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::commands::CommandSpec;
+
+#[derive(Default)]
+struct Counters {
+	calls: AtomicU64,
+	failed_calls: AtomicU64,
+	usec: AtomicU64,
+	max_usec: AtomicU64,
+}
+
+pub struct CommandStats {
+	counters: Vec<Counters>,
+}
+
+impl CommandStats {
+	pub fn new(commands: usize) -> Self {
+		Self {
+			counters: (0..commands).map(|_|Counters::default()).collect(),
+		}
+	}
+
+	pub fn record(&self, id: usize, elapsed: Duration, failed: bool) {
+		let counters = &self.counters[id];
+		let usec = elapsed.as_micros() as u64;
+		counters.calls.fetch_add(1, Ordering::Relaxed);
+		counters.usec.fetch_add(usec, Ordering::Relaxed);
+		counters.max_usec.fetch_max(usec, Ordering::Relaxed);
+		if failed {
+			counters.failed_calls.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+
+	pub fn reset(&self) {
+		for counters in self.counters.iter() {
+			counters.calls.store(0, Ordering::Relaxed);
+			counters.failed_calls.store(0, Ordering::Relaxed);
+			counters.usec.store(0, Ordering::Relaxed);
+			counters.max_usec.store(0, Ordering::Relaxed);
+		}
+	}
+
+	/// Lines in the Redis format for the commands called at least once
+	pub fn write_info<'a, I>(&self, specs: I, out: &mut String) -> std::fmt::Result
+	where I: Iterator<Item=&'a CommandSpec> {
+		for (spec, counters) in specs.zip(self.counters.iter()) {
+			let calls = counters.calls.load(Ordering::Relaxed);
+			if calls == 0 {
+				continue;
+			}
+			let usec = counters.usec.load(Ordering::Relaxed);
+			writeln!(out, "cmdstat_{}:calls={},usec={},usec_per_call={:.2},max_usec={},failed_calls={}",
+				spec.name.to_lowercase(),
+				calls,
+				usec,
+				usec as f64 / calls as f64,
+				counters.max_usec.load(Ordering::Relaxed),
+				counters.failed_calls.load(Ordering::Relaxed),
+			)?;
+		}
+		Ok(())
+	}
+}
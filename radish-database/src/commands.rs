@@ -0,0 +1,655 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Registry of the commands: the single place where commands are dispatched and described
+
+use std::sync::RwLock;
+use std::collections::{HashMap, VecDeque};
+
+use futures::future::BoxFuture;
+
+type Storage = super::Storage;
+type Session = super::Session;
+type Key = super::Key;
+type Value = super::Value;
+type Arguments = super::Arguments;
+type ExecResult = super::ExecResult;
+type Command = super::Command;
+type RedactedCommand<'a> = radish_types::RedactedCommand<'a>;
+
+/// Command modifies the dataset
+pub const WRITE: &str = "write";
+/// Command only reads the dataset
+pub const READONLY: &str = "readonly";
+/// Command changes or reveals server configuration
+pub const ADMIN: &str = "admin";
+/// Command may wait for another client to write its keys
+pub const BLOCKING: &str = "blocking";
+
+pub type Handler = for<'a> fn(&'a Storage, &'a mut Session, Arguments) -> BoxFuture<'a, ExecResult>;
+
+/// Arity counts the command name like Redis does: positive is the exact number of
+/// arguments, negative is the minimum. Key positions are first key, last key and step,
+/// counted from 1 like the arguments; a negative last key is counted from the end,
+/// zero first key means the command has no keys.
+pub struct CommandSpec {
+	pub name: &'static str,
+	pub handler: Handler,
+	pub arity: i32,
+	pub flags: &'static [&'static str],
+	pub first_key: i32,
+	pub last_key: i32,
+	pub key_step: usize,
+	pub summary: &'static str,
+}
+
+macro_rules! handler {
+	($method:ident) => {
+		fn $method<'a>(storage: &'a Storage, _session: &'a mut Session, args: Arguments) -> BoxFuture<'a, ExecResult> {
+			Box::pin(storage.$method(args))
+		}
+	};
+}
+
+/// Handler of a command which reads or changes the state of the connection
+macro_rules! session_handler {
+	($method:ident) => {
+		fn $method<'a>(storage: &'a Storage, session: &'a mut Session, args: Arguments) -> BoxFuture<'a, ExecResult> {
+			Box::pin(storage.$method(session, args))
+		}
+	};
+}
+
+fn unimplemented<'a>(storage: &'a Storage, _session: &'a mut Session, _args: Arguments) -> BoxFuture<'a, ExecResult> {
+	Box::pin(storage.unimplemented())
+}
+
+handler!(keys_now);
+handler!(keys_pnow);
+handler!(keys_del);
+handler!(keys_unlink);
+handler!(keys_touch);
+handler!(keys_keys);
+handler!(keys_exists);
+handler!(keys_randomkey);
+handler!(keys_rename);
+handler!(keys_persist);
+handler!(keys_expire);
+handler!(keys_expire_at);
+handler!(access_object);
+handler!(keys_pexpire);
+handler!(keys_pexpire_at);
+handler!(keys_pttl);
+handler!(keys_ttl);
+handler!(keys_type);
+handler!(keys_dump);
+handler!(keys_restore);
+handler!(keys_scan);
+handler!(keys_sort);
+handler!(keys_copy);
+handler!(expire_expiring);
+handler!(strings_append);
+handler!(strings_get);
+handler!(strings_getset);
+handler!(strings_len);
+handler!(strings_bitcount);
+handler!(strings_bitfield);
+handler!(strings_bitop);
+handler!(strings_decrby);
+handler!(strings_getbit);
+handler!(strings_get_range);
+handler!(strings_incrby);
+handler!(strings_incrby_float);
+handler!(strings_mget);
+handler!(strings_mset);
+handler!(strings_psetex);
+handler!(strings_set);
+handler!(strings_setbit);
+handler!(strings_setex);
+handler!(strings_setnx);
+handler!(strings_set_range);
+handler!(list_len);
+handler!(list_lpop);
+handler!(list_rpop);
+handler!(list_rpop_lpush);
+handler!(list_brpop_lpush);
+handler!(list_blpop);
+handler!(list_brpop);
+handler!(list_rem);
+handler!(list_set);
+handler!(list_lpush);
+handler!(list_rpush);
+handler!(list_lpushx);
+handler!(list_rpushx);
+handler!(list_index);
+handler!(list_lrange);
+handler!(list_insert);
+handler!(list_trim);
+handler!(set_sadd);
+handler!(set_rem);
+handler!(set_pop);
+handler!(set_rand_member);
+handler!(set_scan);
+handler!(set_card);
+handler!(set_move);
+handler!(set_smembers);
+handler!(set_is_member);
+handler!(set_diff);
+handler!(set_inter);
+handler!(set_union);
+handler!(set_diff_store);
+handler!(set_inter_store);
+handler!(set_union_store);
+handler!(hash_hset);
+handler!(hash_set_nx);
+handler!(hash_del);
+handler!(hash_hget);
+handler!(hash_get_all);
+handler!(hash_exists);
+handler!(hash_keys);
+handler!(hash_values);
+handler!(hash_len);
+handler!(hash_strlen);
+handler!(hash_incrby);
+handler!(hash_incrbyfloat);
+handler!(hash_mget);
+handler!(hash_scan);
+handler!(document_set);
+handler!(document_get);
+handler!(document_del);
+handler!(document_numincrby);
+handler!(document_arrappend);
+handler!(bloom_reserve);
+handler!(bloom_add);
+handler!(bloom_madd);
+handler!(bloom_exists);
+handler!(bloom_mexists);
+handler!(bloom_info);
+handler!(timeseries_create);
+handler!(timeseries_add);
+handler!(timeseries_range);
+handler!(timeseries_get);
+handler!(timeseries_info);
+handler!(connection_ping);
+session_handler!(connection_auth);
+session_handler!(connection_select);
+session_handler!(connection_client);
+handler!(server_info);
+handler!(snapshot_save);
+handler!(snapshot_bgsave);
+handler!(json_export);
+handler!(json_import);
+handler!(scripting_script);
+handler!(scripting_evalsha);
+handler!(memory_command);
+handler!(hotkeys_command);
+handler!(config_command);
+handler!(commands_command);
+handler!(commands_help);
+
+const COMMANDS: &[CommandSpec] = &[
+	CommandSpec {name: "NOW", handler: keys_now, arity: 1, flags: &[READONLY], first_key: 0, last_key: 0, key_step: 0, summary: "Current server time in seconds"},
+	CommandSpec {name: "PNOW", handler: keys_pnow, arity: 1, flags: &[READONLY], first_key: 0, last_key: 0, key_step: 0, summary: "Current server time in milliseconds"},
+	CommandSpec {name: "DEL", handler: keys_del, arity: -2, flags: &[WRITE], first_key: 1, last_key: -1, key_step: 1, summary: "Delete keys"},
+	CommandSpec {name: "KEYS", handler: keys_keys, arity: 2, flags: &[READONLY], first_key: 0, last_key: 0, key_step: 0, summary: "Find all keys matching the pattern"},
+	CommandSpec {name: "EXISTS", handler: keys_exists, arity: -2, flags: &[READONLY], first_key: 1, last_key: -1, key_step: 1, summary: "Count existing keys"},
+	CommandSpec {name: "RENAME", handler: keys_rename, arity: 3, flags: &[WRITE], first_key: 1, last_key: 2, key_step: 1, summary: "Rename a key"},
+	CommandSpec {name: "DUMP", handler: keys_dump, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Serialize the value of a key"},
+	CommandSpec {name: "EXPIRE", handler: keys_expire, arity: 3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Set a time to live in seconds"},
+	CommandSpec {name: "EXPIREAT", handler: keys_expire_at, arity: 3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Set an expiration unix time in seconds"},
+	CommandSpec {name: "MIGRATE", handler: unimplemented, arity: -6, flags: &[WRITE], first_key: 0, last_key: 0, key_step: 0, summary: "Transfer keys to another instance"},
+	CommandSpec {name: "MOVE", handler: unimplemented, arity: 3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Move a key to another database"},
+	CommandSpec {name: "OBJECT", handler: access_object, arity: -2, flags: &[READONLY], first_key: 2, last_key: 2, key_step: 1, summary: "Inspect the internals of a key"},
+	CommandSpec {name: "PERSIST", handler: keys_persist, arity: 2, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Remove the expiration of a key"},
+	CommandSpec {name: "PEXPIRE", handler: keys_pexpire, arity: 3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Set a time to live in milliseconds"},
+	CommandSpec {name: "PEXPIREAT", handler: keys_pexpire_at, arity: 3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Set an expiration unix time in milliseconds"},
+	CommandSpec {name: "PTTL", handler: keys_pttl, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get the time to live in milliseconds"},
+	CommandSpec {name: "RANDOMKEY", handler: keys_randomkey, arity: 1, flags: &[READONLY], first_key: 0, last_key: 0, key_step: 0, summary: "Return a random key"},
+	CommandSpec {name: "RENAMENX", handler: unimplemented, arity: 3, flags: &[WRITE], first_key: 1, last_key: 2, key_step: 1, summary: "Rename a key if the new key does not exist"},
+	CommandSpec {name: "RESTORE", handler: keys_restore, arity: -4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Create a key from a serialized value"},
+	CommandSpec {name: "SORT", handler: keys_sort, arity: -2, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Sort the elements of a list or a set"},
+	CommandSpec {name: "COPY", handler: keys_copy, arity: -3, flags: &[WRITE], first_key: 1, last_key: 2, key_step: 1, summary: "Copy a key with its value and time to live"},
+	CommandSpec {name: "TOUCH", handler: keys_touch, arity: -2, flags: &[READONLY], first_key: 1, last_key: -1, key_step: 1, summary: "Update the last access time of keys"},
+	CommandSpec {name: "TTL", handler: keys_ttl, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get the time to live in seconds"},
+	CommandSpec {name: "TYPE", handler: keys_type, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get the type of a key"},
+	CommandSpec {name: "UNLINK", handler: keys_unlink, arity: -2, flags: &[WRITE], first_key: 1, last_key: -1, key_step: 1, summary: "Delete keys asynchronously"},
+	CommandSpec {name: "WAIT", handler: unimplemented, arity: 3, flags: &[], first_key: 0, last_key: 0, key_step: 0, summary: "Wait for replication of the previous writes"},
+	CommandSpec {name: "SCAN", handler: keys_scan, arity: -2, flags: &[READONLY], first_key: 0, last_key: 0, key_step: 0, summary: "Iterate the keyspace"},
+	CommandSpec {name: "EXPIRING", handler: expire_expiring, arity: 2, flags: &[READONLY], first_key: 0, last_key: 0, key_step: 0, summary: "List keys expiring within the number of seconds"},
+
+	CommandSpec {name: "APPEND", handler: strings_append, arity: 3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Append a value to a string"},
+	CommandSpec {name: "GET", handler: strings_get, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get the value of a key"},
+	CommandSpec {name: "GETSET", handler: strings_getset, arity: 3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Set a string and return the old value"},
+	CommandSpec {name: "STRLEN", handler: strings_len, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get the length of a string"},
+	CommandSpec {name: "BITCOUNT", handler: strings_bitcount, arity: -2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Count set bits in a string"},
+	CommandSpec {name: "BITFIELD", handler: strings_bitfield, arity: -2, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Perform bitfield operations"},
+	CommandSpec {name: "BITOP", handler: strings_bitop, arity: -4, flags: &[WRITE], first_key: 2, last_key: -1, key_step: 1, summary: "Bitwise operation between strings"},
+	CommandSpec {name: "BITPOS", handler: unimplemented, arity: -3, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Find the first set or clear bit"},
+	CommandSpec {name: "DECR", handler: strings_decrby, arity: 2, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Decrement an integer by one"},
+	CommandSpec {name: "DECRBY", handler: strings_decrby, arity: 3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Decrement an integer by the number"},
+	CommandSpec {name: "GETBIT", handler: strings_getbit, arity: 3, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get the bit at the offset"},
+	CommandSpec {name: "GETRANGE", handler: strings_get_range, arity: 4, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get a substring"},
+	CommandSpec {name: "INCR", handler: strings_incrby, arity: 2, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Increment an integer by one"},
+	CommandSpec {name: "INCRBY", handler: strings_incrby, arity: 3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Increment an integer by the number"},
+	CommandSpec {name: "INCRBYFLOAT", handler: strings_incrby_float, arity: 3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Increment a float by the number"},
+	CommandSpec {name: "MGET", handler: strings_mget, arity: -2, flags: &[READONLY], first_key: 1, last_key: -1, key_step: 1, summary: "Get the values of keys"},
+	CommandSpec {name: "MSET", handler: strings_mset, arity: -3, flags: &[WRITE], first_key: 1, last_key: -1, key_step: 2, summary: "Set multiple keys"},
+	CommandSpec {name: "MSETNX", handler: unimplemented, arity: -3, flags: &[WRITE], first_key: 1, last_key: -1, key_step: 2, summary: "Set multiple keys if none of them exists"},
+	CommandSpec {name: "PSETEX", handler: strings_psetex, arity: 4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Set a value with a time to live in milliseconds"},
+	CommandSpec {name: "SET", handler: strings_set, arity: -3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Set the string value of a key; IFEQ and IFGT are non-standard compare-and-set conditions"},
+	CommandSpec {name: "SETBIT", handler: strings_setbit, arity: 4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Set or clear the bit at the offset"},
+	CommandSpec {name: "SETEX", handler: strings_setex, arity: 4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Set a value with a time to live in seconds"},
+	CommandSpec {name: "SETNX", handler: strings_setnx, arity: 3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Set a value if the key does not exist"},
+	CommandSpec {name: "SETRANGE", handler: strings_set_range, arity: 4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Overwrite part of a string at the offset"},
+
+	CommandSpec {name: "LLEN", handler: list_len, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get the length of a list"},
+	CommandSpec {name: "LPOP", handler: list_lpop, arity: 2, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Remove and get the first element of a list"},
+	CommandSpec {name: "RPOP", handler: list_rpop, arity: 2, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Remove and get the last element of a list"},
+	CommandSpec {name: "LREM", handler: list_rem, arity: 4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Remove elements from a list"},
+	CommandSpec {name: "LSET", handler: list_set, arity: 4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Set the element of a list by index"},
+	CommandSpec {name: "LPUSH", handler: list_lpush, arity: -3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Prepend elements to a list"},
+	CommandSpec {name: "RPUSH", handler: list_rpush, arity: -3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Append elements to a list"},
+	CommandSpec {name: "LPUSHX", handler: list_lpushx, arity: -3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Prepend elements to an existing list"},
+	CommandSpec {name: "RPUSHX", handler: list_rpushx, arity: -3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Append elements to an existing list"},
+	CommandSpec {name: "LINDEX", handler: list_index, arity: 3, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get an element of a list by index"},
+	CommandSpec {name: "LRANGE", handler: list_lrange, arity: 4, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get a range of elements of a list"},
+	CommandSpec {name: "LINSERT", handler: list_insert, arity: 5, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Insert an element before or after another one"},
+	CommandSpec {name: "LTRIM", handler: list_trim, arity: 4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Trim a list to the range"},
+	CommandSpec {name: "RPOPLPUSH", handler: list_rpop_lpush, arity: 3, flags: &[WRITE], first_key: 1, last_key: 2, key_step: 1, summary: "Move the last element of a list to another list"},
+	CommandSpec {name: "BRPOP", handler: list_brpop, arity: -3, flags: &[WRITE, BLOCKING], first_key: 1, last_key: -2, key_step: 1, summary: "Remove and get the last element, blocking"},
+	CommandSpec {name: "BLPOP", handler: list_blpop, arity: -3, flags: &[WRITE, BLOCKING], first_key: 1, last_key: -2, key_step: 1, summary: "Remove and get the first element, blocking"},
+	CommandSpec {name: "BRPOPLPUSH", handler: list_brpop_lpush, arity: 4, flags: &[WRITE, BLOCKING], first_key: 1, last_key: 2, key_step: 1, summary: "Move the last element of a list to another list, blocking"},
+
+	CommandSpec {name: "SADD", handler: set_sadd, arity: -3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Add members to a set"},
+	CommandSpec {name: "SREM", handler: set_rem, arity: -3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Remove members from a set"},
+	CommandSpec {name: "SPOP", handler: set_pop, arity: -2, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Remove and return random members of a set"},
+	CommandSpec {name: "SSCAN", handler: set_scan, arity: -3, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Iterate the members of a set"},
+	CommandSpec {name: "SCARD", handler: set_card, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get the number of members of a set"},
+	CommandSpec {name: "SMOVE", handler: set_move, arity: 4, flags: &[WRITE], first_key: 1, last_key: 2, key_step: 1, summary: "Move a member from one set to another"},
+	CommandSpec {name: "SMEMBERS", handler: set_smembers, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get all members of a set"},
+	CommandSpec {name: "SISMEMBER", handler: set_is_member, arity: 3, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Check a member of a set"},
+	CommandSpec {name: "SDIFF", handler: set_diff, arity: -2, flags: &[READONLY], first_key: 1, last_key: -1, key_step: 1, summary: "Subtract sets"},
+	CommandSpec {name: "SINTER", handler: set_inter, arity: -2, flags: &[READONLY], first_key: 1, last_key: -1, key_step: 1, summary: "Intersect sets"},
+	CommandSpec {name: "SUNION", handler: set_union, arity: -2, flags: &[READONLY], first_key: 1, last_key: -1, key_step: 1, summary: "Add sets"},
+	CommandSpec {name: "SDIFFSTORE", handler: set_diff_store, arity: -3, flags: &[WRITE], first_key: 1, last_key: -1, key_step: 1, summary: "Subtract sets and store the result"},
+	CommandSpec {name: "SINTERSTORE", handler: set_inter_store, arity: -3, flags: &[WRITE], first_key: 1, last_key: -1, key_step: 1, summary: "Intersect sets and store the result"},
+	CommandSpec {name: "SUNIONSTORE", handler: set_union_store, arity: -3, flags: &[WRITE], first_key: 1, last_key: -1, key_step: 1, summary: "Add sets and store the result"},
+	CommandSpec {name: "SRANDMEMBER", handler: set_rand_member, arity: -2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get random members of a set"},
+
+	CommandSpec {name: "HSET", handler: hash_hset, arity: -4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Set fields of a hash"},
+	CommandSpec {name: "HSETNX", handler: hash_set_nx, arity: 4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Set a field of a hash if it does not exist"},
+	CommandSpec {name: "HDEL", handler: hash_del, arity: -3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Delete fields of a hash"},
+	CommandSpec {name: "HGET", handler: hash_hget, arity: 3, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get the value of a hash field"},
+	CommandSpec {name: "HGETALL", handler: hash_get_all, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get all fields and values of a hash"},
+	CommandSpec {name: "HEXISTS", handler: hash_exists, arity: 3, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Check a field of a hash"},
+	CommandSpec {name: "HKEYS", handler: hash_keys, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get all fields of a hash"},
+	CommandSpec {name: "HVALUES", handler: hash_values, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get all values of a hash"},
+	CommandSpec {name: "HLEN", handler: hash_len, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get the number of fields of a hash"},
+	CommandSpec {name: "HSTRLEN", handler: hash_strlen, arity: 3, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get the length of a hash field value"},
+	CommandSpec {name: "HINCRBY", handler: hash_incrby, arity: 4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Increment an integer hash field"},
+	CommandSpec {name: "HINCRBYFLOAT", handler: hash_incrbyfloat, arity: 4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Increment a float hash field"},
+	CommandSpec {name: "HMGET", handler: hash_mget, arity: -3, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get the values of hash fields"},
+	CommandSpec {name: "HMSET", handler: hash_hset, arity: -4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Set fields of a hash"},
+	CommandSpec {name: "HSCAN", handler: hash_scan, arity: -3, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Iterate the fields of a hash"},
+
+	CommandSpec {name: "JSON.SET", handler: document_set, arity: -4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Set the JSON value at a path; NX and XX check the path"},
+	CommandSpec {name: "JSON.GET", handler: document_get, arity: -2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get the JSON values at paths"},
+	CommandSpec {name: "JSON.DEL", handler: document_del, arity: -2, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Delete the JSON value at a path"},
+	CommandSpec {name: "JSON.NUMINCRBY", handler: document_numincrby, arity: 4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Increment the number at a path"},
+	CommandSpec {name: "JSON.ARRAPPEND", handler: document_arrappend, arity: -4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Append JSON values to the array at a path"},
+
+	CommandSpec {name: "BF.RESERVE", handler: bloom_reserve, arity: -4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Create a bloom filter for the error rate and capacity"},
+	CommandSpec {name: "BF.ADD", handler: bloom_add, arity: 3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Add an item to a bloom filter"},
+	CommandSpec {name: "BF.MADD", handler: bloom_madd, arity: -3, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Add items to a bloom filter"},
+	CommandSpec {name: "BF.EXISTS", handler: bloom_exists, arity: 3, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Check if an item was probably added to a bloom filter"},
+	CommandSpec {name: "BF.MEXISTS", handler: bloom_mexists, arity: -3, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Check items of a bloom filter"},
+	CommandSpec {name: "BF.INFO", handler: bloom_info, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Capacity, size and fill ratio of a bloom filter"},
+
+	CommandSpec {name: "TS.CREATE", handler: timeseries_create, arity: -2, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Create a time series with a retention period and labels"},
+	CommandSpec {name: "TS.ADD", handler: timeseries_add, arity: 4, flags: &[WRITE], first_key: 1, last_key: 1, key_step: 1, summary: "Append a sample to a time series"},
+	CommandSpec {name: "TS.RANGE", handler: timeseries_range, arity: -4, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get samples in a time range, optionally aggregated into buckets"},
+	CommandSpec {name: "TS.GET", handler: timeseries_get, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Get the latest sample of a time series"},
+	CommandSpec {name: "TS.INFO", handler: timeseries_info, arity: 2, flags: &[READONLY], first_key: 1, last_key: 1, key_step: 1, summary: "Sample count, retention and labels of a time series"},
+
+	CommandSpec {name: "PING", handler: connection_ping, arity: -1, flags: &[], first_key: 0, last_key: 0, key_step: 0, summary: "Ping the server"},
+	CommandSpec {name: "AUTH", handler: connection_auth, arity: -2, flags: &[], first_key: 0, last_key: 0, key_step: 0, summary: "Authenticate the connection"},
+	CommandSpec {name: "SELECT", handler: connection_select, arity: 2, flags: &[], first_key: 0, last_key: 0, key_step: 0, summary: "Change the selected database of the connection"},
+	CommandSpec {name: "CLIENT", handler: connection_client, arity: -2, flags: &[], first_key: 0, last_key: 0, key_step: 0, summary: "Get or set the id and the name of the connection"},
+
+	CommandSpec {name: "INFO", handler: server_info, arity: -1, flags: &[], first_key: 0, last_key: 0, key_step: 0, summary: "Get server information and statistics"},
+	CommandSpec {name: "HOTKEYS", handler: hotkeys_command, arity: -1, flags: &[ADMIN], first_key: 0, last_key: 0, key_step: 0, summary: "Most accessed keys with approximate hit counts"},
+	CommandSpec {name: "MEMORY", handler: memory_command, arity: -2, flags: &[READONLY], first_key: 2, last_key: 2, key_step: 1, summary: "Memory usage inspection"},
+	CommandSpec {name: "SCRIPT", handler: scripting_script, arity: -2, flags: &[], first_key: 0, last_key: 0, key_step: 0, summary: "Load, check or flush cached scripts"},
+	CommandSpec {name: "EVALSHA", handler: scripting_evalsha, arity: -3, flags: &[], first_key: 0, last_key: 0, key_step: 0, summary: "Execute a cached script by its SHA1 digest"},
+	CommandSpec {name: "SAVE", handler: snapshot_save, arity: 1, flags: &[ADMIN], first_key: 0, last_key: 0, key_step: 0, summary: "Write the dataset to the snapshot file"},
+	CommandSpec {name: "BGSAVE", handler: snapshot_bgsave, arity: 1, flags: &[ADMIN], first_key: 0, last_key: 0, key_step: 0, summary: "Write the dataset to the snapshot file in the background"},
+	CommandSpec {name: "EXPORT", handler: json_export, arity: -1, flags: &[ADMIN], first_key: 0, last_key: 0, key_step: 0, summary: "Dump the keys matching a pattern as a JSON document"},
+	CommandSpec {name: "IMPORT", handler: json_import, arity: -2, flags: &[WRITE, ADMIN], first_key: 0, last_key: 0, key_step: 0, summary: "Load a JSON document made by EXPORT, replacing or merging the dataset"},
+	CommandSpec {name: "CONFIG", handler: config_command, arity: -2, flags: &[ADMIN], first_key: 0, last_key: 0, key_step: 0, summary: "Get or set runtime configuration, reset statistics"},
+	CommandSpec {name: "HELP", handler: commands_help, arity: -1, flags: &[], first_key: 0, last_key: 0, key_step: 0, summary: "Describe a command or list all of them"},
+	CommandSpec {name: "COMMAND", handler: commands_command, arity: -1, flags: &[], first_key: 0, last_key: 0, key_step: 0, summary: "Describe the commands"},
+];
+
+/// Arguments masked in logs: `count` arguments after `keyword`, or every argument if the
+/// keyword is empty. Commands taking secrets opt in with a line here; the rules follow
+/// the original name of a renamed command, and apply to unknown commands too.
+struct Redaction {
+	command: &'static str,
+	keyword: &'static str,
+	count: usize,
+}
+
+const REDACTIONS: &[Redaction] = &[
+	Redaction {command: "AUTH", keyword: "", count: 0},
+	Redaction {command: "HELLO", keyword: "AUTH", count: 2},
+	Redaction {command: "MIGRATE", keyword: "AUTH", count: 1},
+	Redaction {command: "MIGRATE", keyword: "AUTH2", count: 2},
+	Redaction {command: "CONFIG", keyword: "REQUIREPASS", count: 1},
+];
+
+/// Redis compatible error for a wrong number of arguments
+pub fn wrong_arity(name: &str) -> String {
+	format!("ERR wrong number of arguments for '{}' command", name.to_lowercase())
+}
+
+/// Levenshtein distance over bytes
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+	let mut row = (0..=b.len()).collect::<Vec<usize>>();
+	for (i, ca) in a.iter().enumerate() {
+		let mut diagonal = row[0];
+		row[0] = i + 1;
+		for (j, cb) in b.iter().enumerate() {
+			let substitution = diagonal + if ca == cb {0} else {1};
+			diagonal = row[j + 1];
+			row[j + 1] = std::cmp::min(substitution, std::cmp::min(row[j], row[j + 1]) + 1);
+		}
+	}
+	row[b.len()]
+}
+
+fn display_argument(arg: &Value) -> String {
+	match arg {
+		Value::Buffer(b) => String::from_utf8_lossy(b).into_owned(),
+		arg => format!("{}", arg),
+	}
+}
+
+impl CommandSpec {
+	/// `argc` counts the command name
+	pub fn check_arity(&self, argc: usize) -> Result<(), String> {
+		let argc = argc as i32;
+		let valid = if self.arity >= 0 {argc == self.arity} else {argc >= -self.arity};
+		if valid {
+			Ok(())
+		} else {
+			Err(wrong_arity(self.name))
+		}
+	}
+
+	pub fn has_flag(&self, flag: &str) -> bool {
+		self.flags.contains(&flag)
+	}
+
+	pub fn is_write(&self) -> bool {
+		self.has_flag(WRITE)
+	}
+
+	/// Whether this call modifies the dataset; SORT is a write command only with STORE
+	pub fn writes(&self, args: &Arguments) -> bool {
+		match self.name {
+			"SORT" => args.iter().skip(1).any(|arg|matches!(arg, Value::Buffer(b) if b.eq_ignore_ascii_case(b"STORE"))),
+			_ => self.is_write(),
+		}
+	}
+
+	pub fn is_blocking(&self) -> bool {
+		self.has_flag(BLOCKING)
+	}
+
+	/// Zero based indices of the key arguments among `argc` arguments
+	pub fn key_indices(&self, argc: usize) -> Vec<usize> {
+		if self.first_key <= 0 {
+			return vec![];
+		}
+		let last = if self.last_key < 0 {argc as i32 + self.last_key + 1} else {self.last_key};
+		let last = std::cmp::min(last, argc as i32);
+		(self.first_key..=last)
+			.step_by(self.key_step)
+			.map(|i|(i - 1) as usize)
+			.collect()
+	}
+
+	/// Key arguments of the command
+	pub fn keys(&self, args: &Arguments) -> Vec<Key> {
+		self.key_indices(args.len())
+			.into_iter()
+			.filter_map(|i| match args.get(i) {
+				Some(Value::Buffer(key)) => Some(key.clone()),
+				_ => None,
+			})
+			.collect()
+	}
+
+	/// `name` is the name the command is visible under
+	fn describe(&self, name: &str) -> Value {
+		let flags = self.flags.iter().map(|f|Value::Buffer(f.as_bytes().to_vec())).collect();
+		Value::Array(VecDeque::from(vec![
+			Value::Buffer(name.to_lowercase().into_bytes()),
+			Value::Integer(self.arity as i64),
+			Value::Array(flags),
+			Value::Integer(self.first_key as i64),
+			Value::Integer(self.last_key as i64),
+			Value::Integer(self.key_step as i64),
+			Value::Buffer(self.summary.as_bytes().to_vec()),
+		]))
+	}
+}
+
+struct Names {
+	/// Visible name to the position in the registry, which also identifies the command in the statistics
+	ids: HashMap<String, usize>,
+	/// Visible name of each command, None if the command is disabled
+	names: Vec<Option<String>>,
+}
+
+/// Commands keep their original identity (hooks, statistics, limits) when renamed;
+/// only the name a client has to send changes
+pub struct CommandTable {
+	names: RwLock<Names>,
+}
+
+impl CommandTable {
+	pub fn new() -> Self {
+		Self {
+			names: RwLock::new(Names {
+				ids: COMMANDS.iter().enumerate().map(|(id, spec)|(spec.name.to_owned(), id)).collect(),
+				names: COMMANDS.iter().map(|spec|Some(spec.name.to_owned())).collect(),
+			}),
+		}
+	}
+
+	/// `name` must be in upper case
+	pub fn get(&self, name: &str) -> Option<&'static CommandSpec> {
+		self.find(name).map(|(_, spec)|spec)
+	}
+
+	/// `name` must be in upper case; returns the id of the command along with its spec
+	pub fn find(&self, name: &str) -> Option<(usize, &'static CommandSpec)> {
+		self.names.read().unwrap().ids.get(name).map(|&id|(id, &COMMANDS[id]))
+	}
+
+	/// Count of all commands including disabled ones
+	pub fn len(&self) -> usize {
+		COMMANDS.len()
+	}
+
+	/// All commands by their original names
+	pub fn iter(&self) -> impl Iterator<Item=&'static CommandSpec> {
+		COMMANDS.iter()
+	}
+
+	/// Enabled commands by the names a client has to use
+	pub fn visible(&self) -> Vec<(String, &'static CommandSpec)> {
+		self.names.read().unwrap().names
+			.iter()
+			.zip(COMMANDS.iter())
+			.filter_map(|(name, spec)|name.clone().map(|name|(name, spec)))
+			.collect()
+	}
+
+	/// Renames the command known by its original name; an empty `new_name` disables it.
+	/// Renaming again replaces the previous name.
+	pub fn rename(&self, original: &str, new_name: &str) -> Result<(), String> {
+		let original = original.to_uppercase();
+		let new_name = new_name.to_uppercase();
+		let id = COMMANDS.iter()
+			.position(|spec|spec.name == original)
+			.ok_or_else(||format!("No such command '{}'", original))?;
+		let mut names = self.names.write().unwrap();
+		if !new_name.is_empty() {
+			if let Some(&other) = names.ids.get(&new_name) {
+				if other != id {
+					return Err(format!("Name '{}' is already used by '{}'", new_name, COMMANDS[other].name));
+				}
+			}
+		}
+		if let Some(old_name) = names.names[id].take() {
+			names.ids.remove(&old_name);
+		}
+		if !new_name.is_empty() {
+			names.ids.insert(new_name.clone(), id);
+			names.names[id] = Some(new_name);
+		}
+		Ok(())
+	}
+
+	/// The closest known command name for a misspelled one, if it is close enough.
+	/// Renamed commands are never suggested, so their new names are not disclosed.
+	pub fn suggest(&self, name: &str) -> Option<&'static str> {
+		let name = name.to_uppercase();
+		let threshold = std::cmp::min(2, name.len() / 3);
+		let names = self.names.read().unwrap();
+		COMMANDS
+			.iter()
+			.zip(names.names.iter())
+			.filter(|(spec, visible)|visible.as_deref() == Some(spec.name))
+			.map(|(spec, _)|(edit_distance(name.as_bytes(), spec.name.as_bytes()), spec.name))
+			.filter(|&(distance, _)|distance > 0 && distance <= threshold)
+			.min()
+			.map(|(_, name)|name)
+	}
+
+	/// Positions of the arguments to mask in logs, see `REDACTIONS`
+	pub fn secret_arguments(&self, name: &str, args: &Arguments) -> Vec<usize> {
+		let name = name.to_uppercase();
+		let original = self.find(&name).map_or(&name[..], |(_, spec)|spec.name);
+		let mut secret = Vec::new();
+		for redaction in REDACTIONS.iter().filter(|r|r.command == original) {
+			if redaction.keyword.is_empty() {
+				secret.extend(0..args.len());
+				continue;
+			}
+			for (i, arg) in args.iter().enumerate() {
+				if matches!(arg, Value::Buffer(b) if b.eq_ignore_ascii_case(redaction.keyword.as_bytes())) {
+					secret.extend((i + 1..=i + redaction.count).take_while(|&j|j < args.len()));
+				}
+			}
+		}
+		secret
+	}
+
+	/// Redis-like error for an unknown command with a did-you-mean hint
+	pub fn unknown_command(&self, name: &str, args: &Arguments) -> String {
+		let mut message = format!("ERR unknown command '{}'", name);
+		if !args.is_empty() {
+			message.push_str(", with args beginning with:");
+		}
+		for arg in args.iter().take(3) {
+			message.push_str(&format!(" '{}'", display_argument(arg)));
+		}
+		if let Some(suggestion) = self.suggest(name) {
+			message.push_str(&format!(" (did you mean '{}'?)", suggestion.to_lowercase()));
+		}
+		message
+	}
+}
+
+impl super::Storage {
+	/// Looks the command up by the name it is visible under
+	pub fn command_spec(&self, name: &str) -> Option<&'static CommandSpec> {
+		self.commands.get(&name.to_uppercase())
+	}
+
+	/// Renames a command known by its original name, or disables it if `new_name` is empty.
+	/// Meant for the startup configuration; it is not reachable by clients.
+	pub fn rename_command(&self, original: &str, new_name: &str) -> Result<(), String> {
+		self.commands.rename(original, new_name)
+	}
+
+	/// The command as it may be logged: secrets are masked and values over
+	/// `log-value-length` bytes are cut
+	pub fn display_command<'a>(&self, command: &'a Command) -> RedactedCommand<'a> {
+		let secret = self.commands.secret_arguments(&command.command, &command.arguments);
+		command.display_redacted(secret, self.config.log_value_length())
+	}
+
+	/// Checks shared by every command before its handler is called
+	pub fn commands_precheck(&self, spec: &CommandSpec, args: &Arguments) -> Result<(), String> {
+		spec.check_arity(args.len() + 1)?;
+		if spec.writes(args) && self.config.read_only() {
+			return Err(super::config::READ_ONLY_ERROR.to_owned());
+		}
+		self.limits_check_keys(spec, args)
+	}
+
+	pub async fn commands_help(&self, mut args: Arguments) -> ExecResult {
+		let name = match args.pop_front() {
+			None => {
+				let lines = self.commands
+					.visible()
+					.into_iter()
+					.map(|(name, spec)|Value::Buffer(format!("{} - {}", name, spec.summary).into_bytes()))
+					.collect();
+				return Ok(Value::Array(lines));
+			},
+			Some(arg) => Self::extract_string(Some(arg))?,
+		};
+		match self.command_spec(&name) {
+			Some(spec) => Ok(Value::Buffer(format!("{} - {}", name.to_uppercase(), spec.summary).into_bytes())),
+			None => Err(self.commands.unknown_command(&name, &args)),
+		}
+	}
+
+	pub async fn commands_command(&self, mut args: Arguments) -> ExecResult {
+		let subcommand = match args.pop_front() {
+			None => return Ok(Value::Array(self.commands.visible().iter().map(|(name, spec)|spec.describe(name)).collect())),
+			Some(arg) => Self::extract_string(Some(arg))?,
+		};
+		match &subcommand.to_uppercase()[..] {
+			"COUNT" => Ok(Value::Integer(self.commands.visible().len() as i64)),
+			"INFO" => {
+				let mut out = VecDeque::with_capacity(args.len());
+				while let Some(arg) = args.pop_front() {
+					let name = Self::extract_string(Some(arg))?;
+					out.push_back(self.command_spec(&name).map_or(Value::Nill, |spec|spec.describe(&name)));
+				}
+				Ok(Value::Array(out))
+			},
+			arg => Err(format!("Unexpected argument '{}'", arg)),
+		}
+	}
+}
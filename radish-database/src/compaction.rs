@@ -0,0 +1,130 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Compaction gives back the capacity left over after mass deletions:
+//! neither the containers map nor the collections ever shrink on their own.
+
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+
+use indexmap::{IndexSet, IndexMap};
+
+use super::container::{Container, ContainerImpl};
+use super::timeseries::TimeSeries;
+
+type Value = super::Value;
+
+/// Collections with smaller capacity are not worth to rebuild
+const MIN_CAPACITY: usize = 64;
+/// Capacity is considered excessive if less than 1/SPARSE_RATIO of it is occupied
+const SPARSE_RATIO: usize = 4;
+/// Count of containers locked per one acquisition of the containers map
+const BATCH_SIZE: usize = 256;
+
+fn is_sparse(len: usize, capacity: usize) -> bool {
+	capacity > MIN_CAPACITY && len * SPARSE_RATIO < capacity
+}
+
+pub trait Shrink {
+	fn len(&self) -> usize;
+	fn capacity(&self) -> usize;
+	fn shrink_to_fit(&mut self);
+}
+
+macro_rules! shrink_impl {
+	($type:ty) => {
+		impl Shrink for $type {
+			fn len(&self) -> usize { <$type>::len(self) }
+			fn capacity(&self) -> usize { <$type>::capacity(self) }
+			fn shrink_to_fit(&mut self) { <$type>::shrink_to_fit(self) }
+		}
+	};
+}
+
+shrink_impl!(Vec<u8>);
+shrink_impl!(VecDeque<Value>);
+shrink_impl!(IndexSet<Value>);
+shrink_impl!(IndexMap<Value, Value>);
+
+/// Retention trims the samples from the front, leaving the capacity behind
+impl Shrink for TimeSeries {
+	fn len(&self) -> usize { self.samples.len() }
+	fn capacity(&self) -> usize { self.samples.capacity() }
+	fn shrink_to_fit(&mut self) { self.samples.shrink_to_fit() }
+}
+
+impl<Inner: Shrink> ContainerImpl<Inner> {
+	fn compact(&mut self) -> bool {
+		if !is_sparse(self.inner.len(), self.inner.capacity()) {
+			return false;
+		}
+		self.inner.shrink_to_fit();
+		true
+	}
+}
+
+impl Container {
+	/// Returns true if the content was rebuilt
+	fn compact(&mut self) -> bool {
+		match self {
+			Container::Strings(c) => c.compact(),
+			Container::List(c) => c.compact(),
+			Container::Set(c) => c.compact(),
+			Container::Hash(c) => c.compact(),
+			// serde_json maps can't shrink, arrays are rebuilt by the next write anyway
+			Container::Json(_) => false,
+			// bit arrays are allocated at their final size
+			Container::Bloom(_) => false,
+			Container::TimeSeries(c) => c.compact(),
+		}
+	}
+}
+
+impl super::Storage {
+	/// Shrinks the containers map and every sparse container; returns the count of rebuilt ones.
+	/// The map is locked per batch of containers, so writers are delayed only briefly.
+	pub async fn compact(&self) -> usize {
+		let mut compacted = 0;
+		let mut index = 0;
+		loop {
+			let containers = self.containers.lock().await;
+			if index >= containers.len() {
+				break;
+			}
+			let end = std::cmp::min(index + BATCH_SIZE, containers.len());
+			for i in index..end {
+				let (_, container) = containers.get_index(i).unwrap();
+				let mut container = container.write().await;
+				if container.compact() {
+					self.memory_track(&mut container);
+					compacted += 1;
+				}
+			}
+			index = end;
+		}
+
+		let mut containers = self.containers.lock().await;
+		if is_sparse(containers.len(), containers.capacity()) {
+			containers.shrink_to_fit();
+			compacted += 1;
+		}
+		drop(containers);
+
+		self.counters.compactions.fetch_add(compacted as u64, Ordering::Relaxed);
+		compacted
+	}
+}
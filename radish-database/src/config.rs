@@ -0,0 +1,951 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use super::glob::glob_match;
+use super::limits::Limits;
+
+type Value = super::Value;
+type Arguments = super::Arguments;
+type ExecResult = super::ExecResult;
+
+pub const READ_ONLY_ERROR: &str = "READONLY You can't write against a read only instance";
+
+/// Runtime configuration; parameters used by commands are readable without locks.
+/// Server parameters are applied by the server at start, the persistence and eviction
+/// ones are only kept so far.
+pub struct Config {
+	read_only: AtomicBool,
+	read_only_expire: AtomicBool,
+	max_key_length: AtomicUsize,
+	max_value_size: AtomicUsize,
+	max_collection_elements: AtomicUsize,
+	bind: RwLock<String>,
+	port: AtomicUsize,
+	unixsocket: RwLock<String>,
+	tls_port: AtomicUsize,
+	tls_cert_file: RwLock<String>,
+	tls_key_file: RwLock<String>,
+	requirepass: RwLock<String>,
+	maxmemory: AtomicUsize,
+	maxmemory_policy: RwLock<String>,
+	save: RwLock<String>,
+	appendonly: AtomicBool,
+	dir: RwLock<String>,
+	dbfilename: RwLock<String>,
+	logfile: RwLock<String>,
+	loglevel: RwLock<String>,
+	log_format: RwLock<String>,
+	logfile_max_size: AtomicUsize,
+	logfile_retention: AtomicUsize,
+	timeout: AtomicUsize,
+	tcp_keepalive: AtomicUsize,
+	proxy_protocol: AtomicBool,
+	log_value_length: AtomicUsize,
+	databases: AtomicUsize,
+	reuseport_listeners: AtomicUsize,
+	rdbchecksum: AtomicBool,
+	rdb_compression_level: AtomicUsize,
+	hotkeys_tracking: AtomicBool,
+	active_expire_batch: AtomicUsize,
+	compression_threshold: AtomicUsize,
+	max_frame_size: AtomicUsize,
+	audit_log: RwLock<String>,
+	audit_log_max_size: AtomicUsize,
+	audit_log_retention: AtomicUsize,
+	audit_log_values: AtomicBool,
+	audit_log_value_length: AtomicUsize,
+	audit_log_queue: AtomicUsize,
+	audit_log_overflow: RwLock<String>,
+	/// Serializes CONFIG SET with reloads, so a reload is applied as a whole
+	update: Mutex<()>,
+}
+
+/// Parameter changed by a reload
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigChange {
+	pub name: &'static str,
+	pub old: String,
+	pub new: String,
+}
+
+impl std::fmt::Display for ConfigChange {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if self.name == "requirepass" {
+			write!(f, "{}: changed", self.name)
+		} else {
+			write!(f, "{}: '{}' -> '{}'", self.name, self.old, self.new)
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ReloadReport {
+	pub changed: Vec<ConfigChange>,
+	/// Changes of the parameters read only at start, left as they are
+	pub ignored: Vec<ConfigChange>,
+}
+
+struct Parameter {
+	name: &'static str,
+	get: fn(&Config) -> String,
+	set: fn(&Config, &str) -> Result<(), String>,
+}
+
+fn format_bool(value: bool) -> String {
+	if value {"yes"} else {"no"}.to_owned()
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+	match &value.to_lowercase()[..] {
+		"yes" => Ok(true),
+		"no" => Ok(false),
+		_ => Err(format!("Argument must be 'yes' or 'no', got '{}'", value)),
+	}
+}
+
+fn parse_size(value: &str) -> Result<usize, String> {
+	value.parse::<usize>().map_err(|e|format!("Argument must be a non-negative integer: {}", e))
+}
+
+/// Memory size with an optional unit as in redis.conf: 1k = 1000 bytes, 1kb = 1024 bytes
+fn parse_memory(value: &str) -> Result<usize, String> {
+	let lower = value.to_lowercase();
+	let digits = lower.find(|c: char|!c.is_ascii_digit()).unwrap_or(lower.len());
+	let multiplier: usize = match &lower[digits..] {
+		"" | "b" => 1,
+		"k" => 1000,
+		"kb" => 1024,
+		"m" => 1000 * 1000,
+		"mb" => 1024 * 1024,
+		"g" => 1000 * 1000 * 1000,
+		"gb" => 1024 * 1024 * 1024,
+		_ => return Err(format!("Argument must be a memory size like 100mb, got '{}'", value)),
+	};
+	parse_size(&lower[..digits])?
+		.checked_mul(multiplier)
+		.ok_or_else(||format!("Memory size '{}' is too big", value))
+}
+
+fn parse_one_of(value: &str, allowed: &[&str]) -> Result<String, String> {
+	let lower = value.to_lowercase();
+	if allowed.contains(&&lower[..]) {
+		Ok(lower)
+	} else {
+		Err(format!("Argument must be one of {}, got '{}'", allowed.join(", "), value))
+	}
+}
+
+/// Space separated pairs of seconds and changes, or an empty string
+fn parse_save(value: &str) -> Result<String, String> {
+	let numbers = value.split_whitespace().collect::<Vec<&str>>();
+	if numbers.len() % 2 == 1 || numbers.iter().any(|n|n.parse::<u64>().is_err()) {
+		return Err(format!("Argument must be pairs of seconds and changes, got '{}'", value));
+	}
+	Ok(numbers.join(" "))
+}
+
+fn read_string(value: &RwLock<String>) -> String {
+	value.read().unwrap().clone()
+}
+
+fn write_string(value: &RwLock<String>, new_value: String) {
+	*value.write().unwrap() = new_value;
+}
+
+const MAXMEMORY_POLICIES: &[&str] = &[
+	"noeviction", "allkeys-lru", "allkeys-lfu", "allkeys-random",
+	"volatile-lru", "volatile-lfu", "volatile-random", "volatile-ttl",
+];
+const LOGLEVELS: &[&str] = &["debug", "verbose", "notice", "warning"];
+const LOG_FORMATS: &[&str] = &["text", "json"];
+
+const AUDIT_LOG_OVERFLOWS: &[&str] = &["drop", "block"];
+
+/// Highest zstd level
+const MAX_COMPRESSION_LEVEL: usize = 22;
+
+/// Parameters applied by the server at start; a reload does not change them
+const STARTUP_PARAMETERS: &[&str] = &[
+	"bind", "port", "unixsocket", "tls-port", "tls-cert-file", "tls-key-file", "databases",
+	"reuseport-listeners", "logfile", "log-format", "logfile-max-size", "logfile-retention",
+	"audit-log", "audit-log-max-size", "audit-log-retention",
+	"audit-log-values", "audit-log-value-length", "audit-log-queue", "audit-log-overflow",
+];
+
+const PARAMETERS: &[Parameter] = &[
+	Parameter {
+		name: "read-only",
+		get: |c|format_bool(c.read_only()),
+		set: |c, v|{
+			c.read_only.store(parse_bool(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "read-only-expire",
+		get: |c|format_bool(c.read_only_expire()),
+		set: |c, v|{
+			c.read_only_expire.store(parse_bool(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "max-key-length",
+		get: |c|c.max_key_length.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			c.max_key_length.store(parse_size(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "max-value-size",
+		get: |c|c.max_value_size.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			c.max_value_size.store(parse_size(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "max-collection-elements",
+		get: |c|c.max_collection_elements.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			c.max_collection_elements.store(parse_size(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "bind",
+		get: |c|read_string(&c.bind),
+		set: |c, v|{
+			if v.split_whitespace().next().is_none() {
+				return Err("Argument must be at least one address".to_owned());
+			}
+			write_string(&c.bind, v.to_owned());
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "port",
+		get: |c|c.port.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			let port = v.parse::<u16>().map_err(|e|format!("Argument must be a port number: {}", e))?;
+			c.port.store(port as usize, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "unixsocket",
+		get: |c|read_string(&c.unixsocket),
+		set: |c, v|{
+			write_string(&c.unixsocket, v.to_owned());
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "tls-port",
+		get: |c|c.tls_port.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			let port = v.parse::<u16>().map_err(|e|format!("Argument must be a port number: {}", e))?;
+			c.tls_port.store(port as usize, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "tls-cert-file",
+		get: |c|read_string(&c.tls_cert_file),
+		set: |c, v|{
+			write_string(&c.tls_cert_file, v.to_owned());
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "tls-key-file",
+		get: |c|read_string(&c.tls_key_file),
+		set: |c, v|{
+			write_string(&c.tls_key_file, v.to_owned());
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "requirepass",
+		get: |c|read_string(&c.requirepass),
+		set: |c, v|{
+			write_string(&c.requirepass, v.to_owned());
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "maxmemory",
+		get: |c|c.maxmemory.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			c.maxmemory.store(parse_memory(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "maxmemory-policy",
+		get: |c|read_string(&c.maxmemory_policy),
+		set: |c, v|{
+			write_string(&c.maxmemory_policy, parse_one_of(v, MAXMEMORY_POLICIES)?);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "save",
+		get: |c|read_string(&c.save),
+		set: |c, v|{
+			write_string(&c.save, parse_save(v)?);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "appendonly",
+		get: |c|format_bool(c.appendonly.load(Ordering::Relaxed)),
+		set: |c, v|{
+			c.appendonly.store(parse_bool(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "dir",
+		get: |c|read_string(&c.dir),
+		set: |c, v|{
+			write_string(&c.dir, v.to_owned());
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "dbfilename",
+		get: |c|read_string(&c.dbfilename),
+		set: |c, v|{
+			if v.is_empty() || v.contains('/') {
+				return Err(format!("Argument must be a file name without a path, got '{}'", v));
+			}
+			write_string(&c.dbfilename, v.to_owned());
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "logfile",
+		get: |c|read_string(&c.logfile),
+		set: |c, v|{
+			write_string(&c.logfile, v.to_owned());
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "loglevel",
+		get: |c|read_string(&c.loglevel),
+		set: |c, v|{
+			write_string(&c.loglevel, parse_one_of(v, LOGLEVELS)?);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "log-format",
+		get: |c|read_string(&c.log_format),
+		set: |c, v|{
+			write_string(&c.log_format, parse_one_of(v, LOG_FORMATS)?);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "logfile-max-size",
+		get: |c|c.logfile_max_size.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			c.logfile_max_size.store(parse_memory(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "logfile-retention",
+		get: |c|c.logfile_retention.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			c.logfile_retention.store(parse_size(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "log-value-length",
+		get: |c|c.log_value_length().to_string(),
+		set: |c, v|{
+			c.log_value_length.store(parse_size(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "timeout",
+		get: |c|c.timeout.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			c.timeout.store(parse_size(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "tcp-keepalive",
+		get: |c|c.tcp_keepalive.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			c.tcp_keepalive.store(parse_size(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "proxy-protocol",
+		get: |c|format_bool(c.proxy_protocol()),
+		set: |c, v|{
+			c.proxy_protocol.store(parse_bool(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "databases",
+		get: |c|c.databases.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			match parse_size(v)? {
+				0 => Err("Argument must be at least 1".to_owned()),
+				databases => {
+					c.databases.store(databases, Ordering::Relaxed);
+					Ok(())
+				},
+			}
+		},
+	},
+	Parameter {
+		name: "reuseport-listeners",
+		get: |c|c.reuseport_listeners.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			match parse_size(v)? {
+				0 => Err("Argument must be at least 1".to_owned()),
+				listeners => {
+					c.reuseport_listeners.store(listeners, Ordering::Relaxed);
+					Ok(())
+				},
+			}
+		},
+	},
+	Parameter {
+		name: "rdbchecksum",
+		get: |c|format_bool(c.rdbchecksum()),
+		set: |c, v|{
+			c.rdbchecksum.store(parse_bool(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "rdb-compression-level",
+		get: |c|c.rdb_compression_level().to_string(),
+		set: |c, v|{
+			let level = parse_size(v)?;
+			if level > MAX_COMPRESSION_LEVEL {
+				return Err(format!("Argument must be from 0 to {}, got {}", MAX_COMPRESSION_LEVEL, level));
+			}
+			if level > 0 && !cfg!(feature = "compression") {
+				return Err("Snapshot compression is not supported by this build".to_owned());
+			}
+			c.rdb_compression_level.store(level, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "hotkeys-tracking",
+		get: |c|format_bool(c.hotkeys_tracking()),
+		set: |c, v|{
+			c.hotkeys_tracking.store(parse_bool(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "active-expire-batch",
+		get: |c|c.active_expire_batch().to_string(),
+		set: |c, v|{
+			match parse_size(v)? {
+				0 => Err("Argument must be at least 1".to_owned()),
+				batch => {
+					c.active_expire_batch.store(batch, Ordering::Relaxed);
+					Ok(())
+				},
+			}
+		},
+	},
+	Parameter {
+		name: "compression-threshold",
+		get: |c|c.compression_threshold().to_string(),
+		set: |c, v|{
+			c.compression_threshold.store(parse_memory(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "max-frame-size",
+		get: |c|c.max_frame_size().to_string(),
+		set: |c, v|{
+			c.max_frame_size.store(parse_memory(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "audit-log",
+		get: |c|read_string(&c.audit_log),
+		set: |c, v|{
+			write_string(&c.audit_log, v.to_owned());
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "audit-log-max-size",
+		get: |c|c.audit_log_max_size.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			c.audit_log_max_size.store(parse_memory(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "audit-log-retention",
+		get: |c|c.audit_log_retention.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			c.audit_log_retention.store(parse_size(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "audit-log-values",
+		get: |c|format_bool(c.audit_log_values.load(Ordering::Relaxed)),
+		set: |c, v|{
+			c.audit_log_values.store(parse_bool(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "audit-log-value-length",
+		get: |c|c.audit_log_value_length.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			c.audit_log_value_length.store(parse_size(v)?, Ordering::Relaxed);
+			Ok(())
+		},
+	},
+	Parameter {
+		name: "audit-log-queue",
+		get: |c|c.audit_log_queue.load(Ordering::Relaxed).to_string(),
+		set: |c, v|{
+			match parse_size(v)? {
+				0 => Err("Argument must be at least 1".to_owned()),
+				queue => {
+					c.audit_log_queue.store(queue, Ordering::Relaxed);
+					Ok(())
+				},
+			}
+		},
+	},
+	Parameter {
+		name: "audit-log-overflow",
+		get: |c|read_string(&c.audit_log_overflow),
+		set: |c, v|{
+			write_string(&c.audit_log_overflow, parse_one_of(v, AUDIT_LOG_OVERFLOWS)?);
+			Ok(())
+		},
+	},
+];
+
+impl Default for Config {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Config {
+	pub fn new() -> Self {
+		Self {
+			read_only: AtomicBool::new(false),
+			read_only_expire: AtomicBool::new(true),
+			max_key_length: AtomicUsize::new(0),
+			max_value_size: AtomicUsize::new(0),
+			max_collection_elements: AtomicUsize::new(0),
+			bind: RwLock::new("127.0.0.1".to_owned()),
+			port: AtomicUsize::new(6142),
+			unixsocket: RwLock::new(String::new()),
+			tls_port: AtomicUsize::new(0),
+			tls_cert_file: RwLock::new(String::new()),
+			tls_key_file: RwLock::new(String::new()),
+			requirepass: RwLock::new(String::new()),
+			maxmemory: AtomicUsize::new(0),
+			maxmemory_policy: RwLock::new("noeviction".to_owned()),
+			save: RwLock::new(String::new()),
+			appendonly: AtomicBool::new(false),
+			dir: RwLock::new(".".to_owned()),
+			dbfilename: RwLock::new("dump.rdb".to_owned()),
+			logfile: RwLock::new(String::new()),
+			loglevel: RwLock::new("notice".to_owned()),
+			log_format: RwLock::new("text".to_owned()),
+			logfile_max_size: AtomicUsize::new(0),
+			logfile_retention: AtomicUsize::new(5),
+			timeout: AtomicUsize::new(0),
+			tcp_keepalive: AtomicUsize::new(300),
+			proxy_protocol: AtomicBool::new(false),
+			log_value_length: AtomicUsize::new(128),
+			databases: AtomicUsize::new(1),
+			reuseport_listeners: AtomicUsize::new(1),
+			rdbchecksum: AtomicBool::new(true),
+			rdb_compression_level: AtomicUsize::new(0),
+			hotkeys_tracking: AtomicBool::new(false),
+			active_expire_batch: AtomicUsize::new(1000),
+			compression_threshold: AtomicUsize::new(64 * 1024),
+			max_frame_size: AtomicUsize::new(radish_types::frame::DEFAULT_MAX_FRAME_SIZE),
+			audit_log: RwLock::new(String::new()),
+			audit_log_max_size: AtomicUsize::new(64 * 1024 * 1024),
+			audit_log_retention: AtomicUsize::new(5),
+			audit_log_values: AtomicBool::new(false),
+			audit_log_value_length: AtomicUsize::new(64),
+			audit_log_queue: AtomicUsize::new(10000),
+			audit_log_overflow: RwLock::new("drop".to_owned()),
+			update: Mutex::new(()),
+		}
+	}
+
+	/// Rejects every write command
+	pub fn read_only(&self) -> bool {
+		self.read_only.load(Ordering::Relaxed)
+	}
+
+	/// Expired keys are still removed in read-only mode
+	pub fn read_only_expire(&self) -> bool {
+		self.read_only_expire.load(Ordering::Relaxed)
+	}
+
+	/// Size limits at the moment of the call, zero means unlimited
+	pub fn limits(&self) -> Limits {
+		Limits {
+			max_key_length: self.max_key_length.load(Ordering::Relaxed),
+			max_value_size: self.max_value_size.load(Ordering::Relaxed),
+			max_collection_elements: self.max_collection_elements.load(Ordering::Relaxed),
+		}
+	}
+
+	/// Addresses to listen on, separated by spaces; each gets a TCP and a TLS listener
+	pub fn bind(&self) -> String {
+		read_string(&self.bind)
+	}
+
+	/// Port of the plain TCP listeners, zero binds an ephemeral one
+	pub fn port(&self) -> u16 {
+		self.port.load(Ordering::Relaxed) as u16
+	}
+
+	/// Path of the unix socket listener, empty if it is disabled
+	pub fn unixsocket(&self) -> String {
+		read_string(&self.unixsocket)
+	}
+
+	/// Port of the TLS listeners, zero disables them
+	pub fn tls_port(&self) -> u16 {
+		self.tls_port.load(Ordering::Relaxed) as u16
+	}
+
+	/// PEM file with the certificate chain of the TLS listeners
+	pub fn tls_cert_file(&self) -> String {
+		read_string(&self.tls_cert_file)
+	}
+
+	/// PEM file with the private key of the TLS listeners
+	pub fn tls_key_file(&self) -> String {
+		read_string(&self.tls_key_file)
+	}
+
+	/// Empty if clients do not need to authenticate
+	pub fn requirepass(&self) -> String {
+		read_string(&self.requirepass)
+	}
+
+	pub fn auth_required(&self) -> bool {
+		!self.requirepass.read().unwrap().is_empty()
+	}
+
+	/// Directory of the snapshot file
+	pub fn dir(&self) -> String {
+		read_string(&self.dir)
+	}
+
+	pub fn dbfilename(&self) -> String {
+		read_string(&self.dbfilename)
+	}
+
+	/// Snapshot checksum is verified on load
+	pub fn rdbchecksum(&self) -> bool {
+		self.rdbchecksum.load(Ordering::Relaxed)
+	}
+
+	/// zstd level of the snapshot payload, 0 saves it uncompressed
+	pub fn rdb_compression_level(&self) -> usize {
+		self.rdb_compression_level.load(Ordering::Relaxed)
+	}
+
+	/// Empty means the standard error
+	pub fn logfile(&self) -> String {
+		read_string(&self.logfile)
+	}
+
+	/// One of debug, verbose, notice, warning
+	pub fn loglevel(&self) -> String {
+		read_string(&self.loglevel)
+	}
+
+	/// Either text or json, a JSON object per line
+	pub fn log_format(&self) -> String {
+		read_string(&self.log_format)
+	}
+
+	/// Size after which the log file is rotated, zero means never
+	pub fn logfile_max_size(&self) -> usize {
+		self.logfile_max_size.load(Ordering::Relaxed)
+	}
+
+	/// Count of rotated log files kept
+	pub fn logfile_retention(&self) -> usize {
+		self.logfile_retention.load(Ordering::Relaxed)
+	}
+
+	/// Values logged with the commands are cut to this length, zero logs them in full
+	pub fn log_value_length(&self) -> usize {
+		self.log_value_length.load(Ordering::Relaxed)
+	}
+
+	/// Seconds of idleness after which a client is disconnected, zero means never
+	pub fn timeout(&self) -> usize {
+		self.timeout.load(Ordering::Relaxed)
+	}
+
+	/// Seconds between TCP keepalive probes, zero disables them
+	pub fn tcp_keepalive(&self) -> usize {
+		self.tcp_keepalive.load(Ordering::Relaxed)
+	}
+
+	/// TCP and TLS connections start with a PROXY protocol header carrying the client address
+	pub fn proxy_protocol(&self) -> bool {
+		self.proxy_protocol.load(Ordering::Relaxed)
+	}
+
+	/// Count of listener sockets sharing the port with SO_REUSEPORT, each with its own accept loop
+	pub fn reuseport_listeners(&self) -> usize {
+		self.reuseport_listeners.load(Ordering::Relaxed)
+	}
+
+	/// Count the keys of every command for HOTKEYS
+	pub fn hotkeys_tracking(&self) -> bool {
+		self.hotkeys_tracking.load(Ordering::Relaxed)
+	}
+
+	/// Maximum count of expired keys removed in one cycle of the expiration task
+	pub fn active_expire_batch(&self) -> usize {
+		self.active_expire_batch.load(Ordering::Relaxed)
+	}
+
+	/// Replies of at least this size are compressed for the connections which asked for it
+	pub fn compression_threshold(&self) -> usize {
+		self.compression_threshold.load(Ordering::Relaxed)
+	}
+
+	/// Commands of a bigger frame are refused and the connection is closed, zero means no limit
+	pub fn max_frame_size(&self) -> usize {
+		self.max_frame_size.load(Ordering::Relaxed)
+	}
+
+	/// Path of the audit log of write commands, empty if it is disabled
+	pub fn audit_log(&self) -> String {
+		read_string(&self.audit_log)
+	}
+
+	/// Size after which the audit log is rotated, zero means never
+	pub fn audit_log_max_size(&self) -> usize {
+		self.audit_log_max_size.load(Ordering::Relaxed)
+	}
+
+	/// Count of rotated audit log files kept
+	pub fn audit_log_retention(&self) -> usize {
+		self.audit_log_retention.load(Ordering::Relaxed)
+	}
+
+	/// Values are logged along with the keys, truncated to `audit_log_value_length` bytes if it is not zero
+	pub fn audit_log_values(&self) -> Option<usize> {
+		if self.audit_log_values.load(Ordering::Relaxed) {
+			Some(self.audit_log_value_length.load(Ordering::Relaxed))
+		} else {
+			None
+		}
+	}
+
+	/// Count of records waiting for the writer
+	pub fn audit_log_queue(&self) -> usize {
+		self.audit_log_queue.load(Ordering::Relaxed)
+	}
+
+	/// Commands wait for the writer instead of dropping records if the queue is full
+	pub fn audit_log_blocking(&self) -> bool {
+		*self.audit_log_overflow.read().unwrap() == "block"
+	}
+
+	pub fn get(&self, name: &str) -> Option<String> {
+		let name = name.to_lowercase();
+		PARAMETERS.iter().find(|p|p.name == name).map(|p|(p.get)(self))
+	}
+
+	pub fn set(&self, name: &str, value: &str) -> Result<(), String> {
+		let name = name.to_lowercase();
+		match PARAMETERS.iter().find(|p|p.name == name) {
+			None => Err(format!("Unsupported CONFIG parameter: {}", name)),
+			Some(p) => {
+				let _update = self.update.lock().unwrap();
+				(p.set)(self, value)
+			},
+		}
+	}
+
+	/// Re-reads a file as `load` does, then applies `overrides` like the command line does.
+	/// Parameters missing from both get their defaults. Nothing is changed if any value is invalid;
+	/// `rename-command` directives are skipped, the commands are renamed only at start
+	pub fn reload(&self, contents: &str, overrides: &[(String, String)]) -> Result<ReloadReport, String> {
+		let fresh = Config::new();
+		fresh.load_with(contents, |name, _|{
+			if name.eq_ignore_ascii_case("rename-command") {Some(Ok(()))} else {None}
+		})?;
+		for (name, value) in overrides {
+			fresh.set(name, value).map_err(|e|format!("Override '{}': {}", name, e))?;
+		}
+
+		let _update = self.update.lock().unwrap();
+		let mut report = ReloadReport::default();
+		for p in PARAMETERS {
+			let (old, new) = ((p.get)(self), (p.get)(&fresh));
+			if old == new {
+				continue;
+			}
+			let change = ConfigChange {name: p.name, old, new};
+			if STARTUP_PARAMETERS.contains(&p.name) {
+				report.ignored.push(change);
+			} else {
+				(p.set)(self, &change.new).expect("value is formatted by a valid parameter");
+				report.changed.push(change);
+			}
+		}
+		Ok(report)
+	}
+
+	/// Applies a file in the redis.conf format: a directive and its value per line,
+	/// `#` starts a comment, the value may be quoted. Repeated `save` lines are joined.
+	pub fn load(&self, contents: &str) -> Result<(), String> {
+		self.load_with(contents, |_, _|None)
+	}
+
+	/// Like `load`, but `directive` is asked first and handles the directives which are
+	/// not parameters by returning Some
+	fn load_with<F>(&self, contents: &str, mut directive: F) -> Result<(), String>
+	where F: FnMut(&str, &str) -> Option<Result<(), String>> {
+		let mut save = Vec::new();
+		for (number, line) in contents.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			let (name, value) = match line.find(char::is_whitespace) {
+				None => (line, ""),
+				Some(pos) => (&line[..pos], line[pos..].trim()),
+			};
+			let result = if let Some(result) = directive(name, value) {
+				result
+			} else if name.eq_ignore_ascii_case("save") {
+				let value = unquote(value);
+				if value.is_empty() {
+					save.clear();
+				} else {
+					save.push(value.to_owned());
+				}
+				self.set(name, &save.join(" "))
+			} else {
+				self.set(name, unquote(value))
+			};
+			result.map_err(|err|format!("Bad directive at line {}: '{}': {}", number + 1, line, err))?;
+		}
+		Ok(())
+	}
+}
+
+fn unquote(value: &str) -> &str {
+	let quoted = value.len() >= 2 && (
+		value.starts_with('"') && value.ends_with('"') ||
+		value.starts_with('\'') && value.ends_with('\''));
+	if quoted {&value[1..value.len() - 1]} else {value}
+}
+
+impl super::Storage {
+	pub fn config(&self) -> &Config {
+		&self.config
+	}
+
+	/// `Config::load` which also accepts `rename-command <command> <new name>` directives;
+	/// an empty new name (`""`) disables the command
+	pub fn load_config(&self, contents: &str) -> Result<(), String> {
+		self.config.load_with(contents, |name, value|{
+			if !name.eq_ignore_ascii_case("rename-command") {
+				return None;
+			}
+			Some(match value.find(char::is_whitespace) {
+				None => Err("Expected a command and its new name".to_owned()),
+				Some(pos) => self.rename_command(&value[..pos], unquote(value[pos..].trim())),
+			})
+		})
+	}
+
+	/// `Config::reload` of the file the server was started with
+	pub fn reload_config(&self, contents: &str, overrides: &[(String, String)]) -> Result<ReloadReport, String> {
+		self.config.reload(contents, overrides)
+	}
+
+	pub fn set_read_only(&self, read_only: bool) {
+		self.config.read_only.store(read_only, Ordering::Relaxed);
+	}
+
+	pub async fn config_command(&self, mut args: Arguments) -> ExecResult {
+		let subcommand = Self::extract_string(args.pop_front())?;
+		match &subcommand.to_uppercase()[..] {
+			"GET" => {
+				let pattern = Self::extract_string(args.pop_front())?.to_lowercase();
+				let mut out = VecDeque::new();
+				for p in PARAMETERS.iter().filter(|p|glob_match(pattern.as_bytes(), p.name.as_bytes())) {
+					out.push_back(Value::Buffer(p.name.as_bytes().to_vec()));
+					out.push_back(Value::Buffer((p.get)(&self.config).into_bytes()));
+				}
+				Ok(Value::Array(out))
+			},
+			"SET" => {
+				let name = Self::extract_string(args.pop_front())?;
+				let value = match Self::extract(args.pop_front())? {
+					Value::Integer(i) => i.to_string(),
+					value => Self::extract_string(Some(value))?,
+				};
+				self.config.set(&name, &value)?;
+				Ok(Value::Ok)
+			},
+			"RESETSTAT" => {
+				self.server_reset_stats();
+				Ok(Value::Ok)
+			},
+			arg => Err(format!("Unexpected argument '{}'", arg)),
+		}
+	}
+}
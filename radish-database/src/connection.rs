@@ -0,0 +1,98 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::commands::wrong_arity;
+
+type Session = super::Session;
+type Value = super::Value;
+type Arguments = super::Arguments;
+type ExecResult = super::ExecResult;
+
+impl super::Storage {
+	pub async fn connection_ping(&self, mut args: Arguments) -> ExecResult {
+		if args.len() > 1 {
+			return Err(wrong_arity("PING"));
+		}
+		match args.pop_front() {
+			None => Ok(Value::Buffer(b"PONG".to_vec())),
+			Some(message) => Ok(message),
+		}
+	}
+
+	/// AUTH [username] password; the only user is `default`
+	pub async fn connection_auth(&self, session: &mut Session, mut args: Arguments) -> ExecResult {
+		if args.len() > 2 {
+			return Err(wrong_arity("AUTH"));
+		}
+		let user = if args.len() == 2 {Self::extract_string(args.pop_front())?} else {"default".to_owned()};
+		let password = Self::extract_string(args.pop_front())?;
+		let required = self.config.requirepass();
+		if required.is_empty() {
+			return Err("ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?".to_owned());
+		}
+		if user != "default" || password != required {
+			session.authenticated = false;
+			return Err("WRONGPASS invalid username-password pair or user is disabled.".to_owned());
+		}
+		session.authenticated = true;
+		Ok(Value::Ok)
+	}
+
+	/// Commands other than AUTH are rejected until the session is authenticated;
+	/// `command` is the name from the registry
+	pub fn connection_check_auth(&self, session: &Session, command: &str) -> Result<(), String> {
+		if session.authenticated || command == "AUTH" || !self.config.auth_required() {
+			return Ok(());
+		}
+		Err("NOAUTH Authentication required.".to_owned())
+	}
+
+	/// There is a single keyspace, so only the database 0 can be selected
+	pub async fn connection_select(&self, session: &mut Session, mut args: Arguments) -> ExecResult {
+		let index = Self::extract_integer(args.pop_front())?;
+		if index != 0 {
+			return Err("ERR DB index is out of range".to_owned());
+		}
+		session.database = index as usize;
+		Ok(Value::Ok)
+	}
+
+	pub async fn connection_client(&self, session: &mut Session, mut args: Arguments) -> ExecResult {
+		let subcommand = Self::extract_string(args.pop_front())?;
+		match &subcommand.to_uppercase()[..] {
+			"ID" => Ok(Value::Integer(session.id as i64)),
+			"GETNAME" => Ok(session.name.as_ref().map_or(Value::Nill, |name|Value::Buffer(name.as_bytes().to_vec()))),
+			"SETNAME" => {
+				let name = Self::extract_string(args.pop_front())?;
+				if name.contains(' ') {
+					return Err("ERR Client names cannot contain spaces, newlines or special characters.".to_owned());
+				}
+				session.name = if name.is_empty() {None} else {Some(name)};
+				Ok(Value::Ok)
+			},
+			"COMPRESSION" => {
+				let algorithm = Self::extract_string(args.pop_front())?;
+				session.compression = match &algorithm.to_uppercase()[..] {
+					"LZ4" => true,
+					"OFF" => false,
+					_ => return Err(format!("ERR Unsupported compression '{}', supported: LZ4, OFF", algorithm)),
+				};
+				Ok(Value::Ok)
+			},
+			arg => Err(format!("Unexpected argument '{}'", arg)),
+		}
+	}
+}
@@ -17,22 +17,44 @@
 use std::sync::Arc;
 use std::collections::VecDeque;
 
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, RwLock};
 use indexmap::{IndexSet, IndexMap};
 
+use super::access::AccessMeta;
+use super::bloom::BloomFilter;
+use super::timeseries::TimeSeries;
+
 type Key = super::Key;
+type CompactKey = super::CompactKey;
 type Value = super::Value;
 
 #[derive(Debug)]
 pub struct ContainerImpl<Inner> {
 	pub inner: Inner,
 	pub expiration_time: Option<std::time::SystemTime>,
+	/// Size included in the storage memory total, see `memory_track`
+	pub accounted_size: usize,
+	pub access: AccessMeta,
 }
 impl<Inner: Default> ContainerImpl<Inner> {
 	pub fn new() -> Self {
 		Self {
 			inner: Inner::default(),
 			expiration_time: None,
+			accounted_size: 0,
+			access: AccessMeta::default(),
+		}
+	}
+}
+
+impl<Inner: Clone> ContainerImpl<Inner> {
+	/// Copy of the content and the expiration time; accounting and access metadata start anew
+	pub fn duplicate(&self) -> Self {
+		Self {
+			inner: self.inner.clone(),
+			expiration_time: self.expiration_time,
+			accounted_size: 0,
+			access: AccessMeta::default(),
 		}
 	}
 }
@@ -43,11 +65,168 @@ pub enum Container {
 	List(ContainerImpl<VecDeque<Value>>),
 	Hash(ContainerImpl<IndexMap<Value, Value>>),
 	Strings(ContainerImpl<Vec<u8>>),
+	Json(ContainerImpl<serde_json::Value>),
+	Bloom(ContainerImpl<BloomFilter>),
+	TimeSeries(ContainerImpl<TimeSeries>),
 }
-pub type ContainerPtr = Arc<Mutex<Container>>;
-pub type Containers = IndexMap<Key, ContainerPtr>;
+pub type ContainerPtr = Arc<RwLock<Container>>;
+pub type Containers = IndexMap<CompactKey, ContainerPtr>;
 pub type ContainersPtr = Arc<Mutex<Containers>>;
 
+pub const WRONG_TYPE_ERROR: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+pub const NAN_MEMBER_ERROR: &str = "ERR NaN is not allowed as a set member or hash field";
+
+/// Set members and hash fields are hashed by their bits, so -0.0 is stored as 0.0
+pub fn canonical_member(value: Value) -> Value {
+	match value {
+		Value::Float(n) if f64::from_bits(n) == 0.0 => Value::Float(0f64.to_bits()),
+		Value::Array(items) => Value::Array(items.into_iter().map(canonical_member).collect()),
+		value => value,
+	}
+}
+
+fn has_nan(value: &Value) -> bool {
+	match value {
+		Value::Float(n) => f64::from_bits(*n).is_nan(),
+		Value::Array(items) => items.iter().any(has_nan),
+		_ => false,
+	}
+}
+
+/// Canonical form of a member or field being stored; NaN is rejected because no lookup would equal it
+pub fn normalize_member(value: Value) -> Result<Value, String> {
+	if has_nan(&value) {
+		return Err(NAN_MEMBER_ERROR.to_owned());
+	}
+	Ok(canonical_member(value))
+}
+
+/// Kind of a container as reported by TYPE and matched by SCAN ... TYPE; every place
+/// telling kinds apart matches on it, so a new container variant has to be named there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerKind {
+	String,
+	List,
+	Set,
+	Hash,
+	Json,
+	Bloom,
+	TimeSeries,
+}
+
+impl ContainerKind {
+	pub const ALL: [ContainerKind; 7] = [
+		ContainerKind::String,
+		ContainerKind::List,
+		ContainerKind::Set,
+		ContainerKind::Hash,
+		ContainerKind::Json,
+		ContainerKind::Bloom,
+		ContainerKind::TimeSeries,
+	];
+
+	pub fn as_str(self) -> &'static str {
+		match self {
+			ContainerKind::String => "string",
+			ContainerKind::List => "list",
+			ContainerKind::Set => "set",
+			ContainerKind::Hash => "hash",
+			ContainerKind::Json => "ReJSON-RL",
+			ContainerKind::Bloom => "MBbloom--",
+			ContainerKind::TimeSeries => "TSDB-TYPE",
+		}
+	}
+}
+
+impl std::str::FromStr for ContainerKind {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, String> {
+		Self::ALL.iter()
+			.find(|kind|kind.as_str() == s)
+			.copied()
+			.ok_or_else(||format!("Unexpected type '{}'", s))
+	}
+}
+
+impl std::fmt::Display for ContainerKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl Container {
+	pub fn kind(&self) -> ContainerKind {
+		match self {
+			Container::Strings(_) => ContainerKind::String,
+			Container::List(_) => ContainerKind::List,
+			Container::Set(_) => ContainerKind::Set,
+			Container::Hash(_) => ContainerKind::Hash,
+			Container::Json(_) => ContainerKind::Json,
+			Container::Bloom(_) => ContainerKind::Bloom,
+			Container::TimeSeries(_) => ContainerKind::TimeSeries,
+		}
+	}
+
+	pub fn duplicate(&self) -> Self {
+		match self {
+			Container::Set(c) => Container::Set(c.duplicate()),
+			Container::List(c) => Container::List(c.duplicate()),
+			Container::Hash(c) => Container::Hash(c.duplicate()),
+			Container::Strings(c) => Container::Strings(c.duplicate()),
+			Container::Json(c) => Container::Json(c.duplicate()),
+			Container::Bloom(c) => Container::Bloom(c.duplicate()),
+			Container::TimeSeries(c) => Container::TimeSeries(c.duplicate()),
+		}
+	}
+
+	/// The content of the container, WRONGTYPE if it is of another kind
+	pub fn typed<Inner: ContainerInner>(&self) -> Result<&ContainerImpl<Inner>, String> {
+		Inner::unwrap(self).ok_or_else(||WRONG_TYPE_ERROR.to_owned())
+	}
+
+	pub fn typed_mut<Inner: ContainerInner>(&mut self) -> Result<&mut ContainerImpl<Inner>, String> {
+		Inner::unwrap_mut(self).ok_or_else(||WRONG_TYPE_ERROR.to_owned())
+	}
+}
+
+/// Content of one of the container variants, for access to containers by the type of content
+pub trait ContainerInner: Default + Sized {
+	const KIND: ContainerKind;
+	fn wrap(container: ContainerImpl<Self>) -> Container;
+	fn unwrap(container: &Container) -> Option<&ContainerImpl<Self>>;
+	fn unwrap_mut(container: &mut Container) -> Option<&mut ContainerImpl<Self>>;
+}
+
+macro_rules! container_inner_impl {
+	($type:ty, $variant:ident, $kind:ident) => {
+		impl ContainerInner for $type {
+			const KIND: ContainerKind = ContainerKind::$kind;
+			fn wrap(container: ContainerImpl<Self>) -> Container { Container::$variant(container) }
+			fn unwrap(container: &Container) -> Option<&ContainerImpl<Self>> {
+				match container {
+					Container::$variant(c) => Some(c),
+					_ => None,
+				}
+			}
+			fn unwrap_mut(container: &mut Container) -> Option<&mut ContainerImpl<Self>> {
+				match container {
+					Container::$variant(c) => Some(c),
+					_ => None,
+				}
+			}
+		}
+	};
+}
+
+container_inner_impl!(IndexSet<Value>, Set, Set);
+container_inner_impl!(VecDeque<Value>, List, List);
+container_inner_impl!(IndexMap<Value, Value>, Hash, Hash);
+container_inner_impl!(Vec<u8>, Strings, String);
+container_inner_impl!(serde_json::Value, Json, Json);
+container_inner_impl!(BloomFilter, Bloom, Bloom);
+container_inner_impl!(TimeSeries, TimeSeries, TimeSeries);
+
 
 impl super::Storage {
 
@@ -58,6 +237,16 @@ impl super::Storage {
 		}
 	}
 
+	/// Member or field to look up; lookups accept NaN so members stored before are still reachable
+	pub fn extract_member(arg: Option<Value>) -> Result<Value, String> {
+		Self::extract(arg).map(canonical_member)
+	}
+
+	/// Member or field to store
+	pub fn extract_new_member(arg: Option<Value>) -> Result<Value, String> {
+		normalize_member(Self::extract(arg)?)
+	}
+
 	pub fn extract_buffer(arg: Option<Value>) -> Result<Vec<u8>, String> {
 		match Self::extract(arg)? {
 			Value::Buffer(k) => Ok(k),
@@ -0,0 +1,241 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use indexmap::map::Entry;
+
+use super::container::{Container, ContainerImpl};
+use super::expire::ExpireController;
+use super::bloom::BloomFilter;
+use super::timeseries::TimeSeries;
+
+type Key = super::Key;
+type Value = super::Value;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SnapshotData {
+	String(Vec<u8>),
+	List(Vec<Value>),
+	Set(Vec<Value>),
+	Hash(Vec<(Value, Value)>),
+	Json(serde_json::Value),
+	Bloom(BloomFilter),
+	TimeSeries(TimeSeries),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotEntry {
+	pub key: Key,
+	pub data: SnapshotData,
+	/// Absolute expiration time in milliseconds since UNIX epoch
+	pub expire_at: Option<u64>,
+}
+
+/// Whole dataset in a form independent of the internal containers
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct DatasetSnapshot {
+	pub entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+	/// Drop the current dataset before import
+	Replace,
+	/// Merge into existing keys of the same type; fails if any key has another type
+	Merge,
+}
+
+pub(crate) fn to_millis(tm: SystemTime) -> u64 {
+	tm.duration_since(SystemTime::UNIX_EPOCH).unwrap_or(Duration::new(0, 0)).as_millis() as u64
+}
+
+fn from_millis(millis: u64) -> SystemTime {
+	SystemTime::UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+pub(crate) fn export_container(container: &Container) -> (SnapshotData, Option<SystemTime>) {
+	match container {
+		Container::Strings(c) => (SnapshotData::String(c.inner.clone()), c.expiration_time),
+		Container::List(c) => (SnapshotData::List(c.inner.iter().cloned().collect()), c.expiration_time),
+		Container::Set(c) => (SnapshotData::Set(c.inner.iter().cloned().collect()), c.expiration_time),
+		Container::Hash(c) => (SnapshotData::Hash(c.inner.iter().map(|(f, v)|(f.clone(), v.clone())).collect()), c.expiration_time),
+		Container::Json(c) => (SnapshotData::Json(c.inner.clone()), c.expiration_time),
+		Container::Bloom(c) => (SnapshotData::Bloom(c.inner.clone()), c.expiration_time),
+		Container::TimeSeries(c) => (SnapshotData::TimeSeries(c.inner.clone()), c.expiration_time),
+	}
+}
+
+fn make_impl<Inner: Default>(inner: Inner, expiration_time: Option<SystemTime>) -> ContainerImpl<Inner> {
+	let mut c = ContainerImpl::<Inner>::new();
+	c.inner = inner;
+	c.expiration_time = expiration_time;
+	c
+}
+
+pub(crate) fn import_container(data: SnapshotData, expiration_time: Option<SystemTime>) -> Container {
+	match data {
+		SnapshotData::String(s) => Container::Strings(make_impl(s, expiration_time)),
+		SnapshotData::List(l) => Container::List(make_impl(l.into_iter().collect(), expiration_time)),
+		SnapshotData::Set(s) => Container::Set(make_impl(s.into_iter().collect(), expiration_time)),
+		SnapshotData::Hash(h) => Container::Hash(make_impl(h.into_iter().collect(), expiration_time)),
+		SnapshotData::Json(j) => Container::Json(make_impl(j, expiration_time)),
+		SnapshotData::Bloom(b) => Container::Bloom(make_impl(b, expiration_time)),
+		SnapshotData::TimeSeries(t) => Container::TimeSeries(make_impl(t, expiration_time)),
+	}
+}
+
+/// Format version of the DUMP payload, stored in its first byte
+const DUMP_VERSION: u8 = 1;
+const DUMP_PAYLOAD_ERROR: &str = "ERR DUMP payload version or checksum are wrong";
+
+/// DUMP payload after the version byte
+#[derive(Serialize, Deserialize)]
+struct DumpPayload {
+	data: SnapshotData,
+	/// Remaining time to live in milliseconds
+	ttl: Option<u64>,
+}
+
+/// Serialized value of DUMP with its TTL remaining since `now`
+pub(crate) fn encode_dump(container: &Container, now: SystemTime) -> Result<Vec<u8>, String> {
+	let (data, expiration_time) = export_container(container);
+	let ttl = expiration_time.map(|tm|tm.duration_since(now).unwrap_or(Duration::new(0, 0)).as_millis() as u64);
+	let payload = rmp_serde::to_vec(&DumpPayload {data, ttl}).map_err(|e|format!("Failed to serialize value: {}", e))?;
+
+	let mut out = Vec::with_capacity(1 + payload.len());
+	out.push(DUMP_VERSION);
+	out.extend_from_slice(&payload);
+	Ok(out)
+}
+
+/// Value and remaining TTL in milliseconds from a payload of `encode_dump`
+pub(crate) fn decode_dump(payload: &[u8]) -> Result<(SnapshotData, Option<u64>), String> {
+	match payload.split_first() {
+		Some((&DUMP_VERSION, payload)) => {
+			let payload: DumpPayload = rmp_serde::from_slice(payload).map_err(|_|DUMP_PAYLOAD_ERROR.to_owned())?;
+			Ok((payload.data, payload.ttl))
+		},
+		_ => Err(DUMP_PAYLOAD_ERROR.to_owned()),
+	}
+}
+
+fn same_type(container: &Container, data: &SnapshotData) -> bool {
+	matches!((container, data),
+		(Container::Strings(_), SnapshotData::String(_)) |
+		(Container::List(_), SnapshotData::List(_)) |
+		(Container::Set(_), SnapshotData::Set(_)) |
+		(Container::Hash(_), SnapshotData::Hash(_)) |
+		(Container::Json(_), SnapshotData::Json(_)) |
+		(Container::Bloom(_), SnapshotData::Bloom(_)) |
+		(Container::TimeSeries(_), SnapshotData::TimeSeries(_))
+	)
+}
+
+/// Strings, documents, bloom filters and time series are overwritten, lists appended, sets and hashes united
+fn merge_container(container: &mut Container, data: SnapshotData, expiration_time: Option<SystemTime>) {
+	let expire = match (container, data) {
+		(Container::Strings(c), SnapshotData::String(s)) => {
+			c.inner = s;
+			&mut c.expiration_time
+		},
+		(Container::List(c), SnapshotData::List(l)) => {
+			c.inner.extend(l);
+			&mut c.expiration_time
+		},
+		(Container::Set(c), SnapshotData::Set(s)) => {
+			c.inner.extend(s);
+			&mut c.expiration_time
+		},
+		(Container::Hash(c), SnapshotData::Hash(h)) => {
+			c.inner.extend(h);
+			&mut c.expiration_time
+		},
+		(Container::Json(c), SnapshotData::Json(j)) => {
+			c.inner = j;
+			&mut c.expiration_time
+		},
+		(Container::Bloom(c), SnapshotData::Bloom(b)) => {
+			c.inner = b;
+			&mut c.expiration_time
+		},
+		(Container::TimeSeries(c), SnapshotData::TimeSeries(t)) => {
+			c.inner = t;
+			&mut c.expiration_time
+		},
+		_ => unreachable!("types are checked before merge"),
+	};
+	if expiration_time.is_some() {
+		*expire = expiration_time;
+	}
+}
+
+impl super::Storage {
+	/// Entries already expired are skipped; expirations are registered in the expire controller
+	pub async fn import(&self, snapshot: DatasetSnapshot, mode: ImportMode) -> Result<(), String> {
+		let now = self.clock.now();
+		let mut expirations = Vec::new();
+		let mut containers = self.containers.lock().await;
+
+		if mode == ImportMode::Merge {
+			let mut conflicts = Vec::new();
+			for entry in &snapshot.entries {
+				if let Some(container) = containers.get(&entry.key[..]) {
+					if ! same_type(&*container.read().await, &entry.data) {
+						conflicts.push(String::from_utf8_lossy(&entry.key).into_owned());
+					}
+				}
+			}
+			if ! conflicts.is_empty() {
+				return Err(format!("Type conflicts for keys: {}", conflicts.join(", ")));
+			}
+		} else {
+			containers.clear();
+			*self.expire_controller.lock().await = ExpireController::new();
+		}
+
+		for entry in snapshot.entries {
+			let expiration_time = entry.expire_at.map(|millis|self.clock.from_wall(from_millis(millis)));
+			if let Some(tm) = expiration_time {
+				if tm <= now {
+					continue;
+				}
+				expirations.push((entry.key.clone(), tm));
+			}
+			match containers.entry(entry.key.into()) {
+				Entry::Vacant(e) => {
+					let container = import_container(entry.data, expiration_time);
+					self.access_touch(&container);
+					e.insert(Self::make_container(container));
+				},
+				Entry::Occupied(e) => {
+					let mut container = e.get().write().await;
+					self.snapshot_preserve(e.get(), &container);
+					merge_container(&mut container, entry.data, expiration_time);
+				},
+			}
+		}
+		drop(containers);
+		self.memory_recount().await;
+
+		for (key, tm) in expirations {
+			self.expire_key_at(&key, tm).await;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,435 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+
+//! JSON documents modeled on RedisJSON.
+//!
+//! Paths are a minimal JSONPath subset: the root `$` (or `.`) followed by `.field`,
+//! `["field"]` and `[index]` steps, negative indexes count from the end. Legacy paths
+//! without the root, like `a.b[0]`, are relative to the root. A path selects at most one value.
+
+use indexmap::map::Entry;
+
+use super::container::Container;
+use super::container::WRONG_TYPE_ERROR;
+use super::container::ContainerImpl;
+use super::commands::wrong_arity;
+
+type Json = serde_json::Value;
+type Value = super::Value;
+type Arguments = super::Arguments;
+type ExecResult = super::ExecResult;
+
+type Inner = Json;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+	Field(String),
+	Index(i64),
+}
+
+fn invalid_path(path: &str) -> String {
+	format!("ERR invalid JSON path '{}'", path)
+}
+
+fn missing_path(path: &str) -> String {
+	format!("ERR Path '{}' does not exist", path)
+}
+
+fn parse_path(path: &str) -> Result<Vec<Step>, String> {
+	let rest = match path {
+		"$" | "." => return Ok(Vec::new()),
+		path if path.starts_with('$') => &path[1..],
+		path if path.starts_with('.') || path.starts_with('[') => path,
+		path => return parse_path(&format!(".{}", path)),
+	};
+	let mut steps = Vec::new();
+	let mut chars = rest.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'.' => {
+				let mut field = String::new();
+				while let Some(&c) = chars.peek() {
+					if c == '.' || c == '[' {
+						break;
+					}
+					field.push(c);
+					chars.next();
+				}
+				if field.is_empty() {
+					return Err(invalid_path(path));
+				}
+				steps.push(Step::Field(field));
+			},
+			'[' => {
+				let mut inside = String::new();
+				let mut quote = None;
+				loop {
+					match (chars.next(), quote) {
+						(None, _) => return Err(invalid_path(path)),
+						(Some(']'), None) => break,
+						(Some(c), None) if inside.is_empty() && (c == '"' || c == '\'') => quote = Some(c),
+						(Some(c), Some(q)) if c == q => {
+							if chars.next() != Some(']') {
+								return Err(invalid_path(path));
+							}
+							break;
+						},
+						(Some(c), _) => inside.push(c),
+					}
+				}
+				let step = match quote {
+					Some(_) => Step::Field(inside),
+					None => Step::Index(inside.trim().parse::<i64>().map_err(|_|invalid_path(path))?),
+				};
+				steps.push(step);
+			},
+			_ => return Err(invalid_path(path)),
+		}
+	}
+	Ok(steps)
+}
+
+fn array_index(len: usize, index: i64) -> Option<usize> {
+	let index = if index < 0 {len as i64 + index} else {index};
+	if index >= 0 && (index as usize) < len {Some(index as usize)} else {None}
+}
+
+fn resolve<'a>(mut doc: &'a Json, steps: &[Step]) -> Option<&'a Json> {
+	for step in steps {
+		doc = match (step, doc) {
+			(Step::Field(field), Json::Object(object)) => object.get(field)?,
+			(Step::Index(index), Json::Array(array)) => &array[array_index(array.len(), *index)?],
+			_ => return None,
+		};
+	}
+	Some(doc)
+}
+
+fn resolve_mut<'a>(mut doc: &'a mut Json, steps: &[Step]) -> Option<&'a mut Json> {
+	for step in steps {
+		doc = match (step, doc) {
+			(Step::Field(field), Json::Object(object)) => object.get_mut(field)?,
+			(Step::Index(index), Json::Array(array)) => {
+				let index = array_index(array.len(), *index)?;
+				&mut array[index]
+			},
+			_ => return None,
+		};
+	}
+	Some(doc)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SetCondition {
+	Always,
+	/// NX: only if the path does not exist
+	Missing,
+	/// XX: only if the path exists
+	Existing,
+}
+
+impl SetCondition {
+	fn allows(self, exists: bool) -> bool {
+		match self {
+			SetCondition::Always => true,
+			SetCondition::Missing => !exists,
+			SetCondition::Existing => exists,
+		}
+	}
+}
+
+/// Returns false if nothing was set: the condition failed or the parent does not exist
+fn set_path(doc: &mut Json, steps: &[Step], value: Json, condition: SetCondition) -> Result<bool, String> {
+	let (last, parent) = match steps.split_last() {
+		None => {
+			if !condition.allows(true) {
+				return Ok(false);
+			}
+			*doc = value;
+			return Ok(true);
+		},
+		Some(split) => split,
+	};
+	match (last, resolve_mut(doc, parent)) {
+		(Step::Field(field), Some(Json::Object(object))) => {
+			if !condition.allows(object.contains_key(field)) {
+				return Ok(false);
+			}
+			object.insert(field.clone(), value);
+			Ok(true)
+		},
+		(Step::Index(index), Some(Json::Array(array))) => match array_index(array.len(), *index) {
+			Some(index) if condition.allows(true) => {
+				array[index] = value;
+				Ok(true)
+			},
+			Some(_) => Ok(false),
+			None if condition == SetCondition::Existing => Ok(false),
+			None => Err(format!("ERR array index {} is out of range", index)),
+		},
+		(_, None) => Ok(false),
+		(_, Some(_)) => Err(WRONG_TYPE_ERROR.to_owned()),
+	}
+}
+
+fn delete_path(doc: &mut Json, steps: &[Step]) -> bool {
+	let (last, parent) = match steps.split_last() {
+		None => return false,
+		Some(split) => split,
+	};
+	match (last, resolve_mut(doc, parent)) {
+		(Step::Field(field), Some(Json::Object(object))) => object.remove(field).is_some(),
+		(Step::Index(index), Some(Json::Array(array))) => match array_index(array.len(), *index) {
+			Some(index) => {
+				array.remove(index);
+				true
+			},
+			None => false,
+		},
+		_ => false,
+	}
+}
+
+/// JSON text; numbers, booleans and nil sent as such are taken as the JSON scalars
+fn parse_json(arg: Option<Value>) -> Result<Json, String> {
+	match super::Storage::extract(arg)? {
+		Value::Buffer(text) => serde_json::from_slice(&text).map_err(|e|format!("ERR invalid JSON: {}", e)),
+		Value::Integer(i) => Ok(Json::from(i)),
+		Value::Float(n) => serde_json::Number::from_f64(f64::from_bits(n)).map(Json::Number).ok_or_else(||"ERR number is not finite".to_owned()),
+		Value::Bool(b) => Ok(Json::Bool(b)),
+		Value::Nill => Ok(Json::Null),
+		_ => Err("ERR JSON text expected".to_owned()),
+	}
+}
+
+fn to_reply(json: &Json) -> Value {
+	Value::Buffer(json.to_string().into_bytes())
+}
+
+/// Integers stay integers while the sum fits into i64
+fn add_number(current: &serde_json::Number, increment: &Json) -> Result<Json, String> {
+	if let (Some(a), Some(b)) = (current.as_i64(), increment.as_i64()) {
+		if let Some(sum) = a.checked_add(b) {
+			return Ok(Json::from(sum));
+		}
+	}
+	let sum = current.as_f64().unwrap_or(0.0) + increment.as_f64().ok_or("ERR increment is not a number")?;
+	serde_json::Number::from_f64(sum)
+		.map(Json::Number)
+		.ok_or_else(||format!("ERR result {} is not a finite number", sum))
+}
+
+impl super::Storage {
+	fn document_unwrap_container(container: &Container) -> Result<&ContainerImpl<Inner>, String> {
+		match container {
+			Container::Json(ref c) => Ok(c),
+			_ => Err(WRONG_TYPE_ERROR.to_owned()),
+		}
+	}
+	fn document_unwrap_mut_container(container: &mut Container) -> Result<&mut ContainerImpl<Inner>, String> {
+		match container {
+			Container::Json(ref mut c) => Ok(c),
+			_ => Err(WRONG_TYPE_ERROR.to_owned()),
+		}
+	}
+	/// Missing key gives Nill without creating it
+	async fn document_lock<F: FnOnce(&Inner) -> ExecResult>(&self, key: &super::Key, processor: F) -> ExecResult {
+		match self.try_get_live_container(key).await {
+			None => Ok(Value::Nill),
+			Some(c1) => {
+				let c2 = c1.read().await;
+				self.access_touch(&c2);
+				let c3 = Self::document_unwrap_container(&c2)?;
+				processor(&c3.inner)
+			},
+		}
+	}
+	async fn document_lock_mut<F: FnOnce(&mut Inner) -> ExecResult>(&self, key: &super::Key, processor: F) -> ExecResult {
+		match self.try_get_live_container(key).await {
+			None => Ok(Value::Nill),
+			Some(c1) => {
+				let mut c2 = c1.write().await;
+				self.snapshot_preserve(&c1, &c2);
+				self.access_touch(&c2);
+				let c3 = Self::document_unwrap_mut_container(&mut c2)?;
+				let result = processor(&mut c3.inner);
+				self.memory_track(&mut c2);
+				result
+			},
+		}
+	}
+
+	/// JSON.SET key path value [NX|XX]; a new key can only be created at the root
+	pub async fn document_set(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let path = Self::extract_string(args.pop_front())?;
+		let steps = parse_path(&path)?;
+		let limits = self.limits();
+		limits.check_elements(&args)?;
+		let value = parse_json(args.pop_front())?;
+		let condition = match args.pop_front() {
+			None => SetCondition::Always,
+			Some(arg) => match &Self::extract_string(Some(arg))?.to_uppercase()[..] {
+				"NX" => SetCondition::Missing,
+				"XX" => SetCondition::Existing,
+				option => return Err(format!("ERR unexpected option '{}'", option)),
+			},
+		};
+		if !args.is_empty() {
+			return Err(wrong_arity("JSON.SET"));
+		}
+
+		let mut containers = self.containers.lock().await;
+		self.reap_expired(&mut containers, &key).await;
+		match containers.entry(key[..].into()) {
+			Entry::Vacant(e) => {
+				if !steps.is_empty() {
+					return Err("ERR new objects must be created at the root".to_owned());
+				}
+				if !condition.allows(false) {
+					return Ok(Value::Nill);
+				}
+				let mut cnt = ContainerImpl::<Inner>::new();
+				cnt.inner = value;
+				let mut cnt = Container::Json(cnt);
+				self.memory_track_insert(&key, &mut cnt);
+				self.access_touch(&cnt);
+				e.insert(Self::make_container(cnt));
+				Ok(Value::Ok)
+			},
+			Entry::Occupied(e) => {
+				let c1 = e.get().clone();
+				drop(containers);
+				let mut c2 = c1.write().await;
+				self.snapshot_preserve(&c1, &c2);
+				self.access_touch(&c2);
+				let c3 = Self::document_unwrap_mut_container(&mut c2)?;
+				let done = set_path(&mut c3.inner, &steps, value, condition)?;
+				self.memory_track(&mut c2);
+				Ok(if done {Value::Ok} else {Value::Nill})
+			},
+		}
+	}
+
+	/// JSON.GET key [path ...]; several paths give an object keyed by the paths
+	pub async fn document_get(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let paths = args.into_iter()
+			.map(|arg|Self::extract_string(Some(arg)))
+			.collect::<Result<Vec<String>, String>>()?;
+		let steps = paths.iter()
+			.map(|path|parse_path(path))
+			.collect::<Result<Vec<Vec<Step>>, String>>()?;
+		self.document_lock(&key, |doc| -> ExecResult {
+			match paths.len() {
+				0 => Ok(to_reply(doc)),
+				1 => resolve(doc, &steps[0]).map(to_reply).ok_or_else(||missing_path(&paths[0])),
+				_ => {
+					let mut out = serde_json::Map::new();
+					for (path, steps) in paths.iter().zip(steps.iter()) {
+						let value = resolve(doc, steps).ok_or_else(||missing_path(path))?;
+						out.insert(path.clone(), value.clone());
+					}
+					Ok(to_reply(&Json::Object(out)))
+				},
+			}
+		}).await
+	}
+
+	/// JSON.DEL key [path]; deleting the root removes the key
+	pub async fn document_del(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let path = match args.pop_front() {
+			None => "$".to_owned(),
+			Some(arg) => Self::extract_string(Some(arg))?,
+		};
+		let steps = parse_path(&path)?;
+		if steps.is_empty() {
+			let mut containers = self.containers.lock().await;
+			self.reap_expired(&mut containers, &key).await;
+			let removed = match containers.get(&key[..]) {
+				None => false,
+				Some(c) => {
+					Self::document_unwrap_container(&*c.read().await)?;
+					true
+				},
+			};
+			if removed {
+				let c = containers.remove(&key[..]).expect("key is checked above");
+				self.memory_track_remove(&key, &*c.read().await);
+			}
+			return Ok(Value::Integer(removed as i64));
+		}
+		let result = self.document_lock_mut(&key, |doc| -> ExecResult {
+			Ok(Value::Integer(delete_path(doc, &steps) as i64))
+		}).await?;
+		match result {
+			Value::Nill => Ok(Value::Integer(0)),
+			result => Ok(result),
+		}
+	}
+
+	/// JSON.NUMINCRBY key path number; returns the new number
+	pub async fn document_numincrby(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let path = Self::extract_string(args.pop_front())?;
+		let steps = parse_path(&path)?;
+		let increment = parse_json(args.pop_front())?;
+		if !increment.is_number() {
+			return Err("ERR increment is not a number".to_owned());
+		}
+		self.document_lock_mut(&key, |doc| -> ExecResult {
+			match resolve_mut(doc, &steps) {
+				None => Err(missing_path(&path)),
+				Some(Json::Number(current)) => {
+					let sum = add_number(current, &increment)?;
+					let reply = to_reply(&sum);
+					*current = match sum {
+						Json::Number(n) => n,
+						_ => unreachable!("sum of numbers is a number"),
+					};
+					Ok(reply)
+				},
+				Some(_) => Err(format!("ERR value at path '{}' is not a number", path)),
+			}
+		}).await
+	}
+
+	/// JSON.ARRAPPEND key path value [value ...]; returns the new length of the array
+	pub async fn document_arrappend(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let path = Self::extract_string(args.pop_front())?;
+		let steps = parse_path(&path)?;
+		let limits = self.limits();
+		limits.check_elements(&args)?;
+		let values = args.into_iter()
+			.map(|arg|parse_json(Some(arg)))
+			.collect::<Result<Vec<Json>, String>>()?;
+		self.document_lock_mut(&key, |doc| -> ExecResult {
+			match resolve_mut(doc, &steps) {
+				None => Err(missing_path(&path)),
+				Some(Json::Array(array)) => {
+					limits.check_collection_len(array.len() + values.len())?;
+					array.extend(values);
+					Ok(Value::Integer(array.len() as i64))
+				},
+				Some(_) => Err(format!("ERR value at path '{}' is not an array", path)),
+			}
+		}).await
+	}
+}
@@ -0,0 +1,222 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Typed API for using Storage as an embedded store.
+//! Every method is a thin wrapper over the command handler, without the string dispatch.
+//!
+//! ```
+//! use std::time::Duration;
+//! use radish_database::{Storage, StorageError};
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! let storage = Storage::new();
+//! storage.set(b"greeting", b"hello", Some(Duration::from_secs(60))).await?;
+//! assert_eq!(storage.get(b"greeting").await?, Some(b"hello".to_vec()));
+//! assert!(storage.ttl(b"greeting").await?.unwrap() <= Duration::from_secs(60));
+//!
+//! storage.list_push_back(b"queue", &[b"a", b"b"]).await?;
+//! assert_eq!(storage.list_range(b"queue", 0, 1).await?, vec![b"a".to_vec(), b"b".to_vec()]);
+//!
+//! assert_eq!(storage.incr_by(b"queue", 1).await, Err(StorageError::WrongType));
+//! # Ok::<(), StorageError>(())
+//! # }).unwrap();
+//! ```
+
+use std::time::Duration;
+
+use super::container::WRONG_TYPE_ERROR;
+use super::config::READ_ONLY_ERROR;
+
+type Value = super::Value;
+type Arguments = super::Arguments;
+type ExecResult = super::ExecResult;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+	/// Key holds a value of another type
+	WrongType,
+	/// Storage is in read-only mode
+	ReadOnly,
+	/// Any other failure reported by the handler
+	Failed(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Error::WrongType => write!(f, "{}", WRONG_TYPE_ERROR),
+			Error::ReadOnly => write!(f, "{}", READ_ONLY_ERROR),
+			Error::Failed(err) => write!(f, "{}", err),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+	fn from(err: String) -> Self {
+		if err == WRONG_TYPE_ERROR {
+			Error::WrongType
+		} else if err == READ_ONLY_ERROR {
+			Error::ReadOnly
+		} else {
+			Error::Failed(err)
+		}
+	}
+}
+
+fn buffer(value: &[u8]) -> Value {
+	Value::Buffer(value.to_vec())
+}
+
+fn args(values: Vec<Value>) -> Arguments {
+	values.into_iter().collect()
+}
+
+fn unexpected(value: Value) -> Error {
+	Error::Failed(format!("Unexpected result: {:?}", value))
+}
+
+fn to_integer(result: ExecResult) -> Result<i64> {
+	match result? {
+		Value::Integer(i) => Ok(i),
+		value => Err(unexpected(value)),
+	}
+}
+
+fn to_bool(result: ExecResult) -> Result<bool> {
+	match result? {
+		Value::Bool(b) => Ok(b),
+		Value::Integer(i) => Ok(i != 0),
+		value => Err(unexpected(value)),
+	}
+}
+
+fn to_buffer(value: Value) -> Result<Vec<u8>> {
+	match value {
+		Value::Buffer(b) => Ok(b),
+		Value::Integer(i) => Ok(i.to_string().into_bytes()),
+		value => Err(unexpected(value)),
+	}
+}
+
+fn to_optional_buffer(result: ExecResult) -> Result<Option<Vec<u8>>> {
+	match result? {
+		Value::Nill => Ok(None),
+		value => to_buffer(value).map(Some),
+	}
+}
+
+fn to_buffers(result: ExecResult) -> Result<Vec<Vec<u8>>> {
+	match result? {
+		Value::Array(values) => values.into_iter().map(to_buffer).collect(),
+		value => Err(unexpected(value)),
+	}
+}
+
+fn to_unit(result: ExecResult) -> Result<()> {
+	result?;
+	Ok(())
+}
+
+impl super::Storage {
+	/// Mutators skip the dispatch, so they repeat its checks
+	fn check_writable(&self, key: &[u8]) -> Result<()> {
+		if self.config.read_only() {
+			return Err(Error::ReadOnly);
+		}
+		self.limits().check_key(key)?;
+		Ok(())
+	}
+
+	pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		to_optional_buffer(self.strings_get(args(vec![buffer(key)])).await)
+	}
+
+	/// Sets the value and replaces or removes the time to live
+	pub async fn set(&self, key: &[u8], value: &[u8], ttl: Option<Duration>) -> Result<()> {
+		self.check_writable(key)?;
+		let mut arguments = vec![buffer(key), buffer(value)];
+		if let Some(ttl) = ttl {
+			arguments.push(buffer(b"PX"));
+			arguments.push(Value::Integer(ttl.as_millis() as i64));
+		}
+		to_unit(self.strings_set(args(arguments)).await)
+	}
+
+	/// Returns true if the key existed
+	pub async fn del(&self, key: &[u8]) -> Result<bool> {
+		self.check_writable(key)?;
+		to_bool(self.keys_del(args(vec![buffer(key)])).await)
+	}
+
+	pub async fn incr_by(&self, key: &[u8], increment: i64) -> Result<i64> {
+		self.check_writable(key)?;
+		to_integer(self.strings_incrby(args(vec![buffer(key), Value::Integer(increment)])).await)
+	}
+
+	/// Returns the length of the list after the push
+	pub async fn list_push_back(&self, key: &[u8], values: &[&[u8]]) -> Result<usize> {
+		self.check_writable(key)?;
+		let mut arguments = vec![buffer(key)];
+		arguments.extend(values.iter().map(|v|buffer(v)));
+		to_integer(self.list_rpush(args(arguments)).await).map(|len|len as usize)
+	}
+
+	/// Elements from `start` to `stop` inclusive
+	pub async fn list_range(&self, key: &[u8], start: usize, stop: usize) -> Result<Vec<Vec<u8>>> {
+		to_buffers(self.list_lrange(args(vec![buffer(key), Value::Integer(start as i64), Value::Integer(stop as i64)])).await)
+	}
+
+	/// Returns the count of added members
+	pub async fn set_add(&self, key: &[u8], members: &[&[u8]]) -> Result<usize> {
+		self.check_writable(key)?;
+		let mut arguments = vec![buffer(key)];
+		arguments.extend(members.iter().map(|v|buffer(v)));
+		to_integer(self.set_sadd(args(arguments)).await).map(|count|count as usize)
+	}
+
+	pub async fn set_members(&self, key: &[u8]) -> Result<Vec<Vec<u8>>> {
+		to_buffers(self.set_smembers(args(vec![buffer(key)])).await)
+	}
+
+	pub async fn hash_set(&self, key: &[u8], field: &[u8], value: &[u8]) -> Result<()> {
+		self.check_writable(key)?;
+		to_unit(self.hash_hset(args(vec![buffer(key), buffer(field), buffer(value)])).await)
+	}
+
+	pub async fn hash_get(&self, key: &[u8], field: &[u8]) -> Result<Option<Vec<u8>>> {
+		to_optional_buffer(self.hash_hget(args(vec![buffer(key), buffer(field)])).await)
+	}
+
+	/// Returns false if the key does not exist
+	pub async fn expire(&self, key: &[u8], ttl: Duration) -> Result<bool> {
+		self.check_writable(key)?;
+		to_bool(self.keys_pexpire(args(vec![buffer(key), Value::Integer(ttl.as_millis() as i64)])).await)
+	}
+
+	/// Remaining time to live; None if the key does not exist or has no expiration
+	pub async fn ttl(&self, key: &[u8]) -> Result<Option<Duration>> {
+		match to_integer(self.keys_pttl(args(vec![buffer(key)])).await)? {
+			ms if ms < 0 => Ok(None),
+			ms => Ok(Some(Duration::from_millis(ms as u64))),
+		}
+	}
+}
+
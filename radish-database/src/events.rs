@@ -0,0 +1,260 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Key events for embedders: every successful write command and every expiration is
+//! published to the subscribers whose glob pattern matches the key.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use tokio::sync::Notify;
+
+use super::commands::CommandSpec;
+use super::glob::glob_match;
+
+type CompactKey = super::CompactKey;
+type Value = super::Value;
+type Arguments = super::Arguments;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyEventKind {
+	Set,
+	Del,
+	Expire,
+	Expired,
+	/// The key was renamed away; the event of the destination is `RenameTo`
+	RenameFrom,
+	RenameTo,
+	Incr,
+	Append,
+	Push,
+	Pop,
+	Hset,
+	Hdel,
+	Sadd,
+	Srem,
+	/// Any other write command, by its lower case name
+	Other(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyEvent {
+	pub key: CompactKey,
+	pub event: KeyEventKind,
+	pub at: SystemTime,
+}
+
+fn event_kind(command: &'static str, position: usize) -> KeyEventKind {
+	match command {
+		"SET" | "SETEX" | "PSETEX" | "SETNX" | "MSET" | "GETSET" | "SETRANGE" | "SETBIT" | "COPY" => KeyEventKind::Set,
+		"DEL" => KeyEventKind::Del,
+		"RENAME" if position == 0 => KeyEventKind::RenameFrom,
+		"RENAME" => KeyEventKind::RenameTo,
+		"INCR" | "DECR" | "INCRBY" | "DECRBY" | "INCRBYFLOAT" => KeyEventKind::Incr,
+		"APPEND" => KeyEventKind::Append,
+		"LPUSH" | "RPUSH" | "LPUSHX" | "RPUSHX" | "LINSERT" => KeyEventKind::Push,
+		"LPOP" | "RPOP" | "BLPOP" | "BRPOP" => KeyEventKind::Pop,
+		"HSET" | "HSETNX" | "HINCRBY" | "HINCRBYFLOAT" => KeyEventKind::Hset,
+		"HDEL" => KeyEventKind::Hdel,
+		"SADD" => KeyEventKind::Sadd,
+		"SREM" | "SPOP" => KeyEventKind::Srem,
+		command => KeyEventKind::Other(command),
+	}
+}
+
+/// Replies which tell that nothing was changed, e.g. DEL of missing keys or SETNX of an existing one;
+/// zero is a counter value for the increments
+fn is_noop(command: &str, reply: &Value) -> bool {
+	match reply {
+		Value::Nill | Value::Bool(false) => true,
+		Value::Integer(0) => !matches!(command, "INCR" | "DECR" | "INCRBY" | "DECRBY" | "HINCRBY"),
+		_ => false,
+	}
+}
+
+struct Queue {
+	events: Mutex<VecDeque<KeyEvent>>,
+	capacity: usize,
+	dropped: AtomicU64,
+	notify: Notify,
+	closed: AtomicBool,
+}
+
+impl Queue {
+	/// The oldest event is dropped if the queue is full, so a slow subscriber sees the latest changes
+	fn push(&self, event: KeyEvent) {
+		let mut events = self.events.lock().unwrap();
+		if events.len() >= self.capacity {
+			events.pop_front();
+			self.dropped.fetch_add(1, Ordering::Relaxed);
+		}
+		events.push_back(event);
+		drop(events);
+		self.notify.notify();
+	}
+}
+
+/// Receiving end of `Storage::subscribe_events`; dropping it unsubscribes
+pub struct KeyEventReceiver {
+	queue: Arc<Queue>,
+}
+
+impl KeyEventReceiver {
+	/// Waits for the next event
+	pub async fn recv(&mut self) -> KeyEvent {
+		loop {
+			if let Some(event) = self.try_recv() {
+				return event;
+			}
+			self.queue.notify.notified().await;
+		}
+	}
+
+	pub fn try_recv(&mut self) -> Option<KeyEvent> {
+		self.queue.events.lock().unwrap().pop_front()
+	}
+
+	/// Count of events dropped because the queue was full
+	pub fn dropped(&self) -> u64 {
+		self.queue.dropped.load(Ordering::Relaxed)
+	}
+}
+
+impl Drop for KeyEventReceiver {
+	fn drop(&mut self) {
+		self.queue.closed.store(true, Ordering::Relaxed);
+	}
+}
+
+struct Subscriber {
+	pattern: Option<Vec<u8>>,
+	queue: Arc<Queue>,
+}
+
+pub struct EventBus {
+	subscribers: RwLock<Vec<Subscriber>>,
+	/// Checked before any work, so publishing costs one load without subscribers
+	active: AtomicBool,
+}
+
+impl EventBus {
+	pub fn new() -> Self {
+		Self {
+			subscribers: RwLock::new(Vec::new()),
+			active: AtomicBool::new(false),
+		}
+	}
+
+	pub fn is_active(&self) -> bool {
+		self.active.load(Ordering::Relaxed)
+	}
+
+	fn subscribe(&self, pattern: Option<Vec<u8>>, capacity: usize) -> KeyEventReceiver {
+		let queue = Arc::new(Queue {
+			events: Mutex::new(VecDeque::new()),
+			capacity: std::cmp::max(capacity, 1),
+			dropped: AtomicU64::new(0),
+			notify: Notify::new(),
+			closed: AtomicBool::new(false),
+		});
+		let mut subscribers = self.subscribers.write().unwrap();
+		subscribers.push(Subscriber {
+			pattern,
+			queue: queue.clone(),
+		});
+		self.active.store(true, Ordering::Relaxed);
+		KeyEventReceiver {queue}
+	}
+
+	pub fn publish(&self, key: &[u8], event: KeyEventKind) {
+		if !self.is_active() {
+			return;
+		}
+		let at = SystemTime::now();
+		let key = CompactKey::from(key);
+		let mut closed = false;
+		for subscriber in self.subscribers.read().unwrap().iter() {
+			if subscriber.queue.closed.load(Ordering::Relaxed) {
+				closed = true;
+				continue;
+			}
+			if let Some(pattern) = &subscriber.pattern {
+				if !glob_match(pattern, &key) {
+					continue;
+				}
+			}
+			subscriber.queue.push(KeyEvent {
+				key: key.clone(),
+				event: event.clone(),
+				at,
+			});
+		}
+		if closed {
+			let mut subscribers = self.subscribers.write().unwrap();
+			subscribers.retain(|subscriber|!subscriber.queue.closed.load(Ordering::Relaxed));
+			self.active.store(!subscribers.is_empty(), Ordering::Relaxed);
+		}
+	}
+
+	/// Keys of a write command, taken before the arguments are passed to the handler
+	pub fn command_keys(&self, spec: &CommandSpec, args: &Arguments) -> Option<Vec<CompactKey>> {
+		if !self.is_active() || !spec.writes(args) {
+			return None;
+		}
+		let keys = spec.key_indices(args.len())
+			.into_iter()
+			.filter_map(|i| match args.get(i) {
+				Some(Value::Buffer(key)) => Some(CompactKey::from(key)),
+				_ => None,
+			})
+			.collect();
+		Some(keys)
+	}
+
+	/// Publishes an event for each key of a write command which changed something
+	pub fn publish_command(&self, spec: &CommandSpec, keys: Vec<CompactKey>, reply: &Value) {
+		if is_noop(spec.name, reply) {
+			return;
+		}
+		// a deadline which is already due deletes the key, so they publish their own event
+		if matches!(spec.name, "EXPIRE" | "EXPIREAT" | "PEXPIRE" | "PEXPIREAT") {
+			return;
+		}
+		// a blocking pop changes only the list named in its reply
+		let keys = match (spec.name, reply) {
+			("BLPOP" | "BRPOP", Value::Array(items)) => match items.front() {
+				Some(Value::Buffer(key)) => vec![CompactKey::from(key)],
+				_ => return,
+			},
+			_ => keys,
+		};
+		for (position, key) in keys.iter().enumerate() {
+			self.publish(key, event_kind(spec.name, position));
+		}
+	}
+}
+
+impl super::Storage {
+	/// Subscribes to the events of keys matching the glob `pattern`, all keys if None.
+	/// At most `capacity` events are queued; when it is exceeded the oldest event is
+	/// dropped and counted in `KeyEventReceiver::dropped`
+	pub fn subscribe_events(&self, pattern: Option<&[u8]>, capacity: usize) -> KeyEventReceiver {
+		self.events.subscribe(pattern.map(|p|p.to_vec()), capacity)
+	}
+}
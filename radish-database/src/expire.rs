@@ -14,13 +14,20 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::time::{SystemTime, Duration};
-use std::collections::{BTreeMap, HashSet};
+use std::ops::Bound;
+use std::time::{SystemTime, Duration, UNIX_EPOCH};
+use std::collections::{BTreeMap, HashSet, VecDeque};
 
-type Key = super::Key;
+type CompactKey = super::CompactKey;
+type Value = super::Value;
+type Arguments = super::Arguments;
+type ExecResult = super::ExecResult;
+
+/// Queue entries are checked in batches of at least this number of keys
+const QUEUE_BATCH: usize = 128;
 
 pub struct ExpireController {
-	expires_queue: BTreeMap<SystemTime, HashSet<Key>>,
+	expires_queue: BTreeMap<SystemTime, HashSet<CompactKey>>,
 }
 
 impl ExpireController {
@@ -30,35 +37,78 @@ impl ExpireController {
 		}
 	}
 
-	pub fn pop_now_and_expired_keys(&mut self) -> (SystemTime, HashSet<Key>) {
-		let pivot = SystemTime::now() + Duration::from_micros(1);
-		let tail = self.expires_queue.split_off(&pivot);
-		let mut expireds = std::mem::replace(&mut self.expires_queue, tail);
-		log::debug!("{:?} && {:?}", expireds, self.expires_queue);
-
-		let mut times = expireds.keys().cloned().collect::<Vec<SystemTime>>();
-		let mut out_keys = HashSet::new();
-		while let Some(time) = times.pop() {
-			if let Some(keys) = expireds.remove(&time) {
-				for key in keys {
-					out_keys.insert(key);
-				}
+	/// Takes up to `limit` keys with deadlines up to `now`, the earliest first.
+	/// Returns the time the keys were checked against and the earliest deadline left in the queue,
+	/// which is in the past if the limit cut the batch.
+	pub fn pop_expired_keys(&mut self, now: SystemTime, limit: usize) -> (SystemTime, Vec<CompactKey>, Option<SystemTime>) {
+		let pivot = now + Duration::from_micros(1);
+		let mut out_keys = Vec::new();
+		while out_keys.len() < limit {
+			let time = match self.expires_queue.keys().next() {
+				Some(time) if *time < pivot => *time,
+				_ => break,
+			};
+			let keys = self.expires_queue.get_mut(&time).expect("slot was just found");
+			let take = std::cmp::min(limit - out_keys.len(), keys.len());
+			let taken = keys.iter().take(take).cloned().collect::<Vec<CompactKey>>();
+			for key in &taken {
+				keys.remove(key);
 			}
+			if keys.is_empty() {
+				self.expires_queue.remove(&time);
+			}
+			out_keys.extend(taken);
 		}
-		(pivot, out_keys)
+		(pivot, out_keys, self.expires_queue.keys().next().cloned())
 	}
 
-	pub fn expire_key_at(&mut self, key: &Key, timepoint: SystemTime) {
+	/// Count of queued keys with deadlines up to `now`, including stale entries
+	pub fn pending_expired(&self, now: SystemTime) -> usize {
+		self.expires_queue
+			.range(..=now)
+			.map(|(_, keys)|keys.len())
+			.sum()
+	}
+
+	pub fn expire_key_at(&mut self, key: &[u8], timepoint: SystemTime) {
 		log::debug!("{:?}: will expired at {:?}", key, timepoint);
 
 		let keys = self.expires_queue.entry(timepoint).or_insert_with(||HashSet::new());
-		keys.insert(key.clone());
+		keys.insert(CompactKey::from(key));
+	}
+
+	/// Drops the entry of a key removed before its deadline
+	pub fn forget(&mut self, key: &[u8], timepoint: SystemTime) {
+		if let Some(keys) = self.expires_queue.get_mut(&timepoint) {
+			keys.remove(key);
+			if keys.is_empty() {
+				self.expires_queue.remove(&timepoint);
+			}
+		}
+	}
+
+	/// Queued keys in deadline order, within the `from` and `until` bounds.
+	/// Whole time slots are returned until at least `limit` keys are collected.
+	/// Entries may be stale: the TTL of a key could be overwritten or removed since queueing.
+	pub fn queued(&self, from: Bound<SystemTime>, until: Bound<SystemTime>, limit: usize) -> Vec<(CompactKey, SystemTime)> {
+		let mut out = Vec::new();
+		for (time, keys) in self.expires_queue.range((from, until)) {
+			if out.len() >= limit {
+				break;
+			}
+			out.extend(keys.iter().map(|key|(key.clone(), *time)));
+		}
+		out
 	}
 }
 
 impl super::Storage {
+	/// Timeline of the expiration deadlines, see `Timeline`
+	pub fn clock(&self) -> &super::Timeline {
+		&self.clock
+	}
 
-	pub async fn expire_key_at(&mut self, key: &Key, timepoint: SystemTime) {
+	pub async fn expire_key_at(&self, key: &[u8], timepoint: SystemTime) {
 		let mut controller = self.expire_controller.lock().await;
 		controller.expire_key_at(key, timepoint);
 		drop(controller);
@@ -68,5 +118,59 @@ impl super::Storage {
 			(*awaker)(timepoint);
 		}
 	}
+
+	/// Keeps the queue entries matching the current deadline of the key
+	async fn expire_actual(&self, queued: Vec<(CompactKey, SystemTime)>) -> Vec<(CompactKey, SystemTime)> {
+		let containers = self.containers.lock().await;
+		let mut out = Vec::with_capacity(queued.len());
+		for (key, time) in queued {
+			if let Some(c) = containers.get(&key[..]) {
+				if Self::get_expiration_time(&*c.read().await) == Some(time) {
+					out.push((key, time));
+				}
+			}
+		}
+		out
+	}
+
+	/// Snapshot of the keys which expire within the duration from now, ordered by deadline.
+	/// Keys which are expired but not removed yet are included.
+	pub async fn expiring_keys(&self, within: Duration) -> Vec<(CompactKey, SystemTime)> {
+		let until = match self.clock.now().checked_add(within) {
+			Some(until) => Bound::Included(until),
+			None => Bound::Unbounded,
+		};
+		let queued = self.expire_controller.lock().await.queued(Bound::Unbounded, until, usize::MAX);
+		self.expire_actual(queued).await
+	}
+
+	/// The nearest deadline among the keys with time to live
+	pub async fn next_expiration(&self) -> Option<SystemTime> {
+		let mut from = Bound::Unbounded;
+		loop {
+			let queued = self.expire_controller.lock().await.queued(from, Bound::Unbounded, QUEUE_BATCH);
+			let last = queued.last()?.1;
+			if let Some((_, time)) = self.expire_actual(queued).await.first() {
+				return Some(*time);
+			}
+			from = Bound::Excluded(last);
+		}
+	}
+
+	pub async fn expire_expiring(&self, mut args: Arguments) -> ExecResult {
+		let seconds = match Self::extract_integer(args.pop_front())? {
+			seconds if seconds < 0 => return Err(format!("Seconds must not be negative, got {}", seconds)),
+			seconds => seconds as u64,
+		};
+		let keys = self.expiring_keys(Duration::from_secs(seconds)).await;
+		let out = keys
+			.into_iter()
+			.map(|(key, time)|{
+				let millis = self.clock.to_wall(time).duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+				Value::Array(VecDeque::from(vec![Value::Buffer(key.into()), Value::Integer(millis as i64)]))
+			})
+			.collect();
+		Ok(Value::Array(out))
+	}
 }
 
@@ -0,0 +1,80 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+/// Glob-style matching used by key filters:
+/// `*` any sequence, `?` any byte, `[abc]`, `[^a-z]` classes and `\` escapes
+pub fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+	let (mut p, mut t) = (0, 0);
+	// position after the last `*` in pattern and the text position it matched up to
+	let mut backtrack: Option<(usize, usize)> = None;
+	while t < text.len() {
+		let step = match pattern.get(p) {
+			Some(b'*') => {
+				backtrack = Some((p + 1, t));
+				p += 1;
+				continue;
+			},
+			Some(b'?') => Some(p + 1),
+			Some(b'[') => match_class(pattern, p, text[t]),
+			Some(b'\\') if p + 1 < pattern.len() => if pattern[p + 1] == text[t] {Some(p + 2)} else {None},
+			Some(&c) => if c == text[t] {Some(p + 1)} else {None},
+			None => None,
+		};
+		match (step, backtrack) {
+			(Some(next), _) => {
+				p = next;
+				t += 1;
+			},
+			(None, Some((star_p, star_t))) => {
+				p = star_p;
+				t = star_t + 1;
+				backtrack = Some((star_p, star_t + 1));
+			},
+			(None, None) => return false,
+		}
+	}
+	pattern[p..].iter().all(|&c|c == b'*')
+}
+
+/// Matches `c` against the class starting at `pattern[start] == '['`;
+/// returns the position after the class on success
+fn match_class(pattern: &[u8], start: usize, c: u8) -> Option<usize> {
+	let mut p = start + 1;
+	let negate = pattern.get(p) == Some(&b'^');
+	if negate {
+		p += 1;
+	}
+	let mut matched = false;
+	while p < pattern.len() && pattern[p] != b']' {
+		if pattern[p] == b'\\' && p + 1 < pattern.len() {
+			matched |= pattern[p + 1] == c;
+			p += 2;
+		} else if p + 2 < pattern.len() && pattern[p + 1] == b'-' && pattern[p + 2] != b']' {
+			let (lo, hi) = if pattern[p] <= pattern[p + 2] {(pattern[p], pattern[p + 2])} else {(pattern[p + 2], pattern[p])};
+			matched |= lo <= c && c <= hi;
+			p += 3;
+		} else {
+			matched |= pattern[p] == c;
+			p += 1;
+		}
+	}
+	if matched != negate {
+		Some(std::cmp::min(p + 1, pattern.len()))
+	} else {
+		None
+	}
+}
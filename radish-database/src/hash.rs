@@ -14,13 +14,14 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 
 use indexmap::IndexMap;
 
-use super::container::Container;
 use super::container::ContainerPtr;
-use super::container::ContainerImpl;
+use super::container::{canonical_member, normalize_member};
+use super::options::ScanOptions;
+use super::commands::wrong_arity;
 
 type Key = super::Key;
 type Value = super::Value;
@@ -30,50 +31,56 @@ type ExecResult = super::ExecResult;
 type Inner = IndexMap<Value, Value>;
 
 impl super::Storage {
-	async fn hash_get_container(&self, key: Key) -> ContainerPtr {
-		self.get_container(key, ||Container::Hash(ContainerImpl::<Inner>::new())).await
+	async fn hash_get_container(&self, key: Key) -> Result<ContainerPtr, String> {
+		self.get_typed_container::<Inner>(key).await
 	}
 	async fn _hash_try_get_container(&self, key: &Key) -> Option<ContainerPtr> {
-		self.try_get_container(key).await
-	}
-	async fn hash_unwrap_container(container: &Container) -> Result<&ContainerImpl<Inner>, String> {
-		match container {
-			Container::Hash(ref c) => Ok(c),
-			_ => Err(format!("Unexpected container type")),
-		}
-	}
-	async fn hash_unwrap_mut_container(container: &mut Container) -> Result<&mut ContainerImpl<Inner>, String> {
-		match container {
-			Container::Hash(ref mut c) => Ok(c),
-			_ => Err(format!("Unexpected container type")),
-		}
+		self.try_get_live_container(key).await
 	}
 	async fn hash_lock<F: FnOnce(&Inner) -> ExecResult>(&self, key: Key, processor: F) -> ExecResult {
-		let c1 = self.hash_get_container(key).await;
-		let c2 = c1.lock().await;
-		let c3 = Self::hash_unwrap_container(&c2).await?;
-		processor(&c3.inner)
+		self.read_container(&key, processor).await
 	}
 	async fn hash_lock_mut<F: FnOnce(&mut Inner) -> ExecResult>(&self, key: Key, processor: F) -> ExecResult {
-		let c1 = self.hash_get_container(key).await;
-		let mut c2 = c1.lock().await;
-		let c3 = Self::hash_unwrap_mut_container(&mut c2).await?;
-		processor(&mut c3.inner)
+		let c1 = self.hash_get_container(key).await?;
+		let mut c2 = c1.write().await;
+		self.snapshot_preserve(&c1, &c2);
+		self.access_touch(&c2);
+		let c3 = c2.typed_mut::<Inner>()?;
+		let result = processor(&mut c3.inner);
+		self.memory_track(&mut c2);
+		result
 	}
 	async fn _hash_try_lock_mut<F: FnOnce(&mut Inner) -> ExecResult>(&self, key: Key, processor: F) -> ExecResult {
 		match self._hash_try_get_container(&key).await {
 			None => Ok(Value::Nill),
 			Some(c1) => {
-				let mut c2 = c1.lock().await;
-				let c3 = Self::hash_unwrap_mut_container(&mut c2).await?;
-				processor(&mut c3.inner)
+				let mut c2 = c1.write().await;
+				self.snapshot_preserve(&c1, &c2);
+				self.access_touch(&c2);
+				let c3 = c2.typed_mut::<Inner>()?;
+				let result = processor(&mut c3.inner);
+				self.memory_track(&mut c2);
+				result
 			}
 		}
 	}
 
-	pub async fn hash_set(&self, mut args: Arguments) -> ExecResult {
+	pub async fn hash_hset(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
+		if args.len() % 2 == 1 {
+			return Err(wrong_arity("HSET"));
+		}
+		let mut args = args.into_iter()
+			.enumerate()
+			.map(|(i, arg)|if i % 2 == 0 {normalize_member(arg)} else {Ok(arg)})
+			.collect::<Result<Arguments, String>>()?;
+		let limits = self.limits();
+		limits.check_elements(&args)?;
 		self.hash_lock_mut(key, |hash| -> ExecResult {
+			if limits.max_collection_elements != 0 {
+				let added = args.iter().step_by(2).filter(|&field|!hash.contains_key(field)).collect::<HashSet<&Value>>();
+				limits.check_collection_len(hash.len() + added.len())?;
+			}
 			let mut count = 0;
 			while args.len() >= 2 {
 				let field = args.pop_front().unwrap();
@@ -87,11 +94,14 @@ impl super::Storage {
 
 	pub async fn hash_set_nx(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
+		let limits = self.limits();
+		limits.check_elements(&args)?;
+		let field = Self::extract_new_member(args.pop_front())?;
+		let value = Self::extract(args.pop_front())?;
 		self.hash_lock_mut(key, |hash| -> ExecResult {
-			let field = args.pop_front().unwrap();
-			let value = args.pop_front().unwrap();
-			if let indexmap::map::Entry::Vacant(place) = hash.entry(field) {
-				place.insert(value);
+			if ! hash.contains_key(&field) {
+				limits.check_collection_len(hash.len() + 1)?;
+				hash.insert(field, value);
 				Ok(Value::Bool(true))
 			} else {
 				Ok(Value::Bool(false))
@@ -103,7 +113,7 @@ impl super::Storage {
 		let key = Self::extract_key(args.pop_front())?;
 		self.hash_lock_mut(key, |hash| -> ExecResult {
 			let mut count = 0;
-			for field in args {
+			for field in args.into_iter().map(canonical_member) {
 				if let Some(_) = hash.remove(&field) {
 					count = count + 1;
 				}
@@ -112,9 +122,9 @@ impl super::Storage {
 		}).await
 	}
 
-	pub async fn hash_get(&self, mut args: Arguments) -> ExecResult {
+	pub async fn hash_hget(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let field = Self::extract(args.pop_front())?;
+		let field = Self::extract_member(args.pop_front())?;
 		self.hash_lock(key, |hash| -> ExecResult {
 			match hash.get(&field) {
 				None => Ok(Value::Nill),
@@ -127,7 +137,7 @@ impl super::Storage {
 		let key = Self::extract_key(args.pop_front())?;
 		self.hash_lock(key, |hash| -> ExecResult {
 			let mut out = VecDeque::with_capacity(args.len());
-			while let Some(field) = args.pop_front() {
+			while let Some(field) = args.pop_front().map(canonical_member) {
 			match hash.get(&field) {
 				None => out.push_back(Value::Nill),
 				Some(value) => out.push_back(value.clone()),
@@ -151,7 +161,7 @@ impl super::Storage {
 
 	pub async fn hash_exists(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let field = Self::extract(args.pop_front())?;
+		let field = Self::extract_member(args.pop_front())?;
 		self.hash_lock(key, |hash| -> ExecResult {
 			Ok(Value::Bool(hash.contains_key(&field)))
 		}).await
@@ -180,7 +190,7 @@ impl super::Storage {
 
 	pub async fn hash_strlen(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let field = Self::extract(args.pop_front())?;
+		let field = Self::extract_member(args.pop_front())?;
 		self.hash_lock(key, |hash| -> ExecResult {
 			match hash.get(&field) {
 				Some(Value::Buffer(value)) => Ok(Value::Integer(value.len() as i64)),
@@ -191,7 +201,7 @@ impl super::Storage {
 
 	pub async fn hash_incrby(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let field = Self::extract(args.pop_front())?;
+		let field = Self::extract_new_member(args.pop_front())?;
 		let value = Self::extract_integer(args.pop_front())?;
 		self.hash_lock_mut(key, |hash| -> ExecResult {
 			match hash.entry(field).or_insert(Value::Integer(0)) {
@@ -206,7 +216,7 @@ impl super::Storage {
 
 	pub async fn hash_incrbyfloat(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let field = Self::extract(args.pop_front())?;
+		let field = Self::extract_new_member(args.pop_front())?;
 		let value = Self::extract_float(args.pop_front())?;
 		self.hash_lock_mut(key, |hash| -> ExecResult {
 			match hash.entry(field).or_insert(Value::Float(0f64.to_bits())) {
@@ -222,22 +232,7 @@ impl super::Storage {
 	pub async fn hash_scan(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
 		let start = Self::extract_index(args.pop_front())?;
-
-		let mut pattern: Option<String> = None;
-		let mut max_check = 100usize;
-
-		while let Some(subcmd) = Self::extract_string(args.pop_front()).ok() {
-			match &subcmd.to_uppercase()[..] {
-				"MATCH" => pattern = Some(Self::extract_string(args.pop_front())?),
-				"COUNT" => max_check = Self::extract_index(args.pop_front())?,
-				arg => return Err(format!("Unexpected argument '{}'", arg)),
-			}
-		}
-
-		let pattern = match pattern {
-			None => None,
-			Some(pattern) => Some(regex::bytes::Regex::new(&pattern[..]).map_err(|e|format!("{}", e))?),
-		};
+		let ScanOptions {pattern, count: max_check, ..} = ScanOptions::parse(args, false)?;
 
 		let mut fields = vec![];
 
@@ -279,3 +274,35 @@ impl super::Storage {
 	}
 }
 
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::*;
+
+	fn args(values: Vec<Value>) -> Arguments {
+		values.into_iter().collect()
+	}
+
+	fn buffer(value: &str) -> Value {
+		Value::Buffer(value.as_bytes().to_vec())
+	}
+
+	#[tokio::test]
+	async fn reads_share_the_container_lock() {
+		let storage = super::super::Storage::new();
+		storage.hash_hset(args(vec![buffer("h"), buffer("f"), buffer("v")])).await.unwrap();
+		let container = storage.try_get_live_container(&b"h".to_vec()).await.unwrap();
+		let guard = container.read().await;
+
+		let hget = storage.hash_hget(args(vec![buffer("h"), buffer("f")]));
+		assert_eq!(tokio::time::timeout(Duration::from_secs(1), hget).await, Ok(Ok(buffer("v"))));
+		let hlen = storage.hash_len(args(vec![buffer("h")]));
+		assert_eq!(tokio::time::timeout(Duration::from_secs(1), hlen).await, Ok(Ok(Value::Integer(1))));
+
+		let hset = storage.hash_hset(args(vec![buffer("h"), buffer("g"), buffer("v")]));
+		assert!(tokio::time::timeout(Duration::from_millis(50), hset).await.is_err());
+		drop(guard);
+	}
+}
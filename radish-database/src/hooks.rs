@@ -0,0 +1,94 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use super::commands::CommandSpec;
+
+type Value = super::Value;
+type Command = super::Command;
+type Session = super::Session;
+
+/// Observer of the command execution. Hooks are called outside of any storage lock,
+/// `spec` tells the command flags, e.g. whether it is a write, and `session` the client.
+pub trait ExecutionHook: Send + Sync {
+	/// Called before the handler; an error rejects the command and is sent as the reply
+	fn before(&self, _session: &Session, _command: &Command, _spec: &CommandSpec) -> Result<(), String> {
+		Ok(())
+	}
+
+	/// Called with the reply after the handler is completed
+	fn after(&self, _session: &Session, _command: &Command, _spec: &CommandSpec, _reply: &Value, _elapsed: Duration) {
+	}
+}
+
+/// Identifies an added hook to remove it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HookId(u64);
+
+pub type Hooks = Arc<Vec<(HookId, Arc<dyn ExecutionHook>)>>;
+
+/// The list is replaced on change, so execution only clones the `Arc`
+pub struct HookList {
+	hooks: RwLock<Hooks>,
+	next_id: AtomicU64,
+}
+
+impl HookList {
+	pub fn new() -> Self {
+		Self {
+			hooks: RwLock::new(Arc::new(Vec::new())),
+			next_id: AtomicU64::new(0),
+		}
+	}
+
+	pub fn current(&self) -> Hooks {
+		self.hooks.read().unwrap().clone()
+	}
+
+	fn add(&self, hook: Arc<dyn ExecutionHook>) -> HookId {
+		let id = HookId(self.next_id.fetch_add(1, Ordering::Relaxed));
+		let mut hooks = self.hooks.write().unwrap();
+		let mut list = Vec::clone(&hooks);
+		list.push((id, hook));
+		*hooks = Arc::new(list);
+		id
+	}
+
+	fn remove(&self, id: HookId) -> bool {
+		let mut hooks = self.hooks.write().unwrap();
+		let mut list = Vec::clone(&hooks);
+		list.retain(|(hook_id, _)|*hook_id != id);
+		let removed = list.len() != hooks.len();
+		*hooks = Arc::new(list);
+		removed
+	}
+}
+
+impl super::Storage {
+	/// Hooks are called in the order of adding
+	pub fn add_hook(&self, hook: Box<dyn ExecutionHook>) -> HookId {
+		self.hooks.add(Arc::from(hook))
+	}
+
+	/// Returns false if the hook is removed already; commands running meanwhile may still call it
+	pub fn remove_hook(&self, id: HookId) -> bool {
+		self.hooks.remove(id)
+	}
+}
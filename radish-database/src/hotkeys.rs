@@ -0,0 +1,151 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Hot key tracking enabled by `hotkeys-tracking`: the keys of every command are counted in
+//! a count-min sketch of atomic counters, and the keys whose estimate reaches the top are kept
+//! in a small list. Counts are approximate: the sketch may overestimate, and a top update is
+//! skipped if another connection is updating the list at the moment.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::Hasher;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use super::commands::CommandSpec;
+
+type Key = super::Key;
+type Value = super::Value;
+type Arguments = super::Arguments;
+type ExecResult = super::ExecResult;
+
+const DEPTH: usize = 4;
+const WIDTH: usize = 2048;
+/// Size of the top list, also the maximum count of HOTKEYS
+const TOP_SIZE: usize = 32;
+const DEFAULT_COUNT: usize = 10;
+
+pub struct HotKeys {
+	sketch: Vec<AtomicU32>,
+	/// Estimate a key needs to enter the full top list
+	threshold: AtomicU64,
+	top: Mutex<Vec<(Key, u64)>>,
+}
+
+fn hash(key: &[u8]) -> u64 {
+	let mut hasher = DefaultHasher::new();
+	hasher.write(key);
+	hasher.finish()
+}
+
+impl HotKeys {
+	pub fn new() -> Self {
+		Self {
+			sketch: (0..DEPTH * WIDTH).map(|_|AtomicU32::new(0)).collect(),
+			threshold: AtomicU64::new(0),
+			top: Mutex::new(Vec::with_capacity(TOP_SIZE)),
+		}
+	}
+
+	/// Counts a hit and returns the new estimate of the key
+	fn increment(&self, key: &[u8]) -> u64 {
+		let hash = hash(key);
+		let (h1, h2) = (hash as u32 as usize, (hash >> 32) as usize | 1);
+		(0..DEPTH)
+			.map(|row|{
+				let column = h1.wrapping_add(row.wrapping_mul(h2)) % WIDTH;
+				self.sketch[row * WIDTH + column].fetch_add(1, Ordering::Relaxed) as u64 + 1
+			})
+			.min()
+			.unwrap_or(0)
+	}
+
+	fn update_top(&self, key: &[u8], estimate: u64) {
+		let mut top = match self.top.try_lock() {
+			Ok(top) => top,
+			Err(_) => return,
+		};
+		if let Some(entry) = top.iter_mut().find(|(k, _)|&k[..] == key) {
+			entry.1 = std::cmp::max(entry.1, estimate);
+		} else if top.len() < TOP_SIZE {
+			top.push((key.to_vec(), estimate));
+		} else if let Some(min) = top.iter_mut().min_by_key(|(_, count)|*count) {
+			if min.1 < estimate {
+				*min = (key.to_vec(), estimate);
+			}
+		}
+		if top.len() == TOP_SIZE {
+			let min = top.iter().map(|(_, count)|*count).min().unwrap_or(0);
+			self.threshold.store(min, Ordering::Relaxed);
+		}
+	}
+
+	pub fn record(&self, spec: &CommandSpec, args: &Arguments) {
+		for i in spec.key_indices(args.len()) {
+			if let Some(Value::Buffer(key)) = args.get(i) {
+				let estimate = self.increment(key);
+				if estimate > self.threshold.load(Ordering::Relaxed) {
+					self.update_top(key, estimate);
+				}
+			}
+		}
+	}
+
+	/// Keys with their estimated hits, the hottest first
+	pub fn top(&self, count: usize) -> Vec<(Key, u64)> {
+		let mut top = self.top.lock().unwrap().clone();
+		top.sort_by(|a, b|b.1.cmp(&a.1).then_with(||a.0.cmp(&b.0)));
+		top.truncate(count);
+		top
+	}
+
+	pub fn reset(&self) {
+		let mut top = self.top.lock().unwrap();
+		for counter in self.sketch.iter() {
+			counter.store(0, Ordering::Relaxed);
+		}
+		top.clear();
+		self.threshold.store(0, Ordering::Relaxed);
+	}
+}
+
+impl super::Storage {
+	/// HOTKEYS [count] | HOTKEYS RESET
+	pub async fn hotkeys_command(&self, mut args: Arguments) -> ExecResult {
+		let count = match args.pop_front() {
+			None => DEFAULT_COUNT,
+			Some(Value::Integer(count)) if count > 0 => std::cmp::min(count as usize, TOP_SIZE),
+			Some(Value::Integer(count)) => return Err(format!("Count must be positive, got {}", count)),
+			Some(arg) => match &Self::extract_string(Some(arg))?.to_uppercase()[..] {
+				"RESET" => {
+					self.hotkeys.reset();
+					return Ok(Value::Ok);
+				},
+				arg => return Err(format!("Unexpected argument '{}'", arg)),
+			},
+		};
+		if !self.config.hotkeys_tracking() {
+			return Err("ERR hot keys tracking is disabled, enable it with CONFIG SET hotkeys-tracking yes".to_owned());
+		}
+		let top = self.hotkeys
+			.top(count)
+			.into_iter()
+			.map(|(key, hits)|Value::Array(VecDeque::from(vec![Value::Buffer(key), Value::Integer(hits as i64)])))
+			.collect();
+		Ok(Value::Array(top))
+	}
+}
@@ -0,0 +1,128 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, SystemTime};
+
+use futures::stream::{self, Stream};
+
+use super::container::{Container, ContainerPtr};
+use super::glob::glob_match;
+
+type Key = super::Key;
+
+/// Count of keys checked under one acquisition of the containers lock
+const ITER_CHUNK: usize = 100;
+
+pub type KeyType = super::ContainerKind;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyInfo {
+	pub key_type: KeyType,
+	/// Remaining time to live, None if the key has no expiration
+	pub ttl: Option<Duration>,
+}
+
+struct KeyIter {
+	storage: super::Storage,
+	pattern: Option<Vec<u8>>,
+	/// Keys are walked from the end of the map: removal moves the last key into the freed slot,
+	/// so a key which is not visited yet can only move to a lower, not visited yet, position
+	cursor: Option<usize>,
+	buffer: VecDeque<(Key, ContainerPtr)>,
+	/// The moved last key is already visited, so it may be met twice
+	seen: HashSet<Key>,
+}
+
+impl KeyIter {
+	fn done(&self) -> bool {
+		self.cursor == Some(0)
+	}
+
+	async fn fill(&mut self) {
+		let containers = self.storage.containers.lock().await;
+		let mut cursor = std::cmp::min(self.cursor.unwrap_or_else(||containers.len()), containers.len());
+		let stop = cursor.saturating_sub(ITER_CHUNK);
+		while cursor > stop {
+			cursor -= 1;
+			let (key, container) = containers.get_index(cursor).expect("index is less than len");
+			if let Some(pattern) = &self.pattern {
+				if ! glob_match(pattern, key) {
+					continue;
+				}
+			}
+			self.buffer.push_back((key.to_vec(), container.clone()));
+		}
+		self.cursor = Some(cursor);
+	}
+
+	async fn next(&mut self) -> Option<(Key, KeyInfo)> {
+		loop {
+			if let Some((key, container)) = self.buffer.pop_front() {
+				if self.seen.contains(&key) {
+					continue;
+				}
+				if let Some(info) = key_info(&*container.read().await, self.storage.clock.now()) {
+					self.seen.insert(key.clone());
+					return Some((key, info));
+				}
+				continue;
+			}
+			if self.done() {
+				return None;
+			}
+			self.fill().await;
+		}
+	}
+}
+
+/// None if the key is already expired but not collected yet
+fn key_info(container: &Container, now: SystemTime) -> Option<KeyInfo> {
+	let (key_type, expiration_time) = match container {
+		Container::Strings(c) => (KeyType::String, c.expiration_time),
+		Container::List(c) => (KeyType::List, c.expiration_time),
+		Container::Set(c) => (KeyType::Set, c.expiration_time),
+		Container::Hash(c) => (KeyType::Hash, c.expiration_time),
+		Container::Json(c) => (KeyType::Json, c.expiration_time),
+		Container::Bloom(c) => (KeyType::Bloom, c.expiration_time),
+		Container::TimeSeries(c) => (KeyType::TimeSeries, c.expiration_time),
+	};
+	let ttl = match expiration_time {
+		None => None,
+		Some(tm) => Some(tm.duration_since(now).ok()?),
+	};
+	Some(KeyInfo {key_type, ttl})
+}
+
+impl super::Storage {
+	/// Walks the keyspace without holding the containers lock between chunks.
+	/// Every key which exists for the whole iteration is yielded exactly once;
+	/// keys inserted or removed meanwhile may or may not be yielded.
+	/// `pattern` is a glob filter applied before cloning a key
+	pub fn iter_keys(&self, pattern: Option<&[u8]>) -> impl Stream<Item = (Key, KeyInfo)> + Send {
+		let iter = KeyIter {
+			storage: self.clone(),
+			pattern: pattern.map(|p|p.to_vec()),
+			cursor: None,
+			buffer: VecDeque::with_capacity(ITER_CHUNK),
+			seen: HashSet::new(),
+		};
+		stream::unfold(iter, |mut iter| async move {
+			iter.next().await.map(|item|(item, iter))
+		})
+	}
+}
@@ -0,0 +1,281 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Human readable dump of the dataset.
+//!
+//! ```text
+//! {"keys": [{"key": "k", "type": "hash", "value": [["field", "value"]], "expire_at": 1600000000000}]}
+//! ```
+//! Buffers which are not valid UTF-8 are written as `{"base64": "..."}`, floats which JSON
+//! can't represent as `{"float": "inf"}`; `expire_at` is absolute milliseconds since UNIX epoch.
+
+use serde_json::{json, Map, Number};
+
+use super::dataset::{DatasetSnapshot, SnapshotEntry, SnapshotData, ImportMode};
+use super::glob::glob_match;
+use super::bloom::{BloomFilter, BloomLayer};
+use super::timeseries::TimeSeries;
+
+type Json = serde_json::Value;
+type Value = super::Value;
+type Arguments = super::Arguments;
+type ExecResult = super::ExecResult;
+
+fn buffer_to_json(buf: &[u8]) -> Json {
+	match std::str::from_utf8(buf) {
+		Ok(s) => Json::String(s.to_owned()),
+		Err(_) => json!({"base64": base64::encode(buf)}),
+	}
+}
+
+fn float_to_json(f: f64) -> Json {
+	match Number::from_f64(f) {
+		Some(n) => Json::Number(n),
+		None => json!({"float": f.to_string()}),
+	}
+}
+
+pub fn value_to_json(value: &Value) -> Json {
+	match value {
+		Value::Nill => Json::Null,
+		Value::Ok => Json::String("OK".to_owned()),
+		Value::Bool(b) => Json::Bool(*b),
+		Value::Integer(i) => Json::from(*i),
+		Value::Float(n) => float_to_json(f64::from_bits(*n)),
+		Value::Buffer(b) => buffer_to_json(b),
+		Value::Error(e) => json!({"error": e}),
+		Value::Array(items) => Json::Array(items.iter().map(value_to_json).collect()),
+	}
+}
+
+/// Objects with a single escape field are decoded back to the escaped value
+fn escape_from_json(object: &Map<String, Json>) -> Result<Value, String> {
+	let (name, field) = match (object.len(), object.iter().next()) {
+		(1, Some(field)) => field,
+		_ => return Err(format!("Unexpected JSON object: {}", Json::Object(object.clone()))),
+	};
+	let field = field.as_str().ok_or_else(||format!("Field '{}' must be a string", name))?;
+	match &name[..] {
+		"base64" => base64::decode(field).map(Value::Buffer).map_err(|e|format!("Invalid base64: {}", e)),
+		"float" => field.parse::<f64>().map(|f|Value::Float(f.to_bits())).map_err(|e|format!("Invalid float '{}': {}", field, e)),
+		"error" => Ok(Value::Error(field.to_owned())),
+		_ => Err(format!("Unknown JSON escape '{}'", name)),
+	}
+}
+
+pub fn value_from_json(json: &Json) -> Result<Value, String> {
+	match json {
+		Json::Null => Ok(Value::Nill),
+		Json::Bool(b) => Ok(Value::Bool(*b)),
+		Json::Number(n) => match n.as_i64() {
+			Some(i) => Ok(Value::Integer(i)),
+			None => n.as_f64().map(|f|Value::Float(f.to_bits())).ok_or_else(||format!("Unsupported number {}", n)),
+		},
+		Json::String(s) => Ok(Value::Buffer(s.as_bytes().to_vec())),
+		Json::Array(items) => Ok(Value::Array(items.iter().map(value_from_json).collect::<Result<_, _>>()?)),
+		Json::Object(object) => escape_from_json(object),
+	}
+}
+
+fn key_from_json(json: &Json) -> Result<Vec<u8>, String> {
+	match value_from_json(json)? {
+		Value::Buffer(key) => Ok(key),
+		_ => Err(format!("Key must be a string: {}", json)),
+	}
+}
+
+/// Bit arrays are written in base64
+fn bloom_to_json(filter: &BloomFilter) -> Json {
+	let layers = filter.layers.iter().map(|layer|json!({
+		"bits": base64::encode(&layer.bits),
+		"hashes": layer.hashes,
+		"capacity": layer.capacity,
+		"items": layer.items,
+	})).collect::<Vec<_>>();
+	json!({"error_rate": filter.error_rate, "expansion": filter.expansion, "layers": layers})
+}
+
+fn bloom_from_json(json: &Json) -> Result<BloomFilter, String> {
+	let invalid = ||format!("Invalid bloom filter: {}", json);
+	let number = |json: &Json, name: &str|json.get(name).and_then(Json::as_u64).ok_or_else(invalid);
+	let layers = json.get("layers").and_then(Json::as_array).ok_or_else(invalid)?
+		.iter()
+		.map(|layer|{
+			let bits = layer.get("bits").and_then(Json::as_str).ok_or_else(invalid)?;
+			let layer = BloomLayer {
+				bits: base64::decode(bits).map_err(|_|invalid())?,
+				hashes: number(layer, "hashes")? as u32,
+				capacity: number(layer, "capacity")?,
+				items: number(layer, "items")?,
+			};
+			if layer.bits.is_empty() {
+				return Err(invalid());
+			}
+			Ok(layer)
+		})
+		.collect::<Result<Vec<_>, String>>()?;
+	if layers.is_empty() {
+		return Err(invalid());
+	}
+	Ok(BloomFilter {
+		error_rate: json.get("error_rate").and_then(Json::as_f64).ok_or_else(invalid)?,
+		expansion: number(json, "expansion")? as u32,
+		layers,
+	})
+}
+
+/// Samples are [timestamp, value] pairs, labels an object
+fn timeseries_to_json(series: &TimeSeries) -> Json {
+	let labels = series.labels.iter().map(|(name, value)|(name.clone(), Json::String(value.clone()))).collect::<Map<_, _>>();
+	let samples = series.samples.iter().map(|(ts, value)|json!([ts, float_to_json(*value)])).collect::<Vec<_>>();
+	json!({"retention": series.retention, "labels": labels, "samples": samples})
+}
+
+fn timeseries_from_json(json: &Json) -> Result<TimeSeries, String> {
+	let invalid = ||format!("Invalid time series: {}", json);
+	let labels = match json.get("labels") {
+		None => Vec::new(),
+		Some(labels) => labels.as_object().ok_or_else(invalid)?
+			.iter()
+			.map(|(name, value)|value.as_str().map(|value|(name.clone(), value.to_owned())).ok_or_else(invalid))
+			.collect::<Result<_, _>>()?,
+	};
+	let samples = json.get("samples").and_then(Json::as_array).ok_or_else(invalid)?
+		.iter()
+		.map(|sample|match sample.as_array().map(|pair|&pair[..]) {
+			Some([ts, value]) => Ok((ts.as_u64().ok_or_else(invalid)?, value.as_f64().ok_or_else(invalid)?)),
+			_ => Err(invalid()),
+		})
+		.collect::<Result<Vec<_>, String>>()?;
+	if samples.windows(2).any(|pair|pair[0].0 >= pair[1].0) {
+		return Err(invalid());
+	}
+	Ok(TimeSeries {
+		retention: json.get("retention").and_then(Json::as_u64).unwrap_or(0),
+		labels,
+		samples,
+	})
+}
+
+fn entry_to_json(entry: &SnapshotEntry) -> Json {
+	let (kind, value) = match &entry.data {
+		SnapshotData::String(s) => ("string", buffer_to_json(s)),
+		SnapshotData::List(l) => ("list", Json::Array(l.iter().map(value_to_json).collect())),
+		SnapshotData::Set(s) => ("set", Json::Array(s.iter().map(value_to_json).collect())),
+		SnapshotData::Hash(h) => ("hash", Json::Array(h.iter().map(|(f, v)|json!([value_to_json(f), value_to_json(v)])).collect())),
+		SnapshotData::Json(j) => ("json", j.clone()),
+		SnapshotData::Bloom(b) => ("bloom", bloom_to_json(b)),
+		SnapshotData::TimeSeries(t) => ("timeseries", timeseries_to_json(t)),
+	};
+	let mut object = Map::new();
+	object.insert("key".to_owned(), buffer_to_json(&entry.key));
+	object.insert("type".to_owned(), Json::String(kind.to_owned()));
+	object.insert("value".to_owned(), value);
+	if let Some(expire_at) = entry.expire_at {
+		object.insert("expire_at".to_owned(), Json::from(expire_at));
+	}
+	Json::Object(object)
+}
+
+fn values_from_json(json: &Json) -> Result<Vec<Value>, String> {
+	json.as_array()
+		.ok_or_else(||format!("Array expected: {}", json))?
+		.iter()
+		.map(value_from_json)
+		.collect()
+}
+
+fn pair_from_json(json: &Json) -> Result<(Value, Value), String> {
+	match json.as_array().map(|pair|&pair[..]) {
+		Some([field, value]) => Ok((value_from_json(field)?, value_from_json(value)?)),
+		_ => Err(format!("Field/value pair expected: {}", json)),
+	}
+}
+
+fn entry_from_json(json: &Json) -> Result<SnapshotEntry, String> {
+	let key = key_from_json(json.get("key").ok_or("Entry without 'key'")?)?;
+	let kind = json.get("type").and_then(Json::as_str).ok_or("Entry without 'type'")?;
+	let value = json.get("value").ok_or("Entry without 'value'")?;
+	let data = match kind {
+		"string" => SnapshotData::String(key_from_json(value)?),
+		"list" => SnapshotData::List(values_from_json(value)?),
+		"set" => SnapshotData::Set(values_from_json(value)?),
+		"hash" => SnapshotData::Hash(
+			value.as_array()
+				.ok_or_else(||format!("Array expected: {}", value))?
+				.iter()
+				.map(pair_from_json)
+				.collect::<Result<_, _>>()?
+		),
+		"json" => SnapshotData::Json(value.clone()),
+		"bloom" => SnapshotData::Bloom(bloom_from_json(value)?),
+		"timeseries" => SnapshotData::TimeSeries(timeseries_from_json(value)?),
+		kind => return Err(format!("Unknown type '{}' of key '{}'", kind, String::from_utf8_lossy(&key))),
+	};
+	let expire_at = match json.get("expire_at") {
+		None | Some(Json::Null) => None,
+		Some(expire_at) => Some(expire_at.as_u64().ok_or_else(||format!("Invalid expire_at: {}", expire_at))?),
+	};
+	Ok(SnapshotEntry {key, data, expire_at})
+}
+
+pub fn snapshot_to_json(snapshot: &DatasetSnapshot) -> Json {
+	json!({"keys": snapshot.entries.iter().map(entry_to_json).collect::<Vec<_>>()})
+}
+
+pub fn snapshot_from_json(json: &Json) -> Result<DatasetSnapshot, String> {
+	let entries = json.get("keys")
+		.and_then(Json::as_array)
+		.ok_or("Document without 'keys' array")?
+		.iter()
+		.map(entry_from_json)
+		.collect::<Result<_, _>>()?;
+	Ok(DatasetSnapshot {entries})
+}
+
+impl super::Storage {
+	/// EXPORT [pattern]
+	pub async fn json_export(&self, mut args: Arguments) -> ExecResult {
+		let pattern = match args.pop_front() {
+			None => None,
+			Some(arg) => Some(Self::extract_buffer(Some(arg))?),
+		};
+		let mut snapshot = self.export().await;
+		if let Some(pattern) = pattern {
+			snapshot.entries.retain(|entry|glob_match(&pattern, &entry.key));
+		}
+		Ok(Value::Buffer(snapshot_to_json(&snapshot).to_string().into_bytes()))
+	}
+
+	/// IMPORT document [REPLACE|MERGE]; returns the count of keys in the document
+	pub async fn json_import(&self, mut args: Arguments) -> ExecResult {
+		let document = Self::extract_buffer(args.pop_front())?;
+		let mode = match args.pop_front() {
+			None => ImportMode::Merge,
+			Some(arg) => match &Self::extract_string(Some(arg))?.to_uppercase()[..] {
+				"REPLACE" => ImportMode::Replace,
+				"MERGE" => ImportMode::Merge,
+				mode => return Err(format!("Unexpected import mode '{}'", mode)),
+			},
+		};
+		let json = serde_json::from_slice::<Json>(&document).map_err(|e|format!("Invalid JSON document: {}", e))?;
+		let snapshot = snapshot_from_json(&json)?;
+		let count = snapshot.entries.len();
+		self.import(snapshot, mode).await?;
+		Ok(Value::Integer(count as i64))
+	}
+}
@@ -15,80 +15,177 @@
  */
 
 use std::sync::Arc;
-use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::time::{SystemTime, Duration};
 
-use tokio::sync::{Mutex, MutexGuard};
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use super::container::Container;
 use super::container::ContainerPtr;
+use super::container::Containers;
+use super::container::ContainerImpl;
+use super::container::ContainerInner;
+use super::container::WRONG_TYPE_ERROR;
+use super::options::{OptionParser, ScanOptions};
+use super::events::KeyEventKind;
+use super::dataset::{decode_dump, encode_dump, import_container};
 
 type Key = super::Key;
+type CompactKey = super::CompactKey;
 type Value = super::Value;
 type Arguments = super::Arguments;
 type ExecResult = super::ExecResult;
 
+/// Count of keys matched by KEYS under one acquisition of the containers lock
+const KEYS_CHUNK: usize = 1000;
+/// Count of random keys checked by RANDOMKEY before walking the keyspace for a live one
+const RANDOMKEY_PROBES: usize = 16;
+
+const SORT_OPTIONS: OptionParser = OptionParser::new(&["ASC", "DESC", "ALPHA"], &["STORE"])
+	.multi(&[("LIMIT", 2)])
+	.exclusive(&[&["ASC", "DESC"]]);
+const COPY_OPTIONS: OptionParser = OptionParser::new(&["REPLACE"], &[]);
+const RESTORE_OPTIONS: OptionParser = OptionParser::new(&["REPLACE"], &[]);
+
+fn sort_score(value: &Value) -> Result<f64, String> {
+	let score = match value {
+		Value::Integer(i) => Some(*i as f64),
+		Value::Float(f) => Some(f64::from_bits(*f)),
+		Value::Buffer(b) => std::str::from_utf8(b).ok().and_then(|s|s.parse::<f64>().ok()),
+		_ => None,
+	};
+	score.ok_or_else(||"ERR One or more elements can't be converted into double".to_owned())
+}
+
+fn sort_bytes(value: &Value) -> Vec<u8> {
+	match value {
+		Value::Buffer(b) => b.clone(),
+		value => format!("{}", value).into_bytes(),
+	}
+}
+
 impl super::Storage {
 	pub fn make_container(cnt: Container) -> ContainerPtr {
-		Arc::new(Mutex::new(cnt))
+		Arc::new(RwLock::new(cnt))
 	}
 	pub fn make_container_with<F: FnMut() -> Container>(mut factory: F) -> ContainerPtr {
 		Self::make_container(factory())
 	}
 
-	pub async fn try_get_container(&self, key: &Key) -> Option<ContainerPtr> {
-		let containers = self.containers.lock().await;
-		containers
-		.get(key)
-		.cloned()
+	/// Removes the key if its deadline has passed, as `keys_check_expirations` would; true if the
+	/// key is expired, even when a read-only server keeps it until writes are allowed again.
+	/// The container lock is taken under the containers lock, in the same order as the expiration check
+	pub(crate) async fn reap_expired(&self, containers: &mut Containers, key: &[u8]) -> bool {
+		let c = match containers.get(key) {
+			None => return false,
+			Some(c) => c.clone(),
+		};
+		let c = c.read().await;
+		if Self::is_live(&c, self.clock.now()) {
+			return false;
+		}
+		if self.config.read_only() && !self.config.read_only_expire() {
+			return true;
+		}
+		log::debug!("{:?}: expired and removed on access", key);
+		// the entry of the expire queue is dropped by the next expiration check
+		containers.remove(key);
+		self.memory_track_remove(key, &c);
+		self.counters.key_expired();
+		self.events.publish(key, KeyEventKind::Expired);
+		true
 	}
 
-	pub async fn get_container<F: FnMut() -> Container>(&self, key: Key, factory: F) -> ContainerPtr {
+	pub async fn try_get_live_container(&self, key: &Key) -> Option<ContainerPtr> {
 		let mut containers = self.containers.lock().await;
+		if self.reap_expired(&mut containers, key).await {
+			return None;
+		}
 		containers
-		.entry(key.clone())
-		.or_insert_with(||Self::make_container_with(factory))
-		.clone()
+		.get(&key[..])
+		.cloned()
 	}
 
-	pub async fn try_get_containers(&self, keys: &Vec<Key>) -> Vec<Option<ContainerPtr>> {
-		let containers = self.containers.lock().await;
+	/// Reading commands see a missing key as an empty container, which is not inserted,
+	/// so reading a key never creates it
+	pub async fn read_container<Inner, F>(&self, key: &Key, processor: F) -> ExecResult
+	where Inner: ContainerInner, F: FnOnce(&Inner) -> ExecResult {
+		match self.try_get_live_container(key).await {
+			None => processor(&Inner::default()),
+			Some(c1) => {
+				let c2 = c1.read().await;
+				self.access_touch(&c2);
+				processor(&c2.typed::<Inner>()?.inner)
+			},
+		}
+	}
 
-		keys
-		.iter()
-		.map(|key| {
-			match containers.get(key) {
-				Some(v) => Some(v.clone()),
-				None => None,
+	/// Container of the key holding `Inner`, an empty one is created for a missing key
+	pub async fn get_typed_container<Inner: ContainerInner>(&self, key: Key) -> Result<ContainerPtr, String> {
+		let mut containers = self.get_typed_containers::<Inner>(vec![key]).await?;
+		Ok(containers.remove(0))
+	}
+
+	/// Containers of the keys holding `Inner`; if any key is of another kind it is WRONGTYPE
+	/// and none of the missing keys is created
+	pub async fn get_typed_containers<Inner: ContainerInner>(&self, keys: Vec<Key>) -> Result<Vec<ContainerPtr>, String> {
+		let mut containers = self.containers.lock().await;
+		for key in &keys {
+			self.reap_expired(&mut containers, key).await;
+			if let Some(c) = containers.get(&key[..]) {
+				if c.read().await.kind() != Inner::KIND {
+					return Err(WRONG_TYPE_ERROR.to_owned());
+				}
 			}
+		}
+
+		let out = keys
+		.into_iter()
+		.map(|key| {
+			containers
+			.entry(key[..].into())
+			.or_insert_with(||{
+				self.memory_track_key_insert(&key);
+				Self::make_container(Inner::wrap(ContainerImpl::new()))
+			})
+			.clone()
 		})
-		.collect()
+		.collect();
+		Ok(out)
 	}
 
-	pub async fn get_containers<F: FnMut() -> Container>(&self, mut keys: Vec<Key>, mut factory: F) -> Vec<ContainerPtr> {
+	pub async fn get_live_container<F: FnMut() -> Container>(&self, key: Key, factory: F) -> ContainerPtr {
 		let mut containers = self.containers.lock().await;
+		self.reap_expired(&mut containers, &key).await;
+		containers
+		.entry(key[..].into())
+		.or_insert_with(||{
+			self.memory_track_key_insert(&key);
+			Self::make_container_with(factory)
+		})
+		.clone()
+	}
 
-		keys
-		.drain(..)
-		.map(|key| {
-			if let Some(v) = containers.get(&key) {
-				v.clone()
-			} else {
-				let c = Self::make_container_with(||factory());
-				containers.insert(key, c.clone());
-				c
+	pub async fn try_get_containers(&self, keys: &Vec<Key>) -> Vec<Option<ContainerPtr>> {
+		let mut containers = self.containers.lock().await;
+
+		let mut out = Vec::with_capacity(keys.len());
+		for key in keys {
+			match self.reap_expired(&mut containers, key).await {
+				true => out.push(None),
+				false => out.push(containers.get(&key[..]).cloned()),
 			}
-		})
-		.collect()
+		}
+		out
 	}
 
-	pub async fn lock_all<'a, T: 'a>(mut writes: impl Iterator<Item=&'a Mutex<T>>, mut reads: impl Iterator<Item=Option<&'a Mutex<T>>>) -> (Vec<MutexGuard<'a, T>>, Vec<Option<MutexGuard<'a, T>>>) {
-		let mut mutexes = BTreeMap::<u64, &'a Mutex<T>>::new();
-		let mut guards = HashMap::<u64, MutexGuard<'a, T>>::new();
+	pub async fn lock_all<'a, T: 'a>(mut writes: impl Iterator<Item=&'a RwLock<T>>, mut reads: impl Iterator<Item=Option<&'a RwLock<T>>>) -> (Vec<RwLockWriteGuard<'a, T>>, Vec<Option<RwLockWriteGuard<'a, T>>>) {
+		let mut mutexes = BTreeMap::<u64, &'a RwLock<T>>::new();
+		let mut guards = HashMap::<u64, RwLockWriteGuard<'a, T>>::new();
 		let mut output_order_writes = Vec::<u64>::new();
 		let mut output_order_reads = Vec::<u64>::new();
 		while let Some(m) = writes.next() {
-			let address = m as *const Mutex<T> as u64;
+			let address = m as *const RwLock<T> as u64;
 			mutexes.insert(address, m);
 			output_order_writes.push(address);
 		}
@@ -96,14 +193,14 @@ impl super::Storage {
 			match m {
 				None => output_order_reads.push(0),
 				Some(m) => {
-					let address = m as *const Mutex<T> as u64;
+					let address = m as *const RwLock<T> as u64;
 					mutexes.insert(address, m);
 					output_order_reads.push(address);
 				},
 			}
 		}
 		for (address, m) in mutexes {
-			guards.insert(address, m.lock().await);
+			guards.insert(address, m.write().await);
 		}
 		let writes = output_order_writes
 			.iter()
@@ -123,45 +220,122 @@ impl super::Storage {
 		(writes, reads)
 	}
 
-	pub async fn keys_keys(&self, mut args: Arguments) -> ExecResult {
+	/// Read-locks every distinct lock once, in address order; the returned addresses index the guards in input order.
+	pub async fn lock_all_read<'a, T: 'a>(reads: impl Iterator<Item=Option<&'a RwLock<T>>>) -> (HashMap<u64, RwLockReadGuard<'a, T>>, Vec<Option<u64>>) {
+		let mut mutexes = BTreeMap::<u64, &'a RwLock<T>>::new();
+		let order = reads
+			.map(|m|{
+				m.map(|m|{
+					let address = m as *const RwLock<T> as u64;
+					mutexes.insert(address, m);
+					address
+				})
+			})
+			.collect()
+		;
+		let mut guards = HashMap::<u64, RwLockReadGuard<'a, T>>::new();
+		for (address, m) in mutexes {
+			guards.insert(address, m.read().await);
+		}
+		(guards, order)
+	}
+
+	pub async fn keys_keys(&self, args: Arguments) -> ExecResult {
+		self.keys_keys_in(args, &[]).await
+	}
+
+	/// KEYS over the keys starting with `prefix`, matched and returned without it
+	pub(crate) async fn keys_keys_in(&self, mut args: Arguments, prefix: &[u8]) -> ExecResult {
 		let pattern = Self::extract_key(args.pop_front())?;
 		let pattern = std::str::from_utf8(&pattern[..]).map_err(|e|format!("{}", e))?;
 		let pattern = regex::bytes::Regex::new(pattern).map_err(|e|format!("{}", e))?;
-		let filter = |key: &&Key| -> bool {
-			pattern.is_match(&key[..])
-		};
 
-		let containers = self.containers.lock().await;
-
-		Ok(Value::Array(
-			containers
-			.keys()
-			.filter(filter)
-			.map(|key| Value::Buffer(key.clone()))
-			.collect()
-		))
+		// The map is walked from the end in chunks, releasing the lock between them, like `iter_keys`:
+		// keys which exist for the whole command are returned, keys inserted or removed meanwhile may be not.
+		// A removal moves the last key into its slot, so a key may be met twice
+		let now = self.clock.now();
+		let mut seen = HashSet::new();
+		let mut keys = VecDeque::new();
+		let mut cursor = None;
+		while cursor != Some(0) {
+			let chunk: Vec<(CompactKey, ContainerPtr)> = {
+				let containers = self.containers.lock().await;
+				let end = std::cmp::min(cursor.unwrap_or_else(||containers.len()), containers.len());
+				let start = end.saturating_sub(KEYS_CHUNK);
+				cursor = Some(start);
+				(start..end).rev()
+					.map(|index|containers.get_index(index).expect("index is less than len"))
+					.filter(|(key, _)|key.starts_with(prefix) && pattern.is_match(&key[prefix.len()..]))
+					.map(|(key, c)|(key.clone(), c.clone()))
+					.collect()
+			};
+			for (key, c) in chunk {
+				// keys already expired but not swept yet are left out, like RANDOMKEY does
+				if Self::is_live(&*c.read().await, now) && seen.insert(key.clone()) {
+					keys.push_front(Value::Buffer(key[prefix.len()..].to_vec()));
+				}
+			}
+		}
+		Ok(Value::Array(keys))
 	}
 
 	pub async fn keys_exists(&self, mut args: Arguments) -> ExecResult {
-		let containers = self.containers.lock().await;
+		let mut containers = self.containers.lock().await;
 
 		let mut exists_count = 0;
 		while let Ok(key) = Self::extract_key(args.pop_front()) {
-			if let Some(_) = containers.get(&key) {
-				exists_count = exists_count + 1;
+			if self.reap_expired(&mut containers, &key).await {
+				continue;
+			}
+			if containers.contains_key(&key[..]) {
+				exists_count += 1;
 			}
 		}
 		Ok(Value::Integer(exists_count))
 	}
 
+	pub async fn keys_randomkey(&self, _args: Arguments) -> ExecResult {
+		let containers = self.containers.lock().await;
+		if containers.is_empty() {
+			return Ok(Value::Nill);
+		}
+
+		// Keys already expired but not swept yet are skipped: a few random probes first,
+		// then a walk from the last probe, so a mostly expired keyspace still gives a live key
+		let now = self.clock.now();
+		let len = containers.len();
+		let mut index = 0;
+		for _ in 0..RANDOMKEY_PROBES {
+			index = rand::random::<usize>() % len;
+			let (key, c) = containers.get_index(index).expect("index is less than len");
+			if Self::is_live(&*c.read().await, now) {
+				return Ok(Value::Buffer(key.to_vec()));
+			}
+		}
+		for i in 1..len {
+			let (key, c) = containers.get_index((index + i) % len).expect("index is less than len");
+			if Self::is_live(&*c.read().await, now) {
+				return Ok(Value::Buffer(key.to_vec()));
+			}
+		}
+		Ok(Value::Nill)
+	}
+
+	fn is_live(c: &Container, now: SystemTime) -> bool {
+		match Self::get_expiration_time(c) {
+			Some(tm) => tm > now,
+			None => true,
+		}
+	}
+
 	pub async fn keys_now(&self, _args: Arguments) -> ExecResult {
-		let timepoint = SystemTime::now();
+		let timepoint = self.clock.source().now_system();
 		let timestamp = timepoint.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
 		Ok(Value::Integer(timestamp as i64))
 	}
 
 	pub async fn keys_pnow(&self, _args: Arguments) -> ExecResult {
-		let timepoint = SystemTime::now();
+		let timepoint = self.clock.source().now_system();
 		let timestamp = timepoint.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis();
 		Ok(Value::Integer(timestamp as i64))
 	}
@@ -171,31 +345,85 @@ impl super::Storage {
 
 		let mut removed_count = 0;
 		while let Ok(key) = Self::extract_key(args.pop_front()) {
-			if let Some(_) = containers.remove(&key) {
+			if self.reap_expired(&mut containers, &key).await {
+				continue;
+			}
+			if let Some(c) = containers.remove(&key[..]) {
+				self.memory_track_remove(&key, &*c.read().await);
 				removed_count = removed_count + 1;
 			}
 		}
 		Ok(Value::Integer(removed_count))
 	}
 
+	/// DEL which frees the removed containers in a spawned task, out of the containers lock
+	pub async fn keys_unlink(&self, mut args: Arguments) -> ExecResult {
+		let mut containers = self.containers.lock().await;
+
+		let mut removed = Vec::new();
+		while let Ok(key) = Self::extract_key(args.pop_front()) {
+			if self.reap_expired(&mut containers, &key).await {
+				continue;
+			}
+			if let Some(c) = containers.remove(&key[..]) {
+				self.memory_track_remove(&key, &*c.read().await);
+				removed.push(c);
+			}
+		}
+		drop(containers);
+
+		let removed_count = removed.len() as i64;
+		if removed_count > 0 {
+			tokio::spawn(async move {
+				drop(removed);
+			});
+		}
+		Ok(Value::Integer(removed_count))
+	}
+
+	pub async fn keys_touch(&self, mut args: Arguments) -> ExecResult {
+		let mut containers = self.containers.lock().await;
+
+		let mut touched_count = 0;
+		while let Ok(key) = Self::extract_key(args.pop_front()) {
+			if self.reap_expired(&mut containers, &key).await {
+				continue;
+			}
+			if let Some(c) = containers.get(&key[..]) {
+				self.access_touch(&*c.read().await);
+				touched_count += 1;
+			}
+		}
+		Ok(Value::Integer(touched_count))
+	}
+
 	async fn key_expiration(&self, cnt: &ContainerPtr) -> Option<std::time::SystemTime> {
-		let cnt = cnt.lock().await;
+		let cnt = cnt.read().await;
 		match &*cnt {
 			Container::Set(c) => c.expiration_time,
 			Container::List(c) => c.expiration_time,
 			Container::Hash(c) => c.expiration_time,
 			Container::Strings(c) => c.expiration_time,
+			Container::Json(c) => c.expiration_time,
+			Container::Bloom(c) => c.expiration_time,
+			Container::TimeSeries(c) => c.expiration_time,
 		}
 	}
 
-	pub async fn keys_rename(&mut self, mut args: Arguments) -> ExecResult {
+	pub async fn keys_rename(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
 		let newkey = Self::extract_key(args.pop_front())?;
 
 		let mut containers = self.containers.lock().await;
-		let cnt = containers.remove(&key).ok_or_else(||format!("key '{:?}' not found", &key[..]))?;
+		self.reap_expired(&mut containers, &key).await;
+		self.reap_expired(&mut containers, &newkey).await;
+		let cnt = containers.remove(&key[..]).ok_or_else(||format!("key '{:?}' not found", &key[..]))?;
 		let timepoint = self.key_expiration(&cnt).await;
-		containers.insert(newkey.clone(), cnt);
+		self.memory_track_key_remove(&key);
+		self.memory_track_key_insert(&newkey);
+		if let Some(old) = containers.insert(newkey[..].into(), cnt) {
+			self.memory_track_remove(&newkey, &*old.read().await);
+		}
 		drop(containers);
 
 		if let Some(timepoint) = timepoint {
@@ -204,25 +432,6 @@ impl super::Storage {
 		Ok(Value::Ok)
 	}
 
-	fn check_type(key_type: &str) -> Result<(), String> {
-		match key_type {
-			"set" => Ok(()),
-			"list" => Ok(()),
-			"hash" => Ok(()),
-			"string" => Ok(()),
-			t => Err(format!("Unexpected type '{}'", t)),
-		}
-	}
-
-	fn type_to_string(c: &Container) -> &str {
-		match *c {
-			Container::Set(_) => "set",
-			Container::List(_) => "list",
-			Container::Hash(_) => "hash",
-			Container::Strings(_) => "string",
-		}
-	}
-
 	pub async fn keys_type(&self, mut args: Arguments) -> ExecResult {
 		let keys = args.drain(..).filter_map(|a|Self::extract_key(Some(a)).ok()).collect();
 		let cnts = self.try_get_containers(&keys).await;
@@ -231,9 +440,8 @@ impl super::Storage {
 			let ktype = match c {
 				None => Value::Nill,
 				Some(c) => {
-					let c = c.lock().await;
-					let t = Self::type_to_string(&c);
-					Value::Buffer(Vec::from(t.as_bytes()))
+					let c = c.read().await;
+					Value::Buffer(Vec::from(c.kind().as_str().as_bytes()))
 				}
 			};
 			types.push_back(ktype);
@@ -245,12 +453,15 @@ impl super::Storage {
 		}
 	}
 
-	fn get_expiration_time(c: &Container) -> Option<SystemTime> {
+	pub fn get_expiration_time(c: &Container) -> Option<SystemTime> {
 		match c {
 			Container::Set(c) => c.expiration_time,
 			Container::List(c) => c.expiration_time,
 			Container::Hash(c) => c.expiration_time,
 			Container::Strings(c) => c.expiration_time,
+			Container::Json(c) => c.expiration_time,
+			Container::Bloom(c) => c.expiration_time,
+			Container::TimeSeries(c) => c.expiration_time,
 		}
 	}
 	fn set_expiration_time(c: &mut Container, t: Option<SystemTime>) {
@@ -259,21 +470,24 @@ impl super::Storage {
 			Container::List(c) => &mut c.expiration_time,
 			Container::Hash(c) => &mut c.expiration_time,
 			Container::Strings(c) => &mut c.expiration_time,
+			Container::Json(c) => &mut c.expiration_time,
+			Container::Bloom(c) => &mut c.expiration_time,
+			Container::TimeSeries(c) => &mut c.expiration_time,
 		};
 		*expire = t;
 	}
 
-	async fn keys_expiration_time<F>(&mut self, mut args: Arguments, dur_to_i64: F) -> ExecResult
+	async fn keys_expiration_time<F>(&self, mut args: Arguments, dur_to_i64: F) -> ExecResult
 	where F: FnOnce(Duration)->i64 {
 		let key = Self::extract_key(args.pop_front())?;
-		match self.try_get_container(&key).await {
+		match self.try_get_live_container(&key).await {
 			None => Ok(Value::Integer(-2)),
 			Some(c) => {
-				let c = c.lock().await;
+				let c = c.read().await;
 				match Self::get_expiration_time(&*c) {
 					None => Ok(Value::Integer(-1)),
 					Some(tm) => {
-						let ttl = tm.duration_since(SystemTime::now()).unwrap_or(Duration::new(0, 0));
+						let ttl = tm.duration_since(self.clock.now()).unwrap_or(Duration::new(0, 0));
 						Ok(Value::Integer(dur_to_i64(ttl)))
 					},
 				}
@@ -281,69 +495,138 @@ impl super::Storage {
 		}
 	}
 
-	pub async fn keys_pttl(&mut self, args: Arguments) -> ExecResult {
+	pub async fn keys_pttl(&self, args: Arguments) -> ExecResult {
 		self.keys_expiration_time(args, |ttl|ttl.as_millis() as i64).await
 	}
 
-	pub async fn keys_ttl(&mut self, args: Arguments) -> ExecResult {
+	pub async fn keys_ttl(&self, args: Arguments) -> ExecResult {
 		self.keys_expiration_time(args, |ttl|ttl.as_secs() as i64).await
 	}
 
-	async fn keys_expire_impl(&mut self, key: Key, timepoint: SystemTime) -> ExecResult {
-		let c = self.try_get_container(&key).await;
+	/// `base` moved by `amount` units, backwards for a negative amount
+	fn deadline(base: SystemTime, amount: i64, unit: fn(u64) -> Duration) -> Result<SystemTime, String> {
+		let shifted = match amount {
+			amount if amount < 0 => base.checked_sub(unit(amount.unsigned_abs())),
+			amount => base.checked_add(unit(amount as u64)),
+		};
+		shifted.ok_or_else(||"ERR invalid expire time".to_owned())
+	}
+
+	/// A deadline which is already due deletes the key right away, as DEL would
+	async fn keys_expire_now(&self, key: Key) -> ExecResult {
+		let mut containers = self.containers.lock().await;
+		if self.reap_expired(&mut containers, &key).await {
+			return Ok(Value::Bool(false));
+		}
+		let c = match containers.remove(&key[..]) {
+			None => return Ok(Value::Bool(false)),
+			Some(c) => c,
+		};
+		let c = c.read().await;
+		self.memory_track_remove(&key, &c);
+		let queued = Self::get_expiration_time(&c);
+		drop(c);
+		drop(containers);
+
+		if let Some(timepoint) = queued {
+			self.expire_controller.lock().await.forget(&key, timepoint);
+		}
+		self.events.publish(&key, KeyEventKind::Del);
+		Ok(Value::Bool(true))
+	}
+
+	async fn keys_expire_impl(&self, key: Key, timepoint: SystemTime) -> ExecResult {
+		if timepoint <= self.clock.now() {
+			return self.keys_expire_now(key).await;
+		}
+		let c = self.try_get_live_container(&key).await;
 		match c {
 			None => Ok(Value::Bool(false)),
-			Some(c) => {
-				let mut c = c.lock().await;
+			Some(ptr) => {
+				let mut c = ptr.write().await;
+				self.snapshot_preserve(&ptr, &c);
 				Self::set_expiration_time(&mut *c, Some(timepoint));
 				drop(c);
 				self.expire_key_at(&key, timepoint).await;
+				self.events.publish(&key, KeyEventKind::Expire);
 				Ok(Value::Bool(true))
 			},
 		}
 	}
 
-	pub async fn keys_expire(&mut self, mut args: Arguments) -> ExecResult {
+	pub async fn keys_persist(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let seconds = Self::extract_unsigned_integer(args.pop_front())?;
-		let timepoint = SystemTime::now() + Duration::from_secs(seconds);
+		let ptr = match self.try_get_live_container(&key).await {
+			None => return Ok(Value::Bool(false)),
+			Some(ptr) => ptr,
+		};
+		let mut c = ptr.write().await;
+		let timepoint = match Self::get_expiration_time(&c) {
+			None => return Ok(Value::Bool(false)),
+			Some(timepoint) => timepoint,
+		};
+		self.snapshot_preserve(&ptr, &c);
+		Self::set_expiration_time(&mut c, None);
+		drop(c);
+		self.expire_controller.lock().await.forget(&key, timepoint);
+		Ok(Value::Bool(true))
+	}
+
+	pub async fn keys_expire(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let seconds = Self::extract_integer(args.pop_front())?;
+		let timepoint = Self::deadline(self.clock.now(), seconds, Duration::from_secs)?;
 		self.keys_expire_impl(key, timepoint).await
 	}
 
-	pub async fn keys_expire_at(&mut self, mut args: Arguments) -> ExecResult {
+	pub async fn keys_expire_at(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let seconds = Self::extract_unsigned_integer(args.pop_front())?;
-		let timepoint = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+		let seconds = Self::extract_integer(args.pop_front())?;
+		let timepoint = self.clock.from_wall(Self::deadline(SystemTime::UNIX_EPOCH, seconds, Duration::from_secs)?);
 		self.keys_expire_impl(key, timepoint).await
 	}
 
-	pub async fn keys_pexpire(&mut self, mut args: Arguments) -> ExecResult {
+	pub async fn keys_pexpire(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let millis = Self::extract_unsigned_integer(args.pop_front())?;
-		let timepoint = SystemTime::now() + Duration::from_millis(millis);
+		let millis = Self::extract_integer(args.pop_front())?;
+		let timepoint = Self::deadline(self.clock.now(), millis, Duration::from_millis)?;
 		self.keys_expire_impl(key, timepoint).await
 	}
 
-	pub async fn keys_pexpire_at(&mut self, mut args: Arguments) -> ExecResult {
+	pub async fn keys_pexpire_at(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let millis = Self::extract_unsigned_integer(args.pop_front())?;
-		let timepoint = SystemTime::UNIX_EPOCH + Duration::from_millis(millis);
+		let millis = Self::extract_integer(args.pop_front())?;
+		let timepoint = self.clock.from_wall(Self::deadline(SystemTime::UNIX_EPOCH, millis, Duration::from_millis)?);
 		self.keys_expire_impl(key, timepoint).await
 	}
 
 	pub async fn keys_check_expirations(&self) {
 		log::debug!("Begin expiration check");
 
-		let (now, expired) = {
+		if self.config.read_only() && !self.config.read_only_expire() {
+			// expired keys stay queued until writes are allowed again
+			let retry = self.clock.now() + Duration::from_secs(1);
+			if let Some(awaker) = &mut *self.expire_awaker.lock().await {
+				(*awaker)(retry);
+			}
+			return;
+		}
+
+		let batch = self.config.active_expire_batch();
+		let (now, expired, next) = {
 			let mut controller = self.expire_controller.lock().await;
-			controller.pop_now_and_expired_keys()
+			controller.pop_expired_keys(self.clock.now(), batch)
 		};
 
 		log::debug!("{:?}: {:?}", now, expired);
+		let checked = expired.len();
+		let mut removed = 0u64;
 
+		// containers lock is taken once per batch and before a container lock, in the same order as export
+		let mut containers = self.containers.lock().await;
 		for key in expired {
-			if let Some(c) = self.try_get_container(&key).await {
-				let c = c.lock().await;
+			if let Some(c) = containers.get(&key).cloned() {
+				let c = c.read().await;
 				let tm = Self::get_expiration_time(&*c);
 				log::debug!("{:?}: {:?} vs {:?}", key, tm, now);
 				match tm {
@@ -352,42 +635,176 @@ impl super::Storage {
 							log::warn!("{:?}: will removed at {:?}", key, time);
 						} else {
 							log::debug!("{:?}: expired and removed", key);
-							let mut containers = self.containers.lock().await;
 							containers.remove(&key);
+							self.memory_track_remove(&key, &c);
+							removed += 1;
+							self.counters.key_expired();
+							self.events.publish(&key, KeyEventKind::Expired);
 						}
 					},
 					None => (),
 				}
 			}
 		}
-		log::debug!("Check expiration done");
+		drop(containers);
+
+		// the awaker may coalesce wakeups, so the next deadline is always rescheduled.
+		// Expired keys over the batch are left to the next cycle, so other commands get the lock in between
+		if let Some(next) = next {
+			if next <= now {
+				log::debug!("more than {} keys expired, continue in the next cycle", batch);
+			}
+			if let Some(awaker) = &mut *self.expire_awaker.lock().await {
+				(*awaker)(std::cmp::max(next, self.clock.now()));
+			}
+		}
+		log::debug!(checked, removed, batch_full = checked == batch; "Check expiration done");
 	}
 
-	pub async fn keys_scan(&self, mut args: Arguments) -> ExecResult {
-		let start = Self::extract_index(args.pop_front())?;
+	pub async fn keys_sort(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let mut options = SORT_OPTIONS.parse(args)?;
+		let descending = options.flag("DESC");
+		let alpha = options.flag("ALPHA");
+		let limit = match options.values("LIMIT") {
+			None => None,
+			Some(mut limit) => Some((Self::extract_integer(limit.pop_front())?, Self::extract_integer(limit.pop_front())?)),
+		};
+		let store = match options.value("STORE") {
+			None => None,
+			Some(store) => Some(Self::extract_key(Some(store))?),
+		};
 
-		let mut pattern: Option<String> = None;
-		let mut key_type: Option<String> = None;
-		let mut max_check = 100usize;
+		let write_keys: Vec<Key> = store.iter().cloned().collect();
+		self.with_containers(&write_keys, std::slice::from_ref(&key), |locked| -> ExecResult {
+			let mut elements: Vec<Value> = match locked.get(&key) {
+				None => vec![],
+				Some(Container::List(c)) => c.inner.iter().cloned().collect(),
+				Some(Container::Set(c)) => c.inner.iter().cloned().collect(),
+				Some(_) => return Err(WRONG_TYPE_ERROR.to_owned()),
+			};
+			if alpha {
+				elements.sort_by_cached_key(sort_bytes);
+			} else {
+				let mut scored = elements
+					.into_iter()
+					.map(|v|sort_score(&v).map(|score|(score, v)))
+					.collect::<Result<Vec<(f64, Value)>, String>>()?;
+				scored.sort_by(|a, b|a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+				elements = scored.into_iter().map(|(_, v)|v).collect();
+			}
+			if descending {
+				elements.reverse();
+			}
+			if let Some((offset, count)) = limit {
+				let offset = std::cmp::max(offset, 0) as usize;
+				let count = if count < 0 {elements.len()} else {count as usize};
+				elements = elements.into_iter().skip(offset).take(count).collect();
+			}
 
-		while let Some(subcmd) = Self::extract_string(args.pop_front()).ok() {
-			match &subcmd.to_uppercase()[..] {
-				"MATCH" => pattern = Some(Self::extract_string(args.pop_front())?),
-				"COUNT" => max_check = Self::extract_index(args.pop_front())?,
-				"TYPE" => key_type = Some(Self::extract_string(args.pop_front())?),
-				arg => return Err(format!("Unexpected argument '{}'", arg)),
+			match &store {
+				None => Ok(Value::Array(elements.into())),
+				Some(destination) => {
+					let count = elements.len();
+					if elements.is_empty() {
+						locked.remove(destination)?;
+					} else {
+						let mut list = ContainerImpl::<VecDeque<Value>>::new();
+						list.inner = elements.into();
+						locked.replace(destination, Container::List(list))?;
+					}
+					Ok(Value::Integer(count as i64))
+				},
 			}
+		}).await
+	}
+
+	pub async fn keys_copy(&self, mut args: Arguments) -> ExecResult {
+		let source = Self::extract_key(args.pop_front())?;
+		let destination = Self::extract_key(args.pop_front())?;
+		let replace = COPY_OPTIONS.parse(args)?.flag("REPLACE");
+		if source == destination {
+			return Err("ERR source and destination objects are the same".to_owned());
 		}
-		if let Some(key_type) = &key_type {
-			Self::check_type(&key_type[..])?;
+
+		let copied = self.with_containers(std::slice::from_ref(&destination), std::slice::from_ref(&source), |locked| -> Result<Option<Option<SystemTime>>, String> {
+			let copy = match locked.get(&source) {
+				None => return Ok(None),
+				Some(source) => source.duplicate(),
+			};
+			if !replace && locked.get(&destination).is_some() {
+				return Ok(None);
+			}
+			let expiration_time = Self::get_expiration_time(&copy);
+			locked.replace(&destination, copy)?;
+			Ok(Some(expiration_time))
+		}).await?;
+
+		match copied {
+			None => Ok(Value::Integer(0)),
+			Some(expiration_time) => {
+				if let Some(timepoint) = expiration_time {
+					self.expire_key_at(&destination, timepoint).await;
+				}
+				Ok(Value::Integer(1))
+			},
 		}
+	}
 
-		let pattern = match pattern {
-			None => None,
-			Some(pattern) => Some(regex::bytes::Regex::new(&pattern[..]).map_err(|e|format!("{}", e))?),
+	pub async fn keys_dump(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		match self.try_get_live_container(&key).await {
+			None => Ok(Value::Nill),
+			Some(c) => {
+				let c = c.read().await;
+				Ok(Value::Buffer(encode_dump(&c, self.clock.now())?))
+			},
+		}
+	}
+
+	/// A zero TTL keeps the TTL remaining at the moment of DUMP, if any
+	pub async fn keys_restore(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let ttl = Self::extract_integer(args.pop_front())?;
+		let payload = Self::extract_buffer(args.pop_front())?;
+		let replace = RESTORE_OPTIONS.parse(args)?.flag("REPLACE");
+		if ttl < 0 {
+			return Err("ERR Invalid TTL value, must be >= 0".to_owned());
+		}
+
+		let (data, dumped_ttl) = decode_dump(&payload)?;
+		let ttl = match ttl {
+			0 => dumped_ttl,
+			ttl => Some(ttl as u64),
 		};
+		let expiration_time = ttl.map(|ttl|self.clock.now() + Duration::from_millis(ttl));
+		let container = import_container(data, expiration_time);
+
+		self.with_containers(std::slice::from_ref(&key), &[], |locked| {
+			if !replace && locked.get(&key).is_some() {
+				return Err("BUSYKEY Target key name already exists".to_owned());
+			}
+			locked.replace(&key, container)
+		}).await?;
+
+		if let Some(timepoint) = expiration_time {
+			self.expire_key_at(&key, timepoint).await;
+		}
+		Ok(Value::Ok)
+	}
+
+	pub async fn keys_scan(&self, args: Arguments) -> ExecResult {
+		self.keys_scan_in(args, &[]).await
+	}
+
+	/// SCAN over the keys starting with `prefix`, matched and returned without it.
+	/// The cursor walks the whole keyspace, so COUNT includes the keys out of the prefix
+	pub(crate) async fn keys_scan_in(&self, mut args: Arguments, prefix: &[u8]) -> ExecResult {
+		let start = Self::extract_index(args.pop_front())?;
+		let ScanOptions {pattern, count: max_check, key_type} = ScanOptions::parse(args, true)?;
 
 		let containers = self.containers.lock().await;
+		let now = self.clock.now();
 
 		let mut keys = vec![];
 
@@ -395,26 +812,26 @@ impl super::Storage {
 		let mut next = end;
 		for i in start..end {
 			if let Some((key, container)) = containers.get_index(i) {
-				if let Some(key_type) = &key_type {
-					match container.try_lock() {
-						Err(_) => {
-							next = i;
-							break
-						},
-						Ok(container) => {
-							let t = Self::type_to_string(&container);
-							if key_type != t {
-								continue;
-							}
-						}
-					}
+				if !key.starts_with(prefix) {
+					continue;
 				}
+				let key = &key[prefix.len()..];
 				if let Some(pattern) = &pattern {
-					if ! pattern.is_match(&key[..]) {
+					if ! pattern.is_match(key) {
+						continue;
+					}
+				}
+				// readers of the key do not block the scan, only a pending write does
+				let container = container.read().await;
+				if !Self::is_live(&container, now) {
+					continue;
+				}
+				if let Some(key_type) = &key_type {
+					if *key_type != container.kind() {
 						continue;
 					}
 				}
-				keys.push(key.clone());
+				keys.push(key.to_vec());
 			} else {
 				next = 0;
 				break;
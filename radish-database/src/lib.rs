@@ -21,9 +21,40 @@ mod list;
 mod keys;
 mod hash;
 mod set;
+mod connection;
+mod server;
+mod embedded;
+mod dataset;
+mod memory;
+mod access;
+mod glob;
+mod iter;
+mod stats;
+mod config;
+mod commands;
+mod limits;
+mod options;
+mod hooks;
+mod locking;
+mod compaction;
+mod cmdstat;
+mod session;
+mod scripting;
+mod snapshot;
+mod rdb;
+mod audit;
+mod events;
+mod json;
+mod hotkeys;
+mod document;
+mod bloom;
+mod timeseries;
+mod clock;
+mod namespace;
+mod blocking;
 
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{SystemTime, Instant};
 
 use tokio::sync::Mutex;
 use indexmap::IndexMap;
@@ -31,147 +62,173 @@ use indexmap::IndexMap;
 use container::ContainersPtr;
 
 pub type Key = radish_types::Key;
+pub type CompactKey = radish_types::CompactKey;
 pub type Value = radish_types::Value;
 pub type Arguments = radish_types::Arguments;
 pub type ExecResult = radish_types::ExecResult;
 pub type Command = radish_types::Command;
 
+pub use embedded::{Error as StorageError, Result as StorageResult};
+pub use iter::{KeyInfo, KeyType};
+pub use container::ContainerKind;
+pub use audit::RotatingFile;
+pub use dataset::{DatasetSnapshot, SnapshotEntry, SnapshotData, ImportMode};
+pub use snapshot::{SnapshotHeader, FLAG_ZSTD, encode_snapshot, decode_snapshot};
+pub use json::{snapshot_to_json, snapshot_from_json};
+pub use bloom::{BloomFilter, BloomLayer};
+pub use timeseries::TimeSeries;
+pub use rdb::{RdbSummary, parse_rdb};
+pub use stats::{StorageStats, TypeStats};
+pub use config::{Config, ConfigChange, ReloadReport};
+pub use commands::{CommandSpec, Handler};
+pub use hooks::{ExecutionHook, HookId};
+pub use session::Session;
+pub use events::{KeyEvent, KeyEventKind, KeyEventReceiver};
+pub use clock::{Clock, SystemClock, MockClock, Timeline};
+pub use namespace::NamespacedStorage;
+
 #[derive(Clone)]
 pub struct Storage {
 	containers: ContainersPtr,
 	expire_controller: Arc<Mutex<expire::ExpireController>>,
 	expire_awaker: Arc<Mutex<Option<Box<dyn FnMut(SystemTime) + Send + 'static>>>>,
+	counters: Arc<server::Counters>,
+	memory: Arc<memory::MemoryCounter>,
+	access_clock: Arc<access::AccessClock>,
+	config: Arc<config::Config>,
+	commands: Arc<commands::CommandTable>,
+	hooks: Arc<hooks::HookList>,
+	command_stats: Arc<cmdstat::CommandStats>,
+	scripts: Arc<scripting::ScriptCache>,
+	events: Arc<events::EventBus>,
+	hotkeys: Arc<hotkeys::HotKeys>,
+	snapshot: Arc<snapshot::SnapshotState>,
+	clock: Arc<clock::Timeline>,
+	blocked: Arc<blocking::BlockedClients>,
 }
 
 impl Storage {
 	pub fn new() -> Self {
+		Self::with_clock(Arc::new(clock::SystemClock))
+	}
+
+	/// Storage reading the time from `clock`, e.g. a `MockClock` in tests
+	pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+		let commands = commands::CommandTable::new();
 		Self {
 			containers: Arc::new(Mutex::new(IndexMap::new())),
 			expire_controller: Arc::new(Mutex::new(expire::ExpireController::new())),
 			expire_awaker: Arc::new(Mutex::new(None)),
+			counters: Arc::new(server::Counters::new()),
+			memory: Arc::new(memory::MemoryCounter::new()),
+			access_clock: Arc::new(access::AccessClock::new(clock.clone())),
+			config: Arc::new(config::Config::new()),
+			command_stats: Arc::new(cmdstat::CommandStats::new(commands.len())),
+			commands: Arc::new(commands),
+			hooks: Arc::new(hooks::HookList::new()),
+			scripts: Arc::new(scripting::ScriptCache::default()),
+			events: Arc::new(events::EventBus::new()),
+			hotkeys: Arc::new(hotkeys::HotKeys::new()),
+			snapshot: Arc::new(snapshot::SnapshotState::default()),
+			clock: Arc::new(clock::Timeline::new(clock)),
+			blocked: Arc::new(blocking::BlockedClients::default()),
 		}
 	}
 
+	/// The awaker is shared with the clones of the storage made before the call,
+	/// e.g. the one the awaker itself runs the expiration check on. Should be called at startup.
+	/// Deadlines passed to the awaker are points of the `clock()` timeline.
 	pub fn set_expire_awaker<A>(&mut self, a: A)
 	where A: FnMut(SystemTime) + Send + 'static {
-		self.expire_awaker = Arc::new(Mutex::new(Some(Box::new(a))));
+		*self.expire_awaker.try_lock().expect("awaker is not in use while the storage is set up") = Some(Box::new(a));
 	}
 
 	pub async fn unimplemented(&self) -> ExecResult {
 		Err("Unimplemented".to_owned())
 	}
 
-	pub async fn execute(&mut self, command: Command) -> Value {
-		let result = match &command.command.to_uppercase()[..] {
-			"NOW" => self.keys_now(command.arguments).await,
-			"PNOW" => self.keys_pnow(command.arguments).await,
-			"DEL" => self.keys_del(command.arguments).await,
-			"KEYS" => self.keys_keys(command.arguments).await,
-			"EXISTS" => self.keys_exists(command.arguments).await,
-			"RENAME" => self.keys_rename(command.arguments).await,
-			"DUMP" => self.unimplemented().await,
-			"EXPIRE" => self.keys_expire(command.arguments).await,
-			"EXPIREAT" => self.keys_expire_at(command.arguments).await,
-			"MIGRATE" => self.unimplemented().await,
-			"MOVE" => self.unimplemented().await,
-			"OBJECT" => self.unimplemented().await,
-			"PERSIST" => self.unimplemented().await,
-			"PEXPIRE" => self.keys_pexpire(command.arguments).await,
-			"PEXPIREAT" => self.keys_pexpire_at(command.arguments).await,
-			"PTTL" => self.keys_pttl(command.arguments).await,
-			"RANDOMKEY" => self.unimplemented().await,
-			"RENAMENX" => self.unimplemented().await,
-			"RESTORE" => self.unimplemented().await,
-			"SORT" => self.unimplemented().await,
-			"TOUCH" => self.unimplemented().await,
-			"TTL" => self.keys_ttl(command.arguments).await,
-			"TYPE" => self.keys_type(command.arguments).await,
-			"UNLINK" => self.unimplemented().await,
-			"WAIT" => self.unimplemented().await,
-			"SCAN" => self.keys_scan(command.arguments).await,
-
-			"APPEND" => self.strings_append(command.arguments).await,
-			"GET" => self.strings_get(command.arguments).await,
-			"GETSET" => self.strings_getset(command.arguments).await,
-			"STRLEN" => self.strings_len(command.arguments).await,
-			"BITCOUNT" => self.strings_bitcount(command.arguments).await,
-			"BITFIELD" => self.unimplemented().await,
-			"BITOP" => self.strings_bitop(command.arguments).await,
-			"BITPOS" => self.unimplemented().await,
-			"DECR" => self.strings_decrby(command.arguments).await,
-			"DECRBY" => self.strings_decrby(command.arguments).await,
-			"GETBIT" => self.strings_getbit(command.arguments).await,
-			"GETRANGE" => self.strings_get_range(command.arguments).await,
-			"INCR" => self.strings_incrby(command.arguments).await,
-			"INCRBY" => self.strings_incrby(command.arguments).await,
-			"INCRBYFLOAT" => self.strings_incrby_float(command.arguments).await,
-			"MGET" => self.strings_mget(command.arguments).await,
-			"MSET" => self.strings_mset(command.arguments).await,
-			"MSETNX" => self.unimplemented().await,
-			"PSETEX" => self.strings_psetex(command.arguments).await,
-			"SET" => self.strings_set(command.arguments).await,
-			"SETBIT" => self.strings_setbit(command.arguments).await,
-			"SETEX" => self.strings_setex(command.arguments).await,
-			"SETNX" => self.strings_setnx(command.arguments).await,
-			"SETRANGE" => self.strings_set_range(command.arguments).await,
-
-			"LLEN" => self.list_len(command.arguments).await,
-			"LPOP" => self.list_lpop(command.arguments).await,
-			"RPOP" => self.list_rpop(command.arguments).await,
-			"LREM" => self.list_rem(command.arguments).await,
-			"LSET" => self.list_set(command.arguments).await,
-			"LPUSH" => self.list_lpush(command.arguments).await,
-			"RPUSH" => self.list_rpush(command.arguments).await,
-			"LPUSHX" => self.list_lpushx(command.arguments).await,
-			"RPUSHX" => self.list_rpushx(command.arguments).await,
-			"LINDEX" => self.list_index(command.arguments).await,
-			"LRANGE" => self.list_range(command.arguments).await,
-			"LINSERT" => self.list_insert(command.arguments).await,
-			"LTRIM" => self.list_trim(command.arguments).await,
-			"RPOPLPUSH" => self.unimplemented().await,
-			"BRPOP" => self.unimplemented().await,
-			"BLPOP" => self.unimplemented().await,
-			"BRPOPLPUSH" => self.unimplemented().await,
-
-			"SADD" => self.set_add(command.arguments).await,
-			"SREM" => self.set_rem(command.arguments).await,
-			"SPOP" => self.set_pop(command.arguments).await,
-			"SSCAN" => self.set_scan(command.arguments).await,
-			"SCARD" => self.set_card(command.arguments).await,
-			"SMOVE" => self.set_move(command.arguments).await,
-			"SMEMBERS" => self.set_members(command.arguments).await,
-			"SISMEMBER" => self.set_is_member(command.arguments).await,
-			"SDIFF" => self.set_diff(command.arguments).await,
-			"SINTER" => self.set_inter(command.arguments).await,
-			"SUNION" => self.set_union(command.arguments).await,
-			"SDIFFSTORE" => self.set_diff_store(command.arguments).await,
-			"SINTERSTORE" => self.set_inter_store(command.arguments).await,
-			"SUNIONSTORE" => self.set_union_store(command.arguments).await,
-			"SRANDMEMBER" => self.unimplemented().await,
-
-			"HSET" => self.hash_set(command.arguments).await,
-			"HSETNX" => self.hash_set_nx(command.arguments).await,
-			"HDEL" => self.hash_del(command.arguments).await,
-			"HGET" => self.hash_get(command.arguments).await,
-			"HGETALL" => self.hash_get_all(command.arguments).await,
-			"HEXISTS" => self.hash_exists(command.arguments).await,
-			"HKEYS" => self.hash_keys(command.arguments).await,
-			"HVALUES" => self.hash_values(command.arguments).await,
-			"HLEN" => self.hash_len(command.arguments).await,
-			"HSTRLEN" => self.hash_strlen(command.arguments).await,
-			"HINCRBY" => self.hash_incrby(command.arguments).await,
-			"HINCRBYFLOAT" => self.hash_incrbyfloat(command.arguments).await,
-			"HMGET" => self.hash_mget(command.arguments).await,
-			"HMSET" => self.hash_set(command.arguments).await,
-			"HSCAN" => self.hash_scan(command.arguments).await,
-
-			"" => Err(format!("HELP docs.....")),
-			_ => Err(format!("Unsupported command")),
+	/// Executes the command in the context of the connection's session;
+	/// embedders without connections may pass `Session::default()`
+	pub async fn execute(&self, session: &mut Session, command: Command) -> Value {
+		self.execute_in(session, command, &[]).await
+	}
+
+	/// Executes the command with the keys inside the namespace `prefix`, see `namespace()`
+	async fn execute_in(&self, session: &mut Session, command: Command, prefix: &[u8]) -> Value {
+		self.counters.command_processed();
+		let result = match self.commands.find(&command.command.to_uppercase()) {
+			None if command.command.is_empty() => Err("HELP docs.....".to_owned()),
+			None => Err(self.commands.unknown_command(&command.command, &command.arguments)),
+			Some((id, spec)) => {
+				let started = Instant::now();
+				let result = self.dispatch(spec, session, command, prefix).await;
+				self.command_stats.record(id, started.elapsed(), result.is_err());
+				result
+			},
 		};
+		self.memory_check().await;
 		match result {
 			Ok(r) => r,
 			Err(err) => Value::Error(err),
 		}
 	}
-}
 
+	async fn dispatch(&self, spec: &CommandSpec, session: &mut Session, command: Command, prefix: &[u8]) -> ExecResult {
+		self.connection_check_auth(session, spec.name)?;
+		self.commands_precheck(spec, &command.arguments)?;
+		let command = match prefix {
+			[] => command,
+			prefix => match spec.name {
+				"KEYS" => return self.keys_keys_in(command.arguments, prefix).await,
+				"SCAN" => return self.keys_scan_in(command.arguments, prefix).await,
+				_ => namespace::prefix_keys(spec, command, prefix)?,
+			},
+		};
+		if self.config.hotkeys_tracking() {
+			self.hotkeys.record(spec, &command.arguments);
+		}
+		let event_keys = self.events.command_keys(spec, &command.arguments);
+		// a blocking command checks at the end, as others may block while it waits
+		let blocking_keys = match spec.is_write() && (self.blocked.blocked() > 0 || spec.is_blocking()) {
+			true => Some(spec.keys(&command.arguments)),
+			false => None,
+		};
+		let result = self.run_handler(spec, session, command).await;
+		if let (Some(keys), Ok(reply)) = (event_keys, &result) {
+			self.events.publish_command(spec, keys, reply);
+		}
+		if let (Some(keys), Ok(_)) = (blocking_keys, &result) {
+			self.blocking_signal(&keys).await;
+		}
+		match prefix {
+			[] => result,
+			prefix => result.map(|reply|namespace::strip_reply_key(spec, reply, prefix)),
+		}
+	}
+
+	async fn run_handler(&self, spec: &CommandSpec, session: &mut Session, command: Command) -> ExecResult {
+		let hooks = self.hooks.current();
+		if hooks.is_empty() {
+			return (spec.handler)(self, session, command.arguments).await;
+		}
+
+		for (_, hook) in hooks.iter() {
+			hook.before(session, &command, spec)?;
+		}
+		let started = Instant::now();
+		let result = (spec.handler)(self, session, command.arguments.clone()).await;
+		let elapsed = started.elapsed();
+		let error;
+		let reply = match &result {
+			Ok(reply) => reply,
+			Err(err) => {
+				error = Value::Error(err.clone());
+				&error
+			},
+		};
+		for (_, hook) in hooks.iter() {
+			hook.after(session, &command, spec, reply, elapsed);
+		}
+		result
+	}
+}
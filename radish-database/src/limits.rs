@@ -0,0 +1,94 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use super::commands::CommandSpec;
+
+type Value = super::Value;
+type Arguments = super::Arguments;
+
+/// Snapshot of the size limits from the runtime config; zero means unlimited
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+	pub max_key_length: usize,
+	pub max_value_size: usize,
+	pub max_collection_elements: usize,
+}
+
+fn exceeds(size: usize, limit: usize) -> bool {
+	limit != 0 && size > limit
+}
+
+/// Payload size of a string value or of a collection element
+pub fn element_size(value: &Value) -> usize {
+	match value {
+		Value::Buffer(b) => b.len(),
+		Value::Array(a) => a.iter().map(element_size).sum(),
+		_ => 0,
+	}
+}
+
+impl Limits {
+	pub fn check_key(&self, key: &[u8]) -> Result<(), String> {
+		if exceeds(key.len(), self.max_key_length) {
+			return Err(format!("Key length {} exceeds max-key-length {}", key.len(), self.max_key_length));
+		}
+		Ok(())
+	}
+
+	pub fn check_value_size(&self, size: usize) -> Result<(), String> {
+		if exceeds(size, self.max_value_size) {
+			return Err(format!("Value size {} exceeds max-value-size {}", size, self.max_value_size));
+		}
+		Ok(())
+	}
+
+	pub fn check_elements<'a>(&self, elements: impl IntoIterator<Item=&'a Value>) -> Result<(), String> {
+		if self.max_value_size == 0 {
+			return Ok(());
+		}
+		elements
+			.into_iter()
+			.try_for_each(|e|self.check_value_size(element_size(e)))
+	}
+
+	pub fn check_collection_len(&self, len: usize) -> Result<(), String> {
+		if exceeds(len, self.max_collection_elements) {
+			return Err(format!("Number of elements {} exceeds max-collection-elements {}", len, self.max_collection_elements));
+		}
+		Ok(())
+	}
+}
+
+impl super::Storage {
+	pub fn limits(&self) -> Limits {
+		self.config.limits()
+	}
+
+	/// Pre-dispatch validation of the key arguments of a command
+	pub fn limits_check_keys(&self, spec: &CommandSpec, args: &Arguments) -> Result<(), String> {
+		let limits = self.limits();
+		if limits.max_key_length == 0 {
+			return Ok(());
+		}
+		for index in spec.key_indices(args.len()) {
+			if let Some(Value::Buffer(key)) = args.get(index) {
+				limits.check_key(key)?;
+			}
+		}
+		Ok(())
+	}
+}
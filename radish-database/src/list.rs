@@ -15,8 +15,11 @@
  */
 
 use std::collections::VecDeque;
+use std::ops::Range;
+use std::time::Duration;
 
 use super::container::Container;
+use super::container::WRONG_TYPE_ERROR;
 use super::container::ContainerPtr;
 use super::container::ContainerImpl;
 
@@ -27,44 +30,58 @@ type ExecResult = super::ExecResult;
 
 type Inner = VecDeque<Value>;
 
-impl super::Storage {
-	async fn list_get_container(&self, key: Key) -> ContainerPtr {
-		self.get_container(key, ||Container::List(ContainerImpl::<Inner>::new())).await
+/// Positions from `start` to `stop` inclusive, negative ones count from the tail;
+/// clamped to the list, None if no element is in the range
+fn list_bounds(list: &Inner, start: i64, stop: i64) -> Option<Range<usize>> {
+	let len = list.len() as i64;
+	let start = if start < 0 {std::cmp::max(len + start, 0)} else {start};
+	let stop = if stop < 0 {len + stop} else {std::cmp::min(stop, len - 1)};
+	if start > stop || start >= len {
+		None
+	} else {
+		Some(start as usize..stop as usize + 1)
 	}
-	async fn list_try_get_container(&self, key: &Key) -> Option<ContainerPtr> {
-		self.try_get_container(key).await
+}
+
+/// Position of the index in the list, a negative one counts from the tail; None if it is before the head
+fn list_position(list: &Inner, index: i64) -> Option<usize> {
+	match index {
+		index if index < 0 => list.len().checked_sub(index.unsigned_abs() as usize),
+		index => Some(index as usize),
 	}
-	async fn list_unwrap_container(container: &Container) -> Result<&ContainerImpl<Inner>, String> {
-		match container {
-			Container::List(ref c) => Ok(c),
-			_ => Err(format!("Unexpected container type")),
-		}
+}
+
+impl super::Storage {
+	async fn list_get_container(&self, key: Key) -> Result<ContainerPtr, String> {
+		self.get_typed_container::<Inner>(key).await
 	}
-	async fn list_unwrap_mut_container(container: &mut Container) -> Result<&mut ContainerImpl<Inner>, String> {
-		match container {
-			Container::List(ref mut c) => Ok(c),
-			_ => Err(format!("Unexpected container type")),
-		}
+	async fn list_try_get_container(&self, key: &Key) -> Option<ContainerPtr> {
+		self.try_get_live_container(key).await
 	}
 	async fn list_lock<F: FnOnce(&Inner) -> ExecResult>(&self, key: Key, processor: F) -> ExecResult {
-		let c1 = self.list_get_container(key).await;
-		let c2 = c1.lock().await;
-		let c3 = Self::list_unwrap_container(&c2).await?;
-		processor(&c3.inner)
+		self.read_container(&key, processor).await
 	}
 	async fn list_lock_mut<F: FnOnce(&mut Inner) -> ExecResult>(&self, key: Key, processor: F) -> ExecResult {
-		let c1 = self.list_get_container(key).await;
-		let mut c2 = c1.lock().await;
-		let c3 = Self::list_unwrap_mut_container(&mut c2).await?;
-		processor(&mut c3.inner)
+		let c1 = self.list_get_container(key).await?;
+		let mut c2 = c1.write().await;
+		self.snapshot_preserve(&c1, &c2);
+		self.access_touch(&c2);
+		let c3 = c2.typed_mut::<Inner>()?;
+		let result = processor(&mut c3.inner);
+		self.memory_track(&mut c2);
+		result
 	}
 	async fn list_try_lock_mut<F: FnOnce(&mut Inner) -> ExecResult>(&self, key: Key, processor: F) -> ExecResult {
 		match self.list_try_get_container(&key).await {
 			None => Ok(Value::Nill),
 			Some(c1) => {
-				let mut c2 = c1.lock().await;
-				let c3 = Self::list_unwrap_mut_container(&mut c2).await?;
-				processor(&mut c3.inner)
+				let mut c2 = c1.write().await;
+				self.snapshot_preserve(&c1, &c2);
+				self.access_touch(&c2);
+				let c3 = c2.typed_mut::<Inner>()?;
+				let result = processor(&mut c3.inner);
+				self.memory_track(&mut c2);
+				result
 			}
 		}
 	}
@@ -78,7 +95,10 @@ impl super::Storage {
 
 	pub async fn list_lpush(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
+		let limits = self.limits();
+		limits.check_elements(&args)?;
 		self.list_lock_mut(key, |list| -> ExecResult {
+			limits.check_collection_len(list.len() + args.len())?;
 			for arg in args {
 				list.push_front(arg);
 			}
@@ -88,7 +108,10 @@ impl super::Storage {
 
 	pub async fn list_rpush(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
+		let limits = self.limits();
+		limits.check_elements(&args)?;
 		self.list_lock_mut(key, |list| -> ExecResult {
+			limits.check_collection_len(list.len() + args.len())?;
 			for arg in args {
 				list.push_back(arg);
 			}
@@ -98,7 +121,10 @@ impl super::Storage {
 
 	pub async fn list_lpushx(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
+		let limits = self.limits();
+		limits.check_elements(&args)?;
 		self.list_try_lock_mut(key, |list| -> ExecResult {
+			limits.check_collection_len(list.len() + args.len())?;
 			for arg in args {
 				list.push_front(arg);
 			}
@@ -108,7 +134,10 @@ impl super::Storage {
 
 	pub async fn list_rpushx(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
+		let limits = self.limits();
+		limits.check_elements(&args)?;
 		self.list_try_lock_mut(key, |list| -> ExecResult {
+			limits.check_collection_len(list.len() + args.len())?;
 			for arg in args {
 				list.push_back(arg);
 			}
@@ -136,24 +165,46 @@ impl super::Storage {
 		}).await
 	}
 
+	/// LREM key count value: removes up to `count` occurrences from the head, from the tail
+	/// for a negative count, or all of them for zero
 	pub async fn list_rem(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let index = Self::extract_index(args.pop_front())?;
+		let count = Self::extract_integer(args.pop_front())?;
+		let value = Self::extract(args.pop_front())?;
 		self.list_lock_mut(key, |list| -> ExecResult {
-			match list.remove(index) {
-				Some(v) => Ok(v),
-				None => Err(format!("{}", "Out of index")),
+			let limit = match count {
+				0 => usize::MAX,
+				count => count.unsigned_abs() as usize,
+			};
+			let mut removed = 0;
+			if count >= 0 {
+				list.retain(|v| {
+					let remove = removed < limit && *v == value;
+					removed += remove as usize;
+					!remove
+				});
+			} else {
+				let mut i = list.len();
+				while i > 0 && removed < limit {
+					i -= 1;
+					if list[i] == value {
+						list.remove(i);
+						removed += 1;
+					}
+				}
 			}
+			Ok(Value::Integer(removed as i64))
 		}).await
 	}
 
 	pub async fn list_set(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let index = Self::extract_index(args.pop_front())?;
+		let index = Self::extract_integer(args.pop_front())?;
 		let value = Self::extract(args.pop_front())?;
+		self.limits().check_elements(Some(&value))?;
 		self.list_lock_mut(key, |list| -> ExecResult {
-			match list.get_mut(index) {
-				None => Err(format!("{}\r\n", "Out of index")),
+			match list_position(list, index).and_then(|index|list.get_mut(index)) {
+				None => Err("ERR index out of range".to_owned()),
 				Some(v) => {
 					let mut x = value;
 					std::mem::swap(v, &mut x);
@@ -165,29 +216,24 @@ impl super::Storage {
 
 	pub async fn list_index(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let index = Self::extract_index(args.pop_front())?;
+		let index = Self::extract_integer(args.pop_front())?;
 		self.list_lock(key, |list| -> ExecResult {
-			match list.get(index) {
+			match list_position(list, index).and_then(|index|list.get(index)) {
 				Some(v) => Ok((*v).clone()),
-				None => Err(format!("{}\r\n", "Out of index")),
+				None => Ok(Value::Nill),
 			}
 		}).await
 	}
 
-	pub async fn list_range(&self, mut args: Arguments) -> ExecResult {
+	pub async fn list_lrange(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let start = Self::extract_index(args.pop_front())?;
-		let stop = Self::extract_index(args.pop_front())?;
+		let start = Self::extract_integer(args.pop_front())?;
+		let stop = Self::extract_integer(args.pop_front())?;
 		self.list_lock(key, |list| -> ExecResult {
-			let start = std::cmp::min(start, list.len());
-			let end = std::cmp::min(stop+1, list.len());
-			let mut out = VecDeque::with_capacity(end - start);
-			for i in start..end {
-				if let Some(v) = list.get(i) {
-					out.push_back((*v).clone());
-				}
+			match list_bounds(list, start, stop) {
+				None => Ok(Value::Array(VecDeque::new())),
+				Some(range) => Ok(Value::Array(list.range(range).cloned().collect())),
 			}
-			Ok(Value::Array(out))
 		}).await
 	}
 
@@ -196,6 +242,8 @@ impl super::Storage {
 		let before_after = Self::extract_string(args.pop_front())?;
 		let pivot = Self::extract(args.pop_front())?;
 		let value = Self::extract(args.pop_front())?;
+		let limits = self.limits();
+		limits.check_elements(Some(&value))?;
 		self.list_lock_mut(key, |list| -> ExecResult {
 			let shift = match &before_after.to_lowercase()[..] {
 				"before" => 0,
@@ -205,6 +253,7 @@ impl super::Storage {
 
 			let index = list.iter().position(|v| *v == pivot);
 			if let Some(index) = index {
+				limits.check_collection_len(list.len() + 1)?;
 				list.insert(index + shift, value);
 				Ok(Value::Integer(list.len() as i64))
 			} else {
@@ -218,38 +267,121 @@ impl super::Storage {
 		let start = Self::extract_integer(args.pop_front())?;
 		let stop = Self::extract_integer(args.pop_front())?;
 		self.list_lock_mut(key, |list| -> ExecResult {
-			let start = if start < 0 {list.len() as i64 + start} else {start} as usize;
-			let start = std::cmp::min(start, list.len());
-			let stop = if stop < 0 {list.len() as i64 + stop} else {stop} as usize;
-			let stop = std::cmp::min(stop, list.len());
-			if start > stop || start >= list.len() {
-				list.clear();
-			} else if start > 0 {
-				list.rotate_left(start);
-				list.truncate(stop+1 - start);
-			} else {
-				list.truncate(stop+1);
+			match list_bounds(list, start, stop) {
+				None => list.clear(),
+				Some(range) => {
+					list.truncate(range.end);
+					list.drain(..range.start);
+				},
 			}
-
 			Ok(Value::Ok)
 		}).await
 	}
 
-	pub async fn _list_rpop_lpush(&self, mut args: Arguments) -> ExecResult {
+	/// Both lists are locked at once; the destination is created only if an element is moved
+	pub async fn list_rpop_lpush(&self, mut args: Arguments) -> ExecResult {
 		let source = Self::extract_key(args.pop_front())?;
 		let destination = Self::extract_key(args.pop_front())?;
-		if source == destination {
-			self.list_try_lock_mut(source, |list| -> ExecResult {
-				if let Some(v) = list.pop_back() {
-					list.push_front(v.clone());
-					Ok(v)
-				} else {
-					Ok(Value::Nill)
-				}
-			}).await
-		} else {
-			Ok(Value::Error("Should be atomic!!!".to_string()))
+		let moved = self.list_move(&source, &destination).await?;
+		Ok(moved.unwrap_or(Value::Nill))
+	}
+
+	pub async fn list_brpop_lpush(&self, mut args: Arguments) -> ExecResult {
+		let source = Self::extract_key(args.pop_front())?;
+		let destination = Self::extract_key(args.pop_front())?;
+		let timeout = Self::extract_block_timeout(args.pop_front())?;
+		self.block_on(std::slice::from_ref(&source), timeout, ||self.list_move(&source, &destination)).await
+	}
+
+	pub async fn list_blpop(&self, args: Arguments) -> ExecResult {
+		self.list_block_pop(args, true).await
+	}
+
+	pub async fn list_brpop(&self, args: Arguments) -> ExecResult {
+		self.list_block_pop(args, false).await
+	}
+
+	async fn list_block_pop(&self, mut args: Arguments, front: bool) -> ExecResult {
+		let timeout = Self::extract_block_timeout(args.pop_back())?;
+		let mut keys = Vec::with_capacity(args.len());
+		while let Ok(key) = Self::extract_key(args.pop_front()) {
+			keys.push(key);
+		}
+		self.block_on(&keys, timeout, ||self.list_pop_first(&keys, front)).await
+	}
+
+	/// Timeout of the blocking commands in seconds, a fraction is allowed; zero is None, to wait forever
+	fn extract_block_timeout(arg: Option<Value>) -> Result<Option<Duration>, String> {
+		let seconds = match Self::extract(arg)? {
+			Value::Integer(seconds) => seconds as f64,
+			Value::Float(seconds) => f64::from_bits(seconds),
+			_ => return Err("ERR timeout is not a float or out of range".to_owned()),
+		};
+		if !seconds.is_finite() {
+			return Err("ERR timeout is not a float or out of range".to_owned());
+		}
+		if seconds < 0.0 {
+			return Err("ERR timeout is negative".to_owned());
+		}
+		match seconds == 0.0 {
+			true => Ok(None),
+			false => Ok(Some(Duration::from_secs_f64(seconds))),
 		}
 	}
-}
 
+	/// Pops from the first non-empty list of `keys`, replying with the key and the element
+	async fn list_pop_first(&self, keys: &[Key], front: bool) -> Result<Option<Value>, String> {
+		self.with_containers(keys, &[], |locked| {
+			for key in keys {
+				match locked.get(key) {
+					Some(Container::List(c)) if !c.inner.is_empty() => (),
+					None | Some(Container::List(_)) => continue,
+					Some(_) => return Err(WRONG_TYPE_ERROR.to_owned()),
+				}
+				let value = match locked.get_mut(key)? {
+					Some(Container::List(c)) if front => c.inner.pop_front(),
+					Some(Container::List(c)) => c.inner.pop_back(),
+					_ => unreachable!("the list is checked above"),
+				};
+				let value = value.expect("the list is not empty");
+				return Ok(Some(Value::Array(vec![Value::Buffer(key.clone()), value].into())));
+			}
+			Ok(None)
+		}).await
+	}
+
+	/// Moves the last element of the source to the head of the destination; None if the source is empty
+	async fn list_move(&self, source: &Key, destination: &Key) -> Result<Option<Value>, String> {
+		let limits = self.limits();
+		self.with_containers(&[source.clone(), destination.clone()], &[], |locked| {
+			let destination_len = match locked.get(destination) {
+				None => 0,
+				Some(Container::List(c)) => c.inner.len(),
+				Some(_) => return Err(WRONG_TYPE_ERROR.to_owned()),
+			};
+			match locked.get(source) {
+				Some(Container::List(c)) if !c.inner.is_empty() => (),
+				None | Some(Container::List(_)) => return Ok(None),
+				Some(_) => return Err(WRONG_TYPE_ERROR.to_owned()),
+			}
+			if source != destination {
+				limits.check_collection_len(destination_len + 1)?;
+			}
+
+			let value = match locked.get_mut(source)? {
+				Some(Container::List(c)) => c.inner.pop_back().expect("source is not empty"),
+				_ => unreachable!("source is checked above"),
+			};
+
+			match locked.get_mut(destination)? {
+				Some(Container::List(c)) => c.inner.push_front(value.clone()),
+				_ => {
+					let mut list = ContainerImpl::<Inner>::new();
+					list.inner.push_front(value.clone());
+					locked.replace(destination, Container::List(list))?;
+				},
+			}
+			Ok(Some(value))
+		}).await
+	}
+}
@@ -0,0 +1,158 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Locking of several keys of any type in one critical section
+
+use tokio::sync::RwLockWriteGuard;
+
+use super::container::Container;
+use super::container::ContainerPtr;
+
+type Key = super::Key;
+type Storage = super::Storage;
+
+struct Entry<'a> {
+	key: Key,
+	writable: bool,
+	guard: Option<RwLockWriteGuard<'a, Container>>,
+	created: Option<Container>,
+	removed: bool,
+}
+
+/// Containers locked by `Storage::with_containers`, addressed by key
+pub struct LockedContainers<'a> {
+	storage: &'a Storage,
+	entries: Vec<Entry<'a>>,
+}
+
+impl<'a> LockedContainers<'a> {
+	fn entry(&self, key: &[u8]) -> Option<&Entry<'a>> {
+		self.entries.iter().find(|e|e.key == key)
+	}
+
+	fn writable_entry(&mut self, key: &[u8]) -> Result<&mut Entry<'a>, String> {
+		match self.entries.iter_mut().find(|e|e.key == key) {
+			Some(entry) if entry.writable => Ok(entry),
+			_ => Err(format!("Key {:?} is not locked for writing", key)),
+		}
+	}
+
+	/// None if the key does not exist
+	pub fn get(&self, key: &[u8]) -> Option<&Container> {
+		let entry = self.entry(key)?;
+		if entry.removed {
+			return None;
+		}
+		entry.created.as_ref().or(entry.guard.as_deref())
+	}
+
+	pub fn get_mut(&mut self, key: &[u8]) -> Result<Option<&mut Container>, String> {
+		let entry = self.writable_entry(key)?;
+		if entry.removed {
+			return Ok(None);
+		}
+		match &mut entry.created {
+			Some(created) => Ok(Some(created)),
+			None => Ok(entry.guard.as_deref_mut()),
+		}
+	}
+
+	/// Creates the key or replaces its value of any type
+	pub fn replace(&mut self, key: &[u8], mut container: Container) -> Result<(), String> {
+		let storage = self.storage;
+		let entry = self.writable_entry(key)?;
+		match &mut entry.guard {
+			Some(guard) => {
+				// the existing entry is reused, so its accounting is replaced at once
+				storage.memory_track_remove(&entry.key, guard);
+				storage.memory_track_insert(&entry.key, &mut container);
+				**guard = container;
+			},
+			None => entry.created = Some(container),
+		}
+		entry.removed = false;
+		Ok(())
+	}
+
+	pub fn remove(&mut self, key: &[u8]) -> Result<(), String> {
+		let entry = self.writable_entry(key)?;
+		entry.created = None;
+		entry.removed = true;
+		Ok(())
+	}
+}
+
+impl super::Storage {
+	/// Locks the containers of the keys in a deadlock-safe order and calls `f` while
+	/// holding the keyspace lock, so missing keys can be created in the same critical
+	/// section. Only `write_keys` can be changed, created or removed.
+	pub async fn with_containers<F, R>(&self, write_keys: &[Key], read_keys: &[Key], f: F) -> R
+	where F: FnOnce(&mut LockedContainers) -> R {
+		let mut containers = self.containers.lock().await;
+
+		let mut keys: Vec<(Key, bool)> = Vec::with_capacity(write_keys.len() + read_keys.len());
+		for (key, writable) in write_keys.iter().map(|k|(k, true)).chain(read_keys.iter().map(|k|(k, false))) {
+			match keys.iter_mut().find(|(k, _)|k == key) {
+				Some((_, w)) => *w |= writable,
+				None => keys.push((key.clone(), writable)),
+			}
+		}
+		let mut ptrs: Vec<Option<ContainerPtr>> = Vec::with_capacity(keys.len());
+		for (key, _) in &keys {
+			match self.reap_expired(&mut containers, key).await {
+				true => ptrs.push(None),
+				false => ptrs.push(containers.get(&key[..]).cloned()),
+			}
+		}
+		let (_, guards) = Self::lock_all(std::iter::empty(), ptrs.iter().map(|p|p.as_deref())).await;
+		for (((_, writable), ptr), guard) in keys.iter().zip(&ptrs).zip(&guards) {
+			if let (true, Some(ptr), Some(guard)) = (writable, ptr, guard) {
+				self.snapshot_preserve(ptr, guard);
+			}
+		}
+
+		let mut locked = LockedContainers {
+			storage: self,
+			entries: keys
+				.into_iter()
+				.zip(guards)
+				.map(|((key, writable), guard)|Entry {key, writable, guard, created: None, removed: false})
+				.collect(),
+		};
+		for guard in locked.entries.iter().filter_map(|e|e.guard.as_deref()) {
+			self.access_touch(guard);
+		}
+
+		let result = f(&mut locked);
+
+		for entry in locked.entries.iter_mut().filter(|e|e.writable) {
+			if entry.removed {
+				if let Some(guard) = &entry.guard {
+					self.memory_track_remove(&entry.key, guard);
+					containers.swap_remove(&entry.key[..]);
+				}
+			} else if let Some(mut container) = entry.created.take() {
+				self.memory_track_insert(&entry.key, &mut container);
+				self.access_touch(&container);
+				containers.insert(entry.key[..].into(), Self::make_container(container));
+			} else if let Some(guard) = &mut entry.guard {
+				self.memory_track(guard);
+			}
+		}
+		result
+	}
+}
@@ -0,0 +1,328 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::collections::VecDeque;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use indexmap::{IndexSet, IndexMap};
+
+use super::container::{Container, ContainerImpl};
+use super::bloom::{BloomFilter, BloomLayer};
+use super::timeseries::TimeSeries;
+
+type CompactKey = super::CompactKey;
+type Value = super::Value;
+type Json = serde_json::Value;
+type Arguments = super::Arguments;
+type ExecResult = super::ExecResult;
+
+/// Bookkeeping of an entry in the containers map: hash slot, Arc and Mutex of the container
+const KEY_OVERHEAD: usize = 64;
+/// Collections with more elements are measured by sampling
+const EXACT_LIMIT: usize = 128;
+const SAMPLES: usize = 16;
+/// Recount in debug builds after this count of commands
+#[cfg(debug_assertions)]
+const RECOUNT_PERIOD: u64 = 10000;
+
+pub struct MemoryCounter {
+	/// Signed: a container mutated after it was removed from the map may bring the sum below zero for a while
+	used: AtomicI64,
+	#[cfg_attr(not(debug_assertions), allow(dead_code))]
+	operations: AtomicU64,
+}
+
+impl MemoryCounter {
+	pub fn new() -> Self {
+		Self {
+			used: AtomicI64::new(0),
+			operations: AtomicU64::new(0),
+		}
+	}
+
+	fn add(&self, bytes: usize) {
+		self.used.fetch_add(bytes as i64, Ordering::Relaxed);
+	}
+
+	fn sub(&self, bytes: usize) {
+		self.used.fetch_sub(bytes as i64, Ordering::Relaxed);
+	}
+
+	pub fn used(&self) -> usize {
+		std::cmp::max(self.used.load(Ordering::Relaxed), 0) as usize
+	}
+}
+
+pub fn value_size(value: &Value) -> usize {
+	size_of::<Value>() + match value {
+		Value::Buffer(b) => b.capacity(),
+		Value::Error(e) => e.capacity(),
+		Value::Array(a) => a.iter().map(value_size).sum(),
+		_ => 0,
+	}
+}
+
+fn key_size(key: &[u8]) -> usize {
+	KEY_OVERHEAD + CompactKey::heap_size(key.len())
+}
+
+/// Approximate heap size of a container content
+pub trait Footprint {
+	fn footprint(&self, exact: bool) -> usize;
+}
+
+/// Exact sum for small collections (or if requested), otherwise the average of evenly spread samples
+fn collection_footprint<F: Fn(usize) -> usize>(len: usize, exact: bool, element_size: F) -> usize {
+	if exact || len <= EXACT_LIMIT {
+		(0..len).map(element_size).sum()
+	} else {
+		let step = len / SAMPLES;
+		let sampled: usize = (0..SAMPLES).map(|i|element_size(i * step)).sum();
+		sampled * len / SAMPLES
+	}
+}
+
+impl Footprint for Vec<u8> {
+	fn footprint(&self, _exact: bool) -> usize {
+		self.capacity()
+	}
+}
+
+/// Slots allocated but not used, e.g. left after mass deletions until MEMORY PURGE
+fn spare_slots(len: usize, capacity: usize, slot_size: usize) -> usize {
+	capacity.saturating_sub(len) * slot_size
+}
+
+impl Footprint for VecDeque<Value> {
+	fn footprint(&self, exact: bool) -> usize {
+		collection_footprint(self.len(), exact, |i|value_size(&self[i]))
+			+ spare_slots(self.len(), self.capacity(), size_of::<Value>())
+	}
+}
+
+impl Footprint for IndexSet<Value> {
+	fn footprint(&self, exact: bool) -> usize {
+		// hash index entry per element
+		collection_footprint(self.len(), exact, |i|value_size(&self[i]) + size_of::<usize>() * 2)
+			+ spare_slots(self.len(), self.capacity(), size_of::<Value>() + size_of::<usize>() * 2)
+	}
+}
+
+impl Footprint for IndexMap<Value, Value> {
+	fn footprint(&self, exact: bool) -> usize {
+		collection_footprint(self.len(), exact, |i|{
+			let (field, value) = self.get_index(i).unwrap();
+			value_size(field) + value_size(value) + size_of::<usize>() * 2
+		}) + spare_slots(self.len(), self.capacity(), size_of::<Value>() * 2 + size_of::<usize>() * 2)
+	}
+}
+
+/// Tree nodes are measured like collections: nested arrays and objects are sampled too
+fn json_size(json: &Json, exact: bool) -> usize {
+	size_of::<Json>() + match json {
+		Json::String(s) => s.capacity(),
+		Json::Array(a) => collection_footprint(a.len(), exact, |i|json_size(&a[i], exact)),
+		Json::Object(o) => {
+			let step = if exact || o.len() <= EXACT_LIMIT {1} else {o.len() / SAMPLES};
+			let sampled: usize = o.iter()
+				.step_by(step)
+				.map(|(k, v)|k.capacity() + json_size(v, exact) + size_of::<usize>() * 3)
+				.sum();
+			sampled * step
+		},
+		_ => 0,
+	}
+}
+
+impl Footprint for Json {
+	fn footprint(&self, exact: bool) -> usize {
+		json_size(self, exact) - size_of::<Json>()
+	}
+}
+
+impl Footprint for BloomFilter {
+	fn footprint(&self, _exact: bool) -> usize {
+		self.layers.iter().map(|layer|size_of::<BloomLayer>() + layer.bits.capacity()).sum()
+	}
+}
+
+impl Footprint for TimeSeries {
+	fn footprint(&self, _exact: bool) -> usize {
+		let labels: usize = self.labels.iter().map(|(name, value)|size_of::<(String, String)>() + name.capacity() + value.capacity()).sum();
+		labels + self.samples.capacity() * size_of::<(u64, f64)>()
+	}
+}
+
+impl<Inner: Footprint> ContainerImpl<Inner> {
+	pub fn size_bytes(&self, exact: bool) -> usize {
+		size_of::<Self>() + self.inner.footprint(exact)
+	}
+
+	/// Measures the content and stores it as accounted; returns the previously accounted size
+	fn account(&mut self, exact: bool) -> usize {
+		let size = self.size_bytes(exact);
+		std::mem::replace(&mut self.accounted_size, size)
+	}
+}
+
+impl Container {
+	pub fn size_bytes(&self) -> usize {
+		match self {
+			Container::Strings(c) => c.size_bytes(true),
+			Container::Json(c) => c.size_bytes(false),
+			Container::Bloom(c) => c.size_bytes(true),
+			Container::TimeSeries(c) => c.size_bytes(true),
+			Container::List(c) => c.size_bytes(false),
+			Container::Set(c) => c.size_bytes(false),
+			Container::Hash(c) => c.size_bytes(false),
+		}
+	}
+
+	/// Size included in the storage total
+	pub fn accounted_size(&self) -> usize {
+		match self {
+			Container::Strings(c) => c.accounted_size,
+			Container::Json(c) => c.accounted_size,
+			Container::Bloom(c) => c.accounted_size,
+			Container::TimeSeries(c) => c.accounted_size,
+			Container::List(c) => c.accounted_size,
+			Container::Set(c) => c.accounted_size,
+			Container::Hash(c) => c.accounted_size,
+		}
+	}
+
+	fn account(&mut self, exact: bool) -> usize {
+		match self {
+			Container::Strings(c) => c.account(exact),
+			Container::Json(c) => c.account(exact),
+			Container::Bloom(c) => c.account(exact),
+			Container::TimeSeries(c) => c.account(exact),
+			Container::List(c) => c.account(exact),
+			Container::Set(c) => c.account(exact),
+			Container::Hash(c) => c.account(exact),
+		}
+	}
+}
+
+impl super::Storage {
+	/// Approximate size of the dataset in bytes, O(1)
+	pub fn memory_used(&self) -> usize {
+		self.memory.used()
+	}
+
+	/// Should be called after each mutation of a container which is in the map
+	pub fn memory_track(&self, container: &mut Container) {
+		self.memory.sub(container.account(false));
+		self.memory.add(container.accounted_size());
+	}
+
+	/// Should be called after a key is inserted into the map with a new container
+	pub fn memory_track_insert(&self, key: &[u8], container: &mut Container) {
+		self.memory.add(key_size(key));
+		self.memory_track(container);
+	}
+
+	/// Should be called after a key is removed from the map
+	pub fn memory_track_remove(&self, key: &[u8], container: &Container) {
+		self.memory.sub(key_size(key) + container.accounted_size());
+	}
+
+	pub fn memory_track_key_insert(&self, key: &[u8]) {
+		self.memory.add(key_size(key));
+	}
+
+	pub fn memory_track_key_remove(&self, key: &[u8]) {
+		self.memory.sub(key_size(key));
+	}
+
+	/// Exact recount of the whole dataset; replaces the tracked total and returns (tracked, counted).
+	/// Containers are measured one at a time without the map lock, so writes keep going meanwhile
+	pub async fn memory_recount(&self) -> (usize, usize) {
+		let containers: Vec<_> = self.containers.lock().await.iter()
+			.map(|(key, container)|(key_size(key), container.clone()))
+			.collect();
+		let mut counted = 0;
+		for (key_size, container) in containers {
+			let mut container = container.write().await;
+			container.account(true);
+			counted += key_size + container.accounted_size();
+		}
+		let tracked = self.memory.used.swap(counted as i64, Ordering::Relaxed);
+		(std::cmp::max(tracked, 0) as usize, counted)
+	}
+
+	/// In debug builds periodically compares the tracked total with an exact recount
+	pub(crate) async fn memory_check(&self) {
+		#[cfg(debug_assertions)]
+		{
+			if self.memory.operations.fetch_add(1, Ordering::Relaxed) % RECOUNT_PERIOD != RECOUNT_PERIOD - 1 {
+				return;
+			}
+			let (tracked, counted) = self.memory_recount().await;
+			let drift = (tracked as f64 - counted as f64).abs();
+			if drift > counted as f64 * 0.1 {
+				log::warn!("memory accounting drift: tracked {} vs counted {}", tracked, counted);
+			}
+		}
+	}
+
+	pub async fn memory_command(&self, mut args: Arguments) -> ExecResult {
+		let subcommand = Self::extract_string(args.pop_front())?;
+		match &subcommand.to_uppercase()[..] {
+			"USAGE" => {
+				let key = Self::extract_key(args.pop_front())?;
+				match self.try_get_live_container(&key).await {
+					None => Ok(Value::Nill),
+					Some(c) => Ok(Value::Integer((key_size(&key) + c.read().await.size_bytes()) as i64)),
+				}
+			},
+			"STATS" => {
+				let keys = self.containers.lock().await.len();
+				Ok(Value::Array(VecDeque::from(vec![
+					Value::Buffer(b"dataset.bytes".to_vec()),
+					Value::Integer(self.memory_used() as i64),
+					Value::Buffer(b"keys.count".to_vec()),
+					Value::Integer(keys as i64),
+					Value::Buffer(b"compactions.count".to_vec()),
+					Value::Integer(self.counters.compactions.load(Ordering::Relaxed) as i64),
+				])))
+			},
+			"DOCTOR" => {
+				let (tracked, counted) = self.memory_recount().await;
+				Ok(Value::Buffer(format!("tracked:{} counted:{}", tracked, counted).into_bytes()))
+			},
+			"PURGE" => Ok(Value::Integer(self.compact().await as i64)),
+			arg => Err(format!("Unexpected argument '{}'", arg)),
+		}
+	}
+}
+
+/// Human readable size as in INFO: 1.50K, 2.00M
+pub fn human_size(bytes: usize) -> String {
+	const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+	if bytes < 1024 {
+		return format!("{}B", bytes);
+	}
+	let mut size = bytes as f64 / 1024.0;
+	let mut unit = 0;
+	while size >= 1024.0 && unit + 1 < UNITS.len() {
+		size /= 1024.0;
+		unit += 1;
+	}
+	format!("{:.2}{}", size, UNITS[unit])
+}
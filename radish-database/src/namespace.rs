@@ -0,0 +1,194 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Views of the storage confined to the keys with a prefix. Key arguments get the prefix
+//! at the positions given by the command table, and the keyspace commands see only the
+//! keys of the view, so one tenant can't reach the keys of another through its view.
+
+use std::time::Duration;
+
+use super::commands::CommandSpec;
+
+type Key = super::Key;
+type CompactKey = super::CompactKey;
+type Value = super::Value;
+type Command = super::Command;
+type Session = super::Session;
+type Storage = super::Storage;
+type StorageResult<T> = super::StorageResult<T>;
+
+/// Commands without keys which don't reveal or change anything outside the session
+const KEYLESS: &[&str] = &["PING", "AUTH", "NOW", "PNOW", "HELP", "COMMAND"];
+
+/// Commands with a key among the options, after the keyword
+const KEY_OPTIONS: &[(&str, &str)] = &[("SORT", "STORE")];
+
+/// Commands replying with the key they popped from, as the first element of the reply
+const KEY_REPLIES: &[&str] = &["BLPOP", "BRPOP"];
+
+fn prefixed(prefix: &[u8], key: &[u8]) -> Key {
+	let mut prefixed = Vec::with_capacity(prefix.len() + key.len());
+	prefixed.extend_from_slice(prefix);
+	prefixed.extend_from_slice(key);
+	prefixed
+}
+
+fn add_prefix(prefix: &[u8], arg: Option<&mut Value>) {
+	if let Some(Value::Buffer(key)) = arg {
+		*key = prefixed(prefix, key);
+	}
+}
+
+/// The command with its keys moved into the namespace; commands which can't be confined
+/// to it, such as scripts or the server administration, are refused
+pub(crate) fn prefix_keys(spec: &CommandSpec, mut command: Command, prefix: &[u8]) -> Result<Command, String> {
+	if spec.first_key <= 0 && !KEYLESS.contains(&spec.name) {
+		return Err(format!("ERR '{}' command is not available in a namespace", spec.name.to_lowercase()));
+	}
+	for i in spec.key_indices(command.arguments.len()) {
+		add_prefix(prefix, command.arguments.get_mut(i));
+	}
+	for (_, keyword) in KEY_OPTIONS.iter().filter(|(name, _)|*name == spec.name) {
+		let position = command.arguments.iter().position(|arg| matches!(arg, Value::Buffer(b) if b.eq_ignore_ascii_case(keyword.as_bytes())));
+		if let Some(position) = position {
+			add_prefix(prefix, command.arguments.get_mut(position + 1));
+		}
+	}
+	Ok(command)
+}
+
+/// The reply with the key it names moved out of the namespace
+pub(crate) fn strip_reply_key(spec: &CommandSpec, mut reply: Value, prefix: &[u8]) -> Value {
+	if KEY_REPLIES.contains(&spec.name) {
+		if let Value::Array(items) = &mut reply {
+			if let Some(Value::Buffer(key)) = items.front_mut() {
+				if key.starts_with(prefix) {
+					key.drain(..prefix.len());
+				}
+			}
+		}
+	}
+	reply
+}
+
+/// A cheap view of the storage, see `Storage::namespace`
+#[derive(Clone)]
+pub struct NamespacedStorage {
+	storage: Storage,
+	prefix: Vec<u8>,
+}
+
+impl Storage {
+	/// View of the keys starting with `prefix`; the keys are seen without it
+	pub fn namespace(&self, prefix: &[u8]) -> NamespacedStorage {
+		NamespacedStorage {
+			storage: self.clone(),
+			prefix: prefix.to_vec(),
+		}
+	}
+}
+
+impl NamespacedStorage {
+	pub fn prefix(&self) -> &[u8] {
+		&self.prefix
+	}
+
+	fn key(&self, key: &[u8]) -> Key {
+		prefixed(&self.prefix, key)
+	}
+
+	/// Executes the command with its keys inside the namespace, see `Storage::execute`
+	pub async fn execute(&self, session: &mut Session, command: Command) -> Value {
+		self.storage.execute_in(session, command, &self.prefix).await
+	}
+
+	/// Count of the keys in the namespace
+	pub async fn dbsize(&self) -> usize {
+		let containers = self.storage.containers.lock().await;
+		containers.keys().filter(|key|key.starts_with(&self.prefix)).count()
+	}
+
+	/// Removes every key of the namespace, returns the count of removed keys
+	pub async fn flush(&self) -> StorageResult<usize> {
+		if self.storage.config.read_only() {
+			return Err(super::StorageError::ReadOnly);
+		}
+		let mut containers = self.storage.containers.lock().await;
+		let keys: Vec<CompactKey> = containers.keys().filter(|key|key.starts_with(&self.prefix)).cloned().collect();
+		for key in &keys {
+			if let Some(c) = containers.remove(key) {
+				self.storage.memory_track_remove(key, &*c.read().await);
+			}
+		}
+		Ok(keys.len())
+	}
+
+	pub async fn get(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+		self.storage.get(&self.key(key)).await
+	}
+
+	/// Sets the value and replaces or removes the time to live
+	pub async fn set(&self, key: &[u8], value: &[u8], ttl: Option<Duration>) -> StorageResult<()> {
+		self.storage.set(&self.key(key), value, ttl).await
+	}
+
+	/// Returns true if the key existed
+	pub async fn del(&self, key: &[u8]) -> StorageResult<bool> {
+		self.storage.del(&self.key(key)).await
+	}
+
+	pub async fn incr_by(&self, key: &[u8], increment: i64) -> StorageResult<i64> {
+		self.storage.incr_by(&self.key(key), increment).await
+	}
+
+	/// Returns the length of the list after the push
+	pub async fn list_push_back(&self, key: &[u8], values: &[&[u8]]) -> StorageResult<usize> {
+		self.storage.list_push_back(&self.key(key), values).await
+	}
+
+	/// Elements from `start` to `stop` inclusive
+	pub async fn list_range(&self, key: &[u8], start: usize, stop: usize) -> StorageResult<Vec<Vec<u8>>> {
+		self.storage.list_range(&self.key(key), start, stop).await
+	}
+
+	/// Returns the count of added members
+	pub async fn set_add(&self, key: &[u8], members: &[&[u8]]) -> StorageResult<usize> {
+		self.storage.set_add(&self.key(key), members).await
+	}
+
+	pub async fn set_members(&self, key: &[u8]) -> StorageResult<Vec<Vec<u8>>> {
+		self.storage.set_members(&self.key(key)).await
+	}
+
+	pub async fn hash_set(&self, key: &[u8], field: &[u8], value: &[u8]) -> StorageResult<()> {
+		self.storage.hash_set(&self.key(key), field, value).await
+	}
+
+	pub async fn hash_get(&self, key: &[u8], field: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+		self.storage.hash_get(&self.key(key), field).await
+	}
+
+	/// Returns false if the key does not exist
+	pub async fn expire(&self, key: &[u8], ttl: Duration) -> StorageResult<bool> {
+		self.storage.expire(&self.key(key), ttl).await
+	}
+
+	/// Remaining time to live; None if the key does not exist or has no expiration
+	pub async fn ttl(&self, key: &[u8]) -> StorageResult<Option<Duration>> {
+		self.storage.ttl(&self.key(key)).await
+	}
+}
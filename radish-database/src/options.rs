@@ -0,0 +1,236 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Parsing of the trailing keyword options of a command, e.g. `SET key value NX EX 10`
+
+use std::collections::{HashMap, HashSet};
+
+type Value = super::Value;
+type Arguments = super::Arguments;
+type Storage = super::Storage;
+type ContainerKind = super::ContainerKind;
+
+pub const SYNTAX_ERROR: &str = "ERR syntax error";
+
+/// Declaration of the accepted options; names must be in upper case
+pub struct OptionParser {
+	flags: &'static [&'static str],
+	valued: &'static [&'static str],
+	multi: &'static [(&'static str, usize)],
+	exclusive: &'static [&'static [&'static str]],
+}
+
+/// Options found in the arguments
+pub struct Options {
+	flags: HashSet<&'static str>,
+	values: HashMap<&'static str, Value>,
+}
+
+impl OptionParser {
+	pub const fn new(flags: &'static [&'static str], valued: &'static [&'static str]) -> Self {
+		Self {
+			flags,
+			valued,
+			multi: &[],
+			exclusive: &[],
+		}
+	}
+
+	/// Options followed by the given number of values, e.g. `LIMIT offset count`
+	pub const fn multi(mut self, multi: &'static [(&'static str, usize)]) -> Self {
+		self.multi = multi;
+		self
+	}
+
+	/// Groups of options which can not be used together
+	pub const fn exclusive(mut self, exclusive: &'static [&'static [&'static str]]) -> Self {
+		self.exclusive = exclusive;
+		self
+	}
+
+	/// Consumes all the arguments; unknown, duplicated or conflicting options and
+	/// options without a value are rejected
+	pub fn parse(&self, mut args: Arguments) -> Result<Options, String> {
+		let mut options = Options {
+			flags: HashSet::new(),
+			values: HashMap::new(),
+		};
+		let mut seen = HashSet::new();
+		while let Some(arg) = args.pop_front() {
+			let token = Storage::extract_string(Some(arg)).map_err(|_|SYNTAX_ERROR.to_owned())?.to_uppercase();
+			if let Some(&flag) = self.flags.iter().find(|&&f|f == token) {
+				if !seen.insert(flag) {
+					return Err(SYNTAX_ERROR.to_owned());
+				}
+				options.flags.insert(flag);
+			} else if let Some(&name) = self.valued.iter().find(|&&v|v == token) {
+				if !seen.insert(name) {
+					return Err(SYNTAX_ERROR.to_owned());
+				}
+				let value = args.pop_front().ok_or_else(||SYNTAX_ERROR.to_owned())?;
+				options.values.insert(name, value);
+			} else if let Some(&(name, count)) = self.multi.iter().find(|&&(m, _)|m == token) {
+				if !seen.insert(name) || args.len() < count {
+					return Err(SYNTAX_ERROR.to_owned());
+				}
+				options.values.insert(name, Value::Array(args.drain(..count).collect()));
+			} else {
+				return Err(SYNTAX_ERROR.to_owned());
+			}
+		}
+		for group in self.exclusive {
+			if group.iter().filter(|&name|seen.contains(name)).count() > 1 {
+				return Err(SYNTAX_ERROR.to_owned());
+			}
+		}
+		Ok(options)
+	}
+}
+
+impl Options {
+	pub fn flag(&self, name: &str) -> bool {
+		self.flags.contains(name)
+	}
+
+	pub fn value(&mut self, name: &str) -> Option<Value> {
+		self.values.remove(name)
+	}
+
+	/// Values of an option declared with `multi`
+	pub fn values(&mut self, name: &str) -> Option<Arguments> {
+		match self.value(name)? {
+			Value::Array(values) => Some(values),
+			value => Some(std::iter::once(value).collect()),
+		}
+	}
+
+	pub fn string(&mut self, name: &str) -> Result<Option<String>, String> {
+		self.value(name).map(|v|Storage::extract_string(Some(v))).transpose()
+	}
+
+	pub fn integer(&mut self, name: &str) -> Result<Option<i64>, String> {
+		self.value(name).map(|v|Storage::extract_integer(Some(v))).transpose()
+	}
+
+	pub fn unsigned(&mut self, name: &str) -> Result<Option<u64>, String> {
+		self.value(name).map(|v|Storage::extract_unsigned_integer(Some(v))).transpose()
+	}
+
+	pub fn index(&mut self, name: &str) -> Result<Option<usize>, String> {
+		self.value(name).map(|v|Storage::extract_index(Some(v))).transpose()
+	}
+}
+
+/// Options shared by SCAN, SSCAN and HSCAN
+pub struct ScanOptions {
+	pub pattern: Option<regex::bytes::Regex>,
+	pub count: usize,
+	pub key_type: Option<ContainerKind>,
+}
+
+const SCAN_OPTIONS: OptionParser = OptionParser::new(&[], &["MATCH", "COUNT", "TYPE"]);
+const COLLECTION_SCAN_OPTIONS: OptionParser = OptionParser::new(&[], &["MATCH", "COUNT"]);
+
+impl ScanOptions {
+	/// TYPE is accepted only by the keyspace scan
+	pub fn parse(args: Arguments, keyspace: bool) -> Result<Self, String> {
+		let parser = if keyspace {&SCAN_OPTIONS} else {&COLLECTION_SCAN_OPTIONS};
+		let mut options = parser.parse(args)?;
+		let pattern = match options.string("MATCH")? {
+			None => None,
+			Some(pattern) => Some(regex::bytes::Regex::new(&pattern[..]).map_err(|e|format!("{}", e))?),
+		};
+		Ok(Self {
+			pattern,
+			count: options.index("COUNT")?.unwrap_or(100),
+			key_type: options.string("TYPE")?.map(|t|t.parse()).transpose()?,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const PARSER: OptionParser = OptionParser::new(&["NX", "XX", "KEEPTTL"], &["EX", "PX"])
+		.multi(&[("LIMIT", 2)])
+		.exclusive(&[&["NX", "XX"], &["EX", "PX", "KEEPTTL"]]);
+
+	/// Numbers are sent as integers like clients do
+	fn args(args: &[&str]) -> Arguments {
+		args.iter().map(|arg| match arg.parse::<i64>() {
+			Ok(i) => Value::Integer(i),
+			Err(_) => Value::Buffer(arg.as_bytes().to_vec()),
+		}).collect()
+	}
+
+	fn parse(arguments: &[&str]) -> Result<Options, String> {
+		PARSER.parse(args(arguments))
+	}
+
+	fn error(arguments: &[&str]) -> String {
+		parse(arguments).err().expect("the options must be rejected")
+	}
+
+	#[test]
+	fn flags_and_values() {
+		let mut options = parse(&["NX", "EX", "10", "LIMIT", "5", "20"]).unwrap();
+		assert!(options.flag("NX"));
+		assert!(!options.flag("XX"));
+		assert_eq!(options.integer("EX"), Ok(Some(10)));
+		assert_eq!(options.integer("PX"), Ok(None));
+		assert_eq!(options.values("LIMIT"), Some(args(&["5", "20"])));
+
+		let options = parse(&[]).unwrap();
+		assert!(!options.flag("NX"));
+	}
+
+	#[test]
+	fn mixed_case() {
+		let mut options = parse(&["xx", "Px", "1500", "LiMiT", "0", "1"]).unwrap();
+		assert!(options.flag("XX"));
+		assert_eq!(options.integer("PX"), Ok(Some(1500)));
+		assert_eq!(options.values("LIMIT"), Some(args(&["0", "1"])));
+
+		// values are not case folded
+		let mut options = OptionParser::new(&[], &["MATCH"]).parse(args(&["match", "Key*"])).unwrap();
+		assert_eq!(options.string("MATCH"), Ok(Some("Key*".to_owned())));
+	}
+
+	#[test]
+	fn duplicates() {
+		assert_eq!(error(&["NX", "NX"]), SYNTAX_ERROR);
+		assert_eq!(error(&["nx", "NX"]), SYNTAX_ERROR);
+		assert_eq!(error(&["EX", "1", "ex", "2"]), SYNTAX_ERROR);
+		assert_eq!(error(&["LIMIT", "0", "1", "LIMIT", "0", "1"]), SYNTAX_ERROR);
+	}
+
+	#[test]
+	fn missing_values() {
+		assert_eq!(error(&["EX"]), SYNTAX_ERROR);
+		assert_eq!(error(&["NX", "PX"]), SYNTAX_ERROR);
+		assert_eq!(error(&["LIMIT", "0"]), SYNTAX_ERROR);
+	}
+
+	#[test]
+	fn unknown_and_conflicting() {
+		assert_eq!(error(&["NX", "FOO"]), SYNTAX_ERROR);
+		assert_eq!(error(&["10"]), SYNTAX_ERROR);
+		assert_eq!(error(&["NX", "XX"]), SYNTAX_ERROR);
+		assert_eq!(error(&["EX", "1", "KEEPTTL"]), SYNTAX_ERROR);
+	}
+}
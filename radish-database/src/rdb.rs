@@ -0,0 +1,610 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Reader of Redis RDB files (versions up to 12) for the migration from Redis.
+//! Strings, lists, sets and hashes of every encoding are converted into a `DatasetSnapshot`;
+//! sorted sets, streams and module values are skipped because Radish has no such types.
+//! Only the database 0 is loaded, there is a single keyspace.
+
+use std::collections::BTreeMap;
+
+use super::dataset::{DatasetSnapshot, SnapshotEntry, SnapshotData, ImportMode};
+
+type Value = super::Value;
+
+const OPCODE_SLOT_INFO: u8 = 0xF4;
+const OPCODE_FUNCTION2: u8 = 0xF5;
+const OPCODE_MODULE_AUX: u8 = 0xF7;
+const OPCODE_IDLE: u8 = 0xF8;
+const OPCODE_FREQ: u8 = 0xF9;
+const OPCODE_AUX: u8 = 0xFA;
+const OPCODE_RESIZEDB: u8 = 0xFB;
+const OPCODE_EXPIRETIME_MS: u8 = 0xFC;
+const OPCODE_EXPIRETIME: u8 = 0xFD;
+const OPCODE_SELECTDB: u8 = 0xFE;
+const OPCODE_EOF: u8 = 0xFF;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_MODULE_2: u8 = 7;
+const TYPE_HASH_ZIPMAP: u8 = 9;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_STREAM_LISTPACKS: u8 = 15;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const TYPE_SET_LISTPACK: u8 = 20;
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+
+const MAX_VERSION: u32 = 12;
+
+/// What was loaded and what was left out
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RdbSummary {
+	pub version: u32,
+	pub loaded: usize,
+	/// Keys with an expiration time in the past
+	pub expired: usize,
+	/// Keys of databases other than 0
+	pub other_databases: usize,
+	/// Count of skipped keys by the name of their type
+	pub skipped: BTreeMap<&'static str, usize>,
+}
+
+struct Reader<'a> {
+	data: &'a [u8],
+	pos: usize,
+}
+
+/// Value of a key which may be unsupported
+enum Object {
+	Data(SnapshotData),
+	Skipped(&'static str),
+}
+
+fn buffers(items: Vec<Vec<u8>>) -> Vec<Value> {
+	items.into_iter().map(Value::Buffer).collect()
+}
+
+fn pairs(items: Vec<Vec<u8>>) -> Result<Vec<(Value, Value)>, String> {
+	if items.len() % 2 == 1 {
+		return Err("odd count of hash fields and values".to_owned());
+	}
+	let mut items = items.into_iter();
+	let mut out = Vec::new();
+	while let (Some(field), Some(value)) = (items.next(), items.next()) {
+		out.push((Value::Buffer(field), Value::Buffer(value)));
+	}
+	Ok(out)
+}
+
+fn integer(value: i64) -> Vec<u8> {
+	value.to_string().into_bytes()
+}
+
+/// CRC-64/Jones as used by Redis for the RDB trailer
+fn crc64_jones(data: &[u8]) -> u64 {
+	const POLY: u64 = 0x95AC_9329_AC4B_C9B5;
+	let mut table = [0u64; 256];
+	for (i, entry) in table.iter_mut().enumerate() {
+		let mut crc = i as u64;
+		for _ in 0..8 {
+			crc = if crc & 1 == 1 {(crc >> 1) ^ POLY} else {crc >> 1};
+		}
+		*entry = crc;
+	}
+	data.iter().fold(0u64, |crc, byte|table[((crc ^ *byte as u64) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// A back reference of 3 bytes expands to at most 264 bytes
+const LZF_MAX_RATIO: usize = 88;
+
+/// `expected` comes from the file, so it is checked against what the input can expand to before reserving it
+fn lzf_decompress(input: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+	if expected > input.len().saturating_mul(LZF_MAX_RATIO) {
+		return Err(format!("LZF length {} is impossible for {} compressed bytes", expected, input.len()));
+	}
+	let mut out = Vec::with_capacity(expected);
+	let mut ip = 0;
+	while ip < input.len() {
+		let ctrl = input[ip] as usize;
+		ip += 1;
+		if ctrl < 32 {
+			let run = ctrl + 1;
+			let literal = input.get(ip..ip + run).ok_or("LZF literal out of bounds")?;
+			out.extend_from_slice(literal);
+			ip += run;
+		} else {
+			let mut len = ctrl >> 5;
+			if len == 7 {
+				len += *input.get(ip).ok_or("LZF length out of bounds")? as usize;
+				ip += 1;
+			}
+			let low = *input.get(ip).ok_or("LZF offset out of bounds")? as usize;
+			ip += 1;
+			let back = ((ctrl & 0x1f) << 8) + low + 1;
+			let start = out.len().checked_sub(back).ok_or("LZF reference out of bounds")?;
+			for i in 0..len + 2 {
+				let byte = out[start + i];
+				out.push(byte);
+			}
+		}
+	}
+	if out.len() != expected {
+		return Err(format!("LZF length {} differs from the expected {}", out.len(), expected));
+	}
+	Ok(out)
+}
+
+impl<'a> Reader<'a> {
+	fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+		let end = self.pos.checked_add(count).filter(|end|*end <= self.data.len()).ok_or("unexpected end of file")?;
+		let bytes = &self.data[self.pos..end];
+		self.pos = end;
+		Ok(bytes)
+	}
+
+	fn byte(&mut self) -> Result<u8, String> {
+		Ok(self.bytes(1)?[0])
+	}
+
+	fn u32_le(&mut self) -> Result<u32, String> {
+		let b = self.bytes(4)?;
+		Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+	}
+
+	fn u64_le(&mut self) -> Result<u64, String> {
+		let mut bytes = [0u8; 8];
+		bytes.copy_from_slice(self.bytes(8)?);
+		Ok(u64::from_le_bytes(bytes))
+	}
+
+	/// Length and whether it is a special encoding of a string
+	fn length_encoding(&mut self) -> Result<(u64, bool), String> {
+		let first = self.byte()?;
+		match first >> 6 {
+			0 => Ok(((first & 0x3f) as u64, false)),
+			1 => Ok(((((first & 0x3f) as u64) << 8) | self.byte()? as u64, false)),
+			2 => match first {
+				0x80 => {
+					let b = self.bytes(4)?;
+					Ok((u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as u64, false))
+				},
+				0x81 => {
+					let mut bytes = [0u8; 8];
+					bytes.copy_from_slice(self.bytes(8)?);
+					Ok((u64::from_be_bytes(bytes), false))
+				},
+				_ => Err(format!("unknown length encoding {:#x}", first)),
+			},
+			_ => Ok(((first & 0x3f) as u64, true)),
+		}
+	}
+
+	fn length(&mut self) -> Result<usize, String> {
+		match self.length_encoding()? {
+			(_, true) => Err("string encoding where a length is expected".to_owned()),
+			(len, false) => Ok(len as usize),
+		}
+	}
+
+	fn string(&mut self) -> Result<Vec<u8>, String> {
+		let (len, encoded) = self.length_encoding()?;
+		if !encoded {
+			return Ok(self.bytes(len as usize)?.to_vec());
+		}
+		match len {
+			0 => Ok(integer(self.byte()? as i8 as i64)),
+			1 => {
+				let b = self.bytes(2)?;
+				Ok(integer(i16::from_le_bytes([b[0], b[1]]) as i64))
+			},
+			2 => Ok(integer(self.u32_le()? as i32 as i64)),
+			3 => {
+				let compressed = self.length()?;
+				let expected = self.length()?;
+				lzf_decompress(self.bytes(compressed)?, expected)
+			},
+			_ => Err(format!("unknown string encoding {}", len)),
+		}
+	}
+
+	/// Score of the old sorted set encoding: a length byte and the number as text
+	fn skip_double(&mut self) -> Result<(), String> {
+		match self.byte()? {
+			253..=255 => Ok(()),
+			len => self.bytes(len as usize).map(|_|()),
+		}
+	}
+
+	fn skip_module_opcodes(&mut self) -> Result<(), String> {
+		loop {
+			match self.length()? {
+				0 => return Ok(()),
+				1 | 2 => {
+					self.length()?;
+				},
+				3 => {
+					self.bytes(4)?;
+				},
+				4 => {
+					self.bytes(8)?;
+				},
+				5 => {
+					self.string()?;
+				},
+				opcode => return Err(format!("unknown module opcode {}", opcode)),
+			}
+		}
+	}
+
+	fn skip_stream(&mut self, object_type: u8) -> Result<(), String> {
+		for _ in 0..self.length()? {
+			self.string()?;
+			self.string()?;
+		}
+		// length and last id
+		for _ in 0..3 {
+			self.length()?;
+		}
+		if object_type >= TYPE_STREAM_LISTPACKS_2 {
+			// first id, max deleted id, entries added
+			for _ in 0..5 {
+				self.length()?;
+			}
+		}
+		for _ in 0..self.length()? {
+			self.string()?;
+			self.length()?;
+			self.length()?;
+			if object_type >= TYPE_STREAM_LISTPACKS_2 {
+				self.length()?;
+			}
+			for _ in 0..self.length()? {
+				self.bytes(16 + 8)?;
+				self.length()?;
+			}
+			for _ in 0..self.length()? {
+				self.string()?;
+				self.bytes(8)?;
+				if object_type >= TYPE_STREAM_LISTPACKS_3 {
+					self.bytes(8)?;
+				}
+				let pending = self.length()?;
+				self.bytes(pending.checked_mul(16).ok_or("too many pending entries")?)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn strings(&mut self, count: usize) -> Result<Vec<Vec<u8>>, String> {
+		(0..count).map(|_|self.string()).collect()
+	}
+
+	fn object(&mut self, object_type: u8) -> Result<Object, String> {
+		let data = match object_type {
+			TYPE_STRING => SnapshotData::String(self.string()?),
+			TYPE_LIST => {
+				let len = self.length()?;
+				SnapshotData::List(buffers(self.strings(len)?))
+			},
+			TYPE_SET => {
+				let len = self.length()?;
+				SnapshotData::Set(buffers(self.strings(len)?))
+			},
+			TYPE_HASH => {
+				let len = self.length()?.checked_mul(2).ok_or("too many hash fields")?;
+				SnapshotData::Hash(pairs(self.strings(len)?)?)
+			},
+			TYPE_HASH_ZIPMAP => SnapshotData::Hash(pairs(zipmap(&self.string()?)?)?),
+			TYPE_LIST_ZIPLIST => SnapshotData::List(buffers(ziplist(&self.string()?)?)),
+			TYPE_SET_INTSET => SnapshotData::Set(buffers(intset(&self.string()?)?)),
+			TYPE_HASH_ZIPLIST => SnapshotData::Hash(pairs(ziplist(&self.string()?)?)?),
+			TYPE_HASH_LISTPACK => SnapshotData::Hash(pairs(listpack(&self.string()?)?)?),
+			TYPE_SET_LISTPACK => SnapshotData::Set(buffers(listpack(&self.string()?)?)),
+			TYPE_LIST_QUICKLIST => {
+				let mut items = Vec::new();
+				for _ in 0..self.length()? {
+					items.extend(ziplist(&self.string()?)?);
+				}
+				SnapshotData::List(buffers(items))
+			},
+			TYPE_LIST_QUICKLIST_2 => {
+				let mut items = Vec::new();
+				for _ in 0..self.length()? {
+					match self.length()? {
+						1 => items.push(self.string()?),
+						2 => items.extend(listpack(&self.string()?)?),
+						container => return Err(format!("unknown quicklist container {}", container)),
+					}
+				}
+				SnapshotData::List(buffers(items))
+			},
+			TYPE_ZSET | TYPE_ZSET_2 => {
+				for _ in 0..self.length()? {
+					self.string()?;
+					if object_type == TYPE_ZSET {
+						self.skip_double()?;
+					} else {
+						self.bytes(8)?;
+					}
+				}
+				return Ok(Object::Skipped("zset"));
+			},
+			TYPE_ZSET_ZIPLIST | TYPE_ZSET_LISTPACK => {
+				self.string()?;
+				return Ok(Object::Skipped("zset"));
+			},
+			TYPE_STREAM_LISTPACKS | TYPE_STREAM_LISTPACKS_2 | TYPE_STREAM_LISTPACKS_3 => {
+				self.skip_stream(object_type)?;
+				return Ok(Object::Skipped("stream"));
+			},
+			TYPE_MODULE_2 => {
+				self.length()?;
+				self.skip_module_opcodes()?;
+				return Ok(Object::Skipped("module"));
+			},
+			_ => return Err(format!("unsupported value type {}", object_type)),
+		};
+		Ok(Object::Data(data))
+	}
+}
+
+fn ziplist(blob: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+	let mut r = Reader {data: blob, pos: 10};
+	let mut items = Vec::new();
+	loop {
+		let prevlen = r.byte()?;
+		if prevlen == 0xFF {
+			return Ok(items);
+		}
+		if prevlen == 0xFE {
+			r.bytes(4)?;
+		}
+		let encoding = r.byte()?;
+		let item = match encoding >> 6 {
+			0 => r.bytes((encoding & 0x3f) as usize)?.to_vec(),
+			1 => {
+				let len = (((encoding & 0x3f) as usize) << 8) | r.byte()? as usize;
+				r.bytes(len)?.to_vec()
+			},
+			2 => {
+				let b = r.bytes(4)?;
+				let len = u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize;
+				r.bytes(len)?.to_vec()
+			},
+			_ => integer(match encoding {
+				0xC0 => {
+					let b = r.bytes(2)?;
+					i16::from_le_bytes([b[0], b[1]]) as i64
+				},
+				0xD0 => r.u32_le()? as i32 as i64,
+				0xE0 => r.u64_le()? as i64,
+				0xF0 => {
+					let b = r.bytes(3)?;
+					(i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as i64
+				},
+				0xFE => r.byte()? as i8 as i64,
+				0xF1..=0xFD => (encoding & 0x0f) as i64 - 1,
+				_ => return Err(format!("unknown ziplist encoding {:#x}", encoding)),
+			}),
+		};
+		items.push(item);
+	}
+}
+
+fn listpack(blob: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+	let mut r = Reader {data: blob, pos: 6};
+	let mut items = Vec::new();
+	loop {
+		let start = r.pos;
+		let encoding = r.byte()?;
+		let item = if encoding & 0x80 == 0 {
+			integer((encoding & 0x7f) as i64)
+		} else if encoding & 0xC0 == 0x80 {
+			r.bytes((encoding & 0x3f) as usize)?.to_vec()
+		} else if encoding & 0xE0 == 0xC0 {
+			let value = (((encoding & 0x1f) as i64) << 8) | r.byte()? as i64;
+			integer(if value >= 1 << 12 {value - (1 << 13)} else {value})
+		} else if encoding & 0xF0 == 0xE0 {
+			let len = (((encoding & 0x0f) as usize) << 8) | r.byte()? as usize;
+			r.bytes(len)?.to_vec()
+		} else {
+			match encoding {
+				0xF0 => {
+					let len = r.u32_le()? as usize;
+					r.bytes(len)?.to_vec()
+				},
+				0xF1 => {
+					let b = r.bytes(2)?;
+					integer(i16::from_le_bytes([b[0], b[1]]) as i64)
+				},
+				0xF2 => {
+					let b = r.bytes(3)?;
+					integer((i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as i64)
+				},
+				0xF3 => integer(r.u32_le()? as i32 as i64),
+				0xF4 => integer(r.u64_le()? as i64),
+				0xFF => return Ok(items),
+				_ => return Err(format!("unknown listpack encoding {:#x}", encoding)),
+			}
+		};
+		let entry_len = r.pos - start;
+		let backlen = match entry_len {
+			0..=127 => 1,
+			128..=16382 => 2,
+			16383..=2097150 => 3,
+			2097151..=268435454 => 4,
+			_ => 5,
+		};
+		r.bytes(backlen)?;
+		items.push(item);
+	}
+}
+
+fn intset(blob: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+	let mut r = Reader {data: blob, pos: 0};
+	let width = r.u32_le()? as usize;
+	let count = r.u32_le()? as usize;
+	(0..count).map(|_|{
+		let b = r.bytes(width)?;
+		Ok(integer(match width {
+			2 => i16::from_le_bytes([b[0], b[1]]) as i64,
+			4 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as i64,
+			8 => i64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+			_ => return Err(format!("unknown intset encoding {}", width)),
+		}))
+	}).collect()
+}
+
+fn zipmap(blob: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+	fn length(r: &mut Reader) -> Result<Option<usize>, String> {
+		match r.byte()? {
+			255 => Ok(None),
+			254 => Ok(Some(r.u32_le()? as usize)),
+			len => Ok(Some(len as usize)),
+		}
+	}
+
+	let mut r = Reader {data: blob, pos: 1};
+	let mut items = Vec::new();
+	loop {
+		let key_len = match length(&mut r)? {
+			None => return Ok(items),
+			Some(len) => len,
+		};
+		items.push(r.bytes(key_len)?.to_vec());
+		let value_len = length(&mut r)?.ok_or("zipmap value is missing")?;
+		let free = r.byte()? as usize;
+		items.push(r.bytes(value_len)?.to_vec());
+		r.bytes(free)?;
+	}
+}
+
+/// Converts an RDB file into a snapshot of the database 0
+pub fn parse_rdb(data: &[u8]) -> Result<(DatasetSnapshot, RdbSummary), String> {
+	let mut r = Reader {data, pos: 0};
+	let failed = |r: &Reader, err: String|format!("RDB parse error at offset {}: {}", r.pos, err);
+
+	let magic = r.bytes(9).map_err(|e|failed(&r, e))?;
+	if &magic[..5] != b"REDIS" {
+		return Err("Not an RDB file".to_owned());
+	}
+	let version = std::str::from_utf8(&magic[5..]).ok()
+		.and_then(|v|v.parse::<u32>().ok())
+		.ok_or("Bad RDB version")?;
+	if version > MAX_VERSION {
+		return Err(format!("RDB version {} is newer than supported version {}", version, MAX_VERSION));
+	}
+
+	let now = std::time::SystemTime::now()
+		.duration_since(std::time::SystemTime::UNIX_EPOCH)
+		.unwrap_or_default()
+		.as_millis() as u64;
+	let mut snapshot = DatasetSnapshot::default();
+	let mut summary = RdbSummary {version, ..RdbSummary::default()};
+	let mut database = 0;
+	let mut expire_at = None;
+
+	let mut next = || -> Result<bool, String> {
+		let opcode = r.byte()?;
+		match opcode {
+			OPCODE_EOF => {
+				if version >= 5 && r.data.len() >= r.pos + 8 {
+					let expected = r.u64_le()?;
+					if expected != 0 && expected != crc64_jones(&r.data[..r.pos - 8]) {
+						return Err("checksum mismatch".to_owned());
+					}
+				}
+				return Ok(false);
+			},
+			OPCODE_SELECTDB => database = r.length()?,
+			OPCODE_RESIZEDB => {
+				r.length()?;
+				r.length()?;
+			},
+			OPCODE_AUX => {
+				r.string()?;
+				r.string()?;
+			},
+			OPCODE_EXPIRETIME_MS => expire_at = Some(r.u64_le()?),
+			OPCODE_EXPIRETIME => expire_at = Some(r.u32_le()? as u64 * 1000),
+			OPCODE_IDLE => {
+				r.length()?;
+			},
+			OPCODE_FREQ => {
+				r.byte()?;
+			},
+			OPCODE_MODULE_AUX => {
+				r.length()?;
+				r.length()?;
+				r.length()?;
+				r.skip_module_opcodes()?;
+			},
+			OPCODE_FUNCTION2 => {
+				r.string()?;
+			},
+			OPCODE_SLOT_INFO => {
+				r.length()?;
+				r.length()?;
+				r.length()?;
+			},
+			object_type => {
+				let key = r.string()?;
+				let object = r.object(object_type)?;
+				let expire_at = expire_at.take();
+				match object {
+					Object::Skipped(name) => *summary.skipped.entry(name).or_insert(0) += 1,
+					Object::Data(_) if database != 0 => summary.other_databases += 1,
+					Object::Data(_) if matches!(expire_at, Some(at) if at <= now) => summary.expired += 1,
+					Object::Data(data) => {
+						summary.loaded += 1;
+						snapshot.entries.push(SnapshotEntry {key, data, expire_at});
+					},
+				}
+			},
+		}
+		Ok(true)
+	};
+	loop {
+		match next() {
+			Ok(true) => (),
+			Ok(false) => break,
+			Err(err) => return Err(failed(&r, err)),
+		}
+	}
+	Ok((snapshot, summary))
+}
+
+impl super::Storage {
+	/// Replaces the dataset with the content of a Redis RDB file
+	pub async fn load_rdb(&self, path: &std::path::Path) -> Result<RdbSummary, String> {
+		let data = tokio::fs::read(path).await.map_err(|e|format!("Failed to read '{}': {}", path.display(), e))?;
+		let (snapshot, summary) = parse_rdb(&data)?;
+		self.import(snapshot, ImportMode::Replace).await?;
+		Ok(summary)
+	}
+}
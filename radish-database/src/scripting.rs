@@ -0,0 +1,93 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Script cache: scripts are kept by their SHA1 digest until SCRIPT FLUSH or a restart
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::RwLock;
+
+type Value = super::Value;
+type Arguments = super::Arguments;
+type ExecResult = super::ExecResult;
+
+pub const NO_SCRIPT_ERROR: &str = "NOSCRIPT No matching script. Please use EVAL.";
+
+pub type Digest = [u8; 20];
+pub type ScriptCache = RwLock<HashMap<Digest, String>>;
+
+fn sha1(data: &[u8]) -> Digest {
+	sha1_smol::Sha1::from(data).digest().bytes()
+}
+
+fn to_hex(digest: &Digest) -> String {
+	digest.iter().map(|b|format!("{:02x}", b)).collect()
+}
+
+/// Case insensitive; None if it is not a hex digest
+fn from_hex(hex: &[u8]) -> Option<Digest> {
+	if hex.len() != 40 {
+		return None;
+	}
+	let mut digest = [0u8; 20];
+	for (i, pair) in hex.chunks(2).enumerate() {
+		let pair = std::str::from_utf8(pair).ok()?;
+		digest[i] = u8::from_str_radix(pair, 16).ok()?;
+	}
+	Some(digest)
+}
+
+impl super::Storage {
+	pub async fn scripting_script(&self, mut args: Arguments) -> ExecResult {
+		let subcommand = Self::extract_string(args.pop_front())?;
+		match &subcommand.to_uppercase()[..] {
+			"LOAD" => {
+				let script = Self::extract_string(args.pop_front())?;
+				let digest = sha1(script.as_bytes());
+				self.scripts.write().unwrap().insert(digest, script);
+				Ok(Value::Buffer(to_hex(&digest).into_bytes()))
+			},
+			"EXISTS" => {
+				let scripts = self.scripts.read().unwrap();
+				let mut out = VecDeque::with_capacity(args.len());
+				while let Some(arg) = args.pop_front() {
+					let sha = Self::extract_buffer(Some(arg))?;
+					let exists = matches!(from_hex(&sha), Some(digest) if scripts.contains_key(&digest));
+					out.push_back(Value::Integer(exists as i64));
+				}
+				Ok(Value::Array(out))
+			},
+			"FLUSH" => {
+				self.scripts.write().unwrap().clear();
+				Ok(Value::Ok)
+			},
+			arg => Err(format!("Unexpected argument '{}'", arg)),
+		}
+	}
+
+	pub async fn scripting_evalsha(&self, mut args: Arguments) -> ExecResult {
+		let sha = Self::extract_buffer(args.pop_front())?;
+		let numkeys = Self::extract_index(args.pop_front())?;
+		if numkeys > args.len() {
+			return Err("ERR Number of keys can't be greater than number of args".to_owned());
+		}
+		let digest = from_hex(&sha).ok_or_else(||NO_SCRIPT_ERROR.to_owned())?;
+		match self.scripts.read().unwrap().get(&digest) {
+			None => Err(NO_SCRIPT_ERROR.to_owned()),
+			Some(_) => Err("ERR Scripting engine is not available".to_owned()),
+		}
+	}
+}
@@ -0,0 +1,206 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::fmt::Write;
+use std::time::SystemTime;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+type Value = super::Value;
+type Arguments = super::Arguments;
+type ExecResult = super::ExecResult;
+
+struct ListenerCounters {
+	/// Transport: tcp, tls or unix
+	kind: String,
+	address: String,
+	connected_clients: AtomicU64,
+	total_connections_received: AtomicU64,
+}
+
+pub struct Counters {
+	start_time: SystemTime,
+	connected_clients: AtomicU64,
+	total_connections_received: AtomicU64,
+	total_commands_processed: AtomicU64,
+	pub expired_keys: AtomicU64,
+	pub compactions: AtomicU64,
+	pub audit_log_dropped: AtomicU64,
+	listeners: RwLock<Vec<ListenerCounters>>,
+}
+
+impl Counters {
+	pub fn new() -> Self {
+		Self {
+			start_time: SystemTime::now(),
+			connected_clients: AtomicU64::new(0),
+			total_connections_received: AtomicU64::new(0),
+			total_commands_processed: AtomicU64::new(0),
+			expired_keys: AtomicU64::new(0),
+			compactions: AtomicU64::new(0),
+			audit_log_dropped: AtomicU64::new(0),
+			listeners: RwLock::new(Vec::new()),
+		}
+	}
+
+	pub fn command_processed(&self) {
+		self.total_commands_processed.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn key_expired(&self) {
+		self.expired_keys.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Clears the totals of the stats section; gauges like connected clients are kept
+	pub fn reset(&self) {
+		self.total_connections_received.store(0, Ordering::Relaxed);
+		self.total_commands_processed.store(0, Ordering::Relaxed);
+		self.expired_keys.store(0, Ordering::Relaxed);
+		self.compactions.store(0, Ordering::Relaxed);
+		self.audit_log_dropped.store(0, Ordering::Relaxed);
+		for listener in self.listeners.read().unwrap().iter() {
+			listener.total_connections_received.store(0, Ordering::Relaxed);
+		}
+	}
+}
+
+impl super::Storage {
+	/// Registers a listener of a server for INFO; returns its id for `client_connected`
+	pub fn add_listener(&self, kind: &str, address: &str) -> usize {
+		let mut listeners = self.counters.listeners.write().unwrap();
+		listeners.push(ListenerCounters {
+			kind: kind.to_owned(),
+			address: address.to_owned(),
+			connected_clients: AtomicU64::new(0),
+			total_connections_received: AtomicU64::new(0),
+		});
+		listeners.len() - 1
+	}
+
+	/// Should be called by a server for each connection accepted by the listener
+	pub fn client_connected(&self, listener: usize) {
+		self.counters.connected_clients.fetch_add(1, Ordering::Relaxed);
+		self.counters.total_connections_received.fetch_add(1, Ordering::Relaxed);
+		if let Some(listener) = self.counters.listeners.read().unwrap().get(listener) {
+			listener.connected_clients.fetch_add(1, Ordering::Relaxed);
+			listener.total_connections_received.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+
+	/// Should be called by a server for each closed connection
+	pub fn client_disconnected(&self, listener: usize) {
+		self.counters.connected_clients.fetch_sub(1, Ordering::Relaxed);
+		if let Some(listener) = self.counters.listeners.read().unwrap().get(listener) {
+			listener.connected_clients.fetch_sub(1, Ordering::Relaxed);
+		}
+	}
+
+	async fn server_info_section(&self, section: &str, out: &mut String) -> Result<(), std::fmt::Error> {
+		let counters = &self.counters;
+		match section {
+			"server" => {
+				let uptime = counters.start_time.elapsed().unwrap_or_default();
+				writeln!(out, "# Server")?;
+				writeln!(out, "radish_version:{}", env!("CARGO_PKG_VERSION"))?;
+				writeln!(out, "uptime_in_seconds:{}", uptime.as_secs())?;
+			},
+			"clients" => {
+				writeln!(out, "# Clients")?;
+				writeln!(out, "connected_clients:{}", counters.connected_clients.load(Ordering::Relaxed))?;
+				writeln!(out, "blocked_clients:{}", self.blocked.blocked())?;
+				for (id, listener) in counters.listeners.read().unwrap().iter().enumerate() {
+					writeln!(out, "listener{}:name={},address={},connected_clients={},total_connections_received={}",
+						id,
+						listener.kind,
+						listener.address,
+						listener.connected_clients.load(Ordering::Relaxed),
+						listener.total_connections_received.load(Ordering::Relaxed),
+					)?;
+				}
+			},
+			"stats" => {
+				writeln!(out, "# Stats")?;
+				writeln!(out, "total_connections_received:{}", counters.total_connections_received.load(Ordering::Relaxed))?;
+				writeln!(out, "total_commands_processed:{}", counters.total_commands_processed.load(Ordering::Relaxed))?;
+				writeln!(out, "expired_keys:{}", counters.expired_keys.load(Ordering::Relaxed))?;
+				writeln!(out, "expired_keys_pending:{}", self.expire_controller.lock().await.pending_expired(self.clock.now()))?;
+				writeln!(out, "audit_log_dropped:{}", counters.audit_log_dropped.load(Ordering::Relaxed))?;
+			},
+			"memory" => {
+				let used = self.memory_used();
+				writeln!(out, "# Memory")?;
+				writeln!(out, "used_memory:{}", used)?;
+				writeln!(out, "used_memory_human:{}", super::memory::human_size(used))?;
+				writeln!(out, "compactions:{}", counters.compactions.load(Ordering::Relaxed))?;
+			},
+			"keyspace" => {
+				let stats = self.stats().await;
+				writeln!(out, "# Keyspace")?;
+				writeln!(out, "keys:{}", stats.keys)?;
+				writeln!(out, "expires:{}", stats.keys_with_ttl)?;
+				writeln!(out, "strings:{}", stats.strings.keys)?;
+				writeln!(out, "lists:{}", stats.lists.keys)?;
+				writeln!(out, "sets:{}", stats.sets.keys)?;
+				writeln!(out, "hashes:{}", stats.hashes.keys)?;
+				writeln!(out, "json:{}", stats.json.keys)?;
+				writeln!(out, "bloom:{}", stats.bloom.keys)?;
+				writeln!(out, "timeseries:{}", stats.timeseries.keys)?;
+			},
+			"persistence" => {
+				writeln!(out, "# Persistence")?;
+				self.snapshot_info(out)?;
+			},
+			"commandstats" => {
+				writeln!(out, "# Commandstats")?;
+				self.command_stats.write_info(self.commands.iter(), out)?;
+			},
+			_ => (),
+		}
+		Ok(())
+	}
+
+	/// Clears the statistics reported by INFO stats and INFO commandstats
+	pub fn server_reset_stats(&self) {
+		self.counters.reset();
+		self.command_stats.reset();
+	}
+
+	pub async fn server_info(&self, mut args: Arguments) -> ExecResult {
+		const SECTIONS: [&str; 6] = ["server", "clients", "memory", "persistence", "stats", "keyspace"];
+		const ALL_SECTIONS: [&str; 7] = ["server", "clients", "memory", "persistence", "stats", "commandstats", "keyspace"];
+
+		let section = match args.pop_front() {
+			None => None,
+			Some(arg) => Some(Self::extract_string(Some(arg))?.to_lowercase()),
+		};
+		let sections = match &section {
+			None => SECTIONS.to_vec(),
+			Some(section) if section == "default" => SECTIONS.to_vec(),
+			Some(section) if section == "all" => ALL_SECTIONS.to_vec(),
+			Some(section) => vec![&section[..]],
+		};
+
+		let mut out = String::new();
+		for section in sections {
+			if !out.is_empty() {
+				out.push('\n');
+			}
+			self.server_info_section(section, &mut out).await.map_err(|e|format!("{}", e))?;
+		}
+		Ok(Value::Buffer(out.into_bytes()))
+	}
+}
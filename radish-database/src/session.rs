@@ -0,0 +1,51 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Per connection state passed to every command; commands which do not need it ignore it
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Client ids start from 1; 0 is left for the sessions created with `Session::default()`
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Default)]
+pub struct Session {
+	/// Unique id of the connection as reported by CLIENT ID
+	pub id: u64,
+	/// Remote address of the connection; None for embedded sessions
+	pub addr: Option<String>,
+	/// Set by CLIENT SETNAME
+	pub name: Option<String>,
+	/// Index of the database chosen by SELECT
+	pub database: usize,
+	/// Passed AUTH; only checked if `requirepass` is configured
+	pub authenticated: bool,
+	/// Replies may be sent as compressed frames, set by the handshake or CLIENT COMPRESSION
+	pub compression: bool,
+	/// Negotiated by the handshake; 0 if the client started without it
+	pub protocol_version: u16,
+}
+
+impl Session {
+	/// Session of a new connection with a unique id
+	pub fn new() -> Self {
+		Self {
+			id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+			..Self::default()
+		}
+	}
+}
@@ -14,13 +14,14 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use indexmap::IndexSet;
 
-use super::container::Container;
 use super::container::ContainerPtr;
 use super::container::ContainerImpl;
+use super::container::{canonical_member, normalize_member};
+use super::options::ScanOptions;
 
 type Key = super::Key;
 type Value = super::Value;
@@ -30,48 +31,59 @@ type ExecResult = super::ExecResult;
 type Inner = IndexSet<Value>;
 
 impl super::Storage {
-	async fn set_get_container(&self, key: Key) -> ContainerPtr {
-		self.get_container(key, ||Container::Set(ContainerImpl::<Inner>::new())).await
+	async fn set_get_container(&self, key: Key) -> Result<ContainerPtr, String> {
+		self.get_typed_container::<Inner>(key).await
 	}
-	async fn set_get_containers(&self, keys: Vec<Key>) -> Vec<ContainerPtr> {
-		self.get_containers(keys, ||Container::Set(ContainerImpl::<Inner>::new())).await
-	}
-	async fn set_unwrap_container(container: &Container) -> Result<&ContainerImpl<Inner>, String> {
-		match container {
-			Container::Set(ref c) => Ok(c),
-			_ => Err(format!("Unexpected container type")),
-		}
-	}
-	async fn set_unwrap_mut_container(container: &mut Container) -> Result<&mut ContainerImpl<Inner>, String> {
-		match container {
-			Container::Set(ref mut c) => Ok(c),
-			_ => Err(format!("Unexpected container type")),
-		}
+	async fn set_get_containers(&self, keys: Vec<Key>) -> Result<Vec<ContainerPtr>, String> {
+		self.get_typed_containers::<Inner>(keys).await
 	}
 	async fn set_lock<F: FnOnce(&Inner) -> ExecResult>(&self, key: Key, processor: F) -> ExecResult {
-		let c1 = self.set_get_container(key).await;
-		let c2 = c1.lock().await;
-		let c3 = Self::set_unwrap_container(&c2).await?;
-		processor(&c3.inner)
+		self.read_container(&key, processor).await
 	}
 	async fn set_lock_mut<F: FnOnce(&mut Inner) -> ExecResult>(&self, key: Key, processor: F) -> ExecResult {
-		let c1 = self.set_get_container(key).await;
-		let mut c2 = c1.lock().await;
-		let c3 = Self::set_unwrap_mut_container(&mut c2).await?;
-		processor(&mut c3.inner)
+		let c1 = self.set_get_container(key).await?;
+		let mut c2 = c1.write().await;
+		self.snapshot_preserve(&c1, &c2);
+		self.access_touch(&c2);
+		let c3 = c2.typed_mut::<Inner>()?;
+		let result = processor(&mut c3.inner);
+		self.memory_track(&mut c2);
+		result
 	}
 
 	async fn set_lock_containers<F>(&self, keys: Vec<Key>, callback: F) -> ExecResult
 	where F: FnOnce(VecDeque<&mut ContainerImpl<Inner>>) -> ExecResult {
-		let containers = self.set_get_containers(keys).await;
+		let containers = self.set_get_containers(keys).await?;
 		let (mut guards, _) = Self::lock_all(containers.iter().map(|c|c.as_ref()), std::iter::empty()).await;
+		containers.iter().zip(&guards).for_each(|(c, g)|self.snapshot_preserve(c, g));
+		guards.iter().for_each(|g|self.access_touch(g));
 
 		let mut inners = VecDeque::with_capacity(guards.len());
 		for g in &mut guards {
-			inners.push_back(Self::set_unwrap_mut_container(&mut *g).await?);
+			inners.push_back(g.typed_mut::<Inner>()?);
 		}
 
-		callback(inners)
+		let result = callback(inners);
+		for g in &mut guards {
+			self.memory_track(g);
+		}
+		result
+	}
+
+	/// Missing keys are read as empty sets and are not created
+	async fn set_read_containers<F>(&self, keys: &[Key], callback: F) -> ExecResult
+	where F: FnOnce(VecDeque<&Inner>) -> ExecResult {
+		let empty = Inner::new();
+		self.with_containers(&[], keys, |locked| {
+			let mut inners = VecDeque::with_capacity(keys.len());
+			for key in keys {
+				match locked.get(key) {
+					None => inners.push_back(&empty),
+					Some(c) => inners.push_back(&c.typed::<Inner>()?.inner),
+				}
+			}
+			callback(inners)
+		}).await
 	}
 
 	pub async fn set_card(&self, mut args: Arguments) -> ExecResult {
@@ -81,7 +93,7 @@ impl super::Storage {
 		}).await
 	}
 
-	pub async fn set_members(&self, mut args: Arguments) -> ExecResult {
+	pub async fn set_smembers(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
 		self.set_lock(key, |set| -> ExecResult {
 			Ok(Value::Array(set.iter().map(|v|v.clone()).collect()))
@@ -90,15 +102,22 @@ impl super::Storage {
 
 	pub async fn set_is_member(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let member = Self::extract(args.pop_front())?;
+		let member = Self::extract_member(args.pop_front())?;
 		self.set_lock(key, |set| -> ExecResult {
 			Ok(Value::Integer(if set.contains(&member) {1} else {0}))
 		}).await
 	}
 
-	pub async fn set_add(&self, mut args: Arguments) -> ExecResult {
+	pub async fn set_sadd(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
+		let args = args.into_iter().map(normalize_member).collect::<Result<Arguments, String>>()?;
+		let limits = self.limits();
+		limits.check_elements(&args)?;
 		self.set_lock_mut(key, |set| -> ExecResult {
+			if limits.max_collection_elements != 0 {
+				let added = args.iter().filter(|&arg|!set.contains(arg)).collect::<HashSet<&Value>>();
+				limits.check_collection_len(set.len() + added.len())?;
+			}
 			let mut count: u32 = 0;
 			for arg in args {
 				if set.insert(arg) {
@@ -113,7 +132,7 @@ impl super::Storage {
 		let key = Self::extract_key(args.pop_front())?;
 		self.set_lock_mut(key, |set| {
 			let mut count: u32 = 0;
-			for arg in args {
+			for arg in args.into_iter().map(canonical_member) {
 				if set.remove(&arg) {
 					count = count + 1;
 				}
@@ -140,29 +159,33 @@ impl super::Storage {
 	pub async fn set_move(&self, mut args: Arguments) -> ExecResult {
 		let source = Self::extract_key(args.pop_front())?;
 		let destination = Self::extract_key(args.pop_front())?;
-		let member = Self::extract(args.pop_front())?;
+		let member = Self::extract_new_member(args.pop_front())?;
+		let limits = self.limits();
 		self.set_lock_containers(vec![source, destination], |mut sets| -> ExecResult {
 			let source = sets.pop_front().unwrap();
-			if ! source.inner.remove(&member) {
+			if ! source.inner.contains(&member) {
 				Ok(Value::Integer(0))
 			} else {
 				let destination = sets.pop_front().unwrap();
+				if ! destination.inner.contains(&member) {
+					limits.check_collection_len(destination.inner.len() + 1)?;
+				}
+				source.inner.remove(&member);
 				destination.inner.insert(member);
 				Ok(Value::Integer(1))
 			}
 		}).await
 	}
 
-	fn set_diff_make_iter<'a>(sets: &'a VecDeque<&mut ContainerImpl<Inner>>) -> impl Iterator<Item=Value> + 'a {
+	fn set_diff_make_iter<'a>(sets: &'a VecDeque<&'a Inner>) -> impl Iterator<Item=Value> + 'a {
 		let main_set = sets.get(0).unwrap();
 		main_set
-		.inner
 		.iter()
 		.filter(move |&v| {
 			! sets
 			.iter()
 			.skip(1)
-			.any(|set| set.inner.contains(v))
+			.any(|set| set.contains(v))
 		})
 		.map(|v|v.clone())
 	}
@@ -172,7 +195,7 @@ impl super::Storage {
 		while let Ok(key) = Self::extract_key(args.pop_front()) {
 			keys.push(key);
 		}
-		self.set_lock_containers(keys, |sets| -> ExecResult {
+		self.set_read_containers(&keys, |sets| -> ExecResult {
 			Ok(Value::Array(Self::set_diff_make_iter(&sets).collect()))
 		}).await
 	}
@@ -185,8 +208,9 @@ impl super::Storage {
 		self.set_lock_containers(keys, |mut sets| -> ExecResult {
 			let dest_set = sets.pop_front().unwrap();
 
+			let sources = sets.iter().map(|s|&s.inner).collect();
 			let mut tmp = Inner::new();
-			Self::set_diff_make_iter(&sets).for_each(|v|{tmp.insert(v.clone());});
+			Self::set_diff_make_iter(&sources).for_each(|v|{tmp.insert(v.clone());});
 
 			dest_set.inner.clear();
 			dest_set.expiration_time = None;
@@ -196,16 +220,15 @@ impl super::Storage {
 		}).await
 	}
 
-	fn set_inter_make_iter<'a>(sets: &'a VecDeque<&mut ContainerImpl<Inner>>) -> impl Iterator<Item=Value> + 'a {
+	fn set_inter_make_iter<'a>(sets: &'a VecDeque<&'a Inner>) -> impl Iterator<Item=Value> + 'a {
 		let main_set = sets.get(0).unwrap();
 		main_set
-		.inner
 		.iter()
 		.filter(move |&v| {
 			! sets
 			.iter()
 			.skip(1)
-			.any(|set| ! set.inner.contains(v))
+			.any(|set| ! set.contains(v))
 		})
 		.map(|v|v.clone())
 	}
@@ -215,7 +238,7 @@ impl super::Storage {
 		while let Ok(key) = Self::extract_key(args.pop_front()) {
 			keys.push(key);
 		}
-		self.set_lock_containers(keys, |sets| -> ExecResult {
+		self.set_read_containers(&keys, |sets| -> ExecResult {
 			Ok(Value::Array(Self::set_inter_make_iter(&sets).collect()))
 		}).await
 	}
@@ -228,8 +251,9 @@ impl super::Storage {
 		self.set_lock_containers(keys, |mut sets| -> ExecResult {
 			let dest_set = sets.pop_front().unwrap();
 
+			let sources = sets.iter().map(|s|&s.inner).collect();
 			let mut tmp = Inner::new();
-			Self::set_inter_make_iter(&sets).for_each(|v|{tmp.insert(v.clone());});
+			Self::set_inter_make_iter(&sources).for_each(|v|{tmp.insert(v.clone());});
 
 			dest_set.inner.clear();
 			dest_set.expiration_time = None;
@@ -239,10 +263,10 @@ impl super::Storage {
 		}).await
 	}
 
-	fn set_union_make_iter<'a>(sets: &'a VecDeque<&mut ContainerImpl<Inner>>) -> impl Iterator<Item=Value> + 'a {
+	fn set_union_make_iter<'a>(sets: &'a VecDeque<&'a Inner>) -> impl Iterator<Item=Value> + 'a {
 		sets
 		.iter()
-		.flat_map(|s|s.inner.iter())
+		.flat_map(|s|s.iter())
 		.map(|v|v.clone())
 	}
 
@@ -251,7 +275,7 @@ impl super::Storage {
 		while let Ok(key) = Self::extract_key(args.pop_front()) {
 			keys.push(key);
 		}
-		self.set_lock_containers(keys, |sets| -> ExecResult {
+		self.set_read_containers(&keys, |sets| -> ExecResult {
 			let mut tmp = Inner::new();
 			Self::set_union_make_iter(&sets).for_each(|v|{tmp.insert(v.clone());});
 			Ok(Value::Array(tmp.drain(..).collect()))
@@ -266,8 +290,9 @@ impl super::Storage {
 		self.set_lock_containers(keys, |mut sets| -> ExecResult {
 			let dest_set = sets.pop_front().unwrap();
 
+			let sources = sets.iter().map(|s|&s.inner).collect();
 			let mut tmp = Inner::new();
-			Self::set_union_make_iter(&sets).for_each(|v|{tmp.insert(v.clone());});
+			Self::set_union_make_iter(&sources).for_each(|v|{tmp.insert(v.clone());});
 
 			dest_set.inner.clear();
 			dest_set.expiration_time = None;
@@ -277,23 +302,51 @@ impl super::Storage {
 		}).await
 	}
 
-	pub async fn _set_rand_member(&self, mut args: Arguments) -> ExecResult {
+	/// Without a count a single member, or Nill for an empty set; a positive count gives distinct members,
+	/// a negative one exactly `-count` members which may repeat
+	pub async fn set_rand_member(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let count = if let Ok(count) = Self::extract_integer(args.pop_front()) {count} else {1};
-		let (repeates, count) = if count >= 0 {(false, count as usize)} else {(true, -count as usize)};
-
-		self.set_lock_mut(key, |set| {
-			let mut items = VecDeque::with_capacity(count);
+		let count = match args.pop_front() {
+			None => None,
+			count => Some(Self::extract_integer(count)?),
+		};
+		// each repeated member takes at least a byte of the reply, so a longer one could not be sent anyway
+		if let Some(count) = count.filter(|&c|c < 0) {
+			let count = count.unsigned_abs();
+			if count > self.config.max_frame_size() as u64 {
+				return Err("ERR value is out of range".to_owned());
+			}
+			self.limits().check_collection_len(count as usize)?;
+		}
 
-			if repeates {
-				for _ in 0..count {
+		self.set_lock(key, |set| {
+			if set.is_empty() {
+				return match count {
+					None => Ok(Value::Nill),
+					Some(_) => Ok(Value::Array(VecDeque::new())),
+				};
+			}
+			let count = match count {
+				None => return Ok(set[rand::random::<usize>() % set.len()].clone()),
+				Some(count) => count,
+			};
+
+			let mut items = VecDeque::new();
+			if count < 0 {
+				for _ in 0..count.unsigned_abs() {
 					let index = rand::random::<usize>() % set.len();
-					if let Some(item) = set.get_index(index) {
-						items.push_back(item.clone());
-					}
+					items.push_back(set[index].clone());
 				}
 			} else {
-				return Err("Unimplemented".to_owned());
+				// partial Fisher–Yates over the indices; only the swapped ones are stored, so the set is not copied
+				let count = std::cmp::min(count as usize, set.len());
+				let mut swapped = HashMap::new();
+				for i in 0..count {
+					let j = i + rand::random::<usize>() % (set.len() - i);
+					let picked = *swapped.get(&j).unwrap_or(&j);
+					swapped.insert(j, *swapped.get(&i).unwrap_or(&i));
+					items.push_back(set[picked].clone());
+				}
 			}
 			Ok(Value::Array(items))
 		}).await
@@ -302,22 +355,7 @@ impl super::Storage {
 	pub async fn set_scan(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
 		let start = Self::extract_index(args.pop_front())?;
-
-		let mut pattern: Option<String> = None;
-		let mut max_check = 100usize;
-
-		while let Some(subcmd) = Self::extract_string(args.pop_front()).ok() {
-			match &subcmd.to_uppercase()[..] {
-				"MATCH" => pattern = Some(Self::extract_string(args.pop_front())?),
-				"COUNT" => max_check = Self::extract_index(args.pop_front())?,
-				arg => return Err(format!("Unexpected argument '{}'", arg)),
-			}
-		}
-
-		let pattern = match pattern {
-			None => None,
-			Some(pattern) => Some(regex::bytes::Regex::new(&pattern[..]).map_err(|e|format!("{}", e))?),
-		};
+		let ScanOptions {pattern, count: max_check, ..} = ScanOptions::parse(args, false)?;
 
 		let mut values = vec![];
 
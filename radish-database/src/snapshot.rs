@@ -0,0 +1,349 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Snapshot file: a header, the serialized `DatasetSnapshot` and a trailing checksum.
+//!
+//! ```text
+//! magic "RADISHSN" | version u16 | flags u16 | created at u64 (ms) | payload length u64 | payload | CRC-64 u64
+//! ```
+//! Numbers are big endian; the checksum covers the payload as stored. The only flag so far,
+//! `FLAG_ZSTD`, tells that the payload is a zstd stream, written and read as it is serialized,
+//! so the uncompressed payload is never held in memory.
+//!
+//! Saving does not stop writers: the keys and their containers are listed under the keyspace
+//! lock, which is cheap as only the pointers are cloned, then the containers are serialized
+//! one by one under their own read locks. A writer about to change a container of the list
+//! in place first copies it for the snapshot, unless it is serialized already; replaced and
+//! removed containers are kept alive by the list. So the file holds the dataset as of the
+//! listing and writers never wait for the disk.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::RwLock;
+
+use super::container::{Container, ContainerPtr};
+use super::dataset::{DatasetSnapshot, ImportMode, SnapshotData, SnapshotEntry};
+
+type CompactKey = super::CompactKey;
+type Value = super::Value;
+type Arguments = super::Arguments;
+type ExecResult = super::ExecResult;
+
+const MAGIC: &[u8; 8] = b"RADISHSN";
+/// Latest format version this build can write and read
+pub const FORMAT_VERSION: u16 = 1;
+/// The payload is compressed by zstd
+pub const FLAG_ZSTD: u16 = 0x1;
+const HEADER_SIZE: usize = 8 + 2 + 2 + 8 + 8;
+const CHECKSUM_SIZE: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotHeader {
+	pub version: u16,
+	/// Payload options, see `FLAG_ZSTD`
+	pub flags: u16,
+	pub created_at: SystemTime,
+}
+
+/// CRC-64/XZ (ECMA-182 polynomial, reflected)
+const CRC64_TABLE: [u64; 256] = crc64_table();
+
+const fn crc64_table() -> [u64; 256] {
+	const POLY: u64 = 0xC96C_5795_D787_0F42;
+	let mut table = [0u64; 256];
+	let mut i = 0;
+	while i < 256 {
+		let mut crc = i as u64;
+		let mut bit = 0;
+		while bit < 8 {
+			crc = if crc & 1 == 1 {(crc >> 1) ^ POLY} else {crc >> 1};
+			bit += 1;
+		}
+		table[i] = crc;
+		i += 1;
+	}
+	table
+}
+
+fn crc64(data: &[u8]) -> u64 {
+	let mut crc = !0u64;
+	for byte in data {
+		crc = CRC64_TABLE[((crc ^ *byte as u64) & 0xff) as usize] ^ (crc >> 8);
+	}
+	!crc
+}
+
+fn corrupt(reason: &str) -> String {
+	format!("snapshot corrupt: {}", reason)
+}
+
+fn read_u16(data: &[u8]) -> u16 {
+	u16::from_be_bytes([data[0], data[1]])
+}
+
+fn read_u64(data: &[u8]) -> u64 {
+	let mut bytes = [0u8; 8];
+	bytes.copy_from_slice(&data[..8]);
+	u64::from_be_bytes(bytes)
+}
+
+fn serialize_error(e: impl std::fmt::Display) -> String {
+	format!("Failed to serialize snapshot: {}", e)
+}
+
+#[cfg(feature = "compression")]
+fn compress(snapshot: &DatasetSnapshot, level: usize) -> Result<Vec<u8>, String> {
+	let mut encoder = zstd::stream::write::Encoder::new(Vec::new(), level as i32).map_err(serialize_error)?;
+	rmp_serde::encode::write(&mut encoder, snapshot).map_err(serialize_error)?;
+	encoder.finish().map_err(serialize_error)
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress(_snapshot: &DatasetSnapshot, _level: usize) -> Result<Vec<u8>, String> {
+	Err("Snapshot compression is not supported by this build".to_owned())
+}
+
+#[cfg(feature = "compression")]
+fn decompress(payload: &[u8]) -> Result<DatasetSnapshot, String> {
+	let decoder = zstd::stream::read::Decoder::new(payload).map_err(|e|corrupt(&e.to_string()))?;
+	rmp_serde::from_read(decoder).map_err(|e|corrupt(&e.to_string()))
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(_payload: &[u8]) -> Result<DatasetSnapshot, String> {
+	Err("snapshot is compressed, but compression is not supported by this build".to_owned())
+}
+
+/// A zero `compression_level` stores the payload uncompressed
+pub fn encode_snapshot(snapshot: &DatasetSnapshot, compression_level: usize) -> Result<Vec<u8>, String> {
+	let (flags, payload) = match compression_level {
+		0 => (0, rmp_serde::to_vec(snapshot).map_err(serialize_error)?),
+		level => (FLAG_ZSTD, compress(snapshot, level)?),
+	};
+	let created_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+
+	let mut out = Vec::with_capacity(HEADER_SIZE + payload.len() + CHECKSUM_SIZE);
+	out.extend_from_slice(MAGIC);
+	out.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+	out.extend_from_slice(&flags.to_be_bytes());
+	out.extend_from_slice(&created_at.to_be_bytes());
+	out.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+	out.extend_from_slice(&payload);
+	out.extend_from_slice(&crc64(&payload).to_be_bytes());
+	Ok(out)
+}
+
+/// `verify_checksum` is false only to recover what is possible from a damaged file
+pub fn decode_snapshot(data: &[u8], verify_checksum: bool) -> Result<(SnapshotHeader, DatasetSnapshot), String> {
+	if data.len() < HEADER_SIZE || &data[..8] != MAGIC {
+		return Err(corrupt("not a snapshot file"));
+	}
+	let header = SnapshotHeader {
+		version: read_u16(&data[8..]),
+		flags: read_u16(&data[10..]),
+		created_at: SystemTime::UNIX_EPOCH + Duration::from_millis(read_u64(&data[12..])),
+	};
+	if header.version > FORMAT_VERSION {
+		return Err(format!("snapshot format version {} is newer than supported version {}", header.version, FORMAT_VERSION));
+	}
+	if header.flags & !FLAG_ZSTD != 0 {
+		return Err(format!("snapshot flags {:#x} are not supported", header.flags));
+	}
+
+	let length = read_u64(&data[20..]);
+	let rest = &data[HEADER_SIZE..];
+	if (rest.len() as u64) < length.saturating_add(CHECKSUM_SIZE as u64) {
+		return Err(corrupt("file is truncated"));
+	}
+	let (payload, trailer) = rest.split_at(length as usize);
+	if verify_checksum && crc64(payload) != read_u64(trailer) {
+		return Err(corrupt("checksum mismatch"));
+	}
+	let snapshot = match header.flags & FLAG_ZSTD {
+		0 => rmp_serde::from_slice(payload).map_err(|e|corrupt(&e.to_string()))?,
+		_ => decompress(payload)?,
+	};
+	Ok((header, snapshot))
+}
+
+/// Containers of the snapshot being taken which are not serialized yet
+struct Capture {
+	/// Addresses of the containers which still hold the content of the snapshot
+	pending: HashSet<usize>,
+	/// Content copied by writers before they changed a pending container
+	copies: HashMap<usize, (SnapshotData, Option<SystemTime>)>,
+}
+
+/// Snapshot state shared by the saver and the writers
+#[derive(Default)]
+pub struct SnapshotState {
+	capture: std::sync::Mutex<Option<Capture>>,
+	/// One snapshot is taken at a time
+	saving: tokio::sync::Mutex<()>,
+	bgsave_in_progress: AtomicBool,
+	last_bgsave_failed: AtomicBool,
+	/// Unix time of the last successful save, 0 if none
+	last_save_time: AtomicU64,
+	/// Containers copied by writers during the last snapshot
+	last_copied: AtomicU64,
+}
+
+fn address(container: &RwLock<Container>) -> usize {
+	container as *const RwLock<Container> as usize
+}
+
+impl super::Storage {
+	/// Called by writers holding the write lock of `ptr` before they change `container` in place
+	pub(crate) fn snapshot_preserve(&self, ptr: &RwLock<Container>, container: &Container) {
+		let address = address(ptr);
+		let pending = match &mut *self.snapshot.capture.lock().unwrap() {
+			Some(capture) => capture.pending.remove(&address),
+			None => false,
+		};
+		if pending {
+			// the saver waits for the write lock held by the caller, so it sees the copy
+			let copy = super::dataset::export_container(container);
+			if let Some(capture) = &mut *self.snapshot.capture.lock().unwrap() {
+				capture.copies.insert(address, copy);
+			}
+		}
+	}
+
+	/// Consistent view of every alive key; see the module documentation
+	pub async fn export(&self) -> DatasetSnapshot {
+		let _saving = self.snapshot.saving.lock().await;
+		let (now, listed) = {
+			let containers = self.containers.lock().await;
+			let listed: Vec<(CompactKey, ContainerPtr)> = containers.iter().map(|(key, ptr)|(key.clone(), ptr.clone())).collect();
+			*self.snapshot.capture.lock().unwrap() = Some(Capture {
+				pending: listed.iter().map(|(_, ptr)|address(ptr)).collect(),
+				copies: HashMap::new(),
+			});
+			(self.clock.now(), listed)
+		};
+
+		let mut entries = Vec::with_capacity(listed.len());
+		let mut copied = 0;
+		for (key, ptr) in listed {
+			let container = ptr.read().await;
+			// a container no longer pending was copied by a writer
+			let copy = match &mut *self.snapshot.capture.lock().unwrap() {
+				Some(capture) => match capture.pending.remove(&address(&ptr)) {
+					true => None,
+					false => capture.copies.remove(&address(&ptr)),
+				},
+				None => None,
+			};
+			let (data, expiration_time) = match copy {
+				Some(copy) => {
+					copied += 1;
+					copy
+				},
+				None => super::dataset::export_container(&container),
+			};
+			drop(container);
+			if let Some(tm) = expiration_time {
+				if tm <= now {
+					continue;
+				}
+			}
+			entries.push(SnapshotEntry {
+				key: key.into(),
+				data,
+				expire_at: expiration_time.map(|tm|super::dataset::to_millis(self.clock.to_wall(tm))),
+			});
+		}
+		*self.snapshot.capture.lock().unwrap() = None;
+		self.snapshot.last_copied.store(copied, Ordering::Relaxed);
+		DatasetSnapshot {entries}
+	}
+
+	/// Writes into a temporary file next to `path` and renames it, so a crash never leaves a partial snapshot
+	pub async fn save_snapshot(&self, path: &std::path::Path) -> Result<(), String> {
+		let snapshot = self.export().await;
+		let level = self.config.rdb_compression_level();
+		let data = tokio::task::spawn_blocking(move ||encode_snapshot(&snapshot, level))
+			.await
+			.map_err(|e|format!("Failed to serialize snapshot: {}", e))??;
+		let temporary = path.with_extension("tmp");
+		let failed = |e: std::io::Error|format!("Failed to write snapshot '{}': {}", path.display(), e);
+		tokio::fs::write(&temporary, data).await.map_err(failed)?;
+		tokio::fs::rename(&temporary, path).await.map_err(failed)?;
+		let saved_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+		self.snapshot.last_save_time.store(saved_at.as_secs(), Ordering::Relaxed);
+		Ok(())
+	}
+
+	/// Replaces the dataset with the snapshot from the file
+	pub async fn load_snapshot(&self, path: &std::path::Path, verify_checksum: bool) -> Result<SnapshotHeader, String> {
+		let data = tokio::fs::read(path).await.map_err(|e|format!("Failed to read snapshot '{}': {}", path.display(), e))?;
+		let (header, snapshot) = decode_snapshot(&data, verify_checksum)?;
+		self.import(snapshot, ImportMode::Replace).await?;
+		Ok(header)
+	}
+
+	/// Path of the snapshot file configured by `dir` and `dbfilename`
+	pub fn snapshot_path(&self) -> std::path::PathBuf {
+		std::path::Path::new(&self.config.dir()).join(self.config.dbfilename())
+	}
+
+	pub(crate) fn snapshot_info(&self, out: &mut String) -> std::fmt::Result {
+		use std::fmt::Write;
+		let state = &self.snapshot;
+		writeln!(out, "bgsave_in_progress:{}", state.bgsave_in_progress.load(Ordering::Relaxed) as u8)?;
+		writeln!(out, "last_save_time:{}", state.last_save_time.load(Ordering::Relaxed))?;
+		writeln!(out, "last_bgsave_status:{}", if state.last_bgsave_failed.load(Ordering::Relaxed) {"err"} else {"ok"})?;
+		writeln!(out, "last_save_copied_containers:{}", state.last_copied.load(Ordering::Relaxed))
+	}
+
+	pub async fn snapshot_save(&self, _args: Arguments) -> ExecResult {
+		self.save_snapshot(&self.snapshot_path()).await?;
+		Ok(Value::Ok)
+	}
+
+	/// Saves in a spawned task; the result is reported by INFO persistence
+	pub async fn snapshot_bgsave(&self, _args: Arguments) -> ExecResult {
+		if self.snapshot.bgsave_in_progress.swap(true, Ordering::AcqRel) {
+			return Err("Background save already in progress".to_owned());
+		}
+		let storage = self.clone();
+		tokio::spawn(async move {
+			let path = storage.snapshot_path();
+			let result = storage.save_snapshot(&path).await;
+			if let Err(err) = &result {
+				log::error!("Background save failed: {}", err);
+			}
+			storage.snapshot.last_bgsave_failed.store(result.is_err(), Ordering::Relaxed);
+			storage.snapshot.bgsave_in_progress.store(false, Ordering::Release);
+		});
+		Ok(Value::Buffer(b"Background saving started".to_vec()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn crc64_check_values() {
+		// check value of the CRC-64/XZ catalogue entry
+		assert_eq!(crc64(b"123456789"), 0x995D_C9BB_DF19_39FA);
+		assert_eq!(crc64(b""), 0);
+	}
+}
@@ -0,0 +1,88 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::time::{Duration, SystemTime};
+use std::sync::atomic::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use super::container::Container;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TypeStats {
+	pub keys: u64,
+	/// Accounted memory of the containers, without keys
+	pub memory: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct StorageStats {
+	pub keys: u64,
+	pub strings: TypeStats,
+	pub lists: TypeStats,
+	pub sets: TypeStats,
+	pub hashes: TypeStats,
+	#[serde(default)]
+	pub json: TypeStats,
+	#[serde(default)]
+	pub bloom: TypeStats,
+	#[serde(default)]
+	pub timeseries: TypeStats,
+	pub keys_with_ttl: u64,
+	/// Nearest expiration time in milliseconds since UNIX epoch
+	pub nearest_expiration: Option<u64>,
+	/// Keys removed by expiration since start
+	pub expired_keys: u64,
+	/// Total tracked memory, see `Storage::memory_used`
+	pub memory: u64,
+}
+
+impl StorageStats {
+	fn add(&mut self, container: &Container, clock: &super::Timeline) {
+		let (stats, expiration_time) = match container {
+			Container::Strings(c) => (&mut self.strings, c.expiration_time),
+			Container::List(c) => (&mut self.lists, c.expiration_time),
+			Container::Set(c) => (&mut self.sets, c.expiration_time),
+			Container::Hash(c) => (&mut self.hashes, c.expiration_time),
+			Container::Json(c) => (&mut self.json, c.expiration_time),
+			Container::Bloom(c) => (&mut self.bloom, c.expiration_time),
+			Container::TimeSeries(c) => (&mut self.timeseries, c.expiration_time),
+		};
+		stats.keys += 1;
+		stats.memory += container.accounted_size() as u64;
+		self.keys += 1;
+		if let Some(tm) = expiration_time {
+			let millis = clock.to_wall(tm).duration_since(SystemTime::UNIX_EPOCH).unwrap_or(Duration::new(0, 0)).as_millis() as u64;
+			self.keys_with_ttl += 1;
+			self.nearest_expiration = Some(self.nearest_expiration.map_or(millis, |nearest|std::cmp::min(nearest, millis)));
+		}
+	}
+}
+
+impl super::Storage {
+	/// Scans the keyspace: the containers lock is held only to copy the container pointers
+	pub async fn stats(&self) -> StorageStats {
+		let containers: Vec<_> = self.containers.lock().await.values().cloned().collect();
+		let mut stats = StorageStats::default();
+		for container in containers {
+			stats.add(&*container.read().await, &self.clock);
+		}
+		stats.expired_keys = self.counters.expired_keys.load(Ordering::Relaxed);
+		stats.memory = self.memory_used() as u64;
+		stats
+	}
+}
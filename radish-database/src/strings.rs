@@ -15,6 +15,7 @@
  */
 
 use std::iter::FromIterator;
+use std::ops::Range;
 use std::time::{SystemTime, Duration};
 use std::collections::VecDeque;
 
@@ -23,6 +24,8 @@ use indexmap::map::Entry;
 use super::container::Container;
 use super::container::ContainerPtr;
 use super::container::ContainerImpl;
+use super::commands::wrong_arity;
+use super::options::OptionParser;
 
 type Key = super::Key;
 type Value = super::Value;
@@ -31,6 +34,27 @@ type ExecResult = super::ExecResult;
 
 type Inner = Vec<u8>;
 
+/// IFEQ and IFGT are extensions: SET writes only if the current value equals the expected bytes
+/// or is an integer less than the given one. On a missing key they fail unless NX is given too,
+/// which means "create, or replace if the condition holds". XX is implied by them.
+const SET_OPTIONS: OptionParser = OptionParser::new(&["KEEPTTL", "XX", "NX"], &["EX", "PX", "IFEQ", "IFGT"])
+	.exclusive(&[&["XX", "NX"], &["EX", "PX", "KEEPTTL"], &["IFEQ", "IFGT", "XX"]]);
+
+/// Condition of a compare-and-set
+enum SetCondition {
+	Equal(Inner),
+	LessThan(i64),
+}
+
+impl SetCondition {
+	fn check(&self, current: &Inner) -> Result<bool, String> {
+		match self {
+			SetCondition::Equal(expected) => Ok(current == expected),
+			SetCondition::LessThan(limit) => Ok(inner_parse::<i64>(current, 0)? < *limit),
+		}
+	}
+}
+
 #[derive(Clone, Copy)]
 enum BitOperation {
 	And,
@@ -66,47 +90,171 @@ where	T: std::str::FromStr,
 }
 
 
-impl super::Storage {
-	fn strings_container_factory() -> Container {
-		Container::Strings(ContainerImpl::<Inner>::new())
+/// Integer type of a BITFIELD field: `i1`..`i64` or `u1`..`u63`
+#[derive(Clone, Copy)]
+struct BitfieldType {
+	signed: bool,
+	bits: u32,
+}
+
+impl std::str::FromStr for BitfieldType {
+	type Err = String;
+
+	fn from_str(spec: &str) -> Result<Self, Self::Err> {
+		let error = ||"ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.".to_owned();
+		let signed = match spec.chars().next() {
+			Some('i') | Some('I') => true,
+			Some('u') | Some('U') => false,
+			_ => return Err(error()),
+		};
+		let bits = spec[1..].parse::<u32>().map_err(|_|error())?;
+		match (signed, bits) {
+			(_, 0) | (true, 65..=u32::MAX) | (false, 64..=u32::MAX) => Err(error()),
+			_ => Ok(BitfieldType {signed, bits}),
+		}
 	}
-	async fn strings_get_container(&self, key: Key) -> ContainerPtr {
-		self.get_container(key, Self::strings_container_factory).await
+}
+
+impl BitfieldType {
+	fn mask(&self) -> u64 {
+		match self.bits {
+			64 => u64::MAX,
+			bits => (1 << bits) - 1,
+		}
 	}
-	async fn strings_get_containers(&self, keys: Vec<Key>) -> Vec<ContainerPtr> {
-		self.get_containers(keys, Self::strings_container_factory).await
+	fn min(&self) -> i128 {
+		if self.signed {-(1 << (self.bits - 1))} else {0}
 	}
-	async fn strings_try_get_containers(&self, keys: &Vec<Key>) -> Vec<Option<ContainerPtr>> {
-		self.try_get_containers(keys).await
+	fn max(&self) -> i128 {
+		if self.signed {(1 << (self.bits - 1)) - 1} else {(1 << self.bits) - 1}
 	}
-	fn strings_unwrap_container(container: &Container) -> Result<&ContainerImpl<Inner>, String> {
-		match container {
-			Container::Strings(ref c) => Ok(c),
-			_ => Err(format!("Unexpected container type")),
+	/// Value of the raw bits, sign extended for the signed types
+	fn decode(&self, raw: u64) -> i64 {
+		let shift = 64 - self.bits;
+		match self.signed {
+			true => ((raw << shift) as i64) >> shift,
+			false => raw as i64,
 		}
 	}
-	fn strings_unwrap_mut_container(container: &mut Container) -> Result<&mut ContainerImpl<Inner>, String> {
-		match container {
-			Container::Strings(ref mut c) => Ok(c),
-			_ => Err(format!("Unexpected container type")),
+	/// Fits the value into the type, None if it overflows in the FAIL mode
+	fn fit(&self, value: i128, overflow: BitfieldOverflow) -> Option<i64> {
+		if value >= self.min() && value <= self.max() {
+			return Some(value as i64);
+		}
+		match overflow {
+			BitfieldOverflow::Wrap => Some(self.decode(value as u64 & self.mask())),
+			BitfieldOverflow::Sat if value < self.min() => Some(self.min() as i64),
+			BitfieldOverflow::Sat => Some(self.max() as i64),
+			BitfieldOverflow::Fail => None,
 		}
 	}
+}
+
+#[derive(Clone, Copy)]
+enum BitfieldOverflow {
+	Wrap,
+	Sat,
+	Fail,
+}
+
+impl std::str::FromStr for BitfieldOverflow {
+	type Err = String;
+
+	fn from_str(mode: &str) -> Result<Self, Self::Err> {
+		match &mode.to_lowercase()[..] {
+			"wrap" => Ok(BitfieldOverflow::Wrap),
+			"sat" => Ok(BitfieldOverflow::Sat),
+			"fail" => Ok(BitfieldOverflow::Fail),
+			_ => Err("ERR Invalid OVERFLOW type specified".to_owned()),
+		}
+	}
+}
+
+enum BitfieldOperation {
+	Get,
+	Set(i64, BitfieldOverflow),
+	IncrBy(i64, BitfieldOverflow),
+}
+
+/// Bytes from `start` to `end` inclusive, negative ones count from the tail;
+/// clamped to the string, None if no byte is in the range
+fn byte_bounds(cnt: &Inner, start: i64, end: i64) -> Option<Range<usize>> {
+	let len = cnt.len() as i64;
+	let start = if start < 0 {std::cmp::max(len + start, 0)} else {start};
+	let end = if end < 0 {len + end} else {std::cmp::min(end, len - 1)};
+	if start > end || start >= len {
+		None
+	} else {
+		Some(start as usize..end as usize + 1)
+	}
+}
+
+/// Reads `bits` bits starting at the bit `offset`, the most significant bit first; bits past the end are zeros
+fn read_bits(cnt: &Inner, offset: u64, bits: u32) -> u64 {
+	(offset..offset + bits as u64).fold(0, |value, position| {
+		let byte = cnt.get((position / 8) as usize).copied().unwrap_or(0);
+		(value << 1) | ((byte >> (7 - position % 8)) & 1) as u64
+	})
+}
+
+/// Writes the low `bits` bits of the value starting at the bit `offset`; the buffer must be long enough
+fn write_bits(cnt: &mut Inner, offset: u64, bits: u32, value: u64) {
+	for i in 0..bits as u64 {
+		let position = offset + i;
+		let mask = 0b1000_0000 >> (position % 8);
+		let byte = &mut cnt[(position / 8) as usize];
+		if (value >> (bits as u64 - 1 - i)) & 1 == 1 {
+			*byte |= mask;
+		} else {
+			*byte &= !mask;
+		}
+	}
+}
+
+
+impl super::Storage {
+	async fn strings_get_container(&self, key: Key) -> Result<ContainerPtr, String> {
+		self.get_typed_container::<Inner>(key).await
+	}
+	async fn strings_get_containers(&self, keys: Vec<Key>) -> Result<Vec<ContainerPtr>, String> {
+		self.get_typed_containers::<Inner>(keys).await
+	}
+	async fn strings_try_get_containers(&self, keys: &Vec<Key>) -> Vec<Option<ContainerPtr>> {
+		self.try_get_containers(keys).await
+	}
 	async fn strings_lock<F: FnOnce(&Inner) -> ExecResult>(&self, key: Key, processor: F) -> ExecResult {
-		let c1 = self.strings_get_container(key).await;
-		let c2 = c1.lock().await;
-		let c3 = Self::strings_unwrap_container(&c2)?;
-		processor(&c3.inner)
+		self.read_container(&key, processor).await
 	}
 	async fn strings_lock_mut<F: FnOnce(&mut Inner) -> ExecResult>(&self, key: Key, processor: F) -> ExecResult {
-		let c1 = self.strings_get_container(key).await;
-		let mut c2 = c1.lock().await;
-		let c3 = Self::strings_unwrap_mut_container(&mut c2)?;
-		processor(&mut c3.inner)
+		let c1 = self.strings_get_container(key).await?;
+		let mut c2 = c1.write().await;
+		self.snapshot_preserve(&c1, &c2);
+		self.access_touch(&c2);
+		let c3 = c2.typed_mut::<Inner>()?;
+		let result = processor(&mut c3.inner);
+		self.memory_track(&mut c2);
+		result
+	}
+
+	async fn strings_read_locks<F>(&self, read_keys: &Vec<Key>, callback: F) -> ExecResult
+	where F: FnOnce(VecDeque<Option<&ContainerImpl<Inner>>>) -> ExecResult {
+		let containers = self.strings_try_get_containers(read_keys).await;
+		let (guards, order) = Self::lock_all_read(containers.iter().map(|x|x.as_deref())).await;
+		guards.values().for_each(|g|self.access_touch(g));
+
+		let mut out_reads = VecDeque::with_capacity(order.len());
+		for address in &order {
+			match address {
+				None => out_reads.push_back(None),
+				Some(address) => out_reads.push_back(Some(guards[address].typed::<Inner>()?)),
+			}
+		}
+		callback(out_reads)
 	}
 
 	async fn strings_locks<F>(&self, write_keys: Vec<Key>, read_keys: &Vec<Key>, callback: F) -> ExecResult
 	where F: FnOnce(VecDeque<&mut ContainerImpl<Inner>>, VecDeque<Option<&ContainerImpl<Inner>>>) -> ExecResult {
-		let write_containers = self.strings_get_containers(write_keys).await;
+		let write_containers = self.strings_get_containers(write_keys).await?;
 		let read_containers = self.strings_try_get_containers(read_keys).await;
 		let writes = write_containers.iter().map(|x|x.as_ref());
 		let reads = read_containers.iter().map(|x|{
@@ -116,26 +264,35 @@ impl super::Storage {
 			}
 		});
 		let (mut writes, reads) = Self::lock_all(writes, reads).await;
+		write_containers.iter().zip(&writes).for_each(|(c, g)|self.snapshot_preserve(c, g));
+		writes.iter().for_each(|g|self.access_touch(g));
+		reads.iter().flatten().for_each(|g|self.access_touch(g));
 
 		let mut out_writes = VecDeque::with_capacity(writes.len());
 		for g in &mut writes {
-			out_writes.push_back(Self::strings_unwrap_mut_container(&mut **g)?);
+			out_writes.push_back(g.typed_mut::<Inner>()?);
 		}
 		let mut out_reads = VecDeque::with_capacity(reads.len());
 		for g in &reads {
 			match g {
 				None => out_reads.push_back(None),
-				Some(g) => out_reads.push_back(Some(Self::strings_unwrap_container(&**g)?)),
+				Some(g) => out_reads.push_back(Some(g.typed::<Inner>()?)),
 			}
 		}
 
-		callback(out_writes, out_reads)
+		let result = callback(out_writes, out_reads);
+		for g in &mut writes {
+			self.memory_track(g);
+		}
+		result
 	}
 
 	pub async fn strings_append(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
 		let value = Self::extract_buffer(args.pop_front())?;
+		let limits = self.limits();
 		self.strings_lock_mut(key, |cnt| -> ExecResult {
+			limits.check_value_size(cnt.len() + value.len())?;
 			cnt.append(&mut value.into_iter().collect());
 			Ok(Value::Integer(cnt.len() as i64))
 		}).await
@@ -143,7 +300,7 @@ impl super::Storage {
 
 	pub async fn strings_get(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		self.strings_locks(vec![], &vec![key], |_, mut cnts| -> ExecResult {
+		self.strings_read_locks(&vec![key], |mut cnts| -> ExecResult {
 			let cnt = cnts.remove(0).expect("option should be exists, but not");
 			match cnt {
 				Some(cnt) => Ok(Value::Buffer(cnt.inner.clone())),
@@ -152,44 +309,74 @@ impl super::Storage {
 		}).await
 	}
 
-	pub async fn strings_set(&mut self, mut args: Arguments) -> ExecResult {
+	pub async fn strings_set(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
 		let value = Self::extract_buffer(args.pop_front())?;
+		self.limits().check_value_size(value.len())?;
+
+		let mut options = SET_OPTIONS.parse(args)?;
+		let keepttl = options.flag("KEEPTTL");
+		let set_if_exists = match (options.flag("XX"), options.flag("NX")) {
+			(true, _) => Some(true),
+			(_, true) => Some(false),
+			_ => None,
+		};
+		let expire = match (options.unsigned("EX")?, options.unsigned("PX")?) {
+			(Some(seconds), _) => Some(self.clock.now() + Duration::from_secs(seconds)),
+			(_, Some(millis)) => Some(self.clock.now() + Duration::from_millis(millis)),
+			_ => None,
+		};
 
-		let mut keepttl = false;
-		let mut expire: Option<SystemTime> = None;
-		let mut set_if_exists: Option<bool> = None;
-
-		while let Some(subcmd) = Self::extract_string(args.pop_front()).ok() {
-			match &subcmd.to_uppercase()[..] {
-				"KEEPTTL" => keepttl = true,
-				"XX" => set_if_exists = Some(true),
-				"NX" => set_if_exists = Some(false),
-				"EX" => expire = Some(SystemTime::now() + Duration::from_secs(Self::extract_unsigned_integer(args.pop_front())?)),
-				"PX" => expire = Some(SystemTime::now() + Duration::from_millis(Self::extract_unsigned_integer(args.pop_front())?)),
-				arg => return Err(format!("Unexpected argument '{}'", arg)),
-			}
-		}
+		let condition = match (options.value("IFEQ"), options.integer("IFGT")?) {
+			(Some(expected), _) => Some(SetCondition::Equal(Self::extract_buffer(Some(expected))?)),
+			(_, Some(limit)) => Some(SetCondition::LessThan(limit)),
+			_ => None,
+		};
 
 		let mut cnt = ContainerImpl::<Inner>::new();
 		cnt.inner = value;
-		if ! keepttl {
-			cnt.expiration_time = None;
-		}
-		if let Some(expire) = expire {
-			cnt.expiration_time = Some(expire);
-		}
-		let cnt = Self::make_container(Container::Strings(cnt));
+		cnt.expiration_time = expire;
+		let mut cnt = Container::Strings(cnt);
 
 		let mut containers = self.containers.lock().await;
-		let entry = containers.entry(key.clone());
-		let result = match (set_if_exists, entry) {
-			(None, Entry::Vacant(e)) | (Some(false), Entry::Vacant(e)) => {
-				e.insert(cnt);
+		self.reap_expired(&mut containers, &key).await;
+		let entry = containers.entry(key[..].into());
+		let result = match (set_if_exists, condition, entry) {
+			(None, None, Entry::Vacant(e)) | (Some(false), _, Entry::Vacant(e)) => {
+				self.memory_track_insert(&key, &mut cnt);
+				self.access_touch(&cnt);
+				e.insert(Self::make_container(cnt));
 				Ok(Value::Ok)
 			},
-			(None, Entry::Occupied(mut e)) | (Some(true), Entry::Occupied(mut e)) => {
-				*e.get_mut() = cnt;
+			(_, Some(condition), Entry::Occupied(e)) => {
+				// Changed in place under the container lock, so it is atomic with other writers of the key
+				let mut container = e.get().write().await;
+				self.snapshot_preserve(e.get(), &container);
+				let current = container.typed_mut::<Inner>()?;
+				if condition.check(&current.inner)? {
+					if let Container::Strings(new) = cnt {
+						current.inner = new.inner;
+					}
+					if ! keepttl {
+						current.expiration_time = expire;
+					}
+					self.access_touch(&container);
+					self.memory_track(&mut container);
+					Ok(Value::Ok)
+				} else {
+					Ok(Value::Nill)
+				}
+			},
+			(None, None, Entry::Occupied(mut e)) | (Some(true), None, Entry::Occupied(mut e)) => {
+				if keepttl {
+					if let (Container::Strings(new), Container::Strings(old)) = (&mut cnt, &*e.get().read().await) {
+						new.expiration_time = old.expiration_time;
+					}
+				}
+				self.memory_track_insert(&key, &mut cnt);
+				self.access_touch(&cnt);
+				let old = std::mem::replace(e.get_mut(), Self::make_container(cnt));
+				self.memory_track_remove(&key, &*old.read().await);
 				Ok(Value::Ok)
 			},
 			_ => Ok(Value::Nill),
@@ -202,49 +389,64 @@ impl super::Storage {
 		result
 	}
 
-	pub async fn strings_setex_impl(&mut self, key: Key, timepoint: SystemTime, value: Vec<u8>) -> ExecResult {
-		let cnt = self.strings_get_container(key.clone()).await;
-		let mut cnt = cnt.lock().await;
-		let mut cnt = Self::strings_unwrap_mut_container(&mut cnt)?;
+	pub async fn strings_setex_impl(&self, key: Key, timepoint: SystemTime, value: Vec<u8>) -> ExecResult {
+		self.limits().check_value_size(value.len())?;
+		let cnt = self.strings_get_container(key.clone()).await?;
+		let mut container = cnt.write().await;
+		self.snapshot_preserve(&cnt, &container);
+		self.access_touch(&container);
+		let cnt = container.typed_mut::<Inner>()?;
 
 		cnt.inner = value;
 		cnt.expiration_time = Some(timepoint);
-		drop(cnt);
+		self.memory_track(&mut container);
 
 		self.expire_key_at(&key, timepoint).await;
 		Ok(Value::Ok)
 	}
 
-	pub async fn strings_setex(&mut self, mut args: Arguments) -> ExecResult {
+	/// SETEX and PSETEX take a positive time to live
+	fn extract_setex_ttl(arg: Option<Value>, command: &str) -> Result<u64, String> {
+		match Self::extract_integer(arg)? {
+			ttl if ttl > 0 => Ok(ttl as u64),
+			_ => Err(format!("ERR invalid expire time in '{}' command", command)),
+		}
+	}
+
+	pub async fn strings_setex(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let seconds = Self::extract_unsigned_integer(args.pop_front())?;
+		let seconds = Self::extract_setex_ttl(args.pop_front(), "setex")?;
 		let value = Self::extract_buffer(args.pop_front())?;
-		let timepoint = SystemTime::now() + Duration::from_secs(seconds);
+		let timepoint = self.clock.now() + Duration::from_secs(seconds);
 		self.strings_setex_impl(key, timepoint, value).await
 	}
 
-	pub async fn strings_psetex(&mut self, mut args: Arguments) -> ExecResult {
+	pub async fn strings_psetex(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
-		let millis = Self::extract_unsigned_integer(args.pop_front())?;
+		let millis = Self::extract_setex_ttl(args.pop_front(), "psetex")?;
 		let value = Self::extract_buffer(args.pop_front())?;
-		let timepoint = SystemTime::now() + Duration::from_millis(millis);
+		let timepoint = self.clock.now() + Duration::from_millis(millis);
 		self.strings_setex_impl(key, timepoint, value).await
 	}
 
-	pub async fn strings_setnx(&mut self, mut args: Arguments) -> ExecResult {
+	pub async fn strings_setnx(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
 		let value = Self::extract_buffer(args.pop_front())?;
+		self.limits().check_value_size(value.len())?;
 
 		let mut cnt = ContainerImpl::<Inner>::new();
 		cnt.inner = value;
 		cnt.expiration_time = None;
-		let cnt = Self::make_container(Container::Strings(cnt));
+		let mut cnt = Container::Strings(cnt);
 
 		let mut containers = self.containers.lock().await;
-		match containers.entry(key.clone()) {
+		self.reap_expired(&mut containers, &key).await;
+		match containers.entry(key[..].into()) {
 			Entry::Occupied(_) => Ok(Value::Bool(false)),
 			Entry::Vacant(e) => {
-				e.insert(cnt);
+				self.memory_track_insert(&key, &mut cnt);
+				self.access_touch(&cnt);
+				e.insert(Self::make_container(cnt));
 				Ok(Value::Bool(true))
 			},
 		}
@@ -253,13 +455,14 @@ impl super::Storage {
 	pub async fn strings_getset(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
 		let value = Self::extract_buffer(args.pop_front())?;
+		self.limits().check_value_size(value.len())?;
 		let mut value: Inner = value.into();
 		self.strings_locks(vec![key], &vec![], |mut cnt, _| {
-			let mut cnt = cnt.remove(0).expect("key should be created, but not");
+			let cnt = cnt.remove(0).expect("key should be created, but not");
 			cnt.expiration_time = None;
 			std::mem::swap(&mut cnt.inner, &mut value);
 			Ok(Value::Nill)
-		}).await.unwrap();
+		}).await?;
 		Ok(Value::Buffer(value.into()))
 	}
 
@@ -310,15 +513,12 @@ impl super::Storage {
 		let start = if let Ok(start) = Self::extract_integer(args.pop_front()) {start} else {0};
 		let end = if let Ok(end) = Self::extract_integer(args.pop_front()) {end} else {-1};
 		self.strings_lock(key, |cnt| -> ExecResult {
-			let start =     if start >= 0 {start} else {cnt.len() as i64 + start} as usize;
-			let end   = 1 + if end   >= 0 {  end} else {cnt.len() as i64 +   end} as usize;
-			if start >= cnt.len() || start >= end {
-				return Ok(Value::Integer(0));
-			}
-			let sum: u64 = cnt
+			let range = match byte_bounds(cnt, start, end) {
+				None => return Ok(Value::Integer(0)),
+				Some(range) => range,
+			};
+			let sum: u64 = cnt[range]
 				.iter()
-				.skip(start)
-				.take(end - start)
 				.map(|ch|BITCOUNTMAP[*ch as usize] as u64)
 				.sum();
 			Ok(Value::Integer(sum as i64))
@@ -327,7 +527,7 @@ impl super::Storage {
 
 	pub async fn strings_mget(&self, mut args: Arguments) -> ExecResult {
 		let keys = args.drain(..).filter_map(|a|Self::extract_key(Some(a)).ok()).collect();
-		self.strings_locks(vec![], &keys, |_, cnts| {
+		self.strings_read_locks(&keys, |cnts| {
 			let mut out = VecDeque::with_capacity(cnts.len());
 			for cnt in cnts {
 				match cnt {
@@ -340,17 +540,21 @@ impl super::Storage {
 	}
 
 	pub async fn strings_mset(&self, mut args: Arguments) -> ExecResult {
+		if args.len() % 2 == 1 {
+			return Err(wrong_arity("MSET"));
+		}
 		let mut keys = Vec::with_capacity(args.len() / 2);
 		let mut values = VecDeque::with_capacity(args.len() / 2);
 		while args.len() > 1 {
 			if let Ok(key) = Self::extract_key(args.pop_front()) {
 				keys.push(key);
 				let value = Self::extract_buffer(args.pop_front())?;
+				self.limits().check_value_size(value.len())?;
 				values.push_back(value);
 			}
 		}
 		self.strings_locks(keys, &vec![], |cnts, _| {
-			for mut cnt in cnts {
+			for cnt in cnts {
 				cnt.inner = values.pop_front().unwrap();
 				cnt.expiration_time = None;
 			}
@@ -447,8 +651,10 @@ impl super::Storage {
 		let mut mask = 0b1000_0000;
 		mask >>= bit_index;
 
+		let limits = self.limits();
 		self.strings_lock_mut(key, |cnt| -> ExecResult {
 			if byte_index >= cnt.len() {
+				limits.check_value_size(1 + byte_index)?;
 				cnt.resize(1 + byte_index, 0);
 			}
 			let byte = cnt.get_mut(byte_index).unwrap();
@@ -465,6 +671,82 @@ impl super::Storage {
 		}).await
 	}
 
+	/// Bit offset of a BITFIELD field: absolute, or in units of the field width when prefixed with `#`
+	fn extract_bitfield_offset(arg: Option<Value>, field: BitfieldType) -> Result<u64, String> {
+		let error = ||"ERR bit offset is not an integer or out of range".to_owned();
+		let offset = match Self::extract(arg)? {
+			Value::Integer(offset) => num::cast(offset).ok_or_else(error)?,
+			Value::Buffer(offset) => {
+				let offset = std::str::from_utf8(&offset).map_err(|_|error())?;
+				match offset.strip_prefix('#') {
+					Some(index) => index.parse::<u64>().map_err(|_|error())?.checked_mul(field.bits as u64).ok_or_else(error)?,
+					None => offset.parse::<u64>().map_err(|_|error())?,
+				}
+			},
+			_ => return Err(error()),
+		};
+		match offset + field.bits as u64 <= 1 << 32 {
+			true => Ok(offset),
+			false => Err(error()),
+		}
+	}
+
+	pub async fn strings_bitfield(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+
+		// every subcommand is parsed before the first one is applied, so a syntax error changes nothing
+		let mut operations = Vec::new();
+		let mut overflow = BitfieldOverflow::Wrap;
+		while let Some(subcommand) = args.pop_front() {
+			let subcommand = Self::extract_string(Some(subcommand))?.to_uppercase();
+			if subcommand == "OVERFLOW" {
+				overflow = Self::extract_string(args.pop_front())?.parse()?;
+				continue;
+			}
+			let field = Self::extract_string(args.pop_front())?.parse::<BitfieldType>()?;
+			let offset = Self::extract_bitfield_offset(args.pop_front(), field)?;
+			let operation = match &subcommand[..] {
+				"GET" => BitfieldOperation::Get,
+				"SET" => BitfieldOperation::Set(Self::extract_integer(args.pop_front())?, overflow),
+				"INCRBY" => BitfieldOperation::IncrBy(Self::extract_integer(args.pop_front())?, overflow),
+				_ => return Err("ERR syntax error".to_owned()),
+			};
+			operations.push((field, offset, operation));
+		}
+
+		let limits = self.limits();
+		self.strings_lock_mut(key, |cnt| -> ExecResult {
+			let mut out = VecDeque::with_capacity(operations.len());
+			for (field, offset, operation) in operations {
+				let current = field.decode(read_bits(cnt, offset, field.bits));
+				let (updated, reply) = match operation {
+					BitfieldOperation::Get => (None, Some(current)),
+					BitfieldOperation::Set(value, overflow) => match field.fit(value as i128, overflow) {
+						Some(value) => (Some(value), Some(current)),
+						None => (None, None),
+					},
+					BitfieldOperation::IncrBy(increment, overflow) => match field.fit(current as i128 + increment as i128, overflow) {
+						Some(value) => (Some(value), Some(value)),
+						None => (None, None),
+					},
+				};
+				if let Some(value) = updated {
+					let len = (offset + field.bits as u64).div_ceil(8) as usize;
+					if len > cnt.len() {
+						limits.check_value_size(len)?;
+						cnt.resize(len, 0);
+					}
+					write_bits(cnt, offset, field.bits, value as u64 & field.mask());
+				}
+				out.push_back(match reply {
+					Some(value) => Value::Integer(value),
+					None => Value::Nill,
+				});
+			}
+			Ok(Value::Array(out))
+		}).await
+	}
+
 	pub async fn strings_getbit(&self, mut args: Arguments) -> ExecResult {
 		let key = Self::extract_key(args.pop_front())?;
 		let offset = Self::extract_integer(args.pop_front())? as usize;
@@ -494,16 +776,10 @@ impl super::Storage {
 		let start = Self::extract_integer(args.pop_front())?;
 		let end = Self::extract_integer(args.pop_front())?;
 		self.strings_lock(key, |cnt| -> ExecResult {
-			let start =     if start >= 0 {start} else {cnt.len() as i64 + start} as usize;
-			let end   = 1 + if end   >= 0 {  end} else {cnt.len() as i64 +   end} as usize;
-			if start >= cnt.len() || start >= end {
-				return Ok(Value::Buffer(vec![]));
+			match byte_bounds(cnt, start, end) {
+				None => Ok(Value::Buffer(vec![])),
+				Some(range) => Ok(Value::Buffer(cnt[range].to_vec())),
 			}
-			let iter = cnt
-				.iter()
-				.skip(start)
-				.take(end - start);
-			Ok(Value::Buffer(Vec::from_iter(iter.cloned())))
 		}).await
 	}
 
@@ -513,8 +789,10 @@ impl super::Storage {
 		let value = Self::extract_buffer(args.pop_front())?;
 		let end = start + value.len();
 
+		let limits = self.limits();
 		self.strings_lock_mut(key, |cnt| -> ExecResult {
 			if cnt.len() < end {
+				limits.check_value_size(end)?;
 				cnt.resize(end, 0);
 			}
 			cnt[start..end].copy_from_slice(&value[..]);
@@ -523,3 +801,65 @@ impl super::Storage {
 	}
 }
 
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::time::Instant;
+
+	use super::*;
+
+	fn args(values: Vec<Value>) -> Arguments {
+		values.into_iter().collect()
+	}
+
+	fn key(name: &str) -> Value {
+		Value::Buffer(name.as_bytes().to_vec())
+	}
+
+	#[tokio::test(threaded_scheduler, core_threads = 8)]
+	async fn readers_of_one_key_run_in_parallel() {
+		const READERS: u32 = 8;
+		const DELAY: Duration = Duration::from_millis(100);
+		let storage = Arc::new(super::super::Storage::new());
+		storage.strings_set(args(vec![key("hot"), key("v")])).await.unwrap();
+
+		let started = Instant::now();
+		let readers = (0..READERS).map(|_| {
+			let storage = storage.clone();
+			tokio::spawn(async move {
+				storage.strings_lock(b"hot".to_vec(), |inner| {
+					std::thread::sleep(DELAY);
+					Ok(Value::Buffer(inner.clone()))
+				}).await
+			})
+		}).collect::<Vec<_>>();
+		for reader in readers {
+			assert_eq!(reader.await.unwrap(), Ok(key("v")));
+		}
+		let elapsed = started.elapsed();
+		assert!(elapsed < DELAY * READERS / 2, "{:?}", elapsed);
+	}
+
+	#[tokio::test]
+	async fn reads_share_the_container_lock() {
+		let storage = super::super::Storage::new();
+		storage.strings_set(args(vec![key("k"), key("v")])).await.unwrap();
+		let container = storage.try_get_live_container(&b"k".to_vec()).await.unwrap();
+		let guard = container.read().await;
+
+		let get = storage.strings_get(args(vec![key("k")]));
+		assert_eq!(tokio::time::timeout(Duration::from_secs(1), get).await, Ok(Ok(key("v"))));
+		let mget = storage.strings_mget(args(vec![key("k"), key("missing"), key("k")]));
+		let expected = Value::Array(vec![key("v"), Value::Nill, key("v")].into_iter().collect());
+		assert_eq!(tokio::time::timeout(Duration::from_secs(1), mget).await, Ok(Ok(expected)));
+		let len = storage.strings_len(args(vec![key("k")]));
+		assert_eq!(tokio::time::timeout(Duration::from_secs(1), len).await, Ok(Ok(Value::Integer(1))));
+
+		// a writer still waits for the readers
+		let append = storage.strings_append(args(vec![key("k"), key("w")]));
+		assert!(tokio::time::timeout(Duration::from_millis(50), append).await.is_err());
+		drop(guard);
+		assert_eq!(storage.strings_get(args(vec![key("k")])).await, Ok(key("v")));
+	}
+}
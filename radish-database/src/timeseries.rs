@@ -0,0 +1,318 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+
+//! Time series modeled on RedisTimeSeries: samples are appended in the order of timestamps,
+//! so a range is found by binary search. Samples older than the retention period relative
+//! to the latest one are trimmed on insert.
+
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use indexmap::map::Entry;
+
+use super::container::Container;
+use super::container::WRONG_TYPE_ERROR;
+use super::container::ContainerImpl;
+use super::options::SYNTAX_ERROR;
+
+type Key = super::Key;
+type Value = super::Value;
+type Arguments = super::Arguments;
+type ExecResult = super::ExecResult;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct TimeSeries {
+	/// Milliseconds, zero keeps the samples forever
+	pub retention: u64,
+	pub labels: Vec<(String, String)>,
+	/// Timestamp in milliseconds and the value, ordered by the timestamp
+	pub samples: Vec<(u64, f64)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Aggregation {
+	Avg,
+	Min,
+	Max,
+	Sum,
+}
+
+impl TimeSeries {
+	/// Only timestamps newer than the latest sample are accepted
+	pub fn add(&mut self, timestamp: u64, value: f64) -> Result<(), String> {
+		if let Some(&(latest, _)) = self.samples.last() {
+			if timestamp <= latest {
+				return Err(format!("ERR timestamp {} is not newer than the latest sample {}", timestamp, latest));
+			}
+		}
+		self.samples.push((timestamp, value));
+		if self.retention != 0 {
+			let oldest = timestamp.saturating_sub(self.retention);
+			let expired = self.samples.partition_point(|&(ts, _)|ts < oldest);
+			self.samples.drain(..expired);
+		}
+		Ok(())
+	}
+
+	/// Samples with timestamps in [from; to]
+	pub fn range(&self, from: u64, to: u64) -> &[(u64, f64)] {
+		let start = self.samples.partition_point(|&(ts, _)|ts < from);
+		let end = self.samples.partition_point(|&(ts, _)|ts <= to);
+		if start < end {&self.samples[start..end]} else {&[]}
+	}
+}
+
+impl Aggregation {
+	fn parse(name: &str) -> Result<Self, String> {
+		match &name.to_lowercase()[..] {
+			"avg" => Ok(Aggregation::Avg),
+			"min" => Ok(Aggregation::Min),
+			"max" => Ok(Aggregation::Max),
+			"sum" => Ok(Aggregation::Sum),
+			name => Err(format!("ERR unknown aggregation '{}'", name)),
+		}
+	}
+
+	fn apply(self, values: &[f64]) -> f64 {
+		match self {
+			Aggregation::Avg => values.iter().sum::<f64>() / values.len() as f64,
+			Aggregation::Min => values.iter().cloned().fold(f64::INFINITY, f64::min),
+			Aggregation::Max => values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+			Aggregation::Sum => values.iter().sum(),
+		}
+	}
+}
+
+/// Buckets start at multiples of `bucket`; empty buckets are omitted
+fn aggregate(samples: &[(u64, f64)], aggregation: Aggregation, bucket: u64) -> Vec<(u64, f64)> {
+	let mut out = Vec::new();
+	let mut values = Vec::new();
+	let mut current = None;
+	for &(ts, value) in samples {
+		let start = ts - ts % bucket;
+		if current != Some(start) {
+			if let Some(current) = current {
+				out.push((current, aggregation.apply(&values)));
+			}
+			values.clear();
+			current = Some(start);
+		}
+		values.push(value);
+	}
+	if let Some(current) = current {
+		out.push((current, aggregation.apply(&values)));
+	}
+	out
+}
+
+fn sample_to_value(&(ts, value): &(u64, f64)) -> Value {
+	Value::Array(vec![Value::Integer(ts as i64), Value::Float(value.to_bits())].into_iter().collect())
+}
+
+fn now_millis() -> u64 {
+	SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+fn extract_sample_value(arg: Option<Value>) -> Result<f64, String> {
+	let value = match super::Storage::extract(arg)? {
+		Value::Integer(i) => i as f64,
+		Value::Float(n) => f64::from_bits(n),
+		Value::Buffer(b) => String::from_utf8_lossy(&b).parse::<f64>().map_err(|_|"ERR invalid value".to_owned())?,
+		_ => return Err("ERR invalid value".to_owned()),
+	};
+	if !value.is_finite() {
+		return Err("ERR invalid value".to_owned());
+	}
+	Ok(value)
+}
+
+/// Milliseconds; `-` and `+` are the minimal and the maximal timestamps, `*` is the current time
+fn extract_timestamp(arg: Option<Value>) -> Result<u64, String> {
+	match super::Storage::extract(arg)? {
+		Value::Integer(i) if i >= 0 => Ok(i as u64),
+		Value::Buffer(b) if b == b"-" => Ok(0),
+		Value::Buffer(b) if b == b"+" => Ok(u64::MAX),
+		Value::Buffer(b) if b == b"*" => Ok(now_millis()),
+		_ => Err("ERR invalid timestamp".to_owned()),
+	}
+}
+
+/// [RETENTION ms] [LABELS label value ...]; LABELS takes the rest of the arguments
+fn parse_create_options(mut args: Arguments) -> Result<TimeSeries, String> {
+	let mut series = TimeSeries::default();
+	while let Some(arg) = args.pop_front() {
+		match &super::Storage::extract_string(Some(arg))?.to_uppercase()[..] {
+			"RETENTION" => series.retention = super::Storage::extract_unsigned_integer(args.pop_front())?,
+			"LABELS" => {
+				if args.is_empty() || !args.len().is_multiple_of(2) {
+					return Err(SYNTAX_ERROR.to_owned());
+				}
+				while let Some(name) = args.pop_front() {
+					let name = super::Storage::extract_string(Some(name))?;
+					let value = super::Storage::extract_string(args.pop_front())?;
+					series.labels.retain(|(n, _)|n != &name);
+					series.labels.push((name, value));
+				}
+			},
+			_ => return Err(SYNTAX_ERROR.to_owned()),
+		}
+	}
+	Ok(series)
+}
+
+impl super::Storage {
+	fn timeseries_unwrap_container(container: &Container) -> Result<&ContainerImpl<TimeSeries>, String> {
+		match container {
+			Container::TimeSeries(ref c) => Ok(c),
+			_ => Err(WRONG_TYPE_ERROR.to_owned()),
+		}
+	}
+	fn timeseries_unwrap_mut_container(container: &mut Container) -> Result<&mut ContainerImpl<TimeSeries>, String> {
+		match container {
+			Container::TimeSeries(ref mut c) => Ok(c),
+			_ => Err(WRONG_TYPE_ERROR.to_owned()),
+		}
+	}
+	/// Missing key is given to the processor as None without creating it
+	async fn timeseries_lock<F: FnOnce(Option<&TimeSeries>) -> ExecResult>(&self, key: &Key, processor: F) -> ExecResult {
+		match self.try_get_live_container(key).await {
+			None => processor(None),
+			Some(c1) => {
+				let c2 = c1.read().await;
+				self.access_touch(&c2);
+				let c3 = Self::timeseries_unwrap_container(&c2)?;
+				processor(Some(&c3.inner))
+			},
+		}
+	}
+	/// Missing key is created without retention and labels
+	async fn timeseries_lock_mut<F: FnOnce(&mut TimeSeries) -> ExecResult>(&self, key: Key, processor: F) -> ExecResult {
+		let c1 = self.get_live_container(key, ||Container::TimeSeries(ContainerImpl::<TimeSeries>::new())).await;
+		let mut c2 = c1.write().await;
+		self.snapshot_preserve(&c1, &c2);
+		self.access_touch(&c2);
+		let c3 = Self::timeseries_unwrap_mut_container(&mut c2)?;
+		let result = processor(&mut c3.inner);
+		self.memory_track(&mut c2);
+		result
+	}
+
+	/// TS.CREATE key [RETENTION ms] [LABELS label value ...]
+	pub async fn timeseries_create(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let mut cnt = ContainerImpl::<TimeSeries>::new();
+		cnt.inner = parse_create_options(args)?;
+		let mut cnt = Container::TimeSeries(cnt);
+
+		let mut containers = self.containers.lock().await;
+		self.reap_expired(&mut containers, &key).await;
+		match containers.entry(key[..].into()) {
+			Entry::Occupied(_) => Err("ERR key already exists".to_owned()),
+			Entry::Vacant(e) => {
+				self.memory_track_insert(&key, &mut cnt);
+				self.access_touch(&cnt);
+				e.insert(Self::make_container(cnt));
+				Ok(Value::Ok)
+			},
+		}
+	}
+
+	/// TS.ADD key timestamp|* value; returns the timestamp of the sample
+	pub async fn timeseries_add(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let timestamp = extract_timestamp(args.pop_front())?;
+		let value = extract_sample_value(args.pop_front())?;
+		if timestamp > i64::MAX as u64 {
+			return Err("ERR invalid timestamp".to_owned());
+		}
+		let limits = self.limits();
+		self.timeseries_lock_mut(key, |series| -> ExecResult {
+			limits.check_collection_len(series.samples.len() + 1)?;
+			series.add(timestamp, value)?;
+			Ok(Value::Integer(timestamp as i64))
+		}).await
+	}
+
+	/// TS.RANGE key from to [AGGREGATION avg|min|max|sum bucket_ms]
+	pub async fn timeseries_range(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		let from = extract_timestamp(args.pop_front())?;
+		let to = extract_timestamp(args.pop_front())?;
+		let aggregation = match args.pop_front() {
+			None => None,
+			Some(arg) if Self::extract_string(Some(arg.clone()))?.eq_ignore_ascii_case("AGGREGATION") => {
+				let aggregation = Aggregation::parse(&Self::extract_string(args.pop_front())?)?;
+				let bucket = Self::extract_unsigned_integer(args.pop_front())?;
+				if bucket == 0 {
+					return Err("ERR bucket duration must be positive".to_owned());
+				}
+				Some((aggregation, bucket))
+			},
+			Some(_) => return Err(SYNTAX_ERROR.to_owned()),
+		};
+		if !args.is_empty() {
+			return Err(SYNTAX_ERROR.to_owned());
+		}
+		self.timeseries_lock(&key, |series| -> ExecResult {
+			let samples = match series {
+				None => return Ok(Value::Array(VecDeque::new())),
+				Some(series) => series.range(from, to),
+			};
+			let out = match aggregation {
+				None => samples.iter().map(sample_to_value).collect(),
+				Some((aggregation, bucket)) => aggregate(samples, aggregation, bucket).iter().map(sample_to_value).collect(),
+			};
+			Ok(Value::Array(out))
+		}).await
+	}
+
+	/// TS.GET key: the latest sample, an empty array if there are no samples
+	pub async fn timeseries_get(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		self.timeseries_lock(&key, |series| -> ExecResult {
+			match series {
+				None => Ok(Value::Nill),
+				Some(series) => match series.samples.last() {
+					None => Ok(Value::Array(VecDeque::new())),
+					Some(sample) => Ok(sample_to_value(sample)),
+				},
+			}
+		}).await
+	}
+
+	/// TS.INFO key: pairs of the property name and value
+	pub async fn timeseries_info(&self, mut args: Arguments) -> ExecResult {
+		let key = Self::extract_key(args.pop_front())?;
+		self.timeseries_lock(&key, |series| -> ExecResult {
+			let series = series.ok_or("ERR key does not exist")?;
+			let timestamp = |sample: Option<&(u64, f64)>|Value::Integer(sample.map_or(0, |&(ts, _)|ts as i64));
+			let labels = series.labels.iter()
+				.map(|(name, value)|Value::Array(vec![Value::Buffer(name.as_bytes().to_vec()), Value::Buffer(value.as_bytes().to_vec())].into_iter().collect()))
+				.collect();
+			let info = vec![
+				("totalSamples", Value::Integer(series.samples.len() as i64)),
+				("retentionTime", Value::Integer(series.retention as i64)),
+				("firstTimestamp", timestamp(series.samples.first())),
+				("lastTimestamp", timestamp(series.samples.last())),
+				("labels", Value::Array(labels)),
+			];
+			Ok(Value::Array(info.into_iter().flat_map(|(name, value)|vec![Value::Buffer(name.as_bytes().to_vec()), value]).collect()))
+		}).await
+	}
+}
@@ -0,0 +1,73 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use radish_database::{Storage, MockClock, Value};
+
+use common::*;
+
+fn storage() -> (Storage, Arc<MockClock>) {
+	let clock = Arc::new(MockClock::new());
+	(Storage::with_clock(clock.clone()), clock)
+}
+
+#[tokio::test]
+async fn idle_time_follows_the_clock() {
+	let (storage, clock) = storage();
+	assert_eq!(storage.idle_time(b"a").await, None);
+	ok(&storage, "SET", vec![buf("a"), buf("v")]).await;
+	ok(&storage, "RPUSH", vec![buf("list"), buf("v")]).await;
+	assert_eq!(storage.idle_time(b"a").await, Some(Duration::from_secs(0)));
+
+	clock.advance(&storage, Duration::from_secs(100)).await;
+	assert_eq!(storage.idle_time(b"a").await, Some(Duration::from_secs(100)));
+	assert_eq!(ok(&storage, "OBJECT", vec![buf("IDLETIME"), buf("list")]).await, int(100));
+
+	// a read and a write both touch the key
+	ok(&storage, "GET", vec![buf("a")]).await;
+	ok(&storage, "RPUSH", vec![buf("list"), buf("w")]).await;
+	assert_eq!(storage.idle_time(b"a").await, Some(Duration::from_secs(0)));
+	assert_eq!(storage.idle_time(b"list").await, Some(Duration::from_secs(0)));
+
+	clock.advance(&storage, Duration::from_millis(2500)).await;
+	assert_eq!(storage.idle_time(b"a").await, Some(Duration::from_secs(2)));
+	assert_eq!(run(&storage, "OBJECT", vec![buf("IDLETIME"), buf("missing")]).await, Value::Nill);
+}
+
+#[tokio::test]
+async fn hot_key_is_more_frequent_than_a_cold_one() {
+	let (storage, clock) = storage();
+	ok(&storage, "SET", vec![buf("hot"), buf("v")]).await;
+	ok(&storage, "SET", vec![buf("cold"), buf("v")]).await;
+	let cold = storage.access_frequency(b"cold").await.unwrap();
+
+	for _ in 0..10_000 {
+		ok(&storage, "GET", vec![buf("hot")]).await;
+	}
+	let hot = storage.access_frequency(b"hot").await.unwrap();
+	assert!(hot > cold + 3, "hot {} vs cold {}", hot, cold);
+	assert_eq!(storage.access_frequency(b"cold").await, Some(cold));
+	assert_eq!(ok(&storage, "OBJECT", vec![buf("FREQ"), buf("hot")]).await, int(hot as i64));
+
+	// the counter decays by one per idle minute
+	clock.advance(&storage, Duration::from_secs(3 * 60)).await;
+	assert_eq!(storage.access_frequency(b"hot").await, Some(hot - 3));
+	assert_eq!(storage.access_frequency(b"missing").await, None);
+}
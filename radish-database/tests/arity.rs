@@ -0,0 +1,80 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+/// Names and arities of every registered command from COMMAND
+async fn registry(storage: &Storage) -> Vec<(String, i64)> {
+	let all = match ok(storage, "COMMAND", vec![]).await {
+		Value::Array(all) => all,
+		all => panic!("unexpected COMMAND reply {:?}", all),
+	};
+	all.into_iter().map(|spec| match spec {
+		Value::Array(spec) => match (&spec[0], &spec[1]) {
+			(Value::Buffer(name), Value::Integer(arity)) => (String::from_utf8(name.clone()).unwrap(), *arity),
+			_ => panic!("unexpected spec {:?}", spec),
+		},
+		spec => panic!("unexpected spec {:?}", spec),
+	}).collect()
+}
+
+#[tokio::test]
+async fn every_command_without_arguments() {
+	let storage = Storage::new();
+	let commands = registry(&storage).await;
+	assert!(commands.len() > 100, "{} commands", commands.len());
+
+	let mut checked = 0;
+	for (name, arity) in commands {
+		// a command which takes no arguments must not be run here: FLUSHALL, SAVE and the like
+		if arity == 1 || arity == -1 {
+			continue;
+		}
+		let error = err(&storage, &name.to_uppercase(), vec![]).await;
+		assert_eq!(error, format!("ERR wrong number of arguments for '{}' command", name));
+		checked += 1;
+	}
+	assert!(checked > 100, "{} commands checked", checked);
+}
+
+#[tokio::test]
+async fn extra_arguments() {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("key"), buf("v")]).await;
+	assert_eq!(err(&storage, "GET", vec![buf("key"), buf("junk")]).await, "ERR wrong number of arguments for 'get' command");
+	assert_eq!(err(&storage, "EXPIRE", vec![buf("key"), int(1), buf("junk")]).await, "ERR wrong number of arguments for 'expire' command");
+	assert_eq!(err(&storage, "RPOPLPUSH", vec![buf("a"), buf("b"), buf("c")]).await, "ERR wrong number of arguments for 'rpoplpush' command");
+	assert_eq!(err(&storage, "KEYS", vec![]).await, "ERR wrong number of arguments for 'keys' command");
+	// the key is untouched by the rejected calls
+	assert_eq!(ok(&storage, "TTL", vec![buf("key")]).await, int(-1));
+}
+
+#[tokio::test]
+async fn variadic_commands() {
+	let storage = Storage::new();
+	assert_eq!(err(&storage, "DEL", vec![]).await, "ERR wrong number of arguments for 'del' command");
+	assert_eq!(ok(&storage, "DEL", vec![buf("a")]).await, int(0));
+	assert_eq!(ok(&storage, "DEL", vec![buf("a"), buf("b"), buf("c")]).await, int(0));
+
+	assert_eq!(err(&storage, "HSET", vec![buf("h"), buf("f")]).await, "ERR wrong number of arguments for 'hset' command");
+	assert_eq!(err(&storage, "HSET", vec![buf("h"), buf("f"), buf("v"), buf("g")]).await, "ERR wrong number of arguments for 'hset' command");
+	assert_eq!(err(&storage, "MSET", vec![buf("a"), buf("1"), buf("b")]).await, "ERR wrong number of arguments for 'mset' command");
+	assert_eq!(ok(&storage, "PING", vec![]).await, buf("PONG"));
+}
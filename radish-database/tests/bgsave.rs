@@ -0,0 +1,116 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+mod common;
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use radish_database::{Storage, Value, SnapshotData, decode_snapshot};
+
+use common::*;
+
+const KEYS: usize = 20_000;
+
+fn value(generation: &str) -> Value {
+	buf(&format!("{}{}", generation, "x".repeat(1_000)))
+}
+
+async fn persistence(storage: &Storage, field: &str) -> String {
+	let info = match ok(storage, "INFO", vec![buf("persistence")]).await {
+		Value::Buffer(info) => String::from_utf8(info).unwrap(),
+		info => panic!("unexpected INFO reply {:?}", info),
+	};
+	let prefix = format!("{}:", field);
+	info.lines()
+		.find_map(|line|line.strip_prefix(&prefix).map(str::to_owned))
+		.unwrap_or_else(||panic!("no {} in {}", field, info))
+}
+
+/// A single writer changes the keys in order while BGSAVE runs, so a point-in-time view
+/// holds the changed value for a prefix of the keys and the old one for the rest.
+/// Odd keys are appended to in place, which makes the writer copy them for the saver.
+#[tokio::test(threaded_scheduler)]
+async fn writes_proceed_while_saving_a_consistent_view() {
+	let dir = std::env::temp_dir();
+	let name = format!("radish-bgsave-{}.snapshot", std::process::id());
+	let path = dir.join(&name);
+
+	let storage = Storage::new();
+	ok(&storage, "CONFIG", vec![buf("SET"), buf("dir"), buf(dir.to_str().unwrap())]).await;
+	ok(&storage, "CONFIG", vec![buf("SET"), buf("dbfilename"), buf(&name)]).await;
+	for i in 0..KEYS {
+		ok(&storage, "SET", vec![buf(&format!("k{}", i)), value("old")]).await;
+	}
+
+	let writer = {
+		let storage = storage.clone();
+		tokio::spawn(async move {
+			let mut latencies = Vec::with_capacity(KEYS);
+			let mut while_saving = 0;
+			for i in 0..KEYS {
+				let key = buf(&format!("k{}", i));
+				let started = Instant::now();
+				match i % 2 {
+					0 => ok(&storage, "SET", vec![key, value("new")]).await,
+					_ => ok(&storage, "APPEND", vec![key, buf("!")]).await,
+				};
+				latencies.push(started.elapsed());
+				if persistence(&storage, "bgsave_in_progress").await == "1" {
+					while_saving += 1;
+				}
+			}
+			(latencies, while_saving)
+		})
+	};
+	// save once the writer is under way, so the capture overlaps the writes
+	while ok(&storage, "GET", vec![buf(&format!("k{}", KEYS / 10))]).await == value("old") {
+		tokio::time::delay_for(Duration::from_millis(1)).await;
+	}
+	assert_eq!(ok(&storage, "BGSAVE", vec![]).await, buf("Background saving started"));
+	let (mut latencies, while_saving) = writer.await.unwrap();
+	assert!(while_saving > 0);
+	while persistence(&storage, "bgsave_in_progress").await != "0" {
+		tokio::time::delay_for(Duration::from_millis(10)).await;
+	}
+	assert_eq!(persistence(&storage, "last_bgsave_status").await, "ok");
+
+	latencies.sort();
+	let p99 = latencies[latencies.len() * 99 / 100];
+	assert!(p99 < Duration::from_millis(50), "p99 write latency {:?}", p99);
+
+	let data = std::fs::read(&path).unwrap();
+	let _ = std::fs::remove_file(&path);
+	let (_, snapshot) = decode_snapshot(&data, true).unwrap();
+	assert_eq!(snapshot.entries.len(), KEYS);
+
+	let saved: HashMap<Vec<u8>, Vec<u8>> = snapshot.entries
+		.into_iter()
+		.map(|entry|match entry.data {
+			SnapshotData::String(data) => (entry.key, data),
+			data => panic!("unexpected {:?}", data),
+		})
+		.collect()
+	;
+	let generations: Vec<bool> = (0..KEYS)
+		.map(|i|Value::Buffer(saved[format!("k{}", i).as_bytes()].clone()) != value("old"))
+		.collect()
+	;
+	let rewritten = generations.iter().take_while(|new|**new).count();
+	assert!(rewritten > KEYS / 10, "the save started before the writer, {} keys rewritten", rewritten);
+	assert!(generations[rewritten..].iter().all(|new|!new), "not a point-in-time view: {} keys rewritten before an old one", rewritten);
+}
@@ -0,0 +1,166 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+const NIL: Value = Value::Nill;
+
+async fn bitfield(storage: &Storage, subcommands: Vec<Value>) -> Value {
+	let mut arguments = vec![buf("bf")];
+	arguments.extend(subcommands);
+	ok(storage, "BITFIELD", arguments).await
+}
+
+fn ints(values: &[i64]) -> Value {
+	array(values.iter().map(|value|int(*value)).collect())
+}
+
+#[tokio::test]
+async fn signed_wraparound() {
+	let storage = Storage::new();
+	assert_eq!(bitfield(&storage, vec![buf("SET"), buf("i8"), int(0), int(127)]).await, ints(&[0]));
+	assert_eq!(bitfield(&storage, vec![buf("INCRBY"), buf("i8"), int(0), int(1)]).await, ints(&[-128]));
+	assert_eq!(bitfield(&storage, vec![buf("INCRBY"), buf("i8"), int(0), int(-1)]).await, ints(&[127]));
+	assert_eq!(bitfield(&storage, vec![buf("INCRBY"), buf("i8"), int(0), int(256 + 3)]).await, ints(&[-126]));
+	// a value out of the range is wrapped by SET too
+	assert_eq!(bitfield(&storage, vec![buf("SET"), buf("i8"), int(0), int(200), buf("GET"), buf("i8"), int(0)]).await, ints(&[-126, -56]));
+
+	assert_eq!(bitfield(&storage, vec![buf("SET"), buf("i64"), int(8), int(i64::MAX)]).await, ints(&[0]));
+	assert_eq!(bitfield(&storage, vec![buf("INCRBY"), buf("i64"), int(8), int(1)]).await, ints(&[i64::MIN]));
+	assert_eq!(bitfield(&storage, vec![buf("INCRBY"), buf("i64"), int(8), int(i64::MIN)]).await, ints(&[0]));
+
+	assert_eq!(bitfield(&storage, vec![buf("SET"), buf("i1"), int(100), int(0), buf("INCRBY"), buf("i1"), int(100), int(-1), buf("INCRBY"), buf("i1"), int(100), int(-1)]).await, ints(&[0, -1, 0]));
+}
+
+#[tokio::test]
+async fn unsigned_wraparound() {
+	let storage = Storage::new();
+	assert_eq!(bitfield(&storage, vec![buf("INCRBY"), buf("u8"), int(0), int(255)]).await, ints(&[255]));
+	assert_eq!(bitfield(&storage, vec![buf("INCRBY"), buf("u8"), int(0), int(1)]).await, ints(&[0]));
+	assert_eq!(bitfield(&storage, vec![buf("INCRBY"), buf("u8"), int(0), int(-1)]).await, ints(&[255]));
+
+	let max = i64::MAX;
+	assert_eq!(bitfield(&storage, vec![buf("INCRBY"), buf("u63"), int(8), int(max)]).await, ints(&[max]));
+	assert_eq!(bitfield(&storage, vec![buf("INCRBY"), buf("u63"), int(8), int(1)]).await, ints(&[0]));
+}
+
+#[tokio::test]
+async fn signed_saturation() {
+	let storage = Storage::new();
+	let sat = |subcommand: &str, value: i64|vec![buf("OVERFLOW"), buf("SAT"), buf(subcommand), buf("i8"), int(0), int(value)];
+	assert_eq!(bitfield(&storage, sat("INCRBY", 1000)).await, ints(&[127]));
+	assert_eq!(bitfield(&storage, sat("INCRBY", 1)).await, ints(&[127]));
+	assert_eq!(bitfield(&storage, sat("INCRBY", -1000)).await, ints(&[-128]));
+	assert_eq!(bitfield(&storage, sat("INCRBY", -1)).await, ints(&[-128]));
+	assert_eq!(bitfield(&storage, sat("SET", 500)).await, ints(&[-128]));
+	assert_eq!(bitfield(&storage, sat("SET", -500)).await, ints(&[127]));
+	assert_eq!(bitfield(&storage, vec![buf("GET"), buf("i8"), int(0)]).await, ints(&[-128]));
+
+	let sat64 = |value: i64|vec![buf("OVERFLOW"), buf("SAT"), buf("INCRBY"), buf("i64"), int(8), int(value)];
+	assert_eq!(bitfield(&storage, sat64(i64::MAX)).await, ints(&[i64::MAX]));
+	assert_eq!(bitfield(&storage, sat64(i64::MAX)).await, ints(&[i64::MAX]));
+	assert_eq!(bitfield(&storage, sat64(i64::MIN)).await, ints(&[-1]));
+	assert_eq!(bitfield(&storage, sat64(i64::MIN)).await, ints(&[i64::MIN]));
+	assert_eq!(bitfield(&storage, sat64(-1)).await, ints(&[i64::MIN]));
+}
+
+#[tokio::test]
+async fn unsigned_saturation() {
+	let storage = Storage::new();
+	let sat = |field: &str, value: i64|vec![buf("OVERFLOW"), buf("SAT"), buf("INCRBY"), buf(field), int(0), int(value)];
+	assert_eq!(bitfield(&storage, sat("u8", 300)).await, ints(&[255]));
+	assert_eq!(bitfield(&storage, sat("u8", -300)).await, ints(&[0]));
+	assert_eq!(bitfield(&storage, sat("u63", i64::MAX)).await, ints(&[i64::MAX]));
+	assert_eq!(bitfield(&storage, sat("u63", 1)).await, ints(&[i64::MAX]));
+	assert_eq!(bitfield(&storage, sat("u1", 5)).await, ints(&[1]));
+}
+
+#[tokio::test]
+async fn failed_overflow_changes_nothing() {
+	let storage = Storage::new();
+	let reply = bitfield(&storage, vec![
+		buf("SET"), buf("u8"), int(0), int(250),
+		buf("OVERFLOW"), buf("FAIL"),
+		buf("INCRBY"), buf("u8"), int(0), int(10),
+		buf("SET"), buf("i8"), int(8), int(128),
+		buf("INCRBY"), buf("u8"), int(0), int(5),
+		buf("GET"), buf("u8"), int(0),
+	]).await;
+	assert_eq!(reply, array(vec![int(0), NIL, NIL, int(255), int(255)]));
+	// the failed SET did not grow the string
+	assert_eq!(ok(&storage, "GET", vec![buf("bf")]).await, Value::Buffer(vec![255]));
+}
+
+#[tokio::test]
+async fn overflow_applies_to_the_following_subcommands() {
+	let storage = Storage::new();
+	let reply = bitfield(&storage, vec![
+		buf("INCRBY"), buf("u4"), int(0), int(20),
+		buf("OVERFLOW"), buf("SAT"),
+		buf("INCRBY"), buf("u4"), int(4), int(20),
+		buf("OVERFLOW"), buf("wrap"),
+		buf("INCRBY"), buf("u4"), int(8), int(20),
+	]).await;
+	assert_eq!(reply, ints(&[4, 15, 4]));
+}
+
+#[tokio::test]
+async fn fields_span_byte_boundaries() {
+	let storage = Storage::new();
+	assert_eq!(bitfield(&storage, vec![buf("SET"), buf("u12"), int(6), int(0xabc)]).await, ints(&[0]));
+	// 000000|10 1010|1111 00|000000
+	assert_eq!(ok(&storage, "GET", vec![buf("bf")]).await, Value::Buffer(vec![0b0000_0010, 0b1010_1111, 0b0000_0000]));
+	assert_eq!(bitfield(&storage, vec![buf("GET"), buf("u12"), int(6), buf("GET"), buf("i12"), int(6), buf("GET"), buf("u4"), int(10)]).await, ints(&[0xabc, 0xabc - 0x1000, 0xb]));
+
+	ok(&storage, "SET", vec![buf("bf"), Value::Buffer(vec![0xff; 3])]).await;
+	assert_eq!(bitfield(&storage, vec![buf("SET"), buf("u16"), int(5), int(0)]).await, ints(&[0xffff]));
+	assert_eq!(ok(&storage, "GET", vec![buf("bf")]).await, Value::Buffer(vec![0b1111_1000, 0, 0b0000_0111]));
+}
+
+#[tokio::test]
+async fn offsets_in_field_widths() {
+	let storage = Storage::new();
+	assert_eq!(bitfield(&storage, vec![buf("SET"), buf("u8"), buf("#2"), int(7), buf("GET"), buf("u8"), int(16), buf("GET"), buf("u8"), buf("16")]).await, ints(&[0, 7, 7]));
+	assert_eq!(ok(&storage, "GET", vec![buf("bf")]).await, Value::Buffer(vec![0, 0, 7]));
+	// reads past the end are zeros and don't grow the string
+	assert_eq!(bitfield(&storage, vec![buf("GET"), buf("i64"), buf("#100")]).await, ints(&[0]));
+	assert_eq!(ok(&storage, "STRLEN", vec![buf("bf")]).await, int(3));
+}
+
+#[tokio::test]
+async fn invalid_subcommands_change_nothing() {
+	let storage = Storage::new();
+	let invalid = [
+		(vec![buf("GET"), buf("u64"), int(0)], "ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is."),
+		(vec![buf("GET"), buf("i65"), int(0)], "ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is."),
+		(vec![buf("GET"), buf("i0"), int(0)], "ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is."),
+		(vec![buf("GET"), buf("u8"), int(-1)], "ERR bit offset is not an integer or out of range"),
+		(vec![buf("GET"), buf("u8"), buf("#x")], "ERR bit offset is not an integer or out of range"),
+		(vec![buf("GET"), buf("u8"), int(1 << 32)], "ERR bit offset is not an integer or out of range"),
+		(vec![buf("OVERFLOW"), buf("MAYBE")], "ERR Invalid OVERFLOW type specified"),
+		(vec![buf("DECRBY"), buf("u8"), int(0), int(1)], "ERR syntax error"),
+	];
+	for (subcommands, error) in invalid.iter().cloned() {
+		let mut arguments = vec![buf("bf"), buf("SET"), buf("u8"), int(0), int(1)];
+		arguments.extend(subcommands);
+		assert_eq!(err(&storage, "BITFIELD", arguments).await, error);
+		assert_eq!(ok(&storage, "EXISTS", vec![buf("bf")]).await, int(0));
+	}
+}
@@ -0,0 +1,157 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+const ITEMS: usize = 10_000;
+
+fn items(prefix: &str) -> Vec<Value> {
+	(0..ITEMS).map(|i|buf(&format!("{}:{}", prefix, i))).collect()
+}
+
+async fn madd(storage: &Storage, key: &str, items: &[Value]) -> Vec<Value> {
+	let mut replies = Vec::with_capacity(items.len());
+	for chunk in items.chunks(1000) {
+		let mut args = vec![buf(key)];
+		args.extend(chunk.iter().cloned());
+		match ok(storage, "BF.MADD", args).await {
+			Value::Array(out) => replies.extend(out),
+			reply => panic!("unexpected BF.MADD reply {:?}", reply),
+		}
+	}
+	replies
+}
+
+async fn positives(storage: &Storage, key: &str, items: &[Value]) -> usize {
+	let mut args = vec![buf(key)];
+	args.extend(items.iter().cloned());
+	match ok(storage, "BF.MEXISTS", args).await {
+		Value::Array(out) => out.into_iter().filter(|reply|*reply == Value::Bool(true)).count(),
+		reply => panic!("unexpected BF.MEXISTS reply {:?}", reply),
+	}
+}
+
+async fn info(storage: &Storage, key: &str, field: &str) -> Value {
+	match ok(storage, "BF.INFO", vec![buf(key)]).await {
+		Value::Array(out) => {
+			let out = Vec::from(out);
+			out.chunks(2)
+				.find(|pair|pair[0] == buf(field))
+				.map(|pair|pair[1].clone())
+				.unwrap_or_else(||panic!("no {} in BF.INFO", field))
+		},
+		reply => panic!("unexpected BF.INFO reply {:?}", reply),
+	}
+}
+
+#[tokio::test]
+async fn no_false_negatives_and_bounded_false_positives() {
+	let storage = Storage::new();
+	assert_eq!(ok(&storage, "BF.RESERVE", vec![buf("bf"), float(0.01), int(ITEMS as i64)]).await, Value::Ok);
+	let inserted = items("member");
+	let replies = madd(&storage, "bf", &inserted).await;
+	let added = replies.iter().filter(|reply|**reply == Value::Bool(true)).count();
+	// a few items may collide with earlier ones, but nearly all of them are new
+	assert!(added > ITEMS * 98 / 100);
+
+	assert_eq!(positives(&storage, "bf", &inserted).await, ITEMS);
+	for item in inserted.iter().step_by(97) {
+		assert_eq!(ok(&storage, "BF.EXISTS", vec![buf("bf"), item.clone()]).await, Value::Bool(true));
+	}
+
+	let false_positives = positives(&storage, "bf", &items("held-out")).await;
+	let rate = false_positives as f64 / ITEMS as f64;
+	assert!(rate <= 0.02, "false positive rate {} is above the bound", rate);
+	assert_eq!(info(&storage, "bf", "Number of filters").await, int(1));
+	assert_eq!(info(&storage, "bf", "Number of items inserted").await, int(added as i64));
+}
+
+#[tokio::test]
+async fn scaling_keeps_members_and_the_error_bound() {
+	let storage = Storage::new();
+	ok(&storage, "BF.RESERVE", vec![buf("bf"), float(0.01), int(1000)]).await;
+	let inserted = items("member");
+	madd(&storage, "bf", &inserted).await;
+
+	assert_eq!(positives(&storage, "bf", &inserted).await, ITEMS);
+	// the layers tighten their error rates, so the compound rate stays within twice the target
+	let rate = positives(&storage, "bf", &items("held-out")).await as f64 / ITEMS as f64;
+	assert!(rate <= 0.02, "false positive rate {} is above the bound", rate);
+
+	match info(&storage, "bf", "Number of filters").await {
+		Value::Integer(layers) => assert!(layers >= 4, "{} layers for {} items", layers, ITEMS),
+		reply => panic!("unexpected number of filters {:?}", reply),
+	}
+	match info(&storage, "bf", "Capacity").await {
+		Value::Integer(capacity) => assert!(capacity >= ITEMS as i64),
+		reply => panic!("unexpected capacity {:?}", reply),
+	}
+}
+
+#[tokio::test]
+async fn nonscaling_filter_rejects_items_when_full() {
+	let storage = Storage::new();
+	ok(&storage, "BF.RESERVE", vec![buf("bf"), float(0.001), int(10), buf("NONSCALING")]).await;
+	let inserted: Vec<Value> = (0..10).map(int).collect();
+	assert!(madd(&storage, "bf", &inserted).await.iter().all(|reply|*reply == Value::Bool(true)));
+	assert_eq!(err(&storage, "BF.ADD", vec![buf("bf"), buf("extra")]).await, "ERR non scaling filter is full");
+	// items and their integer form are the same bytes
+	assert_eq!(ok(&storage, "BF.EXISTS", vec![buf("bf"), buf("3")]).await, Value::Bool(true));
+	assert_eq!(ok(&storage, "BF.ADD", vec![buf("bf"), buf("3")]).await, Value::Bool(false));
+	assert_eq!(info(&storage, "bf", "Expansion rate").await, int(0));
+}
+
+#[tokio::test]
+async fn reserve_validation_and_defaults() {
+	let storage = Storage::new();
+	assert_eq!(err(&storage, "BF.RESERVE", vec![buf("bf"), float(1.0), int(10)]).await, "ERR error rate should be between 0 and 1");
+	assert_eq!(err(&storage, "BF.RESERVE", vec![buf("bf"), float(0.0), int(10)]).await, "ERR error rate should be between 0 and 1");
+	assert_eq!(err(&storage, "BF.RESERVE", vec![buf("bf"), float(0.1), int(0)]).await, "ERR capacity should be positive");
+	assert_eq!(err(&storage, "BF.RESERVE", vec![buf("bf"), float(0.1), int(10), buf("EXPANSION"), int(0)]).await, "ERR expansion should be positive");
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("bf")]).await, int(0));
+
+	assert_eq!(ok(&storage, "BF.EXISTS", vec![buf("bf"), buf("a")]).await, Value::Bool(false));
+	assert_eq!(err(&storage, "BF.INFO", vec![buf("bf")]).await, "ERR not found");
+	assert_eq!(ok(&storage, "BF.ADD", vec![buf("bf"), buf("a")]).await, Value::Bool(true));
+	assert_eq!(info(&storage, "bf", "Capacity").await, int(100));
+	assert_eq!(info(&storage, "bf", "Expansion rate").await, int(2));
+	assert_eq!(err(&storage, "BF.RESERVE", vec![buf("bf"), float(0.1), int(10)]).await, "ERR item exists");
+	assert_eq!(ok(&storage, "TYPE", vec![buf("bf")]).await, buf("MBbloom--"));
+
+	ok(&storage, "SET", vec![buf("s"), buf("v")]).await;
+	assert!(err(&storage, "BF.ADD", vec![buf("s"), buf("a")]).await.starts_with("WRONGTYPE"));
+	assert!(err(&storage, "BF.EXISTS", vec![buf("s"), buf("a")]).await.starts_with("WRONGTYPE"));
+}
+
+#[tokio::test]
+async fn dump_keeps_the_bits() {
+	let storage = Storage::new();
+	ok(&storage, "BF.RESERVE", vec![buf("bf"), float(0.01), int(100)]).await;
+	let inserted: Vec<Value> = (0..300).map(|i|buf(&format!("item:{}", i))).collect();
+	madd(&storage, "bf", &inserted).await;
+	let payload = ok(&storage, "DUMP", vec![buf("bf")]).await;
+
+	let target = Storage::new();
+	assert_eq!(ok(&target, "RESTORE", vec![buf("bf"), int(0), payload]).await, Value::Ok);
+	assert_eq!(positives(&target, "bf", &inserted).await, inserted.len());
+	for field in &["Capacity", "Size", "Number of filters", "Number of items inserted", "Fill ratio"] {
+		assert_eq!(info(&target, "bf", field).await, info(&storage, "bf", field).await);
+	}
+}
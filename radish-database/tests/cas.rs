@@ -0,0 +1,140 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! SET ... IFEQ / IFGT compare-and-set
+
+mod common;
+
+use std::sync::Arc;
+
+use radish_database::{Storage, MockClock, Value};
+
+use common::*;
+
+async fn get(storage: &Storage, key: &str) -> Value {
+	ok(storage, "GET", vec![buf(key)]).await
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn exactly_one_of_two_concurrent_cas_wins() {
+	let storage = Storage::new();
+	for round in 0..500 {
+		let expected = format!("v{}", round);
+		ok(&storage, "SET", vec![buf("lock"), buf(&expected)]).await;
+
+		let attempts: Vec<_> = ["first", "second"].iter().map(|owner|{
+			let storage = storage.clone();
+			let expected = expected.clone();
+			tokio::spawn(async move {
+				(*owner, ok(&storage, "SET", vec![buf("lock"), buf(owner), buf("IFEQ"), buf(&expected)]).await)
+			})
+		}).collect();
+		let mut winners = Vec::new();
+		for attempt in attempts {
+			match attempt.await.unwrap() {
+				(owner, Value::Ok) => winners.push(owner),
+				(_, Value::Nill) => (),
+				(_, reply) => panic!("unexpected SET reply {:?}", reply),
+			}
+		}
+		assert_eq!(winners.len(), 1, "round {}", round);
+		assert_eq!(get(&storage, "lock").await, buf(winners[0]));
+	}
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn optimistic_increments_are_not_lost() {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("counter"), buf("0")]).await;
+
+	let tasks: Vec<_> = (0..8).map(|_|{
+		let storage = storage.clone();
+		tokio::spawn(async move {
+			for _ in 0..200 {
+				loop {
+					let current = match get(&storage, "counter").await {
+						Value::Buffer(current) => String::from_utf8(current).unwrap(),
+						reply => panic!("unexpected GET reply {:?}", reply),
+					};
+					let next = (current.parse::<i64>().unwrap() + 1).to_string();
+					// retried after a conflict with another task
+					if ok(&storage, "SET", vec![buf("counter"), buf(&next), buf("IFEQ"), buf(&current)]).await == Value::Ok {
+						break;
+					}
+				}
+			}
+		})
+	}).collect();
+	for task in tasks {
+		task.await.unwrap();
+	}
+	assert_eq!(get(&storage, "counter").await, buf("1600"));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn concurrent_ifgt_keeps_the_maximum() {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("max"), buf("0")]).await;
+	let tasks: Vec<_> = (0..4).map(|task|{
+		let storage = storage.clone();
+		tokio::spawn(async move {
+			for i in 0..500 {
+				let value = i * 4 + task;
+				ok(&storage, "SET", vec![buf("max"), buf(&value.to_string()), buf("IFGT"), int(value)]).await;
+			}
+		})
+	}).collect();
+	for task in tasks {
+		task.await.unwrap();
+	}
+	assert_eq!(get(&storage, "max").await, buf("1999"));
+}
+
+#[tokio::test]
+async fn condition_matrix() {
+	let storage = Storage::with_clock(Arc::new(MockClock::new()));
+	// a missing key fails, unless NX allows to create it
+	assert_eq!(ok(&storage, "SET", vec![buf("k"), buf("a"), buf("IFEQ"), buf("x")]).await, Value::Nill);
+	assert_eq!(ok(&storage, "SET", vec![buf("k"), buf("a"), buf("IFGT"), int(5)]).await, Value::Nill);
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("k")]).await, int(0));
+	assert_eq!(ok(&storage, "SET", vec![buf("k"), buf("a"), buf("IFEQ"), buf("x"), buf("NX")]).await, Value::Ok);
+	assert_eq!(get(&storage, "k").await, buf("a"));
+
+	// a present key is replaced only if the condition holds
+	assert_eq!(ok(&storage, "SET", vec![buf("k"), buf("b"), buf("IFEQ"), buf("x"), buf("NX")]).await, Value::Nill);
+	assert_eq!(ok(&storage, "SET", vec![buf("k"), buf("b"), buf("IFEQ"), buf("a"), buf("NX")]).await, Value::Ok);
+	assert_eq!(get(&storage, "k").await, buf("b"));
+
+	// the TTL is replaced, kept with KEEPTTL or removed
+	assert_eq!(ok(&storage, "SET", vec![buf("k"), buf("c"), buf("IFEQ"), buf("b"), buf("EX"), int(100)]).await, Value::Ok);
+	assert_eq!(ok(&storage, "TTL", vec![buf("k")]).await, int(100));
+	assert_eq!(ok(&storage, "SET", vec![buf("k"), buf("d"), buf("IFEQ"), buf("c"), buf("KEEPTTL")]).await, Value::Ok);
+	assert_eq!(ok(&storage, "TTL", vec![buf("k")]).await, int(100));
+	assert_eq!(ok(&storage, "SET", vec![buf("k"), buf("e"), buf("IFEQ"), buf("d")]).await, Value::Ok);
+	assert_eq!(ok(&storage, "TTL", vec![buf("k")]).await, int(-1));
+
+	// IFGT compares integers
+	ok(&storage, "SET", vec![buf("n"), buf("10")]).await;
+	assert_eq!(ok(&storage, "SET", vec![buf("n"), buf("10"), buf("IFGT"), int(10)]).await, Value::Nill);
+	assert_eq!(ok(&storage, "SET", vec![buf("n"), buf("11"), buf("IFGT"), int(11)]).await, Value::Ok);
+	assert_eq!(err(&storage, "SET", vec![buf("k"), buf("1"), buf("IFGT"), int(1)]).await, "invalid digit found in string");
+
+	// wrong combinations
+	assert_eq!(err(&storage, "SET", vec![buf("k"), buf("v"), buf("IFEQ"), buf("e"), buf("XX")]).await, "ERR syntax error");
+	assert_eq!(err(&storage, "SET", vec![buf("k"), buf("v"), buf("IFEQ"), buf("e"), buf("IFGT"), int(1)]).await, "ERR syntax error");
+	ok(&storage, "RPUSH", vec![buf("list"), buf("a")]).await;
+	assert!(err(&storage, "SET", vec![buf("list"), buf("v"), buf("IFEQ"), buf("a")]).await.starts_with("WRONGTYPE"));
+}
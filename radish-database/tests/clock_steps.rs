@@ -0,0 +1,157 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Steps of the wall clock, e.g. by NTP, against relative and absolute deadlines.
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use radish_database::{Clock, Storage, Timeline, Value};
+
+use common::*;
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+/// Clock whose wall clock may be stepped apart from the monotonic one
+struct SteppingClock(Mutex<(SystemTime, Instant)>);
+
+impl SteppingClock {
+	fn new() -> Arc<Self> {
+		Arc::new(Self(Mutex::new((SystemTime::now(), Instant::now()))))
+	}
+
+	async fn advance(&self, storage: &Storage, by: Duration) {
+		{
+			let mut now = self.0.lock().unwrap();
+			now.0 += by;
+			now.1 += by;
+		}
+		storage.keys_check_expirations().await;
+	}
+
+	async fn step_back(&self, storage: &Storage, by: Duration) {
+		self.0.lock().unwrap().0 -= by;
+		storage.keys_check_expirations().await;
+	}
+
+	async fn step_forward(&self, storage: &Storage, by: Duration) {
+		self.0.lock().unwrap().0 += by;
+		storage.keys_check_expirations().await;
+	}
+}
+
+impl Clock for SteppingClock {
+	fn now_system(&self) -> SystemTime {
+		self.0.lock().unwrap().0
+	}
+
+	fn now_monotonic(&self) -> Instant {
+		self.0.lock().unwrap().1
+	}
+}
+
+async fn relative_ttls(storage: &Storage) {
+	ok(storage, "SET", vec![buf("ex"), buf("v"), buf("EX"), int(10)]).await;
+	ok(storage, "SETEX", vec![buf("setex"), int(10), buf("v")]).await;
+	ok(storage, "SET", vec![buf("pexpire"), buf("v")]).await;
+	ok(storage, "PEXPIRE", vec![buf("pexpire"), int(10_000)]).await;
+}
+
+async fn existing(storage: &Storage) -> i64 {
+	match ok(storage, "EXISTS", vec![buf("ex"), buf("setex"), buf("pexpire")]).await {
+		Value::Integer(count) => count,
+		reply => panic!("unexpected EXISTS reply {:?}", reply),
+	}
+}
+
+#[tokio::test]
+async fn backward_step_does_not_prolong_relative_ttls() {
+	let clock = SteppingClock::new();
+	let storage = Storage::with_clock(clock.clone());
+	relative_ttls(&storage).await;
+
+	clock.advance(&storage, Duration::from_secs(4)).await;
+	clock.step_back(&storage, HOUR).await;
+	assert_eq!(ok(&storage, "PTTL", vec![buf("ex")]).await, int(6_000));
+
+	clock.advance(&storage, Duration::from_millis(5_999)).await;
+	assert_eq!(existing(&storage).await, 3);
+	clock.advance(&storage, Duration::from_millis(1)).await;
+	assert_eq!(existing(&storage).await, 0);
+}
+
+#[tokio::test]
+async fn forward_step_does_not_expire_everything() {
+	let clock = SteppingClock::new();
+	let storage = Storage::with_clock(clock.clone());
+	relative_ttls(&storage).await;
+
+	clock.step_forward(&storage, HOUR).await;
+	assert_eq!(existing(&storage).await, 3);
+	assert_eq!(ok(&storage, "PTTL", vec![buf("setex")]).await, int(10_000));
+
+	clock.advance(&storage, Duration::from_millis(9_999)).await;
+	assert_eq!(existing(&storage).await, 3);
+	clock.advance(&storage, Duration::from_millis(1)).await;
+	assert_eq!(existing(&storage).await, 0);
+}
+
+#[tokio::test]
+async fn absolute_deadlines_follow_the_wall_clock_when_set() {
+	let clock = SteppingClock::new();
+	let storage = Storage::with_clock(clock.clone());
+	clock.step_back(&storage, HOUR).await;
+
+	// a minute from the stepped wall clock is a minute from now
+	let wall = clock.now_system().duration_since(UNIX_EPOCH).unwrap();
+	ok(&storage, "SET", vec![buf("at"), buf("v")]).await;
+	ok(&storage, "PEXPIREAT", vec![buf("at"), int((wall + Duration::from_secs(60)).as_millis() as i64)]).await;
+	match ok(&storage, "PTTL", vec![buf("at")]).await {
+		Value::Integer(ttl) => assert!((59_999..=60_000).contains(&ttl), "{}", ttl),
+		ttl => panic!("unexpected PTTL {:?}", ttl),
+	}
+
+	// a later step does not move the deadline already set
+	clock.step_forward(&storage, 2 * HOUR).await;
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("at")]).await, int(1));
+	clock.advance(&storage, Duration::from_secs(60)).await;
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("at")]).await, int(0));
+}
+
+#[test]
+fn timeline_ignores_wall_clock_steps() {
+	let clock = SteppingClock::new();
+	let timeline = Timeline::new(clock.clone());
+	let started = timeline.now();
+	let deadline = started + Duration::from_secs(10);
+	let instant = timeline.instant(deadline);
+	assert_eq!(instant, clock.now_monotonic() + Duration::from_secs(10));
+
+	clock.0.lock().unwrap().0 -= HOUR;
+	assert_eq!(timeline.now(), started);
+	// the sleep of the awaker still ends 10 seconds from the start
+	assert_eq!(timeline.instant(deadline), instant);
+	// conversions of the wall clock use the offset as of now
+	assert_eq!(timeline.to_wall(started), started - HOUR);
+	assert_eq!(timeline.from_wall(started - HOUR), started);
+
+	clock.0.lock().unwrap().1 += Duration::from_secs(15);
+	assert_eq!(timeline.now(), started + Duration::from_secs(15));
+	// a deadline in the past is due now
+	assert_eq!(timeline.instant(deadline), clock.now_monotonic());
+}
@@ -0,0 +1,126 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::collections::HashMap;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+/// `cmdstat_<name>` lines of INFO commandstats as field maps
+async fn commandstats(storage: &Storage) -> HashMap<String, HashMap<String, String>> {
+	let info = match ok(storage, "INFO", vec![buf("commandstats")]).await {
+		Value::Buffer(info) => String::from_utf8(info).unwrap(),
+		reply => panic!("unexpected INFO reply {:?}", reply),
+	};
+	let mut lines = info.lines();
+	assert_eq!(lines.next(), Some("# Commandstats"));
+	lines
+		.filter(|line|!line.is_empty())
+		.map(|line|{
+			let (name, fields) = line.split_at(line.find(':').unwrap());
+			let name = name.strip_prefix("cmdstat_").unwrap().to_owned();
+			let fields = fields[1..].split(',').map(|field|{
+				let (key, value) = field.split_at(field.find('=').unwrap());
+				(key.to_owned(), value[1..].to_owned())
+			}).collect();
+			(name, fields)
+		})
+		.collect()
+}
+
+fn field(stats: &HashMap<String, HashMap<String, String>>, command: &str, name: &str) -> u64 {
+	stats[command][name].parse().unwrap()
+}
+
+#[tokio::test]
+async fn counters_of_a_known_mix() {
+	let storage = Storage::new();
+	for i in 0..5 {
+		ok(&storage, "SET", vec![buf(&format!("k{}", i)), buf("v")]).await;
+	}
+	for _ in 0..3 {
+		ok(&storage, "GET", vec![buf("k0")]).await;
+	}
+	ok(&storage, "RPUSH", vec![buf("list"), buf("a")]).await;
+	// failed calls: a wrong type and a wrong arity
+	err(&storage, "INCR", vec![buf("list")]).await;
+	err(&storage, "INCR", vec![]).await;
+	ok(&storage, "INCR", vec![buf("counter")]).await;
+	ok(&storage, "BLPOP", vec![buf("empty"), float(0.05)]).await;
+
+	let stats = commandstats(&storage).await;
+	let mut names: Vec<_> = stats.keys().cloned().collect();
+	names.sort();
+	assert_eq!(names, vec!["blpop", "get", "incr", "rpush", "set"]);
+	assert_eq!(field(&stats, "set", "calls"), 5);
+	assert_eq!(field(&stats, "get", "calls"), 3);
+	assert_eq!(field(&stats, "get", "failed_calls"), 0);
+	assert_eq!(field(&stats, "incr", "calls"), 3);
+	assert_eq!(field(&stats, "incr", "failed_calls"), 2);
+	assert_eq!(field(&stats, "rpush", "calls"), 1);
+	assert_eq!(field(&stats, "blpop", "calls"), 1);
+	assert!(field(&stats, "blpop", "max_usec") >= 50_000);
+	assert!(field(&stats, "blpop", "usec") >= field(&stats, "blpop", "max_usec"));
+
+	// INFO is counted once it has replied
+	assert_eq!(field(&commandstats(&storage).await, "info", "calls"), 1);
+}
+
+#[tokio::test]
+async fn line_format() {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("k"), buf("v")]).await;
+	ok(&storage, "SET", vec![buf("k"), buf("v")]).await;
+
+	let stats = commandstats(&storage).await;
+	let set = &stats["set"];
+	let mut keys: Vec<_> = set.keys().map(|k|&k[..]).collect();
+	keys.sort();
+	assert_eq!(keys, vec!["calls", "failed_calls", "max_usec", "usec", "usec_per_call"]);
+	let usec = field(&stats, "set", "usec") as f64;
+	assert_eq!(set["usec_per_call"], format!("{:.2}", usec / 2.0));
+}
+
+#[tokio::test]
+async fn resetstat_clears_the_counters() {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("k"), buf("v")]).await;
+	ok(&storage, "GET", vec![buf("k")]).await;
+	ok(&storage, "CONFIG", vec![buf("RESETSTAT")]).await;
+
+	let stats = commandstats(&storage).await;
+	// only the RESETSTAT call itself is left
+	assert_eq!(stats.keys().collect::<Vec<_>>(), vec!["config"]);
+	assert_eq!(field(&stats, "config", "calls"), 1);
+
+	ok(&storage, "GET", vec![buf("k")]).await;
+	assert_eq!(field(&commandstats(&storage).await, "get", "calls"), 1);
+}
+
+#[tokio::test]
+async fn commandstats_is_a_part_of_info_all() {
+	let storage = Storage::new();
+	ok(&storage, "PING", vec![]).await;
+	let info = |reply| match reply {
+		Value::Buffer(info) => String::from_utf8(info).unwrap(),
+		reply => panic!("unexpected INFO reply {:?}", reply),
+	};
+	assert!(info(ok(&storage, "INFO", vec![buf("all")]).await).contains("\ncmdstat_ping:calls=1,"));
+	assert!(!info(ok(&storage, "INFO", vec![]).await).contains("cmdstat_"));
+}
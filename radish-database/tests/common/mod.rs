@@ -0,0 +1,69 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Helpers shared by the behaviour tests: commands are run through `Storage::execute`
+//! the same way the server does for a connection.
+
+#![allow(dead_code)]
+
+use radish_database::{Storage, Session, Command, Value};
+
+pub fn buf(value: &str) -> Value {
+	Value::Buffer(value.as_bytes().to_vec())
+}
+
+pub fn int(value: i64) -> Value {
+	Value::Integer(value)
+}
+
+pub fn float(value: f64) -> Value {
+	Value::Float(value.to_bits())
+}
+
+pub fn array(values: Vec<Value>) -> Value {
+	Value::Array(values.into())
+}
+
+pub fn bufs(values: &[&str]) -> Value {
+	array(values.iter().map(|v|buf(v)).collect())
+}
+
+pub fn command(name: &str, arguments: Vec<Value>) -> Command {
+	Command {
+		command: name.to_owned(),
+		arguments: arguments.into(),
+	}
+}
+
+pub async fn run(storage: &Storage, name: &str, arguments: Vec<Value>) -> Value {
+	storage.execute(&mut Session::default(), command(name, arguments)).await
+}
+
+/// Runs a command expected to succeed
+pub async fn ok(storage: &Storage, name: &str, arguments: Vec<Value>) -> Value {
+	match run(storage, name, arguments).await {
+		Value::Error(err) => panic!("{} failed: {}", name, err),
+		value => value,
+	}
+}
+
+/// Runs a command expected to fail and returns the error
+pub async fn err(storage: &Storage, name: &str, arguments: Vec<Value>) -> String {
+	match run(storage, name, arguments).await {
+		Value::Error(err) => err,
+		value => panic!("{} succeeded with {:?}", name, value),
+	}
+}
@@ -0,0 +1,118 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+#![cfg(feature = "compression")]
+
+mod common;
+
+use std::path::PathBuf;
+
+use radish_database::{Storage, Value, FLAG_ZSTD, decode_snapshot};
+
+use common::*;
+
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_file(&self.0);
+	}
+}
+
+async fn dataset() -> Storage {
+	let storage = Storage::new();
+	for i in 0..200 {
+		ok(&storage, "SET", vec![buf(&format!("string{}", i)), buf(&"compressible ".repeat(50))]).await;
+	}
+	ok(&storage, "RPUSH", vec![buf("list"), buf("a"), buf("b")]).await;
+	ok(&storage, "SADD", vec![buf("set"), buf("x")]).await;
+	ok(&storage, "HSET", vec![buf("hash"), buf("f"), buf("v")]).await;
+	ok(&storage, "EXPIRE", vec![buf("list"), int(1000)]).await;
+	storage
+}
+
+async fn save(storage: &Storage, level: &str) -> (TempFile, Vec<u8>) {
+	ok(storage, "CONFIG", vec![buf("SET"), buf("rdb-compression-level"), buf(level)]).await;
+	let file = TempFile(std::env::temp_dir().join(format!("radish-compression-{}-{}.snapshot", level, std::process::id())));
+	storage.save_snapshot(&file.0).await.unwrap();
+	let data = std::fs::read(&file.0).unwrap();
+	(file, data)
+}
+
+async fn loaded(file: &TempFile) -> Storage {
+	let storage = Storage::new();
+	storage.load_snapshot(&file.0, true).await.unwrap();
+	storage
+}
+
+async fn key_count(storage: &Storage) -> usize {
+	match ok(storage, "KEYS", vec![buf(".*")]).await {
+		Value::Array(keys) => keys.len(),
+		keys => panic!("unexpected KEYS reply {:?}", keys),
+	}
+}
+
+async fn assert_same(left: &Storage, right: &Storage) {
+	assert_eq!(key_count(left).await, key_count(right).await);
+	for i in 0..200 {
+		let key = buf(&format!("string{}", i));
+		assert_eq!(ok(left, "GET", vec![key.clone()]).await, ok(right, "GET", vec![key]).await);
+	}
+	assert_eq!(ok(left, "LRANGE", vec![buf("list"), int(0), int(-1)]).await, ok(right, "LRANGE", vec![buf("list"), int(0), int(-1)]).await);
+	assert_eq!(ok(left, "SMEMBERS", vec![buf("set")]).await, ok(right, "SMEMBERS", vec![buf("set")]).await);
+	assert_eq!(ok(left, "HGET", vec![buf("hash"), buf("f")]).await, ok(right, "HGET", vec![buf("hash"), buf("f")]).await);
+	assert_eq!(ok(right, "TTL", vec![buf("list")]).await, ok(left, "TTL", vec![buf("list")]).await);
+}
+
+#[tokio::test]
+async fn compressed_and_plain_snapshots_load_identically() {
+	let storage = dataset().await;
+	let (plain, plain_data) = save(&storage, "0").await;
+	let (compressed, compressed_data) = save(&storage, "3").await;
+
+	assert_eq!(decode_snapshot(&plain_data, true).unwrap().0.flags, 0);
+	assert_eq!(decode_snapshot(&compressed_data, true).unwrap().0.flags, FLAG_ZSTD);
+	assert!(compressed_data.len() * 10 < plain_data.len(), "{} vs {} bytes", compressed_data.len(), plain_data.len());
+
+	let from_plain = loaded(&plain).await;
+	let from_compressed = loaded(&compressed).await;
+	assert_same(&storage, &from_plain).await;
+	assert_same(&storage, &from_compressed).await;
+}
+
+#[tokio::test]
+async fn compressed_payload_is_checked() {
+	let storage = dataset().await;
+	let (file, mut data) = save(&storage, "1").await;
+	let middle = data.len() / 2;
+	data[middle] ^= 0x01;
+	std::fs::write(&file.0, &data).unwrap();
+	assert_eq!(
+		Storage::new().load_snapshot(&file.0, true).await.map(|_|()),
+		Err("snapshot corrupt: checksum mismatch".to_owned()),
+	);
+}
+
+#[tokio::test]
+async fn compression_level_is_validated() {
+	let storage = Storage::new();
+	assert_eq!(
+		err(&storage, "CONFIG", vec![buf("SET"), buf("rdb-compression-level"), buf("23")]).await,
+		"Argument must be from 0 to 22, got 23",
+	);
+	assert_eq!(ok(&storage, "CONFIG", vec![buf("GET"), buf("rdb-compression-level")]).await, bufs(&["rdb-compression-level", "0"]));
+}
@@ -0,0 +1,71 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Many tasks executing mixed commands against one shared `Storage`
+
+mod common;
+
+use std::sync::Arc;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+const TASKS: usize = 32;
+const ROUNDS: i64 = 200;
+
+#[tokio::test(threaded_scheduler)]
+async fn mixed_commands_from_many_tasks() {
+	let storage = Arc::new(Storage::new());
+	let tasks: Vec<_> = (0..TASKS).map(|task|{
+		let storage = storage.clone();
+		tokio::spawn(async move {
+			let own = format!("own:{}", task);
+			for round in 0..ROUNDS {
+				// keys shared by every task
+				ok(&storage, "INCR", vec![buf("counter")]).await;
+				ok(&storage, "RPUSH", vec![buf("list"), buf(&round.to_string())]).await;
+				ok(&storage, "SADD", vec![buf("set"), buf(&format!("{}:{}", task, round))]).await;
+				ok(&storage, "HINCRBY", vec![buf("hash"), buf(&own), int(1)]).await;
+				ok(&storage, "GET", vec![buf("counter")]).await;
+				ok(&storage, "LRANGE", vec![buf("list"), int(0), int(10)]).await;
+				// keys of the task
+				ok(&storage, "SET", vec![buf(&own), buf(&round.to_string()), buf("EX"), int(100)]).await;
+				ok(&storage, "EXPIRE", vec![buf(&own), int(200)]).await;
+				ok(&storage, "RENAME", vec![buf(&own), buf(&format!("{}:renamed", own))]).await;
+				ok(&storage, "RENAME", vec![buf(&format!("{}:renamed", own)), buf(&own)]).await;
+				assert_eq!(ok(&storage, "GET", vec![buf(&own)]).await, buf(&round.to_string()));
+				ok(&storage, "TTL", vec![buf(&own)]).await;
+			}
+		})
+	}).collect();
+	for task in tasks {
+		task.await.unwrap();
+	}
+
+	let total = TASKS as i64 * ROUNDS;
+	assert_eq!(ok(&storage, "GET", vec![buf("counter")]).await, buf(&total.to_string()));
+	assert_eq!(ok(&storage, "LLEN", vec![buf("list")]).await, int(total));
+	assert_eq!(ok(&storage, "SCARD", vec![buf("set")]).await, int(total));
+	assert_eq!(ok(&storage, "HLEN", vec![buf("hash")]).await, int(TASKS as i64));
+	for task in 0..TASKS {
+		let own = format!("own:{}", task);
+		assert_eq!(ok(&storage, "HGET", vec![buf("hash"), buf(&own)]).await, int(ROUNDS));
+		assert_eq!(ok(&storage, "GET", vec![buf(&own)]).await, buf(&(ROUNDS - 1).to_string()));
+		assert_eq!(ok(&storage, "EXISTS", vec![buf(&format!("{}:renamed", own))]).await, int(0));
+		assert!(matches!(ok(&storage, "TTL", vec![buf(&own)]).await, Value::Integer(ttl) if ttl > 100));
+	}
+}
@@ -0,0 +1,162 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Config, ConfigChange, Storage};
+
+use common::*;
+
+const REPRESENTATIVE: &str = r#"
+# network
+bind 127.0.0.1 ::1
+port 7000
+unixsocket /tmp/radish.sock
+timeout 300
+tcp-keepalive 60
+
+requirepass "very secret"
+maxmemory 100mb
+maxmemory-policy ALLKEYS-LRU
+
+save 900 1
+save 300 10
+appendonly no
+dir /var/lib/radish
+dbfilename 'dump.rdb'
+logfile ""
+loglevel warning
+databases 16
+"#;
+
+fn get(config: &Config, name: &str) -> String {
+	config.get(name).unwrap_or_else(||panic!("{} is not a parameter", name))
+}
+
+#[test]
+fn representative_file() {
+	let config = Config::new();
+	config.load(REPRESENTATIVE).unwrap();
+
+	assert_eq!(config.bind(), "127.0.0.1 ::1");
+	assert_eq!(config.port(), 7000);
+	assert_eq!(config.unixsocket(), "/tmp/radish.sock");
+	assert_eq!(config.timeout(), 300);
+	assert_eq!(config.tcp_keepalive(), 60);
+	assert_eq!(config.requirepass(), "very secret");
+	assert_eq!(get(&config, "maxmemory"), (100 * 1024 * 1024).to_string());
+	assert_eq!(get(&config, "maxmemory-policy"), "allkeys-lru");
+	assert_eq!(get(&config, "save"), "900 1 300 10");
+	assert_eq!(get(&config, "appendonly"), "no");
+	assert_eq!(config.dir(), "/var/lib/radish");
+	assert_eq!(config.dbfilename(), "dump.rdb");
+	assert_eq!(config.logfile(), "");
+	assert_eq!(config.loglevel(), "warning");
+	assert_eq!(get(&config, "databases"), "16");
+}
+
+#[test]
+fn memory_units() {
+	let config = Config::new();
+	for (value, bytes) in &[("1024", 1024), ("1k", 1000), ("1kb", 1024), ("2M", 2_000_000), ("2mb", 2 << 20), ("1gb", 1 << 30)] {
+		config.load(&format!("maxmemory {}", value)).unwrap();
+		assert_eq!(get(&config, "maxmemory"), bytes.to_string(), "{}", value);
+	}
+}
+
+#[test]
+fn empty_save_clears_the_rules() {
+	let config = Config::new();
+	config.load("save 900 1\nsave \"\"\n").unwrap();
+	assert_eq!(get(&config, "save"), "");
+}
+
+#[test]
+fn unknown_directive_names_the_line() {
+	let config = Config::new();
+	assert_eq!(
+		config.load("port 7000\n\n# comment\nno-such-directive 1\n").unwrap_err(),
+		"Bad directive at line 4: 'no-such-directive 1': Unsupported CONFIG parameter: no-such-directive",
+	);
+}
+
+#[test]
+fn malformed_values() {
+	let cases = [
+		("port seven", "Bad directive at line 1: 'port seven': "),
+		("maxmemory 100 mb", "Bad directive at line 1: 'maxmemory 100 mb': "),
+		("maxmemory 10tb", "Bad directive at line 1: 'maxmemory 10tb': Argument must be a memory size like 100mb, got '10tb'"),
+		("maxmemory-policy sometimes", "Bad directive at line 1: 'maxmemory-policy sometimes': Argument must be one of "),
+		("save 900", "Bad directive at line 1: 'save 900': Argument must be pairs of seconds and changes, got '900'"),
+		("appendonly maybe", "Bad directive at line 1: 'appendonly maybe': Argument must be 'yes' or 'no', got 'maybe'"),
+		("timeout -1", "Bad directive at line 1: 'timeout -1': Argument must be a non-negative integer"),
+	];
+	for (line, error) in cases.iter() {
+		let config = Config::new();
+		let err = config.load(&format!("{}\n", line)).unwrap_err();
+		assert!(err.starts_with(error), "{}: {}", line, err);
+	}
+}
+
+#[test]
+fn lines_before_an_error_are_applied() {
+	let config = Config::new();
+	assert!(config.load("port 7000\nport x\n").is_err());
+	assert_eq!(config.port(), 7000);
+}
+
+#[test]
+fn reload_reports_the_changes() {
+	let config = Config::new();
+	config.load(REPRESENTATIVE).unwrap();
+
+	let changed = REPRESENTATIVE
+		.replace("port 7000", "port 7001")
+		.replace("timeout 300", "timeout 30")
+		.replace("loglevel warning", "")
+		.replace("requirepass \"very secret\"", "requirepass other");
+	let overrides = vec![("maxmemory".to_owned(), "1gb".to_owned())];
+	let report = config.reload(&changed, &overrides).unwrap();
+
+	let change = |name, old: &str, new: &str| ConfigChange {name, old: old.to_owned(), new: new.to_owned()};
+	assert_eq!(report.changed, vec![
+		change("requirepass", "very secret", "other"),
+		change("maxmemory", &(100 << 20).to_string(), &(1 << 30).to_string()),
+		change("loglevel", "warning", &Config::new().loglevel()),
+		change("timeout", "300", "30"),
+	]);
+	// the port is bound at start only
+	assert_eq!(report.ignored, vec![change("port", "7000", "7001")]);
+	assert_eq!(config.port(), 7000);
+	assert_eq!(config.timeout(), 30);
+	// the password is not shown
+	assert_eq!(report.changed[0].to_string(), "requirepass: changed");
+	assert_eq!(report.changed[3].to_string(), "timeout: '300' -> '30'");
+
+	// nothing is applied if the file is invalid
+	let err = config.reload("timeout 10\ntimeout x\n", &[]).unwrap_err();
+	assert!(err.starts_with("Bad directive at line 2"), "{}", err);
+	assert_eq!(config.timeout(), 30);
+	assert!(config.reload(REPRESENTATIVE, &[("timeout".to_owned(), "x".to_owned())]).unwrap_err().starts_with("Override 'timeout': "));
+}
+
+#[tokio::test]
+async fn loaded_values_are_seen_by_config_get() {
+	let storage = Storage::new();
+	storage.load_config("maxmemory 2mb\nrename-command KEYS \"\"\n").unwrap();
+	assert_eq!(ok(&storage, "CONFIG", vec![buf("GET"), buf("maxmemory")]).await, bufs(&["maxmemory", "2097152"]));
+	assert!(err(&storage, "KEYS", vec![buf("*")]).await.contains("unknown command"));
+}
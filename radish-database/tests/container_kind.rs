@@ -0,0 +1,79 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{ContainerKind, Storage, Value};
+
+use common::*;
+
+/// Key name and the command creating a key of the kind; a new kind fails to compile here
+fn create(kind: ContainerKind) -> (&'static str, &'static str, Vec<Value>) {
+	match kind {
+		ContainerKind::String => ("string", "SET", vec![buf("string"), buf("v")]),
+		ContainerKind::List => ("list", "RPUSH", vec![buf("list"), buf("v")]),
+		ContainerKind::Set => ("set", "SADD", vec![buf("set"), buf("v")]),
+		ContainerKind::Hash => ("hash", "HSET", vec![buf("hash"), buf("f"), buf("v")]),
+		ContainerKind::Json => ("json", "JSON.SET", vec![buf("json"), buf("$"), buf("{}")]),
+		ContainerKind::Bloom => ("bloom", "BF.ADD", vec![buf("bloom"), buf("v")]),
+		ContainerKind::TimeSeries => ("series", "TS.ADD", vec![buf("series"), int(1), float(1.0)]),
+	}
+}
+
+async fn populated() -> Storage {
+	let storage = Storage::new();
+	for kind in ContainerKind::ALL.iter() {
+		let (_, name, arguments) = create(*kind);
+		ok(&storage, name, arguments).await;
+	}
+	storage
+}
+
+#[tokio::test]
+async fn type_of_every_kind() {
+	let storage = populated().await;
+	for kind in ContainerKind::ALL.iter() {
+		let (key, _, _) = create(*kind);
+		assert_eq!(ok(&storage, "TYPE", vec![buf(key)]).await, buf(kind.as_str()), "{:?}", kind);
+	}
+	assert_eq!(ok(&storage, "TYPE", vec![buf("missing")]).await, Value::Nill);
+}
+
+#[tokio::test]
+async fn scan_type_filter_of_every_kind() {
+	let storage = populated().await;
+	for kind in ContainerKind::ALL.iter() {
+		let (key, _, _) = create(*kind);
+		let reply = ok(&storage, "SCAN", vec![int(0), buf("COUNT"), int(100), buf("TYPE"), buf(kind.as_str())]).await;
+		assert_eq!(reply, array(vec![int(0), bufs(&[key])]), "{:?}", kind);
+	}
+	assert_eq!(
+		err(&storage, "SCAN", vec![int(0), buf("TYPE"), buf("zset")]).await,
+		"Unexpected type 'zset'",
+	);
+}
+
+#[test]
+fn names_round_trip() {
+	let names: Vec<&str> = ContainerKind::ALL.iter().map(|kind|kind.as_str()).collect();
+	assert_eq!(names, vec!["string", "list", "set", "hash", "ReJSON-RL", "MBbloom--", "TSDB-TYPE"]);
+	for kind in ContainerKind::ALL.iter() {
+		assert_eq!(kind.as_str().parse::<ContainerKind>().unwrap(), *kind);
+		assert_eq!(kind.to_string(), kind.as_str());
+	}
+	// names are case sensitive, as TYPE replies them
+	assert_eq!("STRING".parse::<ContainerKind>().unwrap_err(), "Unexpected type 'STRING'");
+}
@@ -0,0 +1,137 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Storage, Value, DatasetSnapshot, SnapshotData, ImportMode, snapshot_to_json, snapshot_from_json};
+
+use common::*;
+
+async fn fill(storage: &Storage) {
+	ok(storage, "SET", vec![buf("string"), buf("value")]).await;
+	ok(storage, "SET", vec![Value::Buffer(vec![0xff, 0]), Value::Buffer(vec![0, 0xfe])]).await;
+	ok(storage, "RPUSH", vec![buf("list"), buf("a"), int(1), float(2.5), buf("a")]).await;
+	ok(storage, "SADD", vec![buf("set"), buf("x"), buf("y"), int(7)]).await;
+	ok(storage, "HSET", vec![buf("hash"), buf("f1"), buf("v1"), buf("f2"), int(2)]).await;
+	ok(storage, "JSON.SET", vec![buf("doc"), buf("$"), buf(r#"{"a":[1,2,{"b":null}],"c":"d"}"#)]).await;
+	ok(storage, "BF.ADD", vec![buf("bloom"), buf("item")]).await;
+	ok(storage, "TS.ADD", vec![buf("ts"), int(1000), float(1.5)]).await;
+	ok(storage, "TS.ADD", vec![buf("ts"), int(2000), int(3)]).await;
+	ok(storage, "PEXPIRE", vec![buf("hash"), int(100_000)]).await;
+}
+
+/// Entries sorted by key with members of sets and hashes in a stable order
+fn normalized(mut snapshot: DatasetSnapshot) -> DatasetSnapshot {
+	snapshot.entries.sort_by(|a, b|a.key.cmp(&b.key));
+	for entry in &mut snapshot.entries {
+		match &mut entry.data {
+			SnapshotData::Set(members) => members.sort_by_key(|member|format!("{:?}", member)),
+			SnapshotData::Hash(pairs) => pairs.sort_by_key(|pair|format!("{:?}", pair)),
+			_ => (),
+		}
+	}
+	snapshot
+}
+
+#[tokio::test]
+async fn export_covers_every_type() {
+	let storage = Storage::new();
+	fill(&storage).await;
+
+	let snapshot = normalized(storage.export().await);
+	let kinds: Vec<(&[u8], &str, bool)> = snapshot.entries.iter().map(|entry|(&entry.key[..], match entry.data {
+		SnapshotData::String(_) => "string",
+		SnapshotData::List(_) => "list",
+		SnapshotData::Set(_) => "set",
+		SnapshotData::Hash(_) => "hash",
+		SnapshotData::Json(_) => "json",
+		SnapshotData::Bloom(_) => "bloom",
+		SnapshotData::TimeSeries(_) => "timeseries",
+	}, entry.expire_at.is_some())).collect();
+	assert_eq!(kinds, vec![
+		(&b"bloom"[..], "bloom", false),
+		(b"doc", "json", false),
+		(b"hash", "hash", true),
+		(b"list", "list", false),
+		(b"set", "set", false),
+		(b"string", "string", false),
+		(b"ts", "timeseries", false),
+		(b"\xff\x00", "string", false),
+	]);
+	let list = &snapshot.entries[3].data;
+	assert_eq!(*list, SnapshotData::List(vec![buf("a"), int(1), float(2.5), buf("a")]));
+}
+
+#[tokio::test]
+async fn import_round_trip() {
+	let source = Storage::new();
+	fill(&source).await;
+	let snapshot = source.export().await;
+
+	let target = Storage::new();
+	ok(&target, "SET", vec![buf("stale"), buf("v")]).await;
+	target.import(snapshot.clone(), ImportMode::Replace).await.unwrap();
+	assert_eq!(normalized(target.export().await), normalized(snapshot));
+
+	// the imported expiration is registered like the one of PEXPIRE
+	assert_eq!(run(&target, "GET", vec![buf("stale")]).await, Value::Nill);
+	match ok(&target, "PTTL", vec![buf("hash")]).await {
+		Value::Integer(ttl) => assert!(ttl > 90_000 && ttl <= 100_000, "{}", ttl),
+		ttl => panic!("unexpected PTTL {:?}", ttl),
+	}
+	assert_eq!(ok(&target, "BF.EXISTS", vec![buf("bloom"), buf("item")]).await, Value::Bool(true));
+	assert_eq!(ok(&target, "HGET", vec![buf("hash"), buf("f2")]).await, int(2));
+}
+
+#[tokio::test]
+async fn merge_rejects_type_conflicts() {
+	let source = Storage::new();
+	fill(&source).await;
+	let snapshot = source.export().await;
+
+	let target = Storage::new();
+	ok(&target, "SADD", vec![buf("string"), buf("m")]).await;
+	ok(&target, "SET", vec![buf("list"), buf("v")]).await;
+	ok(&target, "RPUSH", vec![buf("other"), buf("v")]).await;
+	let error = target.import(snapshot.clone(), ImportMode::Merge).await.unwrap_err();
+	assert!(error.starts_with("Type conflicts for keys: "), "{}", error);
+	assert!(error.contains("string") && error.contains("list") && !error.contains("other"), "{}", error);
+	// nothing is imported after a conflict
+	assert_eq!(run(&target, "GET", vec![buf("doc")]).await, Value::Nill);
+
+	ok(&target, "DEL", vec![buf("string"), buf("list")]).await;
+	ok(&target, "SADD", vec![buf("set"), buf("z")]).await;
+	target.import(snapshot, ImportMode::Merge).await.unwrap();
+	assert_eq!(ok(&target, "SCARD", vec![buf("set")]).await, int(4));
+	assert_eq!(ok(&target, "LLEN", vec![buf("other")]).await, int(1));
+}
+
+#[tokio::test]
+async fn snapshot_json_serialization() {
+	let storage = Storage::new();
+	fill(&storage).await;
+	let snapshot = normalized(storage.export().await);
+
+	let serialized = serde_json::to_string(&snapshot).unwrap();
+	let deserialized: DatasetSnapshot = serde_json::from_str(&serialized).unwrap();
+	assert_eq!(deserialized, snapshot);
+
+	let document = snapshot_to_json(&snapshot);
+	assert_eq!(normalized(snapshot_from_json(&document).unwrap()), snapshot);
+	let string = document["keys"].as_array().unwrap().iter().find(|entry|entry["key"] == "string").unwrap();
+	assert_eq!(string["type"], "string");
+	assert_eq!(string["value"], "value");
+}
@@ -0,0 +1,155 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+const DOC: &str = r#"{"name":"radish","tags":["a","b"],"stats":{"hits":1,"ratio":0.5},"nested":[{"x":[1,2]}]}"#;
+
+async fn json_get(storage: &Storage, key: &str, paths: &[&str]) -> serde_json::Value {
+	let mut args = vec![buf(key)];
+	args.extend(paths.iter().map(|path|buf(path)));
+	match ok(storage, "JSON.GET", args).await {
+		Value::Buffer(text) => serde_json::from_slice(&text).unwrap(),
+		reply => panic!("unexpected JSON.GET reply {:?}", reply),
+	}
+}
+
+async fn json_set(storage: &Storage, key: &str, path: &str, value: &str, condition: Option<&str>) -> Value {
+	let mut args = vec![buf(key), buf(path), buf(value)];
+	args.extend(condition.map(buf));
+	ok(storage, "JSON.SET", args).await
+}
+
+async fn document() -> Storage {
+	let storage = Storage::new();
+	assert_eq!(json_set(&storage, "doc", "$", DOC, None).await, Value::Ok);
+	storage
+}
+
+#[tokio::test]
+async fn paths_select_nested_values() {
+	let storage = document().await;
+	let doc: serde_json::Value = serde_json::from_str(DOC).unwrap();
+	assert_eq!(json_get(&storage, "doc", &[]).await, doc);
+	assert_eq!(json_get(&storage, "doc", &["$"]).await, doc);
+	assert_eq!(json_get(&storage, "doc", &["."]).await, doc);
+	assert_eq!(json_get(&storage, "doc", &["$.name"]).await, serde_json::json!("radish"));
+	assert_eq!(json_get(&storage, "doc", &["stats.hits"]).await, serde_json::json!(1));
+	assert_eq!(json_get(&storage, "doc", &["$.tags[1]"]).await, serde_json::json!("b"));
+	assert_eq!(json_get(&storage, "doc", &["$.tags[-1]"]).await, serde_json::json!("b"));
+	assert_eq!(json_get(&storage, "doc", &["$.nested[0].x[-2]"]).await, serde_json::json!(1));
+	assert_eq!(json_get(&storage, "doc", &[r#"$["stats"]['ratio']"#]).await, serde_json::json!(0.5));
+	assert_eq!(
+		json_get(&storage, "doc", &["$.name", "$.tags[0]"]).await,
+		serde_json::json!({"$.name": "radish", "$.tags[0]": "a"}),
+	);
+
+	assert_eq!(err(&storage, "JSON.GET", vec![buf("doc"), buf("$.missing")]).await, "ERR Path '$.missing' does not exist");
+	assert_eq!(err(&storage, "JSON.GET", vec![buf("doc"), buf("$.tags[2]")]).await, "ERR Path '$.tags[2]' does not exist");
+	assert_eq!(err(&storage, "JSON.GET", vec![buf("doc"), buf("$.name.x")]).await, "ERR Path '$.name.x' does not exist");
+	for path in &["$..a", "$[x]", "$[1", "$a", "$['a'b]"] {
+		assert_eq!(err(&storage, "JSON.GET", vec![buf("doc"), buf(path)]).await, format!("ERR invalid JSON path '{}'", path));
+	}
+	assert_eq!(ok(&storage, "JSON.GET", vec![buf("missing")]).await, Value::Nill);
+}
+
+#[tokio::test]
+async fn set_updates_nested_structures() {
+	let storage = document().await;
+	assert_eq!(json_set(&storage, "doc", "$.stats.hits", "42", None).await, Value::Ok);
+	assert_eq!(json_set(&storage, "doc", "$.stats.new", r#"{"deep":[true,null]}"#, None).await, Value::Ok);
+	assert_eq!(json_set(&storage, "doc", "$.tags[0]", r#""z""#, None).await, Value::Ok);
+	assert_eq!(json_set(&storage, "doc", "$.nested[0].x[1]", "[3]", None).await, Value::Ok);
+	assert_eq!(json_get(&storage, "doc", &["$.stats"]).await, serde_json::json!({"hits": 42, "ratio": 0.5, "new": {"deep": [true, null]}}));
+	assert_eq!(json_get(&storage, "doc", &["$.tags"]).await, serde_json::json!(["z", "b"]));
+	assert_eq!(json_get(&storage, "doc", &["$.nested"]).await, serde_json::json!([{"x": [1, [3]]}]));
+
+	// a missing parent sets nothing
+	assert_eq!(json_set(&storage, "doc", "$.absent.field", "1", None).await, Value::Nill);
+	assert_eq!(err(&storage, "JSON.SET", vec![buf("doc"), buf("$.tags[5]"), buf("1")]).await, "ERR array index 5 is out of range");
+	assert!(err(&storage, "JSON.SET", vec![buf("doc"), buf("$.name.x"), buf("1")]).await.starts_with("WRONGTYPE"));
+	assert!(err(&storage, "JSON.SET", vec![buf("doc"), buf("$.x"), buf("{bad")]).await.starts_with("ERR invalid JSON: "));
+	assert_eq!(err(&storage, "JSON.SET", vec![buf("new"), buf("$.x"), buf("1")]).await, "ERR new objects must be created at the root");
+	assert_eq!(err(&storage, "JSON.SET", vec![buf("doc"), buf("$.x"), buf("1"), buf("YY")]).await, "ERR unexpected option 'YY'");
+
+	// the root is replaced as a whole
+	assert_eq!(json_set(&storage, "doc", "$", "[1]", None).await, Value::Ok);
+	assert_eq!(json_get(&storage, "doc", &[]).await, serde_json::json!([1]));
+}
+
+#[tokio::test]
+async fn nx_and_xx_conditions() {
+	let storage = Storage::new();
+	assert_eq!(json_set(&storage, "doc", "$", "{}", Some("XX")).await, Value::Nill);
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("doc")]).await, int(0));
+	assert_eq!(json_set(&storage, "doc", "$", r#"{"a":1,"l":[0]}"#, Some("NX")).await, Value::Ok);
+	assert_eq!(json_set(&storage, "doc", "$", "{}", Some("nx")).await, Value::Nill);
+
+	assert_eq!(json_set(&storage, "doc", "$.a", "2", Some("NX")).await, Value::Nill);
+	assert_eq!(json_set(&storage, "doc", "$.b", "3", Some("XX")).await, Value::Nill);
+	assert_eq!(json_set(&storage, "doc", "$.a", "4", Some("XX")).await, Value::Ok);
+	assert_eq!(json_set(&storage, "doc", "$.b", "5", Some("NX")).await, Value::Ok);
+	assert_eq!(json_set(&storage, "doc", "$.l[0]", "6", Some("NX")).await, Value::Nill);
+	assert_eq!(json_set(&storage, "doc", "$.l[3]", "7", Some("XX")).await, Value::Nill);
+	assert_eq!(json_set(&storage, "doc", "$.l[0]", "8", Some("XX")).await, Value::Ok);
+	assert_eq!(json_get(&storage, "doc", &[]).await, serde_json::json!({"a": 4, "l": [8], "b": 5}));
+}
+
+#[tokio::test]
+async fn del_numincrby_and_arrappend() {
+	let storage = document().await;
+	assert_eq!(ok(&storage, "JSON.NUMINCRBY", vec![buf("doc"), buf("$.stats.hits"), buf("2")]).await, buf("3"));
+	assert_eq!(ok(&storage, "JSON.NUMINCRBY", vec![buf("doc"), buf("$.stats.ratio"), int(1)]).await, buf("1.5"));
+	assert_eq!(ok(&storage, "JSON.NUMINCRBY", vec![buf("doc"), buf("$.stats.hits"), buf("0.5")]).await, buf("3.5"));
+	assert_eq!(err(&storage, "JSON.NUMINCRBY", vec![buf("doc"), buf("$.name"), buf("1")]).await, "ERR value at path '$.name' is not a number");
+	assert_eq!(err(&storage, "JSON.NUMINCRBY", vec![buf("doc"), buf("$.stats.hits"), buf(r#""1""#)]).await, "ERR increment is not a number");
+	assert_eq!(err(&storage, "JSON.NUMINCRBY", vec![buf("doc"), buf("$.none"), buf("1")]).await, "ERR Path '$.none' does not exist");
+
+	assert_eq!(ok(&storage, "JSON.ARRAPPEND", vec![buf("doc"), buf("$.tags"), buf(r#""c""#), buf(r#"{"d":1}"#)]).await, int(4));
+	assert_eq!(json_get(&storage, "doc", &["$.tags"]).await, serde_json::json!(["a", "b", "c", {"d": 1}]));
+	assert_eq!(err(&storage, "JSON.ARRAPPEND", vec![buf("doc"), buf("$.stats"), buf("1")]).await, "ERR value at path '$.stats' is not an array");
+
+	assert_eq!(ok(&storage, "JSON.DEL", vec![buf("doc"), buf("$.tags[0]")]).await, int(1));
+	assert_eq!(ok(&storage, "JSON.DEL", vec![buf("doc"), buf("$.stats.hits")]).await, int(1));
+	assert_eq!(ok(&storage, "JSON.DEL", vec![buf("doc"), buf("$.stats.hits")]).await, int(0));
+	assert_eq!(ok(&storage, "JSON.DEL", vec![buf("missing"), buf("$.a")]).await, int(0));
+	assert_eq!(json_get(&storage, "doc", &["$.tags[0]"]).await, serde_json::json!("b"));
+	assert_eq!(json_get(&storage, "doc", &["$.stats"]).await, serde_json::json!({"ratio": 1.5}));
+
+	assert_eq!(ok(&storage, "JSON.DEL", vec![buf("doc")]).await, int(1));
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("doc")]).await, int(0));
+}
+
+#[tokio::test]
+async fn type_wrongtype_and_dump() {
+	let storage = document().await;
+	assert_eq!(ok(&storage, "TYPE", vec![buf("doc")]).await, buf("ReJSON-RL"));
+	ok(&storage, "SET", vec![buf("s"), buf("v")]).await;
+	assert!(err(&storage, "JSON.GET", vec![buf("s")]).await.starts_with("WRONGTYPE"));
+	assert!(err(&storage, "JSON.DEL", vec![buf("s")]).await.starts_with("WRONGTYPE"));
+	assert!(err(&storage, "JSON.SET", vec![buf("s"), buf("$"), buf("1")]).await.starts_with("WRONGTYPE"));
+	assert!(err(&storage, "GET", vec![buf("doc")]).await.starts_with("WRONGTYPE"));
+
+	let payload = ok(&storage, "DUMP", vec![buf("doc")]).await;
+	let target = Storage::new();
+	assert_eq!(ok(&target, "RESTORE", vec![buf("copy"), int(0), payload]).await, Value::Ok);
+	assert_eq!(ok(&target, "TYPE", vec![buf("copy")]).await, buf("ReJSON-RL"));
+	assert_eq!(json_get(&target, "copy", &[]).await, json_get(&storage, "doc", &[]).await);
+}
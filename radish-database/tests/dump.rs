@@ -0,0 +1,124 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use radish_database::{Storage, MockClock, Value};
+
+use common::*;
+
+async fn dump(storage: &Storage, key: &str) -> Value {
+	match ok(storage, "DUMP", vec![buf(key)]).await {
+		Value::Buffer(payload) => Value::Buffer(payload),
+		reply => panic!("unexpected DUMP of {}: {:?}", key, reply),
+	}
+}
+
+async fn sorted_members(storage: &Storage, key: &str) -> Vec<Value> {
+	match ok(storage, "SMEMBERS", vec![buf(key)]).await {
+		Value::Array(members) => {
+			let mut members: Vec<Value> = members.into_iter().collect();
+			members.sort_by_key(|m|format!("{:?}", m));
+			members
+		},
+		reply => panic!("unexpected SMEMBERS reply {:?}", reply),
+	}
+}
+
+#[tokio::test]
+async fn every_type_round_trips() {
+	let source = Storage::new();
+	ok(&source, "SET", vec![buf("string"), buf("value")]).await;
+	ok(&source, "RPUSH", vec![buf("list"), buf("a"), buf("b"), buf("c")]).await;
+	ok(&source, "SADD", vec![buf("set"), buf("x"), buf("y")]).await;
+	ok(&source, "HSET", vec![buf("hash"), buf("f1"), buf("v1"), buf("f2"), buf("v2")]).await;
+
+	let target = Storage::new();
+	for key in &["string", "list", "set", "hash"] {
+		let payload = dump(&source, key).await;
+		assert_eq!(ok(&target, "RESTORE", vec![buf(key), int(0), payload]).await, Value::Ok, "{}", key);
+		assert_eq!(ok(&target, "TYPE", vec![buf(key)]).await, ok(&source, "TYPE", vec![buf(key)]).await, "{}", key);
+		assert_eq!(ok(&target, "TTL", vec![buf(key)]).await, int(-1), "{}", key);
+	}
+	assert_eq!(ok(&target, "GET", vec![buf("string")]).await, buf("value"));
+	assert_eq!(ok(&target, "LRANGE", vec![buf("list"), int(0), int(-1)]).await, bufs(&["a", "b", "c"]));
+	assert_eq!(sorted_members(&target, "set").await, sorted_members(&source, "set").await);
+	assert_eq!(ok(&target, "HLEN", vec![buf("hash")]).await, int(2));
+	assert_eq!(ok(&target, "HGET", vec![buf("hash"), buf("f2")]).await, buf("v2"));
+}
+
+#[tokio::test]
+async fn ttl_is_carried_or_overridden() {
+	let clock = Arc::new(MockClock::new());
+	let storage = Storage::with_clock(clock.clone());
+	ok(&storage, "RPUSH", vec![buf("list"), buf("a")]).await;
+	ok(&storage, "PEXPIRE", vec![buf("list"), int(10_000)]).await;
+	clock.advance(&storage, Duration::from_millis(4_000)).await;
+	let payload = dump(&storage, "list").await;
+
+	// zero keeps the TTL remaining at the moment of DUMP
+	ok(&storage, "RESTORE", vec![buf("kept"), int(0), payload.clone()]).await;
+	assert_eq!(ok(&storage, "PTTL", vec![buf("kept")]).await, int(6_000));
+	ok(&storage, "RESTORE", vec![buf("given"), int(500), payload]).await;
+	assert_eq!(ok(&storage, "PTTL", vec![buf("given")]).await, int(500));
+
+	clock.advance(&storage, Duration::from_millis(6_000)).await;
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("kept"), buf("given")]).await, int(0));
+}
+
+#[tokio::test]
+async fn existing_key_needs_replace() {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("src"), buf("new")]).await;
+	ok(&storage, "RPUSH", vec![buf("dst"), buf("old")]).await;
+	let payload = dump(&storage, "src").await;
+
+	assert_eq!(
+		err(&storage, "RESTORE", vec![buf("dst"), int(0), payload.clone()]).await,
+		"BUSYKEY Target key name already exists",
+	);
+	assert_eq!(ok(&storage, "LRANGE", vec![buf("dst"), int(0), int(-1)]).await, bufs(&["old"]));
+
+	assert_eq!(ok(&storage, "RESTORE", vec![buf("dst"), int(0), payload, buf("REPLACE")]).await, Value::Ok);
+	assert_eq!(ok(&storage, "GET", vec![buf("dst")]).await, buf("new"));
+}
+
+#[tokio::test]
+async fn bad_payloads_and_ttls_are_rejected() {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("src"), buf("v")]).await;
+	assert_eq!(ok(&storage, "DUMP", vec![buf("missing")]).await, Value::Nill);
+	let mut payload = match dump(&storage, "src").await {
+		Value::Buffer(payload) => payload,
+		_ => unreachable!(),
+	};
+
+	let wrong = "ERR DUMP payload version or checksum are wrong";
+	let truncated = payload[..payload.len() - 1].to_vec();
+	assert_eq!(err(&storage, "RESTORE", vec![buf("k"), int(0), Value::Buffer(truncated)]).await, wrong);
+	assert_eq!(err(&storage, "RESTORE", vec![buf("k"), int(0), Value::Buffer(vec![])]).await, wrong);
+	assert_eq!(
+		err(&storage, "RESTORE", vec![buf("k"), int(-1), Value::Buffer(payload.clone())]).await,
+		"ERR Invalid TTL value, must be >= 0",
+	);
+	payload[0] += 1;
+	assert_eq!(err(&storage, "RESTORE", vec![buf("k"), int(0), Value::Buffer(payload)]).await, wrong);
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("k")]).await, int(0));
+}
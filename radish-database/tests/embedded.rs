@@ -0,0 +1,124 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use radish_database::{Storage, StorageError};
+
+use common::*;
+
+fn bytes(items: &[&str]) -> Vec<Vec<u8>> {
+	items.iter().map(|item|item.as_bytes().to_vec()).collect()
+}
+
+#[tokio::test]
+async fn strings() {
+	let storage = Storage::new();
+	assert_eq!(storage.get(b"a").await.unwrap(), None);
+	storage.set(b"a", b"v", None).await.unwrap();
+	assert_eq!(storage.get(b"a").await.unwrap(), Some(b"v".to_vec()));
+	assert_eq!(ok(&storage, "GET", vec![buf("a")]).await, buf("v"));
+
+	assert_eq!(storage.incr_by(b"n", 5).await.unwrap(), 5);
+	assert_eq!(storage.incr_by(b"n", -7).await.unwrap(), -2);
+	assert_eq!(storage.get(b"n").await.unwrap(), Some(b"-2".to_vec()));
+
+	assert!(storage.del(b"a").await.unwrap());
+	assert!(!storage.del(b"a").await.unwrap());
+	assert_eq!(storage.get(b"a").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn expiration() {
+	let storage = Storage::new();
+	assert_eq!(storage.ttl(b"a").await.unwrap(), None);
+	assert!(!storage.expire(b"a", Duration::from_secs(10)).await.unwrap());
+
+	storage.set(b"a", b"v", None).await.unwrap();
+	assert_eq!(storage.ttl(b"a").await.unwrap(), None);
+	assert!(storage.expire(b"a", Duration::from_secs(10)).await.unwrap());
+	let ttl = storage.ttl(b"a").await.unwrap().unwrap();
+	assert!(ttl > Duration::from_secs(9) && ttl <= Duration::from_secs(10), "{:?}", ttl);
+
+	storage.set(b"b", b"v", Some(Duration::from_secs(100))).await.unwrap();
+	assert!(storage.ttl(b"b").await.unwrap().unwrap() > Duration::from_secs(99));
+	storage.set(b"b", b"w", None).await.unwrap();
+	assert_eq!(storage.ttl(b"b").await.unwrap(), None);
+
+	storage.set(b"c", b"v", Some(Duration::from_millis(20))).await.unwrap();
+	tokio::time::delay_for(Duration::from_millis(50)).await;
+	assert_eq!(storage.get(b"c").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn collections() {
+	let storage = Storage::new();
+	assert_eq!(storage.list_push_back(b"list", &[b"a", b"b"]).await.unwrap(), 2);
+	assert_eq!(storage.list_push_back(b"list", &[b"c"]).await.unwrap(), 3);
+	assert_eq!(storage.list_range(b"list", 0, 2).await.unwrap(), bytes(&["a", "b", "c"]));
+	assert_eq!(storage.list_range(b"list", 1, 1).await.unwrap(), bytes(&["b"]));
+	assert!(storage.list_range(b"missing", 0, 10).await.unwrap().is_empty());
+
+	assert_eq!(storage.set_add(b"set", &[b"x", b"y", b"x"]).await.unwrap(), 2);
+	assert_eq!(storage.set_add(b"set", &[b"x"]).await.unwrap(), 0);
+	let mut members = storage.set_members(b"set").await.unwrap();
+	members.sort();
+	assert_eq!(members, bytes(&["x", "y"]));
+
+	storage.hash_set(b"hash", b"f", b"v1").await.unwrap();
+	storage.hash_set(b"hash", b"f", b"v2").await.unwrap();
+	assert_eq!(storage.hash_get(b"hash", b"f").await.unwrap(), Some(b"v2".to_vec()));
+	assert_eq!(storage.hash_get(b"hash", b"g").await.unwrap(), None);
+	assert_eq!(storage.hash_get(b"missing", b"f").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn wrong_type() {
+	let storage = Storage::new();
+	storage.set(b"string", b"v", None).await.unwrap();
+	storage.list_push_back(b"list", &[b"a"]).await.unwrap();
+	storage.set_add(b"set", &[b"a"]).await.unwrap();
+	storage.hash_set(b"hash", b"f", b"v").await.unwrap();
+
+	assert_eq!(storage.get(b"list").await, Err(StorageError::WrongType));
+	assert_eq!(storage.incr_by(b"set", 1).await, Err(StorageError::WrongType));
+	assert_eq!(storage.list_push_back(b"string", &[b"a"]).await, Err(StorageError::WrongType));
+	assert_eq!(storage.list_range(b"hash", 0, 1).await, Err(StorageError::WrongType));
+	assert_eq!(storage.set_add(b"list", &[b"a"]).await, Err(StorageError::WrongType));
+	assert_eq!(storage.set_members(b"string").await, Err(StorageError::WrongType));
+	assert_eq!(storage.hash_set(b"set", b"f", b"v").await, Err(StorageError::WrongType));
+	assert_eq!(storage.hash_get(b"list", b"f").await, Err(StorageError::WrongType));
+	assert!(StorageError::WrongType.to_string().starts_with("WRONGTYPE"));
+
+	// the typed set replaces a value of any type like SET does
+	storage.set(b"list", b"v", None).await.unwrap();
+	assert_eq!(storage.get(b"list").await.unwrap(), Some(b"v".to_vec()));
+}
+
+#[tokio::test]
+async fn other_errors() {
+	let storage = Storage::new();
+	storage.set(b"a", b"v", None).await.unwrap();
+	assert!(matches!(storage.incr_by(b"a", 1).await, Err(StorageError::Failed(_))));
+
+	storage.config().set("read-only", "yes").unwrap();
+	assert_eq!(storage.set(b"a", b"w", None).await, Err(StorageError::ReadOnly));
+	assert_eq!(storage.del(b"a").await, Err(StorageError::ReadOnly));
+	assert_eq!(storage.expire(b"a", Duration::from_secs(1)).await, Err(StorageError::ReadOnly));
+	assert_eq!(storage.get(b"a").await.unwrap(), Some(b"v".to_vec()));
+}
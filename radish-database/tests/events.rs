@@ -0,0 +1,206 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use radish_database::{Storage, KeyEvent, KeyEventKind, KeyEventReceiver, MockClock, Clock};
+
+use common::*;
+
+fn drain(events: &mut KeyEventReceiver) -> Vec<KeyEvent> {
+	std::iter::from_fn(||events.try_recv()).collect()
+}
+
+fn kinds(events: &[KeyEvent]) -> Vec<(String, KeyEventKind)> {
+	events.iter().map(|event|(String::from_utf8_lossy(&event.key).into_owned(), event.event.clone())).collect()
+}
+
+fn expected(events: &[(&str, KeyEventKind)]) -> Vec<(String, KeyEventKind)> {
+	events.iter().map(|(key, kind)|(key.to_string(), kind.clone())).collect()
+}
+
+/// Clock moved without the expiration check, so only lazy expiration removes keys
+struct ManualClock(Mutex<(SystemTime, Instant)>);
+
+impl ManualClock {
+	fn advance(&self, by: Duration) {
+		let mut now = self.0.lock().unwrap();
+		now.0 += by;
+		now.1 += by;
+	}
+}
+
+impl Clock for ManualClock {
+	fn now_system(&self) -> SystemTime {
+		self.0.lock().unwrap().0
+	}
+
+	fn now_monotonic(&self) -> Instant {
+		self.0.lock().unwrap().1
+	}
+}
+
+#[tokio::test]
+async fn events_of_a_workload_in_order() {
+	let storage = Storage::new();
+	let mut events = storage.subscribe_events(None, 100);
+	let started = SystemTime::now();
+
+	ok(&storage, "SET", vec![buf("a"), buf("v")]).await;
+	ok(&storage, "APPEND", vec![buf("a"), buf("w")]).await;
+	ok(&storage, "INCR", vec![buf("n")]).await;
+	ok(&storage, "DECR", vec![buf("n")]).await;
+	ok(&storage, "RPUSH", vec![buf("l"), buf("x"), buf("y")]).await;
+	ok(&storage, "LPOP", vec![buf("l")]).await;
+	ok(&storage, "HSET", vec![buf("h"), buf("f"), buf("v")]).await;
+	ok(&storage, "HDEL", vec![buf("h"), buf("f")]).await;
+	ok(&storage, "SADD", vec![buf("s"), buf("m")]).await;
+	ok(&storage, "SREM", vec![buf("s"), buf("m")]).await;
+	ok(&storage, "RENAME", vec![buf("a"), buf("b")]).await;
+	ok(&storage, "MSET", vec![buf("k1"), buf("v"), buf("k2"), buf("v")]).await;
+	ok(&storage, "DEL", vec![buf("b")]).await;
+
+	let events = drain(&mut events);
+	assert_eq!(kinds(&events), expected(&[
+		("a", KeyEventKind::Set),
+		("a", KeyEventKind::Append),
+		("n", KeyEventKind::Incr),
+		("n", KeyEventKind::Incr),
+		("l", KeyEventKind::Push),
+		("l", KeyEventKind::Pop),
+		("h", KeyEventKind::Hset),
+		("h", KeyEventKind::Hdel),
+		("s", KeyEventKind::Sadd),
+		("s", KeyEventKind::Srem),
+		("a", KeyEventKind::RenameFrom),
+		("b", KeyEventKind::RenameTo),
+		("k1", KeyEventKind::Set),
+		("k2", KeyEventKind::Set),
+		("b", KeyEventKind::Del),
+	]));
+	assert!(events.iter().all(|event|event.at >= started && event.at <= SystemTime::now()));
+	assert!(events.windows(2).all(|pair|pair[0].at <= pair[1].at));
+}
+
+#[tokio::test]
+async fn reads_failures_and_noops_are_silent() {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("a"), buf("v")]).await;
+	let mut events = storage.subscribe_events(None, 100);
+
+	ok(&storage, "GET", vec![buf("a")]).await;
+	ok(&storage, "EXISTS", vec![buf("a")]).await;
+	err(&storage, "LPUSH", vec![buf("a"), buf("x")]).await;
+	ok(&storage, "DEL", vec![buf("missing")]).await;
+	ok(&storage, "SETNX", vec![buf("a"), buf("w")]).await;
+	ok(&storage, "SADD", vec![buf("s"), buf("m")]).await;
+	ok(&storage, "SADD", vec![buf("s"), buf("m")]).await;
+	ok(&storage, "SREM", vec![buf("s"), buf("missing")]).await;
+
+	assert_eq!(kinds(&drain(&mut events)), expected(&[("s", KeyEventKind::Sadd)]));
+}
+
+#[tokio::test]
+async fn glob_filter() {
+	let storage = Storage::new();
+	let mut users = storage.subscribe_events(Some(b"user:*"), 100);
+	let mut all = storage.subscribe_events(None, 100);
+
+	for key in &["user:1", "order:1", "user:2", "users"] {
+		ok(&storage, "SET", vec![buf(key), buf("v")]).await;
+	}
+
+	assert_eq!(kinds(&drain(&mut users)), expected(&[("user:1", KeyEventKind::Set), ("user:2", KeyEventKind::Set)]));
+	assert_eq!(drain(&mut all).len(), 4);
+}
+
+#[tokio::test]
+async fn expired_by_the_active_sweep() {
+	let clock = Arc::new(MockClock::new());
+	let storage = Storage::with_clock(clock.clone());
+	ok(&storage, "SET", vec![buf("a"), buf("v"), buf("EX"), int(10)]).await;
+	ok(&storage, "SET", vec![buf("b"), buf("v")]).await;
+	let mut events = storage.subscribe_events(None, 100);
+
+	ok(&storage, "EXPIRE", vec![buf("b"), int(5)]).await;
+	clock.advance(&storage, Duration::from_secs(6)).await;
+	assert_eq!(kinds(&drain(&mut events)), expected(&[("b", KeyEventKind::Expire), ("b", KeyEventKind::Expired)]));
+
+	clock.advance(&storage, Duration::from_secs(5)).await;
+	assert_eq!(kinds(&drain(&mut events)), expected(&[("a", KeyEventKind::Expired)]));
+}
+
+#[tokio::test]
+async fn expired_on_access() {
+	let clock = Arc::new(ManualClock(Mutex::new((SystemTime::now(), Instant::now()))));
+	let storage = Storage::with_clock(clock.clone());
+	ok(&storage, "SET", vec![buf("a"), buf("v"), buf("EX"), int(10)]).await;
+	let mut events = storage.subscribe_events(None, 100);
+
+	clock.advance(Duration::from_secs(11));
+	assert!(drain(&mut events).is_empty());
+	assert_eq!(ok(&storage, "GET", vec![buf("a")]).await, radish_database::Value::Nill);
+	assert_eq!(kinds(&drain(&mut events)), expected(&[("a", KeyEventKind::Expired)]));
+
+	// the sweep finds the key already gone
+	storage.keys_check_expirations().await;
+	assert!(drain(&mut events).is_empty());
+}
+
+#[tokio::test]
+async fn overflow_drops_the_oldest() {
+	let storage = Storage::new();
+	let mut events = storage.subscribe_events(None, 3);
+
+	for i in 0..5 {
+		ok(&storage, "SET", vec![buf(&format!("k{}", i)), buf("v")]).await;
+	}
+
+	assert_eq!(events.dropped(), 2);
+	let keys: Vec<String> = kinds(&drain(&mut events)).into_iter().map(|(key, _)|key).collect();
+	assert_eq!(keys, vec!["k2", "k3", "k4"]);
+}
+
+#[tokio::test]
+async fn recv_waits_for_the_next_event() {
+	let storage = Storage::new();
+	let mut events = storage.subscribe_events(None, 100);
+	let waiter = tokio::spawn(async move {
+		events.recv().await
+	});
+
+	tokio::time::delay_for(Duration::from_millis(20)).await;
+	ok(&storage, "SET", vec![buf("a"), buf("v")]).await;
+
+	let event = tokio::time::timeout(Duration::from_secs(5), waiter).await.unwrap().unwrap();
+	assert_eq!(kinds(&[event]), expected(&[("a", KeyEventKind::Set)]));
+}
+
+#[tokio::test]
+async fn dropped_receivers_are_unsubscribed() {
+	let storage = Storage::new();
+	let gone = storage.subscribe_events(None, 1);
+	let mut kept = storage.subscribe_events(None, 100);
+	drop(gone);
+
+	ok(&storage, "SET", vec![buf("a"), buf("v")]).await;
+	ok(&storage, "SET", vec![buf("b"), buf("v")]).await;
+	assert_eq!(drain(&mut kept).len(), 2);
+	assert_eq!(kept.dropped(), 0);
+}
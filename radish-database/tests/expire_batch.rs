@@ -0,0 +1,110 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use radish_database::{Storage, MockClock, Clock, Value};
+
+use common::*;
+
+const KEYS: usize = 50_000;
+const BATCH: usize = 1_000;
+
+async fn info_field(storage: &Storage, field: &str) -> i64 {
+	let info = match ok(storage, "INFO", vec![]).await {
+		Value::Buffer(info) => String::from_utf8(info).unwrap(),
+		reply => panic!("unexpected INFO reply {:?}", reply),
+	};
+	let prefix = format!("{}:", field);
+	let line = info.lines().find(|line|line.starts_with(&prefix)).unwrap_or_else(||panic!("no {} in INFO", field));
+	line[prefix.len()..].parse().unwrap()
+}
+
+/// Storage with `KEYS` keys expiring at the same instant, 10 s from now, and the wakeups it requests
+async fn uniform_ttls() -> (Arc<Storage>, Arc<MockClock>, Arc<Mutex<Vec<SystemTime>>>) {
+	let clock = Arc::new(MockClock::new());
+	let mut storage = Storage::with_clock(clock.clone());
+	let wakeups = Arc::new(Mutex::new(Vec::new()));
+	let requested = wakeups.clone();
+	storage.set_expire_awaker(move |at|requested.lock().unwrap().push(at));
+	storage.config().set("active-expire-batch", &BATCH.to_string()).unwrap();
+	for i in 0..KEYS {
+		ok(&storage, "SET", vec![buf(&format!("key:{}", i)), buf("v"), buf("PX"), int(10_000)]).await;
+	}
+	ok(&storage, "SET", vec![buf("persistent"), buf("v")]).await;
+	(Arc::new(storage), clock, wakeups)
+}
+
+#[tokio::test]
+async fn a_cycle_removes_one_batch_and_asks_for_the_next() {
+	let (storage, clock, wakeups) = uniform_ttls().await;
+	assert_eq!(info_field(&storage, "expired_keys_pending").await, 0);
+
+	clock.advance(&storage, Duration::from_millis(10_001)).await;
+	assert_eq!(info_field(&storage, "expired_keys").await, BATCH as i64);
+	assert_eq!(info_field(&storage, "expired_keys_pending").await, (KEYS - BATCH) as i64);
+	assert_eq!(storage.stats().await.keys, (KEYS - BATCH + 1) as u64);
+	// the rest is due, so the next cycle is requested right away
+	let requested = *wakeups.lock().unwrap().last().unwrap();
+	assert!(requested <= clock.now_system(), "{:?} is later than {:?}", requested, clock.now_system());
+
+	let mut cycles = 1;
+	while info_field(&storage, "expired_keys_pending").await > 0 {
+		storage.keys_check_expirations().await;
+		cycles += 1;
+	}
+	assert_eq!(cycles, KEYS / BATCH);
+	assert_eq!(info_field(&storage, "expired_keys").await, KEYS as i64);
+	assert_eq!(storage.stats().await.keys, 1);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn foreground_latency_stays_bounded_while_draining() {
+	let (storage, clock, _) = uniform_ttls().await;
+	clock.advance(&storage, Duration::from_millis(10_001)).await;
+
+	let drainer = {
+		let storage = storage.clone();
+		tokio::spawn(async move {
+			let started = Instant::now();
+			while info_field(&storage, "expired_keys_pending").await > 0 {
+				storage.keys_check_expirations().await;
+			}
+			started.elapsed()
+		})
+	};
+	let mut latencies = Vec::new();
+	let mut i = 0;
+	loop {
+		let started = Instant::now();
+		ok(&storage, "SET", vec![buf(&format!("fg:{}", i)), buf("v")]).await;
+		latencies.push(started.elapsed());
+		i += 1;
+		if info_field(&storage, "expired_keys_pending").await == 0 {
+			break;
+		}
+	}
+	let drained = drainer.await.unwrap();
+
+	let worst = latencies.iter().max().cloned().unwrap_or_default();
+	assert!(latencies.len() > 1, "no SET ran while the keys were drained");
+	// a SET waits for one batch at most, not for the whole backlog
+	assert!(worst * 5 < drained, "the worst SET took {:?} of {:?} spent draining", worst, drained);
+	assert_eq!(storage.stats().await.keys, 1 + latencies.len() as u64);
+}
@@ -0,0 +1,93 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use radish_database::{Storage, MockClock, KeyEventKind, Value};
+
+use common::*;
+
+#[tokio::test]
+async fn setex_rejects_zero_and_negative_ttl() {
+	let storage = Storage::new();
+	for (command, name) in &[("SETEX", "setex"), ("PSETEX", "psetex")] {
+		for ttl in &[0, -1, -100_000] {
+			assert_eq!(
+				err(&storage, command, vec![buf("k"), int(*ttl), buf("v")]).await,
+				format!("ERR invalid expire time in '{}' command", name),
+			);
+			assert_eq!(ok(&storage, "EXISTS", vec![buf("k")]).await, int(0));
+		}
+	}
+	ok(&storage, "SETEX", vec![buf("k"), int(100), buf("v")]).await;
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("k")]).await, int(1));
+}
+
+#[tokio::test]
+async fn expire_with_zero_or_negative_ttl_deletes_now() {
+	let storage = Storage::new();
+	for (command, ttl) in &[("EXPIRE", 0), ("EXPIRE", -5), ("PEXPIRE", 0), ("PEXPIRE", -1)] {
+		ok(&storage, "SET", vec![buf("k"), buf("v")]).await;
+		assert_eq!(ok(&storage, command, vec![buf("k"), int(*ttl)]).await, Value::Bool(true), "{} {}", command, ttl);
+		assert_eq!(ok(&storage, "EXISTS", vec![buf("k")]).await, int(0), "{} {}", command, ttl);
+		assert_eq!(ok(&storage, command, vec![buf("k"), int(*ttl)]).await, Value::Bool(false), "{} {}", command, ttl);
+	}
+}
+
+#[tokio::test]
+async fn expireat_in_the_past_deletes_now() {
+	let storage = Storage::new();
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+	let past = now - Duration::from_secs(10);
+	let cases = [
+		("EXPIREAT", past.as_secs() as i64),
+		("EXPIREAT", 1),
+		("PEXPIREAT", past.as_millis() as i64),
+		("PEXPIREAT", 0),
+	];
+	for (command, at) in &cases {
+		ok(&storage, "RPUSH", vec![buf("k"), buf("v")]).await;
+		assert_eq!(ok(&storage, command, vec![buf("k"), int(*at)]).await, Value::Bool(true), "{} {}", command, at);
+		assert_eq!(ok(&storage, "EXISTS", vec![buf("k")]).await, int(0), "{} {}", command, at);
+	}
+	assert_eq!(ok(&storage, "EXPIREAT", vec![buf("missing"), int(1)]).await, Value::Bool(false));
+}
+
+#[tokio::test]
+async fn deleting_now_forgets_the_queued_deadline() {
+	let clock = Arc::new(MockClock::new());
+	let storage = Storage::with_clock(clock.clone());
+	ok(&storage, "SET", vec![buf("k"), buf("old")]).await;
+	ok(&storage, "EXPIRE", vec![buf("k"), int(100)]).await;
+
+	let mut events = storage.subscribe_events(Some(b"k"), 16);
+	assert_eq!(ok(&storage, "EXPIRE", vec![buf("k"), int(50)]).await, Value::Bool(true));
+	assert_eq!(ok(&storage, "EXPIRE", vec![buf("k"), int(0)]).await, Value::Bool(true));
+	ok(&storage, "SET", vec![buf("k"), buf("new")]).await;
+	// the deadline of the deleted key must not expire the new one
+	clock.advance(&storage, Duration::from_secs(200)).await;
+	assert_eq!(ok(&storage, "GET", vec![buf("k")]).await, buf("new"));
+
+	let mut kinds = Vec::new();
+	while let Some(event) = events.try_recv() {
+		kinds.push(event.event);
+	}
+	assert_eq!(kinds, vec![KeyEventKind::Expire, KeyEventKind::Del, KeyEventKind::Set]);
+}
@@ -0,0 +1,108 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use radish_database::{Storage, MockClock, Clock, Value};
+
+use common::*;
+
+fn storage() -> (Storage, Arc<MockClock>) {
+	let clock = Arc::new(MockClock::new());
+	(Storage::with_clock(clock.clone()), clock)
+}
+
+/// Keys `k1`..`k5` expiring in 10..50 seconds
+async fn staggered(storage: &Storage) {
+	for i in 1..=5 {
+		ok(storage, "SET", vec![buf(&format!("k{}", i)), buf("v"), buf("EX"), int(i * 10)]).await;
+	}
+	ok(storage, "SET", vec![buf("forever"), buf("v")]).await;
+}
+
+async fn expiring(storage: &Storage, within: u64) -> Vec<(String, SystemTime)> {
+	storage.expiring_keys(Duration::from_secs(within)).await
+		.into_iter()
+		.map(|(key, time)|(String::from_utf8(key.to_vec()).unwrap(), time))
+		.collect()
+}
+
+fn names(keys: &[(String, SystemTime)]) -> Vec<&str> {
+	keys.iter().map(|(key, _)|&key[..]).collect()
+}
+
+#[tokio::test]
+async fn windows_of_staggered_deadlines() {
+	let (storage, clock) = storage();
+	assert!(expiring(&storage, 60).await.is_empty());
+	assert_eq!(storage.next_expiration().await, None);
+
+	staggered(&storage).await;
+	let now = clock.now_system();
+	assert!(expiring(&storage, 9).await.is_empty());
+	assert_eq!(names(&expiring(&storage, 10).await), vec!["k1"]);
+	assert_eq!(names(&expiring(&storage, 35).await), vec!["k1", "k2", "k3"]);
+	let all = expiring(&storage, 3600).await;
+	assert_eq!(names(&all), vec!["k1", "k2", "k3", "k4", "k5"]);
+	for (i, (_, time)) in all.iter().enumerate() {
+		assert_eq!(*time, now + Duration::from_secs(10 * (i as u64 + 1)));
+	}
+	assert_eq!(storage.next_expiration().await, Some(now + Duration::from_secs(10)));
+
+	clock.advance(&storage, Duration::from_secs(25)).await;
+	assert_eq!(names(&expiring(&storage, 10).await), vec!["k3"]);
+	assert_eq!(storage.next_expiration().await, Some(now + Duration::from_secs(30)));
+}
+
+#[tokio::test]
+async fn overwritten_ttls_are_reported_once() {
+	let (storage, clock) = storage();
+	staggered(&storage).await;
+	let now = clock.now_system();
+
+	// k1 moves behind k5, k2 loses its TTL, k3 is removed, k4 is replaced by a key without TTL
+	ok(&storage, "EXPIRE", vec![buf("k1"), int(100)]).await;
+	ok(&storage, "PERSIST", vec![buf("k2")]).await;
+	ok(&storage, "DEL", vec![buf("k3")]).await;
+	ok(&storage, "SET", vec![buf("k4"), buf("w")]).await;
+
+	let all = expiring(&storage, 3600).await;
+	assert_eq!(names(&all), vec!["k5", "k1"]);
+	assert_eq!(all[1].1, now + Duration::from_secs(100));
+	assert!(expiring(&storage, 40).await.is_empty());
+	assert_eq!(storage.next_expiration().await, Some(now + Duration::from_secs(50)));
+}
+
+#[tokio::test]
+async fn expiring_command() {
+	let (storage, clock) = storage();
+	staggered(&storage).await;
+	let now = clock.now_system().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+
+	assert_eq!(ok(&storage, "EXPIRING", vec![int(20)]).await, array(vec![
+		array(vec![buf("k1"), int(now + 10_000)]),
+		array(vec![buf("k2"), int(now + 20_000)]),
+	]));
+	assert_eq!(ok(&storage, "EXPIRING", vec![int(5)]).await, Value::Array(Default::default()));
+	assert_eq!(err(&storage, "EXPIRING", vec![int(-1)]).await, "Seconds must not be negative, got -1");
+	match ok(&storage, "EXPIRING", vec![int(i64::MAX)]).await {
+		Value::Array(keys) => assert_eq!(keys.len(), 5),
+		reply => panic!("unexpected EXPIRING reply {:?}", reply),
+	}
+}
@@ -0,0 +1,88 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+const NAN_ERROR: &str = "ERR NaN is not allowed as a set member or hash field";
+
+#[tokio::test]
+async fn set_zero_and_negative_zero_are_one_member() {
+	let storage = Storage::new();
+	assert_eq!(ok(&storage, "SADD", vec![buf("nums"), float(0.0)]).await, int(1));
+	assert_eq!(ok(&storage, "SADD", vec![buf("nums"), float(-0.0)]).await, int(0));
+	assert_eq!(ok(&storage, "SCARD", vec![buf("nums")]).await, int(1));
+	assert_eq!(ok(&storage, "SISMEMBER", vec![buf("nums"), float(-0.0)]).await, int(1));
+	assert_eq!(ok(&storage, "SISMEMBER", vec![buf("nums"), float(0.0)]).await, int(1));
+
+	// the stored member is the canonical 0.0 whichever sign was added first
+	assert_eq!(ok(&storage, "SREM", vec![buf("nums"), float(-0.0)]).await, int(1));
+	assert_eq!(ok(&storage, "SADD", vec![buf("nums"), float(-0.0)]).await, int(1));
+	match ok(&storage, "SMEMBERS", vec![buf("nums")]).await {
+		Value::Array(members) => assert_eq!(members.into_iter().collect::<Vec<_>>(), vec![Value::Float(0f64.to_bits())]),
+		members => panic!("unexpected SMEMBERS {:?}", members),
+	}
+	assert_eq!(ok(&storage, "SREM", vec![buf("nums"), float(0.0)]).await, int(1));
+	assert_eq!(ok(&storage, "SCARD", vec![buf("nums")]).await, int(0));
+}
+
+#[tokio::test]
+async fn set_rejects_nan() {
+	let storage = Storage::new();
+	ok(&storage, "SADD", vec![buf("nums"), float(1.5)]).await;
+	assert_eq!(err(&storage, "SADD", vec![buf("nums"), float(f64::NAN)]).await, NAN_ERROR);
+	assert_eq!(err(&storage, "SADD", vec![buf("nums"), float(2.5), float(f64::NAN)]).await, NAN_ERROR);
+	// nothing of a rejected SADD is added
+	assert_eq!(ok(&storage, "SCARD", vec![buf("nums")]).await, int(1));
+	assert_eq!(ok(&storage, "SISMEMBER", vec![buf("nums"), float(2.5)]).await, int(0));
+	assert_eq!(ok(&storage, "SISMEMBER", vec![buf("nums"), float(f64::NAN)]).await, int(0));
+	assert_eq!(ok(&storage, "SREM", vec![buf("nums"), float(f64::NAN)]).await, int(0));
+	assert_eq!(ok(&storage, "SISMEMBER", vec![buf("nums"), float(1.5)]).await, int(1));
+
+	assert_eq!(err(&storage, "SADD", vec![buf("fresh"), float(f64::NAN)]).await, NAN_ERROR);
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("fresh")]).await, int(0));
+}
+
+#[tokio::test]
+async fn hash_zero_and_negative_zero_are_one_field() {
+	let storage = Storage::new();
+	ok(&storage, "HSET", vec![buf("h"), float(-0.0), buf("negative")]).await;
+	ok(&storage, "HSET", vec![buf("h"), float(0.0), buf("positive")]).await;
+	assert_eq!(ok(&storage, "HLEN", vec![buf("h")]).await, int(1));
+	assert_eq!(ok(&storage, "HGET", vec![buf("h"), float(-0.0)]).await, buf("positive"));
+	assert_eq!(ok(&storage, "HGET", vec![buf("h"), float(0.0)]).await, buf("positive"));
+	assert_eq!(ok(&storage, "HEXISTS", vec![buf("h"), float(-0.0)]).await, Value::Bool(true));
+	assert_eq!(ok(&storage, "HDEL", vec![buf("h"), float(-0.0)]).await, int(1));
+	assert_eq!(ok(&storage, "HLEN", vec![buf("h")]).await, int(0));
+}
+
+#[tokio::test]
+async fn hash_rejects_nan_fields() {
+	let storage = Storage::new();
+	ok(&storage, "HSET", vec![buf("h"), buf("f"), buf("v")]).await;
+	assert_eq!(err(&storage, "HSET", vec![buf("h"), float(f64::NAN), buf("v")]).await, NAN_ERROR);
+	assert_eq!(err(&storage, "HSET", vec![buf("h"), buf("g"), buf("v"), float(f64::NAN), buf("v")]).await, NAN_ERROR);
+	assert_eq!(err(&storage, "HSETNX", vec![buf("h"), float(f64::NAN), buf("v")]).await, NAN_ERROR);
+	assert_eq!(ok(&storage, "HLEN", vec![buf("h")]).await, int(1));
+	assert_eq!(run(&storage, "HGET", vec![buf("h"), float(f64::NAN)]).await, Value::Nill);
+
+	// NaN is fine as a value, only fields are looked up
+	ok(&storage, "HSET", vec![buf("h"), buf("nan"), float(f64::NAN)]).await;
+	assert!(matches!(ok(&storage, "HGET", vec![buf("h"), buf("nan")]).await, Value::Float(n) if f64::from_bits(n).is_nan()));
+}
@@ -0,0 +1,119 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use radish_database::{Storage, Session, Command, CommandSpec, ExecutionHook, Value};
+
+use common::*;
+
+/// Rejects writes to keys starting with "locked"
+struct Guard;
+
+impl ExecutionHook for Guard {
+	fn before(&self, _session: &Session, command: &Command, spec: &CommandSpec) -> Result<(), String> {
+		match command.arguments.front() {
+			Some(Value::Buffer(key)) if spec.is_write() && key.starts_with(b"locked") => Err("ERR key is locked".to_owned()),
+			_ => Ok(()),
+		}
+	}
+}
+
+type Record = (String, Value, Duration);
+
+/// Records every completed command
+#[derive(Clone, Default)]
+struct Recorder {
+	records: Arc<Mutex<Vec<Record>>>,
+}
+
+impl Recorder {
+	fn take(&self) -> Vec<Record> {
+		std::mem::take(&mut *self.records.lock().unwrap())
+	}
+}
+
+impl ExecutionHook for Recorder {
+	fn after(&self, _session: &Session, _command: &Command, spec: &CommandSpec, reply: &Value, elapsed: Duration) {
+		self.records.lock().unwrap().push((spec.name.to_owned(), reply.clone(), elapsed));
+	}
+}
+
+#[tokio::test]
+async fn before_rejects_a_command() {
+	let storage = Storage::new();
+	let recorder = Recorder::default();
+	storage.add_hook(Box::new(Guard));
+	storage.add_hook(Box::new(recorder.clone()));
+
+	assert_eq!(err(&storage, "SET", vec![buf("locked:1"), buf("v")]).await, "ERR key is locked");
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("locked:1")]).await, int(0));
+	// reads of the key and writes of other keys pass
+	assert_eq!(ok(&storage, "GET", vec![buf("locked:1")]).await, Value::Nill);
+	ok(&storage, "SET", vec![buf("free"), buf("v")]).await;
+	// a rejected command reaches neither the handler nor the following hooks
+	let names: Vec<_> = recorder.take().into_iter().map(|(name, _, _)|name).collect();
+	assert_eq!(names, vec!["EXISTS", "GET", "SET"]);
+}
+
+#[tokio::test]
+async fn after_sees_the_reply_and_the_elapsed_time() {
+	let storage = Storage::new();
+	let recorder = Recorder::default();
+	storage.add_hook(Box::new(recorder.clone()));
+
+	ok(&storage, "RPUSH", vec![buf("list"), buf("a"), buf("b")]).await;
+	err(&storage, "INCR", vec![buf("list")]).await;
+	// BLPOP waits for the timeout
+	ok(&storage, "BLPOP", vec![buf("empty"), float(0.2)]).await;
+
+	let records = recorder.take();
+	assert_eq!(records.len(), 3);
+	assert_eq!((&records[0].0[..], &records[0].1), ("RPUSH", &int(2)));
+	assert_eq!(records[1].0, "INCR");
+	assert!(matches!(&records[1].1, Value::Error(_)));
+	assert_eq!((&records[2].0[..], &records[2].1), ("BLPOP", &Value::Nill));
+	assert!(records[2].2 >= Duration::from_millis(200), "{:?}", records[2].2);
+	assert!(records[0].2 < Duration::from_millis(200));
+}
+
+#[tokio::test]
+async fn hooks_are_added_and_removed() {
+	let storage = Storage::new();
+	let first = Recorder::default();
+	let second = Recorder::default();
+	let first_id = storage.add_hook(Box::new(first.clone()));
+	ok(&storage, "PING", vec![]).await;
+	let second_id = storage.add_hook(Box::new(second.clone()));
+	assert_ne!(first_id, second_id);
+	ok(&storage, "PING", vec![]).await;
+	assert_eq!(first.take().len(), 2);
+	assert_eq!(second.take().len(), 1);
+
+	assert!(storage.remove_hook(first_id));
+	assert!(!storage.remove_hook(first_id));
+	ok(&storage, "PING", vec![]).await;
+	assert_eq!(first.take().len(), 0);
+	assert_eq!(second.take().len(), 1);
+
+	let guard = storage.add_hook(Box::new(Guard));
+	err(&storage, "SET", vec![buf("locked"), buf("v")]).await;
+	assert!(storage.remove_hook(guard));
+	ok(&storage, "SET", vec![buf("locked"), buf("v")]).await;
+}
@@ -0,0 +1,139 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::sync::Arc;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+fn tracking() -> Storage {
+	let storage = Storage::new();
+	storage.config().set("hotkeys-tracking", "yes").unwrap();
+	storage
+}
+
+/// Keys with their hits from the HOTKEYS reply
+async fn hotkeys(storage: &Storage, count: Option<i64>) -> Vec<(String, i64)> {
+	let args = count.into_iter().map(int).collect();
+	match ok(storage, "HOTKEYS", args).await {
+		Value::Array(top) => top.into_iter().map(|entry|match entry {
+			Value::Array(pair) => match (&pair[0], &pair[1]) {
+				(Value::Buffer(key), Value::Integer(hits)) => (String::from_utf8(key.clone()).unwrap(), *hits),
+				pair => panic!("unexpected HOTKEYS entry {:?}", pair),
+			},
+			entry => panic!("unexpected HOTKEYS entry {:?}", entry),
+		}).collect(),
+		reply => panic!("unexpected HOTKEYS reply {:?}", reply),
+	}
+}
+
+#[tokio::test]
+async fn hammered_key_tops_the_list() {
+	let storage = tracking();
+	for round in 0..10 {
+		for i in 0..100 {
+			ok(&storage, "SET", vec![buf(&format!("key:{}", i)), buf("v")]).await;
+		}
+		for _ in 0..100 {
+			ok(&storage, "GET", vec![buf("hot")]).await;
+		}
+		ok(&storage, "INCR", vec![buf(&format!("warm:{}", round % 2))]).await;
+	}
+
+	let top = hotkeys(&storage, None).await;
+	assert_eq!(top.len(), 10);
+	assert_eq!(top[0].0, "hot");
+	// the sketch may only overestimate
+	assert!(top[0].1 >= 1000, "{:?}", top);
+	assert!(top[1].1 * 10 <= top[0].1 + 100, "{:?}", top);
+	assert!(top.windows(2).all(|pair|pair[0].1 >= pair[1].1), "{:?}", top);
+}
+
+#[tokio::test]
+async fn every_key_of_a_command_is_counted() {
+	let storage = tracking();
+	for _ in 0..50 {
+		ok(&storage, "MSET", vec![buf("a"), buf("1"), buf("b"), buf("2")]).await;
+		ok(&storage, "MGET", vec![buf("b"), buf("c")]).await;
+	}
+	let top = hotkeys(&storage, Some(3)).await;
+	assert_eq!(top[0], ("b".to_owned(), 100));
+	assert_eq!(top[1..], [("a".to_owned(), 50), ("c".to_owned(), 50)]);
+}
+
+#[tokio::test]
+async fn hot_key_of_concurrent_connections() {
+	let storage = Arc::new(tracking());
+	let tasks: Vec<_> = (0..8).map(|task|{
+		let storage = storage.clone();
+		tokio::spawn(async move {
+			for i in 0..500 {
+				ok(&storage, "INCR", vec![buf("counter")]).await;
+				ok(&storage, "SET", vec![buf(&format!("{}:{}", task, i % 50)), buf("v")]).await;
+			}
+		})
+	}).collect();
+	for task in tasks {
+		task.await.unwrap();
+	}
+	let top = hotkeys(&storage, Some(1)).await;
+	assert_eq!(top[0].0, "counter");
+	// updates of the top list are skipped while another connection holds it
+	assert!(top[0].1 >= 3000, "{:?}", top);
+}
+
+#[tokio::test]
+async fn count_and_reset() {
+	let storage = tracking();
+	for i in 0..40 {
+		for _ in 0..=i {
+			ok(&storage, "GET", vec![buf(&format!("k{}", i))]).await;
+		}
+	}
+	assert_eq!(hotkeys(&storage, Some(3)).await.iter().map(|(key, _)|&key[..]).collect::<Vec<_>>(), vec!["k39", "k38", "k37"]);
+	// the top list keeps 32 keys
+	assert_eq!(hotkeys(&storage, Some(100)).await.len(), 32);
+	assert_eq!(err(&storage, "HOTKEYS", vec![int(0)]).await, "Count must be positive, got 0");
+	assert_eq!(err(&storage, "HOTKEYS", vec![buf("TOP")]).await, "Unexpected argument 'TOP'");
+
+	assert_eq!(ok(&storage, "HOTKEYS", vec![buf("reset")]).await, Value::Ok);
+	assert_eq!(hotkeys(&storage, None).await, vec![]);
+	ok(&storage, "GET", vec![buf("k0")]).await;
+	assert_eq!(hotkeys(&storage, None).await, vec![("k0".to_owned(), 1)]);
+}
+
+#[tokio::test]
+async fn disabled_tracking_counts_nothing() {
+	let storage = Storage::new();
+	for _ in 0..10 {
+		ok(&storage, "GET", vec![buf("a")]).await;
+	}
+	assert_eq!(
+		err(&storage, "HOTKEYS", vec![]).await,
+		"ERR hot keys tracking is disabled, enable it with CONFIG SET hotkeys-tracking yes",
+	);
+
+	ok(&storage, "CONFIG", vec![buf("SET"), buf("hotkeys-tracking"), buf("yes")]).await;
+	assert_eq!(hotkeys(&storage, None).await, vec![]);
+	ok(&storage, "GET", vec![buf("b")]).await;
+	ok(&storage, "CONFIG", vec![buf("SET"), buf("hotkeys-tracking"), buf("no")]).await;
+	ok(&storage, "GET", vec![buf("b")]).await;
+	ok(&storage, "CONFIG", vec![buf("SET"), buf("hotkeys-tracking"), buf("yes")]).await;
+	assert_eq!(hotkeys(&storage, None).await, vec![("b".to_owned(), 1)]);
+}
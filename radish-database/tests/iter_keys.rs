@@ -0,0 +1,81 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures::StreamExt;
+
+use radish_database::{KeyType, Storage};
+
+use common::*;
+
+async fn set(storage: &Storage, key: String) {
+	ok(storage, "SET", vec![buf(&key), buf("v")]).await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn stable_keys_are_yielded_exactly_once() {
+	let storage = Storage::new();
+	for i in 0..20_000 {
+		set(&storage, format!("stable:{}", i)).await;
+		set(&storage, format!("temp:{}", i)).await;
+	}
+
+	let mutator = {
+		let storage = storage.clone();
+		tokio::spawn(async move {
+			for i in 0..20_000 {
+				ok(&storage, "DEL", vec![buf(&format!("temp:{}", i))]).await;
+				set(&storage, format!("new:{}", i)).await;
+			}
+		})
+	};
+	let mut counts = HashMap::new();
+	let mut keys = Box::pin(storage.iter_keys(None));
+	while let Some((key, info)) = keys.next().await {
+		assert_eq!(info.key_type, KeyType::String);
+		*counts.entry(key).or_insert(0) += 1;
+		// give the mutator time to move keys under the cursor
+		if counts.len() % 100 == 0 {
+			tokio::time::delay_for(Duration::from_millis(1)).await;
+		}
+	}
+	mutator.await.unwrap();
+
+	assert!(counts.values().all(|&count|count == 1), "a key is yielded twice");
+	for i in 0..20_000 {
+		assert_eq!(counts.get(format!("stable:{}", i).as_bytes()), Some(&1), "stable:{} is missed", i);
+	}
+}
+
+#[tokio::test]
+async fn pattern_filters_keys() {
+	let storage = Storage::new();
+	for i in 0..500 {
+		set(&storage, format!("user:{}", i)).await;
+		set(&storage, format!("order:{}", i)).await;
+	}
+	ok(&storage, "EXPIRE", vec![buf("user:0"), int(100)]).await;
+
+	let keys: Vec<_> = storage.iter_keys(Some(b"user:*")).collect().await;
+	assert_eq!(keys.len(), 500);
+	assert!(keys.iter().all(|(key, _)|key.starts_with(b"user:")));
+	let (_, info) = keys.iter().find(|(key, _)|key == b"user:0").unwrap();
+	assert!(info.ttl.is_some());
+}
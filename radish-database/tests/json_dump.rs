@@ -0,0 +1,152 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+const BINARY: &[u8] = &[0, 0xff, 0xfe, b'a'];
+
+async fn populate(storage: &Storage) {
+	ok(storage, "SET", vec![buf("string"), buf("value")]).await;
+	ok(storage, "SET", vec![Value::Buffer(BINARY.to_vec()), Value::Buffer(BINARY.to_vec())]).await;
+	ok(storage, "SET", vec![buf("ttl"), buf("v"), buf("EX"), int(1000)]).await;
+	ok(storage, "RPUSH", vec![buf("list"), buf("a"), Value::Buffer(BINARY.to_vec()), buf("c")]).await;
+	ok(storage, "SADD", vec![buf("set"), buf("x"), buf("y"), buf("z")]).await;
+	ok(storage, "HSET", vec![buf("hash"), buf("f1"), buf("v1"), Value::Buffer(BINARY.to_vec()), buf("v2")]).await;
+	ok(storage, "JSON.SET", vec![buf("json"), buf("$"), buf(r#"{"a":[1,2.5,{"b":null}],"c":"d"}"#)]).await;
+	ok(storage, "BF.ADD", vec![buf("bloom"), buf("a")]).await;
+	ok(storage, "TS.ADD", vec![buf("series"), int(1000), float(1.5)]).await;
+	ok(storage, "TS.ADD", vec![buf("series"), int(2000), float(-2.0)]).await;
+}
+
+async fn export(storage: &Storage, pattern: Option<&str>) -> serde_json::Value {
+	let args = pattern.iter().map(|p|buf(p)).collect();
+	match ok(storage, "EXPORT", args).await {
+		Value::Buffer(document) => serde_json::from_slice(&document).unwrap(),
+		reply => panic!("unexpected EXPORT reply {:?}", reply),
+	}
+}
+
+async fn import(storage: &Storage, document: &serde_json::Value, mode: &str) -> Value {
+	run(storage, "IMPORT", vec![buf(&document.to_string()), buf(mode)]).await
+}
+
+/// Entries ordered by key with the members of sets and hashes sorted, for a deep comparison
+fn normalized(document: &serde_json::Value) -> Vec<serde_json::Value> {
+	let mut entries = document["keys"].as_array().unwrap().clone();
+	for entry in &mut entries {
+		if matches!(entry["type"].as_str(), Some("set") | Some("hash")) {
+			entry["value"].as_array_mut().unwrap().sort_by_key(|item|item.to_string());
+		}
+	}
+	entries.sort_by_key(|entry|entry["key"].to_string());
+	entries
+}
+
+fn keys(document: &serde_json::Value) -> Vec<String> {
+	normalized(document).iter().map(|entry|entry["key"].to_string()).collect()
+}
+
+#[tokio::test]
+async fn round_trip_of_every_type() {
+	let source = Storage::new();
+	populate(&source).await;
+	let document = export(&source, None).await;
+	assert_eq!(document["keys"].as_array().unwrap().len(), 9);
+
+	let target = Storage::new();
+	assert_eq!(import(&target, &document, "REPLACE").await, int(9));
+	assert_eq!(normalized(&export(&target, None).await), normalized(&document));
+
+	// the values are restored, not only their JSON
+	assert_eq!(ok(&target, "GET", vec![Value::Buffer(BINARY.to_vec())]).await, Value::Buffer(BINARY.to_vec()));
+	assert_eq!(ok(&target, "LINDEX", vec![buf("list"), int(1)]).await, Value::Buffer(BINARY.to_vec()));
+	assert_eq!(ok(&target, "HGET", vec![buf("hash"), Value::Buffer(BINARY.to_vec())]).await, buf("v2"));
+	assert_eq!(ok(&target, "SCARD", vec![buf("set")]).await, int(3));
+	assert_eq!(ok(&target, "BF.EXISTS", vec![buf("bloom"), buf("a")]).await, Value::Bool(true));
+	assert_eq!(ok(&target, "JSON.GET", vec![buf("json"), buf("$.c")]).await, ok(&source, "JSON.GET", vec![buf("json"), buf("$.c")]).await);
+	let ttl = ok(&target, "TTL", vec![buf("ttl")]).await;
+	assert!(matches!(ttl, Value::Integer(ttl) if ttl > 990 && ttl <= 1000), "{:?}", ttl);
+	assert_eq!(ok(&target, "TTL", vec![buf("string")]).await, int(-1));
+}
+
+#[tokio::test]
+async fn binary_values_use_the_base64_escape() {
+	let storage = Storage::new();
+	populate(&storage).await;
+	let document = export(&storage, None).await;
+	let entries = normalized(&document);
+	let binary = entries.iter().find(|entry|entry["key"].is_object()).unwrap();
+	assert_eq!(binary["key"], serde_json::json!({"base64": "AP/+YQ=="}));
+	assert_eq!(binary["value"], serde_json::json!({"base64": "AP/+YQ=="}));
+	let ttl = entries.iter().find(|entry|entry["key"] == "ttl").unwrap();
+	assert!(ttl["expire_at"].as_u64().unwrap() > 1_600_000_000_000);
+	let list = entries.iter().find(|entry|entry["key"] == "list").unwrap();
+	assert_eq!(list["value"], serde_json::json!(["a", {"base64": "AP/+YQ=="}, "c"]));
+}
+
+#[tokio::test]
+async fn export_of_a_pattern() {
+	let storage = Storage::new();
+	populate(&storage).await;
+	let document = export(&storage, Some("s*")).await;
+	assert_eq!(keys(&document), vec!["\"series\"", "\"set\"", "\"string\""]);
+	assert_eq!(keys(&export(&storage, Some("nothing*")).await), Vec::<String>::new());
+}
+
+#[tokio::test]
+async fn replace_and_merge() {
+	let source = Storage::new();
+	ok(&source, "SET", vec![buf("a"), buf("new")]).await;
+	ok(&source, "SADD", vec![buf("set"), buf("y")]).await;
+	let document = export(&source, None).await;
+
+	let target = Storage::new();
+	ok(&target, "SET", vec![buf("a"), buf("old")]).await;
+	ok(&target, "SET", vec![buf("other"), buf("v")]).await;
+	ok(&target, "SADD", vec![buf("set"), buf("x")]).await;
+	assert_eq!(import(&target, &document, "MERGE").await, int(2));
+	assert_eq!(ok(&target, "GET", vec![buf("a")]).await, buf("new"));
+	assert_eq!(ok(&target, "GET", vec![buf("other")]).await, buf("v"));
+	assert_eq!(ok(&target, "SCARD", vec![buf("set")]).await, int(2));
+
+	assert_eq!(import(&target, &document, "REPLACE").await, int(2));
+	assert_eq!(ok(&target, "EXISTS", vec![buf("other")]).await, int(0));
+	assert_eq!(ok(&target, "SCARD", vec![buf("set")]).await, int(1));
+	assert_eq!(normalized(&export(&target, None).await), normalized(&document));
+}
+
+#[tokio::test]
+async fn invalid_documents_leave_the_dataset_untouched() {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("a"), buf("v")]).await;
+	let before = export(&storage, None).await;
+
+	let merge_conflict = serde_json::json!({"keys": [{"key": "a", "type": "list", "value": ["x"]}]});
+	assert!(matches!(import(&storage, &merge_conflict, "MERGE").await, Value::Error(_)));
+	assert_eq!(err(&storage, "IMPORT", vec![buf("{"), buf("REPLACE")]).await.split(':').next(), Some("Invalid JSON document"));
+	assert_eq!(err(&storage, "IMPORT", vec![buf("{}"), buf("REPLACE")]).await, "Document without 'keys' array");
+	let unknown = serde_json::json!({"keys": [{"key": "b", "type": "zset", "value": []}]});
+	assert_eq!(import(&storage, &unknown, "REPLACE").await, Value::Error("Unknown type 'zset' of key 'b'".to_owned()));
+	let escape = serde_json::json!({"keys": [{"key": {"base64": "!"}, "type": "string", "value": "v"}]});
+	assert!(matches!(import(&storage, &escape, "REPLACE").await, Value::Error(err) if err.starts_with("Invalid base64")));
+	assert_eq!(err(&storage, "IMPORT", vec![buf(r#"{"keys":[]}"#), buf("APPEND")]).await, "Unexpected import mode 'APPEND'");
+
+	assert_eq!(export(&storage, None).await, before);
+}
@@ -0,0 +1,139 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+async fn fill(storage: &Storage, count: usize) {
+	for i in 0..count {
+		ok(storage, "SET", vec![buf(&format!("key:{}", i)), buf("v")]).await;
+	}
+}
+
+fn key_list(reply: Value) -> Vec<Vec<u8>> {
+	match reply {
+		Value::Array(keys) => keys.into_iter().map(|key|match key {
+			Value::Buffer(key) => key,
+			key => panic!("unexpected key {:?}", key),
+		}).collect(),
+		reply => panic!("unexpected KEYS reply {:?}", reply),
+	}
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn no_duplicates_while_keys_are_removed() {
+	let storage = Storage::new();
+	fill(&storage, 50_000).await;
+
+	let remover = {
+		let storage = storage.clone();
+		tokio::spawn(async move {
+			for i in (0..50_000).step_by(2) {
+				ok(&storage, "DEL", vec![buf(&format!("key:{}", i))]).await;
+			}
+		})
+	};
+	for _ in 0..5 {
+		let keys = key_list(ok(&storage, "KEYS", vec![buf("^key:")]).await);
+		let unique: HashSet<_> = keys.iter().collect();
+		assert_eq!(unique.len(), keys.len());
+		// odd keys are never removed
+		let odd = keys.iter().filter(|key|std::str::from_utf8(key).unwrap()[4..].parse::<usize>().unwrap() % 2 == 1).count();
+		assert_eq!(odd, 25_000);
+	}
+	remover.await.unwrap();
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn keys_does_not_block_get() {
+	let storage = Storage::new();
+	fill(&storage, 200_000).await;
+
+	let done = Arc::new(AtomicBool::new(false));
+	let keys = {
+		let storage = storage.clone();
+		let done = done.clone();
+		tokio::spawn(async move {
+			let started = Instant::now();
+			for _ in 0..3 {
+				assert_eq!(key_list(ok(&storage, "KEYS", vec![buf(".*")]).await).len(), 200_000);
+			}
+			done.store(true, Ordering::SeqCst);
+			started.elapsed()
+		})
+	};
+	let mut worst = Duration::from_secs(0);
+	while !done.load(Ordering::SeqCst) {
+		let started = Instant::now();
+		assert_eq!(ok(&storage, "GET", vec![buf("key:7")]).await, buf("v"));
+		worst = std::cmp::max(worst, started.elapsed());
+	}
+	let total = keys.await.unwrap();
+	assert!(worst * 10 < total, "GET waited {:?} while KEYS took {:?}", worst, total);
+}
+
+#[tokio::test]
+async fn randomkey_of_empty_keyspace_is_nil() {
+	let storage = Storage::new();
+	assert_eq!(ok(&storage, "RANDOMKEY", vec![]).await, Value::Nill);
+	ok(&storage, "SET", vec![buf("k"), buf("v")]).await;
+	ok(&storage, "DEL", vec![buf("k")]).await;
+	assert_eq!(ok(&storage, "RANDOMKEY", vec![]).await, Value::Nill);
+}
+
+#[tokio::test]
+async fn randomkey_returns_every_live_key() {
+	let storage = Storage::new();
+	let live: HashSet<Vec<u8>> = (0..5).map(|i|format!("live:{}", i).into_bytes()).collect();
+	for key in &live {
+		ok(&storage, "SET", vec![Value::Buffer(key.clone()), buf("v")]).await;
+	}
+	for i in 0..20 {
+		let key = format!("dead:{}", i);
+		ok(&storage, "SET", vec![buf(&key), buf("v"), buf("PX"), int(10)]).await;
+	}
+	tokio::time::delay_for(Duration::from_millis(50)).await;
+
+	let mut seen = HashSet::new();
+	for _ in 0..500 {
+		match ok(&storage, "RANDOMKEY", vec![]).await {
+			Value::Buffer(key) => {
+				assert!(live.contains(&key), "{:?} is not a live key", String::from_utf8_lossy(&key));
+				seen.insert(key);
+			},
+			reply => panic!("unexpected RANDOMKEY reply {:?}", reply),
+		}
+	}
+	assert_eq!(seen, live);
+}
+
+#[tokio::test]
+async fn randomkey_of_expired_keyspace_is_nil() {
+	let storage = Storage::new();
+	for i in 0..20 {
+		ok(&storage, "SET", vec![buf(&format!("dead:{}", i)), buf("v"), buf("PX"), int(10)]).await;
+	}
+	tokio::time::delay_for(Duration::from_millis(50)).await;
+	assert_eq!(ok(&storage, "RANDOMKEY", vec![]).await, Value::Nill);
+}
@@ -0,0 +1,76 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The storage has no expire awaker here, so expired keys are only removed by the lookups
+
+mod common;
+
+use std::time::Duration;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+async fn expired_key(storage: &Storage, key: &str) {
+	ok(storage, "SET", vec![buf(key), buf("v")]).await;
+	assert_eq!(ok(storage, "PEXPIRE", vec![buf(key), int(10)]).await, Value::Bool(true));
+}
+
+#[tokio::test]
+async fn expired_key_reads_as_missing() {
+	let storage = Storage::new();
+	expired_key(&storage, "k").await;
+	tokio::time::delay_for(Duration::from_millis(50)).await;
+
+	assert_eq!(ok(&storage, "GET", vec![buf("k")]).await, Value::Nill);
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("k")]).await, int(0));
+}
+
+#[tokio::test]
+async fn ttl_of_expired_key_is_minus_two() {
+	let storage = Storage::new();
+	expired_key(&storage, "k").await;
+	tokio::time::delay_for(Duration::from_millis(50)).await;
+
+	assert_eq!(ok(&storage, "TTL", vec![buf("k")]).await, int(-2));
+	assert_eq!(ok(&storage, "PTTL", vec![buf("k")]).await, int(-2));
+}
+
+#[tokio::test]
+async fn keys_scan_and_randomkey_skip_expired_keys() {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("live"), buf("v")]).await;
+	for i in 0..5 {
+		expired_key(&storage, &format!("dead{}", i)).await;
+	}
+	tokio::time::delay_for(Duration::from_millis(50)).await;
+
+	assert_eq!(ok(&storage, "KEYS", vec![buf(".*")]).await, bufs(&["live"]));
+	assert_eq!(ok(&storage, "SCAN", vec![int(0), buf("COUNT"), int(100)]).await, array(vec![int(0), bufs(&["live"])]));
+	assert_eq!(ok(&storage, "RANDOMKEY", vec![]).await, buf("live"));
+}
+
+#[tokio::test]
+async fn writes_recreate_an_expired_key() {
+	let storage = Storage::new();
+	ok(&storage, "RPUSH", vec![buf("l"), buf("old")]).await;
+	ok(&storage, "PEXPIRE", vec![buf("l"), int(10)]).await;
+	tokio::time::delay_for(Duration::from_millis(50)).await;
+
+	assert_eq!(ok(&storage, "RPUSH", vec![buf("l"), buf("new")]).await, int(1));
+	assert_eq!(ok(&storage, "LRANGE", vec![buf("l"), int(0), int(-1)]).await, bufs(&["new"]));
+	assert_eq!(ok(&storage, "TTL", vec![buf("l")]).await, int(-1));
+}
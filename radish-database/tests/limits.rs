@@ -0,0 +1,110 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Storage, StorageError};
+
+use common::*;
+
+async fn configure(storage: &Storage, name: &str, value: usize) {
+	ok(storage, "CONFIG", vec![buf("SET"), buf(name), buf(&value.to_string())]).await;
+}
+
+fn bytes(len: usize) -> String {
+	"x".repeat(len)
+}
+
+#[tokio::test]
+async fn max_key_length() {
+	let storage = Storage::new();
+	configure(&storage, "max-key-length", 8).await;
+
+	ok(&storage, "SET", vec![buf(&bytes(8)), buf("v")]).await;
+	assert_eq!(err(&storage, "SET", vec![buf(&bytes(9)), buf("v")]).await, "Key length 9 exceeds max-key-length 8");
+	// every key position is checked, reads included
+	assert_eq!(err(&storage, "MSET", vec![buf("a"), buf("v"), buf(&bytes(9)), buf("v")]).await, "Key length 9 exceeds max-key-length 8");
+	assert_eq!(err(&storage, "GET", vec![buf(&bytes(9))]).await, "Key length 9 exceeds max-key-length 8");
+	assert_eq!(err(&storage, "SADD", vec![buf(&bytes(9)), buf("m")]).await, "Key length 9 exceeds max-key-length 8");
+	// members are not keys
+	ok(&storage, "SADD", vec![buf("set"), buf(&bytes(100))]).await;
+	assert!(matches!(storage.set(bytes(9).as_bytes(), b"v", None).await, Err(StorageError::Failed(_))));
+}
+
+#[tokio::test]
+async fn max_value_size() {
+	let storage = Storage::new();
+	configure(&storage, "max-value-size", 8).await;
+	let over = "Value size 9 exceeds max-value-size 8";
+
+	ok(&storage, "SET", vec![buf("s"), buf(&bytes(8))]).await;
+	assert_eq!(err(&storage, "SET", vec![buf("s"), buf(&bytes(9))]).await, over);
+
+	ok(&storage, "SET", vec![buf("a"), buf(&bytes(4))]).await;
+	ok(&storage, "APPEND", vec![buf("a"), buf(&bytes(4))]).await;
+	assert_eq!(err(&storage, "APPEND", vec![buf("a"), buf("x")]).await, over);
+	assert_eq!(ok(&storage, "STRLEN", vec![buf("a")]).await, int(8));
+
+	ok(&storage, "SETRANGE", vec![buf("r"), int(4), buf(&bytes(4))]).await;
+	assert_eq!(err(&storage, "SETRANGE", vec![buf("r"), int(5), buf(&bytes(4))]).await, over);
+
+	ok(&storage, "LPUSH", vec![buf("l"), buf(&bytes(8))]).await;
+	assert_eq!(err(&storage, "LPUSH", vec![buf("l"), buf("a"), buf(&bytes(9))]).await, over);
+	// a failed push adds nothing
+	assert_eq!(ok(&storage, "LLEN", vec![buf("l")]).await, int(1));
+
+	ok(&storage, "SADD", vec![buf("set"), buf(&bytes(8))]).await;
+	assert_eq!(err(&storage, "SADD", vec![buf("set"), buf(&bytes(9))]).await, over);
+
+	ok(&storage, "HSET", vec![buf("h"), buf("f"), buf(&bytes(8))]).await;
+	assert_eq!(err(&storage, "HSET", vec![buf("h"), buf("f"), buf(&bytes(9))]).await, over);
+	assert_eq!(err(&storage, "HSET", vec![buf("h"), buf(&bytes(9)), buf("v")]).await, over);
+}
+
+#[tokio::test]
+async fn max_collection_elements() {
+	let storage = Storage::new();
+	configure(&storage, "max-collection-elements", 3).await;
+	let over = "Number of elements 4 exceeds max-collection-elements 3";
+
+	ok(&storage, "RPUSH", vec![buf("l"), buf("a"), buf("b"), buf("c")]).await;
+	assert_eq!(err(&storage, "LPUSH", vec![buf("l"), buf("d")]).await, over);
+	assert_eq!(ok(&storage, "LLEN", vec![buf("l")]).await, int(3));
+
+	ok(&storage, "SADD", vec![buf("s"), buf("a"), buf("b")]).await;
+	// members already present do not count
+	ok(&storage, "SADD", vec![buf("s"), buf("a"), buf("b"), buf("c")]).await;
+	assert_eq!(err(&storage, "SADD", vec![buf("s"), buf("d")]).await, over);
+
+	ok(&storage, "HSET", vec![buf("h"), buf("a"), buf("1"), buf("b"), buf("2"), buf("c"), buf("3")]).await;
+	ok(&storage, "HSET", vec![buf("h"), buf("a"), buf("4")]).await;
+	assert_eq!(err(&storage, "HSET", vec![buf("h"), buf("d"), buf("4")]).await, over);
+}
+
+#[tokio::test]
+async fn config_set_applies_immediately() {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("k"), buf(&bytes(100))]).await;
+
+	configure(&storage, "max-value-size", 10).await;
+	assert_eq!(ok(&storage, "CONFIG", vec![buf("GET"), buf("max-value-size")]).await, bufs(&["max-value-size", "10"]));
+	assert_eq!(err(&storage, "SET", vec![buf("k"), buf(&bytes(11))]).await, "Value size 11 exceeds max-value-size 10");
+	// values stored before stay readable
+	assert_eq!(ok(&storage, "STRLEN", vec![buf("k")]).await, int(100));
+
+	configure(&storage, "max-value-size", 0).await;
+	ok(&storage, "SET", vec![buf("k"), buf(&bytes(11))]).await;
+}
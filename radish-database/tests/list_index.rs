@@ -0,0 +1,98 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+async fn list(storage: &Storage, key: &str, items: &[&str]) {
+	ok(storage, "DEL", vec![buf(key)]).await;
+	let mut args = vec![buf(key)];
+	args.extend(items.iter().map(|i|buf(i)));
+	ok(storage, "RPUSH", args).await;
+}
+
+async fn range(storage: &Storage, key: &str) -> Value {
+	ok(storage, "LRANGE", vec![buf(key), int(0), int(-1)]).await
+}
+
+#[tokio::test]
+async fn lindex_counts_negative_indexes_from_the_tail() {
+	let storage = Storage::new();
+	list(&storage, "l", &["a", "b", "c"]).await;
+	let expected = [(0, buf("a")), (2, buf("c")), (-1, buf("c")), (-3, buf("a")), (3, Value::Nill), (-4, Value::Nill), (i64::MIN, Value::Nill), (i64::MAX, Value::Nill)];
+	for (index, value) in expected.iter().cloned() {
+		assert_eq!(ok(&storage, "LINDEX", vec![buf("l"), int(index)]).await, value, "LINDEX {}", index);
+	}
+	assert_eq!(ok(&storage, "LINDEX", vec![buf("missing"), int(-1)]).await, Value::Nill);
+}
+
+#[tokio::test]
+async fn lset_counts_negative_indexes_from_the_tail() {
+	let storage = Storage::new();
+	list(&storage, "l", &["a", "b", "c"]).await;
+	ok(&storage, "LSET", vec![buf("l"), int(-1), buf("z")]).await;
+	ok(&storage, "LSET", vec![buf("l"), int(-3), buf("x")]).await;
+	ok(&storage, "LSET", vec![buf("l"), int(1), buf("y")]).await;
+	assert_eq!(range(&storage, "l").await, bufs(&["x", "y", "z"]));
+
+	for index in &[3, -4, i64::MIN, i64::MAX] {
+		assert_eq!(err(&storage, "LSET", vec![buf("l"), int(*index), buf("v")]).await, "ERR index out of range");
+	}
+	assert_eq!(range(&storage, "l").await, bufs(&["x", "y", "z"]));
+}
+
+#[tokio::test]
+async fn lrem_from_the_head() {
+	let storage = Storage::new();
+	list(&storage, "l", &["a", "x", "b", "x", "c", "x"]).await;
+	assert_eq!(ok(&storage, "LREM", vec![buf("l"), int(2), buf("x")]).await, int(2));
+	assert_eq!(range(&storage, "l").await, bufs(&["a", "b", "c", "x"]));
+	assert_eq!(ok(&storage, "LREM", vec![buf("l"), int(5), buf("x")]).await, int(1));
+	assert_eq!(range(&storage, "l").await, bufs(&["a", "b", "c"]));
+}
+
+#[tokio::test]
+async fn lrem_from_the_tail() {
+	let storage = Storage::new();
+	list(&storage, "l", &["x", "a", "x", "b", "x"]).await;
+	assert_eq!(ok(&storage, "LREM", vec![buf("l"), int(-2), buf("x")]).await, int(2));
+	assert_eq!(range(&storage, "l").await, bufs(&["x", "a", "b"]));
+	assert_eq!(ok(&storage, "LREM", vec![buf("l"), int(i64::MIN), buf("x")]).await, int(1));
+	assert_eq!(range(&storage, "l").await, bufs(&["a", "b"]));
+}
+
+#[tokio::test]
+async fn lrem_zero_removes_all() {
+	let storage = Storage::new();
+	list(&storage, "l", &["x", "a", "x", "x", "b"]).await;
+	assert_eq!(ok(&storage, "LREM", vec![buf("l"), int(0), buf("x")]).await, int(3));
+	assert_eq!(range(&storage, "l").await, bufs(&["a", "b"]));
+	assert_eq!(ok(&storage, "LREM", vec![buf("l"), int(0), buf("x")]).await, int(0));
+	assert_eq!(ok(&storage, "LREM", vec![buf("missing"), int(0), buf("x")]).await, int(0));
+}
+
+#[tokio::test]
+async fn indexes_must_be_integers() {
+	let storage = Storage::new();
+	list(&storage, "l", &["a"]).await;
+	err(&storage, "LINDEX", vec![buf("l"), buf("first")]).await;
+	err(&storage, "LSET", vec![buf("l"), buf("first"), buf("v")]).await;
+	err(&storage, "LREM", vec![buf("l"), buf("all"), buf("a")]).await;
+	assert_eq!(range(&storage, "l").await, bufs(&["a"]));
+}
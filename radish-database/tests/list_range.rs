@@ -0,0 +1,73 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+mod common;
+
+use radish_database::Storage;
+
+use common::*;
+
+const LIST: [&str; 5] = ["a", "b", "c", "d", "e"];
+
+/// Ranges of LIST and the elements they cover
+const CASES: [(i64, i64, &[&str]); 12] = [
+	(0, -1, &["a", "b", "c", "d", "e"]),
+	(-3, -1, &["c", "d", "e"]),
+	(-100, 100, &["a", "b", "c", "d", "e"]),
+	(1, 2, &["b", "c"]),
+	(-2, 10, &["d", "e"]),
+	(0, 0, &["a"]),
+	(-1, -1, &["e"]),
+	(5, 10, &[]),
+	(100, -1, &[]),
+	(3, 1, &[]),
+	(-1, -3, &[]),
+	(0, -100, &[]),
+];
+
+async fn filled(storage: &Storage, key: &str) {
+	ok(storage, "DEL", vec![buf(key)]).await;
+	ok(storage, "RPUSH", vec![buf(key)].into_iter().chain(LIST.iter().map(|v|buf(v))).collect()).await;
+}
+
+#[tokio::test]
+async fn lrange_normalizes_negative_and_out_of_range_bounds() {
+	let storage = Storage::new();
+	filled(&storage, "list").await;
+	for (start, stop, expected) in &CASES {
+		assert_eq!(
+			ok(&storage, "LRANGE", vec![buf("list"), int(*start), int(*stop)]).await,
+			bufs(expected),
+			"LRANGE {} {}", start, stop,
+		);
+	}
+	assert_eq!(ok(&storage, "LRANGE", vec![buf("missing"), int(0), int(-1)]).await, bufs(&[]));
+}
+
+#[tokio::test]
+async fn ltrim_keeps_what_lrange_returns() {
+	let storage = Storage::new();
+	for (start, stop, expected) in &CASES {
+		filled(&storage, "list").await;
+		ok(&storage, "LTRIM", vec![buf("list"), int(*start), int(*stop)]).await;
+		assert_eq!(
+			ok(&storage, "LRANGE", vec![buf("list"), int(0), int(-1)]).await,
+			bufs(expected),
+			"LTRIM {} {}", start, stop,
+		);
+	}
+}
@@ -0,0 +1,77 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Commands locking keys of different types at once (SORT ... STORE, COPY) running
+//! against each other with the keys in the opposite order
+
+mod common;
+
+use std::time::Duration;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+const ROUNDS: usize = 500;
+
+fn sorted() -> Value {
+	array((1..=50).map(|i|buf(&i.to_string())).collect())
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn overlapping_sort_store_and_copy() {
+	let storage = Storage::new();
+	let members: Vec<_> = (1..=50).rev().map(|i|buf(&i.to_string())).collect();
+	ok(&storage, "SADD", [vec![buf("a")], members].concat()).await;
+
+	// "a" is read and "b" written by SORT, the other way around by COPY;
+	// "a" turns from a set into a list once COPY finds "b"
+	let sort = {
+		let storage = storage.clone();
+		tokio::spawn(async move {
+			for _ in 0..ROUNDS {
+				assert_eq!(ok(&storage, "SORT", vec![buf("a"), buf("STORE"), buf("b")]).await, int(50));
+			}
+		})
+	};
+	let copy = {
+		let storage = storage.clone();
+		tokio::spawn(async move {
+			for _ in 0..ROUNDS {
+				ok(&storage, "COPY", vec![buf("b"), buf("a"), buf("REPLACE")]).await;
+			}
+		})
+	};
+	// a reader never sees a destination half written
+	let reader = {
+		let storage = storage.clone();
+		tokio::spawn(async move {
+			for _ in 0..ROUNDS {
+				match ok(&storage, "LRANGE", vec![buf("b"), int(0), int(-1)]).await {
+					Value::Array(values) if values.is_empty() => (),
+					values => assert_eq!(values, sorted()),
+				}
+			}
+		})
+	};
+	let all = futures::future::try_join3(sort, copy, reader);
+	tokio::time::timeout(Duration::from_secs(60), all).await
+		.expect("deadlock between SORT STORE and COPY")
+		.unwrap();
+
+	assert_eq!(ok(&storage, "SORT", vec![buf("a")]).await, sorted());
+	assert_eq!(ok(&storage, "LRANGE", vec![buf("b"), int(0), int(-1)]).await, sorted());
+}
@@ -0,0 +1,183 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+fn blob(len: usize) -> Value {
+	Value::Buffer(vec![b'x'; len])
+}
+
+async fn usage(storage: &Storage, key: &str) -> usize {
+	match ok(storage, "MEMORY", vec![buf("USAGE"), buf(key)]).await {
+		Value::Integer(size) => size as usize,
+		size => panic!("unexpected MEMORY USAGE {:?}", size),
+	}
+}
+
+/// dataset.bytes and compactions.count of MEMORY STATS
+async fn stats(storage: &Storage) -> (i64, i64) {
+	match ok(storage, "MEMORY", vec![buf("STATS")]).await {
+		Value::Array(stats) => {
+			assert_eq!(stats[0], buf("dataset.bytes"));
+			assert_eq!(stats[4], buf("compactions.count"));
+			match (&stats[1], &stats[5]) {
+				(Value::Integer(bytes), Value::Integer(compactions)) => (*bytes, *compactions),
+				_ => panic!("unexpected MEMORY STATS {:?}", stats),
+			}
+		},
+		stats => panic!("unexpected MEMORY STATS {:?}", stats),
+	}
+}
+
+/// The tracked total must stay within 10% of an exact recount
+async fn assert_consistent(storage: &Storage) {
+	let tracked = storage.memory_used();
+	let (_, counted) = storage.memory_recount().await;
+	let drift = (tracked as f64 - counted as f64).abs();
+	assert!(drift <= counted as f64 * 0.1, "tracked {} vs counted {}", tracked, counted);
+}
+
+#[tokio::test]
+async fn strings_insert_overwrite_delete() {
+	let storage = Storage::new();
+	assert_eq!(storage.memory_used(), 0);
+
+	ok(&storage, "SET", vec![buf("a"), blob(10_000)]).await;
+	let inserted = storage.memory_used();
+	assert!((10_000..10_000 + 512).contains(&inserted), "{}", inserted);
+	assert_eq!(inserted, usage(&storage, "a").await);
+
+	ok(&storage, "SET", vec![buf("a"), blob(1_000)]).await;
+	let overwritten = storage.memory_used();
+	assert!(inserted - overwritten >= 9_000 && inserted - overwritten < 9_000 + 512, "{} -> {}", inserted, overwritten);
+
+	ok(&storage, "APPEND", vec![buf("a"), blob(5_000)]).await;
+	assert!(storage.memory_used() >= overwritten + 5_000);
+	assert_consistent(&storage).await;
+
+	ok(&storage, "SET", vec![buf("b"), blob(2_000)]).await;
+	ok(&storage, "DEL", vec![buf("a")]).await;
+	assert_eq!(storage.memory_used(), usage(&storage, "b").await);
+	ok(&storage, "DEL", vec![buf("b")]).await;
+	assert_eq!(storage.memory_used(), 0);
+}
+
+#[tokio::test]
+async fn collections_grow_and_shrink() {
+	let storage = Storage::new();
+
+	for i in 0..1000 {
+		ok(&storage, "RPUSH", vec![buf("list"), blob(100)]).await;
+		ok(&storage, "SADD", vec![buf("set"), buf(&format!("{:0>100}", i))]).await;
+		ok(&storage, "HSET", vec![buf("hash"), buf(&format!("field:{}", i)), blob(100)]).await;
+	}
+	let full = storage.memory_used();
+	assert!(full >= 3 * 100_000, "{}", full);
+	assert_consistent(&storage).await;
+
+	for _ in 0..500 {
+		ok(&storage, "LPOP", vec![buf("list")]).await;
+	}
+	let popped = storage.memory_used();
+	// the buffers are freed, the slots stay allocated until MEMORY PURGE
+	assert!(full - popped >= 45_000, "{} -> {}", full, popped);
+
+	for i in 0..500 {
+		ok(&storage, "SREM", vec![buf("set"), buf(&format!("{:0>100}", i))]).await;
+		ok(&storage, "HDEL", vec![buf("hash"), buf(&format!("field:{}", i))]).await;
+	}
+	let removed = storage.memory_used();
+	assert!(popped - removed >= 100_000, "{} -> {}", popped, removed);
+	assert_consistent(&storage).await;
+
+	ok(&storage, "DEL", vec![buf("list"), buf("set"), buf("hash")]).await;
+	assert_eq!(storage.memory_used(), 0);
+}
+
+#[tokio::test]
+async fn memory_is_reported_by_info_and_stats() {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("a"), blob(4_096)]).await;
+	let used = storage.memory_used();
+
+	let info = match ok(&storage, "INFO", vec![buf("memory")]).await {
+		Value::Buffer(info) => String::from_utf8(info).unwrap(),
+		info => panic!("unexpected INFO reply {:?}", info),
+	};
+	assert!(info.lines().any(|line|line == format!("used_memory:{}", used)), "{}", info);
+
+	match ok(&storage, "MEMORY", vec![buf("STATS")]).await {
+		Value::Array(stats) => {
+			assert_eq!(stats[0], buf("dataset.bytes"));
+			assert_eq!(stats[1], int(used as i64));
+		},
+		stats => panic!("unexpected MEMORY STATS {:?}", stats),
+	}
+	assert_eq!(run(&storage, "MEMORY", vec![buf("USAGE"), buf("missing")]).await, Value::Nill);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn recount_runs_alongside_writes() {
+	let storage = Storage::new();
+	for i in 0..20_000 {
+		ok(&storage, "SET", vec![buf(&format!("key:{}", i)), blob(64)]).await;
+	}
+	let writer = {
+		let storage = storage.clone();
+		tokio::spawn(async move {
+			for i in 0..2_000 {
+				ok(&storage, "SET", vec![buf(&format!("new:{}", i)), blob(64)]).await;
+				ok(&storage, "DEL", vec![buf(&format!("key:{}", i))]).await;
+			}
+		})
+	};
+	for _ in 0..5 {
+		storage.memory_recount().await;
+	}
+	tokio::time::timeout(std::time::Duration::from_secs(30), writer).await.unwrap().unwrap();
+	assert_consistent(&storage).await;
+}
+
+#[tokio::test]
+async fn purge_shrinks_sparse_containers() {
+	let storage = Storage::new();
+	for i in 0..10_000 {
+		ok(&storage, "HSET", vec![buf("h"), buf(&format!("field:{}", i)), blob(16)]).await;
+	}
+	for i in 100..10_000 {
+		ok(&storage, "HDEL", vec![buf("h"), buf(&format!("field:{}", i))]).await;
+	}
+	let (bytes, compactions) = stats(&storage).await;
+	assert_eq!(compactions, 0);
+
+	let purged = match ok(&storage, "MEMORY", vec![buf("PURGE")]).await {
+		Value::Integer(purged) => purged,
+		purged => panic!("unexpected MEMORY PURGE {:?}", purged),
+	};
+	assert!(purged >= 1, "{}", purged);
+	let (purged_bytes, purged_compactions) = stats(&storage).await;
+	assert_eq!(purged_compactions, purged);
+	assert!(purged_bytes < bytes / 2, "{} -> {}", bytes, purged_bytes);
+	assert_consistent(&storage).await;
+
+	// nothing is sparse any more
+	assert_eq!(ok(&storage, "MEMORY", vec![buf("PURGE")]).await, int(0));
+	assert_eq!(stats(&storage).await, (purged_bytes, purged_compactions));
+}
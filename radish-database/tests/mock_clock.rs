@@ -0,0 +1,119 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Expirations driven by `MockClock`: time moves only by `advance`, which also runs the
+//! expiration check, so the tests neither sleep nor race the deadlines.
+
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use radish_database::{Storage, MockClock, Clock, KeyEventKind, Value};
+
+use common::*;
+
+fn storage() -> (Storage, Arc<MockClock>) {
+	let clock = Arc::new(MockClock::new());
+	(Storage::with_clock(clock.clone()), clock)
+}
+
+#[tokio::test]
+async fn ttl_counts_down_with_the_clock() {
+	let (storage, clock) = storage();
+	ok(&storage, "SET", vec![buf("ex"), buf("v"), buf("EX"), int(10)]).await;
+	ok(&storage, "SETEX", vec![buf("setex"), int(10), buf("v")]).await;
+	ok(&storage, "PSETEX", vec![buf("psetex"), int(10_000), buf("v")]).await;
+	ok(&storage, "SET", vec![buf("expire"), buf("v")]).await;
+	assert_eq!(ok(&storage, "PEXPIRE", vec![buf("expire"), int(10_000)]).await, Value::Bool(true));
+
+	for key in &["ex", "setex", "psetex", "expire"] {
+		assert_eq!(ok(&storage, "PTTL", vec![buf(key)]).await, int(10_000), "{}", key);
+	}
+	clock.advance(&storage, Duration::from_millis(2_500)).await;
+	for key in &["ex", "setex", "psetex", "expire"] {
+		assert_eq!(ok(&storage, "PTTL", vec![buf(key)]).await, int(7_500), "{}", key);
+		assert_eq!(ok(&storage, "TTL", vec![buf(key)]).await, int(7), "{}", key);
+	}
+}
+
+#[tokio::test]
+async fn keys_expire_exactly_at_the_deadline() {
+	let (storage, clock) = storage();
+	ok(&storage, "SET", vec![buf("k"), buf("v")]).await;
+	ok(&storage, "RPUSH", vec![buf("list"), buf("v")]).await;
+	ok(&storage, "PEXPIRE", vec![buf("k"), int(1_000)]).await;
+	ok(&storage, "PEXPIRE", vec![buf("list"), int(1_001)]).await;
+
+	clock.advance(&storage, Duration::from_millis(999)).await;
+	assert_eq!(ok(&storage, "GET", vec![buf("k")]).await, buf("v"));
+	assert_eq!(ok(&storage, "PTTL", vec![buf("k")]).await, int(1));
+
+	clock.advance(&storage, Duration::from_millis(1)).await;
+	assert_eq!(ok(&storage, "GET", vec![buf("k")]).await, Value::Nill);
+	assert_eq!(ok(&storage, "TTL", vec![buf("k")]).await, int(-2));
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("list")]).await, int(1));
+
+	clock.advance(&storage, Duration::from_millis(1)).await;
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("list")]).await, int(0));
+}
+
+#[tokio::test]
+async fn expireat_follows_the_wall_clock_of_the_mock() {
+	let (storage, clock) = storage();
+	let now = clock.now_system().duration_since(UNIX_EPOCH).unwrap();
+	ok(&storage, "SET", vec![buf("k"), buf("v")]).await;
+	let at = (now + Duration::from_secs(60)).as_millis() as i64;
+	assert_eq!(ok(&storage, "PEXPIREAT", vec![buf("k"), int(at)]).await, Value::Bool(true));
+	// the wall clock of the mock is finer than the milliseconds of the deadline
+	match ok(&storage, "PTTL", vec![buf("k")]).await {
+		Value::Integer(ttl) => assert!((59_999..=60_000).contains(&ttl), "{}", ttl),
+		ttl => panic!("unexpected PTTL {:?}", ttl),
+	}
+
+	clock.advance(&storage, Duration::from_secs(59)).await;
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("k")]).await, int(1));
+	clock.advance(&storage, Duration::from_secs(1)).await;
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("k")]).await, int(0));
+}
+
+#[tokio::test]
+async fn persist_and_overwrite_cancel_the_deadline() {
+	let (storage, clock) = storage();
+	ok(&storage, "SETEX", vec![buf("persisted"), int(10), buf("v")]).await;
+	ok(&storage, "SETEX", vec![buf("overwritten"), int(10), buf("v")]).await;
+	assert_eq!(ok(&storage, "PERSIST", vec![buf("persisted")]).await, Value::Bool(true));
+	ok(&storage, "SET", vec![buf("overwritten"), buf("w")]).await;
+
+	clock.advance(&storage, Duration::from_secs(3_600)).await;
+	assert_eq!(ok(&storage, "GET", vec![buf("persisted")]).await, buf("v"));
+	assert_eq!(ok(&storage, "GET", vec![buf("overwritten")]).await, buf("w"));
+	assert_eq!(ok(&storage, "TTL", vec![buf("overwritten")]).await, int(-1));
+}
+
+#[tokio::test]
+async fn expiration_check_publishes_expired() {
+	let (storage, clock) = storage();
+	ok(&storage, "SETEX", vec![buf("k"), int(1), buf("v")]).await;
+	let mut events = storage.subscribe_events(None, 16);
+
+	clock.advance(&storage, Duration::from_secs(1)).await;
+	let event = events.try_recv().expect("no event after the deadline");
+	assert_eq!(event.event, KeyEventKind::Expired);
+	assert_eq!(&event.key[..], b"k");
+	assert!(events.try_recv().is_none());
+}
@@ -0,0 +1,199 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use radish_database::{NamespacedStorage, Storage, Session, Value};
+
+use common::*;
+
+async fn exec(ns: &NamespacedStorage, name: &str, arguments: Vec<Value>) -> Value {
+	ns.execute(&mut Session::default(), command(name, arguments)).await
+}
+
+async fn exec_ok(ns: &NamespacedStorage, name: &str, arguments: Vec<Value>) -> Value {
+	match exec(ns, name, arguments).await {
+		Value::Error(err) => panic!("{} failed: {}", name, err),
+		value => value,
+	}
+}
+
+fn key_set(reply: Value) -> HashSet<Vec<u8>> {
+	match reply {
+		Value::Array(keys) => keys.into_iter().map(|key|match key {
+			Value::Buffer(key) => key,
+			key => panic!("unexpected key {:?}", key),
+		}).collect(),
+		reply => panic!("unexpected reply {:?}", reply),
+	}
+}
+
+/// Every key seen by SCAN in the namespace, walking the cursor to the end
+async fn scan_all(ns: &NamespacedStorage, options: Vec<Value>) -> HashSet<Vec<u8>> {
+	let mut keys = HashSet::new();
+	let mut cursor = 0;
+	loop {
+		let mut arguments = vec![int(cursor), buf("COUNT"), int(3)];
+		arguments.extend(options.iter().cloned());
+		let (next, found) = match exec_ok(ns, "SCAN", arguments).await {
+			Value::Array(mut reply) => match (reply.pop_front(), reply.pop_front()) {
+				(Some(Value::Integer(next)), Some(found)) => (next, found),
+				reply => panic!("unexpected SCAN reply {:?}", reply),
+			},
+			reply => panic!("unexpected SCAN reply {:?}", reply),
+		};
+		keys.extend(key_set(found));
+		if next == 0 {
+			return keys;
+		}
+		cursor = next;
+	}
+}
+
+fn names(keys: &[&str]) -> HashSet<Vec<u8>> {
+	keys.iter().map(|key|key.as_bytes().to_vec()).collect()
+}
+
+const WORKLOAD_KEYS: &[&str] = &["str", "counter", "list", "sorted", "left", "right", "common", "hash", "m1", "m2", "renamed"];
+
+/// The same commands with the same key names; only the values tell the tenants apart
+async fn workload(ns: &NamespacedStorage, tenant: &str) {
+	exec_ok(ns, "SET", vec![buf("str"), buf(tenant)]).await;
+	exec_ok(ns, "INCRBY", vec![buf("counter"), int(5)]).await;
+	exec_ok(ns, "RPUSH", vec![buf("list"), buf("3"), buf("1"), buf("2")]).await;
+	exec_ok(ns, "SORT", vec![buf("list"), buf("STORE"), buf("sorted")]).await;
+	exec_ok(ns, "SADD", vec![buf("left"), buf("x"), buf(tenant)]).await;
+	exec_ok(ns, "SADD", vec![buf("right"), buf(tenant), buf("y")]).await;
+	exec_ok(ns, "SINTERSTORE", vec![buf("common"), buf("left"), buf("right")]).await;
+	exec_ok(ns, "HSET", vec![buf("hash"), buf("owner"), buf(tenant)]).await;
+	exec_ok(ns, "MSET", vec![buf("m1"), buf(tenant), buf("m2"), buf(tenant)]).await;
+	exec_ok(ns, "SET", vec![buf("old"), buf(tenant)]).await;
+	exec_ok(ns, "RENAME", vec![buf("old"), buf("renamed")]).await;
+}
+
+async fn assert_workload(ns: &NamespacedStorage, tenant: &str) {
+	assert_eq!(exec_ok(ns, "GET", vec![buf("str")]).await, buf(tenant));
+	assert_eq!(exec_ok(ns, "GET", vec![buf("counter")]).await, buf("5"));
+	assert_eq!(exec_ok(ns, "LRANGE", vec![buf("sorted"), int(0), int(-1)]).await, bufs(&["1", "2", "3"]));
+	assert_eq!(exec_ok(ns, "SMEMBERS", vec![buf("common")]).await, bufs(&[tenant]));
+	assert_eq!(exec_ok(ns, "HGET", vec![buf("hash"), buf("owner")]).await, buf(tenant));
+	assert_eq!(exec_ok(ns, "MGET", vec![buf("m1"), buf("m2"), buf("old")]).await, array(vec![buf(tenant), buf(tenant), Value::Nill]));
+	assert_eq!(exec_ok(ns, "GET", vec![buf("renamed")]).await, buf(tenant));
+	assert_eq!(ns.dbsize().await, WORKLOAD_KEYS.len());
+	assert_eq!(key_set(exec_ok(ns, "KEYS", vec![buf(".*")]).await), names(WORKLOAD_KEYS));
+	assert_eq!(scan_all(ns, vec![]).await, names(WORKLOAD_KEYS));
+}
+
+#[tokio::test]
+async fn identical_workloads_are_isolated() {
+	let storage = Storage::new();
+	let a = storage.namespace(b"tenant:a:");
+	let b = storage.namespace(b"tenant:b:");
+	workload(&a, "a").await;
+	workload(&b, "b").await;
+	assert_workload(&a, "a").await;
+	assert_workload(&b, "b").await;
+
+	// the underlying storage holds both copies under the prefixes
+	let all = key_set(ok(&storage, "KEYS", vec![buf(".*")]).await);
+	assert_eq!(all.len(), 2 * WORKLOAD_KEYS.len());
+	assert!(all.iter().all(|key|key.starts_with(b"tenant:a:") || key.starts_with(b"tenant:b:")), "{:?}", all);
+	assert_eq!(ok(&storage, "GET", vec![buf("tenant:b:str")]).await, buf("b"));
+}
+
+#[tokio::test]
+async fn keys_of_other_namespaces_are_unreachable() {
+	let storage = Storage::new();
+	let a = storage.namespace(b"a:");
+	let b = storage.namespace(b"b:");
+	ok(&storage, "SET", vec![buf("b:secret"), buf("1")]).await;
+	ok(&storage, "SET", vec![buf("global"), buf("1")]).await;
+
+	// a key naming another namespace stays inside the own one
+	assert_eq!(exec_ok(&a, "GET", vec![buf("b:secret")]).await, Value::Nill);
+	assert_eq!(exec_ok(&a, "EXISTS", vec![buf("global"), buf("b:secret")]).await, int(0));
+	exec_ok(&a, "SET", vec![buf("b:secret"), buf("2")]).await;
+	assert_eq!(exec_ok(&b, "GET", vec![buf("secret")]).await, buf("1"));
+	assert_eq!(ok(&storage, "GET", vec![buf("a:b:secret")]).await, buf("2"));
+
+	// nor the patterns or the type filter of the keyspace commands see outside
+	assert_eq!(key_set(exec_ok(&a, "KEYS", vec![buf("secret|global")]).await), names(&["b:secret"]));
+	assert_eq!(scan_all(&a, vec![buf("MATCH"), buf("^global$")]).await, names(&[]));
+	assert_eq!(scan_all(&b, vec![buf("TYPE"), buf("string")]).await, names(&["secret"]));
+
+	// the typed API is confined the same way
+	assert_eq!(a.get(b"secret").await.unwrap(), None);
+	assert_eq!(b.get(b"secret").await.unwrap(), Some(b"1".to_vec()));
+	assert!(!a.del(b"global").await.unwrap());
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("global")]).await, int(1));
+}
+
+#[tokio::test]
+async fn keyless_commands_are_refused() {
+	let storage = Storage::new();
+	let ns = storage.namespace(b"ns:");
+	for (name, arguments) in [
+		("INFO", vec![]),
+		("CONFIG", vec![buf("GET"), buf("maxmemory")]),
+		("SAVE", vec![]),
+		("RANDOMKEY", vec![]),
+		("SELECT", vec![int(1)]),
+	] {
+		match exec(&ns, name, arguments).await {
+			Value::Error(err) => assert_eq!(err, format!("ERR '{}' command is not available in a namespace", name.to_lowercase())),
+			reply => panic!("{} is allowed in a namespace: {:?}", name, reply),
+		}
+	}
+	assert_eq!(exec_ok(&ns, "PING", vec![]).await, exec_ok(&storage.namespace(b"other:"), "PING", vec![]).await);
+}
+
+#[tokio::test]
+async fn flush_and_dbsize_are_scoped() {
+	let storage = Storage::new();
+	let a = storage.namespace(b"a:");
+	let b = storage.namespace(b"b:");
+	for ns in &[&a, &b] {
+		for i in 0..10 {
+			ns.set(format!("key:{}", i).as_bytes(), b"v", None).await.unwrap();
+		}
+	}
+	ok(&storage, "SET", vec![buf("outside"), buf("v")]).await;
+	assert_eq!(a.dbsize().await, 10);
+	assert_eq!(b.dbsize().await, 10);
+
+	assert_eq!(a.flush().await.unwrap(), 10);
+	assert_eq!(a.dbsize().await, 0);
+	assert_eq!(scan_all(&a, vec![]).await, names(&[]));
+	assert_eq!(b.dbsize().await, 10);
+	assert_eq!(scan_all(&b, vec![]).await.len(), 10);
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("outside")]).await, int(1));
+	assert_eq!(a.flush().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn replies_naming_keys_are_stripped() {
+	let storage = Storage::new();
+	let ns = storage.namespace(b"ns:");
+	exec_ok(&ns, "RPUSH", vec![buf("queue"), buf("job")]).await;
+	assert_eq!(exec_ok(&ns, "BLPOP", vec![buf("empty"), buf("queue"), int(1)]).await, bufs(&["queue", "job"]));
+
+	ns.set(b"session", b"v", Some(Duration::from_secs(100))).await.unwrap();
+	assert!(ns.ttl(b"session").await.unwrap().is_some());
+	assert!(storage.ttl(b"ns:session").await.unwrap().is_some());
+}
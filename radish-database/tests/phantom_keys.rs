@@ -0,0 +1,116 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Read-only commands see a missing key as an empty value and leave the keyspace as it was
+
+mod common;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+fn empty() -> Value {
+	array(vec![])
+}
+
+fn reads() -> Vec<(&'static str, Vec<Value>, Value)> {
+	vec![
+		("GET", vec![buf("missing")], Value::Nill),
+		("STRLEN", vec![buf("missing")], int(0)),
+		("GETRANGE", vec![buf("missing"), int(0), int(-1)], Value::Buffer(vec![])),
+		("BITCOUNT", vec![buf("missing")], int(0)),
+		("GETBIT", vec![buf("missing"), int(7)], Value::Bool(false)),
+		("MGET", vec![buf("missing"), buf("other")], array(vec![Value::Nill, Value::Nill])),
+		("LLEN", vec![buf("missing")], int(0)),
+		("LINDEX", vec![buf("missing"), int(0)], Value::Nill),
+		("LRANGE", vec![buf("missing"), int(0), int(-1)], empty()),
+		("SCARD", vec![buf("missing")], int(0)),
+		("SMEMBERS", vec![buf("missing")], empty()),
+		("SISMEMBER", vec![buf("missing"), buf("m")], int(0)),
+		("SRANDMEMBER", vec![buf("missing")], Value::Nill),
+		("SDIFF", vec![buf("missing"), buf("other")], empty()),
+		("SINTER", vec![buf("missing"), buf("other")], empty()),
+		("SUNION", vec![buf("missing"), buf("other")], empty()),
+		("HGET", vec![buf("missing"), buf("f")], Value::Nill),
+		("HLEN", vec![buf("missing")], int(0)),
+		("HGETALL", vec![buf("missing")], empty()),
+		("HEXISTS", vec![buf("missing"), buf("f")], Value::Bool(false)),
+		("HKEYS", vec![buf("missing")], empty()),
+		("HVALUES", vec![buf("missing")], empty()),
+		("HSTRLEN", vec![buf("missing"), buf("f")], Value::Nill),
+		("HMGET", vec![buf("missing"), buf("f")], array(vec![Value::Nill])),
+	]
+}
+
+#[tokio::test]
+async fn reads_of_missing_keys_create_nothing() {
+	let storage = Storage::new();
+	for (name, arguments, expected) in reads() {
+		assert_eq!(ok(&storage, name, arguments).await, expected, "{}", name);
+		assert_eq!(ok(&storage, "EXISTS", vec![buf("missing"), buf("other")]).await, int(0), "{} created a key", name);
+	}
+	assert_eq!(ok(&storage, "KEYS", vec![buf(".*")]).await, empty());
+	assert_eq!(ok(&storage, "SCAN", vec![int(0)]).await, array(vec![int(0), empty()]));
+	assert_eq!(ok(&storage, "RANDOMKEY", vec![]).await, Value::Nill);
+}
+
+#[tokio::test]
+async fn reads_of_wrong_types_still_fail() {
+	let storage = Storage::new();
+	ok(&storage, "SADD", vec![buf("missing"), buf("m")]).await;
+	for (name, arguments, _) in reads() {
+		if ["GET", "MGET", "SCARD", "SMEMBERS", "SISMEMBER", "SRANDMEMBER", "SDIFF", "SINTER", "SUNION"].contains(&name) {
+			continue;
+		}
+		assert!(err(&storage, name, arguments).await.starts_with("WRONGTYPE"), "{}", name);
+	}
+	assert_eq!(ok(&storage, "TYPE", vec![buf("missing")]).await, buf("set"));
+}
+
+#[tokio::test]
+async fn writes_still_create_the_key() {
+	let storage = Storage::new();
+	ok(&storage, "SETBIT", vec![buf("bits"), int(3), int(1)]).await;
+	ok(&storage, "RPUSH", vec![buf("list"), buf("a")]).await;
+	ok(&storage, "SADD", vec![buf("set"), buf("a")]).await;
+	ok(&storage, "HSET", vec![buf("hash"), buf("f"), buf("v")]).await;
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("bits"), buf("list"), buf("set"), buf("hash")]).await, int(4));
+}
+
+#[tokio::test]
+async fn getrange_clamps_negative_bounds() {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("empty"), buf("")]).await;
+	assert_eq!(ok(&storage, "GETRANGE", vec![buf("empty"), int(0), int(-1)]).await, buf(""));
+	ok(&storage, "SET", vec![buf("k"), buf("hello")]).await;
+	let expected = [(0, -1, "hello"), (-3, -1, "llo"), (-100, -1, "hello"), (0, -100, ""), (1, 100, "ello"), (3, 1, ""), (5, -1, ""), (i64::MIN, i64::MAX, "hello")];
+	for (start, end, value) in expected.iter() {
+		assert_eq!(ok(&storage, "GETRANGE", vec![buf("k"), int(*start), int(*end)]).await, buf(value), "GETRANGE {} {}", start, end);
+	}
+}
+
+#[tokio::test]
+async fn bitcount_clamps_negative_bounds() {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("empty"), buf("")]).await;
+	assert_eq!(ok(&storage, "BITCOUNT", vec![buf("empty")]).await, int(0));
+	assert_eq!(ok(&storage, "BITCOUNT", vec![buf("empty"), int(0), int(-1)]).await, int(0));
+	ok(&storage, "SET", vec![buf("k"), Value::Buffer(vec![0xff, 0x0f, 0x01])]).await;
+	let expected = [(0, -1, 13), (-2, -1, 5), (-100, 0, 8), (0, -100, 0), (1, 100, 5), (2, 1, 0), (i64::MIN, i64::MAX, 13)];
+	for (start, end, count) in expected.iter() {
+		assert_eq!(ok(&storage, "BITCOUNT", vec![buf("k"), int(*start), int(*end)]).await, int(*count), "BITCOUNT {} {}", start, end);
+	}
+}
@@ -0,0 +1,171 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The fixtures hold a key of each RDB encoding of strings, lists, sets and hashes,
+//! in the layout written by Redis 2.6 (RDB 6), 6.2 (RDB 9) and 7.2 (RDB 11). They are
+//! synthetic: built from the format description rather than saved by a Redis server.
+
+mod common;
+
+use std::path::PathBuf;
+
+use radish_database::{Storage, Value, RdbSummary, parse_rdb};
+
+use common::*;
+
+fn fixture(name: &str) -> PathBuf {
+	PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+async fn load(name: &str) -> (Storage, RdbSummary) {
+	let storage = Storage::new();
+	let summary = storage.load_rdb(&fixture(name)).await.unwrap();
+	(storage, summary)
+}
+
+async fn list(storage: &Storage, key: &str) -> Value {
+	ok(storage, "LRANGE", vec![buf(key), int(0), int(-1)]).await
+}
+
+/// Members in their string form, sorted
+async fn members(storage: &Storage, key: &str) -> Vec<String> {
+	let mut members = match ok(storage, "SMEMBERS", vec![buf(key)]).await {
+		Value::Array(members) => members.into_iter().map(|m|match m {
+			Value::Buffer(m) => String::from_utf8(m).unwrap(),
+			m => panic!("unexpected member {:?}", m),
+		}).collect::<Vec<_>>(),
+		reply => panic!("unexpected reply {:?}", reply),
+	};
+	members.sort();
+	members
+}
+
+async fn field(storage: &Storage, key: &str, field: &str) -> Value {
+	ok(storage, "HGET", vec![buf(key), buf(field)]).await
+}
+
+async fn hlen(storage: &Storage, key: &str) -> Value {
+	ok(storage, "HLEN", vec![buf(key)]).await
+}
+
+async fn get(storage: &Storage, key: &str) -> Value {
+	ok(storage, "GET", vec![buf(key)]).await
+}
+
+async fn ttl(storage: &Storage, key: &str) -> i64 {
+	match ok(storage, "TTL", vec![buf(key)]).await {
+		Value::Integer(ttl) => ttl,
+		reply => panic!("unexpected reply {:?}", reply),
+	}
+}
+
+#[tokio::test]
+async fn redis_2_6_encodings() {
+	let (storage, summary) = load("synthetic-redis-2.6.rdb").await;
+	assert_eq!(summary.version, 6);
+	assert_eq!(summary.loaded, 10);
+	assert_eq!(summary.expired, 1);
+	assert_eq!(summary.skipped.get("zset"), Some(&1));
+
+	assert_eq!(get(&storage, "string").await, buf("plain value"));
+	assert_eq!(get(&storage, "int8").await, buf("-100"));
+	assert_eq!(get(&storage, "int16").await, buf("30000"));
+	assert_eq!(get(&storage, "int32").await, buf("-2000000000"));
+	assert_eq!(get(&storage, "compressed").await, buf(&"radish ".repeat(20)));
+	assert_eq!(list(&storage, "ziplist").await, bufs(&["a", "1", "-5", "300", "-70000", "5000000000", &"b".repeat(70)]));
+	assert_eq!(hlen(&storage, "zipmap").await, int(2));
+	assert_eq!(field(&storage, "zipmap", "f2").await, buf("v2"));
+	assert_eq!(members(&storage, "intset").await, vec!["1", "2", "3"]);
+	assert_eq!(hlen(&storage, "hash_ziplist").await, int(2));
+	assert_eq!(field(&storage, "hash_ziplist", "n").await, buf("12"));
+	assert!(ttl(&storage, "expires_s").await > 0);
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("expired"), buf("zset_ziplist")]).await, int(0));
+}
+
+#[tokio::test]
+async fn redis_6_2_encodings() {
+	let (storage, summary) = load("synthetic-redis-6.2.rdb").await;
+	assert_eq!(summary.version, 9);
+	assert_eq!(summary.loaded, 7);
+	assert_eq!(summary.other_databases, 1);
+	assert_eq!(summary.skipped.get("zset"), Some(&1));
+
+	assert!(ttl(&storage, "expires_ms").await > 0);
+	assert_eq!(get(&storage, "compressed").await, buf(&"radish ".repeat(20)));
+	assert_eq!(list(&storage, "quicklist").await, bufs(&["a", "b", "1", "-2", &"c".repeat(100)]));
+	assert_eq!(members(&storage, "set").await, vec!["x", "y", "z"]);
+	assert_eq!(members(&storage, "intset").await, vec!["-5", "5000000000", "70000"]);
+	assert_eq!(hlen(&storage, "hash").await, int(2));
+	assert_eq!(field(&storage, "hash", "f2").await, buf("100"));
+	assert_eq!(field(&storage, "hash_ziplist", "b").await, buf("2"));
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("zset"), buf("other_db")]).await, int(0));
+}
+
+#[tokio::test]
+async fn redis_7_2_encodings() {
+	let (storage, summary) = load("synthetic-redis-7.2.rdb").await;
+	assert_eq!(summary.version, 11);
+	assert_eq!(summary.loaded, 7);
+	assert_eq!(summary.expired, 1);
+	assert_eq!(summary.skipped.get("zset"), Some(&1));
+
+	assert_eq!(get(&storage, "idle").await, buf("v"));
+	assert_eq!(get(&storage, "freq").await, buf("v"));
+	assert_eq!(list(&storage, "quicklist2").await, bufs(&["a", "1", "-2", "1000", "b", &"plain node value ".repeat(10)]));
+	assert_eq!(members(&storage, "set_listpack").await, vec!["7", "x", "y"]);
+	assert_eq!(members(&storage, "intset").await, vec!["1", "2", "3"]);
+	assert_eq!(field(&storage, "hash_listpack", "f1").await, buf("v1"));
+	assert_eq!(field(&storage, "hash_listpack", "n").await, buf("-100000"));
+	assert!(ttl(&storage, "expires_ms").await > 0);
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("expired_list"), buf("zset_listpack")]).await, int(0));
+}
+
+#[test]
+fn truncated_files_are_parse_errors() {
+	for name in &["synthetic-redis-2.6.rdb", "synthetic-redis-6.2.rdb", "synthetic-redis-7.2.rdb"] {
+		let data = std::fs::read(fixture(name)).unwrap();
+		// a file cut before the trailer of RDB 5+ ends without the EOF opcode
+		for len in 0..data.len() - 9 {
+			assert!(parse_rdb(&data[..len]).is_err(), "{} cut at {} bytes is loaded", name, len);
+		}
+	}
+}
+
+#[test]
+fn flipped_byte_is_a_checksum_mismatch() {
+	let mut data = std::fs::read(fixture("synthetic-redis-7.2.rdb")).unwrap();
+	let pos = data.windows(5).position(|w|w == b"later").unwrap();
+	data[pos] ^= 0x20;
+	let err = parse_rdb(&data).unwrap_err();
+	assert!(err.contains("checksum mismatch"), "{}", err);
+}
+
+#[test]
+fn newer_version_is_rejected() {
+	let mut data = std::fs::read(fixture("synthetic-redis-7.2.rdb")).unwrap();
+	data[5..9].copy_from_slice(b"0013");
+	let err = parse_rdb(&data).unwrap_err();
+	assert!(err.contains("newer than supported"), "{}", err);
+}
+
+#[test]
+fn lzf_length_is_not_trusted() {
+	let mut data = b"REDIS0006".to_vec();
+	// string "k" compressed into 1 byte which claims to expand to 4 GiB
+	data.extend_from_slice(&[0x00, 0x01, b'k', 0xC3, 0x01, 0x80, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0xFF]);
+	let err = parse_rdb(&data).unwrap_err();
+	assert!(err.contains("LZF length"), "{}", err);
+}
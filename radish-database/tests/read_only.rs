@@ -0,0 +1,151 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use radish_database::{Storage, StorageError, MockClock, Value};
+
+use common::*;
+
+const READONLY: &str = "READONLY You can't write against a read only instance";
+
+async fn filled() -> Storage {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("string"), buf("v")]).await;
+	ok(&storage, "RPUSH", vec![buf("list"), buf("a"), buf("b")]).await;
+	ok(&storage, "SADD", vec![buf("set"), buf("a")]).await;
+	ok(&storage, "HSET", vec![buf("hash"), buf("f"), buf("v")]).await;
+	ok(&storage, "JSON.SET", vec![buf("json"), buf("$"), buf("{\"a\":1}")]).await;
+	ok(&storage, "BF.ADD", vec![buf("bloom"), buf("a")]).await;
+	storage
+}
+
+#[tokio::test]
+async fn writes_of_every_category_are_rejected() {
+	let storage = filled().await;
+	storage.set_read_only(true);
+
+	let writes = vec![
+		("SET", vec![buf("string"), buf("w")]),
+		("APPEND", vec![buf("string"), buf("w")]),
+		("INCR", vec![buf("counter")]),
+		("DEL", vec![buf("string")]),
+		("EXPIRE", vec![buf("string"), int(10)]),
+		("RENAME", vec![buf("string"), buf("other")]),
+		("RPUSH", vec![buf("list"), buf("c")]),
+		("LPOP", vec![buf("list")]),
+		("SADD", vec![buf("set"), buf("b")]),
+		("SREM", vec![buf("set"), buf("a")]),
+		("SUNIONSTORE", vec![buf("dest"), buf("set")]),
+		("HSET", vec![buf("hash"), buf("f"), buf("w")]),
+		("HDEL", vec![buf("hash"), buf("f")]),
+		("JSON.SET", vec![buf("json"), buf("$.a"), buf("2")]),
+		("BF.ADD", vec![buf("bloom"), buf("b")]),
+	];
+	for (name, args) in writes {
+		assert_eq!(err(&storage, name, args).await, READONLY, "{}", name);
+	}
+
+	assert_eq!(ok(&storage, "GET", vec![buf("string")]).await, buf("v"));
+	assert_eq!(ok(&storage, "LRANGE", vec![buf("list"), int(0), int(-1)]).await, bufs(&["a", "b"]));
+	assert_eq!(ok(&storage, "SMEMBERS", vec![buf("set")]).await, bufs(&["a"]));
+	assert_eq!(ok(&storage, "HGET", vec![buf("hash"), buf("f")]).await, buf("v"));
+	assert_eq!(ok(&storage, "SUNION", vec![buf("set")]).await, bufs(&["a"]));
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("json"), buf("bloom")]).await, int(2));
+	assert_eq!(ok(&storage, "TTL", vec![buf("string")]).await, int(-1));
+	assert_eq!(ok(&storage, "BF.EXISTS", vec![buf("bloom"), buf("a")]).await, Value::Bool(true));
+	ok(&storage, "PING", vec![]).await;
+
+	storage.set_read_only(false);
+	ok(&storage, "SET", vec![buf("string"), buf("w")]).await;
+	assert_eq!(ok(&storage, "GET", vec![buf("string")]).await, buf("w"));
+}
+
+#[tokio::test]
+async fn every_command_is_classified() {
+	let storage = Storage::new();
+	let reply = ok(&storage, "COMMAND", vec![]).await;
+	let names: Vec<String> = match reply {
+		Value::Array(commands) => commands.into_iter().map(|command|match command {
+			Value::Array(mut fields) => match fields.pop_front() {
+				Some(Value::Buffer(name)) => String::from_utf8(name).unwrap(),
+				name => panic!("unexpected name {:?}", name),
+			},
+			command => panic!("unexpected command {:?}", command),
+		}).collect(),
+		reply => panic!("unexpected COMMAND reply {:?}", reply),
+	};
+	assert!(names.len() > 100);
+	for name in names {
+		let spec = storage.command_spec(&name).unwrap();
+		assert!(!(spec.is_write() && spec.has_flag("readonly")), "{} is both write and readonly", name);
+		// a command which touches keys has to say if it writes them
+		if spec.first_key != 0 {
+			assert!(spec.is_write() || spec.has_flag("readonly"), "{} is not classified", name);
+		}
+	}
+}
+
+#[tokio::test]
+async fn config_set_toggles_the_mode() {
+	let storage = filled().await;
+	ok(&storage, "CONFIG", vec![buf("SET"), buf("read-only"), buf("yes")]).await;
+	assert_eq!(ok(&storage, "CONFIG", vec![buf("GET"), buf("read-only")]).await, bufs(&["read-only", "yes"]));
+	assert_eq!(err(&storage, "SET", vec![buf("k"), buf("v")]).await, READONLY);
+	// configuration is not a write to the dataset
+	ok(&storage, "CONFIG", vec![buf("SET"), buf("read-only"), buf("no")]).await;
+	ok(&storage, "SET", vec![buf("k"), buf("v")]).await;
+}
+
+#[tokio::test]
+async fn embedded_api_is_rejected_too() {
+	let storage = filled().await;
+	storage.set_read_only(true);
+	assert!(matches!(storage.set(b"k", b"v", None).await, Err(StorageError::ReadOnly)));
+	assert!(matches!(storage.del(b"string").await, Err(StorageError::ReadOnly)));
+	assert!(matches!(storage.hash_set(b"hash", b"f", b"w").await, Err(StorageError::ReadOnly)));
+	assert_eq!(storage.get(b"string").await.unwrap(), Some(b"v".to_vec()));
+}
+
+#[tokio::test]
+async fn expiration_while_read_only() {
+	let clock = Arc::new(MockClock::new());
+	let storage = Storage::with_clock(clock.clone());
+	ok(&storage, "SET", vec![buf("a"), buf("v"), buf("PX"), int(1_000)]).await;
+	ok(&storage, "SET", vec![buf("b"), buf("v"), buf("PX"), int(1_000)]).await;
+	storage.set_read_only(true);
+
+	// by default expired keys are still removed
+	clock.advance(&storage, Duration::from_millis(1_000)).await;
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("a"), buf("b")]).await, int(0));
+	assert_eq!(storage.stats().await.keys, 0);
+
+	storage.set_read_only(false);
+	ok(&storage, "SET", vec![buf("a"), buf("v"), buf("PX"), int(1_000)]).await;
+	ok(&storage, "CONFIG", vec![buf("SET"), buf("read-only-expire"), buf("no")]).await;
+	storage.set_read_only(true);
+	clock.advance(&storage, Duration::from_millis(1_000)).await;
+	// the key reads as missing but is kept
+	assert_eq!(ok(&storage, "GET", vec![buf("a")]).await, Value::Nill);
+	assert_eq!(storage.stats().await.keys, 1);
+
+	storage.set_read_only(false);
+	clock.advance(&storage, Duration::from_millis(1_000)).await;
+	assert_eq!(storage.stats().await.keys, 0);
+}
@@ -0,0 +1,81 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::Storage;
+
+use common::*;
+
+fn display(storage: &Storage, name: &str, arguments: &[&str]) -> String {
+	let arguments = arguments.iter().map(|arg|buf(arg)).collect();
+	storage.display_command(&command(name, arguments)).to_string()
+}
+
+/// Display of a buffer argument
+fn shown(arg: &str) -> String {
+	format!("{:?}", arg.as_bytes())
+}
+
+#[test]
+fn secrets_are_masked() {
+	let storage = Storage::new();
+	assert_eq!(display(&storage, "AUTH", &["secret"]), "AUTH: [(redacted)]");
+	assert_eq!(display(&storage, "auth", &["user", "secret"]), "auth: [(redacted),(redacted)]");
+	assert_eq!(
+		display(&storage, "HELLO", &["3", "auth", "user", "secret", "SETNAME", "app"]),
+		format!("HELLO: [{},{},(redacted),(redacted),{},{}]", shown("3"), shown("auth"), shown("SETNAME"), shown("app")),
+	);
+	assert_eq!(
+		display(&storage, "CONFIG", &["SET", "requirepass", "secret"]),
+		format!("CONFIG: [{},{},(redacted)]", shown("SET"), shown("requirepass")),
+	);
+	assert_eq!(
+		display(&storage, "CONFIG", &["SET", "maxmemory", "1mb"]),
+		format!("CONFIG: [{},{},{}]", shown("SET"), shown("maxmemory"), shown("1mb")),
+	);
+	assert_eq!(
+		display(&storage, "MIGRATE", &["host", "AUTH2", "user", "secret", "KEYS", "k"]),
+		format!("MIGRATE: [{},{},(redacted),(redacted),{},{}]", shown("host"), shown("AUTH2"), shown("KEYS"), shown("k")),
+	);
+	// a keyword at the end has nothing to mask
+	assert_eq!(display(&storage, "HELLO", &["3", "AUTH"]), format!("HELLO: [{},{}]", shown("3"), shown("AUTH")));
+	assert_eq!(display(&storage, "GET", &["secret"]), format!("GET: [{}]", shown("secret")));
+}
+
+#[test]
+fn renamed_commands_keep_their_rules() {
+	let storage = Storage::new();
+	storage.rename_command("AUTH", "LOGIN").unwrap();
+	assert_eq!(display(&storage, "login", &["secret"]), "login: [(redacted)]");
+	// the old name is unknown now, and its arguments are still masked
+	assert_eq!(display(&storage, "AUTH", &["secret"]), "AUTH: [(redacted)]");
+}
+
+#[test]
+fn long_values_are_cut() {
+	let storage = Storage::new();
+	let value = "x".repeat(1 << 20);
+	assert_eq!(
+		display(&storage, "SET", &["k", &value]),
+		format!("SET: [{},{}...(1048576 bytes)]", shown("k"), shown(&value[..128])),
+	);
+
+	storage.config().set("log-value-length", "0").unwrap();
+	assert_eq!(display(&storage, "SET", &["k", &value]), format!("SET: [{},{}]", shown("k"), shown(&value)));
+	storage.config().set("log-value-length", "2").unwrap();
+	assert_eq!(display(&storage, "AUTH", &[&value]), "AUTH: [(redacted)]");
+}
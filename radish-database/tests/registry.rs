@@ -0,0 +1,84 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+fn keys(storage: &Storage, name: &str, args: &[&str]) -> Vec<String> {
+	let spec = storage.command_spec(name).unwrap();
+	let args = args.iter().map(|arg|buf(arg)).collect();
+	spec.keys(&args).into_iter().map(|key|String::from_utf8(key).unwrap()).collect()
+}
+
+#[tokio::test]
+async fn sample_specs() {
+	let storage = Storage::new();
+
+	let get = storage.command_spec("get").unwrap();
+	assert_eq!((get.name, get.arity, get.first_key, get.last_key, get.key_step), ("GET", 2, 1, 1, 1));
+	assert!(!get.is_write() && get.has_flag("readonly") && !get.is_blocking());
+
+	let set = storage.command_spec("SET").unwrap();
+	assert_eq!((set.arity, set.first_key, set.last_key, set.key_step), (-3, 1, 1, 1));
+	assert!(set.is_write());
+
+	let mset = storage.command_spec("MSET").unwrap();
+	assert_eq!((mset.arity, mset.first_key, mset.last_key, mset.key_step), (-3, 1, -1, 2));
+
+	let blpop = storage.command_spec("BLPOP").unwrap();
+	assert_eq!((blpop.arity, blpop.first_key, blpop.last_key), (-3, 1, -2));
+	assert!(blpop.is_write() && blpop.is_blocking());
+
+	let ping = storage.command_spec("Ping").unwrap();
+	assert_eq!((ping.arity, ping.first_key), (-1, 0));
+	assert!(ping.flags.is_empty());
+
+	assert!(storage.command_spec("CONFIG").unwrap().has_flag("admin"));
+	assert!(storage.command_spec("NOSUCHCOMMAND").is_none());
+}
+
+#[tokio::test]
+async fn key_positions() {
+	let storage = Storage::new();
+	assert_eq!(keys(&storage, "GET", &["a"]), vec!["a"]);
+	assert_eq!(keys(&storage, "DEL", &["a", "b", "c"]), vec!["a", "b", "c"]);
+	assert_eq!(keys(&storage, "MSET", &["a", "1", "b", "2"]), vec!["a", "b"]);
+	assert_eq!(keys(&storage, "BLPOP", &["a", "b", "0"]), vec!["a", "b"]);
+	assert_eq!(keys(&storage, "RPOPLPUSH", &["src", "dst"]), vec!["src", "dst"]);
+	assert!(keys(&storage, "KEYS", &["*"]).is_empty());
+	assert!(keys(&storage, "PING", &[]).is_empty());
+}
+
+#[tokio::test]
+async fn command_reports_the_registry() {
+	let storage = Storage::new();
+	let info = ok(&storage, "COMMAND", vec![buf("INFO"), buf("get"), buf("mset"), buf("nosuchcommand")]).await;
+	assert_eq!(info, array(vec![
+		array(vec![buf("get"), int(2), bufs(&["readonly"]), int(1), int(1), int(1), buf("Get the value of a key")]),
+		array(vec![buf("mset"), int(-3), bufs(&["write"]), int(1), int(-1), int(2), buf("Set multiple keys")]),
+		Value::Nill,
+	]));
+
+	let count = ok(&storage, "COMMAND", vec![buf("COUNT")]).await;
+	match ok(&storage, "COMMAND", vec![]).await {
+		Value::Array(all) => assert_eq!(int(all.len() as i64), count),
+		all => panic!("unexpected COMMAND reply {:?}", all),
+	}
+	assert_eq!(ok(&storage, "HELP", vec![buf("get")]).await, buf("GET - Get the value of a key"));
+}
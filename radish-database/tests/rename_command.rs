@@ -0,0 +1,163 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use radish_database::{Storage, Session, Command, CommandSpec, ExecutionHook, Value};
+
+use common::*;
+
+async fn command_count(storage: &Storage) -> i64 {
+	match ok(storage, "COMMAND", vec![buf("COUNT")]).await {
+		Value::Integer(count) => count,
+		reply => panic!("unexpected COMMAND COUNT reply {:?}", reply),
+	}
+}
+
+async fn help_lines(storage: &Storage) -> Vec<String> {
+	match ok(storage, "HELP", vec![]).await {
+		Value::Array(lines) => lines.into_iter().map(|line|match line {
+			Value::Buffer(line) => String::from_utf8(line).unwrap(),
+			line => panic!("unexpected HELP line {:?}", line),
+		}).collect(),
+		reply => panic!("unexpected HELP reply {:?}", reply),
+	}
+}
+
+/// Name of the first element of a COMMAND INFO entry
+fn described_name(info: &Value) -> Option<Value> {
+	match info {
+		Value::Array(items) => items.front().cloned(),
+		_ => None,
+	}
+}
+
+#[tokio::test]
+async fn disabled_command_is_unknown_everywhere() {
+	let storage = Storage::new();
+	let count = command_count(&storage).await;
+	ok(&storage, "SET", vec![buf("a"), buf("v")]).await;
+	storage.rename_command("keys", "").unwrap();
+
+	assert_eq!(err(&storage, "KEYS", vec![buf(".*")]).await, "ERR unknown command 'KEYS', with args beginning with: '.*' (did you mean 'hkeys'?)");
+	assert_eq!(ok(&storage, "GET", vec![buf("a")]).await, buf("v"));
+	assert_eq!(command_count(&storage).await, count - 1);
+	assert_eq!(ok(&storage, "COMMAND", vec![buf("INFO"), buf("keys")]).await, array(vec![Value::Nill]));
+	assert!(help_lines(&storage).await.iter().all(|line|!line.starts_with("KEYS ")));
+	assert!(storage.command_spec("KEYS").is_none());
+}
+
+#[tokio::test]
+async fn renamed_command_dispatches_to_the_original() {
+	let storage = Storage::new();
+	let count = command_count(&storage).await;
+	storage.rename_command("CONFIG", "b840fc02").unwrap();
+
+	assert_eq!(err(&storage, "CONFIG", vec![buf("GET"), buf("maxmemory")]).await, "ERR unknown command 'CONFIG', with args beginning with: 'GET' 'maxmemory'");
+	assert_eq!(ok(&storage, "B840FC02", vec![buf("GET"), buf("maxmemory")]).await, bufs(&["maxmemory", "0"]));
+	assert_eq!(ok(&storage, "b840fc02", vec![buf("SET"), buf("maxmemory"), buf("1mb")]).await, Value::Ok);
+	assert_eq!(storage.config().get("maxmemory").unwrap(), "1048576");
+
+	assert_eq!(command_count(&storage).await, count);
+	let info = ok(&storage, "COMMAND", vec![buf("INFO"), buf("b840fc02"), buf("config")]).await;
+	match info {
+		Value::Array(items) => {
+			assert_eq!(described_name(&items[0]), Some(buf("b840fc02")));
+			assert_eq!(items[1], Value::Nill);
+		},
+		reply => panic!("unexpected COMMAND INFO reply {:?}", reply),
+	}
+	let help = help_lines(&storage).await;
+	assert!(help.iter().any(|line|line.starts_with("B840FC02 - ")), "{:?}", help);
+	assert!(help.iter().all(|line|!line.starts_with("CONFIG - ")), "{:?}", help);
+
+	// the spec and the statistics keep the original identity
+	assert_eq!(storage.command_spec("B840FC02").unwrap().name, "CONFIG");
+	let stats = match ok(&storage, "INFO", vec![buf("commandstats")]).await {
+		Value::Buffer(stats) => String::from_utf8(stats).unwrap(),
+		reply => panic!("unexpected INFO reply {:?}", reply),
+	};
+	assert!(stats.contains("cmdstat_config:calls=2,"), "{}", stats);
+}
+
+#[derive(Default, Clone)]
+struct Seen(Arc<Mutex<Vec<&'static str>>>);
+
+impl ExecutionHook for Seen {
+	fn before(&self, _session: &Session, _command: &Command, spec: &CommandSpec) -> Result<(), String> {
+		self.0.lock().unwrap().push(spec.name);
+		if spec.has_flag("admin") {
+			return Err("NOPERM admin commands are not allowed".to_owned());
+		}
+		Ok(())
+	}
+}
+
+#[tokio::test]
+async fn permission_checks_see_the_original_command() {
+	let storage = Storage::new();
+	let seen = Seen::default();
+	storage.add_hook(Box::new(seen.clone()));
+	storage.rename_command("CONFIG", "HIDDENCONFIG").unwrap();
+
+	assert_eq!(err(&storage, "HIDDENCONFIG", vec![buf("GET"), buf("port")]).await, "NOPERM admin commands are not allowed");
+	assert_eq!(*seen.0.lock().unwrap(), vec!["CONFIG"]);
+}
+
+#[tokio::test]
+async fn renames_replace_each_other() {
+	let storage = Storage::new();
+	storage.rename_command("GET", "FETCH").unwrap();
+	storage.rename_command("get", "READ").unwrap();
+	ok(&storage, "SET", vec![buf("a"), buf("v")]).await;
+	assert_eq!(ok(&storage, "READ", vec![buf("a")]).await, buf("v"));
+	assert!(err(&storage, "FETCH", vec![buf("a")]).await.starts_with("ERR unknown command 'FETCH'"));
+
+	// back to the original name
+	storage.rename_command("GET", "GET").unwrap();
+	assert_eq!(ok(&storage, "GET", vec![buf("a")]).await, buf("v"));
+	assert!(err(&storage, "READ", vec![buf("a")]).await.starts_with("ERR unknown command 'READ'"));
+}
+
+#[tokio::test]
+async fn invalid_renames() {
+	let storage = Storage::new();
+	assert_eq!(storage.rename_command("NOSUCH", "X").unwrap_err(), "No such command 'NOSUCH'");
+	assert_eq!(storage.rename_command("SET", "get").unwrap_err(), "Name 'GET' is already used by 'GET'");
+	storage.rename_command("KEYS", "").unwrap();
+	// a disabled command is still known by its original name
+	storage.rename_command("KEYS", "MYKEYS").unwrap();
+	assert!(storage.command_spec("MYKEYS").is_some());
+}
+
+#[tokio::test]
+async fn config_file_directives() {
+	let storage = Storage::new();
+	storage.load_config("maxmemory 2mb\nrename-command KEYS \"\"\nrename-command CONFIG b840fc02\n").unwrap();
+	assert_eq!(storage.config().get("maxmemory").unwrap(), "2097152");
+	assert!(err(&storage, "KEYS", vec![buf(".*")]).await.starts_with("ERR unknown command"));
+	assert!(err(&storage, "CONFIG", vec![buf("GET"), buf("port")]).await.starts_with("ERR unknown command"));
+	assert_eq!(ok(&storage, "B840FC02", vec![buf("GET"), buf("maxmemory")]).await, bufs(&["maxmemory", "2097152"]));
+
+	let error = storage.load_config("rename-command KEYS\n").unwrap_err();
+	assert!(error.contains("Expected a command and its new name"), "{}", error);
+	let error = storage.load_config("rename-command NOSUCH x\n").unwrap_err();
+	assert!(error.contains("No such command 'NOSUCH'"), "{}", error);
+	// rename-command is not a parameter of Config::load
+	assert!(storage.config().load("rename-command GET FETCH\n").is_err());
+}
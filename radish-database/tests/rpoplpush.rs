@@ -0,0 +1,114 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+async fn list(storage: &Storage, key: &str, items: &[&str]) {
+	let mut args = vec![buf(key)];
+	args.extend(items.iter().map(|i|buf(i)));
+	ok(storage, "RPUSH", args).await;
+}
+
+async fn range(storage: &Storage, key: &str) -> Value {
+	ok(storage, "LRANGE", vec![buf(key), int(0), int(-1)]).await
+}
+
+#[tokio::test]
+async fn moves_the_tail_to_the_head_of_another_list() {
+	let storage = Storage::new();
+	list(&storage, "src", &["a", "b", "c"]).await;
+	list(&storage, "dst", &["x"]).await;
+	assert_eq!(ok(&storage, "RPOPLPUSH", vec![buf("src"), buf("dst")]).await, buf("c"));
+	assert_eq!(ok(&storage, "RPOPLPUSH", vec![buf("src"), buf("dst")]).await, buf("b"));
+	assert_eq!(range(&storage, "src").await, bufs(&["a"]));
+	assert_eq!(range(&storage, "dst").await, bufs(&["b", "c", "x"]));
+}
+
+#[tokio::test]
+async fn creates_the_destination() {
+	let storage = Storage::new();
+	list(&storage, "src", &["only"]).await;
+	assert_eq!(ok(&storage, "RPOPLPUSH", vec![buf("src"), buf("dst")]).await, buf("only"));
+	assert_eq!(range(&storage, "src").await, bufs(&[]));
+	assert_eq!(ok(&storage, "RPOPLPUSH", vec![buf("src"), buf("dst")]).await, Value::Nill);
+	assert_eq!(range(&storage, "dst").await, bufs(&["only"]));
+}
+
+#[tokio::test]
+async fn missing_source_is_nil_without_the_destination() {
+	let storage = Storage::new();
+	assert_eq!(ok(&storage, "RPOPLPUSH", vec![buf("missing"), buf("dst")]).await, Value::Nill);
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("missing"), buf("dst")]).await, int(0));
+
+	ok(&storage, "RPUSH", vec![buf("empty"), buf("x")]).await;
+	ok(&storage, "RPOP", vec![buf("empty")]).await;
+	assert_eq!(ok(&storage, "RPOPLPUSH", vec![buf("empty"), buf("dst")]).await, Value::Nill);
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("dst")]).await, int(0));
+}
+
+#[tokio::test]
+async fn same_key_rotates_the_list() {
+	let storage = Storage::new();
+	list(&storage, "l", &["a", "b", "c"]).await;
+	assert_eq!(ok(&storage, "RPOPLPUSH", vec![buf("l"), buf("l")]).await, buf("c"));
+	assert_eq!(range(&storage, "l").await, bufs(&["c", "a", "b"]));
+}
+
+#[tokio::test]
+async fn wrong_types_pop_nothing() {
+	let storage = Storage::new();
+	list(&storage, "src", &["a"]).await;
+	ok(&storage, "SET", vec![buf("str"), buf("v")]).await;
+	ok(&storage, "SADD", vec![buf("set"), buf("m")]).await;
+
+	assert!(err(&storage, "RPOPLPUSH", vec![buf("src"), buf("str")]).await.starts_with("WRONGTYPE"));
+	assert!(err(&storage, "RPOPLPUSH", vec![buf("set"), buf("src")]).await.starts_with("WRONGTYPE"));
+	assert_eq!(range(&storage, "src").await, bufs(&["a"]));
+	assert_eq!(ok(&storage, "GET", vec![buf("str")]).await, buf("v"));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn concurrent_moves_lose_nothing() {
+	let storage = Storage::new();
+	let items: Vec<String> = (0..1000).map(|i|i.to_string()).collect();
+	list(&storage, "a", &items.iter().map(|i|&i[..]).collect::<Vec<_>>()).await;
+
+	// elements go around a -> b -> a while LLEN of both is watched from outside
+	let movers: Vec<_> = vec![("a", "b"), ("b", "a"), ("a", "b"), ("b", "a")].into_iter().map(|(source, destination)|{
+		let storage = storage.clone();
+		tokio::spawn(async move {
+			for _ in 0..2000 {
+				run(&storage, "RPOPLPUSH", vec![buf(source), buf(destination)]).await;
+			}
+		})
+	}).collect();
+	for mover in movers {
+		tokio::time::timeout(Duration::from_secs(30), mover).await.unwrap().unwrap();
+	}
+
+	let len = |reply: Value|match reply {
+		Value::Integer(len) => len,
+		reply => panic!("unexpected LLEN reply {:?}", reply),
+	};
+	let total = len(ok(&storage, "LLEN", vec![buf("a")]).await) + len(ok(&storage, "LLEN", vec![buf("b")]).await);
+	assert_eq!(total, 1000);
+}
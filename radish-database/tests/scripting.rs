@@ -0,0 +1,75 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+#[tokio::test]
+async fn load_returns_the_redis_digest() {
+	let storage = Storage::new();
+	// digests as returned by redis-server for the same scripts
+	for (script, sha) in &[
+		("return 1", "e0e1f9fabfc9d4800c877a703b823ac0578ff8db"),
+		("return 'Immabe a cached script'", "c664a3bf70bd1d45c4284ffebb65a6f2299bfc9f"),
+		("return redis.call('GET', KEYS[1])", "d3c21d0c2b9ca22f82737626a27bcaf5d288f99f"),
+		("", "da39a3ee5e6b4b0d3255bfef95601890afd80709"),
+	] {
+		assert_eq!(ok(&storage, "SCRIPT", vec![buf("LOAD"), buf(script)]).await, buf(sha));
+	}
+	// loading twice keeps one entry under the same digest
+	assert_eq!(ok(&storage, "SCRIPT", vec![buf("load"), buf("return 1")]).await, buf("e0e1f9fabfc9d4800c877a703b823ac0578ff8db"));
+}
+
+#[tokio::test]
+async fn exists_and_flush() {
+	let storage = Storage::new();
+	ok(&storage, "SCRIPT", vec![buf("LOAD"), buf("return 1")]).await;
+	assert_eq!(
+		ok(&storage, "SCRIPT", vec![
+			buf("EXISTS"),
+			buf("e0e1f9fabfc9d4800c877a703b823ac0578ff8db"),
+			buf("E0E1F9FABFC9D4800C877A703B823AC0578FF8DB"),
+			buf("c664a3bf70bd1d45c4284ffebb65a6f2299bfc9f"),
+			buf("not a digest"),
+		]).await,
+		array(vec![int(1), int(1), int(0), int(0)]),
+	);
+
+	assert_eq!(ok(&storage, "SCRIPT", vec![buf("FLUSH")]).await, Value::Ok);
+	assert_eq!(
+		ok(&storage, "SCRIPT", vec![buf("EXISTS"), buf("e0e1f9fabfc9d4800c877a703b823ac0578ff8db")]).await,
+		array(vec![int(0)]),
+	);
+	assert!(err(&storage, "EVALSHA", vec![buf("e0e1f9fabfc9d4800c877a703b823ac0578ff8db"), int(0)]).await.starts_with("NOSCRIPT"));
+}
+
+#[tokio::test]
+async fn evalsha_of_a_script_never_loaded() {
+	let storage = Storage::new();
+	assert_eq!(
+		err(&storage, "EVALSHA", vec![buf("c664a3bf70bd1d45c4284ffebb65a6f2299bfc9f"), int(0)]).await,
+		"NOSCRIPT No matching script. Please use EVAL.",
+	);
+	assert!(err(&storage, "EVALSHA", vec![buf("abc"), int(0)]).await.starts_with("NOSCRIPT"));
+	assert!(err(&storage, "EVALSHA", vec![buf("c664a3bf70bd1d45c4284ffebb65a6f2299bfc9f"), int(1)]).await.starts_with("ERR Number of keys"));
+
+	// a loaded script is found, even if it can't be run by this build
+	ok(&storage, "SCRIPT", vec![buf("LOAD"), buf("return 'Immabe a cached script'")]).await;
+	assert!(!err(&storage, "EVALSHA", vec![buf("c664a3bf70bd1d45c4284ffebb65a6f2299bfc9f"), int(0)]).await.starts_with("NOSCRIPT"));
+}
@@ -0,0 +1,90 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Two connections' sessions against one storage: the state of one never leaks into the other
+
+mod common;
+
+use radish_database::{Storage, Session, Value};
+
+use common::*;
+
+async fn exec(storage: &Storage, session: &mut Session, name: &str, arguments: Vec<Value>) -> Value {
+	storage.execute(session, command(name, arguments)).await
+}
+
+#[tokio::test]
+async fn names_and_ids_are_per_session() {
+	let storage = Storage::new();
+	let mut first = Session::new();
+	let mut second = Session::new();
+
+	let first_id = exec(&storage, &mut first, "CLIENT", vec![buf("ID")]).await;
+	let second_id = exec(&storage, &mut second, "CLIENT", vec![buf("ID")]).await;
+	assert_ne!(first_id, second_id);
+	assert_eq!(first_id, int(first.id as i64));
+
+	assert_eq!(exec(&storage, &mut first, "CLIENT", vec![buf("SETNAME"), buf("worker")]).await, Value::Ok);
+	assert_eq!(exec(&storage, &mut second, "CLIENT", vec![buf("SETNAME"), buf("monitor")]).await, Value::Ok);
+	assert_eq!(exec(&storage, &mut first, "CLIENT", vec![buf("GETNAME")]).await, buf("worker"));
+	assert_eq!(exec(&storage, &mut second, "CLIENT", vec![buf("GETNAME")]).await, buf("monitor"));
+
+	// an empty name clears only the own one
+	exec(&storage, &mut first, "CLIENT", vec![buf("SETNAME"), buf("")]).await;
+	assert_eq!(exec(&storage, &mut first, "CLIENT", vec![buf("GETNAME")]).await, Value::Nill);
+	assert_eq!(second.name.as_deref(), Some("monitor"));
+}
+
+#[tokio::test]
+async fn select_is_per_session() {
+	let storage = Storage::new();
+	let mut first = Session::new();
+	let mut second = Session::new();
+	exec(&storage, &mut second, "SET", vec![buf("k"), buf("v")]).await;
+
+	assert_eq!(exec(&storage, &mut first, "SELECT", vec![int(0)]).await, Value::Ok);
+	assert_eq!(first.database, 0);
+	// the keyspace is single, other databases are refused without touching the session
+	assert_eq!(exec(&storage, &mut second, "SELECT", vec![int(1)]).await, Value::Error("ERR DB index is out of range".to_owned()));
+	assert_eq!(second.database, 0);
+	assert_eq!(exec(&storage, &mut first, "GET", vec![buf("k")]).await, buf("v"));
+}
+
+#[tokio::test]
+async fn authentication_is_per_session() {
+	let storage = Storage::new();
+	ok(&storage, "CONFIG", vec![buf("SET"), buf("requirepass"), buf("secret")]).await;
+	let mut first = Session::new();
+	let mut second = Session::new();
+
+	assert_eq!(exec(&storage, &mut first, "AUTH", vec![buf("secret")]).await, Value::Ok);
+	assert_eq!(exec(&storage, &mut first, "SET", vec![buf("k"), buf("v")]).await, Value::Ok);
+	assert_eq!(exec(&storage, &mut second, "GET", vec![buf("k")]).await, Value::Error("NOAUTH Authentication required.".to_owned()));
+
+	// a failed AUTH logs out only its own session
+	assert!(matches!(exec(&storage, &mut second, "AUTH", vec![buf("wrong")]).await, Value::Error(_)));
+	assert!(first.authenticated && !second.authenticated);
+	assert_eq!(exec(&storage, &mut first, "GET", vec![buf("k")]).await, buf("v"));
+}
+
+#[tokio::test]
+async fn embedders_use_the_default_session() {
+	let storage = Storage::new();
+	let mut session = Session::default();
+	assert_eq!(exec(&storage, &mut session, "SET", vec![buf("k"), buf("v")]).await, Value::Ok);
+	assert_eq!(exec(&storage, &mut session, "CLIENT", vec![buf("GETNAME")]).await, Value::Nill);
+	assert_eq!(session.addr, None);
+}
@@ -0,0 +1,118 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Layout of the file, see the snapshot module: a 28 byte header with the version at
+//! offset 8, the payload and a trailing 8 byte checksum.
+
+mod common;
+
+use std::path::PathBuf;
+
+use radish_database::{Storage, Value, decode_snapshot};
+
+use common::*;
+
+const HEADER_SIZE: usize = 28;
+
+struct TempFile(PathBuf);
+
+impl TempFile {
+	fn new(name: &str) -> Self {
+		Self(std::env::temp_dir().join(format!("radish-{}-{}.snapshot", name, std::process::id())))
+	}
+}
+
+impl Drop for TempFile {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_file(&self.0);
+	}
+}
+
+async fn saved(name: &str) -> (TempFile, Vec<u8>) {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("string"), buf("value")]).await;
+	ok(&storage, "RPUSH", vec![buf("list"), buf("a"), buf("b")]).await;
+	ok(&storage, "HSET", vec![buf("hash"), buf("f"), buf("v")]).await;
+	let file = TempFile::new(name);
+	storage.save_snapshot(&file.0).await.unwrap();
+	let data = std::fs::read(&file.0).unwrap();
+	(file, data)
+}
+
+/// Loads into a storage with a key of its own, which must survive a failed load
+async fn load(file: &TempFile, data: &[u8], verify_checksum: bool) -> (Storage, Result<(), String>) {
+	std::fs::write(&file.0, data).unwrap();
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("mine"), buf("v")]).await;
+	let result = storage.load_snapshot(&file.0, verify_checksum).await.map(|_|());
+	(storage, result)
+}
+
+#[tokio::test]
+async fn round_trip() {
+	let (file, data) = saved("round-trip").await;
+	let (storage, result) = load(&file, &data, true).await;
+	result.unwrap();
+	assert_eq!(run(&storage, "GET", vec![buf("mine")]).await, Value::Nill);
+	assert_eq!(ok(&storage, "LRANGE", vec![buf("list"), int(0), int(-1)]).await, bufs(&["a", "b"]));
+	assert_eq!(ok(&storage, "HGET", vec![buf("hash"), buf("f")]).await, buf("v"));
+}
+
+#[tokio::test]
+async fn flipped_byte_fails_the_checksum() {
+	let (file, mut data) = saved("flipped").await;
+	let middle = HEADER_SIZE + (data.len() - HEADER_SIZE - 8) / 2;
+	data[middle] ^= 0x01;
+
+	let (storage, result) = load(&file, &data, true).await;
+	assert_eq!(result, Err("snapshot corrupt: checksum mismatch".to_owned()));
+	assert_eq!(ok(&storage, "GET", vec![buf("mine")]).await, buf("v"));
+	assert_eq!(run(&storage, "GET", vec![buf("string")]).await, Value::Nill);
+
+	// the same file with a damaged checksum only loads when the check is skipped
+	let (_, mut data) = saved("flipped-trailer").await;
+	let last = data.len() - 1;
+	data[last] ^= 0xff;
+	assert_eq!(load(&file, &data, true).await.1, Err("snapshot corrupt: checksum mismatch".to_owned()));
+	let (storage, result) = load(&file, &data, false).await;
+	result.unwrap();
+	assert_eq!(ok(&storage, "GET", vec![buf("string")]).await, buf("value"));
+}
+
+#[tokio::test]
+async fn truncated_and_foreign_files() {
+	let (file, data) = saved("truncated").await;
+	assert_eq!(load(&file, &data[..data.len() - 1], true).await.1, Err("snapshot corrupt: file is truncated".to_owned()));
+	assert_eq!(load(&file, &data[..HEADER_SIZE], false).await.1, Err("snapshot corrupt: file is truncated".to_owned()));
+	assert_eq!(load(&file, &data[..10], true).await.1, Err("snapshot corrupt: not a snapshot file".to_owned()));
+	assert_eq!(load(&file, b"REDIS0009 and more bytes than a header", true).await.1, Err("snapshot corrupt: not a snapshot file".to_owned()));
+}
+
+#[tokio::test]
+async fn future_version_is_refused() {
+	let (file, mut data) = saved("future").await;
+	let (header, _) = decode_snapshot(&data, true).unwrap();
+	let future = header.version + 1;
+	data[8..10].copy_from_slice(&future.to_be_bytes());
+
+	let (storage, result) = load(&file, &data, false).await;
+	assert_eq!(result, Err(format!("snapshot format version {} is newer than supported version {}", future, header.version)));
+	assert_eq!(ok(&storage, "GET", vec![buf("mine")]).await, buf("v"));
+
+	data[8..10].copy_from_slice(&header.version.to_be_bytes());
+	data[10..12].copy_from_slice(&2u16.to_be_bytes());
+	assert_eq!(load(&file, &data, true).await.1, Err("snapshot flags 0x2 are not supported".to_owned()));
+}
@@ -0,0 +1,87 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::Storage;
+
+use common::*;
+
+async fn list(storage: &Storage, key: &str, items: &[&str]) {
+	ok(storage, "DEL", vec![buf(key)]).await;
+	let mut args = vec![buf(key)];
+	args.extend(items.iter().map(|i|buf(i)));
+	ok(storage, "RPUSH", args).await;
+}
+
+#[tokio::test]
+async fn numeric_sort_with_negative_numbers() {
+	let storage = Storage::new();
+	list(&storage, "l", &["3", "-1.5", "10", "-20", "0"]).await;
+	assert_eq!(ok(&storage, "SORT", vec![buf("l")]).await, bufs(&["-20", "-1.5", "0", "3", "10"]));
+	assert_eq!(ok(&storage, "SORT", vec![buf("l"), buf("DESC")]).await, bufs(&["10", "3", "0", "-1.5", "-20"]));
+	assert_eq!(ok(&storage, "SORT", vec![buf("l"), buf("LIMIT"), int(1), int(2)]).await, bufs(&["-1.5", "0"]));
+}
+
+#[tokio::test]
+async fn alpha_sort_compares_bytes() {
+	let storage = Storage::new();
+	list(&storage, "l", &["b", "10", "B", "a", "9"]).await;
+	assert_eq!(ok(&storage, "SORT", vec![buf("l"), buf("ALPHA")]).await, bufs(&["10", "9", "B", "a", "b"]));
+	assert_eq!(ok(&storage, "SORT", vec![buf("l"), buf("alpha"), buf("desc")]).await, bufs(&["b", "a", "B", "9", "10"]));
+
+	ok(&storage, "SADD", vec![buf("s"), buf("y"), buf("x"), buf("z")]).await;
+	assert_eq!(ok(&storage, "SORT", vec![buf("s"), buf("ALPHA")]).await, bufs(&["x", "y", "z"]));
+}
+
+#[tokio::test]
+async fn non_numeric_element_is_an_error() {
+	let storage = Storage::new();
+	list(&storage, "l", &["1", "two"]).await;
+	assert_eq!(err(&storage, "SORT", vec![buf("l")]).await, "ERR One or more elements can't be converted into double");
+}
+
+#[tokio::test]
+async fn store_overwrites_the_destination() {
+	let storage = Storage::new();
+	list(&storage, "l", &["2", "1", "3"]).await;
+	ok(&storage, "HSET", vec![buf("dst"), buf("f"), buf("v")]).await;
+	assert_eq!(ok(&storage, "SORT", vec![buf("l"), buf("STORE"), buf("dst")]).await, int(3));
+	assert_eq!(ok(&storage, "TYPE", vec![buf("dst")]).await, buf("list"));
+	assert_eq!(ok(&storage, "LRANGE", vec![buf("dst"), int(0), int(-1)]).await, bufs(&["1", "2", "3"]));
+
+	// an empty result removes the destination
+	assert_eq!(ok(&storage, "SORT", vec![buf("missing"), buf("STORE"), buf("dst")]).await, int(0));
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("dst")]).await, int(0));
+}
+
+#[tokio::test]
+async fn hash_and_string_are_wrong_types() {
+	let storage = Storage::new();
+	ok(&storage, "HSET", vec![buf("h"), buf("f"), buf("1")]).await;
+	ok(&storage, "SET", vec![buf("s"), buf("1")]).await;
+	assert!(err(&storage, "SORT", vec![buf("h")]).await.starts_with("WRONGTYPE"));
+	assert!(err(&storage, "SORT", vec![buf("s")]).await.starts_with("WRONGTYPE"));
+}
+
+#[tokio::test]
+async fn only_store_is_rejected_by_a_read_only_server() {
+	let storage = Storage::new();
+	list(&storage, "l", &["2", "1"]).await;
+	storage.config().set("read-only", "yes").unwrap();
+	assert_eq!(ok(&storage, "SORT", vec![buf("l")]).await, bufs(&["1", "2"]));
+	assert!(err(&storage, "SORT", vec![buf("l"), buf("STORE"), buf("dst")]).await.starts_with("READONLY"));
+}
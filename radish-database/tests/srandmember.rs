@@ -0,0 +1,62 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::collections::HashSet;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+async fn members(storage: &Storage, count: i64) -> Vec<Value> {
+	match ok(storage, "SRANDMEMBER", vec![buf("s"), int(count)]).await {
+		Value::Array(items) => items.into_iter().collect(),
+		value => panic!("unexpected reply {:?}", value),
+	}
+}
+
+#[tokio::test]
+async fn counts() {
+	let storage = Storage::new();
+	ok(&storage, "SADD", vec![buf("s"), buf("a"), buf("b"), buf("c")]).await;
+
+	let distinct = members(&storage, 10).await;
+	assert_eq!(distinct.len(), 3);
+	assert_eq!(distinct.iter().collect::<HashSet<_>>().len(), 3);
+	assert_eq!(members(&storage, 2).await.len(), 2);
+	assert_eq!(members(&storage, -7).await.len(), 7);
+	assert_eq!(ok(&storage, "SCARD", vec![buf("s")]).await, int(3));
+}
+
+#[tokio::test]
+async fn empty_set() {
+	let storage = Storage::new();
+	assert_eq!(ok(&storage, "SRANDMEMBER", vec![buf("s")]).await, Value::Nill);
+	assert_eq!(ok(&storage, "SRANDMEMBER", vec![buf("s"), int(-3)]).await, array(vec![]));
+}
+
+#[tokio::test]
+async fn huge_negative_count_is_rejected() {
+	let storage = Storage::new();
+	ok(&storage, "SADD", vec![buf("s"), buf("a")]).await;
+	assert_eq!(err(&storage, "SRANDMEMBER", vec![buf("s"), int(i64::MIN)]).await, "ERR value is out of range");
+	assert_eq!(err(&storage, "SRANDMEMBER", vec![buf("s"), int(-(1 << 40))]).await, "ERR value is out of range");
+
+	ok(&storage, "CONFIG", vec![buf("SET"), buf("max-collection-elements"), buf("5")]).await;
+	assert!(err(&storage, "SRANDMEMBER", vec![buf("s"), int(-6)]).await.contains("max-collection-elements"));
+	assert_eq!(members(&storage, -5).await.len(), 5);
+}
@@ -0,0 +1,100 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use radish_database::{Storage, StorageStats, MockClock, Clock};
+
+use common::*;
+
+fn storage() -> (Storage, Arc<MockClock>) {
+	let clock = Arc::new(MockClock::new());
+	(Storage::with_clock(clock.clone()), clock)
+}
+
+fn key_counts(stats: &StorageStats) -> [u64; 5] {
+	[stats.keys, stats.strings.keys, stats.lists.keys, stats.sets.keys, stats.hashes.keys]
+}
+
+#[tokio::test]
+async fn empty_storage() {
+	let (storage, _) = storage();
+	let stats = storage.stats().await;
+	assert_eq!(key_counts(&stats), [0, 0, 0, 0, 0]);
+	assert_eq!(stats.keys_with_ttl, 0);
+	assert_eq!(stats.nearest_expiration, None);
+	assert_eq!(stats.expired_keys, 0);
+}
+
+#[tokio::test]
+async fn counters_follow_creates_overwrites_and_expirations() {
+	let (storage, clock) = storage();
+	ok(&storage, "SET", vec![buf("s1"), buf("v")]).await;
+	ok(&storage, "SET", vec![buf("s2"), buf("v")]).await;
+	ok(&storage, "RPUSH", vec![buf("l1"), buf("a"), buf("b")]).await;
+	ok(&storage, "RPUSH", vec![buf("l2"), buf("a")]).await;
+	ok(&storage, "SADD", vec![buf("set"), buf("a")]).await;
+	ok(&storage, "HSET", vec![buf("h"), buf("f"), buf("v")]).await;
+	let stats = storage.stats().await;
+	assert_eq!(key_counts(&stats), [6, 2, 2, 1, 1]);
+	assert!(stats.lists.memory > 0);
+	assert!(stats.hashes.memory > 0);
+
+	// a list key becomes a string
+	ok(&storage, "DEL", vec![buf("l1")]).await;
+	ok(&storage, "SET", vec![buf("l1"), buf("v")]).await;
+	assert_eq!(key_counts(&storage.stats().await), [6, 3, 1, 1, 1]);
+
+	let now = clock.now_system().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+	ok(&storage, "PEXPIRE", vec![buf("s1"), int(1_000)]).await;
+	ok(&storage, "PEXPIRE", vec![buf("set"), int(5_000)]).await;
+	let stats = storage.stats().await;
+	assert_eq!(stats.keys_with_ttl, 2);
+	assert_eq!(stats.nearest_expiration, Some(now + 1_000));
+
+	clock.advance(&storage, Duration::from_millis(1_000)).await;
+	let stats = storage.stats().await;
+	assert_eq!(key_counts(&stats), [5, 2, 1, 1, 1]);
+	assert_eq!(stats.keys_with_ttl, 1);
+	assert_eq!(stats.nearest_expiration, Some(now + 5_000));
+	assert_eq!(stats.expired_keys, 1);
+
+	clock.advance(&storage, Duration::from_millis(4_000)).await;
+	let stats = storage.stats().await;
+	assert_eq!(key_counts(&stats), [4, 2, 1, 0, 1]);
+	assert_eq!(stats.keys_with_ttl, 0);
+	assert_eq!(stats.nearest_expiration, None);
+	assert_eq!(stats.expired_keys, 2);
+
+	// removal by DEL is not an expiration
+	ok(&storage, "DEL", vec![buf("s2")]).await;
+	let stats = storage.stats().await;
+	assert_eq!(key_counts(&stats), [3, 1, 1, 0, 1]);
+	assert_eq!(stats.expired_keys, 2);
+}
+
+#[tokio::test]
+async fn stats_are_serializable() {
+	let (storage, _) = storage();
+	ok(&storage, "SET", vec![buf("k"), buf("v"), buf("EX"), int(10)]).await;
+	ok(&storage, "SADD", vec![buf("set"), buf("a")]).await;
+	let stats = storage.stats().await;
+	let json = serde_json::to_string(&stats).unwrap();
+	assert_eq!(serde_json::from_str::<StorageStats>(&json).unwrap(), stats);
+}
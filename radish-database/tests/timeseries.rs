@@ -0,0 +1,134 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+fn samples(expected: &[(i64, f64)]) -> Value {
+	array(expected.iter().map(|&(ts, value)|array(vec![int(ts), float(value)])).collect())
+}
+
+async fn add(storage: &Storage, key: &str, samples: &[(i64, f64)]) {
+	for &(ts, value) in samples {
+		assert_eq!(ok(storage, "TS.ADD", vec![buf(key), int(ts), float(value)]).await, int(ts));
+	}
+}
+
+async fn range(storage: &Storage, key: &str, from: Value, to: Value, aggregation: Option<(&str, i64)>) -> Value {
+	let mut args = vec![buf(key), from, to];
+	if let Some((name, bucket)) = aggregation {
+		args.extend(vec![buf("AGGREGATION"), buf(name), int(bucket)]);
+	}
+	ok(storage, "TS.RANGE", args).await
+}
+
+const SERIES: &[(i64, f64)] = &[(1000, 1.0), (1500, 3.0), (1999, 5.0), (2000, 10.0), (2500, 2.0), (4100, 7.0)];
+
+#[tokio::test]
+async fn out_of_order_samples_are_rejected() {
+	let storage = Storage::new();
+	add(&storage, "ts", &[(1000, 1.0), (2000, 2.0)]).await;
+	assert_eq!(err(&storage, "TS.ADD", vec![buf("ts"), int(1500), float(3.0)]).await, "ERR timestamp 1500 is not newer than the latest sample 2000");
+	assert_eq!(err(&storage, "TS.ADD", vec![buf("ts"), int(2000), float(3.0)]).await, "ERR timestamp 2000 is not newer than the latest sample 2000");
+	assert_eq!(range(&storage, "ts", buf("-"), buf("+"), None).await, samples(&[(1000, 1.0), (2000, 2.0)]));
+	assert_eq!(ok(&storage, "TS.GET", vec![buf("ts")]).await, array(vec![int(2000), float(2.0)]));
+
+	// the current time is always newer than the samples above
+	match ok(&storage, "TS.ADD", vec![buf("ts"), buf("*"), buf("4.5")]).await {
+		Value::Integer(ts) => assert!(ts > 2000),
+		reply => panic!("unexpected TS.ADD reply {:?}", reply),
+	}
+	assert_eq!(err(&storage, "TS.ADD", vec![buf("ts"), int(-1), float(1.0)]).await, "ERR invalid timestamp");
+	assert_eq!(err(&storage, "TS.ADD", vec![buf("ts"), buf("*"), buf("nan")]).await, "ERR invalid value");
+	assert_eq!(err(&storage, "TS.ADD", vec![buf("ts"), buf("*"), buf("x")]).await, "ERR invalid value");
+}
+
+#[tokio::test]
+async fn retention_prunes_old_samples_on_insert() {
+	let storage = Storage::new();
+	assert_eq!(ok(&storage, "TS.CREATE", vec![buf("ts"), buf("RETENTION"), int(1000), buf("LABELS"), buf("host"), buf("a")]).await, Value::Ok);
+	add(&storage, "ts", &[(1000, 1.0), (1500, 2.0), (2000, 3.0)]).await;
+	// 2000 - 1000 keeps the sample at exactly 1000
+	assert_eq!(range(&storage, "ts", buf("-"), buf("+"), None).await, samples(&[(1000, 1.0), (1500, 2.0), (2000, 3.0)]));
+	add(&storage, "ts", &[(2100, 4.0)]).await;
+	assert_eq!(range(&storage, "ts", buf("-"), buf("+"), None).await, samples(&[(1500, 2.0), (2000, 3.0), (2100, 4.0)]));
+	add(&storage, "ts", &[(5000, 5.0)]).await;
+	assert_eq!(range(&storage, "ts", buf("-"), buf("+"), None).await, samples(&[(5000, 5.0)]));
+
+	assert_eq!(ok(&storage, "TS.INFO", vec![buf("ts")]).await, array(vec![
+		buf("totalSamples"), int(1),
+		buf("retentionTime"), int(1000),
+		buf("firstTimestamp"), int(5000),
+		buf("lastTimestamp"), int(5000),
+		buf("labels"), array(vec![bufs(&["host", "a"])]),
+	]));
+
+	// without retention nothing is pruned
+	add(&storage, "forever", &[(0, 1.0), (u32::MAX as i64, 2.0)]).await;
+	assert_eq!(range(&storage, "forever", buf("-"), buf("+"), None).await, samples(&[(0, 1.0), (u32::MAX as i64, 2.0)]));
+}
+
+#[tokio::test]
+async fn range_bounds_are_inclusive() {
+	let storage = Storage::new();
+	add(&storage, "ts", SERIES).await;
+	assert_eq!(range(&storage, "ts", int(1500), int(2000), None).await, samples(&SERIES[1..4]));
+	assert_eq!(range(&storage, "ts", int(1501), int(1998), None).await, samples(&[]));
+	assert_eq!(range(&storage, "ts", int(3000), int(1000), None).await, samples(&[]));
+	assert_eq!(range(&storage, "ts", int(0), int(999), None).await, samples(&[]));
+	assert_eq!(range(&storage, "ts", int(4100), buf("+"), None).await, samples(&SERIES[5..]));
+	assert_eq!(range(&storage, "missing", buf("-"), buf("+"), None).await, samples(&[]));
+}
+
+#[tokio::test]
+async fn aggregation_bucket_math() {
+	let storage = Storage::new();
+	add(&storage, "ts", SERIES).await;
+	// buckets [1000; 2000) and [2000; 3000), the empty [3000; 4000) is omitted
+	assert_eq!(range(&storage, "ts", buf("-"), buf("+"), Some(("avg", 1000))).await, samples(&[(1000, 3.0), (2000, 6.0), (4000, 7.0)]));
+	assert_eq!(range(&storage, "ts", buf("-"), buf("+"), Some(("MIN", 1000))).await, samples(&[(1000, 1.0), (2000, 2.0), (4000, 7.0)]));
+	assert_eq!(range(&storage, "ts", buf("-"), buf("+"), Some(("max", 1000))).await, samples(&[(1000, 5.0), (2000, 10.0), (4000, 7.0)]));
+	assert_eq!(range(&storage, "ts", buf("-"), buf("+"), Some(("sum", 1000))).await, samples(&[(1000, 9.0), (2000, 12.0), (4000, 7.0)]));
+
+	// buckets are aligned to multiples of the duration, not to the first sample
+	assert_eq!(range(&storage, "ts", buf("-"), buf("+"), Some(("avg", 700))).await, samples(&[(700, 1.0), (1400, 6.0), (2100, 2.0), (3500, 7.0)]));
+	// the range is cut before aggregating
+	assert_eq!(range(&storage, "ts", int(1500), int(2500), Some(("avg", 1000))).await, samples(&[(1000, 4.0), (2000, 6.0)]));
+	assert_eq!(range(&storage, "ts", buf("-"), buf("+"), Some(("sum", 10000))).await, samples(&[(0, 28.0)]));
+	assert_eq!(range(&storage, "ts", buf("-"), buf("+"), Some(("max", 1))).await, samples(SERIES));
+
+	assert_eq!(err(&storage, "TS.RANGE", vec![buf("ts"), buf("-"), buf("+"), buf("AGGREGATION"), buf("avg"), int(0)]).await, "ERR bucket duration must be positive");
+	assert_eq!(err(&storage, "TS.RANGE", vec![buf("ts"), buf("-"), buf("+"), buf("AGGREGATION"), buf("median"), int(10)]).await, "ERR unknown aggregation 'median'");
+	assert_eq!(err(&storage, "TS.RANGE", vec![buf("ts"), buf("-"), buf("+"), buf("COUNT"), int(10)]).await, "ERR syntax error");
+}
+
+#[tokio::test]
+async fn create_get_and_wrongtype() {
+	let storage = Storage::new();
+	assert_eq!(ok(&storage, "TS.GET", vec![buf("ts")]).await, Value::Nill);
+	assert_eq!(ok(&storage, "TS.CREATE", vec![buf("ts")]).await, Value::Ok);
+	assert_eq!(ok(&storage, "TS.GET", vec![buf("ts")]).await, array(vec![]));
+	assert_eq!(err(&storage, "TS.CREATE", vec![buf("ts")]).await, "ERR key already exists");
+	assert_eq!(err(&storage, "TS.CREATE", vec![buf("other"), buf("LABELS"), buf("odd")]).await, "ERR syntax error");
+	assert_eq!(ok(&storage, "TYPE", vec![buf("ts")]).await, buf("TSDB-TYPE"));
+
+	ok(&storage, "SET", vec![buf("s"), buf("v")]).await;
+	assert!(err(&storage, "TS.ADD", vec![buf("s"), int(1), float(1.0)]).await.starts_with("WRONGTYPE"));
+	assert!(err(&storage, "TS.RANGE", vec![buf("s"), buf("-"), buf("+")]).await.starts_with("WRONGTYPE"));
+}
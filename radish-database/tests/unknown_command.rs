@@ -0,0 +1,55 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::Storage;
+
+use common::*;
+
+#[tokio::test]
+async fn near_miss_suggests_the_command() {
+	let storage = Storage::new();
+	assert_eq!(
+		err(&storage, "HSETT", vec![buf("h"), buf("f"), buf("v")]).await,
+		"ERR unknown command 'HSETT', with args beginning with: 'h' 'f' 'v' (did you mean 'hset'?)",
+	);
+	assert_eq!(err(&storage, "lrnge", vec![]).await, "ERR unknown command 'lrnge' (did you mean 'lrange'?)");
+	assert_eq!(
+		err(&storage, "HELP", vec![buf("EXPIREATT")]).await,
+		"ERR unknown command 'EXPIREATT' (did you mean 'expireat'?)",
+	);
+}
+
+#[tokio::test]
+async fn far_miss_has_no_suggestion() {
+	let storage = Storage::new();
+	assert_eq!(
+		err(&storage, "FOOBARBAZ", vec![buf("a"), int(1), buf("c"), buf("d")]).await,
+		"ERR unknown command 'FOOBARBAZ', with args beginning with: 'a' '1' 'c'",
+	);
+	// too short for a typo to be told from another command
+	assert_eq!(err(&storage, "GTE", vec![buf("a")]).await, "ERR unknown command 'GTE', with args beginning with: 'a'");
+}
+
+#[tokio::test]
+async fn renamed_commands_are_not_suggested() {
+	let storage = Storage::new();
+	storage.rename_command("HSET", "SECRETHSET").unwrap();
+	assert_eq!(err(&storage, "HSETT", vec![]).await, "ERR unknown command 'HSETT'");
+	assert_eq!(err(&storage, "HSET", vec![buf("h"), buf("f"), buf("v")]).await, "ERR unknown command 'HSET', with args beginning with: 'h' 'f' 'v' (did you mean 'hget'?)");
+	assert_eq!(ok(&storage, "SECRETHSET", vec![buf("h"), buf("f"), buf("v")]).await, int(1));
+}
@@ -0,0 +1,109 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod common;
+
+use radish_database::{Storage, Value};
+
+use common::*;
+
+const WRONG_TYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+const KINDS: [&str; 4] = ["string", "list", "set", "hash"];
+
+async fn create(storage: &Storage, kind: &str) {
+	match kind {
+		"string" => ok(storage, "SET", vec![buf("k"), buf("v")]).await,
+		"list" => ok(storage, "LPUSH", vec![buf("k"), buf("v")]).await,
+		"set" => ok(storage, "SADD", vec![buf("k"), buf("v")]).await,
+		"hash" => ok(storage, "HSET", vec![buf("k"), buf("f"), buf("v")]).await,
+		_ => unreachable!(),
+	};
+}
+
+/// Reading and writing commands of the kind
+fn commands(kind: &str) -> Vec<(&'static str, Vec<Value>)> {
+	match kind {
+		"string" => vec![
+			("GET", vec![buf("k")]),
+			("STRLEN", vec![buf("k")]),
+			("APPEND", vec![buf("k"), buf("x")]),
+			("INCR", vec![buf("k")]),
+			("GETSET", vec![buf("k"), buf("x")]),
+		],
+		"list" => vec![
+			("LRANGE", vec![buf("k"), int(0), int(-1)]),
+			("LLEN", vec![buf("k")]),
+			("LPUSH", vec![buf("k"), buf("x")]),
+			("RPOP", vec![buf("k")]),
+		],
+		"set" => vec![
+			("SMEMBERS", vec![buf("k")]),
+			("SISMEMBER", vec![buf("k"), buf("v")]),
+			("SADD", vec![buf("k"), buf("x")]),
+			("SREM", vec![buf("k"), buf("v")]),
+		],
+		"hash" => vec![
+			("HGET", vec![buf("k"), buf("f")]),
+			("HGETALL", vec![buf("k")]),
+			("HSET", vec![buf("k"), buf("x"), buf("y")]),
+			("HDEL", vec![buf("k"), buf("f")]),
+		],
+		_ => unreachable!(),
+	}
+}
+
+#[tokio::test]
+async fn every_cross_type_command_is_wrongtype() {
+	for &existing in KINDS.iter() {
+		for &other in KINDS.iter().filter(|&&k|k != existing) {
+			for (name, args) in commands(other) {
+				let storage = Storage::new();
+				create(&storage, existing).await;
+				assert_eq!(err(&storage, name, args).await, WRONG_TYPE, "{} against a {} key", name, existing);
+				assert_eq!(ok(&storage, "TYPE", vec![buf("k")]).await, buf(existing), "{} changed a {} key", name, existing);
+			}
+		}
+	}
+}
+
+#[tokio::test]
+async fn multi_key_commands_check_every_key_before_writing() {
+	let storage = Storage::new();
+	ok(&storage, "SADD", vec![buf("a"), buf("x")]).await;
+	ok(&storage, "LPUSH", vec![buf("b"), buf("x")]).await;
+	assert_eq!(err(&storage, "SUNIONSTORE", vec![buf("dst"), buf("a"), buf("b")]).await, WRONG_TYPE);
+	assert_eq!(err(&storage, "SINTER", vec![buf("a"), buf("b")]).await, WRONG_TYPE);
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("dst")]).await, int(0));
+}
+
+#[tokio::test]
+async fn wrongtype_does_not_create_missing_keys() {
+	let storage = Storage::new();
+	ok(&storage, "SET", vec![buf("s"), buf("v")]).await;
+	ok(&storage, "LPUSH", vec![buf("l"), buf("v")]).await;
+	assert_eq!(err(&storage, "RPOPLPUSH", vec![buf("s"), buf("fresh")]).await, WRONG_TYPE);
+	assert_eq!(err(&storage, "SUNIONSTORE", vec![buf("fresh"), buf("l")]).await, WRONG_TYPE);
+	assert_eq!(ok(&storage, "EXISTS", vec![buf("fresh")]).await, int(0));
+}
+
+#[tokio::test]
+async fn set_replaces_a_key_of_any_type() {
+	let storage = Storage::new();
+	ok(&storage, "LPUSH", vec![buf("l"), buf("v")]).await;
+	assert_eq!(run(&storage, "SET", vec![buf("l"), buf("v")]).await, Value::Ok);
+	assert_eq!(ok(&storage, "TYPE", vec![buf("l")]).await, buf("string"));
+}
@@ -0,0 +1,159 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! `#[derive(HashModel)]` maps the named fields of a struct to the fields of a hash.
+//! Every field type must implement `radish_client::HashField`; `Option` fields are
+//! not written when None and are None when absent. Field attributes:
+//! `#[radish(rename = "name")]` sets the hash field name, `#[radish(skip)]` leaves
+//! the field out and fills it with `Default::default()` on load.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Lit, Meta, NestedMeta, Type};
+
+struct FieldOptions {
+	rename: Option<String>,
+	skip: bool,
+}
+
+fn field_options(attrs: &[syn::Attribute]) -> syn::Result<FieldOptions> {
+	let mut options = FieldOptions {
+		rename: None,
+		skip: false,
+	};
+	for attr in attrs.iter().filter(|attr|attr.path.is_ident("radish")) {
+		let list = match attr.parse_meta()? {
+			Meta::List(list) => list,
+			meta => return Err(syn::Error::new_spanned(meta, "expected #[radish(...)]")),
+		};
+		for item in list.nested {
+			match item {
+				NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => options.skip = true,
+				NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("rename") => match nv.lit {
+					Lit::Str(name) => options.rename = Some(name.value()),
+					lit => return Err(syn::Error::new_spanned(lit, "rename expects a string")),
+				},
+				item => return Err(syn::Error::new_spanned(item, "unknown radish attribute: expected rename or skip")),
+			}
+		}
+	}
+	Ok(options)
+}
+
+fn is_option(ty: &Type) -> bool {
+	match ty {
+		Type::Path(path) => path.qself.is_none() && path.path.segments.last().is_some_and(|s|s.ident == "Option"),
+		_ => false,
+	}
+}
+
+#[proc_macro_derive(HashModel, attributes(radish))]
+pub fn derive_hash_model(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+	match expand(&input) {
+		Ok(tokens) => tokens.into(),
+		Err(err) => err.to_compile_error().into(),
+	}
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+	let fields = match &input.data {
+		Data::Struct(data) => match &data.fields {
+			Fields::Named(fields) => &fields.named,
+			_ => return Err(syn::Error::new_spanned(&input.ident, "HashModel requires a struct with named fields")),
+		},
+		_ => return Err(syn::Error::new(Span::call_site(), "HashModel can be derived only for structs")),
+	};
+
+	let mut writes = Vec::new();
+	let mut slots = Vec::new();
+	let mut reads = Vec::new();
+	let mut inits = Vec::new();
+	for field in fields {
+		let ident = field.ident.as_ref().expect("named field");
+		let options = field_options(&field.attrs)?;
+		if options.skip {
+			inits.push(quote! {#ident: ::std::default::Default::default()});
+			continue;
+		}
+		let name = options.rename.unwrap_or_else(||ident.to_string().trim_start_matches("r#").to_owned());
+		let slot = format_ident!("__field_{}", ident);
+		let optional = is_option(&field.ty);
+
+		writes.push(if optional {
+			quote! {
+				if let ::std::option::Option::Some(value) = &self.#ident {
+					fields.push((::radish_client::Value::Buffer(#name.as_bytes().to_vec()), ::radish_client::HashField::to_field(value)));
+				}
+			}
+		} else {
+			quote! {
+				fields.push((::radish_client::Value::Buffer(#name.as_bytes().to_vec()), ::radish_client::HashField::to_field(&self.#ident)));
+			}
+		});
+		slots.push(quote! {let mut #slot = ::std::option::Option::None;});
+		reads.push(quote! {
+			#name => #slot = ::std::option::Option::Some(::radish_client::HashField::from_field(value)
+				.map_err(|e|::std::format!("field '{}': {}", #name, e))?),
+		});
+		inits.push(if optional {
+			quote! {#ident: #slot}
+		} else {
+			quote! {#ident: #slot.ok_or_else(||::std::format!("missing field '{}'", #name))?}
+		});
+	}
+
+	let load = if reads.is_empty() {
+		quote! {let _ = fields;}
+	} else {
+		quote! {
+			for (field, value) in fields {
+				let field = match field {
+					::radish_client::Value::Buffer(field) => ::std::string::String::from_utf8_lossy(&field).into_owned(),
+					field => return ::std::result::Result::Err(::std::format!("unexpected field name {:?}", field)),
+				};
+				match &field[..] {
+					#(#reads)*
+					_ => (),
+				}
+			}
+		}
+	};
+
+	let ident = &input.ident;
+	let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+	Ok(quote! {
+		impl #impl_generics ::radish_client::HashModel for #ident #ty_generics #where_clause {
+			fn to_hash(&self) -> ::std::vec::Vec<(::radish_client::Value, ::radish_client::Value)> {
+				let mut fields = ::std::vec::Vec::new();
+				#(#writes)*
+				fields
+			}
+
+			fn from_hash(fields: ::std::vec::Vec<(::radish_client::Value, ::radish_client::Value)>) -> ::std::result::Result<Self, ::std::string::String> {
+				#(#slots)*
+				#load
+				::std::result::Result::Ok(Self {
+					#(#inits,)*
+				})
+			}
+		}
+	})
+}
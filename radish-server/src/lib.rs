@@ -0,0 +1,225 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Radish server: the listeners of a storage and the connections they accept.
+//! The binary adds the configuration, logging and signals; an embedder or a test
+//! can serve a storage in-process with `Server` and stop it with `ServerHandle`.
+
+mod listener;
+pub mod logging;
+mod proxy;
+mod shutdown;
+mod tls;
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use radish_types::*;
+use radish_database::{Storage, Session};
+
+pub use shutdown::DRAIN_TIMEOUT;
+
+/// Connection buffers grown above this size are released after the frame
+const MAX_RETAINED_BUFFER: usize = 1024 * 1024;
+
+/// Reused between frames; the capacity is kept unless it grew too big
+fn recycle(buf: &mut Vec<u8>) {
+	buf.clear();
+	if buf.capacity() > MAX_RETAINED_BUFFER {
+		buf.shrink_to_fit();
+	}
+}
+
+/// Fails with "Idle timeout" if the client sends nothing for `timeout` seconds; 0 disables the timeout
+async fn read_with_timeout<S: AsyncRead + Unpin>(sock: &mut S, buf: &mut [u8], timeout: usize) -> Result<std::io::Result<usize>, String> {
+	if timeout == 0 {
+		Ok(sock.read_exact(buf).await)
+	} else {
+		tokio::time::timeout(Duration::from_secs(timeout as u64), sock.read_exact(buf))
+			.await
+			.map_err(|_|"Idle timeout".to_owned())
+	}
+}
+
+/// Ok once the shutdown is triggered; a command being executed is finished and replied first
+async fn command_loop_executor<S>(conn_name: &str, mut sock: S, addr: Option<String>, storage: &Storage, mut shutdown: shutdown::Shutdown) -> Result<(), String>
+where S: AsyncRead + AsyncWrite + Unpin {
+	let mut session = Session::new();
+	session.addr = addr;
+	let mut input = Vec::new();
+	let mut output = Vec::new();
+	// a client with the handshake starts with a hello, any other first frame is a command of v0
+	let mut first_frame = true;
+	loop {
+		// read per frame, so a reloaded timeout applies to open connections too
+		let timeout = storage.config().timeout();
+		let mut header = [0; frame::HEADER_SIZE];
+		let read = read_with_timeout(&mut sock, &mut header, timeout);
+		let read = tokio::select! {
+			read = read => read?,
+			_ = shutdown.wait() => return Ok(()),
+		};
+		read.map_err(|_|"Failed to read frame size".to_owned())?;
+		let len = match frame::decode_header(header, storage.config().max_frame_size()) {
+			Ok((_, true)) => Err("Compressed commands are not supported".to_owned()),
+			Ok((len, false)) => Ok(len),
+			Err(err) => Err(err),
+		};
+		let len = match len {
+			Ok(len) => len,
+			Err(err) => {
+				// the body is left unread, so the connection is closed after the reply
+				output.extend_from_slice(&[0; frame::HEADER_SIZE]);
+				rmp_serde::encode::write(&mut output, &Value::Error(format!("ERR {}", err))).map_err(|_|"Failed to serialize result".to_owned())?;
+				let reply = frame::encode_header(output.len() - frame::HEADER_SIZE, false)?;
+				output[..frame::HEADER_SIZE].copy_from_slice(&reply);
+				let _ = sock.write_all(&output[..]).await;
+				return Err(err);
+			},
+		};
+		input.resize(len, 0);
+		read_with_timeout(&mut sock, &mut input[..], timeout).await?.map_err(|_|"Failed to read command".to_owned())?;
+
+		if first_frame {
+			first_frame = false;
+			if let Some(hello) = handshake::Hello::decode(&input)? {
+				let reply = handshake::Hello::new(handshake::SUPPORTED_FEATURES).negotiate(&hello);
+				log::debug!("{}: handshake {:?}", conn_name, reply);
+				session.protocol_version = reply.version;
+				session.compression = reply.has(handshake::FEATURE_COMPRESSION);
+				let hello = reply.encode();
+				output.extend_from_slice(&frame::encode_header(hello.len(), false)?);
+				output.extend_from_slice(&hello);
+				sock.write_all(&output[..]).await.map_err(|_|"Failed to write handshake".to_owned())?;
+				recycle(&mut output);
+				recycle(&mut input);
+				continue;
+			}
+		}
+
+		let cmd: Command = rmp_serde::from_read_ref(&input).map_err(|_|"Failed to deserialize command".to_owned())?;
+		recycle(&mut input);
+		let traced = log::log_enabled!(log::Level::Debug).then(||(cmd.command.clone(), Instant::now()));
+		if let Some((command, _)) = &traced {
+			log::debug!(conn = session.id, peer = conn_name, command = command.as_str(); "{}: {}", conn_name, storage.display_command(&cmd));
+		}
+		let result = storage.execute(&mut session, cmd).await;
+		if let Some((command, started)) = &traced {
+			let duration_us = started.elapsed().as_micros() as u64;
+			log::debug!(conn = session.id, peer = conn_name, command = command.as_str(), duration_us, result = logging::result_kind(&result); "{}: {}", conn_name, result);
+		}
+
+		// the size is patched in after the body is serialized, so the frame goes out in one write
+		output.extend_from_slice(&[0; frame::HEADER_SIZE]);
+		rmp_serde::encode::write(&mut output, &result).map_err(|_|"Failed to serialize result".to_owned())?;
+		let compressed = if session.compression {
+			compression::compress_frame(&output[frame::HEADER_SIZE..], storage.config().compression_threshold())
+		} else {
+			None
+		};
+		let compressed = match compressed {
+			Some(compressed) => {
+				output.truncate(frame::HEADER_SIZE);
+				output.extend_from_slice(&compressed);
+				true
+			},
+			None => false,
+		};
+		let header = frame::encode_header(output.len() - frame::HEADER_SIZE, compressed)?;
+		output[..frame::HEADER_SIZE].copy_from_slice(&header);
+		sock.write_all(&output[..]).await.map_err(|_|"Failed to write result".to_owned())?;
+		recycle(&mut output);
+	}
+}
+
+/// Listeners bound for a storage; connections are accepted once it is served
+pub struct Server {
+	storage: Arc<Storage>,
+	listeners: Vec<listener::Listener>,
+}
+
+/// Server running in a background task, see `Server::start`
+pub struct ServerHandle {
+	stop: oneshot::Sender<()>,
+	task: JoinHandle<Result<(), String>>,
+}
+
+impl Server {
+	/// Binds every listener configured in the storage; the error names the listener which failed
+	pub async fn bind(storage: Storage) -> Result<Self, String> {
+		let listeners = listener::bind_all(&storage).await?;
+		Ok(Self {storage: Arc::new(storage), listeners})
+	}
+
+	/// Transport and bound address of each listener, e.g. "tcp 127.0.0.1:6142"
+	pub fn listeners(&self) -> Vec<String> {
+		self.listeners.iter().map(|l|l.name.clone()).collect()
+	}
+
+	/// Accepts connections until `stop` completes or a listener fails. Then the connections finish
+	/// the commands they are executing and close; it returns once they are drained or after `DRAIN_TIMEOUT`
+	pub async fn serve<F>(self, stop: F) -> Result<(), String>
+	where F: Future<Output=()> {
+		let Self {storage, listeners} = self;
+		let (trigger, shutdown) = shutdown::channel();
+		let (done, mut finished) = mpsc::channel(1);
+		for listener in listeners {
+			let storage = storage.clone();
+			let shutdown = shutdown.clone();
+			let mut done = done.clone();
+			tokio::spawn(async move {
+				let result = listener::accept_loop(listener, storage, shutdown).await;
+				let _ = done.send(result).await;
+			});
+		}
+		drop(done);
+		drop(shutdown);
+		let result = tokio::select! {
+			result = finished.recv() => result.unwrap_or(Ok(())),
+			_ = stop => {
+				log::info!("shutting down");
+				Ok(())
+			},
+		};
+		if !trigger.drain(DRAIN_TIMEOUT).await {
+			log::warn!("connections are still open after {:?}", DRAIN_TIMEOUT);
+		}
+		listener::cleanup(&storage);
+		result
+	}
+
+	/// Serves in a background task until the handle shuts it down
+	pub fn start(self) -> ServerHandle {
+		let (stop, stopped) = oneshot::channel();
+		let task = tokio::spawn(self.serve(async {
+			let _ = stopped.await;
+		}));
+		ServerHandle {stop, task}
+	}
+}
+
+impl ServerHandle {
+	/// Triggers the graceful shutdown and waits until the server is drained
+	pub async fn shutdown(self) -> Result<(), String> {
+		let _ = self.stop.send(());
+		self.task.await.map_err(|e|format!("Server task failed: {}", e))?
+	}
+}
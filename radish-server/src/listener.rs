@@ -0,0 +1,265 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Listeners of the server: plain TCP and TLS on every address of `bind`, and a unix socket.
+//! Each socket has its own accept loop feeding the shared Storage. With `proxy-protocol`
+//! TCP and TLS connections are identified by the client address from the PROXY header.
+
+use std::sync::Arc;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::TlsAcceptor;
+
+use radish_database::Storage;
+
+use super::shutdown::Shutdown;
+
+enum Socket {
+	Tcp(TcpListener),
+	Tls(TcpListener, TlsAcceptor),
+	#[cfg(unix)]
+	Unix(UnixListener),
+}
+
+pub struct Listener {
+	/// Id of the listener in the INFO counters; shared by the SO_REUSEPORT sockets of an address
+	id: usize,
+	/// Transport and address, e.g. "tls 0.0.0.0:6143"
+	pub name: String,
+	socket: Socket,
+}
+
+#[cfg(unix)]
+fn reuseport_listener(addr: &SocketAddr) -> std::io::Result<std::net::TcpListener> {
+	use net2::unix::UnixTcpBuilderExt;
+
+	let builder = if addr.is_ipv4() {net2::TcpBuilder::new_v4()?} else {net2::TcpBuilder::new_v6()?};
+	builder.reuse_address(true)?;
+	builder.reuse_port(true)?;
+	let listener = builder.bind(addr)?.listen(1024)?;
+	listener.set_nonblocking(true)?;
+	Ok(listener)
+}
+
+#[cfg(not(unix))]
+fn reuseport_listener(_addr: &SocketAddr) -> std::io::Result<std::net::TcpListener> {
+	Err(std::io::Error::new(std::io::ErrorKind::Other, "SO_REUSEPORT is not supported on this platform"))
+}
+
+fn reuseport_listeners(addr: &str, count: usize) -> std::io::Result<Vec<TcpListener>> {
+	let addr = addr.to_socket_addrs()?
+		.next()
+		.ok_or_else(||std::io::Error::new(std::io::ErrorKind::InvalidInput, "No address to bind"))?;
+	let first = reuseport_listener(&addr)?;
+	// with port 0 the rest share the ephemeral port assigned to the first one
+	let addr = first.local_addr()?;
+	let mut listeners = vec![TcpListener::from_std(first)?];
+	for _ in 1..count {
+		listeners.push(reuseport_listener(&addr).and_then(TcpListener::from_std)?);
+	}
+	Ok(listeners)
+}
+
+/// Several listeners need SO_REUSEPORT; without it the server falls back to one
+async fn bind_tcp(addr: &str, count: usize) -> std::io::Result<Vec<TcpListener>> {
+	if count > 1 {
+		match reuseport_listeners(addr, count) {
+			Ok(listeners) => return Ok(listeners),
+			Err(err) => log::warn!("failed to bind {} listeners with SO_REUSEPORT, using one: {}", count, err),
+		}
+	}
+	Ok(vec![TcpListener::bind(addr).await?])
+}
+
+/// A stale socket file of a previous run is removed before binding
+#[cfg(unix)]
+fn bind_unix(path: &str) -> std::io::Result<Socket> {
+	let _ = std::fs::remove_file(path);
+	Ok(Socket::Unix(UnixListener::bind(path)?))
+}
+
+#[cfg(not(unix))]
+fn bind_unix(_path: &str) -> std::io::Result<Socket> {
+	Err(std::io::Error::new(std::io::ErrorKind::Other, "Unix sockets are not supported on this platform"))
+}
+
+/// Binds every configured listener; the error names the listener which failed
+pub async fn bind_all(storage: &Storage) -> Result<Vec<Listener>, String> {
+	let config = storage.config();
+	let bind = config.bind();
+	let mut listeners = Vec::new();
+
+	for host in bind.split_whitespace() {
+		let addr = format!("{}:{}", host, config.port());
+		let sockets = bind_tcp(&addr, config.reuseport_listeners())
+			.await
+			.map_err(|e|format!("Failed to bind listener 'tcp {}': {}", addr, e))?;
+		// port 0 gets an ephemeral port, which is logged with the name of the listener
+		let addr = sockets[0].local_addr().map(|a|a.to_string()).unwrap_or(addr);
+		let name = format!("tcp {}", addr);
+		let id = storage.add_listener("tcp", &addr);
+		listeners.extend(sockets.into_iter().map(|socket|Listener {id, name: name.clone(), socket: Socket::Tcp(socket)}));
+	}
+
+	if config.tls_port() != 0 {
+		let acceptor = super::tls::acceptor(&config.tls_cert_file(), &config.tls_key_file())?;
+		for host in bind.split_whitespace() {
+			let name = format!("tls {}:{}", host, config.tls_port());
+			let socket = TcpListener::bind(&name[4..])
+				.await
+				.map_err(|e|format!("Failed to bind listener '{}': {}", name, e))?;
+			let id = storage.add_listener("tls", &name[4..]);
+			listeners.push(Listener {id, name, socket: Socket::Tls(socket, acceptor.clone())});
+		}
+	}
+
+	let path = config.unixsocket();
+	if !path.is_empty() {
+		let name = format!("unix {}", path);
+		let socket = bind_unix(&path).map_err(|e|format!("Failed to bind listener '{}': {}", name, e))?;
+		let id = storage.add_listener("unix", &path);
+		listeners.push(Listener {id, name, socket});
+	}
+
+	if listeners.is_empty() {
+		return Err("No listeners configured: set port, tls-port or unixsocket".to_owned());
+	}
+	Ok(listeners)
+}
+
+/// Removes the socket file of the unix listener
+pub fn cleanup(storage: &Storage) {
+	let path = storage.config().unixsocket();
+	if !path.is_empty() {
+		if let Err(err) = std::fs::remove_file(&path) {
+			log::warn!("failed to remove unix socket '{}': {}", path, err);
+		}
+	}
+}
+
+fn configure_tcp(conn_name: &str, sock: &TcpStream, keepalive: usize) {
+	if let Err(err) = sock.set_nodelay(true) {
+		log::warn!("{}: failed to set TCP_NODELAY: {}", conn_name, err);
+	}
+	if keepalive > 0 {
+		if let Err(err) = sock.set_keepalive(Some(Duration::from_secs(keepalive as u64))) {
+			log::warn!("{}: failed to set SO_KEEPALIVE: {}", conn_name, err);
+		}
+	}
+}
+
+/// The address from the PROXY header if `proxy-protocol` is on, otherwise the peer address.
+/// None if the connection has to be dropped for a missing or malformed header
+async fn client_addr(sock: &mut TcpStream, peer: SocketAddr, storage: &Storage) -> Option<SocketAddr> {
+	if !storage.config().proxy_protocol() {
+		return Some(peer);
+	}
+	match tokio::time::timeout(super::proxy::HEADER_TIMEOUT, super::proxy::read_header(sock)).await {
+		Ok(Ok(source)) => Some(source.unwrap_or(peer)),
+		Ok(Err(err)) => {
+			log::info!("{:?}: {}", peer, err);
+			None
+		},
+		Err(_) => {
+			log::info!("{:?}: no PROXY header in {:?}", peer, super::proxy::HEADER_TIMEOUT);
+			None
+		},
+	}
+}
+
+fn log_connected(addr: &SocketAddr, peer: &SocketAddr, listener: &str) {
+	if addr == peer {
+		log::info!(peer:% = addr, listener; "{:?}: connected to {}", addr, listener);
+	} else {
+		log::info!(peer:% = addr, listener, proxy:% = peer; "{:?}: connected to {} via proxy {:?}", addr, listener, peer);
+	}
+}
+
+async fn serve<S>(conn_name: String, sock: S, addr: Option<String>, listener: usize, storage: Arc<Storage>, shutdown: Shutdown)
+where S: AsyncRead + AsyncWrite + Unpin {
+	storage.client_connected(listener);
+	match super::command_loop_executor(&conn_name, sock, addr, &storage, shutdown).await {
+		Ok(_) => log::info!(peer = conn_name.as_str(); "{}: closed by shutdown", conn_name),
+		Err(err) => log::info!(peer = conn_name.as_str(), reason = err.as_str(); "{}: closed with error: {}", conn_name, err),
+	}
+	storage.client_disconnected(listener);
+}
+
+/// Ok once the shutdown is triggered; the connections it accepted keep running until they drain
+pub async fn accept_loop(listener: Listener, storage: Arc<Storage>, mut shutdown: Shutdown) -> Result<(), String> {
+	let keepalive = storage.config().tcp_keepalive();
+	let Listener {id, name, socket} = listener;
+	let failed = |e: std::io::Error|format!("Listener '{}' failed: {}", name, e);
+	match socket {
+		Socket::Tcp(mut socket) => loop {
+			let (mut sock, peer) = tokio::select! {
+				accepted = socket.accept() => accepted.map_err(failed)?,
+				_ = shutdown.wait() => return Ok(()),
+			};
+			configure_tcp(&format!("{:?}", peer), &sock, keepalive);
+			let storage = storage.clone();
+			let name = name.clone();
+			let shutdown = shutdown.clone();
+			// the PROXY header is read by the connection task, so a slow client can't stall the accept loop
+			tokio::spawn(async move {
+				if let Some(addr) = client_addr(&mut sock, peer, &storage).await {
+					log_connected(&addr, &peer, &name);
+					serve(format!("{:?}", addr), sock, Some(addr.to_string()), id, storage, shutdown).await;
+				}
+			});
+		},
+		Socket::Tls(mut socket, acceptor) => loop {
+			let (mut sock, peer) = tokio::select! {
+				accepted = socket.accept() => accepted.map_err(failed)?,
+				_ = shutdown.wait() => return Ok(()),
+			};
+			configure_tcp(&format!("{:?}", peer), &sock, keepalive);
+			let acceptor = acceptor.clone();
+			let storage = storage.clone();
+			let name = name.clone();
+			let shutdown = shutdown.clone();
+			// the handshake is done by the connection task, so a slow client can't stall the accept loop
+			tokio::spawn(async move {
+				let addr = match client_addr(&mut sock, peer, &storage).await {
+					Some(addr) => addr,
+					None => return,
+				};
+				match acceptor.accept(sock).await {
+					Ok(sock) => {
+						log_connected(&addr, &peer, &name);
+						serve(format!("{:?}", addr), sock, Some(addr.to_string()), id, storage, shutdown).await;
+					},
+					Err(err) => log::info!("{:?}: TLS handshake failed: {}", addr, err),
+				}
+			});
+		},
+		#[cfg(unix)]
+		Socket::Unix(mut socket) => loop {
+			let (sock, _) = tokio::select! {
+				accepted = socket.accept() => accepted.map_err(failed)?,
+				_ = shutdown.wait() => return Ok(()),
+			};
+			log::info!(listener = name.as_str(); "{}: connected", name);
+			tokio::spawn(serve(name.clone(), sock, Some(name[5..].to_owned()), id, storage.clone(), shutdown.clone()));
+		},
+	}
+}
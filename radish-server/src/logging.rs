@@ -0,0 +1,66 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! JSON lines format of the log, enabled by `log-format json`. The key-values of a record,
+//! such as the connection and the command, become fields next to the message.
+
+use std::io::Write;
+
+use serde_json::{Map, Value as Json};
+use log::kv::{Key, Value, VisitSource, Error};
+
+use radish_types::Value as Reply;
+
+struct Fields(Map<String, Json>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+	fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+		let value = if let Some(n) = value.to_u64() {
+			Json::from(n)
+		} else if let Some(n) = value.to_i64() {
+			Json::from(n)
+		} else if let Some(n) = value.to_f64() {
+			Json::from(n)
+		} else if let Some(b) = value.to_bool() {
+			Json::from(b)
+		} else {
+			Json::from(value.to_string())
+		};
+		self.0.insert(key.as_str().to_owned(), value);
+		Ok(())
+	}
+}
+
+pub fn format_json(buf: &mut env_logger::fmt::Formatter, record: &log::Record) -> std::io::Result<()> {
+	let mut fields = Fields(Map::new());
+	fields.0.insert("time".to_owned(), Json::from(buf.timestamp_millis().to_string()));
+	fields.0.insert("level".to_owned(), Json::from(record.level().as_str()));
+	fields.0.insert("target".to_owned(), Json::from(record.target()));
+	fields.0.insert("message".to_owned(), Json::from(record.args().to_string()));
+	let _ = record.key_values().visit(&mut fields);
+	serde_json::to_writer(&mut *buf, &fields.0)?;
+	writeln!(buf)
+}
+
+/// Kind of a reply as logged in the `result` field
+pub fn result_kind(reply: &Reply) -> &'static str {
+	match reply {
+		Reply::Error(_) => "error",
+		Reply::Nill => "nil",
+		_ => "ok",
+	}
+}
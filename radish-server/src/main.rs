@@ -14,74 +14,276 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::convert::TryFrom;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{SystemTime, Duration};
 
-use tokio::net::{TcpListener, TcpStream};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use radish_database::{Storage, RotatingFile};
+use radish_server::{Server, logging};
 
-use radish_types::*;
-use radish_database::Storage;
+/// Period of the background pass giving back memory after mass deletions
+const COMPACTION_PERIOD: Duration = Duration::from_secs(60);
 
-async fn command_loop_executor(conn_name: &str, mut sock: TcpStream, mut storage: Storage) -> Result<(), String> {
-	loop {
-		let len = sock.read_u32().await.map_err(|_|"Failed to read frame size".to_owned())?;
-		let mut buf = vec![0; len as usize];
-		sock.read_exact(&mut buf[..]).await.map_err(|_|"Failed to read command".to_owned())?;
+/// Where the configuration came from, kept to apply it again on SIGHUP
+struct ConfigSource {
+	file: Option<String>,
+	overrides: Vec<(String, String)>,
+}
 
-		let cmd: Command = rmp_serde::from_read_ref(&buf).map_err(|_|"Failed to deserialize command".to_owned())?;
-		log::debug!("{}: {}", conn_name, cmd);
-		let result = storage.execute(cmd).await;
-		log::debug!("{}: {}", conn_name, result);
+/// `host:port` of the TCP listener, applied over the config file and under the command line
+const ADDR_ENV: &str = "RADISH_ADDR";
 
-		let buf = rmp_serde::to_vec(&result).map_err(|_|"Failed to serialize result".to_owned())?;
-		let len = u32::try_from(buf.len()).map_err(|_|"Length of result is too big".to_owned())?;
-		sock.write_u32(len).await.map_err(|_|"Failed to write frame size".to_owned())?;
-		sock.write_all(&buf[..]).await.map_err(|_|"Failed to write result".to_owned())?;
+/// Splits `host:port`; an IPv6 host is kept in brackets, as `bind` expects it
+fn split_addr(addr: &str) -> Result<(String, String), String> {
+	let invalid = ||format!("Invalid address '{}': host:port is expected", addr);
+	let (host, port) = addr.rsplit_once(':').ok_or_else(invalid)?;
+	if host.is_empty() || port.parse::<u16>().is_err() {
+		return Err(invalid());
 	}
+	Ok((host.to_owned(), port.to_owned()))
 }
 
-#[tokio::main]
-async fn main() -> tokio::io::Result<()> {
-	env_logger::init();
+/// radish-server [config-file] [--config config-file] [--read-only] [--skip-checksum] [--load-rdb file] [--<parameter> value]...
+/// The file is applied first, then RADISH_ADDR, so the command line overrides both.
+/// Returns the Redis RDB file to load instead of the snapshot, if any.
+fn configure(storage: &Storage, args: Vec<String>) -> Result<(ConfigSource, Option<String>), String> {
+	let mut args = args.into_iter().peekable();
+	let mut file = match args.peek() {
+		Some(arg) if !arg.starts_with("--") => args.next(),
+		_ => None,
+	};
+	let mut overrides = Vec::new();
+	let mut rdb = None;
+	while let Some(arg) = args.next() {
+		match &arg[..] {
+			"--load-rdb" => rdb = Some(args.next().ok_or("Option '--load-rdb' requires a file")?),
+			"--config" => file = Some(args.next().ok_or("Option '--config' requires a file")?),
+			"--read-only" => overrides.push(("read-only".to_owned(), "yes".to_owned())),
+			"--skip-checksum" => overrides.push(("rdbchecksum".to_owned(), "no".to_owned())),
+			arg if arg.starts_with("--") => {
+				let value = args.next().ok_or_else(||format!("Option '{}' requires a value", arg))?;
+				overrides.push((arg[2..].to_owned(), value));
+			},
+			arg => return Err(format!("Unexpected argument '{}'", arg)),
+		}
+	}
+
+	if let Some(file) = &file {
+		let contents = std::fs::read_to_string(file).map_err(|e|format!("Failed to read '{}': {}", file, e))?;
+		storage.load_config(&contents).map_err(|e|format!("{}: {}", file, e))?;
+	}
+	if let Ok(addr) = std::env::var(ADDR_ENV) {
+		let (host, port) = split_addr(&addr).map_err(|e|format!("{}: {}", ADDR_ENV, e))?;
+		storage.config().set("bind", &host).map_err(|e|format!("{}: {}", ADDR_ENV, e))?;
+		storage.config().set("port", &port).map_err(|e|format!("{}: {}", ADDR_ENV, e))?;
+	}
+	for (name, value) in &overrides {
+		storage.config().set(name, value).map_err(|e|format!("Option '--{}': {}", name, e))?;
+	}
+	Ok((ConfigSource {file, overrides}, rdb))
+}
 
-	let addr = "127.0.0.1:6142";
-	let mut listener = TcpListener::bind(addr).await.unwrap();
+/// Applies the changed runtime parameters of the config file; the file is read again,
+/// the command line options still override it
+fn reload_config(storage: &Storage, source: &ConfigSource) -> Result<(), String> {
+	let file = source.file.as_ref().ok_or("no config file to reload")?;
+	let contents = std::fs::read_to_string(file).map_err(|e|format!("Failed to read '{}': {}", file, e))?;
+	let report = storage.reload_config(&contents, &source.overrides).map_err(|e|format!("{}: {}", file, e))?;
+	for change in &report.changed {
+		log::info!("config reloaded {}", change);
+	}
+	for change in &report.ignored {
+		log::warn!("config change ignored until restart {}", change);
+	}
+	if report.changed.is_empty() && report.ignored.is_empty() {
+		log::info!("config reloaded, nothing changed");
+	}
+	apply_loglevel(storage);
+	Ok(())
+}
+
+#[cfg(unix)]
+async fn reload_on_hangup(storage: Storage, source: ConfigSource) {
+	use tokio::signal::unix::{signal, SignalKind};
 
+	let mut hangup = match signal(SignalKind::hangup()) {
+		Ok(hangup) => hangup,
+		Err(err) => {
+			log::warn!("SIGHUP is not handled: {}", err);
+			return;
+		},
+	};
+	while hangup.recv().await.is_some() {
+		log::info!("SIGHUP received, reloading config");
+		if let Err(err) = reload_config(&storage, &source) {
+			log::error!("config is not reloaded: {}", err);
+		}
+	}
+}
+
+#[cfg(not(unix))]
+async fn reload_on_hangup(_storage: Storage, _source: ConfigSource) {
+}
+
+/// Redis RDB file given with `--load-rdb`, otherwise the own snapshot if it exists
+async fn load_dataset(storage: &Storage, rdb: Option<String>) -> Result<(), String> {
+	if let Some(rdb) = rdb {
+		let summary = storage.load_rdb(std::path::Path::new(&rdb)).await?;
+		log::info!("loaded {} keys from RDB '{}' version {}, {} already expired", summary.loaded, rdb, summary.version, summary.expired);
+		if summary.other_databases > 0 {
+			log::warn!("skipped {} keys of databases other than 0", summary.other_databases);
+		}
+		for (key_type, count) in summary.skipped {
+			log::warn!("skipped {} keys of unsupported type {}", count, key_type);
+		}
+		return Ok(());
+	}
+
+	let snapshot = storage.snapshot_path();
+	if snapshot.exists() {
+		let header = storage.load_snapshot(&snapshot, storage.config().rdbchecksum()).await?;
+		log::info!("loaded snapshot '{}' created at {:?}", snapshot.display(), header.created_at);
+	}
+	Ok(())
+}
+
+/// Without RUST_LOG the level is controlled by `loglevel`
+static LOG_FROM_ENV: AtomicBool = AtomicBool::new(false);
+
+fn apply_loglevel(storage: &Storage) {
+	if LOG_FROM_ENV.load(Ordering::Relaxed) {
+		return;
+	}
+	log::set_max_level(match &storage.config().loglevel()[..] {
+		"debug" => log::LevelFilter::Debug,
+		"verbose" | "notice" => log::LevelFilter::Info,
+		_ => log::LevelFilter::Warn,
+	});
+}
+
+/// RUST_LOG takes precedence over the configured level
+fn init_logger(storage: &Storage) -> Result<(), String> {
+	LOG_FROM_ENV.store(std::env::var_os(env_logger::DEFAULT_FILTER_ENV).is_some(), Ordering::Relaxed);
+	// the filter lets everything through, the level of `loglevel` is set as the max level of `log`
+	let mut builder = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("debug"));
+	let logfile = storage.config().logfile();
+	if !logfile.is_empty() {
+		let file = RotatingFile::open(logfile.clone().into(), storage.config().logfile_max_size(), storage.config().logfile_retention())
+			.map_err(|e|format!("Failed to open log file '{}': {}", logfile, e))?;
+		builder.target(env_logger::Target::Pipe(Box::new(file)));
+	}
+	if storage.config().log_format() == "json" {
+		builder.format(logging::format_json);
+	}
+	builder.init();
+	apply_loglevel(storage);
+	Ok(())
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() {
+	use tokio::signal::unix::{signal, SignalKind};
+
+	match signal(SignalKind::terminate()) {
+		Ok(mut terminate) => {
+			tokio::select! {
+				_ = tokio::signal::ctrl_c() => (),
+				_ = terminate.recv() => (),
+			}
+		},
+		Err(_) => {
+			let _ = tokio::signal::ctrl_c().await;
+		},
+	}
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() {
+	let _ = tokio::signal::ctrl_c().await;
+}
+
+#[tokio::main]
+async fn main() {
 	let mut storage = Storage::new();
+	let (source, rdb) = match configure(&storage, std::env::args().skip(1).collect()) {
+		Ok(configured) => configured,
+		Err(err) => {
+			eprintln!("{}", err);
+			std::process::exit(1);
+		},
+	};
+	if let Err(err) = init_logger(&storage) {
+		eprintln!("{}", err);
+		std::process::exit(1);
+	}
+	if let Err(err) = load_dataset(&storage, rdb).await {
+		log::error!("{}", err);
+		std::process::exit(1);
+	}
+	match storage.start_audit_log() {
+		Ok(true) => log::info!("audit log is written to '{}'", storage.config().audit_log()),
+		Ok(false) => (),
+		Err(err) => {
+			log::error!("{}", err);
+			std::process::exit(1);
+		},
+	}
+
+	let server = match Server::bind(storage.clone()).await {
+		Ok(server) => server,
+		Err(err) => {
+			log::error!("{}", err);
+			std::process::exit(1);
+		},
+	};
+	for listener in server.listeners() {
+		log::info!("listening on {}", listener);
+	}
 	let st = storage.clone();
+	// time of the earliest pending wakeup; later ones are skipped, since each check reschedules the next deadline
+	let scheduled = Arc::new(std::sync::Mutex::new(None::<SystemTime>));
 	storage.set_expire_awaker(move |timepoint|{
+		{
+			let mut scheduled = scheduled.lock().unwrap();
+			if matches!(*scheduled, Some(earlier) if earlier <= timepoint) {
+				return;
+			}
+			*scheduled = Some(timepoint);
+		}
+		let scheduled = scheduled.clone();
 		let st = st.clone();
-		//TODO: each future has low cost but it still take O(N) of memory for each call
 		tokio::spawn(async move {
 			//1 mill needs because quant size of delay_until is 1ms
 			let timepoint = timepoint + Duration::from_millis(1);
 			log::debug!("wait untill {:?}", timepoint);
-			let delta = loop {
-				let now = SystemTime::now();
-				let delta = timepoint.duration_since(now).unwrap_or(Duration::new(0, 0));
-				if delta <= Duration::from_secs(3700) {
-					break delta;
+			// a monotonic deadline, so steps of the wall clock neither delay nor hasten the check
+			tokio::time::delay_until(tokio::time::Instant::from_std(st.clock().instant(timepoint))).await;
+			{
+				let mut scheduled = scheduled.lock().unwrap();
+				if *scheduled == Some(timepoint - Duration::from_millis(1)) {
+					*scheduled = None;
 				}
-				tokio::time::delay_for(Duration::from_secs(3600)).await;
-			};
-			tokio::time::delay_for(delta).await;
+			}
 			st.keys_check_expirations().await;
 		});
 	});
 
-	loop {
-		let (sock, _) = listener.accept().await?;
-		let conn_name = format!("{:?}", sock.peer_addr());
-		log::info!("{}: connected", conn_name);
+	tokio::spawn(reload_on_hangup(storage.clone(), source));
 
-		let storage = storage.clone();
-		tokio::spawn(async move {
-			match command_loop_executor(&conn_name, sock, storage.clone()).await {
-				Ok(_) => log::info!("{}: unexpected finished loop", conn_name),
-				Err(err) => log::info!("{}: closed with error: {}", conn_name, err),
+	let st = storage.clone();
+	tokio::spawn(async move {
+		loop {
+			tokio::time::delay_for(COMPACTION_PERIOD).await;
+			let compacted = st.compact().await;
+			if compacted > 0 {
+				log::debug!("compacted {} containers", compacted);
 			}
-		});
+		}
+	});
+
+	// the connections left after the drain timeout are closed along with the runtime when main returns
+	if let Err(err) = server.serve(shutdown_signal()).await {
+		log::error!("{}", err);
+		std::process::exit(1);
 	}
 }
-
@@ -0,0 +1,234 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! PROXY protocol header sent by load balancers in front of the server, see
+//! <https://www.haproxy.org/download/2.0/doc/proxy-protocol.txt>.
+//! Both the text version 1 and the binary version 2 are accepted; the header comes
+//! before anything else on the connection, including the TLS handshake.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// The header must arrive this soon after accept
+pub const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+/// Including "PROXY " and the trailing CRLF
+const V1_MAX_LENGTH: usize = 107;
+
+fn invalid(reason: &str) -> String {
+	format!("Invalid PROXY header: {}", reason)
+}
+
+/// Source address of a v1 line without the CRLF; None for `UNKNOWN`
+fn parse_v1(line: &[u8]) -> Result<Option<SocketAddr>, String> {
+	let line = std::str::from_utf8(line).map_err(|_|invalid("not ASCII"))?;
+	let fields: Vec<&str> = line.split(' ').collect();
+	match fields.get(1) {
+		Some(&"UNKNOWN") => return Ok(None),
+		Some(&"TCP4") | Some(&"TCP6") if fields.len() == 6 => (),
+		_ => return Err(invalid("expected 'PROXY TCP4|TCP6 <src> <dst> <sport> <dport>' or 'PROXY UNKNOWN'")),
+	}
+	let parse_ip = |ip: &str| -> Result<IpAddr, String> {
+		let ip: IpAddr = ip.parse().map_err(|_|invalid(&format!("bad address '{}'", ip)))?;
+		match (fields[1], ip) {
+			("TCP4", IpAddr::V4(_)) | ("TCP6", IpAddr::V6(_)) => Ok(ip),
+			_ => Err(invalid(&format!("address '{}' is not {}", ip, fields[1]))),
+		}
+	};
+	let parse_port = |port: &str| -> Result<u16, String> {
+		// leading zeros are forbidden by the specification
+		if port.len() > 1 && port.starts_with('0') {
+			return Err(invalid(&format!("bad port '{}'", port)));
+		}
+		port.parse().map_err(|_|invalid(&format!("bad port '{}'", port)))
+	};
+	let source = parse_ip(fields[2])?;
+	parse_ip(fields[3])?;
+	let port = parse_port(fields[4])?;
+	parse_port(fields[5])?;
+	Ok(Some(SocketAddr::new(source, port)))
+}
+
+/// Source address of a v2 header; None for LOCAL connections, e.g. health checks,
+/// and for the address families other than TCP and UDP over IPv4 and IPv6
+fn parse_v2(version_command: u8, family: u8, addresses: &[u8]) -> Result<Option<SocketAddr>, String> {
+	if version_command >> 4 != 2 {
+		return Err(invalid(&format!("unsupported version {}", version_command >> 4)));
+	}
+	match version_command & 0x0f {
+		0 => return Ok(None),
+		1 => (),
+		command => return Err(invalid(&format!("unsupported command {}", command))),
+	}
+	let port = |at: usize|u16::from_be_bytes([addresses[at], addresses[at + 1]]);
+	match family >> 4 {
+		1 => {
+			if addresses.len() < 12 {
+				return Err(invalid("IPv4 addresses are truncated"));
+			}
+			let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+			Ok(Some(SocketAddr::new(IpAddr::V4(ip), port(8))))
+		},
+		2 => {
+			if addresses.len() < 36 {
+				return Err(invalid("IPv6 addresses are truncated"));
+			}
+			let mut ip = [0; 16];
+			ip.copy_from_slice(&addresses[..16]);
+			Ok(Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(ip)), port(32))))
+		},
+		0 | 3 => Ok(None),
+		other => Err(invalid(&format!("unsupported address family {}", other))),
+	}
+}
+
+/// Reads exactly the header, so the rest of the stream is left to the protocol after it.
+/// Returns the source address it advertises, None if the connection should keep its own address
+pub async fn read_header<S: AsyncRead + Unpin>(sock: &mut S) -> Result<Option<SocketAddr>, String> {
+	let failed = |e: std::io::Error|format!("Failed to read PROXY header: {}", e);
+	let mut header = vec![0; V2_SIGNATURE.len()];
+	sock.read_exact(&mut header).await.map_err(failed)?;
+
+	if header[..] == V2_SIGNATURE {
+		let mut rest = [0; 4];
+		sock.read_exact(&mut rest).await.map_err(failed)?;
+		let length = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+		// the addresses are followed by optional TLVs which are not used
+		let mut addresses = vec![0; length];
+		sock.read_exact(&mut addresses).await.map_err(failed)?;
+		return parse_v2(rest[0], rest[1], &addresses);
+	}
+
+	if !header.starts_with(b"PROXY ") {
+		return Err(invalid("no PROXY header"));
+	}
+	while !header.ends_with(b"\r\n") {
+		if header.len() >= V1_MAX_LENGTH {
+			return Err(invalid("line is too long"));
+		}
+		header.push(sock.read_u8().await.map_err(failed)?);
+	}
+	parse_v1(&header[..header.len() - 2])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	async fn read(mut header: &[u8]) -> (Result<Option<SocketAddr>, String>, Vec<u8>) {
+		let result = read_header(&mut header).await;
+		(result, header.to_vec())
+	}
+
+	fn v2(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+		let mut header = V2_SIGNATURE.to_vec();
+		header.extend_from_slice(&[0x20 | command, family]);
+		header.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+		header.extend_from_slice(addresses);
+		header
+	}
+
+	#[tokio::test]
+	async fn v1_headers() {
+		let (source, rest) = read(b"PROXY TCP4 192.168.0.1 10.0.0.1 56324 6379\r\nPING").await;
+		assert_eq!(source.unwrap(), Some("192.168.0.1:56324".parse().unwrap()));
+		// the stream after the header is left to the protocol
+		assert_eq!(rest, b"PING");
+
+		let (source, _) = read(b"PROXY TCP6 2001:db8::1 ::1 65535 6379\r\n").await;
+		assert_eq!(source.unwrap(), Some("[2001:db8::1]:65535".parse().unwrap()));
+		let (source, _) = read(b"PROXY UNKNOWN ffff::1 ::1 1 2\r\n").await;
+		assert_eq!(source.unwrap(), None);
+	}
+
+	#[tokio::test]
+	async fn malformed_v1_headers() {
+		let cases: &[(&[u8], &str)] = &[
+			(b"GET / HTTP/1.1\r\n", "Invalid PROXY header: no PROXY header"),
+			(b"PROXY TCP4 1.2.3.4 5.6.7.8 1\r\n", "Invalid PROXY header: expected 'PROXY TCP4|TCP6 <src> <dst> <sport> <dport>' or 'PROXY UNKNOWN'"),
+			(b"PROXY UDP4 1.2.3.4 5.6.7.8 1 2\r\n", "Invalid PROXY header: expected 'PROXY TCP4|TCP6 <src> <dst> <sport> <dport>' or 'PROXY UNKNOWN'"),
+			(b"PROXY TCP4 1.2.3 5.6.7.8 1 2\r\n", "Invalid PROXY header: bad address '1.2.3'"),
+			(b"PROXY TCP4 ::1 5.6.7.8 1 2\r\n", "Invalid PROXY header: address '::1' is not TCP4"),
+			(b"PROXY TCP6 ::1 5.6.7.8 1 2\r\n", "Invalid PROXY header: address '5.6.7.8' is not TCP6"),
+			(b"PROXY TCP4 1.2.3.4 5.6.7.8 01 2\r\n", "Invalid PROXY header: bad port '01'"),
+			(b"PROXY TCP4 1.2.3.4 5.6.7.8 1 65536\r\n", "Invalid PROXY header: bad port '65536'"),
+			(b"PROXY TCP4 1.2.3.4 5.6.7.8 \xff 2\r\n", "Invalid PROXY header: not ASCII"),
+		];
+		for (header, expected) in cases {
+			assert_eq!(read(header).await.0.unwrap_err(), *expected, "{:?}", String::from_utf8_lossy(header));
+		}
+
+		let long = format!("PROXY TCP4 {}\r\n", "1".repeat(120));
+		assert_eq!(read(long.as_bytes()).await.0.unwrap_err(), "Invalid PROXY header: line is too long");
+		// truncated before the CRLF or before the signature is complete
+		assert!(read(b"PROXY TCP4 1.2.3.4").await.0.unwrap_err().starts_with("Failed to read PROXY header: "));
+		assert!(read(b"PROX").await.0.unwrap_err().starts_with("Failed to read PROXY header: "));
+	}
+
+	#[tokio::test]
+	async fn v2_headers() {
+		let ipv4 = [192, 168, 0, 1, 10, 0, 0, 1, 0xdc, 0x04, 0x18, 0xeb];
+		let mut header = v2(1, 0x11, &ipv4);
+		header.extend_from_slice(b"PING");
+		let (source, rest) = read(&header).await;
+		assert_eq!(source.unwrap(), Some("192.168.0.1:56324".parse().unwrap()));
+		assert_eq!(rest, b"PING");
+
+		let mut ipv6 = vec![0x20, 0x01, 0x0d, 0xb8];
+		ipv6.extend_from_slice(&[0; 11]);
+		ipv6.push(1);
+		ipv6.extend_from_slice(&[0; 16]);
+		ipv6.extend_from_slice(&[0xff, 0xff, 0x18, 0xeb]);
+		assert_eq!(read(&v2(1, 0x21, &ipv6)).await.0.unwrap(), Some("[2001:db8::1]:65535".parse().unwrap()));
+
+		// TLVs after the addresses are skipped
+		let mut tlvs = ipv4.to_vec();
+		tlvs.extend_from_slice(&[0x04, 0x00, 0x02, 0xab, 0xcd]);
+		let mut header = v2(1, 0x11, &tlvs);
+		header.extend_from_slice(b"PING");
+		let (source, rest) = read(&header).await;
+		assert_eq!(source.unwrap(), Some("192.168.0.1:56324".parse().unwrap()));
+		assert_eq!(rest, b"PING");
+
+		// LOCAL, e.g. a health check, and unix sockets keep the peer address
+		assert_eq!(read(&v2(0, 0x11, &ipv4)).await.0.unwrap(), None);
+		assert_eq!(read(&v2(1, 0x31, &[0; 216])).await.0.unwrap(), None);
+		assert_eq!(read(&v2(1, 0x00, &[])).await.0.unwrap(), None);
+	}
+
+	#[tokio::test]
+	async fn malformed_v2_headers() {
+		let ipv4 = [192, 168, 0, 1, 10, 0, 0, 1, 0xdc, 0x04, 0x18, 0xeb];
+		assert_eq!(read(&v2(1, 0x11, &ipv4[..11])).await.0.unwrap_err(), "Invalid PROXY header: IPv4 addresses are truncated");
+		assert_eq!(read(&v2(1, 0x21, &[0; 35])).await.0.unwrap_err(), "Invalid PROXY header: IPv6 addresses are truncated");
+		assert_eq!(read(&v2(2, 0x11, &ipv4)).await.0.unwrap_err(), "Invalid PROXY header: unsupported command 2");
+		assert_eq!(read(&v2(1, 0x41, &ipv4)).await.0.unwrap_err(), "Invalid PROXY header: unsupported address family 4");
+
+		let mut version = v2(1, 0x11, &ipv4);
+		version[12] = 0x11;
+		assert_eq!(read(&version).await.0.unwrap_err(), "Invalid PROXY header: unsupported version 1");
+
+		// the declared length is longer than the data
+		let mut truncated = v2(1, 0x11, &ipv4);
+		truncated.truncate(truncated.len() - 4);
+		assert!(read(&truncated).await.0.unwrap_err().starts_with("Failed to read PROXY header: "));
+		assert!(read(&V2_SIGNATURE[..]).await.0.unwrap_err().starts_with("Failed to read PROXY header: "));
+	}
+}
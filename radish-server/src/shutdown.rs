@@ -0,0 +1,72 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Graceful shutdown: the accept loops stop, every connection finishes the command it is
+//! executing and closes before the next frame, and the server waits for them to drain.
+
+use std::time::Duration;
+
+use tokio::sync::{mpsc, watch};
+
+/// How long the server waits for the open connections to finish their commands
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Held by the tasks which have to stop on shutdown; the server waits until every clone is dropped
+#[derive(Clone)]
+pub struct Shutdown {
+	triggered: watch::Receiver<bool>,
+	_drain: mpsc::Sender<()>,
+}
+
+/// Starts the shutdown and waits for the tasks holding `Shutdown`
+pub struct ShutdownTrigger {
+	trigger: watch::Sender<bool>,
+	drained: mpsc::Receiver<()>,
+}
+
+pub fn channel() -> (ShutdownTrigger, Shutdown) {
+	let (trigger, triggered) = watch::channel(false);
+	let (drain, drained) = mpsc::channel(1);
+	(ShutdownTrigger {trigger, drained}, Shutdown {triggered, _drain: drain})
+}
+
+impl Shutdown {
+	pub fn is_triggered(&self) -> bool {
+		*self.triggered.borrow()
+	}
+
+	/// Completes once the shutdown is triggered
+	pub async fn wait(&mut self) {
+		while !self.is_triggered() {
+			if self.triggered.recv().await.is_none() {
+				return;
+			}
+		}
+	}
+}
+
+impl ShutdownTrigger {
+	pub fn trigger(&self) {
+		let _ = self.trigger.broadcast(true);
+	}
+
+	/// Triggers the shutdown and waits for the tasks to drop their `Shutdown`;
+	/// false if some of them are still running after `timeout`
+	pub async fn drain(mut self, timeout: Duration) -> bool {
+		self.trigger();
+		tokio::time::timeout(timeout, self.drained.recv()).await.is_ok()
+	}
+}
@@ -0,0 +1,53 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+use std::sync::Arc;
+use std::io::BufReader;
+
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::{Certificate, PrivateKey, NoClientAuth, ServerConfig};
+use tokio_rustls::rustls::internal::pemfile;
+
+fn open(path: &str, what: &str) -> Result<BufReader<std::fs::File>, String> {
+	let file = std::fs::File::open(path).map_err(|e|format!("Failed to open TLS {} '{}': {}", what, path, e))?;
+	Ok(BufReader::new(file))
+}
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>, String> {
+	match pemfile::certs(&mut open(path, "certificate")?) {
+		Ok(certs) if !certs.is_empty() => Ok(certs),
+		_ => Err(format!("No certificates found in '{}'", path)),
+	}
+}
+
+/// PKCS#8 keys are tried first, then PKCS#1 RSA keys
+fn load_key(path: &str) -> Result<PrivateKey, String> {
+	let pkcs8 = pemfile::pkcs8_private_keys(&mut open(path, "key")?).unwrap_or_default();
+	let rsa = || pemfile::rsa_private_keys(&mut open(path, "key")?).map_err(|_|format!("Failed to parse TLS key '{}'", path));
+	let keys = if pkcs8.is_empty() {rsa()?} else {pkcs8};
+	keys.into_iter().next().ok_or_else(||format!("No private key found in '{}'", path))
+}
+
+/// Acceptor of the TLS listeners; clients are not asked for certificates
+pub fn acceptor(cert_file: &str, key_file: &str) -> Result<TlsAcceptor, String> {
+	if cert_file.is_empty() || key_file.is_empty() {
+		return Err("TLS listeners require tls-cert-file and tls-key-file".to_owned());
+	}
+	let mut config = ServerConfig::new(NoClientAuth::new());
+	config.set_single_cert(load_certs(cert_file)?, load_key(key_file)?).map_err(|e|format!("Invalid TLS certificate or key: {}", e))?;
+	Ok(TlsAcceptor::from(Arc::new(config)))
+}
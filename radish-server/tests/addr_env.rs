@@ -0,0 +1,120 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! RADISH_ADDR is applied over the config file and under the command line
+
+#![cfg(unix)]
+
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command as Process, Stdio};
+
+use radish_client::Client;
+
+struct ServerProcess {
+	child: Child,
+	dir: PathBuf,
+	addr: String,
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("radish-addr-env-{}-{}", name, std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+fn server(dir: &Path, addr: &str, args: &[&str]) -> Process {
+	let mut process = Process::new(env!("CARGO_BIN_EXE_radish-server"));
+	process
+		.args(args)
+		.args(["--dir", dir.to_str().unwrap()])
+		.env("RADISH_ADDR", addr)
+		.env_remove("RUST_LOG");
+	process
+}
+
+impl ServerProcess {
+	/// Waits for the TCP listener to be logged; fails if the server exits first
+	fn start(name: &str, addr: &str, args: &[&str]) -> Self {
+		let dir = temp_dir(name);
+		let mut child = server(&dir, addr, args).stderr(Stdio::piped()).spawn().unwrap();
+		let stderr = BufReader::new(child.stderr.take().unwrap());
+		let mut lines = stderr.lines();
+		let addr = loop {
+			match lines.next() {
+				Some(line) => {
+					let line = line.unwrap();
+					if let Some(position) = line.find("listening on tcp ") {
+						break line[position + 17..].trim().to_owned();
+					}
+				},
+				None => panic!("the server exited: {:?}", child.wait()),
+			}
+		};
+		// the pipe is drained in the background so the server never blocks on its log
+		std::thread::spawn(move ||lines.for_each(drop));
+		Self {child, dir, addr}
+	}
+}
+
+impl Drop for ServerProcess {
+	fn drop(&mut self) {
+		let _ = self.child.kill();
+		let _ = self.child.wait();
+		let _ = std::fs::remove_dir_all(&self.dir);
+	}
+}
+
+#[tokio::test]
+async fn listens_on_radish_addr() {
+	let server = ServerProcess::start("listen", "127.0.0.1:0", &[]);
+	// port 0 is an ephemeral port, logged as bound
+	assert!(server.addr.starts_with("127.0.0.1:"), "{}", server.addr);
+	assert_ne!(server.addr, "127.0.0.1:0");
+	let client = Client::connect(&server.addr).await.unwrap();
+	client.set("k", "v").await.unwrap();
+	assert_eq!(client.get("k").await.unwrap(), Some(b"v".to_vec()));
+}
+
+#[tokio::test]
+async fn applied_over_the_config_file() {
+	let dir = temp_dir("file");
+	let file = dir.join("radish.conf");
+	std::fs::write(&file, "bind 127.0.0.2\nport 0\n").unwrap();
+	let server = ServerProcess::start("file-server", "127.0.0.3:0", &[file.to_str().unwrap()]);
+	assert!(server.addr.starts_with("127.0.0.3:"), "{}", server.addr);
+	let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn command_line_overrides_radish_addr() {
+	let server = ServerProcess::start("override", "127.0.0.2:0", &["--bind", "127.0.0.1"]);
+	assert!(server.addr.starts_with("127.0.0.1:"), "{}", server.addr);
+	Client::connect(&server.addr).await.unwrap().set("k", "v").await.unwrap();
+}
+
+#[test]
+fn invalid_address_stops_the_startup() {
+	let dir = temp_dir("invalid");
+	for addr in &["localhost", "127.0.0.1:", ":6142", "127.0.0.1:http", "127.0.0.1:65536"] {
+		let output = server(&dir, addr, &[]).output().unwrap();
+		let stderr = String::from_utf8_lossy(&output.stderr);
+		assert_eq!(output.status.code(), Some(1), "{}: {}", addr, stderr);
+		assert!(stderr.contains(&format!("RADISH_ADDR: Invalid address '{}': host:port is expected", addr)), "{}", stderr);
+		assert!(!stderr.contains("panicked"), "{}", stderr);
+	}
+	let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,119 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Allocations per request of the connection loop: the frame buffers are reused, so a
+//! request costs no more allocations than decoding and executing its command does
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use radish_database::{Storage, Session, Command, Value};
+use radish_server::Server;
+use radish_types::frame;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+		System.alloc(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		System.dealloc(ptr, layout)
+	}
+
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+		System.realloc(ptr, layout, new_size)
+	}
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const REQUESTS: usize = 10_000;
+
+fn get_command() -> Command {
+	Command {
+		command: "GET".to_owned(),
+		arguments: vec![Value::Buffer(b"key".to_vec())].into(),
+	}
+}
+
+async fn roundtrips(sock: &mut TcpStream, request: &[u8], reply: &mut [u8], count: usize) {
+	for _ in 0..count {
+		sock.write_all(request).await.unwrap();
+		let mut header = [0; frame::HEADER_SIZE];
+		sock.read_exact(&mut header).await.unwrap();
+		let (len, _) = frame::decode_header(header, reply.len()).unwrap();
+		sock.read_exact(&mut reply[..len]).await.unwrap();
+	}
+}
+
+/// Allocations of the requests sent one by one over a connection, the client allocates nothing
+async fn per_request_over_connection(addr: &str) -> f64 {
+	let body = rmp_serde::to_vec(&get_command()).unwrap();
+	let mut request = frame::encode_header(body.len(), false).unwrap().to_vec();
+	request.extend_from_slice(&body);
+	let mut reply = vec![0; 1024];
+
+	let mut sock = TcpStream::connect(addr).await.unwrap();
+	// the buffers of the connection grow on the first requests
+	roundtrips(&mut sock, &request, &mut reply, 100).await;
+	let before = ALLOCATIONS.load(Ordering::Relaxed);
+	roundtrips(&mut sock, &request, &mut reply, REQUESTS).await;
+	(ALLOCATIONS.load(Ordering::Relaxed) - before) as f64 / REQUESTS as f64
+}
+
+/// Allocations of decoding, executing and encoding the same command in place
+async fn per_request_in_process(storage: &Storage) -> f64 {
+	let body = rmp_serde::to_vec(&get_command()).unwrap();
+	let mut session = Session::default();
+	let mut output = Vec::with_capacity(1024);
+	let before = ALLOCATIONS.load(Ordering::Relaxed);
+	for _ in 0..REQUESTS {
+		let command: Command = rmp_serde::from_read_ref(&body).unwrap();
+		let result = storage.execute(&mut session, command).await;
+		rmp_serde::encode::write(&mut output, &result).unwrap();
+		output.clear();
+	}
+	(ALLOCATIONS.load(Ordering::Relaxed) - before) as f64 / REQUESTS as f64
+}
+
+#[tokio::test]
+async fn connection_loop_adds_no_allocations_per_request() {
+	let storage = Storage::new();
+	storage.config().set("bind", "127.0.0.1").unwrap();
+	storage.config().set("port", "0").unwrap();
+	let server = Server::bind(storage.clone()).await.unwrap();
+	let addr = server.listeners()[0].trim_start_matches("tcp ").to_owned();
+	let server = server.start();
+	storage.set(b"key", b"value", None).await.unwrap();
+
+	let in_process = per_request_in_process(&storage).await;
+	let over_connection = per_request_over_connection(&addr).await;
+	println!("allocations per request: {:.2} in process, {:.2} over a connection", in_process, over_connection);
+	// a fresh input or output buffer per request would add at least 2
+	assert!(over_connection < in_process + 1.0, "{:.2} against {:.2}", over_connection, in_process);
+
+	server.shutdown().await.unwrap();
+}
@@ -0,0 +1,203 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command as Process};
+use std::time::{Duration, Instant};
+
+use radish_client::{Client, Command, Value};
+use serde_json::Value as Json;
+
+/// radish-server writing JSON logs at debug level to a file of its own directory
+struct ServerProcess {
+	child: Child,
+	dir: PathBuf,
+}
+
+impl ServerProcess {
+	fn start(name: &str, options: &[&str]) -> Self {
+		let dir = std::env::temp_dir().join(format!("radish-json-log-{}-{}", name, std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+		std::fs::create_dir_all(&dir).unwrap();
+		let logfile = dir.join("radish.log");
+		let child = Process::new(env!("CARGO_BIN_EXE_radish-server"))
+			.args(["--bind", "127.0.0.1", "--port", "0", "--dir", dir.to_str().unwrap()])
+			.args(["--log-format", "json", "--loglevel", "debug", "--logfile", logfile.to_str().unwrap()])
+			.args(options)
+			.env_remove("RUST_LOG")
+			.spawn()
+			.unwrap();
+		Self {child, dir}
+	}
+
+	fn logfile(&self) -> PathBuf {
+		self.dir.join("radish.log")
+	}
+
+	/// Every line of the log parsed; the test fails on a line which is not a JSON object
+	fn records(&self) -> Vec<Json> {
+		read_records(&self.logfile())
+	}
+
+	fn wait_for(&self, matches: impl Fn(&Json) -> bool) -> Json {
+		let started = Instant::now();
+		loop {
+			if let Some(record) = self.records().into_iter().find(|record|matches(record)) {
+				return record;
+			}
+			assert!(started.elapsed() < Duration::from_secs(10), "no such record in {:#?}", self.records());
+			std::thread::sleep(Duration::from_millis(10));
+		}
+	}
+
+	fn addr(&self) -> String {
+		let record = self.wait_for(|record|message(record).starts_with("listening on tcp "));
+		message(&record)["listening on tcp ".len()..].to_owned()
+	}
+}
+
+impl Drop for ServerProcess {
+	fn drop(&mut self) {
+		let _ = self.child.kill();
+		let _ = self.child.wait();
+		let _ = std::fs::remove_dir_all(&self.dir);
+	}
+}
+
+fn read_records(path: &Path) -> Vec<Json> {
+	std::fs::read_to_string(path).unwrap_or_default().lines()
+		.map(|line|{
+			let record: Json = serde_json::from_str(line).unwrap_or_else(|e|panic!("{}: {:?}", e, line));
+			assert!(record.is_object(), "{:?}", line);
+			record
+		})
+		.collect()
+}
+
+fn message(record: &Json) -> &str {
+	record["message"].as_str().unwrap_or_default()
+}
+
+fn command(name: &str, args: &[&str]) -> Command {
+	Command {
+		command: name.to_owned(),
+		arguments: args.iter().map(|arg|Value::Buffer(arg.as_bytes().to_vec())).collect(),
+	}
+}
+
+#[tokio::test]
+async fn scripted_session_is_logged_with_fields() {
+	let server = ServerProcess::start("session", &[]);
+	let client = Client::connect(&server.addr()).await.unwrap();
+	client.set("k", "v").await.unwrap();
+	assert_eq!(client.get("missing").await.unwrap(), None);
+	assert!(client.execute(command("INCR", &["k"])).await.is_err());
+	drop(client);
+
+	let replies = |name: &'static str|move |record: &Json|record["command"] == name && record.get("result").is_some();
+	let set = server.wait_for(replies("SET"));
+	let get = server.wait_for(replies("GET"));
+	let incr = server.wait_for(replies("INCR"));
+	assert_eq!(set["result"], "ok");
+	assert_eq!(get["result"], "nil");
+	assert_eq!(incr["result"], "error");
+	for record in &[&set, &get, &incr] {
+		assert_eq!(record["level"], "DEBUG");
+		assert!(record["time"].is_string(), "{}", record);
+		assert!(record["target"].is_string(), "{}", record);
+		assert!(record["conn"].is_u64(), "{}", record);
+		assert!(record["duration_us"].is_u64(), "{}", record);
+		assert!(record["peer"].as_str().unwrap().starts_with("127.0.0.1:"), "{}", record);
+	}
+	// all of them come from the same connection
+	assert_eq!(set["conn"], incr["conn"]);
+	assert_eq!(set["peer"], get["peer"]);
+
+	// the command itself is logged before it runs, without the result
+	let request = server.wait_for(|record|record["command"] == "SET" && record.get("result").is_none());
+	assert_eq!(request["conn"], set["conn"]);
+
+	let peer = set["peer"].as_str().unwrap().to_owned();
+	let connected = server.wait_for(|record|record["listener"].is_string() && record["peer"] == peer.as_str());
+	assert_eq!(connected["level"], "INFO");
+	let closed = server.wait_for(|record|record["reason"].is_string() && record["peer"] == peer.as_str());
+	assert!(message(&closed).contains("closed with error"), "{}", closed);
+}
+
+#[tokio::test]
+async fn expiration_sweep_is_logged_with_fields() {
+	let server = ServerProcess::start("sweep", &[]);
+	let client = Client::connect(&server.addr()).await.unwrap();
+	client.execute(Command {
+		command: "SET".to_owned(),
+		arguments: vec![Value::Buffer(b"k".to_vec()), Value::Buffer(b"v".to_vec()), Value::Buffer(b"PX".to_vec()), Value::Integer(10)].into(),
+	}).await.unwrap();
+
+	let sweep = server.wait_for(|record|message(record) == "Check expiration done" && record["removed"] == 1);
+	assert!(sweep["checked"].as_u64().unwrap() >= 1, "{}", sweep);
+	assert_eq!(sweep["batch_full"], false);
+}
+
+#[tokio::test]
+async fn log_file_is_rotated() {
+	let server = ServerProcess::start("rotation", &["--logfile-max-size", "4096", "--logfile-retention", "2"]);
+	let client = Client::connect(&server.addr()).await.unwrap();
+	for i in 0..200 {
+		client.set(format!("key:{}", i), "v").await.unwrap();
+	}
+	let rotated = |index: usize|{
+		let mut name = server.logfile().into_os_string();
+		name.push(format!(".{}", index));
+		PathBuf::from(name)
+	};
+	let started = Instant::now();
+	while !rotated(2).exists() {
+		assert!(started.elapsed() < Duration::from_secs(10), "the log is not rotated");
+		std::thread::sleep(Duration::from_millis(10));
+	}
+	assert!(!rotated(3).exists());
+	for path in &[server.logfile(), rotated(1), rotated(2)] {
+		let size = std::fs::metadata(path).unwrap().len();
+		assert!(size <= 4096 + 1024, "{:?} has {} bytes", path, size);
+		// a record is never split between the files
+		read_records(path);
+	}
+}
+
+#[tokio::test]
+async fn text_is_the_default_format() {
+	let dir = std::env::temp_dir().join(format!("radish-json-log-text-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let logfile = dir.join("radish.log");
+	let mut child = Process::new(env!("CARGO_BIN_EXE_radish-server"))
+		.args(["--bind", "127.0.0.1", "--port", "0", "--dir", dir.to_str().unwrap(), "--logfile", logfile.to_str().unwrap()])
+		.env_remove("RUST_LOG")
+		.spawn()
+		.unwrap();
+	let started = Instant::now();
+	let content = loop {
+		let content = std::fs::read_to_string(&logfile).unwrap_or_default();
+		if content.contains("listening on") {
+			break content;
+		}
+		assert!(started.elapsed() < Duration::from_secs(10), "the server did not start");
+		std::thread::sleep(Duration::from_millis(10));
+	};
+	let _ = child.kill();
+	let _ = child.wait();
+	let _ = std::fs::remove_dir_all(&dir);
+	assert!(content.lines().all(|line|serde_json::from_str::<Json>(line).is_err()), "{}", content);
+}
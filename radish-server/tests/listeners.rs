@@ -0,0 +1,133 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use tokio::net::TcpStream;
+
+use radish_client::{Address, Client, ClientConfig, Command, Value};
+use radish_database::{Storage, Session};
+use radish_server::Server;
+
+async fn info_lines(storage: &Storage, prefix: &str) -> Vec<String> {
+	let info = storage.execute(&mut Session::default(), Command {command: "INFO".to_owned(), arguments: Default::default()}).await;
+	let info = match info {
+		Value::Buffer(info) => String::from_utf8(info).unwrap(),
+		info => panic!("unexpected INFO reply {:?}", info),
+	};
+	info.lines().filter(|line|line.starts_with(prefix)).map(str::to_owned).collect()
+}
+
+fn socket_path(name: &str) -> String {
+	std::env::temp_dir().join(format!("radish-{}-{}.sock", name, std::process::id())).to_str().unwrap().to_owned()
+}
+
+fn address(listener: &str) -> Address {
+	let (kind, addr) = listener.split_once(' ').unwrap();
+	match kind {
+		"tcp" => Address::Tcp(addr.to_owned()),
+		"unix" => Address::Unix(addr.into()),
+		kind => panic!("unexpected listener {}", kind),
+	}
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn every_listener_serves_the_same_storage() {
+	let path = socket_path("listeners");
+	let storage = Storage::new();
+	storage.config().set("bind", "127.0.0.1 127.0.0.2").unwrap();
+	storage.config().set("port", "0").unwrap();
+	storage.config().set("unixsocket", &path).unwrap();
+	let server = Server::bind(storage.clone()).await.unwrap();
+	let listeners = server.listeners();
+	assert_eq!(listeners.len(), 3, "{:?}", listeners);
+	assert!(listeners[0].starts_with("tcp 127.0.0.1:"), "{:?}", listeners);
+	assert!(listeners[1].starts_with("tcp 127.0.0.2:"), "{:?}", listeners);
+	assert_eq!(listeners[2], format!("unix {}", path));
+	let server = server.start();
+
+	let mut clients = Vec::new();
+	for listener in &listeners {
+		clients.push(Client::connect_to(address(listener), ClientConfig::default()).await.unwrap());
+	}
+	let tasks: Vec<_> = clients.into_iter().enumerate().map(|(i, client)|tokio::spawn(async move {
+		for round in 0..100 {
+			client.set(format!("{}:{}", i, round), "v").await.unwrap();
+			client.incr("shared").await.unwrap();
+		}
+		client
+	})).collect();
+	let mut clients = Vec::new();
+	for task in tasks {
+		clients.push(task.await.unwrap());
+	}
+	assert_eq!(clients[2].get("shared").await.unwrap(), Some(b"300".to_vec()));
+	assert_eq!(clients[0].get("1:99").await.unwrap(), Some(b"v".to_vec()));
+
+	let info = info_lines(&storage, "listener").await;
+	assert_eq!(info.len(), 3, "{:?}", info);
+	for (line, listener) in info.iter().zip(&listeners) {
+		let (kind, addr) = listener.split_once(' ').unwrap();
+		assert!(line.contains(&format!(":name={},address={},connected_clients=1,total_connections_received=1", kind, addr)), "{}", line);
+	}
+
+	server.shutdown().await.unwrap();
+	for listener in &listeners[..2] {
+		assert!(TcpStream::connect(&listener[4..]).await.is_err(), "{} is still accepting", listener);
+	}
+	assert!(!std::path::Path::new(&path).exists(), "the unix socket is not removed");
+	for client in &clients {
+		assert!(client.get("shared").await.is_err());
+	}
+	assert!(info_lines(&storage, "listener").await.iter().all(|line|line.contains(",connected_clients=0,")));
+}
+
+#[tokio::test]
+async fn failed_listener_is_named() {
+	let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+	let port = taken.local_addr().unwrap().port();
+
+	let storage = Storage::new();
+	storage.config().set("bind", "127.0.0.1").unwrap();
+	storage.config().set("port", &port.to_string()).unwrap();
+	let err = Server::bind(storage).await.err().unwrap();
+	assert!(err.starts_with(&format!("Failed to bind listener 'tcp 127.0.0.1:{}': ", port)), "{}", err);
+
+	let storage = Storage::new();
+	storage.config().set("bind", "127.0.0.1").unwrap();
+	storage.config().set("port", "0").unwrap();
+	storage.config().set("unixsocket", "/nonexistent/dir/radish.sock").unwrap();
+	let err = Server::bind(storage).await.err().unwrap();
+	assert!(err.starts_with("Failed to bind listener 'unix /nonexistent/dir/radish.sock': "), "{}", err);
+}
+
+#[tokio::test]
+async fn stale_socket_file_is_replaced() {
+	let path = socket_path("stale");
+	std::fs::write(&path, b"").unwrap();
+	let storage = Storage::new();
+	storage.config().set("bind", "127.0.0.1").unwrap();
+	storage.config().set("port", "0").unwrap();
+	storage.config().set("unixsocket", &path).unwrap();
+	let server = Server::bind(storage).await.unwrap();
+	assert_eq!(server.listeners()[1], format!("unix {}", path));
+	let server = server.start();
+
+	let client = Client::connect_to(Address::Unix(path.clone().into()), ClientConfig::default()).await.unwrap();
+	client.set("k", "v").await.unwrap();
+	assert_eq!(client.get("k").await.unwrap(), Some(b"v".to_vec()));
+
+	server.shutdown().await.unwrap();
+	assert!(!std::path::Path::new(&path).exists());
+}
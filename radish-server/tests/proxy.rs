@@ -0,0 +1,129 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use radish_client::{protocol, frame, Command, Value};
+use radish_database::Storage;
+use radish_server::Server;
+
+/// Server with `proxy-protocol yes` writing the audit log to `log`
+async fn start(log: &Path) -> (String, radish_server::ServerHandle) {
+	let storage = Storage::new();
+	storage.config().set("bind", "127.0.0.1").unwrap();
+	storage.config().set("port", "0").unwrap();
+	storage.config().set("proxy-protocol", "yes").unwrap();
+	storage.config().set("audit-log", log.to_str().unwrap()).unwrap();
+	assert!(storage.start_audit_log().unwrap());
+	let server = Server::bind(storage).await.unwrap();
+	let addr = server.listeners()[0].trim_start_matches("tcp ").to_owned();
+	(addr, server.start())
+}
+
+fn audit_log(name: &str) -> PathBuf {
+	let path = std::env::temp_dir().join(format!("radish-proxy-{}-{}.log", name, std::process::id()));
+	let _ = std::fs::remove_file(&path);
+	path
+}
+
+fn set(key: &str) -> Command {
+	Command {
+		command: "SET".to_owned(),
+		arguments: vec![Value::Buffer(key.as_bytes().to_vec()), Value::Buffer(b"v".to_vec())].into(),
+	}
+}
+
+async fn wait_for_line(path: &Path, needle: &str) -> String {
+	let started = Instant::now();
+	loop {
+		let content = std::fs::read_to_string(path).unwrap_or_default();
+		if let Some(line) = content.lines().find(|line|line.contains(needle)) {
+			return line.to_owned();
+		}
+		assert!(started.elapsed() < Duration::from_secs(5), "no audit record with '{}' in {:?}", needle, content);
+		tokio::time::delay_for(Duration::from_millis(10)).await;
+	}
+}
+
+async fn is_dropped(sock: &mut TcpStream) -> bool {
+	let mut buf = [0; 16];
+	matches!(tokio::time::timeout(Duration::from_secs(1), sock.read(&mut buf)).await, Ok(Ok(0)) | Ok(Err(_)))
+}
+
+#[tokio::test]
+async fn proxied_address_is_recorded() {
+	let log = audit_log("valid");
+	let (addr, server) = start(&log).await;
+
+	let mut sock = TcpStream::connect(&addr).await.unwrap();
+	sock.write_all(b"PROXY TCP4 203.0.113.7 10.0.0.1 4242 6379\r\n").await.unwrap();
+	assert_eq!(protocol::request(&mut sock, &set("v1"), frame::DEFAULT_MAX_FRAME_SIZE).await.unwrap(), Value::Ok);
+	assert!(wait_for_line(&log, r#"keys=["v1"]"#).await.contains(" addr=203.0.113.7:4242 "));
+
+	let mut header = b"\r\n\r\n\0\r\nQUIT\n\x21\x21\x00\x24".to_vec();
+	header.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x09]);
+	header.extend_from_slice(&[0; 16]);
+	header.extend_from_slice(&[0x1f, 0x90, 0x18, 0xeb]);
+	let mut sock = TcpStream::connect(&addr).await.unwrap();
+	sock.write_all(&header).await.unwrap();
+	assert_eq!(protocol::request(&mut sock, &set("v2"), frame::DEFAULT_MAX_FRAME_SIZE).await.unwrap(), Value::Ok);
+	assert!(wait_for_line(&log, r#"keys=["v2"]"#).await.contains(" addr=[2001:db8::9]:8080 "));
+
+	// a LOCAL header keeps the address of the peer
+	let mut sock = TcpStream::connect(&addr).await.unwrap();
+	sock.write_all(b"\r\n\r\n\0\r\nQUIT\n\x20\x00\x00\x00").await.unwrap();
+	assert_eq!(protocol::request(&mut sock, &set("local"), frame::DEFAULT_MAX_FRAME_SIZE).await.unwrap(), Value::Ok);
+	let peer = sock.local_addr().unwrap().to_string();
+	assert!(wait_for_line(&log, r#"keys=["local"]"#).await.contains(&format!(" addr={} ", peer)));
+
+	server.shutdown().await.unwrap();
+	let _ = std::fs::remove_file(&log);
+}
+
+#[tokio::test]
+async fn connections_without_a_valid_header_are_dropped() {
+	let log = audit_log("invalid");
+	let (addr, server) = start(&log).await;
+
+	// a client talking the protocol directly
+	let mut sock = TcpStream::connect(&addr).await.unwrap();
+	assert!(protocol::request(&mut sock, &set("direct"), frame::DEFAULT_MAX_FRAME_SIZE).await.is_err());
+
+	let malformed: &[&[u8]] = &[
+		b"PROXY TCP4 203.0.113.7 10.0.0.1 4242\r\n",
+		b"PROXY TCP4 203.0.113.7 10.0.0.1 04242 6379\r\n",
+		b"GARBAGE GARBAGE GARBAGE\r\n",
+		b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x04\x01\x02\x03\x04",
+	];
+	for header in malformed {
+		let mut sock = TcpStream::connect(&addr).await.unwrap();
+		sock.write_all(header).await.unwrap();
+		assert!(is_dropped(&mut sock).await, "{:?} is accepted", String::from_utf8_lossy(header));
+	}
+
+	// the connections still work once a header is sent
+	let mut sock = TcpStream::connect(&addr).await.unwrap();
+	sock.write_all(b"PROXY UNKNOWN\r\n").await.unwrap();
+	assert_eq!(protocol::request(&mut sock, &set("after"), frame::DEFAULT_MAX_FRAME_SIZE).await.unwrap(), Value::Ok);
+	assert!(!std::fs::read_to_string(&log).unwrap_or_default().contains(r#"keys=["direct"]"#));
+
+	server.shutdown().await.unwrap();
+	let _ = std::fs::remove_file(&log);
+}
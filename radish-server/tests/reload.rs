@@ -0,0 +1,146 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+#![cfg(unix)]
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command as Process, Stdio};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+
+use radish_client::{Client, Command, Value};
+
+/// radish-server started with a config file; its log lines are collected from stderr
+struct ServerProcess {
+	child: Child,
+	dir: std::path::PathBuf,
+	logs: Receiver<String>,
+	addr: String,
+}
+
+impl ServerProcess {
+	fn start(name: &str, config: &str) -> Self {
+		let dir = std::env::temp_dir().join(format!("radish-reload-{}-{}", name, std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let file = dir.join("radish.conf");
+		std::fs::write(&file, config).unwrap();
+		let mut child = Process::new(env!("CARGO_BIN_EXE_radish-server"))
+			.arg(&file)
+			.args(["--dir", dir.to_str().unwrap()])
+			.env_remove("RUST_LOG")
+			.stderr(Stdio::piped())
+			.spawn()
+			.unwrap();
+		let (sender, logs) = channel();
+		let stderr = child.stderr.take().unwrap();
+		std::thread::spawn(move ||{
+			for line in BufReader::new(stderr).lines() {
+				if sender.send(line.unwrap()).is_err() {
+					break;
+				}
+			}
+		});
+		let mut server = Self {child, dir, logs, addr: String::new()};
+		let line = server.wait_for_log("listening on tcp ");
+		server.addr = line[line.find("listening on tcp ").unwrap() + 17..].trim().to_owned();
+		server
+	}
+
+	fn rewrite(&self, config: &str) {
+		std::fs::write(self.dir.join("radish.conf"), config).unwrap();
+	}
+
+	fn hangup(&self) {
+		let status = Process::new("kill").args(["-HUP", &self.child.id().to_string()]).status().unwrap();
+		assert!(status.success());
+	}
+
+	fn wait_for_log(&self, needle: &str) -> String {
+		let deadline = Instant::now() + Duration::from_secs(10);
+		loop {
+			let left = deadline.saturating_duration_since(Instant::now());
+			match self.logs.recv_timeout(left) {
+				Ok(line) if line.contains(needle) => return line,
+				Ok(_) => continue,
+				Err(_) => panic!("no log line with '{}'", needle),
+			}
+		}
+	}
+}
+
+impl Drop for ServerProcess {
+	fn drop(&mut self) {
+		let _ = self.child.kill();
+		let _ = self.child.wait();
+		let _ = std::fs::remove_dir_all(&self.dir);
+	}
+}
+
+async fn config_get(client: &Client, name: &str) -> String {
+	let command = Command {
+		command: "CONFIG".to_owned(),
+		arguments: vec![Value::Buffer(b"GET".to_vec()), Value::Buffer(name.as_bytes().to_vec())].into(),
+	};
+	match client.execute(command).await.unwrap() {
+		Value::Array(mut pair) if pair.len() == 2 => match pair.pop_back() {
+			Some(Value::Buffer(value)) => String::from_utf8(value).unwrap(),
+			value => panic!("unexpected CONFIG GET value {:?}", value),
+		},
+		reply => panic!("unexpected CONFIG GET reply {:?}", reply),
+	}
+}
+
+const CONFIG: &str = "bind 127.0.0.1\nport 0\ntimeout 300\nmaxmemory 1mb\nsave 900 1\nloglevel notice\n";
+
+#[tokio::test]
+async fn hangup_applies_the_runtime_parameters() {
+	let server = ServerProcess::start("apply", CONFIG);
+	let client = Client::connect(&server.addr).await.unwrap();
+	client.set("k", "v").await.unwrap();
+
+	server.rewrite("bind 127.0.0.1\nport 6390\ntimeout 30\nmaxmemory 2mb\nsave 60 100\nsave 10 1000\nloglevel notice\n");
+	server.hangup();
+	server.wait_for_log("SIGHUP received, reloading config");
+	assert!(server.wait_for_log("config reloaded maxmemory").ends_with("maxmemory: '1048576' -> '2097152'"));
+	assert!(server.wait_for_log("config reloaded save").ends_with("save: '900 1' -> '60 100 10 1000'"));
+	assert!(server.wait_for_log("config reloaded timeout").ends_with("timeout: '300' -> '30'"));
+	assert!(server.wait_for_log("config change ignored until restart").ends_with("port: '0' -> '6390'"));
+
+	// the connection opened before the reload is still served and sees the new values
+	assert_eq!(config_get(&client, "timeout").await, "30");
+	assert_eq!(config_get(&client, "maxmemory").await, "2097152");
+	assert_eq!(config_get(&client, "save").await, "60 100 10 1000");
+	assert_eq!(config_get(&client, "port").await, "0");
+	assert_eq!(client.get("k").await.unwrap(), Some(b"v".to_vec()));
+}
+
+#[tokio::test]
+async fn invalid_file_changes_nothing() {
+	let server = ServerProcess::start("invalid", CONFIG);
+	let client = Client::connect(&server.addr).await.unwrap();
+
+	server.rewrite("timeout 30\nmaxmemory 2mb\nsave 60\n");
+	server.hangup();
+	server.wait_for_log("config is not reloaded");
+	assert_eq!(config_get(&client, "timeout").await, "300");
+	assert_eq!(config_get(&client, "maxmemory").await, "1048576");
+	assert_eq!(config_get(&client, "save").await, "900 1");
+
+	// an unchanged file is reported as such
+	server.rewrite(CONFIG);
+	server.hangup();
+	server.wait_for_log("config reloaded, nothing changed");
+}
@@ -0,0 +1,66 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+#![cfg(target_os = "linux")]
+
+use std::collections::HashSet;
+
+use radish_client::{Client, Command, Value};
+use radish_database::Storage;
+use radish_server::Server;
+
+const CLIENTS: usize = 32;
+
+#[tokio::test(threaded_scheduler)]
+async fn two_listeners_share_the_port() {
+	let storage = Storage::new();
+	storage.config().set("bind", "127.0.0.1").unwrap();
+	storage.config().set("port", "0").unwrap();
+	storage.config().set("reuseport-listeners", "2").unwrap();
+	let server = Server::bind(storage.clone()).await.unwrap();
+
+	let listeners = server.listeners();
+	assert_eq!(listeners.len(), 2, "{:?}", listeners);
+	assert_eq!(listeners[0], listeners[1]);
+	assert!(!listeners[0].ends_with(":0"));
+	let addr = listeners[0].trim_start_matches("tcp ").to_owned();
+	let server = server.start();
+
+	// the kernel spreads the connections over both sockets
+	let mut ids = HashSet::new();
+	let mut clients = Vec::new();
+	for i in 0..CLIENTS {
+		let client = Client::connect(&addr).await.unwrap();
+		client.set(format!("k{}", i), "v").await.unwrap();
+		let id = client.execute(Command {command: "CLIENT".to_owned(), arguments: vec![Value::Buffer(b"ID".to_vec())].into()}).await.unwrap();
+		assert!(ids.insert(format!("{:?}", id)), "client id {:?} is reused", id);
+		clients.push(client);
+	}
+	for (i, client) in clients.iter().enumerate() {
+		assert_eq!(client.get(format!("k{}", (i + 1) % CLIENTS)).await.unwrap(), Some(b"v".to_vec()));
+	}
+
+	server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn one_listener_without_the_option() {
+	let storage = Storage::new();
+	storage.config().set("bind", "127.0.0.1").unwrap();
+	storage.config().set("port", "0").unwrap();
+	let server = Server::bind(storage).await.unwrap();
+	assert_eq!(server.listeners().len(), 1);
+}
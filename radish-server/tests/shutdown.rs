@@ -0,0 +1,96 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use radish_client::{Client, Command, Value};
+use radish_database::{Storage, Session};
+use radish_server::{Server, DRAIN_TIMEOUT};
+
+async fn start() -> (Storage, String, radish_server::ServerHandle) {
+	let storage = Storage::new();
+	storage.config().set("bind", "127.0.0.1").unwrap();
+	storage.config().set("port", "0").unwrap();
+	let server = Server::bind(storage.clone()).await.unwrap();
+	let addr = server.listeners()[0].trim_start_matches("tcp ").to_owned();
+	(storage, addr, server.start())
+}
+
+async fn connected_clients(storage: &Storage) -> String {
+	let info = storage.execute(&mut Session::default(), Command {command: "INFO".to_owned(), arguments: Default::default()}).await;
+	let info = match info {
+		Value::Buffer(info) => String::from_utf8(info).unwrap(),
+		info => panic!("unexpected INFO reply {:?}", info),
+	};
+	info.lines().find(|l|l.starts_with("connected_clients:")).unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn shutdown_closes_the_listener_and_drains_the_connections() {
+	let (storage, addr, server) = start().await;
+
+	let client = Client::connect(&addr).await.unwrap();
+	client.set("k", "v").await.unwrap();
+	assert_eq!(client.get("k").await.unwrap(), Some(b"v".to_vec()));
+	let mut idle = TcpStream::connect(&addr).await.unwrap();
+	tokio::time::delay_for(Duration::from_millis(50)).await;
+	assert_eq!(connected_clients(&storage).await, "connected_clients:2");
+
+	let started = Instant::now();
+	server.shutdown().await.unwrap();
+	assert!(started.elapsed() < DRAIN_TIMEOUT, "connection tasks were not drained");
+
+	assert_eq!(connected_clients(&storage).await, "connected_clients:0");
+	assert!(TcpStream::connect(&addr).await.is_err(), "listener is still accepting");
+	assert_eq!(idle.read(&mut [0; 16]).await.unwrap(), 0, "idle connection is not closed");
+	assert!(client.get("k").await.is_err());
+}
+
+#[tokio::test]
+async fn command_in_flight_is_replied_before_the_connection_closes() {
+	let (_storage, addr, server) = start().await;
+
+	let client = Client::connect(&addr).await.unwrap();
+	let blocked = tokio::spawn(async move {
+		let blpop = Command {
+			command: "BLPOP".to_owned(),
+			arguments: vec![Value::Buffer(b"list".to_vec()), Value::Integer(1)].into(),
+		};
+		client.execute(blpop).await
+	});
+	tokio::time::delay_for(Duration::from_millis(100)).await;
+
+	server.shutdown().await.unwrap();
+	assert_eq!(blocked.await.unwrap().unwrap(), Value::Nill);
+}
+
+#[tokio::test]
+async fn stalled_command_body_hits_the_idle_timeout() {
+	let (storage, addr, server) = start().await;
+	storage.config().set("timeout", "1").unwrap();
+
+	let mut sock = TcpStream::connect(&addr).await.unwrap();
+	sock.write_all(&radish_types::frame::encode_header(100, false).unwrap()).await.unwrap();
+	sock.write_all(&[0; 10]).await.unwrap();
+	let started = Instant::now();
+	let read = tokio::time::timeout(Duration::from_secs(5), sock.read(&mut [0; 16])).await;
+	assert!(matches!(read, Ok(Ok(0)) | Ok(Err(_))), "connection is not closed: {:?}", read);
+	assert!(started.elapsed() >= Duration::from_millis(900), "{:?}", started.elapsed());
+	server.shutdown().await.unwrap();
+}
@@ -0,0 +1,110 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Reply compression negotiated by the handshake, see `handshake::FEATURE_COMPRESSION`,
+//! or with `CLIENT COMPRESSION LZ4`.
+//!
+//! A compressed frame has the high bit of the length set; its body is the big-endian u32
+//! size of the original body followed by the body compressed in the LZ4 block format.
+//! Servers never set the bit unless the connection asked for it.
+
+use std::convert::TryInto;
+
+/// Flag in the frame length marking a compressed body
+pub const COMPRESSED_FRAME: u32 = 0x8000_0000;
+
+/// Size of the prefix compressed to estimate the ratio of the whole body
+const SAMPLE_SIZE: usize = 16 * 1024;
+/// A byte of an LZ4 block expands to at most 255 bytes
+const MAX_RATIO: usize = 255;
+
+/// LZ4 block format, without the size prefix
+pub fn compress(input: &[u8]) -> Vec<u8> {
+	lz4_flex::block::compress(input)
+}
+
+/// Decompresses an LZ4 block which must expand to exactly `size` bytes
+pub fn decompress(input: &[u8], size: usize) -> Result<Vec<u8>, String> {
+	// a lying size must not reserve more than the block can expand to
+	if size > input.len().saturating_mul(MAX_RATIO) {
+		return Err(format!("Block of {} bytes can't expand to {} bytes", input.len(), size));
+	}
+	let out = lz4_flex::block::decompress(input, size).map_err(|e|format!("Invalid LZ4 block: {}", e))?;
+	if out.len() != size {
+		return Err(format!("Block expands to {} bytes instead of {}", out.len(), size));
+	}
+	Ok(out)
+}
+
+/// Body of a compressed frame, or None if the body is below `threshold` or doesn't compress
+/// to at most 90% of its size; large bodies are estimated by their prefix first
+pub fn compress_frame(body: &[u8], threshold: usize) -> Option<Vec<u8>> {
+	if body.len() < threshold || body.len() > (!COMPRESSED_FRAME) as usize {
+		return None;
+	}
+	let worth = |original: usize, compressed: usize|compressed < original / 10 * 9;
+	if body.len() > SAMPLE_SIZE * 2 && !worth(SAMPLE_SIZE, compress(&body[..SAMPLE_SIZE]).len()) {
+		return None;
+	}
+	let compressed = compress(body);
+	if !worth(body.len(), compressed.len() + 4) {
+		return None;
+	}
+	let mut frame = Vec::with_capacity(compressed.len() + 4);
+	frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+	frame.extend_from_slice(&compressed);
+	Some(frame)
+}
+
+/// Original body of a frame made by `compress_frame`. The original size is limited by `max_size`
+/// like the frame itself, zero means no limit
+pub fn decompress_frame(frame: &[u8], max_size: usize) -> Result<Vec<u8>, String> {
+	if frame.len() < 4 {
+		return Err("Compressed frame is too short".to_owned());
+	}
+	let size = u32::from_be_bytes(frame[..4].try_into().expect("4 bytes slice")) as usize;
+	if max_size != 0 && size > max_size {
+		return Err(format!("Decompressed size {} exceeds the limit of {} bytes", size, max_size));
+	}
+	decompress(&frame[4..], size)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trip() {
+		let body = b"radish ".repeat(1000);
+		let frame = compress_frame(&body, 0).expect("repeated text compresses");
+		assert!(frame.len() < body.len() / 10);
+		assert_eq!(decompress_frame(&frame, body.len()).unwrap(), body);
+	}
+
+	#[test]
+	fn declared_size_over_the_limit_is_rejected() {
+		let body = b"radish ".repeat(1000);
+		let frame = compress_frame(&body, 0).unwrap();
+		assert!(decompress_frame(&frame, body.len() - 1).unwrap_err().contains("exceeds the limit"));
+
+		// a few bytes claiming to expand to 4 GiB
+		let mut bomb = u32::MAX.to_be_bytes().to_vec();
+		bomb.extend_from_slice(&[0x1f, b'a', 1, 0, 0xff, 0xff, 0xff]);
+		assert!(decompress_frame(&bomb, 64 << 20).unwrap_err().contains("exceeds the limit"));
+		assert!(decompress_frame(&bomb, 0).is_err());
+	}
+}
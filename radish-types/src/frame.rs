@@ -0,0 +1,47 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Framing of the wire protocol shared by the server and the clients.
+//!
+//! Every frame is a big-endian u32 length prefix followed by the body. The highest bit of
+//! the prefix marks a compressed body, see `compression`, so a body is below 2 GiB.
+//! A receiver checks the length against its limit before allocating anything for the body.
+
+use super::compression::COMPRESSED_FRAME;
+
+pub const HEADER_SIZE: usize = 4;
+/// Limit of a frame body unless configured otherwise
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+/// Length prefix of a frame with the body of `size` bytes
+pub fn encode_header(size: usize, compressed: bool) -> Result<[u8; HEADER_SIZE], String> {
+	if size > (!COMPRESSED_FRAME) as usize {
+		return Err(format!("Frame of {} bytes is too big", size));
+	}
+	let flag = if compressed {COMPRESSED_FRAME} else {0};
+	Ok((size as u32 | flag).to_be_bytes())
+}
+
+/// Size of the body and whether it is compressed. A body over `max_size` is an error;
+/// zero `max_size` means no limit
+pub fn decode_header(header: [u8; HEADER_SIZE], max_size: usize) -> Result<(usize, bool), String> {
+	let len = u32::from_be_bytes(header);
+	let size = (len & !COMPRESSED_FRAME) as usize;
+	if max_size != 0 && size > max_size {
+		return Err(format!("Frame of {} bytes is over the limit of {} bytes", size, max_size));
+	}
+	Ok((size, len & COMPRESSED_FRAME != 0))
+}
@@ -0,0 +1,139 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Versioned handshake of a connection.
+//!
+//! A client which supports it sends a hello frame before any command: the magic `RDSH`,
+//! the big-endian u16 protocol version and the big-endian u32 feature flags. The server
+//! replies with a hello frame holding the lower of both versions and the features
+//! supported by both sides; afterwards each side uses only the negotiated features.
+//! Trailing bytes of a hello are ignored, so later versions may extend it.
+//!
+//! Version 0 is the protocol without the handshake. A server serves a connection whose
+//! first frame has no magic as version 0, so old clients keep working. A server without
+//! the handshake drops the connection on a hello, so a client reconnects in version 0.
+
+use std::convert::TryInto;
+
+pub const MAGIC: [u8; 4] = *b"RDSH";
+/// The protocol without the handshake
+pub const LEGACY_VERSION: u16 = 0;
+pub const PROTOCOL_VERSION: u16 = 1;
+const HELLO_SIZE: usize = 10;
+
+/// Replies may be sent as compressed frames, see `compression`
+pub const FEATURE_COMPRESSION: u32 = 1 << 0;
+/// Features implemented by this version of the crate
+pub const SUPPORTED_FEATURES: u32 = FEATURE_COMPRESSION;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hello {
+	pub version: u16,
+	pub features: u32,
+}
+
+impl Hello {
+	/// Hello of this version; `features` should be a subset of `SUPPORTED_FEATURES`
+	pub fn new(features: u32) -> Self {
+		Self {
+			version: PROTOCOL_VERSION,
+			features,
+		}
+	}
+
+	/// The result of a connection without the handshake
+	pub fn legacy() -> Self {
+		Self {
+			version: LEGACY_VERSION,
+			features: 0,
+		}
+	}
+
+	pub fn has(&self, feature: u32) -> bool {
+		self.features & feature == feature
+	}
+
+	pub fn encode(&self) -> Vec<u8> {
+		let mut frame = Vec::with_capacity(HELLO_SIZE);
+		frame.extend_from_slice(&MAGIC);
+		frame.extend_from_slice(&self.version.to_be_bytes());
+		frame.extend_from_slice(&self.features.to_be_bytes());
+		frame
+	}
+
+	/// None if the frame is not a hello, i.e. it is the first command of a legacy client.
+	/// A msgpack command never starts with the magic
+	pub fn decode(frame: &[u8]) -> Result<Option<Self>, String> {
+		if !frame.starts_with(&MAGIC) {
+			return Ok(None);
+		}
+		if frame.len() < HELLO_SIZE {
+			return Err(format!("Handshake frame is too short: {} bytes", frame.len()));
+		}
+		let version = u16::from_be_bytes(frame[4..6].try_into().expect("2 bytes slice"));
+		if version == LEGACY_VERSION {
+			return Err("Handshake with protocol version 0".to_owned());
+		}
+		Ok(Some(Self {
+			version,
+			features: u32::from_be_bytes(frame[6..10].try_into().expect("4 bytes slice")),
+		}))
+	}
+
+	/// Reply of the side with `self` capabilities to the peer's hello
+	pub fn negotiate(&self, peer: &Hello) -> Hello {
+		Hello {
+			version: std::cmp::min(self.version, peer.version),
+			features: self.features & peer.features,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trip() {
+		let hello = Hello::new(FEATURE_COMPRESSION);
+		let frame = hello.encode();
+		assert_eq!(frame, b"RDSH\x00\x01\x00\x00\x00\x01");
+		assert_eq!(Hello::decode(&frame).unwrap(), Some(hello));
+		assert!(hello.has(FEATURE_COMPRESSION));
+		assert!(!Hello::new(0).has(FEATURE_COMPRESSION));
+	}
+
+	#[test]
+	fn legacy_and_malformed_frames() {
+		// the first command of a v0 client is a msgpack map or array
+		assert_eq!(Hello::decode(&[0x82, 0xa7]).unwrap(), None);
+		assert_eq!(Hello::decode(b"").unwrap(), None);
+		assert_eq!(Hello::decode(b"RDSH\x00\x01").unwrap_err(), "Handshake frame is too short: 6 bytes");
+		assert_eq!(Hello::decode(b"RDSH\x00\x00\x00\x00\x00\x00").unwrap_err(), "Handshake with protocol version 0");
+		// a later version may append fields
+		assert_eq!(Hello::decode(b"RDSH\x00\x07\x00\x00\x00\x03extra").unwrap(), Some(Hello {version: 7, features: 3}));
+	}
+
+	#[test]
+	fn negotiation_takes_the_common_part() {
+		let server = Hello::new(SUPPORTED_FEATURES);
+		let newer = Hello {version: PROTOCOL_VERSION + 1, features: u32::MAX};
+		assert_eq!(server.negotiate(&newer), Hello {version: PROTOCOL_VERSION, features: SUPPORTED_FEATURES});
+		assert_eq!(server.negotiate(&Hello::new(0)), Hello::new(0));
+		assert_eq!(Hello::legacy(), Hello {version: LEGACY_VERSION, features: 0});
+	}
+}
@@ -0,0 +1,235 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Compact key of the keyspace. Short keys are stored inline without a heap allocation,
+//! longer keys are shared, so the copies kept by the expiration queue and the events are
+//! a reference count away. Hashing and comparison are the ones of `[u8]`, so maps keyed by
+//! `CompactKey` are looked up by a plain byte slice.
+
+use std::borrow::Borrow;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// Keys up to this length are stored inline; the key is as big as a `Vec<u8>` header
+pub const INLINE_CAPACITY: usize = 22;
+
+#[derive(Clone)]
+enum Repr {
+	Inline {len: u8, bytes: [u8; INLINE_CAPACITY]},
+	Shared(Arc<[u8]>),
+}
+
+#[derive(Clone)]
+pub struct CompactKey(Repr);
+
+impl CompactKey {
+	pub fn new(key: &[u8]) -> Self {
+		if key.len() <= INLINE_CAPACITY {
+			let mut bytes = [0; INLINE_CAPACITY];
+			bytes[..key.len()].copy_from_slice(key);
+			Self(Repr::Inline {len: key.len() as u8, bytes})
+		} else {
+			Self(Repr::Shared(Arc::from(key)))
+		}
+	}
+
+	pub fn as_bytes(&self) -> &[u8] {
+		match &self.0 {
+			Repr::Inline {len, bytes} => &bytes[..*len as usize],
+			Repr::Shared(bytes) => bytes,
+		}
+	}
+
+	/// Heap bytes taken by a key of `len` bytes, shared between its clones
+	pub fn heap_size(len: usize) -> usize {
+		if len <= INLINE_CAPACITY {
+			0
+		} else {
+			// the strong and weak counts of the Arc
+			len + 2 * std::mem::size_of::<usize>()
+		}
+	}
+}
+
+impl Deref for CompactKey {
+	type Target = [u8];
+
+	fn deref(&self) -> &[u8] {
+		self.as_bytes()
+	}
+}
+
+impl AsRef<[u8]> for CompactKey {
+	fn as_ref(&self) -> &[u8] {
+		self.as_bytes()
+	}
+}
+
+impl Borrow<[u8]> for CompactKey {
+	fn borrow(&self) -> &[u8] {
+		self.as_bytes()
+	}
+}
+
+impl Hash for CompactKey {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.as_bytes().hash(state)
+	}
+}
+
+impl PartialEq for CompactKey {
+	fn eq(&self, other: &Self) -> bool {
+		self.as_bytes() == other.as_bytes()
+	}
+}
+
+impl Eq for CompactKey {}
+
+impl PartialOrd for CompactKey {
+	fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for CompactKey {
+	fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+		self.as_bytes().cmp(other.as_bytes())
+	}
+}
+
+impl PartialEq<[u8]> for CompactKey {
+	fn eq(&self, other: &[u8]) -> bool {
+		self.as_bytes() == other
+	}
+}
+
+impl PartialEq<Vec<u8>> for CompactKey {
+	fn eq(&self, other: &Vec<u8>) -> bool {
+		self.as_bytes() == &other[..]
+	}
+}
+
+impl std::fmt::Debug for CompactKey {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		self.as_bytes().fmt(f)
+	}
+}
+
+impl From<&[u8]> for CompactKey {
+	fn from(key: &[u8]) -> Self {
+		Self::new(key)
+	}
+}
+
+impl From<Vec<u8>> for CompactKey {
+	fn from(key: Vec<u8>) -> Self {
+		if key.len() <= INLINE_CAPACITY {
+			Self::new(&key)
+		} else {
+			Self(Repr::Shared(Arc::from(key.into_boxed_slice())))
+		}
+	}
+}
+
+impl From<&Vec<u8>> for CompactKey {
+	fn from(key: &Vec<u8>) -> Self {
+		Self::new(key)
+	}
+}
+
+impl From<CompactKey> for Vec<u8> {
+	fn from(key: CompactKey) -> Self {
+		key.as_bytes().to_vec()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::hash_map::DefaultHasher;
+	use std::collections::{BTreeSet, HashMap};
+
+	fn hash<T: Hash + ?Sized>(value: &T) -> u64 {
+		let mut hasher = DefaultHasher::new();
+		value.hash(&mut hasher);
+		hasher.finish()
+	}
+
+	fn is_inline(key: &CompactKey) -> bool {
+		matches!(key.0, Repr::Inline {..})
+	}
+
+	#[test]
+	fn as_big_as_a_vec() {
+		assert_eq!(std::mem::size_of::<CompactKey>(), std::mem::size_of::<Vec<u8>>());
+	}
+
+	#[test]
+	fn inline_up_to_the_capacity() {
+		for len in 0..=INLINE_CAPACITY + 2 {
+			let bytes: Vec<u8> = (0..len as u8).collect();
+			for key in [CompactKey::new(&bytes), CompactKey::from(bytes.clone()), CompactKey::from(&bytes)] {
+				assert_eq!(is_inline(&key), len <= INLINE_CAPACITY, "{} bytes", len);
+				assert_eq!(key.as_bytes(), &bytes[..]);
+				assert_eq!(Vec::from(key), bytes);
+			}
+			assert_eq!(CompactKey::heap_size(len) == 0, len <= INLINE_CAPACITY);
+		}
+	}
+
+	#[test]
+	fn clones_share_long_keys() {
+		let key = CompactKey::new(&[7; 100]);
+		let clone = key.clone();
+		assert_eq!(key.as_bytes().as_ptr(), clone.as_bytes().as_ptr());
+
+		let short = CompactKey::new(b"short");
+		assert_ne!(short.as_bytes().as_ptr(), short.clone().as_bytes().as_ptr());
+	}
+
+	#[test]
+	fn hashed_and_compared_as_slices() {
+		for bytes in &[&b""[..], b"key", &[0; INLINE_CAPACITY], &[0xff; 64]] {
+			let key = CompactKey::new(bytes);
+			assert_eq!(hash(&key), hash(*bytes));
+			assert_eq!(&key, *bytes);
+			assert_eq!(key, bytes.to_vec());
+		}
+		// trailing zeros of the inline buffer don't make keys equal
+		assert_ne!(CompactKey::new(b"a"), CompactKey::new(b"a\0"));
+		assert_ne!(hash(&CompactKey::new(b"a")), hash(&CompactKey::new(b"a\0")));
+	}
+
+	#[test]
+	fn maps_are_looked_up_by_slices() {
+		let mut map = HashMap::new();
+		map.insert(CompactKey::new(b"short"), 1);
+		map.insert(CompactKey::new(&[b'x'; 40]), 2);
+		assert_eq!(map.get(&b"short"[..]), Some(&1));
+		assert_eq!(map.get(&[b'x'; 40][..]), Some(&2));
+		assert_eq!(map.get(&b"missing"[..]), None);
+	}
+
+	#[test]
+	fn ordered_as_slices() {
+		let keys = [&b"b"[..], b"a", b"ab", b"", &[b'a'; 30], &[b'c'; 30]];
+		let compact: BTreeSet<CompactKey> = keys.iter().map(|key|CompactKey::new(key)).collect();
+		let slices: BTreeSet<&[u8]> = keys.iter().copied().collect();
+		assert!(compact.iter().map(|key|key.as_bytes()).eq(slices.into_iter()));
+	}
+}
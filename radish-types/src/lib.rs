@@ -14,6 +14,13 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+pub mod compression;
+pub mod frame;
+pub mod handshake;
+pub mod key;
+
+pub use key::CompactKey;
+
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 
@@ -34,7 +41,7 @@ pub enum Value {
 }
 
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Command {
 	pub command: String,
 	pub arguments: Arguments,
@@ -75,3 +82,139 @@ impl std::fmt::Display for Command {
 	}
 }
 
+/// Display of a command for logs, see `Command::display_redacted`
+pub struct RedactedCommand<'a> {
+	command: &'a Command,
+	secret: Vec<usize>,
+	limit: usize,
+}
+
+impl Command {
+	/// Like Display, but the arguments at the `secret` positions are masked and buffers
+	/// longer than `limit` bytes are cut; zero limit keeps them whole
+	pub fn display_redacted(&self, secret: Vec<usize>, limit: usize) -> RedactedCommand<'_> {
+		RedactedCommand {
+			command: self,
+			secret,
+			limit,
+		}
+	}
+}
+
+impl std::fmt::Display for RedactedCommand<'_> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let args =
+			self.command
+			.arguments
+			.iter()
+			.enumerate()
+			.map(|(i, arg)|match arg {
+				_ if self.secret.contains(&i) => "(redacted)".to_owned(),
+				Value::Buffer(v) if self.limit > 0 && v.len() > self.limit => format!("{:?}...({} bytes)", &v[..self.limit], v.len()),
+				arg => format!("{}", arg),
+			})
+			.collect::<Vec<String>>()
+			.join(",")
+		;
+		write!(f, "{}: [{}]", self.command.command, args)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn values() -> Vec<Value> {
+		let binary = (0..=255).collect::<Vec<u8>>();
+		vec![
+			Value::Nill,
+			Value::Ok,
+			Value::Bool(true),
+			Value::Bool(false),
+			Value::Integer(i64::MIN),
+			Value::Integer(-1),
+			Value::Integer(i64::MAX),
+			Value::Float(1.5f64.to_bits()),
+			Value::Float(f64::NAN.to_bits()),
+			Value::Float((-0.0f64).to_bits()),
+			Value::Buffer(Vec::new()),
+			Value::Buffer(binary.clone()),
+			Value::Buffer("ключ 🔑".as_bytes().to_vec()),
+			Value::Error("WRONGTYPE Operation against a key holding the wrong kind of value".to_owned()),
+			Value::Error(String::new()),
+			Value::Array(VecDeque::new()),
+			Value::Array(vec![
+				Value::Integer(1),
+				Value::Array(vec![
+					Value::Buffer(binary),
+					Value::Array(vec![Value::Nill, Value::Error("ERR nested".to_owned())].into()),
+				].into()),
+				Value::Ok,
+			].into()),
+		]
+	}
+
+	#[test]
+	fn value_msgpack_round_trip() {
+		for value in values() {
+			let data = rmp_serde::to_vec(&value).unwrap();
+			assert_eq!(rmp_serde::from_read_ref::<_, Value>(&data).unwrap(), value);
+		}
+	}
+
+	#[test]
+	fn value_json_round_trip() {
+		for value in values() {
+			let json = serde_json::to_string(&value).unwrap();
+			assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), value, "{}", json);
+		}
+	}
+
+	#[test]
+	fn command_round_trip() {
+		let commands = vec![
+			Command {command: "PING".to_owned(), arguments: Arguments::new()},
+			Command {command: "HSET".to_owned(), arguments: values().into()},
+			Command {command: "jsön.set".to_owned(), arguments: vec![Value::Buffer(vec![0, 0xff, b'\n'])].into()},
+		];
+		for cmd in commands {
+			let data = rmp_serde::to_vec(&cmd).unwrap();
+			assert_eq!(rmp_serde::from_read_ref::<_, Command>(&data).unwrap(), cmd);
+			let json = serde_json::to_string(&cmd).unwrap();
+			assert_eq!(serde_json::from_str::<Command>(&json).unwrap(), cmd);
+		}
+
+		let result = CommandResult {results: Value::Array(values().into())};
+		let data = rmp_serde::to_vec(&result).unwrap();
+		assert_eq!(rmp_serde::from_read_ref::<_, CommandResult>(&data).unwrap(), result);
+	}
+
+	#[test]
+	fn redacted_display() {
+		let auth = Command {
+			command: "AUTH".to_owned(),
+			arguments: vec![Value::Buffer(b"user".to_vec()), Value::Buffer(b"secret".to_vec())].into(),
+		};
+		assert_eq!(auth.display_redacted(vec![0, 1], 128).to_string(), "AUTH: [(redacted),(redacted)]");
+		assert_eq!(auth.display_redacted(vec![1], 128).to_string(), "AUTH: [[117, 115, 101, 114],(redacted)]");
+		assert_eq!(auth.display_redacted(Vec::new(), 0).to_string(), auth.to_string());
+
+		let set = Command {
+			command: "SET".to_owned(),
+			arguments: vec![Value::Buffer(b"k".to_vec()), Value::Buffer(vec![b'x'; 1 << 20]), Value::Integer(10)].into(),
+		};
+		assert_eq!(set.display_redacted(Vec::new(), 4).to_string(), "SET: [[107],[120, 120, 120, 120]...(1048576 bytes),10]");
+		// a value of exactly the limit is kept whole
+		assert_eq!(set.display_redacted(Vec::new(), 1).to_string(), "SET: [[107],[120]...(1048576 bytes),10]");
+		assert_eq!(set.display_redacted(Vec::new(), 0).to_string(), set.to_string());
+	}
+
+	#[test]
+	fn truncated_data_is_rejected() {
+		let data = rmp_serde::to_vec(&Value::Array(values().into())).unwrap();
+		for len in [0, 1, data.len() / 2, data.len() - 1] {
+			assert!(rmp_serde::from_read_ref::<_, Value>(&data[..len]).is_err(), "{}", len);
+		}
+	}
+}
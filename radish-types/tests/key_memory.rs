@@ -0,0 +1,71 @@
+/* Copyright (c) 2020 Dmitry Shatilov <shatilov dot diman at gmail dot com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Heap taken by a keyspace of short keys, counted by the allocator of the test binary
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use radish_types::CompactKey;
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst);
+		ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+		System.alloc(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+		ALLOCATIONS.fetch_sub(1, Ordering::SeqCst);
+		System.dealloc(ptr, layout)
+	}
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const KEYS: usize = 2_000_000;
+
+/// Live bytes and allocations of a set of `KEYS` keys like "user:0001234"
+fn keyspace<K: Eq + Hash>(key: impl Fn(Vec<u8>) -> K) -> (usize, usize) {
+	let (bytes, allocations) = (ALLOCATED.load(Ordering::SeqCst), ALLOCATIONS.load(Ordering::SeqCst));
+	let mut keys = HashSet::with_capacity(KEYS);
+	for i in 0..KEYS {
+		keys.insert(key(format!("user:{:07}", i).into_bytes()));
+	}
+	let used = (ALLOCATED.load(Ordering::SeqCst) - bytes, ALLOCATIONS.load(Ordering::SeqCst) - allocations);
+	assert_eq!(keys.len(), KEYS);
+	used
+}
+
+#[test]
+fn short_keys_take_less_heap() {
+	let (vec_bytes, vec_allocations) = keyspace(|key|key);
+	let (compact_bytes, compact_allocations) = keyspace(CompactKey::from);
+
+	// the table is of the same size, only the separate allocation of every key is gone
+	assert_eq!(compact_allocations, 1);
+	assert_eq!(vec_allocations, KEYS + 1);
+	assert!(compact_bytes + KEYS * 12 <= vec_bytes, "{} bytes against {}", compact_bytes, vec_bytes);
+}